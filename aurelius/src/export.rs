@@ -0,0 +1,182 @@
+//! Standalone pages, showing rendered markdown the way the preview does without a server, so that
+//! a document can be saved as a single HTML file.
+//!
+//! The page's stylesheets and scripts are inlined, and so are images stored next to the markdown,
+//! as data URLs.
+
+use std::fs;
+use std::path::Path;
+
+use handlebars::Handlebars;
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+
+use crate::{Direction, Theme, STATIC_FILES};
+
+/// How a standalone page looks, like the preview with the same settings.
+#[derive(Debug, Clone)]
+pub struct Style {
+    /// Color scheme of the page.
+    pub theme: Theme,
+    /// Direction of the text.
+    pub direction: Direction,
+    /// The highlight.js theme used for code blocks.
+    pub highlight_theme: String,
+    /// CSS added after the default styles, like the stylesheet of
+    /// [`Server::set_user_stylesheet`].
+    ///
+    /// [`Server::set_user_stylesheet`]: crate::Server::set_user_stylesheet
+    pub user_css: Option<String>,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            theme: Theme::default(),
+            direction: Direction::default(),
+            highlight_theme: String::from("github"),
+            user_css: None,
+        }
+    }
+}
+
+/// A bundled static file, e.g. `css/styles.css`.
+fn static_file(path: &str) -> Option<&'static str> {
+    STATIC_FILES
+        .get_file(path)
+        .and_then(|file| file.contents_utf8())
+}
+
+/// The data URL of the image at `src`, an attribute value, if it's a relative path to a file in
+/// `dir`.
+fn data_url(src: &str, dir: &Path) -> Option<String> {
+    let src = src.replace("&amp;", "&");
+    let path = src.split(['#', '?']).next()?;
+    let is_url = path.split('/').next()?.contains(':');
+    if path.is_empty() || path.starts_with('/') || is_url {
+        return None;
+    }
+    let path = dir.join(percent_decode_str(path).decode_utf8().ok()?.as_ref());
+    file_data_url(&path)
+}
+
+/// The data URL of the file at `path`, with the MIME type its extension implies, if it has one.
+pub fn file_data_url(path: &Path) -> Option<String> {
+    let mime_type = mime_guess::from_path(path).first()?;
+    let contents = fs::read(path).ok()?;
+    Some(format!(
+        "data:{};base64,{}",
+        mime_type,
+        base64::encode(&contents)
+    ))
+}
+
+/// `html` with the relative sources of its images, which are relative to `dir`, replaced by data
+/// URLs. Images that can't be read are left alone.
+fn inline_images(html: &str, dir: &Path) -> String {
+    const SRC: &str = " src=\"";
+
+    let mut inlined = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(img) = rest.find("<img ") {
+        let tag_end = rest[img..].find('>').map_or(rest.len(), |end| img + end);
+        let src = match rest[img..tag_end].find(SRC) {
+            Some(src) => img + src + SRC.len(),
+            None => {
+                inlined.push_str(&rest[..tag_end]);
+                rest = &rest[tag_end..];
+                continue;
+            }
+        };
+        let src_end = rest[src..].find('"').map_or(rest.len(), |end| src + end);
+        inlined.push_str(&rest[..src]);
+        match data_url(&rest[src..src_end], dir) {
+            Some(url) => inlined.push_str(&url),
+            None => inlined.push_str(&rest[src..src_end]),
+        }
+        rest = &rest[src_end..];
+    }
+    inlined.push_str(rest);
+    inlined
+}
+
+/// A page titled `title` showing `html`, rendered from markdown read from the directory `dir`, if
+/// it was read from a file.
+///
+/// Code is highlighted and math rendered once the page loads. Mermaid diagrams are left as their
+/// source, as drawing them needs a script from the web.
+pub fn standalone_page(html: &str, title: &str, dir: Option<&Path>, style: &Style) -> String {
+    #[derive(Debug, Serialize)]
+    struct Data<'a> {
+        title: &'a str,
+        html: &'a str,
+        theme: Theme,
+        direction: Direction,
+        stylesheets: Vec<&'a str>,
+        scripts: Vec<&'static str>,
+    }
+
+    let highlight_theme = format!(
+        "vendor/highlight.js/build/styles/{}.min.css",
+        style.highlight_theme
+    );
+    let mut stylesheets = vec![
+        static_file(&highlight_theme),
+        static_file("css/styles.css"),
+        static_file("vendor/github-markdown-css/github-markdown.css"),
+    ];
+    if style.theme == Theme::Dark {
+        stylesheets.push(static_file("css/dark.css"));
+    }
+    stylesheets.push(style.user_css.as_deref());
+    let scripts = [
+        "vendor/highlight.js/build/highlight.min.js",
+        "vendor/katex/katex.min.js",
+        "js/standalone.js",
+    ];
+
+    let html = match dir {
+        Some(dir) => inline_images(html, dir),
+        None => html.to_owned(),
+    };
+    let data = Data {
+        title,
+        html: &html,
+        theme: style.theme,
+        direction: style.direction,
+        stylesheets: stylesheets.into_iter().flatten().collect(),
+        scripts: scripts
+            .iter()
+            .filter_map(|path| static_file(path))
+            .collect(),
+    };
+    Handlebars::new()
+        .render_template(include_str!("../templates/standalone.html"), &data)
+        .expect("invalid template syntax")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{inline_images, standalone_page, Style};
+
+    #[test]
+    fn standalone_pages_inline_images() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("images")).unwrap();
+        fs::write(dir.path().join("images/a cat.png"), b"cat").unwrap();
+
+        let html = r#"<p><img src="images/a%20cat.png" alt="A cat" /> <img src="https://example.com/dog.png" alt="" /> <img src="missing.png" alt="" /></p>"#;
+        assert_eq!(
+            inline_images(html, dir.path()),
+            r#"<p><img src="data:image/png;base64,Y2F0" alt="A cat" /> <img src="https://example.com/dog.png" alt="" /> <img src="missing.png" alt="" /></p>"#
+        );
+
+        let page = standalone_page("<h1>Cats</h1>", "Cats & dogs", None, &Style::default());
+        assert!(page.contains("<title>Cats &amp; dogs</title>"));
+        assert!(page.contains("<h1>Cats</h1>"));
+        assert!(page.contains("katex"));
+        assert!(!page.contains("/__/"));
+    }
+}
@@ -15,13 +15,17 @@
 //! ```no_run
 //! use aurelius::Server;
 //!
-//! let mut server = Server::bind("localhost:0")?;
-//! println!("listening on {}", server.addr());
+//! # async fn run() -> std::io::Result<()> {
+//! let mut server = Server::bind("localhost:0").await?;
+//! println!("listening on {}", server.url());
 //!
-//! server.open_browser()?;
+//! server.open_browser().await?;
 //!
-//! server.send(String::from("# Hello, world"));
-//! # Ok::<_, Box<dyn std::error::Error>>(())
+//! server.send("# Hello, world").await?;
+//!
+//! server.shutdown().await;
+//! # Ok(())
+//! # }
 //! ```
 //!
 //! # Acknowledgments
@@ -40,19 +44,20 @@
 // #![warn(missing_debug_implementations)]
 #![warn(missing_docs)]
 
+use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
 use std::fs;
 use std::io::{self, prelude::*};
-use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
-use std::ops::Deref;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream};
 use std::path::Path;
 use std::path::PathBuf;
-use std::process::Command;
 use std::process::Stdio;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex, RwLock};
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
+use std::time::UNIX_EPOCH;
 
 use buf_redux::BufReader;
 use crossbeam_channel::{select, Sender};
@@ -61,15 +66,24 @@ use handlebars::Handlebars;
 use httparse::{Request, Status, EMPTY_HEADER};
 use include_dir::{include_dir, Dir};
 use log::*;
-use pulldown_cmark::{Options, Parser};
 use serde::Serialize;
 use sha1::{Digest, Sha1};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::process::Command;
+use tokio::task;
 use tungstenite::{protocol::Role, Message, WebSocket};
 use url::Url;
 
 use crate::id_map::IdMap;
+use crate::render::{ExternalCommand, PulldownCmark, Renderer};
+use crate::static_roots::StaticRoots;
 
+pub mod export;
 mod id_map;
+mod math;
+pub mod render;
+pub mod sanitize;
+mod static_roots;
 
 const STATIC_FILES: Dir = include_dir!("static");
 
@@ -77,13 +91,19 @@ const STATIC_FILES: Dir = include_dir!("static");
 ///
 /// Listens for HTTP connections and serves a page containing a live markdown preview. The page
 /// contains JavaScript to open a websocket connection back to the server for rendering updates.
+///
+/// Markdown is published on named channels, each with its own page, so several documents can be
+/// previewed at once. The default channel, which the methods without a channel argument use, is
+/// served at the server's root URL; see [`Server::channel_url`] for the others.
 //#[derive(Debug)]
 pub struct Server {
     addr: SocketAddr,
     config: Arc<Mutex<Config>>,
-    external_renderer: Option<fn(&str) -> io::Result<String>>,
-    md_clients: Arc<Mutex<IdMap<Sender<Signal>>>>,
-    html: Arc<RwLock<Option<String>>>,
+    renderer: Arc<Mutex<Box<dyn Renderer>>>,
+    /// The markdown, file and HTML of the last render, reused when the same markdown is sent to
+    /// several channels in a row.
+    last_render: Option<(String, Option<PathBuf>, Arc<str>)>,
+    channels: Arc<Mutex<HashMap<String, Channel>>>,
     /// Indicates whether the server should initiate shutdown.
     ///
     /// On drop, we want the server to clean up existing connections gracefully and stop listening
@@ -98,23 +118,25 @@ pub struct Server {
 impl Server {
     /// Binds the server to a specified address.
     ///
-    /// Binding on port 0 will request a port assignment from the OS. Use `addr()` to query the
-    /// assigned port.
-    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
-        let listener = TcpListener::bind(addr)?;
+    /// Binding on port 0 will request a port assignment from the OS. Use `addr()` or `url()` to
+    /// query the assigned port.
+    ///
+    /// Connections are handled on background threads, so the server keeps running whether or
+    /// not the caller's runtime is polling anything.
+    pub async fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?.into_std()?;
+        listener.set_nonblocking(false)?;
         let addr = listener.local_addr()?;
 
         info!("listening on {}", addr);
 
         let shutdown = Arc::new(AtomicBool::new(false));
-        let md_clients = Arc::new(Mutex::new(IdMap::default()));
+        let channels = Arc::new(Mutex::new(HashMap::new()));
         let config = Arc::new(Mutex::new(Config::default()));
-        let html = Arc::new(RwLock::new(None));
 
         let conn_shutdown = Arc::clone(&shutdown);
-        let conn_md_clients = Arc::clone(&md_clients);
+        let conn_channels = Arc::clone(&channels);
         let conn_config = Arc::clone(&config);
-        let conn_html = Arc::clone(&html);
 
         let join_handle = thread::spawn(move || {
             crossbeam_thread::scope(|s| {
@@ -129,15 +151,13 @@ impl Server {
                     };
 
                     let handler_config = Arc::clone(&conn_config);
-                    let handler_md_clients = Arc::clone(&conn_md_clients);
-                    let handler_html = Arc::clone(&conn_html);
+                    let handler_channels = Arc::clone(&conn_channels);
 
                     s.spawn(|_| {
                         let handler = Handler {
                             conn,
                             config: handler_config,
-                            md_clients: handler_md_clients,
-                            html: handler_html,
+                            channels: handler_channels,
                         };
 
                         if let Err(e) = handler.handle() {
@@ -153,7 +173,8 @@ impl Server {
                                 {
                                     ()
                                 }
-                                _ => panic!("unexpected error occurred: {}", e),
+                                // A misbehaving connection only takes down its own preview.
+                                _ => warn!("preview connection failed: {}", e),
                             }
                         }
                     });
@@ -165,77 +186,198 @@ impl Server {
         Ok(Server {
             addr,
             config,
-            md_clients,
-            html,
-            external_renderer: None,
+            channels,
+            renderer: Arc::new(Mutex::new(Box::new(PulldownCmark::default()))),
+            last_render: None,
             shutdown,
             listener_join_handle: Some(join_handle),
         })
     }
 
+    /// Returns whether the server still accepts connections.
+    ///
+    /// The server stops by itself only if accepting connections fails, in which case it should
+    /// be shut down and bound again.
+    pub fn is_running(&self) -> bool {
+        self.listener_join_handle
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+    }
+
     /// Returns the socket address that the server is listening on.
     pub fn addr(&self) -> SocketAddr {
         self.addr
     }
 
+    /// Returns the URL of the preview page, e.g. `http://127.0.0.1:38219/`.
+    ///
+    /// A server bound on every interface, like `0.0.0.0`, is reached locally through the
+    /// loopback address.
+    pub fn url(&self) -> Url {
+        let mut addr = self.addr;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr.ip() {
+                IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            });
+        }
+        Url::parse(&format!("http://{}/", addr)).expect("socket address is a valid host")
+    }
+
+    /// Returns the URL serving the text set with [`Server::set_metrics`], e.g.
+    /// `http://127.0.0.1:38219/__/metrics`.
+    pub fn metrics_url(&self) -> Url {
+        self.url().join("__/metrics").expect("path is a valid URL")
+    }
+
+    /// Returns the URL of the page previewing `channel`, e.g.
+    /// `http://127.0.0.1:38219/?channel=notes`.
+    pub fn channel_url(&self, channel: &str) -> Url {
+        let mut url = self.url();
+        if !channel.is_empty() {
+            url.query_pairs_mut().append_pair("channel", channel);
+        }
+        url
+    }
+
     /// Publish new markdown to be rendered by the server.
     ///
-    /// The new HTML will be sent to all connected websocket clients.
+    /// The new HTML will be sent to all connected websocket clients. Rendering happens on a
+    /// blocking thread, since renderers may run external programs.
     ///
     /// # Errors
     ///
-    /// This method forwards errors from an external renderer, if set. Otherwise, the method is
-    /// infallible.
-    pub fn send(&mut self, markdown: String) -> io::Result<()> {
-        let html = if let Some(render_func) = self.external_renderer {
-            render_func(&markdown)?
-        } else {
-            let mut html = String::with_capacity(markdown.len());
-            let parser = Parser::new_ext(
-                &markdown,
-                Options::ENABLE_FOOTNOTES
-                    | Options::ENABLE_TABLES
-                    | Options::ENABLE_STRIKETHROUGH
-                    | Options::ENABLE_TASKLISTS,
-            );
-
-            pulldown_cmark::html::push_html(&mut html, parser);
-
-            html
-        };
+    /// This method forwards errors from the renderer. The default renderer is infallible.
+    pub async fn send(&mut self, markdown: &str) -> io::Result<()> {
+        self.send_to(DEFAULT_CHANNEL, markdown).await
+    }
+
+    /// Publish new markdown read from the file at `path` to be rendered by the server.
+    ///
+    /// Like `send`, but lets the renderer know which file it is rendering, and makes relative
+    /// links in the preview, like images next to the file, resolve against its directory.
+    pub async fn send_file(&mut self, markdown: &str, path: &Path) -> io::Result<()> {
+        self.send_file_to(DEFAULT_CHANNEL, markdown, path).await
+    }
+
+    /// Publish new markdown on `channel`, like `send`.
+    ///
+    /// Only the previews of that channel are updated.
+    pub async fn send_to(&mut self, channel: &str, markdown: &str) -> io::Result<()> {
+        let html = self.render(markdown, None).await?;
+        self.publish(channel, html, markdown.lines().count(), None);
+        Ok(())
+    }
 
-        // let html = if let Some(renderer) = &mut self.external_renderer {
-        //     let child = renderer.spawn()?;
+    /// Publish new markdown read from the file at `path` on `channel`, like `send_file`.
+    ///
+    /// The file's directory is served from its static root, if it has one, or under a path of its
+    /// own otherwise.
+    pub async fn send_file_to(
+        &mut self,
+        channel: &str,
+        markdown: &str,
+        path: &Path,
+    ) -> io::Result<()> {
+        let html = self.render(markdown, Some(path)).await?;
+        let base_url = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map(|dir| self.config.lock().unwrap().static_roots.serve_dir(dir));
+        self.publish(channel, html, markdown.lines().count(), base_url);
+        Ok(())
+    }
 
-        //     // NOTE: Maybe add explicit flush
-        //     child.stdin.unwrap().write_all(markdown.as_bytes())?;
+    async fn render(&mut self, markdown: &str, path: Option<&Path>) -> io::Result<Arc<str>> {
+        if let Some((last_markdown, last_path, html)) = &self.last_render {
+            if last_markdown == markdown && last_path.as_deref() == path {
+                return Ok(Arc::clone(html));
+            }
+        }
 
-        //     let mut html = String::with_capacity(markdown.len());
-        //     child.stdout.unwrap().read_to_string(&mut html)?;
+        let renderer = Arc::clone(&self.renderer);
+        let markdown = markdown.to_owned();
+        let path = path.map(Path::to_path_buf);
 
-        //     html
-        // } else {
-        //     let mut html = String::with_capacity(markdown.len());
-        //     let parser = Parser::new_ext(
-        //         &markdown,
-        //         Options::ENABLE_FOOTNOTES
-        //             | Options::ENABLE_TABLES
-        //             | Options::ENABLE_STRIKETHROUGH
-        //             | Options::ENABLE_TASKLISTS,
-        //     );
+        let (markdown, path, html) = task::spawn_blocking(move || {
+            let html = renderer.lock().unwrap().render(&markdown, path.as_deref());
+            (markdown, path, html)
+        })
+        .await
+        .map_err(io::Error::other)?;
 
-        //     pulldown_cmark::html::push_html(&mut html, parser);
+        // Whatever rendered it, the preview mustn't run scripts from the note.
+        let html = Arc::from(sanitize::sanitize(&html?));
+        self.last_render = Some((markdown, path, Arc::clone(&html)));
+        Ok(html)
+    }
 
-        //     html
-        // };
+    fn publish(
+        &mut self,
+        channel: &str,
+        html: Arc<str>,
+        line_count: usize,
+        base_url: Option<String>,
+    ) {
+        let mut channels = self.channels.lock().unwrap();
+        let channel = channels.entry(channel.to_owned()).or_default();
+        channel.html = Some(Arc::clone(&html));
+        channel.line_count = line_count;
+
+        if channel.base_url != base_url {
+            channel.base_url = base_url;
+            for client in channel.clients.values() {
+                let _ = client.send(Signal::BaseUrl(channel.base_url.clone()));
+            }
+        }
+        for client in channel.clients.values() {
+            // The connection may have gone away without closing.
+            let _ = client.send(Signal::Html(Arc::clone(&html)));
+        }
+    }
 
-        *self.html.write().unwrap() = Some(html);
+    /// Scroll the preview to the part rendered from `line` (zero-based) of the markdown.
+    ///
+    /// Renderers that mark source lines, like [`PulldownCmark::with_source_lines`], let the
+    /// preview scroll to the exact block. Otherwise the position is estimated from how far into
+    /// the document the line is. The preview keeps its position when the markdown is updated.
+    pub fn scroll_to_line(&self, line: u32) {
+        self.scroll_to_line_in(DEFAULT_CHANNEL, line);
+    }
 
-        for client in self.md_clients.lock().unwrap().values() {
-            client.send(Signal::NewMarkdown).unwrap();
+    /// Scroll the previews of `channel` to `line`, like `scroll_to_line`.
+    pub fn scroll_to_line_in(&self, channel: &str, line: u32) {
+        let mut channels = self.channels.lock().unwrap();
+        let channel = match channels.get_mut(channel) {
+            Some(channel) => channel,
+            None => return,
+        };
+        channel.scroll_line = Some(line);
+        let signal = Signal::Scroll {
+            line,
+            line_count: channel.line_count,
+        };
+        for client in channel.clients.values() {
+            let _ = client.send(signal.clone());
         }
+    }
 
-        Ok(())
+    /// Returns the number of previews showing `channel`.
+    pub fn viewers_in(&self, channel: &str) -> usize {
+        self.channels
+            .lock()
+            .unwrap()
+            .get(channel)
+            .map_or(0, |channel| channel.clients.values().count())
+    }
+
+    /// Close the previews of `channel` and forget its markdown.
+    pub fn close_channel(&mut self, channel: &str) {
+        if let Some(channel) = self.channels.lock().unwrap().remove(channel) {
+            for client in channel.clients.values() {
+                let _ = client.send(Signal::Close);
+            }
+        }
     }
 
     /// Set the directory that static files will be served from.
@@ -244,9 +386,33 @@ impl Server {
     /// non-root paths will be joined to this folder and used to serve files from the filesystem.
     /// Typically this is used to serve image links relative to the markdown file.
     ///
-    /// By default, the server will not serve static files.
+    /// This is the same as `add_static_root("/", root)`. By default, the server only serves the
+    /// directories of files sent with `send_file`.
     pub fn set_static_root(&mut self, root: impl Into<PathBuf>) {
-        self.config.lock().unwrap().static_root = Some(root.into());
+        self.add_static_root("/", root);
+    }
+
+    /// Serve the files in `dir` under the URL path `prefix`, e.g. a theme's assets under
+    /// `/theme/`.
+    ///
+    /// Requests are served from the root with the longest matching prefix, and a root added
+    /// under an existing prefix replaces it. Paths that would leave the root, like
+    /// `/theme/../secret`, are not found.
+    ///
+    /// Files are sent with an `ETag` and must be revalidated on every use, so browsers only
+    /// download them again once they change. Files sent with `send_file` that are inside a root
+    /// have their relative links resolved against their directory.
+    pub fn add_static_root(&mut self, prefix: &str, dir: impl Into<PathBuf>) {
+        self.config
+            .lock()
+            .unwrap()
+            .static_roots
+            .insert(prefix, dir.into());
+    }
+
+    /// Stop serving the static root added under `prefix`.
+    pub fn remove_static_root(&mut self, prefix: &str) {
+        self.config.lock().unwrap().static_roots.remove(prefix);
     }
 
     /// Set the highlight.js theme used for code blocks.
@@ -256,6 +422,55 @@ impl Server {
         self.config.lock().unwrap().highlight_theme = theme;
     }
 
+    /// Set the color scheme of the preview.
+    ///
+    /// The dark theme darkens the default stylesheet. Custom stylesheets can follow the theme
+    /// with the `data-theme` attribute of the page's `<html>` element, which is `light` or `dark`.
+    /// Like the other styling options, this takes effect when the preview page is next loaded.
+    ///
+    /// Defaults to [`Theme::Light`].
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.config.lock().unwrap().theme = theme;
+    }
+
+    /// Set the direction of the text on the preview page, through the `dir` attribute of its
+    /// `<html>` element. Takes effect when the preview page is next loaded.
+    ///
+    /// [`PulldownCmark::with_direction`] marks the blocks of a document written the other way.
+    ///
+    /// Defaults to [`Direction::Ltr`].
+    pub fn set_direction(&mut self, direction: Direction) {
+        self.config.lock().unwrap().direction = direction;
+    }
+
+    /// Set a stylesheet to add to the page after all the others.
+    ///
+    /// Unlike `set_custom_css`, this adjusts the default styles rather than replacing them. The
+    /// file is read whenever the page is loaded, so changes to it show up when the preview is
+    /// refreshed. Pass `None` to remove it.
+    pub fn set_user_stylesheet(&mut self, path: Option<PathBuf>) {
+        self.config.lock().unwrap().user_stylesheet = path;
+    }
+
+    /// Set a Handlebars template to serve the preview page with instead of the built-in one.
+    ///
+    /// The template places the parts of the page where it likes: `{{{ css }}}` for the
+    /// stylesheets, `{{{ body }}}` for the element the markdown is rendered into, `{{{ scripts }}}`
+    /// for the scripts updating it, and `{{ title }}` for the title, along with `{{ theme }}` and
+    /// `{{ direction }}`. Like `set_user_stylesheet`, the file is read whenever the page is
+    /// loaded. A template that can't be read or rendered is logged, and the built-in page served
+    /// instead. Pass `None` to go back to the built-in page.
+    pub fn set_template(&mut self, path: Option<PathBuf>) {
+        self.config.lock().unwrap().template = path;
+    }
+
+    /// Serve the text `metrics` returns at [`Server::metrics_url`], e.g. counters in Prometheus'
+    /// text format. It's called on a connection thread for every request, and may block. Pass
+    /// `None` to stop serving it.
+    pub fn set_metrics(&mut self, metrics: Option<Box<dyn Fn() -> String + Send + Sync>>) {
+        self.config.lock().unwrap().metrics = metrics.map(|metrics| MetricsSource(metrics.into()));
+    }
+
     /// Set custom CSS links and files to be served with the rendered HTML.
     ///
     /// Accepts URLs and absolute paths. URLs will be inserted as `<link>` tags. The contents of
@@ -291,6 +506,8 @@ impl Server {
     ///
     /// The `Command` supplied to this function should expect markdown on stdin and print HTML on
     /// stdout.
+    /// Only its program and arguments are used: it is run again for every render, as described
+    /// for [`render::ExternalCommand`].
     ///
     /// # Example
     ///
@@ -298,29 +515,34 @@ impl Server {
     ///
     ///
     /// ```no_run
-    /// use std::process::Command;
+    /// use tokio::process::Command;
     /// use aurelius::Server;
     ///
-    /// let mut server = Server::bind("localhost:0")?;
+    /// # async fn run() -> std::io::Result<()> {
+    /// let mut server = Server::bind("localhost:0").await?;
     ///
     /// let mut pandoc = Command::new("pandoc");
     /// pandoc.args(&["-f", "markdown", "-t", "html"]);
     ///
     /// server.set_external_renderer(pandoc);
-    /// # Ok::<_, Box<dyn std::error::Error>>(())
-    ///
+    /// # Ok(())
+    /// # }
     /// ```
     ///
     /// [`pulldown_cmark`]: https://github.com/raphlinus/pulldown-cmark
     /// [CommonMark]: https://commonmark.org/
     /// [`pandoc`]: https://pandoc.org/
-    //pub fn set_external_renderer(&mut self, mut command: Command) {
-    //    command
-    //        .stdin(Stdio::piped())
-    //        .stdout(Stdio::piped())
-    //        .stderr(Stdio::null());
-    //    self.external_renderer = Some(command);
-    //}
+    pub fn set_external_renderer(&mut self, command: Command) {
+        self.set_renderer(ExternalCommand::from(command.as_std()));
+    }
+
+    /// Set the renderer used for markdown. See the [`render`] module for the ones available.
+    ///
+    /// Defaults to [`PulldownCmark`].
+    pub fn set_renderer(&mut self, renderer: impl Renderer + 'static) {
+        *self.renderer.lock().unwrap() = Box::new(renderer);
+        self.last_render = None;
+    }
 
     /// Opens the user's default browser with the server's URL in the background.
     ///
@@ -332,7 +554,12 @@ impl Server {
     /// | Linux    | `xdg-open` |
     /// | OS X     | `open -g`  |
     /// | Windows  | `explorer` |
-    pub fn open_browser(&self) -> io::Result<()> {
+    pub async fn open_browser(&self) -> io::Result<()> {
+        self.open_browser_to(DEFAULT_CHANNEL).await
+    }
+
+    /// Opens the user's default browser with the preview of `channel`, like `open_browser`.
+    pub async fn open_browser_to(&self, channel: &str) -> io::Result<()> {
         let command = if cfg!(target_os = "macos") {
             let mut command = Command::new("open");
             command.arg("-g");
@@ -343,20 +570,95 @@ impl Server {
             Command::new("xdg-open")
         };
 
-        self.open_specific_browser(command)
+        spawn_browser(command, &self.channel_url(channel))
     }
 
-    /// Opens a browser with a specified command. The HTTP address of the server will be appended
-    /// to the command as an argument.
-    pub fn open_specific_browser(&self, mut command: Command) -> io::Result<()> {
-        command.arg(&format!("http://{}", self.addr()));
+    /// Opens a browser with a specified command. The URL of the server will be appended to the
+    /// command as an argument.
+    pub async fn open_specific_browser(&self, command: Command) -> io::Result<()> {
+        spawn_browser(command, &self.url())
+    }
 
-        command.stdout(Stdio::null()).stderr(Stdio::null());
+    /// Stops the server.
+    ///
+    /// Open previews are sent a close frame, no new connections are accepted, and this waits for
+    /// the connections to finish. Dropping the server does the same, but blocks the thread it is
+    /// dropped on while it waits.
+    pub async fn shutdown(self) {
+        let _ = task::spawn_blocking(move || drop(self)).await;
+    }
+}
 
-        info!("spawning browser: {:?}", command);
-        command.spawn()?;
-        Ok(())
+/// The preview page: the template set with `Server::set_template`, or the built-in one.
+fn page(config: &Config) -> String {
+    #[derive(Debug, Serialize)]
+    struct Data<'a> {
+        remote_custom_css: &'a [Url],
+        local_custom_css: &'a [String],
+        highlight_theme: &'a str,
+        theme: Theme,
+        direction: Direction,
+        dark: bool,
+        user_css: Option<String>,
+        title: &'a str,
+        css: String,
+        body: String,
+        scripts: String,
     }
+
+    let user_css = config.user_stylesheet.as_ref().and_then(|path| {
+        fs::read_to_string(path)
+            .map_err(|e| warn!("could not read {}: {}", path.display(), e))
+            .ok()
+    });
+    let mut data = Data {
+        remote_custom_css: &config.css_links,
+        local_custom_css: &config.custom_styles,
+        highlight_theme: &config.highlight_theme,
+        theme: config.theme,
+        direction: config.direction,
+        dark: config.theme == Theme::Dark,
+        user_css,
+        // The preview takes its title from the first heading once it's rendered.
+        title: "Markdown Composer",
+        css: String::new(),
+        body: String::new(),
+        scripts: String::new(),
+    };
+    let handlebars = Handlebars::new();
+    let render = |template: &str, data: &Data| {
+        handlebars
+            .render_template(template, data)
+            .expect("invalid template syntax")
+    };
+    data.css = render(include_str!("../templates/css.html"), &data);
+    data.body = render(include_str!("../templates/body.html"), &data);
+    data.scripts = render(include_str!("../templates/scripts.html"), &data);
+
+    if let Some(path) = &config.template {
+        let rendered = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|template| {
+                handlebars
+                    .render_template(&template, &data)
+                    .map_err(|e| e.to_string())
+            });
+        match rendered {
+            Ok(html) => return html,
+            Err(e) => warn!("could not use the template {}: {}", path.display(), e),
+        }
+    }
+    render(include_str!("../templates/markdown_view.html"), &data)
+}
+
+fn spawn_browser(mut command: Command, url: &Url) -> io::Result<()> {
+    command.arg(url.as_str());
+
+    command.stdout(Stdio::null()).stderr(Stdio::null());
+
+    info!("spawning browser: {:?}", command);
+    command.spawn()?;
+    Ok(())
 }
 
 impl Drop for Server {
@@ -366,38 +668,114 @@ impl Drop for Server {
 
         // Shutdown all websocket connections.
         {
-            let clients = std::mem::take(&mut *self.md_clients.lock().unwrap());
+            let channels = std::mem::take(&mut *self.channels.lock().unwrap());
 
-            for client in clients.values() {
-                client.send(Signal::Close).unwrap();
+            for client in channels
+                .values()
+                .flat_map(|channel| channel.clients.values())
+            {
+                let _ = client.send(Signal::Close);
             }
         }
 
-        // Wait for connection threads to complete.
-        self.listener_join_handle.take().unwrap().join().unwrap();
+        // Wait for connection threads to complete. A panic on one of them has been reported
+        // already, and shouldn't take down whoever is dropping the server.
+        let _ = self.listener_join_handle.take().unwrap().join();
     }
 }
 
+/// The channel used by the methods that don't take one, served at the root URL.
+pub const DEFAULT_CHANNEL: &str = "";
+
+/// Markdown published on a channel, and the previews showing it.
+#[derive(Debug, Default)]
+struct Channel {
+    html: Option<Arc<str>>,
+    /// URL path that relative links in the HTML are resolved against.
+    base_url: Option<String>,
+    /// Number of lines in the markdown last sent.
+    line_count: usize,
+    /// Line the previews were last scrolled to, so new ones open at the same place.
+    scroll_line: Option<u32>,
+    clients: IdMap<Sender<Signal>>,
+}
+
+#[derive(Debug, Clone)]
 enum Signal {
-    NewMarkdown,
+    Html(Arc<str>),
+    BaseUrl(Option<String>),
+    Scroll { line: u32, line_count: usize },
     Close,
 }
 
+/// Color scheme of the preview page.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// Dark text on a light background.
+    #[default]
+    Light,
+    /// Light text on a dark background.
+    Dark,
+}
+
+/// Direction of text, for scripts like Latin that run left to right and ones like Arabic and
+/// Hebrew that run right to left.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Left to right.
+    #[default]
+    Ltr,
+    /// Right to left.
+    Rtl,
+}
+
+impl Direction {
+    /// The value of an HTML `dir` attribute for the direction.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Direction::Ltr => "ltr",
+            Direction::Rtl => "rtl",
+        }
+    }
+}
+
+/// Produces the text served at `/__/metrics`.
+#[derive(Clone)]
+struct MetricsSource(Arc<dyn Fn() -> String + Send + Sync>);
+
+impl fmt::Debug for MetricsSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MetricsSource")
+    }
+}
+
 #[derive(Debug)]
 struct Config {
-    static_root: Option<PathBuf>,
+    static_roots: StaticRoots,
     highlight_theme: String,
+    theme: Theme,
+    direction: Direction,
     css_links: Vec<Url>,
     custom_styles: Vec<String>,
+    user_stylesheet: Option<PathBuf>,
+    template: Option<PathBuf>,
+    metrics: Option<MetricsSource>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
-            static_root: None,
+            static_roots: StaticRoots::default(),
             highlight_theme: String::from("github"),
+            theme: Theme::default(),
+            direction: Direction::default(),
             css_links: vec![],
             custom_styles: vec![],
+            user_stylesheet: None,
+            template: None,
+            metrics: None,
         }
     }
 }
@@ -406,8 +784,7 @@ impl Default for Config {
 struct Handler {
     conn: TcpStream,
     config: Arc<Mutex<Config>>,
-    md_clients: Arc<Mutex<IdMap<Sender<Signal>>>>,
-    html: Arc<RwLock<Option<String>>>,
+    channels: Arc<Mutex<HashMap<String, Channel>>>,
 }
 
 impl Handler {
@@ -486,26 +863,43 @@ impl Handler {
 
         let (md_tx, md_rx) = crossbeam_channel::unbounded();
 
-        let client_id = self.md_clients.lock().unwrap().insert(md_tx);
+        let channel_name = channel_name(req.path.unwrap_or("/"));
+        let (client_id, html, base_url, scroll) = {
+            let mut channels = self.channels.lock().unwrap();
+            let channel = channels.entry(channel_name.clone()).or_default();
+            (
+                channel.clients.insert(md_tx),
+                channel.html.clone(),
+                channel.base_url.clone(),
+                channel.scroll_line.map(|line| (line, channel.line_count)),
+            )
+        };
 
         let mut writer = WebSocket::from_raw_socket(self.conn.try_clone()?, Role::Server, None);
         let mut reader = WebSocket::from_raw_socket(self.conn, Role::Server, None);
 
-        // If there's HTML already present, send it to the client.
-        {
-            let html = self.html.read().unwrap();
-            if let Some(html) = html.as_ref() {
-                writer.write_message(Message::text(html))?;
+        // If there's HTML already present, send it to the client, so a reloaded page or a second
+        // tab shows the document without waiting for the next update.
+        if base_url.is_some() {
+            writer.write_message(Message::text(base_url_message(base_url.as_deref())))?;
+        }
+        if let Some(html) = html {
+            writer.write_message(Message::text(&*html))?;
+            if let Some((line, line_count)) = scroll {
+                writer.write_message(Message::text(scroll_message(line, line_count)))?;
             }
         }
 
-        let clients = Arc::clone(&self.md_clients);
+        let channels = Arc::clone(&self.channels);
         thread::spawn(move || loop {
             match reader.read_message() {
                 Err(_) => break,
                 Ok(Message::Close(_)) => {
-                    // The client may already be dropped by the time we get here.
-                    clients.lock().unwrap().remove(client_id);
+                    // The client or its whole channel may already be dropped by the time we get
+                    // here.
+                    if let Some(channel) = channels.lock().unwrap().get_mut(&channel_name) {
+                        channel.clients.remove(client_id);
+                    }
                     break;
                 }
                 Ok(_) => (),
@@ -523,9 +917,22 @@ impl Handler {
                         break;
                     }
 
-                    let html = self.html.read().unwrap();
-                    writer.write_message(Message::text(html.as_ref().expect("no HTML present")))?;
-                    writer.write_pending()?;
+                    if let Ok(Signal::Scroll { line, line_count }) = msg {
+                        writer.write_message(Message::text(scroll_message(line, line_count)))?;
+                        writer.write_pending()?;
+                        continue;
+                    }
+
+                    if let Ok(Signal::BaseUrl(base_url)) = msg {
+                        writer.write_message(Message::text(base_url_message(base_url.as_deref())))?;
+                        writer.write_pending()?;
+                        continue;
+                    }
+
+                    if let Ok(Signal::Html(html)) = msg {
+                        writer.write_message(Message::text(&*html))?;
+                        writer.write_pending()?;
+                    }
                 }
             }
         }
@@ -534,34 +941,38 @@ impl Handler {
     }
 
     fn serve_http(&mut self, req: Request) -> io::Result<()> {
+        // The query only matters to the page's script, which picks the channel from it.
         let path = req.path.unwrap();
-
-        if path.starts_with("/__/") {
+        let path = path.split_once('?').map_or(path, |(path, _)| path);
+
+        if path == "/__/metrics" {
+            let metrics = self.config.lock().unwrap().metrics.clone();
+            match metrics {
+                Some(MetricsSource(metrics)) => {
+                    let text = metrics();
+                    write!(self.conn, "HTTP/1.1 200 OK\r\n")?;
+                    write!(self.conn, "Connection: close\r\n")?;
+                    write!(self.conn, "Content-Type: text/plain; version=0.0.4\r\n")?;
+                    write!(self.conn, "Cache-Control: no-store\r\n")?;
+                    write!(self.conn, "\r\n")?;
+                    self.conn.write_all(text.as_bytes())?;
+                }
+                None => write!(self.conn, "HTTP/1.1 404 Not Found\r\n\r\n")?,
+            }
+        } else if path.starts_with("/__/") {
             let path = path.trim_start_matches("/__/");
 
+            // Bundled files only change with the crate, and every server has its own origin.
             match STATIC_FILES.get_file(path) {
-                Some(file) => self.write_file_contents(file.path, file.contents)?,
+                Some(file) => self.write_file_contents(
+                    file.path,
+                    file.contents,
+                    &[("Cache-Control", "max-age=3600")],
+                )?,
                 None => write!(self.conn, "HTTP/1.1 404 Not Found\r\n\r\n")?,
             }
         } else if path == "/" {
-            #[derive(Debug, Serialize)]
-            struct Data<'a> {
-                remote_custom_css: &'a [Url],
-                local_custom_css: &'a [String],
-                highlight_theme: &'a str,
-            }
-
-            let html = {
-                let config = self.config.lock().unwrap();
-                let data = Data {
-                    remote_custom_css: &config.css_links,
-                    local_custom_css: &config.custom_styles,
-                    highlight_theme: &config.highlight_theme,
-                };
-                Handlebars::new()
-                    .render_template(include_str!("../templates/markdown_view.html"), &data)
-                    .expect("invalid template syntax")
-            };
+            let html = page(&self.config.lock().unwrap());
 
             write!(self.conn, "HTTP/1.1 200 OK\r\n")?;
             write!(self.conn, "Connection: close\r\n")?;
@@ -569,17 +980,15 @@ impl Handler {
             write!(self.conn, "\r\n")?;
             self.conn.write_all(html.as_bytes())?;
         } else {
-            let root = self
-                .config
-                .lock()
-                .unwrap()
-                .deref()
-                .static_root
-                .clone()
-                .map(|root| root.join(&url_path_to_file_path(path)));
-
-            match root {
-                Some(file_path) => self.write_file(&file_path)?,
+            let file_path = self.config.lock().unwrap().static_roots.resolve(path);
+            let if_none_match = req
+                .headers
+                .iter()
+                .find(|header| header.name.eq_ignore_ascii_case("If-None-Match"))
+                .and_then(|header| std::str::from_utf8(header.value).ok());
+
+            match file_path {
+                Some(file_path) => self.write_file(&file_path, if_none_match)?,
                 None => write!(self.conn, "HTTP/1.1 404 Not Found\r\n\r\n")?,
             }
         }
@@ -589,13 +998,22 @@ impl Handler {
         Ok(())
     }
 
-    fn write_file_contents(&mut self, path: impl AsRef<Path>, contents: &[u8]) -> io::Result<()> {
+    fn write_file_contents(
+        &mut self,
+        path: impl AsRef<Path>,
+        contents: &[u8],
+        headers: &[(&str, &str)],
+    ) -> io::Result<()> {
         write!(self.conn, "HTTP/1.1 200 OK\r\n")?;
 
         if let Some(mime_type) = mime_guess::from_path(path.as_ref()).first() {
             write!(self.conn, "Content-Type: {}\r\n", mime_type)?;
         }
 
+        for (name, value) in headers {
+            write!(self.conn, "{}: {}\r\n", name, value)?;
+        }
+
         write!(self.conn, "Connection: close\r\n")?;
         write!(self.conn, "\r\n")?;
         self.conn.write_all(contents)?;
@@ -603,11 +1021,42 @@ impl Handler {
         Ok(())
     }
 
-    fn write_file(&mut self, path: &Path) -> io::Result<()> {
-        if let Ok(contents) = fs::read(&path) {
-            self.write_file_contents(path, &contents)?;
-        } else {
-            write!(self.conn, "HTTP/1.1 404 Not Found\r\n\r\n")?;
+    /// Send the file at `path`, or tell the client its copy is still good if `if_none_match` has
+    /// the file's current tag.
+    fn write_file(&mut self, path: &Path, if_none_match: Option<&str>) -> io::Result<()> {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => {
+                write!(self.conn, "HTTP/1.1 404 Not Found\r\n\r\n")?;
+                return Ok(());
+            }
+        };
+
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_nanos());
+        let etag = format!("\"{:x}-{:x}\"", metadata.len(), modified);
+
+        let unchanged = if_none_match.is_some_and(|tags| {
+            tags.split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        });
+        if unchanged {
+            write!(self.conn, "HTTP/1.1 304 Not Modified\r\n")?;
+            write!(self.conn, "ETag: {}\r\n", etag)?;
+            write!(self.conn, "Connection: close\r\n\r\n")?;
+            return Ok(());
+        }
+
+        match fs::read(path) {
+            Ok(contents) => self.write_file_contents(
+                path,
+                &contents,
+                &[("Cache-Control", "no-cache"), ("ETag", &etag)],
+            )?,
+            Err(_) => write!(self.conn, "HTTP/1.1 404 Not Found\r\n\r\n")?,
         }
 
         Ok(())
@@ -624,21 +1073,43 @@ fn websocket_accept(key: &[u8]) -> String {
     base64::encode(&accept.result())
 }
 
-fn url_path_to_file_path(path: &str) -> PathBuf {
-    path.trim_start_matches('/').split('/').collect()
+/// The channel named in the query of a request target like `/?channel=notes`.
+fn channel_name(target: &str) -> String {
+    let query = target.split_once('?').map_or("", |(_, query)| query);
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "channel")
+        .map(|(_, channel)| channel.into_owned())
+        .unwrap_or_else(|| DEFAULT_CHANNEL.to_owned())
+}
+
+/// The message telling the client to scroll to `line` of a document `line_count` lines long.
+fn scroll_message(line: u32, line_count: usize) -> String {
+    // Rendered HTML won't start like this, so the client can tell them apart.
+    format!(r#"{{"scrollToLine":{},"lineCount":{}}}"#, line, line_count)
+}
+
+/// The message telling the client what relative links resolve against.
+fn base_url_message(base_url: Option<&str>) -> String {
+    // Base URLs are built from percent-encoded segments, so they never need escaping.
+    match base_url {
+        Some(base_url) => format!(r#"{{"baseUrl":"{}"}}"#, base_url),
+        None => String::from(r#"{"baseUrl":null}"#),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::error::Error;
     use std::io::{Read, Write};
-    use std::path::{Path, PathBuf};
+    use std::path::Path;
 
     use matches::assert_matches;
+    use tokio::process::Command;
     use tungstenite::handshake::client::Request;
     use tungstenite::Message;
     use tungstenite::WebSocket;
 
+    use super::render::PulldownCmark;
     use super::Server;
 
     fn assert_websocket_closed<S: Read + Write>(websocket: &mut WebSocket<S>) {
@@ -651,31 +1122,43 @@ mod tests {
         }
     }
 
-    #[test]
-    fn uri_path_to_file_path() {
-        assert_eq!(
-            super::url_path_to_file_path("/file.txt"),
-            Path::new("file.txt")
-        );
-        assert_eq!(
-            super::url_path_to_file_path("/a/b/c/d"),
-            vec!["a", "b", "c", "d"].iter().collect::<PathBuf>(),
-        );
+    #[tokio::test]
+    async fn connect_http() -> Result<(), Box<dyn Error>> {
+        let server = Server::bind("localhost:0").await?;
+        let addr = server.addr();
+        assert_eq!(server.url().as_str(), format!("http://{}/", addr));
+
+        reqwest::get(server.url()).await?;
+
+        Ok(())
     }
 
-    #[test]
-    fn connect_http() -> Result<(), Box<dyn Error>> {
-        let server = Server::bind("localhost:0")?;
-        let addr = server.addr();
+    #[tokio::test]
+    async fn connect_http_on_every_interface() -> Result<(), Box<dyn Error>> {
+        let server = Server::bind("0.0.0.0:0").await?;
+        let port = server.addr().port();
+        assert_eq!(server.url().as_str(), format!("http://127.0.0.1:{}/", port));
 
-        reqwest::blocking::get(&format!("http://{}", addr))?;
+        reqwest::get(server.url()).await?;
 
         Ok(())
     }
 
-    #[test]
-    fn connect_websocket() -> Result<(), Box<dyn Error>> {
-        let server = Server::bind("localhost:0")?;
+    #[tokio::test]
+    async fn serve_metrics() -> Result<(), Box<dyn Error>> {
+        let mut server = Server::bind("localhost:0").await?;
+        let url = server.metrics_url();
+        assert_eq!(reqwest::get(url.clone()).await?.status(), 404);
+
+        server.set_metrics(Some(Box::new(|| String::from("requests_total 3\n"))));
+        assert_eq!(reqwest::get(url).await?.text().await?, "requests_total 3\n");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn connect_websocket() -> Result<(), Box<dyn Error>> {
+        let server = Server::bind("localhost:0").await?;
         let addr = server.addr();
 
         let req = Request {
@@ -688,18 +1171,18 @@ mod tests {
         Ok(())
     }
 
-    #[test]
-    fn send_with_no_clients() -> Result<(), Box<dyn Error>> {
-        let mut server = Server::bind("localhost:0")?;
+    #[tokio::test]
+    async fn send_with_no_clients() -> Result<(), Box<dyn Error>> {
+        let mut server = Server::bind("localhost:0").await?;
 
-        server.send(String::from("This shouldn't hang")).unwrap();
+        server.send("This shouldn't hang").await.unwrap();
 
         Ok(())
     }
 
-    #[test]
-    fn send_html() -> Result<(), Box<dyn Error>> {
-        let mut server = Server::bind("localhost:0")?;
+    #[tokio::test]
+    async fn send_html() -> Result<(), Box<dyn Error>> {
+        let mut server = Server::bind("localhost:0").await?;
         let addr = server.addr();
 
         let req = Request {
@@ -709,21 +1192,42 @@ mod tests {
 
         let (mut websocket, _) = tungstenite::connect(req)?;
 
-        server.send(String::from("<p>Hello, world!</p>"))?;
+        server.send("<p>Hello, world!</p>").await?;
         let message = websocket.read_message()?;
         assert_eq!(message.to_text()?, "<p>Hello, world!</p>");
 
-        server.send(String::from("<p>Goodbye, world!</p>"))?;
+        server.send("<p>Goodbye, world!</p>").await?;
         let message = websocket.read_message()?;
         assert_eq!(message.to_text()?, "<p>Goodbye, world!</p>");
 
         Ok(())
     }
 
-    #[test]
-    fn send_markdown() -> Result<(), Box<dyn Error>> {
-        let mut server = Server::bind("localhost:0")?;
+    #[tokio::test]
+    async fn send_markdown() -> Result<(), Box<dyn Error>> {
+        let mut server = Server::bind("localhost:0").await?;
+        let addr = server.addr();
+
+        let req = Request {
+            url: format!("ws://{}", addr).parse()?,
+            extra_headers: None,
+        };
+
+        let (mut websocket, _) = tungstenite::connect(req)?;
+
+        server.send("*Hello*").await?;
+        let message = websocket.read_message()?;
+        assert_eq!(message.to_text()?.trim(), "<p><em>Hello</em></p>");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn send_markdown_with_external_renderer() -> Result<(), Box<dyn Error>> {
+        let mut server = Server::bind("localhost:0").await?;
         let addr = server.addr();
+        server.set_external_renderer(Command::new("cat"));
 
         let req = Request {
             url: format!("ws://{}", addr).parse()?,
@@ -732,16 +1236,158 @@ mod tests {
 
         let (mut websocket, _) = tungstenite::connect(req)?;
 
-        server.send(String::from("*Hello*"))?;
+        server.send("*Hello*").await?;
+        let message = websocket.read_message()?;
+        assert_eq!(message.to_text()?, "*Hello*");
+
+        server.set_renderer(PulldownCmark::default());
+        server.send("*Hello*").await?;
         let message = websocket.read_message()?;
         assert_eq!(message.to_text()?.trim(), "<p><em>Hello</em></p>");
 
         Ok(())
     }
 
+    #[tokio::test]
+    async fn scroll_to_line() -> Result<(), Box<dyn Error>> {
+        let mut server = Server::bind("localhost:0").await?;
+        let addr = server.addr();
+
+        let req = Request {
+            url: format!("ws://{}", addr).parse()?,
+            extra_headers: None,
+        };
+
+        let (mut websocket, _) = tungstenite::connect(req)?;
+
+        server.send("# Title\n\ntext").await?;
+        websocket.read_message()?;
+
+        server.scroll_to_line(2);
+        let message = websocket.read_message()?;
+        assert_eq!(message.to_text()?, r#"{"scrollToLine":2,"lineCount":3}"#);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn relative_links_resolve_against_the_file() -> Result<(), Box<dyn Error>> {
+        let mut server = Server::bind("localhost:0").await?;
+        server.set_static_root("/vault");
+        server
+            .send_file("# Old", Path::new("/vault/daily notes/today.md"))
+            .await?;
+
+        let req = Request {
+            url: format!("ws://{}", server.addr()).parse()?,
+            extra_headers: None,
+        };
+        let (mut websocket, _) = tungstenite::connect(req)?;
+
+        let message = websocket.read_message()?;
+        assert_eq!(message.to_text()?, r#"{"baseUrl":"/daily%20notes/"}"#);
+        assert_eq!(websocket.read_message()?.to_text()?.trim(), "<h1>Old</h1>");
+
+        // The base only changes with the file's directory.
+        server
+            .send_file("# New", Path::new("/vault/daily notes/today.md"))
+            .await?;
+        assert_eq!(websocket.read_message()?.to_text()?.trim(), "<h1>New</h1>");
+
+        server.send("# Unsaved").await?;
+        let message = websocket.read_message()?;
+        assert_eq!(message.to_text()?, r#"{"baseUrl":null}"#);
+        assert_eq!(
+            websocket.read_message()?.to_text()?.trim(),
+            "<h1>Unsaved</h1>"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn new_clients_get_the_last_render() -> Result<(), Box<dyn Error>> {
+        let mut server = Server::bind("localhost:0").await?;
+        let addr = server.addr();
+        let req = || Request {
+            url: format!("ws://{}", addr).parse().unwrap(),
+            extra_headers: None,
+        };
+        let (mut first, _) = tungstenite::connect(req())?;
+
+        server.send("# Title\n\ntext").await?;
+        first.read_message()?;
+        server.scroll_to_line(2);
+        first.read_message()?;
+
+        // A second tab, or the first one reloaded, opens where the first one is.
+        let (mut second, _) = tungstenite::connect(req())?;
+        assert_eq!(
+            second.read_message()?.to_text()?.trim(),
+            "<h1>Title</h1>\n<p>text</p>"
+        );
+        assert_eq!(
+            second.read_message()?.to_text()?,
+            r#"{"scrollToLine":2,"lineCount":3}"#
+        );
+
+        Ok(())
+    }
+
     #[test]
-    fn close_websockets_on_drop() -> Result<(), Box<dyn Error>> {
-        let server = Server::bind("localhost:0")?;
+    fn channel_names() {
+        assert_eq!(super::channel_name("/"), "");
+        assert_eq!(super::channel_name("/?channel=notes%2Fa.md"), "notes/a.md");
+        assert_eq!(super::channel_name("/?x=1&channel=b"), "b");
+    }
+
+    #[tokio::test]
+    async fn channels_are_previewed_separately() -> Result<(), Box<dyn Error>> {
+        let mut server = Server::bind("localhost:0").await?;
+        let channel_url = server.channel_url("notes/a b.md");
+        assert_eq!(channel_url.query(), Some("channel=notes%2Fa+b.md"));
+        assert_eq!(server.channel_url(""), server.url());
+
+        let default_req = Request {
+            url: format!("ws://{}", server.addr()).parse()?,
+            extra_headers: None,
+        };
+        let channel_req = Request {
+            url: format!("ws://{}/?{}", server.addr(), channel_url.query().unwrap()).parse()?,
+            extra_headers: None,
+        };
+        let (mut default, _) = tungstenite::connect(default_req)?;
+        let (mut channel, _) = tungstenite::connect(channel_req)?;
+
+        server.send_to("notes/a b.md", "# A").await?;
+        server.send("# Default").await?;
+        assert_eq!(channel.read_message()?.to_text()?.trim(), "<h1>A</h1>");
+        assert_eq!(
+            default.read_message()?.to_text()?.trim(),
+            "<h1>Default</h1>"
+        );
+
+        server.scroll_to_line_in("notes/a b.md", 0);
+        let message = channel.read_message()?;
+        assert_eq!(message.to_text()?, r#"{"scrollToLine":0,"lineCount":1}"#);
+
+        assert_eq!(server.viewers_in("notes/a b.md"), 1);
+        server.close_channel("notes/a b.md");
+        assert_websocket_closed(&mut channel);
+        assert_eq!(server.viewers_in("notes/a b.md"), 0);
+
+        server.send("# Still here").await?;
+        assert_eq!(
+            default.read_message()?.to_text()?.trim(),
+            "<h1>Still here</h1>"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn close_websockets_on_drop() -> Result<(), Box<dyn Error>> {
+        let server = Server::bind("localhost:0").await?;
         let addr = server.addr();
 
         let req = Request {
@@ -758,12 +1404,32 @@ mod tests {
         Ok(())
     }
 
-    #[test]
-    fn queue_html_if_no_clients() -> Result<(), Box<dyn Error>> {
-        let mut server = Server::bind("localhost:0")?;
+    #[tokio::test]
+    async fn close_websockets_on_shutdown() -> Result<(), Box<dyn Error>> {
+        let server = Server::bind("localhost:0").await?;
         let addr = server.addr();
 
-        server.send(String::from("# Markdown"))?;
+        let req = Request {
+            url: format!("ws://{}", addr).parse()?,
+            extra_headers: None,
+        };
+
+        let (mut websocket, _) = tungstenite::connect(req).unwrap();
+
+        server.shutdown().await;
+
+        assert_websocket_closed(&mut websocket);
+        assert!(std::net::TcpStream::connect(addr).is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn queue_html_if_no_clients() -> Result<(), Box<dyn Error>> {
+        let mut server = Server::bind("localhost:0").await?;
+        let addr = server.addr();
+
+        server.send("# Markdown").await?;
 
         let req = Request {
             url: format!("ws://{}", addr).parse()?,
@@ -782,9 +1448,9 @@ mod tests {
         Ok(())
     }
 
-    #[test]
-    fn closed_websocket_removed_from_clients() -> Result<(), Box<dyn Error>> {
-        let mut server = Server::bind("localhost:0")?;
+    #[tokio::test]
+    async fn closed_websocket_removed_from_clients() -> Result<(), Box<dyn Error>> {
+        let mut server = Server::bind("localhost:0").await?;
         let addr = server.addr();
 
         let req = Request {
@@ -799,7 +1465,7 @@ mod tests {
 
         assert_websocket_closed(&mut websocket);
 
-        server.send(String::from("# Markdown")).unwrap();
+        server.send("# Markdown").await.unwrap();
 
         assert_matches!(
             websocket.read_message(),
@@ -0,0 +1,249 @@
+//! TeX math between dollar signs.
+//!
+//! pulldown-cmark doesn't know about math, so `$a_1 * b_1$` would come out with emphasis in it.
+//! Math is cut out of the markdown before it is parsed, leaving placeholders that are replaced in
+//! the HTML by `<x-equation>` elements, like the ones md4c writes, which the page renders with
+//! KaTeX.
+//!
+//! The rules are pandoc's: `$$` starts display math, and `$` starts inline math if it is followed
+//! by a non-space character and closed by a `$` that follows a non-space character and isn't
+//! followed by a digit, so "$5 and $10" is left alone. Neither may span a blank line. `\$` is a
+//! literal dollar sign.
+
+use std::ops::Range;
+
+/// Start of a placeholder, followed by the equation's number.
+const START: char = '\u{E000}';
+/// End of a placeholder.
+const END: char = '\u{E001}';
+/// Starts every line after the first of a placeholder, which keeps the equation's newlines so
+/// that lines of the source still line up, without leaving blank lines in it.
+const CONTINUATION: char = '\u{E002}';
+
+/// An equation cut out of the markdown.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Equation {
+    pub tex: String,
+    pub display: bool,
+}
+
+/// The end of the math starting at `start`, which holds a `$`, and whether it is display math.
+fn find_math(markdown: &str, start: usize) -> Option<(usize, bool)> {
+    let rest = &markdown[start..];
+    let (open, display) = if rest.starts_with("$$") {
+        (2, true)
+    } else {
+        (1, false)
+    };
+    let body = &rest[open..];
+    if !display && body.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\n' if body[i + 1..]
+                .trim_start_matches([' ', '\t'])
+                .starts_with('\n') =>
+            {
+                return None
+            }
+            '$' if display && i > 0 && body[i..].starts_with("$$") => {
+                return Some((start + open + i + 2, true));
+            }
+            '$' if display => (),
+            '$' => {
+                let after_digit = body[i + 1..].starts_with(|c: char| c.is_ascii_digit());
+                let after_space = body[..i].ends_with(char::is_whitespace);
+                if !after_digit && !after_space {
+                    return Some((start + open + i + 1, false));
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Replace the math in `markdown` with placeholders, leaving the `code` ranges alone.
+///
+/// Returns the new markdown and the equations, in the order of their placeholders.
+pub(crate) fn extract(markdown: &str, code: &[Range<usize>]) -> (String, Vec<Equation>) {
+    let mut out = String::with_capacity(markdown.len());
+    let mut equations = Vec::new();
+    let mut copied = 0;
+    let mut i = 0;
+    while i < markdown.len() {
+        if let Some(range) = code.iter().find(|range| range.contains(&i)) {
+            i = range.end;
+            continue;
+        }
+        let c = markdown[i..].chars().next().unwrap();
+        match c {
+            '\\' => {
+                i += 1 + markdown[i + 1..].chars().next().map_or(0, char::len_utf8);
+                continue;
+            }
+            '$' => {
+                if let Some((end, display)) = find_math(markdown, i) {
+                    let delimiter = if display { 2 } else { 1 };
+                    let tex = &markdown[i + delimiter..end - delimiter];
+                    out.push_str(&markdown[copied..i]);
+                    out.push(START);
+                    out.push_str(&equations.len().to_string());
+                    for _ in tex.matches('\n') {
+                        out.push('\n');
+                        out.push(CONTINUATION);
+                    }
+                    out.push(END);
+                    equations.push(Equation {
+                        tex: tex.trim().to_string(),
+                        display,
+                    });
+                    copied = end;
+                    i = end;
+                    continue;
+                }
+                // Skip the rest of a `$$` that doesn't start math, so it can't end some later.
+                i += markdown[i..].len() - markdown[i..].trim_start_matches('$').len();
+                continue;
+            }
+            _ => i += c.len_utf8(),
+        }
+    }
+    out.push_str(&markdown[copied..]);
+    (out, equations)
+}
+
+fn escape(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+/// Replace the placeholders in `html` with the `equations` they stand for.
+///
+/// Placeholders inside tags, like the `alt` text of images, get the TeX back as text.
+pub(crate) fn restore(html: &str, equations: &[Equation]) -> String {
+    if equations.is_empty() {
+        return html.to_string();
+    }
+
+    let mut out = String::with_capacity(html.len());
+    let mut in_tag = false;
+    let mut rest = html;
+    while let Some(start) = rest.find([START, '<', '>']) {
+        let c = rest[start..].chars().next().unwrap();
+        out.push_str(&rest[..start]);
+        rest = &rest[start + c.len_utf8()..];
+        if c != START {
+            in_tag = c == '<';
+            out.push(c);
+            continue;
+        }
+
+        let end = rest.find(END).unwrap_or(rest.len());
+        let number = rest[..end].split(['\n', CONTINUATION]).next().unwrap();
+        rest = &rest[(end + END.len_utf8()).min(rest.len())..];
+        let equation = match number.parse::<usize>().ok().and_then(|n| equations.get(n)) {
+            Some(equation) => equation,
+            None => continue,
+        };
+
+        let delimiter = if equation.display { "$$" } else { "$" };
+        if in_tag {
+            out.push_str(delimiter);
+            escape(&equation.tex, &mut out);
+            out.push_str(delimiter);
+        } else {
+            out.push_str(if equation.display {
+                "<x-equation type=\"display\">"
+            } else {
+                "<x-equation>"
+            });
+            escape(&equation.tex, &mut out);
+            out.push_str("</x-equation>");
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract, restore, Equation};
+
+    fn equations(markdown: &str) -> Vec<Equation> {
+        extract(markdown, &[]).1
+    }
+
+    fn inline(tex: &str) -> Equation {
+        Equation {
+            tex: tex.to_string(),
+            display: false,
+        }
+    }
+
+    fn display(tex: &str) -> Equation {
+        Equation {
+            tex: tex.to_string(),
+            display: true,
+        }
+    }
+
+    #[test]
+    fn dollar_delimiters() {
+        assert_eq!(equations("so $a_1 * b_1$ holds"), vec![inline("a_1 * b_1")]);
+        assert_eq!(
+            equations("$$\n\\sum_i x_i\n$$ and $y$"),
+            vec![display("\\sum_i x_i"), inline("y")]
+        );
+        assert_eq!(equations("$5 and $10"), vec![]);
+        assert_eq!(equations("$ a $"), vec![]);
+        assert_eq!(equations("a \\$ b $c$"), vec![inline("c")]);
+        assert_eq!(equations("$\\$$"), vec![inline("\\$")]);
+        assert_eq!(equations("$a\n\nb$"), vec![]);
+        assert_eq!(equations("$$ a\nb $$"), vec![display("a\nb")]);
+    }
+
+    #[test]
+    fn code_is_left_alone() {
+        let markdown = "`$a$` $b$";
+        let (markdown, equations) = extract(markdown, &[0..5]);
+        assert_eq!(markdown, "`$a$` \u{E000}0\u{E001}");
+        assert_eq!(equations, vec![inline("b")]);
+    }
+
+    #[test]
+    fn placeholders_keep_lines() {
+        let (markdown, _) = extract("a\n$$\nx\n$$\nb", &[]);
+        assert_eq!(markdown.lines().count(), 5);
+        assert!(!markdown.lines().any(str::is_empty));
+    }
+
+    #[test]
+    fn restore_equations() {
+        let (markdown, equations) = extract("$a<b$ $$c$$ $d$", &[]);
+        let html = format!("<p><img alt=\"{}\"></p>", markdown);
+        assert_eq!(
+            restore(&html, &equations),
+            "<p><img alt=\"$a&lt;b$ $$c$$ $d$\"></p>"
+        );
+        let html = format!("<p>{}</p>", markdown);
+        assert_eq!(
+            restore(&html, &equations),
+            "<p><x-equation>a&lt;b</x-equation> <x-equation type=\"display\">c</x-equation> \
+             <x-equation>d</x-equation></p>"
+        );
+    }
+}
@@ -0,0 +1,619 @@
+//! Markdown renderers.
+//!
+//! The server renders markdown with a [`Renderer`]. Three are provided:
+//!
+//! - [`PulldownCmark`], the default, renders in-process.
+//! - [`ExternalCommand`] starts a program for every render. The program reads markdown on stdin
+//!   and writes HTML on stdout, and must exit successfully.
+//! - [`RendererProcess`] starts a program once and keeps sending it documents, which avoids
+//!   paying for process startup on every keystroke. See its documentation for the protocol.
+
+use std::collections::HashSet;
+use std::io::{self, prelude::*, BufReader};
+use std::ops::Range;
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::thread;
+
+use pulldown_cmark::{escape::escape_html, CodeBlockKind, Event, Options, Parser, Tag};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{math, Direction};
+
+/// Renders markdown to HTML.
+pub trait Renderer: Send {
+    /// Render `markdown`. `path` is the file the markdown was read from, if known.
+    fn render(&mut self, markdown: &str, path: Option<&Path>) -> io::Result<String>;
+}
+
+/// Renders markdown in-process with [`pulldown_cmark`].
+///
+/// Footnotes, tables, strikethrough and task lists are enabled. TeX math between dollar signs is
+/// written out as `<x-equation>` elements, which the preview renders with KaTeX, and `mermaid`
+/// code blocks as `<pre class="mermaid">`, which it draws with Mermaid. Top-level blocks whose
+/// text runs the other way from the page, like a paragraph of Arabic in an English note, are
+/// wrapped in a `<div>` with their `dir`.
+///
+/// [`pulldown_cmark`]: https://github.com/raphlinus/pulldown-cmark
+#[derive(Debug, Default)]
+pub struct PulldownCmark {
+    source_lines: bool,
+    heading_ids: bool,
+    direction: Direction,
+}
+
+impl PulldownCmark {
+    /// A renderer that marks where each top-level block starts in the markdown, so the preview
+    /// can be scrolled to a line of the source (see [`Server::scroll_to_line`]).
+    ///
+    /// Every block is preceded by an empty `<span data-source-line="N">`, where `N` is the
+    /// zero-based line the block starts on.
+    ///
+    /// [`Server::scroll_to_line`]: crate::Server::scroll_to_line
+    pub fn with_source_lines() -> Self {
+        PulldownCmark {
+            source_lines: true,
+            ..PulldownCmark::default()
+        }
+    }
+
+    /// Render for a page whose text runs in `direction` (see [`Server::set_direction`]), so that
+    /// the blocks written the other way are the ones marked.
+    ///
+    /// A block's direction is that of the first letter of its text, leaving out code and link
+    /// destinations. Blocks without letters follow the page.
+    ///
+    /// [`Server::set_direction`]: crate::Server::set_direction
+    pub fn with_direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Give headings `id`s, so they can be linked to, GitHub style: the slug of their text, with
+    /// `-1`, `-2` and so on added to the ones an earlier heading already has.
+    ///
+    /// Slugs are lowercase, with spaces turned into `-` and punctuation other than `-` and `_`
+    /// dropped, after normalizing the text to NFC.
+    pub fn with_heading_ids(mut self) -> Self {
+        self.heading_ids = true;
+        self
+    }
+}
+
+const OPTIONS: Options = Options::ENABLE_FOOTNOTES
+    .union(Options::ENABLE_TABLES)
+    .union(Options::ENABLE_STRIKETHROUGH)
+    .union(Options::ENABLE_TASKLISTS);
+
+/// The byte ranges of the code spans and blocks in `markdown`, where dollar signs aren't math.
+fn code_ranges(markdown: &str) -> Vec<Range<usize>> {
+    Parser::new_ext(markdown, OPTIONS)
+        .into_offset_iter()
+        .filter(|(event, _)| matches!(event, Event::Code(_) | Event::Start(Tag::CodeBlock(_))))
+        .map(|(_, range)| range)
+        .collect()
+}
+
+/// Turn fenced `mermaid` code blocks into `<pre class="mermaid">` elements holding the diagram.
+fn mermaid_blocks<'a>(events: impl Iterator<Item = Event<'a>>) -> impl Iterator<Item = Event<'a>> {
+    let mut in_diagram = false;
+    events.map(move |event| match event {
+        Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(ref info)))
+            if info.split_whitespace().next() == Some("mermaid") =>
+        {
+            in_diagram = true;
+            Event::Html("<pre class=\"mermaid\">".into())
+        }
+        Event::End(Tag::CodeBlock(_)) if in_diagram => {
+            in_diagram = false;
+            Event::Html("</pre>\n".into())
+        }
+        Event::Text(text) if in_diagram => {
+            let mut html = String::with_capacity(text.len());
+            escape_html(&mut html, &text).unwrap();
+            Event::Html(html.into())
+        }
+        event => event,
+    })
+}
+
+/// The slug of the heading `text`, as [`PulldownCmark::with_heading_ids`] describes.
+fn slugify(text: &str) -> String {
+    text.trim()
+        .nfc()
+        .filter_map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                Some(c.to_lowercase().next().unwrap_or(c))
+            } else if c.is_whitespace() {
+                Some('-')
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Write headings out with unique `id`s, taken from their text.
+fn heading_ids<'a>(events: impl Iterator<Item = Event<'a>>) -> impl Iterator<Item = Event<'a>> {
+    let mut used = HashSet::new();
+    let mut heading: Option<Vec<Event<'a>>> = None;
+    events.flat_map(move |event| match event {
+        Event::Start(Tag::Heading(..)) => {
+            heading = Some(Vec::new());
+            Vec::new()
+        }
+        Event::End(Tag::Heading(level, _, _)) => {
+            let content = heading.take().unwrap_or_default();
+            let text = content
+                .iter()
+                .filter_map(|event| match event {
+                    Event::Text(text) | Event::Code(text) => Some(&**text),
+                    _ => None,
+                })
+                .collect::<String>();
+            let slug = slugify(&text);
+            let mut id = slug.clone();
+            let mut n = 0;
+            while !used.insert(id.clone()) {
+                n += 1;
+                id = format!("{}-{}", slug, n);
+            }
+
+            let open = Event::Html(format!("<{} id=\"{}\">", level, id).into());
+            let close = Event::Html(format!("</{}>\n", level).into());
+            let mut events = Vec::with_capacity(content.len() + 2);
+            events.push(open);
+            events.extend(content);
+            events.push(close);
+            events
+        }
+        event => match heading.as_mut() {
+            Some(content) => {
+                content.push(event);
+                Vec::new()
+            }
+            None => vec![event],
+        },
+    })
+}
+
+/// Write `events` out as HTML, giving headings `id`s if `heading_ids` is set.
+fn push_html<'a>(html: &mut String, events: impl Iterator<Item = Event<'a>>, heading_ids: bool) {
+    let events = mermaid_blocks(events);
+    if heading_ids {
+        pulldown_cmark::html::push_html(html, self::heading_ids(events));
+    } else {
+        pulldown_cmark::html::push_html(html, events);
+    }
+}
+
+/// The direction of `c` if it's a letter.
+fn char_direction(c: char) -> Option<Direction> {
+    if !c.is_alphabetic() {
+        return None;
+    }
+    // Hebrew, Arabic, Syriac, Thaana, N'Ko and the rest of the right-to-left blocks, with their
+    // presentation forms.
+    match c as u32 {
+        0x0590..=0x08FF
+        | 0xFB1D..=0xFDFF
+        | 0xFE70..=0xFEFF
+        | 0x10800..=0x10FFF
+        | 0x1E800..=0x1EFFF => Some(Direction::Rtl),
+        _ => Some(Direction::Ltr),
+    }
+}
+
+/// The direction of the first letter of the text of `markdown`, if it has any.
+fn text_direction(markdown: &str) -> Option<Direction> {
+    Parser::new_ext(markdown, OPTIONS).find_map(|event| match event {
+        Event::Text(text) => text.chars().find_map(char_direction),
+        _ => None,
+    })
+}
+
+/// Wrap the top-level blocks of `markdown` whose text runs the other way from `direction` in a
+/// `<div>` with their `dir`.
+fn mark_directions<'a>(
+    events: impl Iterator<Item = (Event<'a>, Range<usize>)> + 'a,
+    markdown: &'a str,
+    direction: Direction,
+) -> impl Iterator<Item = (Event<'a>, Range<usize>)> + 'a {
+    let mut depth = 0;
+    let mut wrapped = false;
+    events.flat_map(move |(event, range)| {
+        let mut open = None;
+        let mut close = None;
+        match event {
+            Event::Start(_) => {
+                if depth == 0 {
+                    if let Some(other) =
+                        text_direction(&markdown[range.clone()]).filter(|&block| block != direction)
+                    {
+                        let div = format!("<div dir=\"{}\">\n", other.as_str());
+                        open = Some((Event::Html(div.into()), range.clone()));
+                        wrapped = true;
+                    }
+                }
+                depth += 1;
+            }
+            Event::End(_) => {
+                depth -= 1;
+                if depth == 0 && wrapped {
+                    wrapped = false;
+                    close = Some((Event::Html("</div>\n".into()), range.clone()));
+                }
+            }
+            _ => (),
+        }
+        open.into_iter().chain(Some((event, range))).chain(close)
+    })
+}
+
+impl Renderer for PulldownCmark {
+    fn render(&mut self, markdown: &str, _: Option<&Path>) -> io::Result<String> {
+        let (markdown, equations) = math::extract(markdown, &code_ranges(markdown));
+        let markdown = markdown.as_str();
+        let mut html = String::with_capacity(markdown.len());
+        let events = mark_directions(
+            Parser::new_ext(markdown, OPTIONS).into_offset_iter(),
+            markdown,
+            self.direction,
+        );
+
+        if !self.source_lines {
+            let events = events.map(|(event, _)| event);
+            push_html(&mut html, events, self.heading_ids);
+            return Ok(math::restore(&html, &equations));
+        }
+
+        let mut depth = 0;
+        let mut line = 0;
+        let mut counted = 0;
+        let events = events.flat_map(|(event, range)| {
+            let marker = match event {
+                Event::Start(_) if depth == 0 => {
+                    line += markdown[counted..range.start].matches('\n').count();
+                    counted = range.start;
+                    Some(Event::Html(
+                        format!("<span data-source-line=\"{}\"></span>", line).into(),
+                    ))
+                }
+                _ => None,
+            };
+            match event {
+                Event::Start(_) => depth += 1,
+                Event::End(_) => depth -= 1,
+                _ => (),
+            }
+            marker.into_iter().chain(Some(event))
+        });
+        push_html(&mut html, events, self.heading_ids);
+
+        Ok(math::restore(&html, &equations))
+    }
+}
+
+/// Replace `{path}` and `{dir}` in `arg` with the rendered file and its directory.
+///
+/// Both are replaced with the empty string if the file isn't known.
+fn expand(arg: &str, path: Option<&Path>) -> String {
+    let file = path.map(|path| path.to_string_lossy()).unwrap_or_default();
+    let dir = path
+        .and_then(Path::parent)
+        .map(|dir| dir.to_string_lossy())
+        .unwrap_or_default();
+    arg.replace("{path}", &file).replace("{dir}", &dir)
+}
+
+/// Renders markdown by running a program for each render.
+///
+/// The program is given the markdown on stdin and must print HTML on stdout and exit with a
+/// successful status. Its arguments may contain `{path}` and `{dir}`, which are replaced with the
+/// path of the rendered file and its directory.
+#[derive(Debug)]
+pub struct ExternalCommand {
+    program: String,
+    args: Vec<String>,
+}
+
+impl ExternalCommand {
+    /// Run `program` with `args` for every render.
+    pub fn new(program: impl Into<String>, args: Vec<String>) -> Self {
+        ExternalCommand {
+            program: program.into(),
+            args,
+        }
+    }
+}
+
+impl From<&Command> for ExternalCommand {
+    fn from(command: &Command) -> Self {
+        ExternalCommand::new(
+            command.get_program().to_string_lossy(),
+            command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+        )
+    }
+}
+
+impl Renderer for ExternalCommand {
+    fn render(&mut self, markdown: &str, path: Option<&Path>) -> io::Result<String> {
+        let mut child = Command::new(&self.program)
+            .args(self.args.iter().map(|arg| expand(arg, path)))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        // Write from another thread so a renderer producing a lot of output can't block on a
+        // full stdout pipe while we're still writing its input.
+        let mut stdin = child.stdin.take().unwrap();
+        let markdown = markdown.to_owned();
+        let writer = thread::spawn(move || stdin.write_all(markdown.as_bytes()));
+
+        let output = child.wait_with_output()?;
+        writer.join().unwrap()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "external renderer failed: {}",
+                output.status
+            )));
+        }
+
+        String::from_utf8(output.stdout).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Renders markdown with a long-lived program.
+///
+/// The program is started on the first render and kept running. Documents are exchanged over its
+/// stdin and stdout as messages with a `Content-Length` header giving the length of the UTF-8 body
+/// in bytes, as in the Language Server Protocol:
+///
+/// ```text
+/// Content-Length: 7\r\n
+/// \r\n
+/// *Hello*
+/// ```
+///
+/// For every markdown message the program writes one HTML message back. The server may add a
+/// `Path` header naming the rendered file, which the program is free to ignore. If the program
+/// exits or sends something else, the render fails and the program is started again on the next
+/// one.
+#[derive(Debug)]
+pub struct RendererProcess {
+    program: String,
+    args: Vec<String>,
+    process: Option<(Child, ChildStdin, BufReader<ChildStdout>)>,
+}
+
+impl RendererProcess {
+    /// Keep `program` running with `args` to render documents.
+    pub fn new(program: impl Into<String>, args: Vec<String>) -> Self {
+        RendererProcess {
+            program: program.into(),
+            args,
+            process: None,
+        }
+    }
+
+    fn exchange(&mut self, markdown: &str, path: Option<&Path>) -> io::Result<String> {
+        if self.process.is_none() {
+            let mut child = Command::new(&self.program)
+                .args(&self.args)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()?;
+            let stdin = child.stdin.take().unwrap();
+            let stdout = BufReader::new(child.stdout.take().unwrap());
+            self.process = Some((child, stdin, stdout));
+        }
+        let (_, stdin, stdout) = self.process.as_mut().unwrap();
+
+        let mut header = format!("Content-Length: {}\r\n", markdown.len());
+        if let Some(path) = path {
+            header.push_str(&format!("Path: {}\r\n", path.display()));
+        }
+        header.push_str("\r\n");
+        stdin.write_all(header.as_bytes())?;
+        stdin.write_all(markdown.as_bytes())?;
+        stdin.flush()?;
+
+        let mut length = None;
+        loop {
+            let mut line = String::new();
+            if stdout.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "renderer process exited",
+                ));
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+        let length = length.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+        })?;
+
+        let mut html = vec![0; length];
+        stdout.read_exact(&mut html)?;
+        String::from_utf8(html).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+impl Renderer for RendererProcess {
+    fn render(&mut self, markdown: &str, path: Option<&Path>) -> io::Result<String> {
+        let result = self.exchange(markdown, path);
+        if result.is_err() {
+            if let Some((mut child, _, _)) = self.process.take() {
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+        }
+        result
+    }
+}
+
+impl Drop for RendererProcess {
+    fn drop(&mut self) {
+        if let Some((mut child, stdin, _)) = self.process.take() {
+            // Closing stdin tells the program there is nothing more to render.
+            drop(stdin);
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{expand, ExternalCommand, PulldownCmark, Renderer, RendererProcess};
+    use crate::Direction;
+
+    #[test]
+    fn pulldown_cmark() {
+        let html = PulldownCmark::default().render("*Hello*", None).unwrap();
+        assert_eq!(html.trim(), "<p><em>Hello</em></p>");
+
+        let html = PulldownCmark::with_source_lines()
+            .render("# Title\n\n- a\n- b\n\ntext", None)
+            .unwrap();
+        // Ignore newlines, which pulldown-cmark versions place differently around raw HTML.
+        assert_eq!(
+            html.replace('\n', ""),
+            "<span data-source-line=\"0\"></span><h1>Title</h1>\
+             <span data-source-line=\"2\"></span><ul><li>a</li><li>b</li></ul>\
+             <span data-source-line=\"5\"></span><p>text</p>"
+        );
+    }
+
+    #[test]
+    fn pulldown_cmark_heading_ids() {
+        let html = PulldownCmark::with_source_lines()
+            .with_heading_ids()
+            .render("# Setup\n## *Linux*\n# Usage\n## Linux\n## `Linux`", None)
+            .unwrap();
+        assert_eq!(
+            html.replace('\n', ""),
+            "<span data-source-line=\"0\"></span><h1 id=\"setup\">Setup</h1>\
+             <span data-source-line=\"1\"></span><h2 id=\"linux\"><em>Linux</em></h2>\
+             <span data-source-line=\"2\"></span><h1 id=\"usage\">Usage</h1>\
+             <span data-source-line=\"3\"></span><h2 id=\"linux-1\">Linux</h2>\
+             <span data-source-line=\"4\"></span><h2 id=\"linux-2\"><code>Linux</code></h2>"
+        );
+    }
+
+    #[test]
+    fn pulldown_cmark_math() {
+        let html = PulldownCmark::with_source_lines()
+            .render("$$\na_1 * b_1\n$$\n\n`$x$` and $y_2*$\n\ntext", None)
+            .unwrap();
+        assert_eq!(
+            html.replace('\n', ""),
+            "<span data-source-line=\"0\"></span>\
+             <p><x-equation type=\"display\">a_1 * b_1</x-equation></p>\
+             <span data-source-line=\"4\"></span>\
+             <p><code>$x$</code> and <x-equation>y_2*</x-equation></p>\
+             <span data-source-line=\"6\"></span><p>text</p>"
+        );
+    }
+
+    #[test]
+    fn pulldown_cmark_directions() {
+        let markdown =
+            "Hello\n\n# שלום עולם\n\n[مرحبا](https://example.com)\n\n`code` بالعربية\n\n1234";
+        let html = PulldownCmark::default().render(markdown, None).unwrap();
+        assert_eq!(
+            html.replace('\n', ""),
+            "<p>Hello</p>\
+             <div dir=\"rtl\"><h1>שלום עולם</h1></div>\
+             <div dir=\"rtl\"><p><a href=\"https://example.com\">مرحبا</a></p></div>\
+             <div dir=\"rtl\"><p><code>code</code> بالعربية</p></div>\
+             <p>1234</p>"
+        );
+
+        let html = PulldownCmark::with_source_lines()
+            .with_direction(Direction::Rtl)
+            .render("مرحبا\n\nHello", None)
+            .unwrap();
+        assert_eq!(
+            html.replace('\n', ""),
+            "<span data-source-line=\"0\"></span><p>مرحبا</p>\
+             <div dir=\"ltr\"><span data-source-line=\"2\"></span><p>Hello</p></div>"
+        );
+    }
+
+    #[test]
+    fn pulldown_cmark_mermaid() {
+        let html = PulldownCmark::default()
+            .render(
+                "```mermaid\ngraph TD\n  A-->B\n```\n\n```rust\nA-->B\n```",
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            html,
+            "<pre class=\"mermaid\">graph TD\n  A--&gt;B\n</pre>\n\
+             <pre><code class=\"language-rust\">A--&gt;B\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn expand_arguments() {
+        let path = Path::new("/notes/today.md");
+        assert_eq!(
+            expand("--file={path}", Some(path)),
+            "--file=/notes/today.md"
+        );
+        assert_eq!(expand("{dir}/style.css", Some(path)), "/notes/style.css");
+        assert_eq!(expand("{path}", None), "");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn external_command() {
+        let mut renderer = ExternalCommand::new("sh", vec!["-c".into(), "cat; echo {path}".into()]);
+        let html = renderer
+            .render("*Hello*\n", Some(Path::new("a.md")))
+            .unwrap();
+        assert_eq!(html, "*Hello*\na.md\n");
+
+        let mut failing = ExternalCommand::new("false", vec![]);
+        assert!(failing.render("", None).is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn renderer_process_is_reused_and_restarted() {
+        // Echo every message back, counting them so we can tell whether the process was reused.
+        let script = r#"n=0
+            while IFS= read -r header; do
+                length=$(echo "$header" | tr -d '\r' | cut -d' ' -f2)
+                while IFS= read -r line && [ "$(echo "$line" | tr -d '\r')" != "" ]; do :; done
+                body=$(head -c "$length")
+                n=$((n + 1))
+                reply="$n:$body"
+                printf 'Content-Length: %s\r\n\r\n%s' "${#reply}" "$reply"
+            done"#;
+        let mut renderer = RendererProcess::new("sh", vec!["-c".into(), script.into()]);
+
+        assert_eq!(renderer.render("one", None).unwrap(), "1:one");
+        assert_eq!(renderer.render("two", None).unwrap(), "2:two");
+
+        // Kill the process behind the renderer's back; the next render fails and restarts it.
+        renderer.process.as_mut().unwrap().0.kill().unwrap();
+        assert!(renderer.render("three", None).is_err());
+        assert_eq!(renderer.render("four", None).unwrap(), "1:four");
+    }
+}
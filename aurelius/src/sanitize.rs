@@ -0,0 +1,174 @@
+//! Cleaning rendered HTML of anything that could run a script in the preview.
+//!
+//! Markdown may contain raw HTML, and the preview is served from a local server the browser
+//! trusts, so a note (or a renderer) can't be allowed to run scripts in it. The HTML is parsed the
+//! way a browser would and only elements and attributes on an allowlist are kept: those markdown
+//! renders to, plus what the preview adds, like source line markers, equations and SVG charts.
+
+use std::sync::OnceLock;
+
+use ammonia::Builder;
+
+/// Elements kept besides ammonia's defaults.
+const TAGS: &[&str] = &[
+    "input",
+    "x-equation",
+    // SVG, for charts and diagrams.
+    "svg",
+    "g",
+    "defs",
+    "title",
+    "desc",
+    "path",
+    "rect",
+    "circle",
+    "ellipse",
+    "line",
+    "polyline",
+    "polygon",
+    "text",
+    "tspan",
+    "marker",
+    "linearGradient",
+    "radialGradient",
+    "stop",
+    "clipPath",
+];
+
+/// Attributes kept on any element besides ammonia's defaults.
+const ATTRIBUTES: &[&str] = &[
+    "class",
+    "id",
+    "dir",
+    "style",
+    // SVG presentation and geometry.
+    "xmlns",
+    "viewBox",
+    "preserveAspectRatio",
+    "width",
+    "height",
+    "x",
+    "y",
+    "x1",
+    "y1",
+    "x2",
+    "y2",
+    "dx",
+    "dy",
+    "cx",
+    "cy",
+    "r",
+    "rx",
+    "ry",
+    "d",
+    "points",
+    "transform",
+    "fill",
+    "fill-opacity",
+    "fill-rule",
+    "stroke",
+    "stroke-width",
+    "stroke-opacity",
+    "stroke-dasharray",
+    "stroke-linecap",
+    "stroke-linejoin",
+    "opacity",
+    "font-family",
+    "font-size",
+    "font-weight",
+    "font-style",
+    "text-anchor",
+    "dominant-baseline",
+    "offset",
+    "stop-color",
+    "stop-opacity",
+    "markerWidth",
+    "markerHeight",
+    "refX",
+    "refY",
+    "orient",
+    "marker-start",
+    "marker-end",
+    "clip-path",
+];
+
+/// `html` with only allowed elements and attributes, and links only to URLs that don't run code.
+pub fn sanitize(html: &str) -> String {
+    static BUILDER: OnceLock<Builder<'static>> = OnceLock::new();
+    BUILDER
+        .get_or_init(|| {
+            let mut builder = Builder::default();
+            builder
+                .add_tags(TAGS)
+                .add_generic_attributes(ATTRIBUTES)
+                .add_generic_attribute_prefixes(&["data-"])
+                .add_tag_attributes("input", &["type", "checked", "disabled"])
+                .add_tag_attributes("x-equation", &["type"])
+                .link_rel(None);
+            builder
+        })
+        .clean(html)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sanitize;
+
+    #[test]
+    fn scripts_are_removed() {
+        assert_eq!(
+            sanitize("<p>a</p><script>alert(1)</script><SCRIPT src=x></SCRIPT>b"),
+            "<p>a</p>b"
+        );
+        assert_eq!(sanitize("<iframe src=\"x\">text</iframe>"), "text");
+        assert_eq!(
+            sanitize("<svg><script>alert(1)</script><path d=\"M0 0\"/></svg>"),
+            "<svg><path d=\"M0 0\"></path></svg>"
+        );
+    }
+
+    #[test]
+    fn handlers_and_script_urls_are_removed() {
+        assert_eq!(
+            sanitize("<img src=\"x.png\" onerror=\"alert(1)\" alt='a > b'>"),
+            "<img src=\"x.png\" alt=\"a &gt; b\">"
+        );
+        assert_eq!(
+            sanitize(
+                "<a href=\"javascript:alert(1)\">a</a><a HREF=' java&#x09;script&colon;x'>b</a>"
+            ),
+            "<a>a</a><a>b</a>"
+        );
+        // Browsers end a numeric reference at the first character that isn't a digit.
+        assert_eq!(
+            sanitize("<a href=\"&#106avascript:alert(1)\">x</a>"),
+            "<a>x</a>"
+        );
+    }
+
+    #[test]
+    fn svg_animations_are_removed() {
+        assert_eq!(
+            sanitize(
+                "<svg><a><animate attributeName=\"href\" values=\"javascript:alert(1)\"/>\
+                 <text>x</text></a></svg>"
+            ),
+            "<svg><a><text>x</text></a></svg>"
+        );
+        assert_eq!(
+            sanitize("<svg><set attributeName=\"onmouseover\" to=\"alert(1)\"/></svg>"),
+            "<svg></svg>"
+        );
+    }
+
+    #[test]
+    fn the_preview_markup_is_kept() {
+        let html = "<span data-source-line=\"3\"></span><h1 id=\"a\">1 &lt; 2</h1>\
+            <div dir=\"rtl\"><p><a href=\"https://example.com\">x</a></p></div>\
+            <ul><li><input disabled=\"\" type=\"checkbox\">task</li></ul>\
+            <x-equation type=\"display\">a &lt; b</x-equation>\
+            <svg class=\"chart\" viewBox=\"0 0 10 10\"><circle cx=\"1\" cy=\"1\" r=\"1\"></circle></svg>";
+        assert_eq!(sanitize(html), html);
+    }
+}
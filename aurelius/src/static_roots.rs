@@ -0,0 +1,195 @@
+//! Directories served under URL prefixes.
+
+use std::path::{Component, Path, PathBuf};
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+/// Characters escaped in the path segments of URLs we build: everything but the unreserved ones.
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+#[derive(Debug, Default)]
+pub(crate) struct StaticRoots {
+    /// URL prefixes, normalized to start and end with `/`, and the directories served under them.
+    roots: Vec<(String, PathBuf)>,
+    /// Number of directories given prefixes of their own by `serve_dir`.
+    extra_dirs: usize,
+}
+
+/// Normalize a URL prefix to start and end with a slash, so `attachments` and `/attachments/`
+/// are the same.
+fn normalize_prefix(prefix: &str) -> String {
+    let trimmed = prefix.trim_matches('/');
+    if trimmed.is_empty() {
+        String::from("/")
+    } else {
+        format!("/{}/", trimmed)
+    }
+}
+
+impl StaticRoots {
+    /// Serve `dir` under `prefix`, replacing the directory previously served there.
+    pub fn insert(&mut self, prefix: &str, dir: PathBuf) {
+        let prefix = normalize_prefix(prefix);
+        self.roots.retain(|(existing, _)| *existing != prefix);
+        self.roots.push((prefix, dir));
+        // Try the most specific prefixes first.
+        self.roots
+            .sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    }
+
+    /// Stop serving anything under `prefix`.
+    pub fn remove(&mut self, prefix: &str) {
+        let prefix = normalize_prefix(prefix);
+        self.roots.retain(|(existing, _)| *existing != prefix);
+    }
+
+    /// The file that the (still percent-encoded) URL path `path` names, if it is inside a root.
+    ///
+    /// Paths that could escape their root, with `..` segments or encoded separators, name
+    /// nothing.
+    pub fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let (prefix, dir) = self
+            .roots
+            .iter()
+            .find(|(prefix, _)| path.starts_with(prefix.as_str()))?;
+
+        let mut file = dir.clone();
+        for segment in path[prefix.len()..].split('/') {
+            let segment = percent_decode_str(segment).decode_utf8().ok()?;
+            let mut components = Path::new(segment.as_ref()).components();
+            match (components.next(), components.next()) {
+                (Some(Component::Normal(name)), None) => file.push(name),
+                (Some(Component::CurDir), None) | (None, _) => (),
+                _ => return None,
+            }
+        }
+        Some(file)
+    }
+
+    /// The URL path of the directory `dir`, ending in `/`, if it is inside a root.
+    pub fn url_path(&self, dir: &Path) -> Option<String> {
+        // The deepest root containing the directory gives the most specific URL.
+        let (prefix, relative) = self
+            .roots
+            .iter()
+            .filter_map(|(prefix, root)| Some((prefix, dir.strip_prefix(root).ok()?)))
+            .min_by_key(|(_, relative)| relative.components().count())?;
+
+        let mut url = prefix.clone();
+        for component in relative.components() {
+            let name = match component {
+                Component::Normal(name) => name.to_str()?,
+                _ => return None,
+            };
+            url.extend(utf8_percent_encode(name, SEGMENT));
+            url.push('/');
+        }
+        Some(url)
+    }
+
+    /// The URL path of the directory `dir`, serving it under a new prefix if it isn't inside a
+    /// root.
+    pub fn serve_dir(&mut self, dir: &Path) -> String {
+        if let Some(url) = self.url_path(dir) {
+            return url;
+        }
+        let prefix = format!("/__dirs/{}/", self.extra_dirs);
+        self.extra_dirs += 1;
+        self.insert(&prefix, dir.to_path_buf());
+        prefix
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::StaticRoots;
+
+    fn roots() -> StaticRoots {
+        let mut roots = StaticRoots::default();
+        roots.insert("/", PathBuf::from("/vault"));
+        roots.insert("theme", PathBuf::from("/themes/dark"));
+        roots
+    }
+
+    #[test]
+    fn resolve_paths_under_the_most_specific_prefix() {
+        let roots = roots();
+        assert_eq!(
+            roots.resolve("/file.txt"),
+            Some(PathBuf::from("/vault/file.txt"))
+        );
+        assert_eq!(
+            roots.resolve("/a/b/c/d"),
+            Some(
+                vec!["/vault", "a", "b", "c", "d"]
+                    .iter()
+                    .collect::<PathBuf>()
+            ),
+        );
+        assert_eq!(
+            roots.resolve("/attachments/my%20diagram.png"),
+            Some(PathBuf::from("/vault/attachments/my diagram.png"))
+        );
+        assert_eq!(
+            roots.resolve("/theme/fonts/a.woff"),
+            Some(PathBuf::from("/themes/dark/fonts/a.woff"))
+        );
+        assert_eq!(
+            roots.resolve("/themes/a.css"),
+            Some(PathBuf::from("/vault/themes/a.css"))
+        );
+    }
+
+    #[test]
+    fn paths_cannot_escape_their_root() {
+        let roots = roots();
+        assert_eq!(roots.resolve("/../etc/passwd"), None);
+        assert_eq!(roots.resolve("/a/%2e%2e/%2e%2e/etc/passwd"), None);
+        assert_eq!(roots.resolve("/a%2F..%2F..%2Fetc/passwd"), None);
+        assert_eq!(roots.resolve("/theme/../secret"), None);
+        assert_eq!(StaticRoots::default().resolve("/file.txt"), None);
+    }
+
+    #[test]
+    fn url_paths_of_directories() {
+        let mut roots = roots();
+        roots.insert("/notes/", PathBuf::from("/vault/notes"));
+        assert_eq!(roots.url_path(Path::new("/vault")), Some(String::from("/")));
+        assert_eq!(
+            roots.url_path(Path::new("/vault/daily notes")),
+            Some(String::from("/daily%20notes/"))
+        );
+        assert_eq!(
+            roots.url_path(Path::new("/vault/notes/2023")),
+            Some(String::from("/notes/2023/"))
+        );
+        assert_eq!(roots.url_path(Path::new("/elsewhere")), None);
+
+        roots.remove("notes");
+        assert_eq!(
+            roots.url_path(Path::new("/vault/notes/2023")),
+            Some(String::from("/notes/2023/"))
+        );
+        roots.remove("/");
+        assert_eq!(roots.url_path(Path::new("/vault/notes/2023")), None);
+    }
+
+    #[test]
+    fn serve_directories_outside_roots() {
+        let mut roots = roots();
+        assert_eq!(roots.serve_dir(Path::new("/vault/notes")), "/notes/");
+        assert_eq!(roots.serve_dir(Path::new("/tmp/a")), "/__dirs/0/");
+        assert_eq!(roots.serve_dir(Path::new("/tmp/b")), "/__dirs/1/");
+        assert_eq!(roots.serve_dir(Path::new("/tmp/a/c d")), "/__dirs/0/c%20d/");
+        assert_eq!(
+            roots.resolve("/__dirs/1/image.png"),
+            Some(PathBuf::from("/tmp/b/image.png"))
+        );
+    }
+}
@@ -16,45 +16,195 @@ document.addEventListener('DOMContentLoaded', function() {
         }
     }
 
+    // Render math with KaTeX. Equations come as the <x-equation> elements written by the
+    // built-in renderer and md4c, or as pandoc's <span class="math">. KaTeX writes MathML, which
+    // browsers display without its stylesheet and fonts.
     function renderMath() {
-      if (typeof renderMathInElement === 'function') {
-        let equations = document.getElementsByTagName("x-equation");
-        for (let equation of equations) {
-            if (equation.type === "display") {
-                equation.innerHTML = "$$" + equation.innerHTML + "$$";
-            } else {
-                equation.innerHTML = "$" + equation.innerHTML + "$";
+        if (typeof katex === 'undefined') {
+            return;
+        }
+        var equations = previewWindow.querySelectorAll('x-equation, span.math');
+        for (var i = 0; i < equations.length; i++) {
+            var equation = equations[i];
+            var tex = equation.textContent;
+            var display = equation.getAttribute('type') === 'display' ||
+                equation.classList.contains('display');
+            if (equation.tagName === 'SPAN') {
+                // pandoc keeps the \( \) or \[ \] delimiters.
+                tex = tex.replace(/^\\[([]/, '').replace(/\\[)\]]$/, '');
+            }
+            katex.render(tex, equation, {
+                displayMode: display,
+                output: 'mathml',
+                throwOnError: false
+            });
+        }
+    }
+
+    // Mermaid is large and comes from a CDN, so it's only loaded once a note has a diagram.
+    var mermaidUrl = 'https://cdn.jsdelivr.net/npm/mermaid@10/dist/mermaid.min.js';
+    var mermaidLoading = false;
+
+    function renderDiagrams() {
+        // Renderers other than the built-in one leave diagrams as code blocks.
+        var blocks = previewWindow.querySelectorAll('pre > code.language-mermaid');
+        for (var i = 0; i < blocks.length; i++) {
+            var pre = blocks[i].parentNode;
+            pre.textContent = blocks[i].textContent;
+            pre.className = 'mermaid';
+        }
+
+        var diagrams = previewWindow.querySelectorAll('pre.mermaid');
+        if (diagrams.length === 0) {
+            return;
+        }
+        if (typeof mermaid === 'undefined') {
+            if (!mermaidLoading) {
+                mermaidLoading = true;
+                var script = document.createElement('script');
+                script.src = mermaidUrl;
+                script.onload = function() {
+                    var dark = document.documentElement.dataset.theme === 'dark';
+                    mermaid.initialize({
+                        startOnLoad: false,
+                        theme: dark ? 'dark' : 'default'
+                    });
+                    renderDiagrams();
+                };
+                document.head.appendChild(script);
             }
+            return;
         }
-            
-        renderMathInElement(
-            document.getElementById("markdown-preview"),
-            {
-                delimiters: [
-                    // {left: "\\begin{align*}", right: "\\end{align*}", display: true},
-                    {left: "$$", right: "$$", display: true},
-                    {left: "\\[", right: "\\]", display: true},
-                    {left: "$", right: "$", display: false},
-                    {left: "\\(", right: "\\)", display: false}
-                ]
+        mermaid.run({ nodes: diagrams });
+    }
+
+    // Name the page after the note, which is what screen readers announce for the tab.
+    function updateTitle() {
+        var heading = previewWindow.querySelector('h1, h2, h3, h4, h5, h6');
+        if (heading !== null && heading.textContent.trim() !== '') {
+            document.title = heading.textContent.trim();
+        }
+    }
+
+    // Move focus to the heading `step` headings after the focused one, or after the top of the
+    // window if no heading has focus. Headings are only made focusable as they're reached, so
+    // they don't all end up in the tab order.
+    function focusHeading(step) {
+        var headings = Array.prototype.slice.call(
+            previewWindow.querySelectorAll('h1, h2, h3, h4, h5, h6'));
+        if (headings.length === 0) {
+            return;
+        }
+        var current = headings.indexOf(document.activeElement);
+        if (current === -1) {
+            // Start from the first heading below the top of the window.
+            current = headings.findIndex(function(heading) {
+                return heading.getBoundingClientRect().top >= 0;
+            });
+            if (current === -1) {
+                current = headings.length;
             }
-        );
-      }
+            current -= step > 0 ? 1 : 0;
+        }
+        var next = headings[Math.min(Math.max(current + step, 0), headings.length - 1)];
+        next.setAttribute('tabindex', '-1');
+        next.focus();
     }
 
+    document.addEventListener('keydown', function(event) {
+        if (event.altKey || event.ctrlKey || event.metaKey) {
+            return;
+        }
+        if (event.key === ']') {
+            focusHeading(1);
+        } else if (event.key === '[') {
+            focusHeading(-1);
+        } else {
+            return;
+        }
+        event.preventDefault();
+    });
 
+    var previewWindow = document.getElementById('markdown-preview');
+    renderDiagrams();
     syntaxHighlight();
     renderMath();
-    var previewWindow = document.getElementById('markdown-preview');
-    var webSocketUrl = 'ws://' + window.location.host;
+    updateTitle();
+    // The query names the channel to preview, if it isn't the default one.
+    var webSocketUrl = 'ws://' + window.location.host + '/' + window.location.search;
 
     var socket = new ReconnectingWebSocket(webSocketUrl);
     socket.maxReconnectInterval = 5000;
 
+    // The last position the editor asked for, kept across updates so the preview doesn't jump
+    // back to the top whenever the markdown changes.
+    var scrollTarget = null;
+
+    // The directory of the previewed file on this server, if it's served here.
+    var baseUrl = null;
+
+    // Make relative links and images point into the previewed file's directory. A <base> element
+    // would also redirect the links to the server's own assets.
+    function resolveRelativeLinks() {
+        if (baseUrl === null) {
+            return;
+        }
+        var absolute = /^([a-z][a-z0-9+.-]*:|\/|#)/i;
+        var elements = previewWindow.querySelectorAll('[src], [href]');
+        for (var i = 0; i < elements.length; i++) {
+            ['src', 'href'].forEach(function(name) {
+                var value = elements[i].getAttribute(name);
+                if (value !== null && value !== '' && !absolute.test(value)) {
+                    elements[i].setAttribute(name, baseUrl + value);
+                }
+            });
+        }
+    }
+
+    function scrollToLine(line, lineCount) {
+        var markers = previewWindow.querySelectorAll('[data-source-line]');
+        var top;
+        if (markers.length > 0) {
+            // Scroll to the last block starting at or before the line.
+            var marker = markers[0];
+            for (var i = 0; i < markers.length; i++) {
+                if (parseInt(markers[i].dataset.sourceLine, 10) > line) {
+                    break;
+                }
+                marker = markers[i];
+            }
+            top = marker.getBoundingClientRect().top + window.pageYOffset;
+        } else if (lineCount > 0) {
+            // Without markers, assume the lines are spread evenly over the page.
+            top = document.body.scrollHeight * line / lineCount;
+        } else {
+            return;
+        }
+
+        // Leave some of the preceding content in view.
+        window.scrollTo(0, Math.max(0, top - window.innerHeight / 3));
+    }
+
     socket.onmessage = function(event) {
-        document.getElementById('markdown-preview').innerHTML = event.data;
+        if (event.data.startsWith('{"scrollToLine"')) {
+            scrollTarget = JSON.parse(event.data);
+            scrollToLine(scrollTarget.scrollToLine, scrollTarget.lineCount);
+            return;
+        }
+        if (event.data.startsWith('{"baseUrl"')) {
+            baseUrl = JSON.parse(event.data).baseUrl;
+            return;
+        }
+
+        previewWindow.innerHTML = event.data;
+        resolveRelativeLinks();
+        renderDiagrams();
         syntaxHighlight();
         renderMath();
+        updateTitle();
+        if (scrollTarget !== null) {
+            scrollToLine(scrollTarget.scrollToLine, scrollTarget.lineCount);
+        }
     }
 
     socket.onclose = function(event) {
@@ -0,0 +1,30 @@
+// Highlights code and renders math in a page saved from the preview, which has no server to keep
+// it up to date. See markdown_client.js for the live preview.
+document.addEventListener('DOMContentLoaded', function() {
+    if (typeof hljs !== 'undefined') {
+        var codeBlocks = document.querySelectorAll('pre code');
+        for (var i = 0; i < codeBlocks.length; i++) {
+            hljs.highlightElement(codeBlocks[i]);
+            codeBlocks[i].parentNode.style.background = (
+                getComputedStyle(codeBlocks[i]).getPropertyValue('background'));
+        }
+    }
+
+    if (typeof katex !== 'undefined') {
+        var equations = document.querySelectorAll('x-equation, span.math');
+        for (var j = 0; j < equations.length; j++) {
+            var equation = equations[j];
+            var tex = equation.textContent;
+            var display = equation.getAttribute('type') === 'display' ||
+                equation.classList.contains('display');
+            if (equation.tagName === 'SPAN') {
+                tex = tex.replace(/^\\[([]/, '').replace(/\\[)\]]$/, '');
+            }
+            katex.render(tex, equation, {
+                displayMode: display,
+                output: 'mathml',
+                throwOnError: false
+            });
+        }
+    }
+});
@@ -100,6 +100,11 @@ async fn static_files() -> Result<(), Box<dyn Error>> {
     assert_eq!(res.headers()["Content-Type"], "application/javascript");
     res.text().await?;
 
+    // Math is rendered without reaching out to a CDN.
+    let res = reqwest::get(&format!("http://{}/__/vendor/katex/katex.min.js", addr)).await?;
+    assert!(res.status().is_success());
+    res.text().await?;
+
     Ok(())
 }
 
@@ -136,3 +141,110 @@ async fn static_file_not_found() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn static_roots_under_prefixes() -> Result<(), Box<dyn Error>> {
+    let notes = tempfile::tempdir()?;
+    let theme = tempfile::tempdir()?;
+    fs::write(notes.path().join("note.txt"), "note").await?;
+    fs::write(theme.path().join("note.txt"), "theme").await?;
+
+    let mut server = new_server().await?;
+    server.set_static_root(notes.path());
+    server.add_static_root("/theme/", theme.path());
+    let addr = server.addr();
+
+    let res = reqwest::get(&format!("http://{}/note.txt", addr)).await?;
+    assert_eq!(res.text().await?, "note");
+    let res = reqwest::get(&format!("http://{}/theme/note.txt", addr)).await?;
+    assert_eq!(res.text().await?, "theme");
+
+    server.remove_static_root("theme");
+    let res = reqwest::get(&format!("http://{}/theme/note.txt", addr)).await?;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn static_root_traversal() -> Result<(), Box<dyn Error>> {
+    let tmp_dir = tempfile::tempdir()?;
+    let root = tmp_dir.path().join("root");
+    fs::create_dir(&root).await?;
+    fs::write(tmp_dir.path().join("secret.txt"), "secret").await?;
+
+    let mut server = new_server().await?;
+    server.set_static_root(&root);
+
+    // Send the request by hand, since HTTP clients normalize the path.
+    for path in &["/../secret.txt", "/%2e%2e/secret.txt", "/..%2Fsecret.txt"] {
+        let mut conn = tokio::net::TcpStream::connect(server.addr()).await?;
+        let req = format!("GET {} HTTP/1.1\r\n\r\n", path);
+        conn.write_all(req.as_bytes()).await?;
+
+        let mut res = String::new();
+        conn.read_to_string(&mut res).await?;
+        assert!(
+            res.starts_with("HTTP/1.1 404 Not Found"),
+            "{}: {}",
+            path,
+            res
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unchanged_static_files_are_not_sent_again() -> Result<(), Box<dyn Error>> {
+    let tmp_dir = tempfile::tempdir()?;
+    fs::write(tmp_dir.path().join("image.svg"), "<svg/>").await?;
+
+    let mut server = new_server().await?;
+    server.set_static_root(tmp_dir.path());
+    let url = format!("http://{}/image.svg", server.addr());
+
+    let res = reqwest::get(&url).await?;
+    assert_eq!(res.headers()["Cache-Control"], "no-cache");
+    let etag = res.headers()["ETag"].clone();
+
+    let client = reqwest::Client::new();
+    let res = client
+        .get(&url)
+        .header("If-None-Match", etag.clone())
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    fs::write(tmp_dir.path().join("image.svg"), "<svg></svg>").await?;
+    let res = client
+        .get(&url)
+        .header("If-None-Match", etag)
+        .send()
+        .await?;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.text().await?, "<svg></svg>");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn files_next_to_the_previewed_file() -> Result<(), Box<dyn Error>> {
+    let tmp_dir = tempfile::tempdir()?;
+    fs::create_dir(tmp_dir.path().join("assets")).await?;
+    fs::write(tmp_dir.path().join("assets/diagram.svg"), "<svg/>").await?;
+
+    let mut server = new_server().await?;
+    server
+        .send_file("![](assets/diagram.svg)", &tmp_dir.path().join("note.md"))
+        .await?;
+
+    // Without a static root, the file's directory gets a path of its own.
+    let url = format!("http://{}/__dirs/0/assets/diagram.svg", server.addr());
+    let res = reqwest::get(&url).await?;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["Content-Type"], "image/svg+xml");
+    assert_eq!(res.text().await?, "<svg/>");
+
+    Ok(())
+}
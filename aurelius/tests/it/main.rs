@@ -4,6 +4,7 @@ use aurelius::Server;
 
 mod files;
 mod options;
+mod render;
 
 async fn new_server() -> anyhow::Result<Server> {
     let addr = lookup_host("localhost:0").await?.next().unwrap();
@@ -4,6 +4,8 @@ use std::fs;
 use futures_util::TryStreamExt;
 use tempfile::NamedTempFile;
 
+use aurelius::Theme;
+
 use crate::new_server;
 
 #[tokio::test]
@@ -90,6 +92,89 @@ async fn highlight_theme() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+#[tokio::test]
+async fn dark_theme() -> Result<(), Box<dyn Error>> {
+    let mut server = new_server().await?;
+
+    let text = reqwest::get(&format!("http://{}", server.addr()))
+        .await?
+        .text()
+        .await?;
+    assert!(text.contains(r#"<html data-theme="light">"#));
+    assert!(!text.contains("dark.css"));
+
+    server.set_theme(Theme::Dark);
+
+    let text = reqwest::get(&format!("http://{}", server.addr()))
+        .await?
+        .text()
+        .await?;
+    assert!(text.contains(r#"<html data-theme="dark">"#));
+    assert!(text.contains("github-markdown.css"));
+    assert!(text.contains("dark.css"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn user_stylesheet() -> Result<(), Box<dyn Error>> {
+    let temp_file = NamedTempFile::new()?;
+    fs::write(&temp_file, "a { color: #FF0000; }")?;
+
+    let mut server = new_server().await?;
+    server.set_user_stylesheet(Some(temp_file.path().to_path_buf()));
+
+    let text = reqwest::get(&format!("http://{}", server.addr()))
+        .await?
+        .text()
+        .await?;
+    assert!(text.contains("<style>a { color: #FF0000; }</style>"));
+    assert!(text.contains("github-markdown.css"));
+
+    // The stylesheet is read again for every page load.
+    fs::write(&temp_file, "a { color: #00FF00; }")?;
+    let text = reqwest::get(&format!("http://{}", server.addr()))
+        .await?
+        .text()
+        .await?;
+    assert!(text.contains("<style>a { color: #00FF00; }</style>"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn custom_template() -> Result<(), Box<dyn Error>> {
+    let temp_file = NamedTempFile::new()?;
+    fs::write(
+        &temp_file,
+        "<html><head>{{{ css }}}<title>{{ title }}</title></head>\
+         <body><header>Acme</header>{{{ body }}}{{{ scripts }}}</body></html>",
+    )?;
+
+    let mut server = new_server().await?;
+    server.set_template(Some(temp_file.path().to_path_buf()));
+
+    let text = reqwest::get(&format!("http://{}", server.addr()))
+        .await?
+        .text()
+        .await?;
+    assert!(text.contains("<header>Acme</header>"));
+    assert!(text.contains("github-markdown.css"));
+    assert!(text.contains(r#"id="markdown-preview""#));
+    assert!(text.contains("markdown_client.js"));
+
+    // A template that doesn't render falls back to the built-in page.
+    fs::write(&temp_file, "{{#if}}")?;
+    let text = reqwest::get(&format!("http://{}", server.addr()))
+        .await?
+        .text()
+        .await?;
+    assert!(text.contains("<!doctype html>"));
+    assert!(text.contains(r#"id="markdown-preview""#));
+
+    Ok(())
+}
+
 #[cfg(not(windows))]
 #[tokio::test]
 async fn external_renderer() -> Result<(), Box<dyn Error>> {
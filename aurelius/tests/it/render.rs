@@ -0,0 +1,45 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use aurelius::render::{PulldownCmark, Renderer};
+
+/// The markdown files under `tests/fixtures`, which the server's snapshot tests render too.
+fn fixtures() -> Vec<(String, String)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut fixtures = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            (name, fs::read_to_string(&path).unwrap())
+        })
+        .collect::<Vec<_>>();
+    fixtures.sort();
+    fixtures
+}
+
+#[test]
+fn fixtures_keep_their_source_lines() -> Result<(), Box<dyn Error>> {
+    for (name, markdown) in fixtures() {
+        let html = PulldownCmark::with_source_lines().render(&markdown, None)?;
+        let lines = html
+            .split("data-source-line=\"")
+            .skip(1)
+            .map(|rest| rest[..rest.find('"').unwrap()].parse::<usize>().unwrap())
+            .collect::<Vec<_>>();
+
+        assert!(!lines.is_empty(), "{} has no blocks", name);
+        assert!(lines.windows(2).all(|pair| pair[0] < pair[1]), "{}", name);
+        for line in lines {
+            let text = markdown.lines().nth(line).unwrap_or_default();
+            assert!(
+                !text.trim().is_empty(),
+                "{} marks blank line {}",
+                name,
+                line
+            );
+        }
+    }
+    Ok(())
+}
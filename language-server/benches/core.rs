@@ -0,0 +1,66 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use language_server::{
+    completion,
+    config::LinkStyle,
+    index::{Note, NoteIndex},
+};
+
+mod fixtures;
+
+const VAULT_SIZE: usize = 10_000;
+
+fn indexing(c: &mut Criterion) {
+    let vault = fixtures::vault(VAULT_SIZE);
+
+    let mut group = c.benchmark_group("index");
+    group.sample_size(10);
+    group.bench_function("scan 10k notes", |b| {
+        b.iter(|| NoteIndex::scan(black_box(vault.path())))
+    });
+    group.finish();
+}
+
+fn parsing(c: &mut Criterion) {
+    let content = (0..200)
+        .map(|i| fixtures::note_content(i, VAULT_SIZE))
+        .collect::<String>();
+
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(content.len() as u64));
+    group.bench_function("long note", |b| b.iter(|| Note::parse(black_box(&content))));
+    group.finish();
+}
+
+fn queries(c: &mut Criterion) {
+    let vault = fixtures::vault(VAULT_SIZE);
+    let index = NoteIndex::scan(vault.path());
+    let root_note = vault.path().join("index.md");
+    let target = vault.path().join(format!("{}.md", fixtures::note_name(42)));
+
+    c.bench_function("completion in 10k notes", |b| {
+        b.iter(|| completion::note_completions(&index, black_box(&root_note), LinkStyle::Relative))
+    });
+    c.bench_function("references in 10k notes", |b| {
+        b.iter(|| index.backlinks(black_box(&target)).len())
+    });
+}
+
+fn rendering(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut server = runtime
+        .block_on(aurelius::Server::bind("127.0.0.1:0"))
+        .unwrap();
+    let markdown = (0..200)
+        .map(|i| fixtures::note_content(i, VAULT_SIZE))
+        .collect::<String>();
+
+    let mut group = c.benchmark_group("render");
+    group.throughput(Throughput::Bytes(markdown.len() as u64));
+    group.bench_function("long note", |b| {
+        b.iter(|| runtime.block_on(server.send(black_box(&markdown))).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, indexing, parsing, queries, rendering);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+//! Synthetic vaults shared by the benchmarks and the performance budget tests.
+
+use std::fs;
+
+use tempfile::TempDir;
+
+const NOTES_PER_FOLDER: usize = 100;
+
+/// Path of note `i` relative to the vault root, without the `.md` extension.
+pub fn note_name(i: usize) -> String {
+    format!("folder{}/note{}", i / NOTES_PER_FOLDER, i)
+}
+
+/// The content of note `i` in a vault of `count` notes: a few sections of prose with wiki links,
+/// markdown links, tags and an embed.
+pub fn note_content(i: usize, count: usize) -> String {
+    let mut content = format!("# Note {i}\n\n");
+    for section in 0..5 {
+        let wiki = (i * 31 + section * 17) % count;
+        let markdown = (i * 7 + section * 13 + 1) % count;
+        content.push_str(&format!(
+            "## Section {section}\n\n\
+             Some text linking to [[{}]] and [another note](../{}.md#section-1) #topic{section}\n\
+             Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do eiusmod tempor.\n\n",
+            note_name(wiki),
+            note_name(markdown),
+        ));
+    }
+    content.push_str("![[attachments/diagram.png]]\n");
+    content
+}
+
+/// Create a vault of `count` notes in a temporary directory.
+pub fn vault(count: usize) -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for i in 0..count {
+        let path = dir.path().join(format!("{}.md", note_name(i)));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, note_content(i, count)).unwrap();
+    }
+    dir
+}
@@ -0,0 +1,37 @@
+## Diagnostics
+
+broken-link = Keine Notiz namens „{ $target }“
+broken-url = { $url } ist defekt: { $problem }
+link-case = Der Link muss „{ $corrected }“ lauten, um auf Dateisystemen mit Groß- und Kleinschreibung zu funktionieren
+orphan-note = Keine andere Notiz verlinkt hierher
+dead-end-note = Diese Notiz verlinkt keine andere Notiz
+unknown-note-type = Unbekannter Notiztyp „{ $name }“, erwartet wird einer von: { $expected }
+missing-note-field = Notizen vom Typ „{ $name }“ brauchen das Feld „{ $field }“
+missing-alt-text = Bild ohne Alternativtext
+skipped-heading-level = Überschrift springt von Ebene { $previous } auf Ebene { $level }
+unknown-word = Unbekanntes Wort „{ $word }“
+duplicate-name = Eine andere Notiz heißt auch „{ $name }“, Links mit diesem Namen sind daher mehrdeutig
+duplicate-name-other = Die andere Notiz mit diesem Namen
+duplicate-heading = Eine frühere Überschrift hat denselben Anker „#{ $anchor }“
+duplicate-heading-first = Die frühere Überschrift
+
+## Code lenses and preview badges
+
+backlinks =
+    { $count ->
+        [one] 1 Rückverweis
+       *[other] { $count } Rückverweise
+    }
+open-tasks =
+    { $count ->
+        [one] 1 offene Aufgabe
+       *[other] { $count } offene Aufgaben
+    }
+
+## Inlay hints
+
+section-length =
+    { $words ->
+        [one] ≈ 1 Wort · { $minutes } Min.
+       *[other] ≈ { $words } Wörter · { $minutes } Min.
+    }
@@ -0,0 +1,39 @@
+# English, the fallback for locales and messages without a translation.
+
+## Diagnostics
+
+broken-link = No note named "{ $target }"
+broken-url = { $url } is broken: { $problem }
+link-case = Link should be spelled "{ $corrected }" to work on case-sensitive file systems
+orphan-note = No other note links here
+dead-end-note = This note links to no other note
+unknown-note-type = Unknown note type "{ $name }", expected one of: { $expected }
+missing-note-field = Notes of type "{ $name }" require the "{ $field }" field
+missing-alt-text = Image has no alt text
+skipped-heading-level = Heading jumps from level { $previous } to level { $level }
+unknown-word = Unknown word "{ $word }"
+duplicate-name = Another note is also called "{ $name }", so links by that name are ambiguous
+duplicate-name-other = The other note called this
+duplicate-heading = An earlier heading has the same anchor "#{ $anchor }"
+duplicate-heading-first = The earlier heading
+
+## Code lenses and preview badges
+
+backlinks =
+    { $count ->
+        [one] 1 backlink
+       *[other] { $count } backlinks
+    }
+open-tasks =
+    { $count ->
+        [one] 1 open task
+       *[other] { $count } open tasks
+    }
+
+## Inlay hints
+
+section-length =
+    { $words ->
+        [one] ≈ 1 word · { $minutes } min
+       *[other] ≈ { $words } words · { $minutes } min
+    }
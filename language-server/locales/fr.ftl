@@ -0,0 +1,37 @@
+## Diagnostics
+
+broken-link = Aucune note nommée « { $target } »
+broken-url = { $url } est cassé : { $problem }
+link-case = Le lien doit s’écrire « { $corrected } » pour fonctionner sur les systèmes de fichiers sensibles à la casse
+orphan-note = Aucune autre note ne mène ici
+dead-end-note = Cette note ne mène à aucune autre note
+unknown-note-type = Type de note « { $name } » inconnu, types possibles : { $expected }
+missing-note-field = Les notes de type « { $name } » demandent le champ « { $field } »
+missing-alt-text = Image sans texte alternatif
+skipped-heading-level = Le titre passe du niveau { $previous } au niveau { $level }
+unknown-word = Mot inconnu « { $word } »
+duplicate-name = Une autre note s’appelle aussi « { $name } », les liens par ce nom sont donc ambigus
+duplicate-name-other = L’autre note de ce nom
+duplicate-heading = Un titre précédent a la même ancre « #{ $anchor } »
+duplicate-heading-first = Le titre précédent
+
+## Code lenses and preview badges
+
+backlinks =
+    { $count ->
+        [one] { $count } rétrolien
+       *[other] { $count } rétroliens
+    }
+open-tasks =
+    { $count ->
+        [one] { $count } tâche ouverte
+       *[other] { $count } tâches ouvertes
+    }
+
+## Inlay hints
+
+section-length =
+    { $words ->
+        [one] ≈ { $words } mot · { $minutes } min
+       *[other] ≈ { $words } mots · { $minutes } min
+    }
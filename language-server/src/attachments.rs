@@ -0,0 +1,204 @@
+//! Attachments no note links to, for `noteLs.findUnusedAttachments`, and moving them out of the
+//! way into the vault's trash.
+//!
+//! Attachments are the files in the vault's attachments folder. One is used if a note links to
+//! it or embeds it, by its path or, for wiki embeds, by its name alone. Links in comments don't
+//! count, as they don't in the index.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use unicode_normalization::UnicodeNormalization;
+use walkdir::WalkDir;
+
+use crate::{
+    comments,
+    index::NoteIndex,
+    links::{self, Link, LinkKind},
+};
+
+/// Directory (relative to the vault root) that unused attachments are moved to.
+pub const TRASH_DIR: &str = ".trash";
+
+/// Every path and bare file name referenced by the notes in `index`. The links of the notes in
+/// `open`, which may not be saved yet, are read from their content there instead.
+///
+/// Wiki embeds like `![[image.png]]` are resolved by name anywhere in the vault, so their file
+/// names are collected separately from the resolved paths of markdown links.
+fn referenced_files(
+    index: &NoteIndex,
+    open: &HashMap<PathBuf, String>,
+) -> (HashSet<PathBuf>, HashSet<String>) {
+    let root = index.root();
+    let mut paths = HashSet::new();
+    let mut names = HashSet::new();
+    let mut add = |note: &Path, links: &[Link]| {
+        let note_dir = note.parent().unwrap_or(root);
+        for link in links {
+            let target = link.decoded_path();
+            if target.is_empty() {
+                continue;
+            }
+
+            if link.kind == LinkKind::Wiki {
+                if let Some(name) = Path::new(target.as_ref()).file_name() {
+                    names.insert(name.to_string_lossy().nfc().collect::<String>());
+                }
+            }
+
+            for base in [note_dir, root] {
+                if let Ok(path) = base.join(target.as_ref()).canonicalize() {
+                    paths.insert(path);
+                }
+            }
+        }
+    };
+
+    let open = open
+        .iter()
+        .map(|(path, content)| (links::nfc_path(path), (path, content)))
+        .collect::<HashMap<_, _>>();
+    for (path, note) in index.notes() {
+        if !open.contains_key(&links::nfc_path(path)) {
+            add(path, &note.links);
+        }
+    }
+    // Open notes new to the vault aren't indexed until they're saved.
+    for (path, content) in open.values() {
+        if path.starts_with(root) && index.is_note(path) && !index.is_ignored(path) {
+            add(path, &links::parse_links(&comments::blank(content)));
+        }
+    }
+
+    (paths, names)
+}
+
+/// Find the attachments in `folder`, relative to the vault root, that no note in `index`, or open
+/// in `open`, links to. Attachments the vault leaves out, e.g. with a `.gitignore` file, are
+/// never unused.
+pub fn find_unused(
+    index: &NoteIndex,
+    open: &HashMap<PathBuf, String>,
+    folder: &Path,
+) -> Vec<PathBuf> {
+    let (paths, names) = referenced_files(index, open);
+
+    WalkDir::new(index.root().join(folder))
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .filter(|path| !index.is_ignored(path))
+        .filter(|path| {
+            let by_name = path
+                .file_name()
+                .map(|name| names.contains(&name.to_string_lossy().nfc().collect::<String>()))
+                .unwrap_or(false);
+            let by_path = path
+                .canonicalize()
+                .map(|path| paths.contains(&path))
+                .unwrap_or(false);
+            !by_name && !by_path
+        })
+        .collect()
+}
+
+/// `path`, or if something is there already, the first of `name 2.ext`, `name 3.ext`, … that's
+/// free.
+fn free_path(path: PathBuf) -> PathBuf {
+    if !path.exists() {
+        return path;
+    }
+    let stem = path
+        .file_stem()
+        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|n| path.with_file_name(format!("{stem} {n}{extension}")))
+        .find(|path| !path.exists())
+        .expect("some name is free")
+}
+
+/// Move `files` into the vault's trash directory, keeping their path relative to `root`. A file
+/// trashed earlier by the same name is kept, and the new one numbered.
+pub fn move_to_trash(root: &Path, files: &[PathBuf]) -> io::Result<()> {
+    let trash = root.join(TRASH_DIR);
+
+    for file in files {
+        let relative = file.strip_prefix(root).unwrap_or(file);
+        let destination = free_path(trash.join(relative));
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(file, destination)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ATTACHMENTS: &str = "attachments";
+
+    #[test]
+    fn unused_attachments_are_found() {
+        let root = tempfile::tempdir().unwrap();
+        let attachments = root.path().join(ATTACHMENTS);
+        fs::create_dir(&attachments).unwrap();
+
+        for name in ["used.png", "embedded.png", "unused.png"] {
+            fs::write(attachments.join(name), "").unwrap();
+        }
+        fs::write(
+            root.path().join("note.md"),
+            "![](attachments/used.png)\n![[embedded.png]]\n",
+        )
+        .unwrap();
+
+        let index = NoteIndex::scan(root.path());
+        let folder = Path::new(ATTACHMENTS);
+        let unused = find_unused(&index, &HashMap::new(), folder);
+        assert_eq!(unused, vec![attachments.join("unused.png")]);
+
+        // What an open note links to counts before it's saved, except in comments.
+        let open = HashMap::from([(
+            root.path().join("note.md"),
+            "![[embedded.png]] ![[unused.png]] %% ![](attachments/used.png) %%".to_string(),
+        )]);
+        let mut unused_open = find_unused(&index, &open, folder);
+        unused_open.sort();
+        assert_eq!(unused_open, vec![attachments.join("used.png")]);
+
+        move_to_trash(root.path(), &unused).unwrap();
+        assert!(!attachments.join("unused.png").exists());
+        assert!(root
+            .path()
+            .join(TRASH_DIR)
+            .join(ATTACHMENTS)
+            .join("unused.png")
+            .exists());
+    }
+
+    #[test]
+    fn trashed_files_keep_earlier_ones() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        let trashed = root.join(TRASH_DIR).join("old.png");
+        for content in ["first", "second", "third"] {
+            fs::write(root.join("old.png"), content).unwrap();
+            move_to_trash(root, &[root.join("old.png")]).unwrap();
+        }
+        let read = |name: &str| fs::read_to_string(root.join(TRASH_DIR).join(name)).unwrap();
+        assert_eq!(fs::read_to_string(trashed).unwrap(), "first");
+        assert_eq!(read("old 2.png"), "second");
+        assert_eq!(read("old 3.png"), "third");
+    }
+}
@@ -0,0 +1,245 @@
+//! Backups of a whole vault, for `noteLs.backupVault`, as a safety net that doesn't depend on
+//! git.
+//!
+//! A backup is a zip of every file in the vault that isn't left out of it, with a
+//! `manifest.json` listing the notes with their titles, tags and a SHA-256 hash of their content.
+//! Backups are named after the vault and the time they were made, so the oldest ones can be
+//! found by name and pruned.
+
+use std::{
+    fs::{self, File},
+    io::{self, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{bundle, index::NoteIndex, new_notes::Date};
+
+/// The name of the manifest in a backup.
+pub const MANIFEST: &str = "manifest.json";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestNote {
+    /// The note's path in the vault.
+    pub path: String,
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+    pub sha256: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    /// When the backup was made, e.g. `2024-01-31T09:30:00Z`.
+    pub created: String,
+    pub notes: Vec<ManifestNote>,
+}
+
+/// The time `seconds` after the Unix epoch, split into the date and `[hour, minute, second]`.
+fn split_time(seconds: u64) -> (Date, [u64; 3]) {
+    let date = Date::from_days((seconds / 86_400) as i64);
+    let time = seconds % 86_400;
+    (date, [time / 3600, time / 60 % 60, time % 60])
+}
+
+/// The name of the vault at `root`, which its backups are named after.
+fn vault_name(root: &Path) -> String {
+    root.file_name().map_or_else(
+        || String::from("vault"),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+/// The file name of a backup of the vault at `root` made `seconds` after the Unix epoch, e.g.
+/// `notes-2024-01-31-093000.zip`.
+pub fn archive_name(root: &Path, seconds: u64) -> String {
+    let (date, [hour, minute, second]) = split_time(seconds);
+    format!(
+        "{}-{date}-{hour:02}{minute:02}{second:02}.zip",
+        vault_name(root)
+    )
+}
+
+/// Whether `file_name` is the name of a backup of the vault at `root`.
+fn is_backup(file_name: &str, root: &Path) -> bool {
+    let Some(stamp) = file_name
+        .strip_prefix(&vault_name(root))
+        .and_then(|rest| rest.strip_prefix('-'))
+        .and_then(|rest| rest.strip_suffix(".zip"))
+    else {
+        return false;
+    };
+    // `YYYY-MM-DD-hhmmss`
+    stamp.len() == 17
+        && stamp.char_indices().all(|(i, c)| match i {
+            4 | 7 | 10 => c == '-',
+            _ => c.is_ascii_digit(),
+        })
+}
+
+/// The title and tags of a note, as its index has them.
+struct NoteDetails {
+    title: Option<String>,
+    tags: Vec<String>,
+}
+
+/// A backup of some of the files of a vault, with what it needs from the vault's index copied
+/// out, so it can be written without holding the index.
+pub struct Backup {
+    root: PathBuf,
+    /// The files, with the details of those that are notes.
+    files: Vec<(PathBuf, Option<NoteDetails>)>,
+}
+
+impl Backup {
+    /// A backup of `files`, the files of the vault indexed by `index`.
+    pub fn new(index: &NoteIndex, files: Vec<PathBuf>) -> Self {
+        let files = files
+            .into_iter()
+            .map(|path| {
+                let details = index.get(&path).map(|note| NoteDetails {
+                    title: note.title().map(str::to_string),
+                    tags: note.tags.iter().map(|tag| tag.name.clone()).collect(),
+                });
+                (path, details)
+            })
+            .collect();
+        Backup {
+            root: index.root().to_path_buf(),
+            files,
+        }
+    }
+
+    /// The manifest of the backup, made `seconds` after the Unix epoch.
+    fn manifest(&self, seconds: u64) -> io::Result<Manifest> {
+        let (date, [hour, minute, second]) = split_time(seconds);
+        let mut notes = Vec::new();
+        for (path, details) in &self.files {
+            let Some(details) = details else {
+                continue;
+            };
+            let hash = Sha256::digest(fs::read(path)?);
+            notes.push(ManifestNote {
+                path: bundle::entry_name(&self.root, path),
+                title: details.title.clone(),
+                tags: details.tags.clone(),
+                sha256: format!("{hash:x}"),
+            });
+        }
+        notes.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Manifest {
+            created: format!("{date}T{hour:02}:{minute:02}:{second:02}Z"),
+            notes,
+        })
+    }
+
+    /// Write the backup, made `seconds` after the Unix epoch, to `writer`.
+    pub fn write<W: Write + Seek>(&self, writer: W, seconds: u64) -> io::Result<()> {
+        let manifest = self.manifest(seconds)?;
+        let mut zip = ZipWriter::new(writer);
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (path, _) in &self.files {
+            zip.start_file(bundle::entry_name(&self.root, path), options)?;
+            io::copy(&mut File::open(path)?, &mut zip)?;
+        }
+        zip.start_file(MANIFEST, options)?;
+        serde_json::to_writer_pretty(&mut zip, &manifest)?;
+        zip.finish()?;
+        Ok(())
+    }
+}
+
+/// Delete all but the `keep` newest backups of the vault at `root` in `folder`, returning the
+/// paths deleted.
+pub fn prune(folder: &Path, root: &Path, keep: usize) -> io::Result<Vec<PathBuf>> {
+    let mut backups = fs::read_dir(folder)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| is_backup(&entry.file_name().to_string_lossy(), root))
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    // The names sort by the time they were made.
+    backups.sort();
+    let stale = backups.len().saturating_sub(keep);
+    let stale = backups.into_iter().take(stale).collect::<Vec<_>>();
+    for path in &stale {
+        fs::remove_file(path)?;
+    }
+    Ok(stale)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::*;
+
+    #[test]
+    fn backups_hold_the_vault_and_a_manifest() {
+        let vault = tempfile::tempdir().unwrap();
+        let root = vault.path().join("notes");
+        fs::create_dir_all(root.join("img")).unwrap();
+        fs::write(root.join("a.md"), "---\ntitle: A\n---\n#idea").unwrap();
+        fs::write(root.join("img/cat.png"), "meow").unwrap();
+        let index = NoteIndex::scan(&root);
+        let files = vec![root.join("a.md"), root.join("img/cat.png")];
+
+        let mut zip = Cursor::new(Vec::new());
+        Backup::new(&index, files)
+            .write(&mut zip, 1_706_693_400)
+            .unwrap();
+        let mut archive = zip::ZipArchive::new(zip).unwrap();
+        let mut read = |name: &str| {
+            let mut content = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            content
+        };
+        assert_eq!(read("img/cat.png"), "meow");
+        let manifest: serde_json::Value = serde_json::from_str(&read(MANIFEST)).unwrap();
+        assert_eq!(
+            manifest,
+            serde_json::json!({
+                "created": "2024-01-31T09:30:00Z",
+                "notes": [{
+                    "path": "a.md",
+                    "title": "A",
+                    "tags": ["idea"],
+                    "sha256": format!("{:x}", Sha256::digest("---\ntitle: A\n---\n#idea")),
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn only_the_newest_backups_are_kept() {
+        let folder = tempfile::tempdir().unwrap();
+        let folder = folder.path();
+        let root = Path::new("/vaults/notes");
+        assert_eq!(
+            archive_name(root, 1_706_693_400),
+            "notes-2024-01-31-093000.zip"
+        );
+        for name in [
+            "notes-2024-01-31-093000.zip",
+            "notes-2023-12-01-120000.zip",
+            "notes-2024-02-01-000000.zip",
+            "notes-important.zip",
+            "other-2020-01-01-000000.zip",
+        ] {
+            fs::write(folder.join(name), "").unwrap();
+        }
+
+        let pruned = prune(folder, root, 2).unwrap();
+
+        assert_eq!(pruned, vec![folder.join("notes-2023-12-01-120000.zip")]);
+        assert!(folder.join("notes-important.zip").exists());
+        assert!(folder.join("other-2020-01-01-000000.zip").exists());
+    }
+}
@@ -0,0 +1,118 @@
+//! Badges after the links in the preview, counting the backlinks of the notes they point to and
+//! the open tasks of project notes, so the preview doubles as a dashboard of the vault.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    fences::Fences,
+    frontmatter,
+    i18n::Messages,
+    index::{Note, NoteIndex},
+    links,
+};
+
+/// The `type` or tag of project notes, whose badges count their open tasks.
+pub const PROJECT: &str = "project";
+
+fn is_project(note: &Note) -> bool {
+    note.frontmatter.note_type.as_deref() == Some(PROJECT)
+        || note.tags.iter().any(|tag| tag.name == PROJECT)
+}
+
+/// The badge for the note at `target`, if it's in `index`.
+fn badge(index: &NoteIndex, target: &Path, messages: &Messages) -> Option<String> {
+    let note = index.get(target)?;
+    let linking = index
+        .backlinks(target)
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect::<HashSet<_>>();
+    let mut text = messages.count("backlinks", linking.len());
+    if is_project(note) {
+        text.push_str(" · ");
+        text.push_str(&messages.count("open-tasks", note.open_tasks));
+    }
+    Some(format!("<sup class=\"note-badge\">{text}</sup>"))
+}
+
+/// `markdown`, the content of the note at `path`, with a badge after every link to a note in
+/// `index`. Links in frontmatter and fenced code blocks are left alone, and so are embeds.
+pub fn decorate(markdown: &str, path: &Path, index: &NoteIndex, messages: &Messages) -> String {
+    let mut badges = HashMap::<PathBuf, Option<String>>::new();
+    let mut fences = Fences::default();
+    let body_start = frontmatter::body_start(markdown) as usize;
+
+    let mut decorated = markdown
+        .lines()
+        .enumerate()
+        .map(|(n, line)| {
+            if n < body_start || fences.in_code(line) {
+                return line.to_string();
+            }
+
+            let links = links::parse_line(line, n as u32);
+            let mut line = line.to_string();
+            // From the end of the line, so the offsets of the links before stay put.
+            for link in links.iter().rev() {
+                if link.embed {
+                    continue;
+                }
+                let Some(target) = index.resolve(path, link) else {
+                    continue;
+                };
+                let badge = badges
+                    .entry(target)
+                    .or_insert_with_key(|target| badge(index, target, messages));
+                if let Some(badge) = badge {
+                    line.insert_str(link.end, badge);
+                }
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if markdown.ends_with('\n') {
+        decorated.push('\n');
+    }
+    decorated
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn links_to_notes_get_badges() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        let write = |name: &str, content: &str| fs::write(root.join(name), content).unwrap();
+        write(
+            "plan.md",
+            "---\ntype: project\n---\n- [ ] a\n- [ ] b\n- [x] c\n",
+        );
+        write("idea.md", "# Idea\n");
+        write("other.md", "[[plan]] [[plan]]");
+        let home = "# Home\n\nSee [[plan]] and [the idea](idea.md).\n\
+                    ![[idea]] [[missing]]\n```\n[[plan]]\n```\n";
+        write("home.md", home);
+        let index = NoteIndex::scan(root);
+
+        let decorated = decorate(home, &root.join("home.md"), &index, &Messages::default());
+        assert_eq!(decorated.lines().count(), home.lines().count());
+        assert_eq!(
+            decorated.lines().nth(2),
+            Some(
+                "See [[plan]]<sup class=\"note-badge\">2 backlinks · 2 open tasks</sup> and \
+                 [the idea](idea.md)<sup class=\"note-badge\">1 backlink</sup>."
+            )
+        );
+        assert_eq!(decorated.lines().nth(3), Some("![[idea]] [[missing]]"));
+        assert_eq!(decorated.lines().nth(5), Some("[[plan]]"));
+        assert!(decorated.ends_with('\n'));
+    }
+}
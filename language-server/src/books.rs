@@ -0,0 +1,296 @@
+//! A reading log kept in the frontmatter of notes of type `book`.
+//!
+//! A book's progress is its `page`, out of its `pages`, or its `percent`. Its `status` is
+//! `reading` or `finished`, or else follows from the progress. `noteLs.updateReadingProgress`
+//! keeps these fields in step, and `noteLs/books` lists the books being read and the ones
+//! finished.
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Position, Range, TextEdit, Url};
+
+use crate::{
+    frontmatter::{self, Frontmatter},
+    index::NoteIndex,
+    uri,
+};
+
+/// The `type` of book notes.
+pub const BOOK_TYPE: &str = "book";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Unread,
+    Reading,
+    Finished,
+}
+
+fn number(frontmatter: &Frontmatter, name: &str) -> Option<f64> {
+    let value = frontmatter.value(name)?.trim().trim_end_matches('%');
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite() && *n >= 0.0)
+}
+
+/// How much of the book has been read, in percent: its `percent`, or else its `page` out of its
+/// `pages`.
+pub fn percent(frontmatter: &Frontmatter) -> Option<u32> {
+    let percent = match number(frontmatter, "percent") {
+        Some(percent) => percent,
+        None => {
+            let pages = number(frontmatter, "pages").filter(|pages| *pages > 0.0)?;
+            number(frontmatter, "page")? / pages * 100.0
+        }
+    };
+    Some(percent.min(100.0).round() as u32)
+}
+
+pub fn status(frontmatter: &Frontmatter) -> Status {
+    match frontmatter.value("status") {
+        Some("finished" | "read") => Status::Finished,
+        Some("reading") => Status::Reading,
+        Some(_) => Status::Unread,
+        None => match percent(frontmatter) {
+            Some(100) => Status::Finished,
+            Some(percent) if percent > 0 => Status::Reading,
+            _ if number(frontmatter, "page").is_some_and(|page| page > 0.0) => Status::Reading,
+            _ => Status::Unread,
+        },
+    }
+}
+
+/// The fields to set for reading up to `page`, or `percent` of the book, or for finishing it.
+/// The one not given is worked out from the book's `pages` if it has them.
+pub fn progress_fields(
+    frontmatter: &Frontmatter,
+    page: Option<u32>,
+    percent: Option<u32>,
+    finished: bool,
+) -> Vec<(&'static str, String)> {
+    let pages = number(frontmatter, "pages").filter(|pages| *pages > 0.0);
+    let (page, percent) = match (page, percent) {
+        _ if finished => (pages.map(|pages| pages as u32), Some(100)),
+        (Some(page), _) => (
+            Some(page),
+            pages.map(|pages| (f64::from(page) / pages * 100.0).min(100.0).round() as u32),
+        ),
+        (None, Some(percent)) => (
+            pages.map(|pages| (f64::from(percent.min(100)) / 100.0 * pages).round() as u32),
+            Some(percent.min(100)),
+        ),
+        (None, None) => (None, None),
+    };
+
+    let mut fields = Vec::new();
+    if let Some(page) = page {
+        fields.push(("page", page.to_string()));
+    }
+    if let Some(percent) = percent {
+        fields.push(("percent", percent.to_string()));
+    }
+    let status = if finished || percent == Some(100) {
+        "finished"
+    } else {
+        "reading"
+    };
+    fields.push(("status", status.to_string()));
+    fields
+}
+
+/// Edits to `document` setting the top-level frontmatter `fields` to their values, adding the
+/// ones it doesn't have, and adding frontmatter if it has none.
+pub fn set_fields(document: &str, fields: &[(&str, String)]) -> Vec<TextEdit> {
+    let start = Position::new(0, 0);
+    let Some((yaml, body_line)) = frontmatter::split(document) else {
+        let block = fields
+            .iter()
+            .map(|(name, value)| format!("{name}: {value}\n"))
+            .collect::<String>();
+        return vec![TextEdit::new(
+            Range::new(start, start),
+            format!("---\n{block}---\n"),
+        )];
+    };
+
+    let mut edits = Vec::new();
+    let mut missing = String::new();
+    for (name, value) in fields {
+        let existing = yaml
+            .lines()
+            .enumerate()
+            .find(|(_, line)| line.split_once(':').is_some_and(|(key, _)| key == *name));
+        match existing {
+            Some((n, line)) => {
+                // The YAML starts on the second line of the document.
+                let n = n as u32 + 1;
+                edits.push(TextEdit::new(
+                    Range::new(Position::new(n, 0), Position::new(n, line.len() as u32)),
+                    format!("{name}: {value}"),
+                ));
+            }
+            None => missing.push_str(&format!("{name}: {value}\n")),
+        }
+    }
+    if !missing.is_empty() {
+        let closing = Position::new(body_line - 1, 0);
+        edits.push(TextEdit::new(Range::new(closing, closing), missing));
+    }
+    edits
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BooksParams {
+    /// A note in the vault to list the books of. Defaults to the note last edited, or else the
+    /// vault opened first.
+    pub uri: Option<Url>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Book {
+    pub uri: Url,
+    pub title: String,
+    pub author: Option<String>,
+    pub percent: Option<u32>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Books {
+    pub reading: Vec<Book>,
+    pub finished: Vec<Book>,
+}
+
+/// The books in `index` being read and the ones finished, by title.
+pub fn books(index: &NoteIndex) -> Books {
+    let mut books = Books::default();
+    for (path, note) in index.notes() {
+        let frontmatter = &note.frontmatter;
+        if frontmatter.note_type.as_deref() != Some(BOOK_TYPE) {
+            continue;
+        }
+        let list = match status(frontmatter) {
+            Status::Reading => &mut books.reading,
+            Status::Finished => &mut books.finished,
+            Status::Unread => continue,
+        };
+        let Some(uri) = uri::from_path(path) else {
+            continue;
+        };
+        let title = note
+            .title()
+            .map(str::to_string)
+            .or_else(|| Some(path.file_stem()?.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+        list.push(Book {
+            uri,
+            title,
+            author: frontmatter.value("author").map(str::to_string),
+            percent: percent(frontmatter),
+        });
+    }
+    books.reading.sort_by(|a, b| a.title.cmp(&b.title));
+    books.finished.sort_by(|a, b| a.title.cmp(&b.title));
+    books
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+
+    fn parse(document: &str) -> Frontmatter {
+        frontmatter::parse(document).unwrap_or_default()
+    }
+
+    #[test]
+    fn progress_follows_pages() {
+        let book = parse("---\ntype: book\npages: 400\npage: 100\n---\n");
+        assert_eq!(percent(&book), Some(25));
+        assert_eq!(status(&book), Status::Reading);
+        assert_eq!(
+            progress_fields(&book, Some(200), None, false),
+            vec![
+                ("page", String::from("200")),
+                ("percent", String::from("50")),
+                ("status", String::from("reading"))
+            ]
+        );
+        assert_eq!(
+            progress_fields(&book, None, None, true),
+            vec![
+                ("page", String::from("400")),
+                ("percent", String::from("100")),
+                ("status", String::from("finished"))
+            ]
+        );
+
+        let book = parse("---\ntype: book\npercent: 100%\n---\n");
+        assert_eq!(status(&book), Status::Finished);
+        let book = parse("---\ntype: book\nstatus: reading\n---\n");
+        assert_eq!(status(&book), Status::Reading);
+        assert_eq!(status(&parse("---\ntype: book\n---\n")), Status::Unread);
+    }
+
+    #[test]
+    fn fields_are_replaced_or_added() {
+        let fields = [
+            ("page", String::from("12")),
+            ("status", String::from("reading")),
+        ];
+        let edits = set_fields("---\ntitle: Dune\npage: 3\n---\nBody", &fields);
+        assert_eq!(
+            edits,
+            vec![
+                TextEdit::new(
+                    Range::new(Position::new(2, 0), Position::new(2, 7)),
+                    String::from("page: 12")
+                ),
+                TextEdit::new(
+                    Range::new(Position::new(3, 0), Position::new(3, 0)),
+                    String::from("status: reading\n")
+                ),
+            ]
+        );
+        assert_eq!(
+            set_fields("Body", &fields)[0].new_text,
+            "---\npage: 12\nstatus: reading\n---\n"
+        );
+    }
+
+    #[test]
+    fn books_are_listed_by_status() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        let write = |name: &str, content: &str| fs::write(root.join(name), content).unwrap();
+        write(
+            "dune.md",
+            "---\ntype: book\ntitle: Dune\nauthor: Herbert\npercent: 40\n---\n",
+        );
+        write(
+            "emma.md",
+            "---\ntype: book\nstatus: finished\n---\n# Emma\n",
+        );
+        write("next.md", "---\ntype: book\n---\n");
+        write("journal.md", "---\npercent: 40\n---\n");
+        let index = NoteIndex::scan(root);
+
+        let books = books(&index);
+        let titles = |books: &[Book]| {
+            books
+                .iter()
+                .map(|book| book.title.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles(&books.reading), vec!["Dune"]);
+        assert_eq!(titles(&books.finished), vec!["Emma"]);
+        assert_eq!(books.reading[0].author.as_deref(), Some("Herbert"));
+        assert_eq!(books.reading[0].percent, Some(40));
+        assert_eq!(
+            uri::to_path(&books.finished[0].uri).as_deref(),
+            Some(Path::new(&root.join("emma.md")))
+        );
+    }
+}
@@ -0,0 +1,273 @@
+//! Zip bundles of a slice of the vault, for `noteLs.bundleNote`.
+//!
+//! A bundle holds a note, the notes it links to up to some depth, and the attachments they use,
+//! laid out as they are in the vault. Links between files in the bundle are rewritten as paths
+//! relative to the linking note, so they work without the rest of the vault, and links to notes
+//! left out become plain text.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::{self, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use unicode_normalization::UnicodeNormalization;
+use walkdir::WalkDir;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    index::NoteIndex,
+    links::{self, Link, LinkKind},
+};
+
+/// The files that go into a bundle.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Contents {
+    /// The notes, the bundled note first.
+    pub notes: Vec<PathBuf>,
+    pub attachments: Vec<PathBuf>,
+}
+
+/// Finds the attachments links point to: relative to the linking note or the vault root, or for
+/// wiki links, by file name anywhere in the vault.
+struct Attachments<'a> {
+    index: &'a NoteIndex,
+    /// Files in the vault by name, read the first time a wiki link needs them.
+    by_name: Option<HashMap<String, PathBuf>>,
+}
+
+impl<'a> Attachments<'a> {
+    fn new(index: &'a NoteIndex) -> Self {
+        Self {
+            index,
+            by_name: None,
+        }
+    }
+
+    fn find(&mut self, from: &Path, link: &Link) -> Option<PathBuf> {
+        if link.is_external() || link.target_path().is_empty() {
+            return None;
+        }
+        let note_dir = from.parent().unwrap_or(self.index.root());
+        let by_path = link
+            .candidates(note_dir, self.index.root())
+            .into_iter()
+            .find(|candidate| candidate.is_file() && !self.index.is_note(candidate));
+        if by_path.is_some() || link.kind != LinkKind::Wiki {
+            return by_path;
+        }
+
+        let root = self.index.root();
+        let by_name = self.by_name.get_or_insert_with(|| {
+            WalkDir::new(root)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+                .map(|e| {
+                    let name = e.file_name().to_string_lossy().nfc().collect::<String>();
+                    (name, e.into_path())
+                })
+                .collect()
+        });
+        let name = link.decoded_path().nfc().collect::<String>();
+        by_name
+            .get(&name)
+            .filter(|path| !self.index.is_note(path))
+            .cloned()
+    }
+}
+
+/// The notes within `depth` links of the note at `path`, and the attachments they link to.
+pub fn contents(index: &NoteIndex, path: &Path, depth: usize) -> Contents {
+    let mut contents = Contents::default();
+    let mut attachments = Attachments::new(index);
+    let mut seen = HashSet::from([links::nfc_path(path)]);
+    let mut queue = VecDeque::from([(path.to_path_buf(), 0)]);
+    while let Some((note, distance)) = queue.pop_front() {
+        contents.notes.push(note.clone());
+        let Some(parsed) = index.get(&note) else {
+            continue;
+        };
+        for link in &parsed.links {
+            match index.resolve(&note, link) {
+                Some(target) if distance < depth => {
+                    if seen.insert(links::nfc_path(&target)) {
+                        queue.push_back((target, distance + 1));
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    if let Some(attachment) = attachments.find(&note, link) {
+                        if seen.insert(links::nfc_path(&attachment)) {
+                            contents.attachments.push(attachment);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    contents
+}
+
+/// The text a link to a file left out of the bundle is replaced with.
+fn plain_text(link: &Link) -> String {
+    let text = match link.kind {
+        LinkKind::Wiki => link.alias().unwrap_or(link.target_path()),
+        LinkKind::Markdown => link.text.as_deref().unwrap_or_default(),
+    };
+    text.to_string()
+}
+
+/// `link` in the note at `from` pointing at `target` by its path relative to the note.
+fn relative_link(link: &Link, from: &Path, target: &Path) -> String {
+    let note_dir = from.parent().unwrap_or(from);
+    let mut relative = links::relative_path(note_dir, target);
+    if link.kind == LinkKind::Wiki && Path::new(link.target_path()).extension().is_none() {
+        relative.set_extension("");
+    }
+    let relative = links::path_to_target(&relative);
+    match link.kind {
+        LinkKind::Wiki => link.with_target(&relative),
+        LinkKind::Markdown => link.with_target(&links::encode_target(&relative)),
+    }
+}
+
+/// `content`, the content of the note at `path`, with its links rewritten to work in a bundle
+/// of `contents`.
+pub fn rewrite_links(content: &str, path: &Path, index: &NoteIndex, contents: &Contents) -> String {
+    let bundled = contents
+        .notes
+        .iter()
+        .chain(&contents.attachments)
+        .map(|path| links::nfc_path(path))
+        .collect::<HashSet<_>>();
+    let mut attachments = Attachments::new(index);
+
+    let mut lines = content
+        .lines()
+        .enumerate()
+        .map(|(n, line)| {
+            let mut rewritten = line.to_string();
+            // From the end of the line, so the offsets of the links before stay put.
+            for link in links::parse_line(line, n as u32).iter().rev() {
+                if link.is_external() || link.target_path().is_empty() {
+                    continue;
+                }
+                let target = index
+                    .resolve(path, link)
+                    .or_else(|| attachments.find(path, link));
+                let new = match target {
+                    Some(target) if bundled.contains(&links::nfc_path(&target)) => {
+                        relative_link(link, path, &target)
+                    }
+                    _ => plain_text(link),
+                };
+                rewritten.replace_range(link.start..link.end, &new);
+            }
+            rewritten
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if content.ends_with('\n') {
+        lines.push('\n');
+    }
+    lines
+}
+
+/// The name of the file at `path` in a bundle of the vault at `root`.
+pub fn entry_name(root: &Path, path: &Path) -> String {
+    match path.strip_prefix(root) {
+        Ok(relative) => links::path_to_target(relative),
+        Err(_) => path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    }
+}
+
+/// Write a zip of `files`, pairs of a name in the zip and the file's bytes, to `writer`.
+pub fn write_zip<W: Write + Seek>(writer: W, files: &[(String, Vec<u8>)]) -> io::Result<()> {
+    let mut zip = ZipWriter::new(writer);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, bytes) in files {
+        zip.start_file(name.as_str(), options)?;
+        zip.write_all(bytes)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        io::{Cursor, Read},
+    };
+
+    use super::*;
+
+    #[test]
+    fn linked_notes_and_attachments_are_bundled() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::create_dir_all(root.join("notes/deep")).unwrap();
+        fs::create_dir(root.join("img")).unwrap();
+        let write = |name: &str, content: &str| fs::write(root.join(name), content).unwrap();
+        write("img/cat.png", "");
+        write("img/dog.png", "");
+        write(
+            "notes/start.md",
+            "[[deep/a|A]] and [b](b.md) ![cat](../img/cat.png) ![[dog.png]] \
+             [site](https://example.com)\n",
+        );
+        write("notes/deep/a.md", "[[c]] ![[../../img/cat.png]]");
+        write("b.md", "back to [[notes/start]]");
+        write("c.md", "");
+        let index = NoteIndex::scan(root);
+
+        let start = root.join("notes/start.md");
+        let contents = contents(&index, &start, 1);
+        assert_eq!(
+            contents,
+            Contents {
+                notes: vec![
+                    start.clone(),
+                    root.join("notes/deep/a.md"),
+                    root.join("b.md")
+                ],
+                attachments: vec![root.join("img/cat.png"), root.join("img/dog.png")],
+            }
+        );
+        assert_eq!(
+            super::contents(&index, &start, 0).notes,
+            vec![start.clone()]
+        );
+
+        let rewritten = |path: &Path| {
+            let content = fs::read_to_string(path).unwrap();
+            rewrite_links(&content, path, &index, &contents)
+        };
+        assert_eq!(
+            rewritten(&start),
+            "[[deep/a|A]] and [b](../b.md) ![cat](../img/cat.png) ![[../img/dog.png]] \
+             [site](https://example.com)\n"
+        );
+        assert_eq!(
+            rewritten(&root.join("notes/deep/a.md")),
+            "c ![[../../img/cat.png]]"
+        );
+        assert_eq!(entry_name(root, &start), "notes/start.md");
+
+        let mut zip = Cursor::new(Vec::new());
+        let files = vec![(String::from("notes/start.md"), b"# Start".to_vec())];
+        write_zip(&mut zip, &files).unwrap();
+        let mut archive = zip::ZipArchive::new(zip).unwrap();
+        let mut content = String::new();
+        archive
+            .by_name("notes/start.md")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "# Start");
+    }
+}
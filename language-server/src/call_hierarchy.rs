@@ -0,0 +1,137 @@
+//! The vault's link graph as a call hierarchy, so editors with a view for one can browse it: the
+//! notes linking to a note are its incoming calls, and the notes it links to its outgoing ones.
+
+use std::path::{Path, PathBuf};
+
+use tower_lsp::lsp_types::{
+    CallHierarchyIncomingCall, CallHierarchyItem, CallHierarchyOutgoingCall, Position, Range,
+    SymbolKind,
+};
+
+use crate::{index::NoteIndex, links, uri};
+
+/// The item for the note at `path`, named after its title, or else its path in the vault, which
+/// is its detail either way. It's placed on the note's `#` heading, if it has one.
+pub fn item(index: &NoteIndex, path: &Path) -> Option<CallHierarchyItem> {
+    let note = index.get(path)?;
+    let relative = path.strip_prefix(index.root()).unwrap_or(path);
+    let relative = links::path_to_target(&links::nfc_path(relative));
+    let line = note
+        .headings
+        .iter()
+        .find(|heading| heading.level == 1)
+        .map_or(0, |heading| heading.line);
+    let range = Range::new(Position::new(line, 0), Position::new(line, 0));
+    Some(CallHierarchyItem {
+        name: note
+            .title()
+            .map_or_else(|| relative.clone(), str::to_string),
+        kind: SymbolKind::FILE,
+        tags: None,
+        detail: Some(relative),
+        uri: uri::from_path(path)?,
+        range,
+        selection_range: range,
+        data: None,
+    })
+}
+
+/// The notes linking to the note at `target`, each with the ranges of its links to it. Links of
+/// a note to itself are left out, as in the graph.
+pub fn incoming_calls(index: &NoteIndex, target: &Path) -> Vec<CallHierarchyIncomingCall> {
+    let target_key = links::nfc_path(target);
+    // Backlinks come sorted by the note they're in.
+    let mut sources: Vec<(&Path, Vec<Range>)> = Vec::new();
+    for (source, link) in index.backlinks(target) {
+        if links::nfc_path(source) == target_key {
+            continue;
+        }
+        match sources.last_mut() {
+            Some((last, ranges)) if *last == source => ranges.push(link.range()),
+            _ => sources.push((source, vec![link.range()])),
+        }
+    }
+    sources
+        .into_iter()
+        .filter_map(|(source, from_ranges)| {
+            Some(CallHierarchyIncomingCall {
+                from: item(index, source)?,
+                from_ranges,
+            })
+        })
+        .collect()
+}
+
+/// The notes the note at `source` links to, in the order it first does, each with the ranges of
+/// the links to it in `source`. Links to attachments and missing notes are left out.
+pub fn outgoing_calls(index: &NoteIndex, source: &Path) -> Vec<CallHierarchyOutgoingCall> {
+    let Some(note) = index.get(source) else {
+        return Vec::new();
+    };
+    let source_key = links::nfc_path(source);
+    let mut targets: Vec<(PathBuf, Vec<Range>)> = Vec::new();
+    for link in &note.links {
+        let Some(target) = index.resolve(source, link) else {
+            continue;
+        };
+        let key = links::nfc_path(&target);
+        if key == source_key {
+            continue;
+        }
+        match targets
+            .iter_mut()
+            .find(|(other, _)| links::nfc_path(other) == key)
+        {
+            Some((_, ranges)) => ranges.push(link.range()),
+            None => targets.push((target, vec![link.range()])),
+        }
+    }
+    targets
+        .into_iter()
+        .filter_map(|(target, from_ranges)| {
+            Some(CallHierarchyOutgoingCall {
+                to: item(index, &target)?,
+                from_ranges,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn links_are_calls_between_notes() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::write(
+            root.join("a.md"),
+            "# Alpha\n[[b]] [[a]]\n[[b#x]] [[missing]]",
+        )
+        .unwrap();
+        fs::write(root.join("b.md"), "[[a]]").unwrap();
+        let index = NoteIndex::scan(root);
+
+        let a = item(&index, &root.join("a.md")).unwrap();
+        assert_eq!(a.name, "Alpha");
+        assert_eq!(a.detail.as_deref(), Some("a.md"));
+
+        let outgoing = outgoing_calls(&index, &root.join("a.md"));
+        assert_eq!(outgoing.len(), 1);
+        assert_eq!(outgoing[0].to.name, "b.md");
+        let lines = outgoing[0]
+            .from_ranges
+            .iter()
+            .map(|range| range.start.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [1, 2]);
+
+        let incoming = incoming_calls(&index, &root.join("a.md"));
+        assert_eq!(incoming.len(), 1);
+        assert_eq!(incoming[0].from.name, "b.md");
+        assert_eq!(incoming[0].from_ranges[0].end.character, 5);
+    }
+}
@@ -0,0 +1,414 @@
+//! Charts of data written in notes, drawn as SVG for the preview and exports.
+//!
+//! A ```` ```chart ```` block holds rows of comma-separated values, or a markdown table, after
+//! optional `type: bar` or `type: line` and `title: ...` lines:
+//!
+//! ````markdown
+//! ```chart
+//! type: line
+//! title: Weight
+//! Week, Morning, Evening
+//! 1, 80.2, 81
+//! 2, 79.5, 80.4
+//! ```
+//! ````
+//!
+//! The first row names the columns. The first column labels the points, and every other column is
+//! a series of numbers; cells that aren't numbers are left out. A `<!-- chart -->` or
+//! `<!-- chart: line -->` comment right above a markdown table draws the table the same way,
+//! above it.
+
+use std::fmt::Write;
+
+use crate::{code_blocks, habits::escape};
+
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 320.0;
+const MARGIN_LEFT: f64 = 56.0;
+const MARGIN_RIGHT: f64 = 16.0;
+const MARGIN_BOTTOM: f64 = 40.0;
+/// Colors of the series, in order.
+const COLORS: [&str; 6] = [
+    "#4e79a7", "#f28e2b", "#59a14f", "#e15759", "#76b7b2", "#b07aa1",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Kind {
+    #[default]
+    Bar,
+    Line,
+}
+
+impl Kind {
+    fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "bar" => Some(Kind::Bar),
+            "line" => Some(Kind::Line),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub name: String,
+    pub values: Vec<Option<f64>>,
+}
+
+/// A chart, with a value of each series for each label.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chart {
+    pub kind: Kind,
+    pub title: Option<String>,
+    pub labels: Vec<String>,
+    pub series: Vec<Series>,
+}
+
+/// The cells of a row of comma-separated values or of a markdown table, or `None` for a table's
+/// delimiter row.
+fn cells(line: &str) -> Option<Vec<String>> {
+    let line = line.trim();
+    if !line.starts_with('|') {
+        return Some(
+            line.split(',')
+                .map(|cell| cell.trim().to_string())
+                .collect(),
+        );
+    }
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    let cells = line.split('|').map(str::trim).collect::<Vec<_>>();
+    let delimiter = cells
+        .iter()
+        .all(|cell| !cell.is_empty() && cell.chars().all(|c| matches!(c, '-' | ':')));
+    (!delimiter).then(|| cells.into_iter().map(str::to_string).collect())
+}
+
+impl Chart {
+    /// The chart of `lines`: a header row and rows of data, after the options of a chart block.
+    pub fn parse<'a>(kind: Kind, lines: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        let mut chart = Chart {
+            kind,
+            title: None,
+            labels: Vec::new(),
+            series: Vec::new(),
+        };
+        let mut rows = Vec::new();
+        for line in lines.into_iter().filter(|line| !line.trim().is_empty()) {
+            if rows.is_empty() {
+                let option = line.split_once(':').map(|(key, value)| (key.trim(), value));
+                match option {
+                    Some(("type", value)) => {
+                        chart.kind = Kind::parse(value).unwrap_or(chart.kind);
+                        continue;
+                    }
+                    Some(("title", value)) => {
+                        chart.title = Some(value.trim().to_string());
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            rows.extend(cells(line));
+        }
+
+        let mut rows = rows.into_iter();
+        let header = rows.next()?;
+        chart.series = header
+            .iter()
+            .skip(1)
+            .map(|name| Series {
+                name: name.clone(),
+                values: Vec::new(),
+            })
+            .collect();
+        for row in rows {
+            let mut cells = row.into_iter();
+            chart.labels.push(cells.next().unwrap_or_default());
+            for series in &mut chart.series {
+                let cell = cells.next().unwrap_or_default();
+                series
+                    .values
+                    .push(cell.parse().ok().filter(|v: &f64| v.is_finite()));
+            }
+        }
+        let has_values = chart
+            .series
+            .iter()
+            .flat_map(|series| &series.values)
+            .any(Option::is_some);
+        has_values.then_some(chart)
+    }
+
+    /// The chart as an SVG element on a single line.
+    pub fn svg(&self) -> String {
+        let top = if self.title.is_some() { 36.0 } else { 16.0 };
+        let legend = if self.series.len() > 1 { 20.0 } else { 0.0 };
+        let plot_width = WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+        let plot_height = HEIGHT - top - MARGIN_BOTTOM - legend;
+        let bottom = top + plot_height;
+
+        let values = self
+            .series
+            .iter()
+            .flat_map(|series| series.values.iter().flatten());
+        let (min, max) = values.fold((0.0f64, 0.0f64), |(min, max), v| (min.min(*v), max.max(*v)));
+        let step = tick_step(max - min);
+        let low = (min / step).floor() * step;
+        let high = ((max / step).ceil() * step).max(low + step);
+        let y = |value: f64| bottom - (value - low) / (high - low) * plot_height;
+
+        let mut svg = format!(
+            "<svg class=\"chart\" xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {WIDTH} {HEIGHT}\" \
+             font-family=\"sans-serif\" font-size=\"12\" fill=\"currentColor\">"
+        );
+        if let Some(title) = &self.title {
+            let _ = write!(
+                svg,
+                "<text x=\"{}\" y=\"22\" text-anchor=\"middle\" font-size=\"15\" font-weight=\"bold\">{}</text>",
+                WIDTH / 2.0,
+                escape(title)
+            );
+        }
+
+        // Grid lines and their values.
+        let mut tick = low;
+        while tick <= high + step / 2.0 {
+            let _ = write!(
+                svg,
+                "<line x1=\"{MARGIN_LEFT}\" x2=\"{}\" y1=\"{y:.1}\" y2=\"{y:.1}\" stroke=\"currentColor\" \
+                 stroke-opacity=\"0.15\"/><text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>",
+                WIDTH - MARGIN_RIGHT,
+                MARGIN_LEFT - 6.0,
+                y(tick) + 4.0,
+                number(tick),
+                y = y(tick),
+            );
+            tick += step;
+        }
+
+        // Labels, thinned out so they don't overlap.
+        let count = self.labels.len().max(1);
+        let slot = plot_width / count as f64;
+        let every = (60.0 / slot).ceil().max(1.0) as usize;
+        let x = |i: usize| MARGIN_LEFT + slot * (i as f64 + 0.5);
+        for (i, label) in self.labels.iter().enumerate().step_by(every) {
+            let _ = write!(
+                svg,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+                x(i),
+                bottom + 18.0,
+                escape(label)
+            );
+        }
+
+        let zero = y(0.0f64.clamp(low, high));
+        let bar_width = slot * 0.8 / self.series.len().max(1) as f64;
+        for (n, series) in self.series.iter().enumerate() {
+            let color = COLORS[n % COLORS.len()];
+            match self.kind {
+                Kind::Bar => {
+                    for (i, value) in series.values.iter().enumerate() {
+                        let Some(value) = value else {
+                            continue;
+                        };
+                        let left = x(i) - slot * 0.4 + bar_width * n as f64;
+                        let (from, to) = (zero.min(y(*value)), zero.max(y(*value)));
+                        let _ = write!(
+                            svg,
+                            "<rect x=\"{left:.1}\" y=\"{from:.1}\" width=\"{bar_width:.1}\" height=\"{:.1}\" \
+                             fill=\"{color}\"><title>{}: {}</title></rect>",
+                            to - from,
+                            escape(&series.name),
+                            number(*value)
+                        );
+                    }
+                }
+                Kind::Line => {
+                    // Missing values break the line.
+                    let mut path = String::new();
+                    let mut pen_down = false;
+                    for (i, value) in series.values.iter().enumerate() {
+                        match value {
+                            Some(value) => {
+                                let command = if pen_down { 'L' } else { 'M' };
+                                let _ = write!(path, "{command}{:.1} {:.1}", x(i), y(*value));
+                                let _ = write!(
+                                    svg,
+                                    "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\" fill=\"{color}\"><title>{}: {}</title></circle>",
+                                    x(i),
+                                    y(*value),
+                                    escape(&series.name),
+                                    number(*value)
+                                );
+                                pen_down = true;
+                            }
+                            None => pen_down = false,
+                        }
+                    }
+                    let _ = write!(
+                        svg,
+                        "<path d=\"{path}\" fill=\"none\" stroke=\"{color}\" stroke-width=\"2\"/>"
+                    );
+                }
+            }
+        }
+
+        if self.series.len() > 1 {
+            let mut left = MARGIN_LEFT;
+            for (n, series) in self.series.iter().enumerate() {
+                let color = COLORS[n % COLORS.len()];
+                let _ = write!(
+                    svg,
+                    "<rect x=\"{left:.1}\" y=\"{:.1}\" width=\"10\" height=\"10\" fill=\"{color}\"/>\
+                     <text x=\"{:.1}\" y=\"{:.1}\">{}</text>",
+                    HEIGHT - 18.0,
+                    left + 14.0,
+                    HEIGHT - 9.0,
+                    escape(&series.name)
+                );
+                left += 28.0 + 7.0 * series.name.chars().count() as f64;
+            }
+        }
+        svg.push_str("</svg>");
+        svg
+    }
+}
+
+/// A round step between grid lines splitting `range` into about five.
+fn tick_step(range: f64) -> f64 {
+    if range <= 0.0 {
+        return 1.0;
+    }
+    let rough = range / 5.0;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .find(|step| step * magnitude >= rough)
+        .unwrap_or(10.0);
+    step * magnitude
+}
+
+/// `value` without trailing zeros, and with at most three decimals.
+fn number(value: f64) -> String {
+    let text = format!("{:.3}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" => String::from("0"),
+        text => text.to_string(),
+    }
+}
+
+/// The kind of chart a `<!-- chart -->` comment asks for, if `line` is one.
+fn directive(line: &str) -> Option<Kind> {
+    let comment = line
+        .trim()
+        .strip_prefix("<!--")?
+        .strip_suffix("-->")?
+        .trim();
+    let rest = comment.strip_prefix("chart")?;
+    match rest.trim().strip_prefix(':') {
+        Some(kind) => Kind::parse(kind),
+        None => rest.trim().is_empty().then_some(Kind::Bar),
+    }
+}
+
+/// `markdown` with its chart blocks, and the tables under chart comments, drawn as charts.
+///
+/// Like habit trackers, every line of a chart block stays on a line of its own, so the lines of
+/// the rest of the note still match the source when the preview scrolls to one.
+pub fn render(markdown: &str) -> String {
+    let mut lines = markdown.lines().map(str::to_string).collect::<Vec<_>>();
+    let mut changed = false;
+    for block in code_blocks::blocks(markdown) {
+        if block.language != "chart" {
+            continue;
+        }
+        let Some(chart) = Chart::parse(Kind::Bar, block.code.lines()) else {
+            continue;
+        };
+        for line in &mut lines[block.start as usize..=block.end as usize] {
+            // Blank lines would end the HTML block.
+            *line = String::from("<!-- -->");
+        }
+        lines[block.start as usize] = format!("<div class=\"chart\">{}</div>", chart.svg());
+        changed = true;
+    }
+
+    let source = markdown.lines().collect::<Vec<_>>();
+    for (n, line) in source.iter().enumerate() {
+        let Some(kind) = directive(line) else {
+            continue;
+        };
+        let table = source[n + 1..]
+            .iter()
+            .take_while(|line| line.trim_start().starts_with('|'))
+            .copied();
+        if let Some(chart) = Chart::parse(kind, table) {
+            // The table must start a block of its own after the chart.
+            lines[n] = format!("<div class=\"chart\">{}</div>\n", chart.svg());
+            changed = true;
+        }
+    }
+
+    if !changed {
+        return markdown.to_string();
+    }
+    let mut rendered = lines.join("\n");
+    if markdown.ends_with('\n') {
+        rendered.push('\n');
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charts_are_read_from_csv_and_tables() {
+        let chart = Chart::parse(
+            Kind::Bar,
+            "type: line\ntitle: Weight\nWeek, Morning, Evening\n1, 80.2, 81\n2, , 80.4\n".lines(),
+        )
+        .unwrap();
+        assert_eq!(chart.kind, Kind::Line);
+        assert_eq!(chart.title.as_deref(), Some("Weight"));
+        assert_eq!(chart.labels, ["1", "2"]);
+        assert_eq!(chart.series[0].name, "Morning");
+        assert_eq!(chart.series[0].values, [Some(80.2), None]);
+        assert_eq!(chart.series[1].values, [Some(81.0), Some(80.4)]);
+
+        let table = "| Month | Spent |\n|---|--:|\n| Jan | 120 |\n| Feb | 95.5 |";
+        let chart = Chart::parse(Kind::Bar, table.lines()).unwrap();
+        assert_eq!(chart.labels, ["Jan", "Feb"]);
+        assert_eq!(chart.series[0].values, [Some(120.0), Some(95.5)]);
+        assert_eq!(Chart::parse(Kind::Bar, "a, b\nx, y".lines()), None);
+
+        assert_eq!(
+            (tick_step(120.0), tick_step(0.8), tick_step(0.0)),
+            (50.0, 0.2, 1.0)
+        );
+        assert_eq!(
+            [number(95.5), number(100.0), number(-0.0001)],
+            ["95.5", "100", "0"]
+        );
+    }
+
+    #[test]
+    fn charts_are_drawn_in_place() {
+        let markdown = "# Spending\n```chart\nMonth, Spent\nJan, 120\n```\n\
+                        <!-- chart: line -->\n| Month | Spent |\n|---|---|\n| Jan | 120 |\n";
+        let rendered = render(markdown);
+        let lines = rendered.lines().collect::<Vec<_>>();
+        assert!(lines[1].starts_with("<div class=\"chart\"><svg"));
+        assert!(lines[1].contains("<rect"));
+        assert_eq!(lines[2..5], ["<!-- -->"; 3]);
+        assert!(lines[5].contains("<path d=\"M"));
+        assert_eq!(lines[6], "");
+        assert_eq!(lines[7], "| Month | Spent |");
+
+        let plain = "<!-- chart -->\nNo table\n```rust\nfn main() {}\n```\n";
+        assert_eq!(render(plain), plain);
+    }
+}
@@ -0,0 +1,360 @@
+//! Citations of BibTeX entries, written `[@key]` or `@key` as in Pandoc: completions of their keys
+//! and hovers showing the full reference.
+//!
+//! Entries come from the `.bib` files of the `bibliography` setting, read when they're needed so
+//! changes made by a reference manager show up right away. Only what's needed to tell entries
+//! apart and cite them is read: the key, the type and the fields, with braces and escapes of
+//! accents left in the values as they are.
+
+use std::{collections::BTreeMap, path::Path};
+
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, Documentation, Hover, HoverContents,
+    MarkupContent, MarkupKind, Position, Range, TextEdit,
+};
+
+/// An entry of a `.bib` file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Entry {
+    pub key: String,
+    /// The entry type, in lowercase, e.g. `article`.
+    pub kind: String,
+    /// The fields, by their lowercase names.
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Entry {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .get(name)
+            .map(String::as_str)
+            .filter(|value| !value.is_empty())
+    }
+
+    /// The authors, or the editors if there are none, by their last names: one, two joined with
+    /// "and", or the first one and "et al.".
+    pub fn authors(&self) -> Option<String> {
+        let names = self.field("author").or_else(|| self.field("editor"))?;
+        let last_names = names
+            .split(" and ")
+            .map(|name| match name.split_once(',') {
+                Some((last, _)) => last.trim(),
+                None => name.split_whitespace().last().unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+        Some(match last_names.as_slice() {
+            [one] => one.to_string(),
+            [first, second] => format!("{first} and {second}"),
+            [first, ..] => format!("{first} et al."),
+            [] => return None,
+        })
+    }
+
+    /// A short description, e.g. `Knuth (1984)`.
+    pub fn short(&self) -> String {
+        let authors = self.authors().unwrap_or_else(|| self.key.clone());
+        match self.field("year") {
+            Some(year) => format!("{authors} ({year})"),
+            None => authors,
+        }
+    }
+
+    /// The full reference, e.g. `Knuth, Donald E. (1984). Literate Programming. The Computer
+    /// Journal, 27(2), 97–111.`
+    pub fn reference(&self) -> String {
+        let mut reference = self
+            .field("author")
+            .or_else(|| self.field("editor"))
+            .map(|names| names.replace(" and ", "; "))
+            .unwrap_or_else(|| self.key.clone());
+        if let Some(year) = self.field("year") {
+            reference.push_str(&format!(" ({year})"));
+        }
+        reference.push('.');
+        if let Some(title) = self.field("title") {
+            reference.push_str(&format!(" {title}."));
+        }
+        let source = ["journal", "booktitle", "publisher", "school", "institution"]
+            .into_iter()
+            .find_map(|field| self.field(field));
+        if let Some(source) = source {
+            reference.push_str(&format!(" {source}"));
+            if let Some(volume) = self.field("volume") {
+                reference.push_str(&format!(", {volume}"));
+                if let Some(number) = self.field("number") {
+                    reference.push_str(&format!("({number})"));
+                }
+            }
+            if let Some(pages) = self.field("pages") {
+                reference.push_str(&format!(", {}", pages.replace("--", "–")));
+            }
+            reference.push('.');
+        }
+        if let Some(doi) = self.field("doi") {
+            reference.push_str(&format!(" https://doi.org/{doi}"));
+        } else if let Some(url) = self.field("url") {
+            reference.push_str(&format!(" {url}"));
+        }
+        reference
+    }
+}
+
+/// `value` without the braces grouping it and with its whitespace collapsed.
+fn clean(value: &str) -> String {
+    value
+        .replace(['{', '}'], "")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The value at the start of `text`, braced, quoted or bare, and the length it takes up.
+fn value(text: &str) -> (String, usize) {
+    let close = match text.chars().next() {
+        Some('{') => '}',
+        Some('"') => '"',
+        _ => {
+            let end = text.find([',', '}']).unwrap_or(text.len());
+            return (clean(&text[..end]), end);
+        }
+    };
+    let mut depth = 0;
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            c if c == close && depth == 0 => return (clean(&text[1..i]), i + 1),
+            _ => {}
+        }
+    }
+    (clean(&text[1..]), text.len())
+}
+
+/// The fields of an entry, from the text after its key up to its closing brace.
+fn fields(mut text: &str) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+    loop {
+        text = text.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+        let Some(equals) = text.find('=') else {
+            break;
+        };
+        if text[..equals].contains('}') {
+            break;
+        }
+        let name = text[..equals].trim().to_lowercase();
+        let rest = text[equals + 1..].trim_start();
+        let (value, len) = value(rest);
+        fields.insert(name, value);
+        text = &rest[len..];
+    }
+    fields
+}
+
+/// The entries of the BibTeX file `bib`, each starting a line. `@string`, `@preamble` and
+/// `@comment` aren't entries.
+pub fn parse(bib: &str) -> Vec<Entry> {
+    let starts = bib
+        .match_indices('@')
+        .map(|(i, _)| i)
+        .filter(|&i| {
+            let line_start = bib[..i].rfind('\n').map_or(0, |n| n + 1);
+            bib[line_start..i].trim().is_empty()
+        })
+        .collect::<Vec<_>>();
+    let ends = starts.iter().skip(1).copied().chain([bib.len()]);
+    starts
+        .iter()
+        .zip(ends)
+        .filter_map(|(&start, end)| {
+            let entry = &bib[start + 1..end];
+            let (kind, rest) = entry.split_once(['{', '('])?;
+            let kind = kind.trim().to_lowercase();
+            if kind.is_empty()
+                || !kind.chars().all(|c| c.is_ascii_alphabetic())
+                || matches!(kind.as_str(), "string" | "preamble" | "comment")
+            {
+                return None;
+            }
+            let (key, rest) = rest.split_once(',')?;
+            let key = key.trim();
+            (!key.is_empty() && !key.contains(char::is_whitespace)).then(|| Entry {
+                key: key.to_string(),
+                kind,
+                fields: fields(rest),
+            })
+        })
+        .collect()
+}
+
+/// The entries of the `.bib` files at `paths`, relative to the vault at `root` unless absolute.
+/// Files that can't be read are left out.
+pub async fn load(root: &Path, paths: &[impl AsRef<Path>]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for path in paths {
+        if let Ok(bib) = tokio::fs::read_to_string(root.join(path)).await {
+            entries.extend(parse(&bib));
+        }
+    }
+    entries
+}
+
+/// Whether `c` can be in a citation key, after its first character.
+fn is_key_char(c: char) -> bool {
+    c.is_alphanumeric() || "_-:.#$%&+?<>~/".contains(c)
+}
+
+/// The byte offset of the `@` of the citation `line` has at byte `character` and the end of its
+/// key. The `@` starts the line or follows whitespace, `[`, `;` or `-`, so e-mail addresses
+/// aren't citations.
+fn citation_at(line: &str, character: usize) -> Option<(usize, usize)> {
+    let before = line.get(..character)?;
+    let key_start = before.len()
+        - before
+            .chars()
+            .rev()
+            .take_while(|&c| is_key_char(c))
+            .map(char::len_utf8)
+            .sum::<usize>();
+    let at = key_start.checked_sub(1)?;
+    if !before[at..].starts_with('@') {
+        return None;
+    }
+    let starts = before[..at]
+        .chars()
+        .next_back()
+        .is_none_or(|c| c.is_whitespace() || matches!(c, '[' | ';' | '-'));
+    if !starts {
+        return None;
+    }
+    let key_end = character + line[character..].len()
+        - line[character..].trim_start_matches(is_key_char).len();
+    // Keys can't end in punctuation, which is taken to end the sentence instead.
+    let key = line[key_start..key_end].trim_end_matches(|c: char| !c.is_alphanumeric());
+    Some((at, key_start + key.len()))
+}
+
+/// What's been typed of the citation key at `position` of `document`, and the range from its
+/// `@` to the cursor, replaced by a completion.
+pub fn typed_key(document: &str, position: Position) -> Option<(&str, Range)> {
+    let line = document.lines().nth(position.line as usize)?;
+    let character = position.character as usize;
+    let (at, _) = citation_at(line, character)?;
+    let range = Range::new(Position::new(position.line, at as u32), position);
+    Some((&line[at + 1..character], range))
+}
+
+/// The key of the citation at `position` of `document`, and its range.
+pub fn key_at(document: &str, position: Position) -> Option<(&str, Range)> {
+    let line = document.lines().nth(position.line as usize)?;
+    let character = position.character as usize;
+    let key_end = character
+        + line
+            .get(character..)?
+            .chars()
+            .take_while(|&c| is_key_char(c))
+            .map(char::len_utf8)
+            .sum::<usize>();
+    let (at, end) = citation_at(line, key_end)?;
+    let key = &line[at + 1..end];
+    let range = Range::new(
+        Position::new(position.line, at as u32),
+        Position::new(position.line, end as u32),
+    );
+    (!key.is_empty() && character <= end).then_some((key, range))
+}
+
+/// Completions of citation keys, replacing `range`: every entry, described by its authors and
+/// year, with its title.
+pub fn completions(entries: &[Entry], range: Range) -> Vec<CompletionItem> {
+    entries
+        .iter()
+        .map(|entry| CompletionItem {
+            label: entry.key.clone(),
+            kind: Some(CompletionItemKind::REFERENCE),
+            detail: Some(match entry.field("title") {
+                Some(title) => format!("{} — {title}", entry.short()),
+                None => entry.short(),
+            }),
+            documentation: Some(Documentation::String(entry.reference())),
+            // Typing part of the title or an author's name finds the entry too.
+            filter_text: Some(format!(
+                "@{} {} {}",
+                entry.key,
+                entry.field("author").unwrap_or_default(),
+                entry.field("title").unwrap_or_default()
+            )),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                range,
+                format!("@{}", entry.key),
+            ))),
+            ..CompletionItem::default()
+        })
+        .collect()
+}
+
+/// The full reference of `entry` for a hover over a citation of it at `range`.
+pub fn hover(entry: &Entry, range: Range, markdown: bool) -> Hover {
+    let value = match markdown {
+        true => format!(
+            "**@{}** ({})\n\n{}",
+            entry.key,
+            entry.kind,
+            entry.reference()
+        ),
+        false => format!("@{} ({})\n\n{}", entry.key, entry.kind, entry.reference()),
+    };
+    let kind = match markdown {
+        true => MarkupKind::Markdown,
+        false => MarkupKind::PlainText,
+    };
+    Hover {
+        contents: HoverContents::Markup(MarkupContent { kind, value }),
+        range: Some(range),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_read_and_described() {
+        let bib = "@string{tcj = \"The Computer Journal\"}\n\
+                   @Article{knuth84,\n  author = {Knuth, Donald E.},\n  \
+                   title = {Literate {P}rogramming},\n  journal = \"The Computer Journal\",\n  \
+                   year = 1984, volume = {27}, number = {2}, pages = {97--111}\n}\n\
+                   @comment{ignored}\n\
+                   @book{gof, title={Design Patterns}, author={Erich Gamma and Helm, Richard and \
+                   Ralph Johnson and John Vlissides}, publisher={Addison-Wesley}, year={1994}}\n";
+        let entries = parse(bib);
+        assert_eq!(
+            entries.iter().map(|e| e.key.as_str()).collect::<Vec<_>>(),
+            ["knuth84", "gof"]
+        );
+        assert_eq!(entries[0].kind, "article");
+        assert_eq!(entries[0].field("title"), Some("Literate Programming"));
+        assert_eq!(entries[0].short(), "Knuth (1984)");
+        assert_eq!(
+            entries[0].reference(),
+            "Knuth, Donald E. (1984). Literate Programming. The Computer Journal, 27(2), 97–111."
+        );
+        assert_eq!(entries[1].short(), "Gamma et al. (1994)");
+    }
+
+    #[test]
+    fn citations_are_found_at_the_cursor() {
+        let document = "As shown [@knuth84, p. 3; @gof]. Mail me@example.com or @gof.";
+        let at = |character| Position::new(0, character);
+
+        let (typed, range) = typed_key(document, at(14)).unwrap();
+        assert_eq!(typed, "knu");
+        assert_eq!(range, Range::new(at(10), at(14)));
+        assert_eq!(typed_key(document, at(11)).unwrap().0, "");
+        assert_eq!(typed_key(document, at(43)), None);
+
+        assert_eq!(key_at(document, at(12)).unwrap().0, "knuth84");
+        assert_eq!(key_at(document, at(28)).unwrap().0, "gof");
+        assert_eq!(key_at(document, at(57)).unwrap().0, "gof");
+        assert_eq!(key_at(document, at(5)), None);
+    }
+}
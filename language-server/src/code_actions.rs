@@ -0,0 +1,856 @@
+//! Quick fixes offered for links in a note, and refactorings of its text.
+
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::Path,
+};
+
+use serde_json::json;
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, Command, CreateFile, CreateFileOptions, Diagnostic,
+    DocumentChangeOperation, DocumentChanges, OneOf, OptionalVersionedTextDocumentIdentifier,
+    Position, Range, ResourceOp, TextDocumentEdit, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::{
+    blocks::{self, Block},
+    code_blocks::CodeBlock,
+    commands,
+    comments::{self, Kind},
+    encoding::Encoding,
+    links::{self, Link, LinkKind},
+    new_notes::Date,
+    tasks::{self, Task},
+    templates::{self, Template},
+    toc, uri,
+};
+
+/// Most characters of a selection's first line used for the name of the note it's extracted to.
+const EXTRACTED_TITLE_CHARS: usize = 60;
+
+/// A note name for `text`, taken from its first line without markdown markers or characters
+/// that can't be in file names or wiki links.
+fn title_for(text: &str) -> String {
+    let first = text
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or("");
+    let first = first
+        .trim()
+        .trim_start_matches(['#', '>', '-', '*', '+'])
+        .trim();
+    let title = first
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '[' | ']' | '#' | '^' => ' ',
+            c => c,
+        })
+        .take(EXTRACTED_TITLE_CHARS)
+        .collect::<String>();
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    let title = title.trim_end_matches('.');
+    if title.is_empty() {
+        String::from("Untitled")
+    } else {
+        title.to_string()
+    }
+}
+
+/// The target of a wiki link from a note in `note_dir` to the note at `path`.
+pub fn wiki_target(note_dir: &Path, path: &Path) -> String {
+    links::path_to_target(&links::relative_path(note_dir, &path.with_extension("")))
+}
+
+/// The edit moving the `range` selected in `document`, the note at `uri`, into a new note in
+/// `folder` and linking to the new note in its place, with the new note's name.
+///
+/// The new note is named `title`, or else after the first heading of the selection, or its first
+/// line if it has none. A number is added if a note by that name exists already. It gets the
+/// first of the vault's note `extensions`.
+pub fn extract_edit(
+    uri: &Url,
+    document: &str,
+    range: Range,
+    folder: &Path,
+    extensions: &[String],
+    title: Option<&str>,
+) -> Option<(String, WorkspaceEdit)> {
+    let (start, end) = (
+        Encoding::Utf8.offset(document, range.start),
+        Encoding::Utf8.offset(document, range.end),
+    );
+    let selected = document.get(start..end)?;
+    if selected.trim().is_empty() {
+        return None;
+    }
+
+    let note_dir = uri::to_path(uri)?.parent()?.to_path_buf();
+    let heading = selected
+        .lines()
+        .find(|line| line.trim_start().starts_with('#'));
+    let base = title_for(title.or(heading).unwrap_or(selected));
+    let extension = extensions.first().map_or("md", String::as_str);
+    let title = (1..)
+        .map(|n| match n {
+            1 => base.clone(),
+            n => format!("{base} {n}"),
+        })
+        .find(|title| !folder.join(format!("{title}.{extension}")).exists())?;
+    let new_path = folder.join(format!("{title}.{extension}"));
+    let new_uri = uri::from_path(&new_path)?;
+    let target = wiki_target(&note_dir, &new_path);
+
+    let operations = vec![
+        DocumentChangeOperation::Op(ResourceOp::Create(CreateFile {
+            uri: new_uri.clone(),
+            options: Some(CreateFileOptions {
+                overwrite: Some(false),
+                ignore_if_exists: Some(false),
+            }),
+            annotation_id: None,
+        })),
+        DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri: new_uri,
+                version: None,
+            },
+            edits: vec![OneOf::Left(TextEdit::new(
+                Range::new(Position::new(0, 0), Position::new(0, 0)),
+                format!("{}\n", selected.trim_end()),
+            ))],
+        }),
+        DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri: uri.clone(),
+                version: None,
+            },
+            edits: vec![OneOf::Left(TextEdit::new(
+                range,
+                // Keep the line break ending a selection of whole lines.
+                match selected.ends_with('\n') {
+                    true => format!("[[{target}]]\n"),
+                    false => format!("[[{target}]]"),
+                },
+            ))],
+        }),
+    ];
+
+    let edit = WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Operations(operations)),
+        ..WorkspaceEdit::default()
+    };
+    Some((title, edit))
+}
+
+/// Move the `range` selected in `document`, the note at `uri`, into a new note in `folder` named
+/// after the selection, and link to the new note in its place.
+pub fn extract_selection(
+    uri: &Url,
+    document: &str,
+    range: Range,
+    folder: &Path,
+    extensions: &[String],
+) -> Option<CodeAction> {
+    let (title, edit) = extract_edit(uri, document, range, folder, extensions, None)?;
+    Some(CodeAction {
+        title: format!("Extract selection to new note '{title}'"),
+        kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+        edit: Some(edit),
+        ..CodeAction::default()
+    })
+}
+
+/// Like `extract_selection`, with `noteLs.extractToNote`, for clients that ask the user for the
+/// new note's name and add it as `title`.
+pub fn extract_to_note(uri: &Url, range: Range) -> CodeAction {
+    let title = String::from("Extract to new note…");
+    CodeAction {
+        title: title.clone(),
+        kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+        command: Some(Command::new(
+            title,
+            commands::EXTRACT_TO_NOTE.to_string(),
+            Some(vec![json!({ "uri": uri, "range": range })]),
+        )),
+        ..CodeAction::default()
+    }
+}
+
+/// Comment out the `range` selected in `document`, the note at `uri`, with `%% %%`, or uncomment
+/// the comments it's in or selects.
+pub fn toggle_comment(uri: &Url, document: &str, range: Range) -> Option<CodeAction> {
+    let (start, end) = (
+        Encoding::Utf8.offset(document, range.start),
+        Encoding::Utf8.offset(document, range.end),
+    );
+    if start >= end {
+        return None;
+    }
+    let comments = comments::parse_comments(document)
+        .into_iter()
+        .filter(|comment| comment.start < end && start < comment.end)
+        .collect::<Vec<_>>();
+
+    let (title, edits) = if comments.is_empty() {
+        // A selection of whole lines ends after a line break, which stays after the comment.
+        let selected = &document[start..end];
+        let end = match selected.strip_suffix('\n') {
+            Some(selected) if !selected.is_empty() => start + selected.trim_end_matches('\r').len(),
+            _ => end,
+        };
+        let (start, end) = (
+            Encoding::Utf8.position(document, start),
+            Encoding::Utf8.position(document, end),
+        );
+        let open = Kind::Percent.open().to_string();
+        let close = Kind::Percent.close().to_string();
+        (
+            "Comment out",
+            vec![
+                TextEdit::new(Range::new(start, start), open),
+                TextEdit::new(Range::new(end, end), close),
+            ],
+        )
+    } else {
+        let mut edits = Vec::new();
+        for comment in comments {
+            let (open, close) = (comment.kind.open(), comment.kind.close());
+            let text_start = comment.start + open.len();
+            edits.push(TextEdit::new(
+                Range::new(
+                    Encoding::Utf8.position(document, comment.start),
+                    Encoding::Utf8.position(document, text_start),
+                ),
+                String::new(),
+            ));
+            // A comment that's never closed goes on to the end of the note.
+            let text = &document[text_start..comment.end];
+            if text.ends_with(close) {
+                edits.push(TextEdit::new(
+                    Range::new(
+                        Encoding::Utf8.position(document, comment.end - close.len()),
+                        Encoding::Utf8.position(document, comment.end),
+                    ),
+                    String::new(),
+                ));
+            }
+        }
+        ("Uncomment", edits)
+    };
+
+    Some(CodeAction {
+        title: title.to_string(),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..WorkspaceEdit::default()
+        }),
+        ..CodeAction::default()
+    })
+}
+
+/// A quick fix applying a single edit to the document at `uri`.
+fn quick_fix(title: String, uri: &Url, edit: TextEdit) -> CodeAction {
+    CodeAction {
+        title,
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+            ..WorkspaceEdit::default()
+        }),
+        ..CodeAction::default()
+    }
+}
+
+/// Check `task` in `document`, or uncheck it if it's done, along with the tasks nested under it.
+pub fn toggle_task(uri: &Url, document: &str, task: &Task) -> CodeAction {
+    let title = match task.done {
+        true => "Mark task as not done",
+        false => "Mark task as done",
+    };
+    CodeAction {
+        title: title.to_string(),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(
+                uri.clone(),
+                tasks::toggle(document, task),
+            )])),
+            ..WorkspaceEdit::default()
+        }),
+        ..CodeAction::default()
+    }
+}
+
+/// Bring the table of contents of `document`, the note at `uri`, up to date, or insert one before
+/// `line` if it has none. There's nothing to offer if it's up to date.
+pub fn update_toc(uri: &Url, document: &str, line: u32) -> Option<CodeAction> {
+    let title = match toc::block(document) {
+        Some(_) => "Update table of contents",
+        None => "Insert table of contents",
+    };
+    let edit = toc::update(document, Some(line))?;
+    Some(CodeAction {
+        title: title.to_string(),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), vec![edit])])),
+            ..WorkspaceEdit::default()
+        }),
+        ..CodeAction::default()
+    })
+}
+
+/// A new ID for the block with `text`, six letters and digits like the ones Obsidian makes, that
+/// none of the `used` blocks has. The same text gets the same ID.
+fn new_block_id(text: &str, used: &[Block]) -> String {
+    (0u32..)
+        .map(|attempt| {
+            let mut hasher = DefaultHasher::new();
+            (text, attempt).hash(&mut hasher);
+            let mut n = hasher.finish();
+            (0..6)
+                .map(|_| {
+                    let digit = (n % 36) as u32;
+                    n /= 36;
+                    char::from_digit(digit, 36).unwrap_or('0')
+                })
+                .collect::<String>()
+        })
+        .find(|id| !used.iter().any(|block| block.id == *id))
+        .unwrap_or_default()
+}
+
+/// Mark the paragraph, heading or list item at `line` of `document`, the note at `uri`, with a new
+/// block ID, so links can point at it as `[[note#^id]]`. There's nothing to offer outside of one,
+/// or if it's marked already.
+pub fn add_block_id(uri: &Url, document: &str, line: u32) -> Option<CodeAction> {
+    let (start, end) = blocks::block_at(document, line)?;
+    let used = blocks::parse_blocks(document);
+    if used.iter().any(|block| block.start == start) {
+        return None;
+    }
+    let text = document
+        .lines()
+        .skip(start as usize)
+        .take((end - start + 1) as usize);
+    let id = new_block_id(&text.collect::<Vec<_>>().join("\n"), &used);
+    let last = document.lines().nth(end as usize)?.trim_end();
+    let at = Position::new(end, last.len() as u32);
+    Some(CodeAction {
+        title: format!("Add block ID ^{id}"),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(
+                uri.clone(),
+                vec![TextEdit::new(Range::new(at, at), format!(" ^{id}"))],
+            )])),
+            ..WorkspaceEdit::default()
+        }),
+        ..CodeAction::default()
+    })
+}
+
+/// Percent-encode the spaces in a markdown link target.
+///
+/// Markdown doesn't allow spaces in link destinations, so most renderers don't treat
+/// `[text](my note.md)` as a link at all.
+pub fn encode_spaces(uri: &Url, link: &Link) -> Option<CodeAction> {
+    let target = link.target_path();
+    if link.kind != LinkKind::Markdown
+        || link.is_external()
+        || target.starts_with('<')
+        || !target.contains(' ')
+    {
+        return None;
+    }
+
+    let encoded = links::encode_target(&link.decoded_path());
+    Some(quick_fix(
+        format!("Encode link target as `{encoded}`"),
+        uri,
+        TextEdit::new(link.range(), link.with_target(&encoded)),
+    ))
+}
+
+/// Respell a link's target to match the case of the file it points to.
+///
+/// `target` is the corrected target, from `diagnostics::case_correction`.
+pub fn fix_case(uri: &Url, link: &Link, target: &str) -> CodeAction {
+    quick_fix(
+        format!("Change link to `{target}`"),
+        uri,
+        TextEdit::new(link.range(), link.with_target(target)),
+    )
+}
+
+/// Replace `link` with `new`, the same link in the other style, from `link_style`.
+pub fn convert_link(uri: &Url, link: &Link, new: String) -> CodeAction {
+    let title = match link.kind {
+        LinkKind::Wiki => "Convert to markdown link",
+        LinkKind::Markdown => "Convert to wiki link",
+    };
+    CodeAction {
+        title: title.to_string(),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(
+                uri.clone(),
+                vec![TextEdit::new(link.range(), new)],
+            )])),
+            ..WorkspaceEdit::default()
+        }),
+        ..CodeAction::default()
+    }
+}
+
+/// Apply `edits`, from `link_style::convert_all`, converting every link in the note at `uri` to
+/// `style`. There's nothing to offer without any.
+pub fn convert_all_links(uri: &Url, style: LinkKind, edits: Vec<TextEdit>) -> Option<CodeAction> {
+    if edits.is_empty() {
+        return None;
+    }
+    let title = match style {
+        LinkKind::Markdown => "Convert all links to markdown links",
+        LinkKind::Wiki => "Convert all links to wiki links",
+    };
+    Some(CodeAction {
+        title: title.to_string(),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..WorkspaceEdit::default()
+        }),
+        ..CodeAction::default()
+    })
+}
+
+/// Add alt text to an embedded image that has none, with `noteLs.setAltText`.
+///
+/// The command is given the note and `range`, the range of the image as the editor counts it,
+/// and clients that can ask the user for the text add it as `alt`.
+pub fn add_alt_text(uri: &Url, range: Range) -> CodeAction {
+    let title = String::from("Add alt text");
+    CodeAction {
+        title: title.clone(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        command: Some(Command::new(
+            title,
+            commands::SET_ALT_TEXT.to_string(),
+            Some(vec![json!({ "uri": uri, "range": range })]),
+        )),
+        ..CodeAction::default()
+    }
+}
+
+/// Add `word`, which the spell checker doesn't know, to the dictionary of the vault of `uri` with
+/// `noteLs.addToDictionary`.
+pub fn add_to_dictionary(uri: &Url, word: &str, diagnostic: &Diagnostic) -> CodeAction {
+    let title = format!("Add \"{word}\" to dictionary");
+    CodeAction {
+        title: title.clone(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        command: Some(Command::new(
+            title,
+            commands::ADD_TO_DICTIONARY.to_string(),
+            Some(vec![json!({ "uri": uri, "word": word })]),
+        )),
+        ..CodeAction::default()
+    }
+}
+
+/// Run the code `block`, whose language has a runner, with `noteLs.runCodeBlock`.
+pub fn run_code_block(uri: &Url, block: &CodeBlock) -> CodeAction {
+    let title = format!("Run {} block", block.language);
+    CodeAction {
+        title: title.clone(),
+        command: Some(Command::new(
+            title,
+            commands::RUN_CODE_BLOCK.to_string(),
+            Some(vec![json!({ "uri": uri, "line": block.start })]),
+        )),
+        ..CodeAction::default()
+    }
+}
+
+/// Alt text to start from for the image `link` embeds: its file name, with dashes and
+/// underscores as spaces.
+pub fn default_alt_text(link: &Link) -> String {
+    let path = link.decoded_path();
+    let name = Path::new(path.as_ref())
+        .file_stem()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    name.replace(['-', '_'], " ")
+}
+
+/// The source of the image embed `link` with `alt` as its alt text. Wiki embeds keep their size
+/// after the text, e.g. `![[cat.png|A cat|300]]`.
+pub fn with_alt_text(link: &Link, alt: &str) -> String {
+    match link.kind {
+        LinkKind::Markdown => format!("![{alt}]({})", link.target),
+        LinkKind::Wiki => match link.target.split_once('|') {
+            Some((path, size)) => format!("![[{path}|{alt}|{size}]]"),
+            None => format!("![[{}|{alt}]]", link.target),
+        },
+    }
+}
+
+/// Change the heading on `line` of `document` to a heading of `level`, e.g. from `###` to `##`.
+pub fn fix_heading_level(uri: &Url, document: &str, line: u32, level: u8) -> Option<CodeAction> {
+    let text = document.lines().nth(line as usize)?;
+    let indent = text.len() - text.trim_start().len();
+    let markers = text[indent..].bytes().take_while(|&b| b == b'#').count();
+    Some(quick_fix(
+        format!("Change to a level {level} heading"),
+        uri,
+        TextEdit::new(
+            Range::new(
+                Position::new(line, indent as u32),
+                Position::new(line, (indent + markers) as u32),
+            ),
+            "#".repeat(level as usize),
+        ),
+    ))
+}
+
+/// Create the missing note `path` that a broken wiki `link` in the note at `from` points to.
+///
+/// The note is filled in from `template` if there is one, see `templates`, with the note's name
+/// as its title and `date` as the day it's created. If the note is created somewhere the link doesn't point to, e.g. in an inbox
+/// folder, the link is changed to point to it.
+pub fn create_note(
+    from: &Url,
+    link: &Link,
+    path: &Path,
+    root: &Path,
+    template: Option<&str>,
+    date: Date,
+) -> Option<CodeAction> {
+    if link.kind != LinkKind::Wiki {
+        return None;
+    }
+
+    let note_dir = uri::to_path(from)?.parent()?.to_path_buf();
+    let uri = uri::from_path(path)?;
+    let title = path.file_stem()?.to_string_lossy();
+    let name = path.file_name()?.to_string_lossy();
+
+    let mut operations = vec![DocumentChangeOperation::Op(ResourceOp::Create(
+        CreateFile {
+            uri: uri.clone(),
+            options: Some(CreateFileOptions {
+                overwrite: Some(false),
+                ignore_if_exists: Some(true),
+            }),
+            annotation_id: None,
+        },
+    ))];
+    if let Some(template) = template.filter(|template| !template.is_empty()) {
+        let mut content = templates::fill(template, &title, date);
+        // The note isn't opened, so there's no cursor to place.
+        templates::take_cursor(&mut content);
+        operations.push(DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier { uri, version: None },
+            edits: vec![OneOf::Left(TextEdit::new(
+                Range::new(Position::new(0, 0), Position::new(0, 0)),
+                content,
+            ))],
+        }));
+    }
+    if !link
+        .candidates(&note_dir, root)
+        .iter()
+        .any(|candidate| candidate == path)
+    {
+        operations.push(DocumentChangeOperation::Edit(TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri: from.clone(),
+                version: None,
+            },
+            edits: vec![OneOf::Left(TextEdit::new(
+                link.range(),
+                link.with_target(&wiki_target(&note_dir, path)),
+            ))],
+        }));
+    }
+
+    Some(CodeAction {
+        title: format!("Create note '{name}'"),
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit: Some(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Operations(operations)),
+            ..WorkspaceEdit::default()
+        }),
+        ..CodeAction::default()
+    })
+}
+
+/// Create the missing note like `create_note` does, from `template` in the templates folder.
+pub fn create_note_from(
+    from: &Url,
+    link: &Link,
+    path: &Path,
+    root: &Path,
+    template: &Template,
+    date: Date,
+) -> Option<CodeAction> {
+    let mut action = create_note(from, link, path, root, Some(&template.content), date)?;
+    let name = path.file_name()?.to_string_lossy();
+    action.title = format!("Create note '{name}' from template '{}'", template.name);
+    Some(action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spaces_in_markdown_targets_are_encoded() {
+        let uri = Url::parse("file:///vault/note.md").unwrap();
+        let parsed = links::parse_line("[a](my note.md#Some heading) [[my note]] [b](ok.md)", 0);
+
+        let actions = parsed
+            .iter()
+            .filter_map(|link| encode_spaces(&uri, link))
+            .collect::<Vec<_>>();
+        assert_eq!(actions.len(), 1);
+
+        let edits = &actions[0].edit.as_ref().unwrap().changes.as_ref().unwrap()[&uri];
+        assert_eq!(edits[0].new_text, "[a](my%20note.md#Some heading)");
+    }
+
+    #[test]
+    fn accessibility_problems_are_fixed() {
+        let uri = Url::parse("file:///vault/note.md").unwrap();
+        let parsed = links::parse_line("![](my_cat-photo.png) ![[cat.png|300]] ![[cat.png]]", 0);
+        assert_eq!(default_alt_text(&parsed[0]), "my cat photo");
+        assert_eq!(
+            with_alt_text(&parsed[0], "A cat"),
+            "![A cat](my_cat-photo.png)"
+        );
+        assert_eq!(with_alt_text(&parsed[1], "A cat"), "![[cat.png|A cat|300]]");
+        assert_eq!(with_alt_text(&parsed[2], "A cat"), "![[cat.png|A cat]]");
+        let action = add_alt_text(&uri, parsed[1].range());
+        assert_eq!(
+            action.command.unwrap().arguments.unwrap()[0]["range"]["start"]["character"],
+            22
+        );
+
+        let action = fix_heading_level(
+            &uri,
+            "# Top
+  ####  Deep",
+            1,
+            2,
+        )
+        .unwrap();
+        let edits = &action.edit.unwrap().changes.unwrap()[&uri];
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(1, 2), Position::new(1, 6))
+        );
+        assert_eq!(edits[0].new_text, "##");
+    }
+
+    #[test]
+    fn selections_are_extracted_to_new_notes() {
+        let vault = tempfile::tempdir().unwrap();
+        std::fs::write(vault.path().join("Ideas more.md"), "").unwrap();
+        let uri = uri::from_path(&vault.path().join("note.md")).unwrap();
+        let document = "# Note\n## Ideas: more\nfirst\n\nafter";
+        let range = Range::new(Position::new(1, 0), Position::new(3, 0));
+        let md = [String::from("md")];
+
+        let action = extract_selection(&uri, document, range, vault.path(), &md).unwrap();
+        assert_eq!(action.title, "Extract selection to new note 'Ideas more 2'");
+        let Some(DocumentChanges::Operations(operations)) = action.edit.unwrap().document_changes
+        else {
+            panic!("expected operations");
+        };
+        let text = |operation: &DocumentChangeOperation| match operation {
+            DocumentChangeOperation::Edit(TextDocumentEdit { edits, .. }) => match &edits[0] {
+                OneOf::Left(edit) => edit.new_text.clone(),
+                OneOf::Right(edit) => edit.text_edit.new_text.clone(),
+            },
+            _ => panic!("expected an edit"),
+        };
+        assert_eq!(text(&operations[1]), "## Ideas: more\nfirst\n");
+        assert_eq!(text(&operations[2]), "[[Ideas more 2]]\n");
+
+        let empty = Range::new(Position::new(3, 0), Position::new(3, 0));
+        assert!(extract_selection(&uri, document, empty, vault.path(), &md).is_none());
+
+        let inbox = vault.path().join("inbox");
+        let action = extract_selection(&uri, document, range, &inbox, &md).unwrap();
+        let Some(DocumentChanges::Operations(operations)) = action.edit.unwrap().document_changes
+        else {
+            panic!("expected operations");
+        };
+        assert_eq!(text(&operations[2]), "[[inbox/Ideas more]]\n");
+
+        let document = "Intro\n### Plans\nlater";
+        let range = Range::new(Position::new(0, 0), Position::new(2, 5));
+        let (title, _) = extract_edit(&uri, document, range, vault.path(), &md, None).unwrap();
+        assert_eq!(title, "Plans");
+        let markdown = [String::from("markdown"), String::from("md")];
+        let (title, edit) = extract_edit(
+            &uri,
+            document,
+            range,
+            vault.path(),
+            &markdown,
+            Some("My: plans"),
+        )
+        .unwrap();
+        assert_eq!(title, "My plans");
+        let Some(DocumentChanges::Operations(operations)) = edit.document_changes else {
+            panic!("expected operations");
+        };
+        let DocumentChangeOperation::Op(ResourceOp::Create(create)) = &operations[0] else {
+            panic!("expected the note to be created");
+        };
+        assert!(create.uri.path().ends_with("/My%20plans.markdown"));
+        assert_eq!(title_for("\n- [ ] a/b"), "a b");
+    }
+
+    #[test]
+    fn selections_are_commented_out_and_back() {
+        let uri = Url::parse("file:///vault/note.md").unwrap();
+        let edits = |action: CodeAction| {
+            action.edit.unwrap().changes.unwrap()[&uri]
+                .iter()
+                .map(|edit| (edit.range.start, edit.range.end, edit.new_text.clone()))
+                .collect::<Vec<_>>()
+        };
+        let document = "Draft\nmore\nSee %%old <!-- x -->%% <!-- kept -->\n";
+        let lines = Range::new(Position::new(0, 0), Position::new(2, 0));
+        let action = toggle_comment(&uri, document, lines).unwrap();
+        assert_eq!(action.title, "Comment out");
+        assert_eq!(
+            edits(action),
+            [
+                (Position::new(0, 0), Position::new(0, 0), String::from("%%")),
+                (Position::new(1, 4), Position::new(1, 4), String::from("%%")),
+            ]
+        );
+
+        let inside = Range::new(Position::new(2, 6), Position::new(2, 8));
+        let action = toggle_comment(&uri, document, inside).unwrap();
+        assert_eq!(action.title, "Uncomment");
+        assert_eq!(
+            edits(action),
+            [
+                (Position::new(2, 4), Position::new(2, 6), String::new()),
+                (Position::new(2, 20), Position::new(2, 22), String::new()),
+            ]
+        );
+        assert!(toggle_comment(&uri, document, Range::new(lines.end, lines.end)).is_none());
+    }
+
+    #[test]
+    fn broken_links_create_the_note_from_a_template() {
+        let from = Url::parse("file:///vault/note.md").unwrap();
+        let root = Path::new("/vault");
+        let link = &links::parse_line("[[new note#Part]]", 0)[0];
+        let date = Date::from_days(20_742);
+        let action = create_note(
+            &from,
+            link,
+            Path::new("/vault/new note.md"),
+            root,
+            Some("# {{title}}\n{{cursor}}"),
+            date,
+        )
+        .unwrap();
+        assert_eq!(action.title, "Create note 'new note.md'");
+
+        let Some(DocumentChanges::Operations(operations)) = action.edit.unwrap().document_changes
+        else {
+            panic!("expected operations");
+        };
+        assert!(matches!(
+            &operations[0],
+            DocumentChangeOperation::Op(ResourceOp::Create(create))
+                if create.uri.path() == "/vault/new%20note.md"
+        ));
+        let DocumentChangeOperation::Edit(edit) = &operations[1] else {
+            panic!("expected an edit");
+        };
+        let OneOf::Left(edit) = &edit.edits[0] else {
+            panic!("expected a text edit");
+        };
+        assert_eq!(edit.new_text, "# new note\n");
+        assert_eq!(operations.len(), 2);
+
+        // Notes created elsewhere get the link pointed at them.
+        let action = create_note(
+            &from,
+            link,
+            Path::new("/vault/inbox/new note.md"),
+            root,
+            None,
+            date,
+        );
+        let Some(DocumentChanges::Operations(operations)) =
+            action.unwrap().edit.unwrap().document_changes
+        else {
+            panic!("expected operations");
+        };
+        let DocumentChangeOperation::Edit(edit) = &operations[1] else {
+            panic!("expected an edit");
+        };
+        assert_eq!(edit.text_document.uri, from);
+        let OneOf::Left(edit) = &edit.edits[0] else {
+            panic!("expected a text edit");
+        };
+        assert_eq!(edit.new_text, "[[inbox/new note#Part]]");
+
+        let template = Template {
+            name: String::from("meeting"),
+            content: String::from("Met on {{date}}"),
+        };
+        let action = create_note_from(
+            &from,
+            link,
+            Path::new("/vault/new note.md"),
+            root,
+            &template,
+            date,
+        )
+        .unwrap();
+        assert_eq!(
+            action.title,
+            "Create note 'new note.md' from template 'meeting'"
+        );
+        let Some(DocumentChanges::Operations(operations)) = action.edit.unwrap().document_changes
+        else {
+            panic!("expected operations");
+        };
+        let DocumentChangeOperation::Edit(edit) = &operations[1] else {
+            panic!("expected an edit");
+        };
+        let OneOf::Left(edit) = &edit.edits[0] else {
+            panic!("expected a text edit");
+        };
+        assert_eq!(edit.new_text, "Met on 2026-10-16");
+    }
+
+    #[test]
+    fn blocks_get_a_new_id() {
+        let uri = Url::parse("file:///vault/note.md").unwrap();
+        let document = "# Note\nFirst\nsecond  \n\nMarked ^done\n";
+
+        let action = add_block_id(&uri, document, 1).unwrap();
+        let edits = &action.edit.unwrap().changes.unwrap()[&uri];
+        let id = edits[0].new_text.trim_start().trim_start_matches('^');
+        assert_eq!(action.title, format!("Add block ID ^{id}"));
+        assert_eq!(id.len(), 6);
+        assert!(id.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_eq!(edits[0].range.start, Position::new(2, 6));
+
+        assert!(add_block_id(&uri, document, 3).is_none());
+        assert!(add_block_id(&uri, document, 4).is_none());
+    }
+}
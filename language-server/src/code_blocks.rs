@@ -0,0 +1,265 @@
+//! Running fenced code blocks, for `noteLs.runCodeBlock`.
+//!
+//! Only blocks of the languages `Config::code_runners` has a program for run. The program gets
+//! the block's code on stdin, runs in the note's folder with nothing in its environment but the
+//! path, home and language, and is killed once `Config::code_run_timeout_ms` has passed. What it
+//! prints goes in an `output` block right below the code, replacing the one a previous run left.
+
+use std::{env, io, path::Path, process::Stdio, time::Duration};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    process::Command,
+};
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+use crate::{
+    config::CodeRunner,
+    fences::{closes, fence},
+};
+
+/// The language of the blocks holding what code printed.
+pub const OUTPUT_LANGUAGE: &str = "output";
+
+/// Most bytes kept of what a block prints on stdout, and again on stderr.
+const MAX_OUTPUT: usize = 64 * 1024;
+
+/// Variables passed on from the server's environment.
+const KEPT_VARIABLES: [&str; 3] = ["PATH", "HOME", "LANG"];
+
+/// A fenced code block in a note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeBlock {
+    /// The first word after the opening fence, if any.
+    pub language: String,
+    pub code: String,
+    /// The lines of the opening and closing fences.
+    pub start: u32,
+    pub end: u32,
+}
+
+/// The fenced code blocks in `document`. Blocks that are never closed are left out.
+pub fn blocks(document: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut lines = document.lines().zip(0u32..);
+    while let Some((line, start)) = lines.next() {
+        let Some((opening, info)) = fence(line) else {
+            continue;
+        };
+        let mut code = String::new();
+        for (line, n) in lines.by_ref() {
+            if closes(line, opening) {
+                blocks.push(CodeBlock {
+                    language: info.split_whitespace().next().unwrap_or("").to_string(),
+                    code,
+                    start,
+                    end: n,
+                });
+                break;
+            }
+            code.push_str(line);
+            code.push('\n');
+        }
+    }
+    blocks
+}
+
+/// The block of code `line` is in, if it isn't the output of another.
+pub fn block_at(document: &str, line: u32) -> Option<CodeBlock> {
+    blocks(document)
+        .into_iter()
+        .find(|block| (block.start..=block.end).contains(&line))
+        .filter(|block| block.language != OUTPUT_LANGUAGE)
+}
+
+/// The output block of `output`, fenced with more backticks than it has in a row.
+fn output_block(output: &str) -> String {
+    let longest = output.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    let newline = match output.ends_with('\n') || output.is_empty() {
+        true => "",
+        false => "\n",
+    };
+    format!("{fence}{OUTPUT_LANGUAGE}\n{output}{newline}{fence}\n")
+}
+
+/// An edit putting `output` in an output block right below `block`, replacing the one there.
+pub fn output_edit(document: &str, block: &CodeBlock, output: &str) -> TextEdit {
+    let below = Position::new(block.end + 1, 0);
+    let previous = blocks(document)
+        .into_iter()
+        .find(|next| next.start == block.end + 1 && next.language == OUTPUT_LANGUAGE);
+    let (range, new_text) = match previous {
+        Some(previous) => (
+            Range::new(below, Position::new(previous.end + 1, 0)),
+            output_block(output),
+        ),
+        // The closing fence may be the last line, without a newline.
+        None if document.lines().count() as u32 <= block.end + 1 && !document.ends_with('\n') => {
+            let fence = document.lines().last().unwrap_or("");
+            let end = Position::new(block.end, fence.len() as u32);
+            (Range::new(end, end), format!("\n{}", output_block(output)))
+        }
+        None => (Range::new(below, below), output_block(output)),
+    };
+    TextEdit::new(range, new_text)
+}
+
+/// Read from `pipe` into `read` until it's closed or `read` holds more than `MAX_OUTPUT` bytes,
+/// which tells there was more. The pipe is closed after, which stops most programs that keep
+/// writing. What's read is kept in `read` if this is cancelled.
+async fn read_capped(pipe: Option<impl AsyncRead + Unpin>, read: &mut Vec<u8>) -> io::Result<()> {
+    let Some(pipe) = pipe else {
+        return Ok(());
+    };
+    let mut pipe = pipe.take(MAX_OUTPUT as u64 + 1);
+    let mut chunk = [0; 4096];
+    loop {
+        match pipe.read(&mut chunk).await? {
+            0 => return Ok(()),
+            n => read.extend_from_slice(&chunk[..n]),
+        }
+    }
+}
+
+/// Run `code` with `runner` in `dir`, giving up after `timeout`, and return what it printed: its
+/// stdout, then its stderr, then a note of how it ended if it didn't end well. If it's stopped,
+/// what it printed until then is kept.
+pub async fn run(
+    runner: &CodeRunner,
+    code: &str,
+    dir: &Path,
+    timeout: Duration,
+) -> io::Result<String> {
+    let mut command = Command::new(&runner.command);
+    command
+        .args(&runner.args)
+        .current_dir(dir)
+        .env_clear()
+        .envs(
+            KEPT_VARIABLES
+                .iter()
+                .filter_map(|name| Some((name, env::var_os(name)?))),
+        )
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let mut child = command.spawn()?;
+    let mut stdin = child.stdin.take().ok_or(io::ErrorKind::BrokenPipe)?;
+    let (stdout_pipe, stderr_pipe) = (child.stdout.take(), child.stderr.take());
+
+    let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+    let finished = tokio::time::timeout(timeout, async {
+        let write = async {
+            // Programs that don't read their input close stdin early; that's not an error.
+            let _ = stdin.write_all(code.as_bytes()).await;
+            drop(stdin);
+        };
+        let ((), read_stdout, read_stderr) = tokio::join!(
+            write,
+            read_capped(stdout_pipe, &mut stdout),
+            read_capped(stderr_pipe, &mut stderr)
+        );
+        read_stdout?;
+        read_stderr?;
+        child.wait().await
+    })
+    .await;
+
+    let mut output = String::new();
+    for printed in [stdout, stderr] {
+        output.push_str(&String::from_utf8_lossy(
+            &printed[..printed.len().min(MAX_OUTPUT)],
+        ));
+        if !output.is_empty() && !output.ends_with('\n') {
+            output.push('\n');
+        }
+        if printed.len() > MAX_OUTPUT {
+            output.push_str(&format!("[output cut after {MAX_OUTPUT} bytes]\n"));
+        }
+    }
+    match finished {
+        Err(_) => output.push_str(&format!("[stopped after {} ms]\n", timeout.as_millis())),
+        Ok(status) => {
+            let status = status?;
+            if !status.success() {
+                match status.code() {
+                    Some(code) => output.push_str(&format!("[exited with status {code}]\n")),
+                    None => output.push_str("[killed]\n"),
+                }
+            }
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_goes_below_the_block() {
+        let document = "# Sums\n```python\nprint(1 + 1)\n```\nText\n~~~~ sh\nls\n~~~~";
+        let block = block_at(document, 2).unwrap();
+        assert_eq!(block.language, "python");
+        assert_eq!(block.code, "print(1 + 1)\n");
+        assert_eq!((block.start, block.end), (1, 3));
+        assert_eq!(block_at(document, 4), None);
+
+        let edit = output_edit(document, &block, "2\n");
+        assert_eq!(
+            edit.range,
+            Range::new(Position::new(4, 0), Position::new(4, 0))
+        );
+        assert_eq!(edit.new_text, "```output\n2\n```\n");
+
+        // Running it again replaces the output, fenced so backticks in it don't close it.
+        let document = "```python\nprint(2)\n```\n```output\n1\n```\nText\n";
+        let block = block_at(document, 0).unwrap();
+        let edit = output_edit(document, &block, "``` 2");
+        assert_eq!(
+            edit.range,
+            Range::new(Position::new(3, 0), Position::new(6, 0))
+        );
+        assert_eq!(edit.new_text, "````output\n``` 2\n````\n");
+        assert_eq!(block_at(document, 4), None);
+
+        let last = block_at("~~~~ sh\nls\n~~~~", 1).unwrap();
+        assert_eq!(last.language, "sh");
+        let edit = output_edit("~~~~ sh\nls\n~~~~", &last, "");
+        assert_eq!(edit.range.start, Position::new(2, 4));
+        assert_eq!(edit.new_text, "\n```output\n```\n");
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn code_runs_with_a_clean_environment_and_a_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let sh = CodeRunner {
+            language: String::from("sh"),
+            command: String::from("sh"),
+            args: Vec::new(),
+        };
+        let timeout = Duration::from_secs(5);
+        // Cargo runs tests with the package's name in the environment.
+        let code = "pwd; echo \"[$CARGO_PKG_NAME]\"; echo oops >&2; exit 3";
+        let output = run(&sh, code, dir.path(), timeout).await.unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        assert_eq!(
+            output,
+            format!("{}\n[]\noops\n[exited with status 3]\n", dir.display())
+        );
+
+        // What it printed before it was stopped is kept.
+        let output = run(
+            &sh,
+            "echo started; sleep 5",
+            &dir,
+            Duration::from_millis(500),
+        )
+        .await
+        .unwrap();
+        assert_eq!(output, "started\n[stopped after 500 ms]\n");
+    }
+}
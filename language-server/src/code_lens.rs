@@ -0,0 +1,125 @@
+//! Backlink counts shown as code lenses for `textDocument/codeLens`.
+//!
+//! Clicking a lens shows the backlinks with `editor.action.showReferences`, which VS Code and
+//! several other editors implement, passing the same locations a references request at the
+//! lens would return.
+
+use std::path::Path;
+
+use serde_json::json;
+use tower_lsp::lsp_types::{CodeLens, Command, Location, Position, Range, Url};
+
+use crate::{
+    i18n::Messages,
+    index::{Note, NoteIndex},
+    uri,
+};
+
+/// The client-side command that lists locations in a peek view.
+pub const SHOW_REFERENCES: &str = "editor.action.showReferences";
+
+fn lens(uri: &Url, line: u32, locations: Vec<Location>, messages: &Messages) -> CodeLens {
+    let position = Position::new(line, 0);
+    CodeLens {
+        range: Range::new(position, position),
+        command: Some(Command {
+            title: messages.count("backlinks", locations.len()),
+            command: SHOW_REFERENCES.to_string(),
+            arguments: Some(vec![json!(uri), json!(position), json!(locations)]),
+        }),
+        data: None,
+    }
+}
+
+/// A lens with the number of backlinks above the title of `note`, the note at `path`, and with
+/// `headings`, above every heading that is linked to. The backlinks' locations are handed to the
+/// editor through `to_client`, to count their characters the way it does.
+pub fn backlink_lenses(
+    path: &Path,
+    note: &Note,
+    index: &NoteIndex,
+    headings: bool,
+    messages: &Messages,
+    to_client: impl Fn(Location) -> Location,
+) -> Vec<CodeLens> {
+    let Some(uri) = uri::from_path(path) else {
+        return Vec::new();
+    };
+    let backlinks = index.backlinks(path);
+    let location =
+        |source: &Path, range| Some(to_client(Location::new(uri::from_path(source)?, range)));
+
+    let title = note.headings.iter().find(|heading| heading.level == 1);
+    let title_line = match (&note.frontmatter.title, title) {
+        (None, Some(title)) => title.line,
+        _ => 0,
+    };
+    let all = backlinks
+        .iter()
+        .filter_map(|(source, link)| location(source, link.range()))
+        .collect();
+    let mut lenses = vec![lens(&uri, title_line, all, messages)];
+
+    if headings {
+        for heading in &note.headings {
+            if title.is_some_and(|title| title.line == heading.line) {
+                continue;
+            }
+            let locations = backlinks
+                .iter()
+                .filter(|(_, link)| {
+                    link.anchor()
+                        .and_then(|anchor| note.find_heading(anchor))
+                        .is_some_and(|linked| linked.line == heading.line)
+                })
+                .filter_map(|(source, link)| location(source, link.range()))
+                .collect::<Vec<_>>();
+            if !locations.is_empty() {
+                lenses.push(lens(&uri, heading.line, locations, messages));
+            }
+        }
+    }
+    lenses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backlinks_are_counted_per_heading() {
+        let mut index = NoteIndex::from_paths(Path::new("/vault"), Vec::new());
+        let content = "intro\n# Title\n## First\n## Second";
+        index.update("/vault/note.md".into(), content);
+        index.update("/vault/a.md".into(), "[[note]] [[note#First]]");
+        index.update("/vault/b.md".into(), "[[note#first]]");
+        let note = Note::parse(content);
+        let path = Path::new("/vault/note.md");
+
+        let titles = |lenses: Vec<CodeLens>| {
+            lenses
+                .into_iter()
+                .map(|lens| (lens.range.start.line, lens.command.unwrap().title))
+                .collect::<Vec<_>>()
+        };
+        let messages = Messages::default();
+        assert_eq!(
+            titles(backlink_lenses(
+                path,
+                &note,
+                &index,
+                false,
+                &messages,
+                |l| l
+            )),
+            vec![(1, String::from("3 backlinks"))]
+        );
+        assert_eq!(
+            titles(backlink_lenses(path, &note, &index, true, &messages, |l| l)),
+            vec![
+                (1, String::from("3 backlinks")),
+                (2, String::from("2 backlinks"))
+            ]
+        );
+    }
+}
@@ -0,0 +1,383 @@
+//! Commands exposed through `workspace/executeCommand`.
+
+use std::path::PathBuf;
+
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
+use tower_lsp::{
+    jsonrpc::{Error, Result},
+    lsp_types::{Position, Range, Url},
+};
+
+use crate::graph;
+
+pub const ADD_TO_DICTIONARY: &str = "noteLs.addToDictionary";
+pub const BACKUP_VAULT: &str = "noteLs.backupVault";
+pub const BUNDLE_NOTE: &str = "noteLs.bundleNote";
+pub const COMPARE_VAULTS: &str = "noteLs.compareVaults";
+pub const DAILY_NOTE_OPEN: &str = "noteLs.dailyNote.open";
+pub const DAILY_NOTE_PREVIOUS: &str = "noteLs.dailyNote.previous";
+pub const DAILY_NOTE_NEXT: &str = "noteLs.dailyNote.next";
+pub const EXPORT_HTML: &str = "noteLs.export.html";
+pub const EXPORT_PDF: &str = "noteLs.export.pdf";
+pub const EXTRACT_TO_NOTE: &str = "noteLs.extractToNote";
+pub const FIND_UNUSED_ATTACHMENTS: &str = "noteLs.findUnusedAttachments";
+pub const FLATTEN_NOTE: &str = "noteLs.flattenNote";
+pub const GRAPH_EXPORT: &str = "noteLs.graph.export";
+pub const TOGGLE_HABIT: &str = "noteLs.toggleHabit";
+pub const INIT_VAULT: &str = "noteLs.initVault";
+pub const INSERT_EXCERPT: &str = "noteLs.insertExcerpt";
+pub const LINK_REPORT: &str = "noteLs.linkReport";
+pub const LINKS_CHECK: &str = "noteLs.links.check";
+pub const NEW_NOTE: &str = "noteLs.newNote";
+pub const PREVIEW_OPEN: &str = "noteLs.preview.open";
+pub const PREVIEW_CLOSE: &str = "noteLs.preview.close";
+pub const PREVIEW_TOGGLE: &str = "noteLs.preview.toggle";
+pub const PUBLISH: &str = "noteLs.publish";
+pub const RECENT_NOTES: &str = "noteLs.recentNotes";
+pub const REPORT_ORPHANS: &str = "noteLs.report.orphans";
+pub const RUN_CODE_BLOCK: &str = "noteLs.runCodeBlock";
+pub const SEARCH: &str = "noteLs.search";
+pub const SET_ALT_TEXT: &str = "noteLs.setAltText";
+pub const SET_PROFILE: &str = "noteLs.setProfile";
+pub const TOGGLE_TASK: &str = "noteLs.task.toggle";
+pub const TASKS_LIST: &str = "noteLs.tasks.list";
+pub const TOC_UPDATE: &str = "noteLs.toc.update";
+pub const UPDATE_READING_PROGRESS: &str = "noteLs.updateReadingProgress";
+
+/// All commands the server supports, advertised in the server capabilities.
+pub fn all() -> Vec<String> {
+    vec![
+        ADD_TO_DICTIONARY.to_string(),
+        BACKUP_VAULT.to_string(),
+        BUNDLE_NOTE.to_string(),
+        COMPARE_VAULTS.to_string(),
+        DAILY_NOTE_OPEN.to_string(),
+        DAILY_NOTE_PREVIOUS.to_string(),
+        DAILY_NOTE_NEXT.to_string(),
+        EXPORT_HTML.to_string(),
+        EXPORT_PDF.to_string(),
+        EXTRACT_TO_NOTE.to_string(),
+        FIND_UNUSED_ATTACHMENTS.to_string(),
+        FLATTEN_NOTE.to_string(),
+        GRAPH_EXPORT.to_string(),
+        TOGGLE_HABIT.to_string(),
+        INIT_VAULT.to_string(),
+        INSERT_EXCERPT.to_string(),
+        LINK_REPORT.to_string(),
+        LINKS_CHECK.to_string(),
+        NEW_NOTE.to_string(),
+        PREVIEW_OPEN.to_string(),
+        PREVIEW_CLOSE.to_string(),
+        PREVIEW_TOGGLE.to_string(),
+        PUBLISH.to_string(),
+        RECENT_NOTES.to_string(),
+        REPORT_ORPHANS.to_string(),
+        RUN_CODE_BLOCK.to_string(),
+        SEARCH.to_string(),
+        SET_ALT_TEXT.to_string(),
+        SET_PROFILE.to_string(),
+        TOGGLE_TASK.to_string(),
+        TASKS_LIST.to_string(),
+        TOC_UPDATE.to_string(),
+        UPDATE_READING_PROGRESS.to_string(),
+    ]
+}
+
+/// Deserialize the first command argument, falling back to the default if none was given.
+pub fn parse_args<T: DeserializeOwned + Default>(arguments: Vec<Value>) -> Result<T> {
+    match arguments.into_iter().next() {
+        Some(arg) => serde_json::from_value(arg).map_err(|e| Error::invalid_params(e.to_string())),
+        None => Ok(T::default()),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AddToDictionaryArgs {
+    /// A note in the vault whose dictionary to add to. Defaults to the note last edited, or else
+    /// the vault opened first.
+    pub uri: Option<Url>,
+    pub word: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BundleNoteArgs {
+    /// The note to bundle. Defaults to the note last edited.
+    pub uri: Option<Url>,
+    /// How many links away from the note linked notes are still bundled. Defaults to 1, the
+    /// notes it links to directly.
+    pub depth: Option<usize>,
+    /// Where to write the zip, relative to the vault root. Defaults to a file named after the
+    /// note in the temporary directory.
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DailyNoteArgs {
+    /// A note in the vault whose daily notes to use, which for `noteLs.dailyNote.previous` and
+    /// `noteLs.dailyNote.next` is also the daily note to move on from. Defaults to the note last
+    /// edited. Starting from a note that isn't a daily note moves on from today.
+    pub uri: Option<Url>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FindUnusedAttachmentsArgs {
+    /// Move the unused attachments into the vault's trash directory.
+    pub move_to_trash: bool,
+    /// A note in the vault to look in. Defaults to the note last edited, or else the vault
+    /// opened first.
+    pub uri: Option<Url>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FlattenNoteArgs {
+    /// The note to flatten. Defaults to the note last edited.
+    pub uri: Option<Url>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct InitVaultArgs {
+    /// A note or folder in the vault to set up. Defaults to the note last edited, or else the
+    /// vault opened first.
+    pub uri: Option<Url>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct InsertExcerptArgs {
+    /// The note to quote.
+    pub target_note: Option<Url>,
+    /// The heading whose section to quote. Without one, the whole note is quoted.
+    pub heading: Option<String>,
+    /// The note to insert the excerpt into. Defaults to the note last edited.
+    pub uri: Option<Url>,
+    /// Where to insert the excerpt. Defaults to the end of the note.
+    pub position: Option<Position>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LinkReportArgs {
+    /// A note in the vault to report on. Defaults to the note last edited, or else the vault
+    /// opened first.
+    pub uri: Option<Url>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LinksCheckArgs {
+    /// The note whose external links to check again. Defaults to the note last edited.
+    pub uri: Option<Url>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RunCodeBlockArgs {
+    /// The note with the code block. Defaults to the note last edited.
+    pub uri: Option<Url>,
+    /// A line of the code block, including its fences.
+    pub line: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SetAltTextArgs {
+    /// The note embedding the image.
+    pub uri: Option<Url>,
+    /// The range of the image's embed, e.g. `![](cat.png)`.
+    pub range: Option<Range>,
+    /// The alt text, which clients ask the user for. Without it, the image's file name is used
+    /// as a start.
+    pub alt: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TocUpdateArgs {
+    /// The note whose table of contents to update.
+    pub uri: Option<Url>,
+    /// The line to insert a table of contents before if the note has none. Defaults to the line
+    /// after the note's title.
+    pub line: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExtractToNoteArgs {
+    /// The note the selection is in.
+    pub uri: Option<Url>,
+    /// The selection to move to the new note.
+    pub range: Option<Range>,
+    /// The name of the new note, which clients ask the user for. Without it, the note is named
+    /// after the selection.
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BackupVaultArgs {
+    /// A note in the vault to back up. Defaults to the note last edited, or else the vault
+    /// opened first.
+    pub uri: Option<Url>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CompareVaultsArgs {
+    /// The directory of one copy of the vault.
+    pub path_a: Option<PathBuf>,
+    /// The directory of the other copy, compared against the first.
+    pub path_b: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GraphExportArgs {
+    /// A note in the vault to export the graph of. Defaults to the note last edited, or else the
+    /// vault opened first.
+    pub uri: Option<Url>,
+    /// `"dot"` or `"json"`.
+    pub format: graph::Format,
+    /// Where to write the graph, relative to the vault root unless absolute. Defaults to a file
+    /// in the temporary directory named after the vault.
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchArgs {
+    /// Words to look for. Notes have to contain all of them, in any case.
+    pub query: String,
+    /// A note in the vault to search. Defaults to the note last edited, or else the vault opened
+    /// first.
+    pub uri: Option<Url>,
+    /// The most matches to return. Defaults to 100.
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RecentNotesArgs {
+    /// A note in the vault to list the notes of. Defaults to the note last edited, or else the
+    /// vault opened first.
+    pub uri: Option<Url>,
+    /// How many days back to look. Defaults to 7.
+    pub days: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ReportOrphansArgs {
+    /// A note in the vault to report on. Defaults to the note last edited, or else the vault
+    /// opened first.
+    pub uri: Option<Url>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NewNoteArgs {
+    /// The name of the note. Defaults to `Untitled`.
+    pub title: Option<String>,
+    /// The name of one of the configured note types. Without one, the user is asked to pick a
+    /// type if any are configured.
+    #[serde(rename = "type")]
+    pub note_type: Option<String>,
+    /// The name of a template in the templates folder, without its extension. Without one, the
+    /// user is asked to pick a template if the folder has any and the note type has none.
+    pub template: Option<String>,
+    /// The note the new one is created from, which decides the vault and, for some
+    /// `newNoteLocation` settings, the folder. Defaults to the note last edited.
+    pub uri: Option<Url>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PreviewArgs {
+    /// The note whose own preview to open or close. Without one, the commands act on the
+    /// preview that follows the note being edited.
+    pub uri: Option<Url>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExportArgs {
+    /// The note to export. Defaults to the note last edited.
+    pub uri: Option<Url>,
+    /// The file to write, relative to the vault root unless absolute. Defaults to the note's path
+    /// with an `.html` or `.pdf` extension.
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PublishArgs {
+    /// A note in the vault to publish, which the `published` hook is run on. Defaults to the note
+    /// last edited, or else the vault opened first.
+    pub uri: Option<Url>,
+    /// The folder to write the site to, relative to the vault root unless absolute. Defaults to
+    /// a folder next to the vault, named after it with `-site` added.
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ToggleTaskArgs {
+    /// The note with the task. Defaults to the note last edited.
+    pub uri: Option<Url>,
+    /// A position on the task's line.
+    pub position: Option<Position>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SetProfileArgs {
+    /// The profile to switch to. Without one, the profile the settings choose is used again.
+    pub profile: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TasksListArgs {
+    /// A note in the vault to list the tasks of. Defaults to the note last edited, or else the
+    /// vault opened first.
+    pub uri: Option<Url>,
+    /// Only list tasks with this tag, or a tag nested under it, or in notes tagged with it.
+    pub tag: Option<String>,
+    /// Only list tasks due on or before this day, as `YYYY-MM-DD` or `today`.
+    pub due_before: Option<String>,
+    /// Only list tasks due on or after this day, as `YYYY-MM-DD` or `today`.
+    pub due_after: Option<String>,
+    /// The most tasks to return. Defaults to 500.
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UpdateReadingProgressArgs {
+    /// The book note to update. Defaults to the note last edited.
+    pub uri: Option<Url>,
+    /// The page read up to.
+    pub page: Option<u32>,
+    /// How much of the book has been read, used if no page is given.
+    pub percent: Option<u32>,
+    /// Mark the book as finished, whatever the page.
+    pub finished: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ToggleHabitArgs {
+    /// The note with the habit tracker. Defaults to the note last edited.
+    pub uri: Option<Url>,
+    /// The habit to check or uncheck for today. Defaults to the one whose column `position` is
+    /// in.
+    pub habit: Option<String>,
+    /// The cursor, which picks the tracker it's in, or else the first one in the note.
+    pub position: Option<Position>,
+}
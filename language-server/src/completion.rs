@@ -0,0 +1,1073 @@
+use std::{
+    cmp::Reverse,
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde_json::json;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, Documentation, InsertTextFormat,
+    MarkupContent, MarkupKind, Position, Range, TextEdit,
+};
+
+use crate::{
+    code_actions, code_blocks,
+    config::{LinkStyle, NoteType},
+    contents::CacheStats,
+    fences, frontmatter, headings,
+    index::{Note, NoteIndex},
+    links::{self, Link, LinkKind},
+};
+
+/// Most characters of a note's first paragraph shown in the documentation of its completion.
+const SUMMARY_CHARS: usize = 300;
+
+/// The languages the highlight.js bundled with the preview highlights, with their aliases. Any
+/// of them can name the language of a code block.
+const HIGHLIGHTED_LANGUAGES: [(&str, &str, &[&str]); 34] = [
+    ("bash", "Bash", &["sh"]),
+    ("c", "C", &["h"]),
+    (
+        "cpp",
+        "C++",
+        &["cc", "c++", "h++", "hpp", "hh", "hxx", "cxx"],
+    ),
+    ("csharp", "C#", &["cs", "c#"]),
+    ("css", "CSS", &[]),
+    ("diff", "Diff", &["patch"]),
+    ("go", "Go", &["golang"]),
+    ("ini", "INI and TOML", &["toml"]),
+    ("java", "Java", &["jsp"]),
+    ("javascript", "JavaScript", &["js", "jsx", "mjs", "cjs"]),
+    ("json", "JSON", &[]),
+    ("kotlin", "Kotlin", &["kt", "kts"]),
+    ("less", "Less", &[]),
+    ("lua", "Lua", &[]),
+    ("makefile", "Makefile", &["mk", "mak", "make"]),
+    ("markdown", "Markdown", &["md", "mkdown", "mkd"]),
+    (
+        "objectivec",
+        "Objective-C",
+        &["mm", "objc", "obj-c", "obj-c++", "objective-c++"],
+    ),
+    ("perl", "Perl", &["pl", "pm"]),
+    ("php", "PHP", &[]),
+    ("php-template", "PHP template", &[]),
+    ("plaintext", "Plain text", &["text", "txt"]),
+    ("python", "Python", &["py", "gyp", "ipython"]),
+    ("python-repl", "Python session", &["pycon"]),
+    ("r", "R", &[]),
+    ("ruby", "Ruby", &["rb", "gemspec", "podspec", "thor", "irb"]),
+    ("rust", "Rust", &["rs"]),
+    ("scss", "SCSS", &[]),
+    ("shell", "Shell session", &["console", "shellsession"]),
+    ("sql", "SQL", &[]),
+    ("swift", "Swift", &[]),
+    ("typescript", "TypeScript", &["ts", "tsx"]),
+    ("vbnet", "Visual Basic .NET", &["vb"]),
+    (
+        "xml",
+        "HTML and XML",
+        &[
+            "html", "xhtml", "rss", "atom", "xjb", "xsd", "xsl", "plist", "wsf", "svg",
+        ],
+    ),
+    ("yaml", "YAML", &["yml"]),
+];
+
+/// Languages of blocks the preview draws instead of showing their code.
+const DRAWN_LANGUAGES: [(&str, &str); 2] = [
+    ("mermaid", "Mermaid diagram"),
+    ("chart", "Chart of the data in the block"),
+];
+
+/// When the file at `path` was last modified, or the epoch if that's unknown.
+fn modified(path: &Path) -> SystemTime {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// Completions for a wiki link in the note at `path`.
+///
+/// With `LinkStyle::Relative` these are the other notes in its folder or below, named relative
+/// to the folder; with `LinkStyle::Root` every other note, named relative to the vault root.
+///
+/// Notes are ordered by how likely they are to be linked: the closest ones first, then the most
+/// linked to, then the most recently modified.
+pub fn note_completions(index: &NoteIndex, path: &Path, style: LinkStyle) -> Vec<CompletionItem> {
+    let base = match style {
+        LinkStyle::Relative => path.parent(),
+        LinkStyle::Root => Some(index.root()),
+    };
+    let Some(base) = base else {
+        return Vec::new();
+    };
+
+    let link_counts = index.link_counts();
+    let mut notes = index
+        .notes()
+        .filter(|(note, _)| *note != path && !index.is_ignored(note))
+        .filter_map(|(note, parsed)| Some((note.strip_prefix(base).ok()?, note, parsed.title())))
+        .map(|(relative, note, title)| {
+            let rank = (
+                relative.components().count(),
+                Reverse(link_counts.get(note).copied().unwrap_or(0)),
+                Reverse(modified(note)),
+            );
+            (rank, relative, note, title)
+        })
+        .collect::<Vec<_>>();
+    notes.sort_by(|(a, a_path, _, _), (b, b_path, _, _)| a.cmp(b).then_with(|| a_path.cmp(b_path)));
+
+    let mut items = notes
+        .into_iter()
+        .map(|(_, relative, note, title)| CompletionItem {
+            // Insert names in NFC, whatever form the file system stores them in.
+            label: links::nfc_path(relative).to_string_lossy().into(),
+            kind: Some(CompletionItemKind::FILE),
+            detail: title.map(str::to_string),
+            data: Some(json!({ "path": note })),
+            ..CompletionItem::default()
+        })
+        .collect::<Vec<_>>();
+
+    // Notes can also be linked by their aliases, from anywhere in the vault.
+    let mut aliases = index
+        .notes()
+        .filter(|(note, _)| *note != path && !index.is_ignored(note))
+        .flat_map(|(note, parsed)| {
+            parsed.frontmatter.aliases.iter().map(move |alias| {
+                let relative = note.strip_prefix(index.root()).unwrap_or(note);
+                CompletionItem {
+                    label: alias.clone(),
+                    kind: Some(CompletionItemKind::REFERENCE),
+                    detail: Some(links::path_to_target(relative)),
+                    data: Some(json!({ "path": note })),
+                    ..CompletionItem::default()
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    aliases.sort_by(|a, b| a.label.cmp(&b.label));
+    items.extend(aliases);
+    items
+}
+
+/// The note a note completion links to, for resolving its documentation.
+pub fn completed_note(item: &CompletionItem) -> Option<PathBuf> {
+    let path = item.data.as_ref()?.get("path")?.as_str()?;
+    Some(PathBuf::from(path))
+}
+
+/// The first paragraph of the body of `content`, leaving out the frontmatter and headings,
+/// shortened to `SUMMARY_CHARS`.
+fn first_paragraph(content: &str) -> String {
+    let paragraph = content
+        .lines()
+        .skip(frontmatter::body_start(content) as usize)
+        .skip_while(|line| line.trim().is_empty() || line.starts_with('#'))
+        .take_while(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    match paragraph.char_indices().nth(SUMMARY_CHARS) {
+        Some((end, _)) => format!("{}…", &paragraph[..end]),
+        None => paragraph,
+    }
+}
+
+/// Documentation for a completion of the note at `path`, whose content is `content`: its title,
+/// a summary of its frontmatter, and its first paragraph.
+pub fn note_documentation(path: &Path, content: &str, markdown: bool) -> Documentation {
+    let note = Note::parse(content);
+    let title = note
+        .title()
+        .map(str::to_string)
+        .or_else(|| Some(path.file_stem()?.to_string_lossy().into_owned()))
+        .unwrap_or_default();
+
+    let mut summary = Vec::new();
+    if !note.frontmatter.aliases.is_empty() {
+        summary.push(format!("Aliases: {}", note.frontmatter.aliases.join(", ")));
+    }
+    if !note.frontmatter.tags.is_empty() {
+        let tags = note
+            .frontmatter
+            .tags
+            .iter()
+            .map(|tag| format!("#{}", tag.name));
+        summary.push(format!("Tags: {}", tags.collect::<Vec<_>>().join(" ")));
+    }
+    if let Some(date) = &note.frontmatter.date {
+        summary.push(format!("Date: {date}"));
+    }
+    let paragraph = first_paragraph(content);
+
+    let mut value = if markdown {
+        format!("**{title}**")
+    } else {
+        title
+    };
+    if !summary.is_empty() {
+        value.push_str("\n\n");
+        value.push_str(&summary.join(if markdown { "  \n" } else { "\n" }));
+    }
+    if !paragraph.is_empty() {
+        value.push_str(if markdown { "\n\n---\n\n" } else { "\n\n" });
+        value.push_str(&paragraph);
+    }
+
+    Documentation::MarkupContent(MarkupContent {
+        kind: if markdown {
+            MarkupKind::Markdown
+        } else {
+            MarkupKind::PlainText
+        },
+        value,
+    })
+}
+
+/// Most note completions returned at once. The list is marked incomplete, so the editor asks
+/// again as the user types and the best matches for the longer query come back.
+const MAX_NOTE_COMPLETIONS: usize = 100;
+
+/// How well `text` matches `query`, if it contains the characters of `query` in order, ignoring
+/// case. Higher is better.
+///
+/// Characters matched right after each other or at the start of a word count more, so `dn`
+/// ranks "daily/notes" above "diamond".
+fn fuzzy_score(text: &str, query: &str) -> Option<i64> {
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    let mut chars = text.chars().enumerate();
+    let mut before = None;
+    for q in query.chars().flat_map(char::to_lowercase) {
+        loop {
+            let (i, c) = chars.next()?;
+            let word_start = match before {
+                None => true,
+                Some(b) => {
+                    matches!(b, '/' | ' ' | '-' | '_' | '.')
+                        || (b.is_lowercase() && c.is_uppercase())
+                }
+            };
+            before = Some(c);
+            if c.to_lowercase().eq(std::iter::once(q)) {
+                score += 1;
+                if previous.is_some_and(|previous| previous + 1 == i) {
+                    score += 4;
+                }
+                if word_start {
+                    score += 6;
+                }
+                previous = Some(i);
+                break;
+            }
+        }
+    }
+    Some(score)
+}
+
+/// How well a note completion matches `query`, by its label or its detail: the note's title, or
+/// the path of the note an alias stands for.
+fn item_score(item: &CompletionItem, query: &str) -> Option<i64> {
+    let detail = item
+        .detail
+        .as_deref()
+        .and_then(|detail| fuzzy_score(detail, query));
+    fuzzy_score(&item.label, query).max(detail)
+}
+
+/// Note completions from the last request, reused while the user keeps typing the same link.
+///
+/// Candidates only depend on the names of notes, so they're kept until a note is added, removed,
+/// renamed or retitled. Each keystroke usually extends the query, and the notes matching the
+/// longer query are found among those that matched the shorter one.
+#[derive(Debug, Default)]
+pub struct CompletionCache {
+    /// The note, link style and index names generation the candidates were computed for.
+    key: Option<(PathBuf, LinkStyle, u64)>,
+    candidates: Vec<CompletionItem>,
+    query: String,
+    /// Indices of the candidates matching `query`.
+    matched: Vec<usize>,
+    /// How often the candidates could be reused.
+    stats: CacheStats,
+}
+
+impl CompletionCache {
+    /// Completions for a wiki link in the note at `path`, like `note_completions`, matching the
+    /// `query` typed so far. The best matches come first, and at most `MAX_NOTE_COMPLETIONS` are
+    /// returned.
+    pub fn note_completions(
+        &mut self,
+        index: &NoteIndex,
+        path: &Path,
+        style: LinkStyle,
+        query: &str,
+    ) -> Vec<CompletionItem> {
+        let key = (path.to_path_buf(), style, index.names_generation());
+        let refine = if self.key.as_ref() == Some(&key) {
+            self.stats.hits += 1;
+            query.starts_with(self.query.as_str())
+        } else {
+            self.stats.misses += 1;
+            self.candidates = note_completions(index, path, style);
+            self.key = Some(key);
+            false
+        };
+        let previous = if refine {
+            std::mem::take(&mut self.matched)
+        } else {
+            (0..self.candidates.len()).collect()
+        };
+
+        let mut scored = previous
+            .into_iter()
+            .filter_map(|i| Some((item_score(&self.candidates[i], query)?, i)))
+            .collect::<Vec<_>>();
+        self.matched = scored.iter().map(|&(_, i)| i).collect();
+        self.query = query.to_string();
+
+        // Ties keep the candidates' order, closest notes first.
+        scored.sort_by_key(|&(score, i)| (Reverse(score), i));
+        scored
+            .into_iter()
+            .take(MAX_NOTE_COMPLETIONS)
+            .enumerate()
+            .map(|(rank, (_, i))| {
+                let item = &self.candidates[i];
+                CompletionItem {
+                    // Keep our ranking, and let the editor match titles too.
+                    sort_text: Some(format!("{rank:05}")),
+                    filter_text: Some(match &item.detail {
+                        Some(detail) => format!("{} {detail}", item.label),
+                        None => item.label.clone(),
+                    }),
+                    ..item.clone()
+                }
+            })
+            .collect()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+}
+
+/// The text typed so far inside a wiki link that is still open at `character` on `line`.
+pub fn open_wiki_link(line: &str, character: usize) -> Option<&str> {
+    let before = line.get(..character)?;
+    let inner = &before[before.rfind("[[")? + 2..];
+    (!inner.contains("]]")).then_some(inner)
+}
+
+/// The target typed so far inside a markdown link that is still open at `character` on `line`,
+/// after its `](`.
+pub fn open_markdown_link(line: &str, character: usize) -> Option<&str> {
+    let before = line.get(..character)?;
+    let inner = &before[before.rfind("](")? + 2..];
+    (!inner.contains([')', ' '])).then_some(inner)
+}
+
+/// The language typed so far after an opening fence at `position` of `document`, and its range.
+/// Fences closing a block don't have one.
+pub fn open_fence(document: &str, position: Position) -> Option<(&str, Range)> {
+    let line = document.lines().nth(position.line as usize)?;
+    let before = line.get(..position.character as usize)?;
+    let (_, typed) = fences::fence(before)?;
+    let closing = code_blocks::blocks(document)
+        .iter()
+        .any(|block| block.end == position.line);
+    if closing || typed.contains(char::is_whitespace) || typed.contains('`') {
+        return None;
+    }
+    let start = Position::new(position.line, (before.len() - typed.len()) as u32);
+    Some((typed, Range::new(start, position)))
+}
+
+/// Completions for the language of a code block, replacing `range`: the ones the preview
+/// highlights, by their names and aliases, the ones it draws, and `others` with what they're
+/// for, like the languages of code runners.
+pub fn fence_language_completions(range: Range, others: &[(&str, &str)]) -> Vec<CompletionItem> {
+    let mut items = HIGHLIGHTED_LANGUAGES
+        .iter()
+        .flat_map(|&(name, description, aliases)| {
+            let aliases = aliases
+                .iter()
+                .map(move |&alias| (alias, format!("{description}, same as `{name}`")));
+            std::iter::once((name, description.to_string())).chain(aliases)
+        })
+        .collect::<Vec<_>>();
+    for &(language, description) in DRAWN_LANGUAGES.iter().chain(others) {
+        match items.iter_mut().find(|(known, _)| *known == language) {
+            Some((_, detail)) => *detail = format!("{detail}; {description}"),
+            None => items.push((language, description.to_string())),
+        }
+    }
+    items
+        .into_iter()
+        .map(|(language, detail)| CompletionItem {
+            label: language.to_string(),
+            kind: Some(CompletionItemKind::ENUM_MEMBER),
+            detail: Some(detail),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                range,
+                language.to_string(),
+            ))),
+            ..CompletionItem::default()
+        })
+        .collect()
+}
+
+/// Note completions turned into targets of a markdown link, replacing the `range` typed so far
+/// with the percent-encoded path of the note. Aliases only work in wiki links, so they're left
+/// out.
+pub fn markdown_link_completions(items: Vec<CompletionItem>, range: Range) -> Vec<CompletionItem> {
+    items
+        .into_iter()
+        .filter(|item| item.kind == Some(CompletionItemKind::FILE))
+        .map(|item| {
+            let target = links::encode_target(&item.label);
+            CompletionItem {
+                filter_text: Some(match &item.detail {
+                    Some(detail) => format!("{target} {detail}"),
+                    None => target.clone(),
+                }),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, target))),
+                ..item
+            }
+        })
+        .collect()
+}
+
+/// Escape `text` for use in a snippet, where `$`, `}` and `\` are special.
+fn escape_snippet(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('$', "\\$")
+        .replace('}', "\\}")
+}
+
+/// Note completions for a wiki link, each followed by a snippet completion that also gives the
+/// link an alias: `note|${1:Title}` leaves the cursor on the alias, which starts out as the
+/// note's title. Notes without a title only get the plain completion.
+pub fn with_alias_snippets(items: Vec<CompletionItem>) -> Vec<CompletionItem> {
+    let mut with_snippets = Vec::with_capacity(items.len());
+    for item in items {
+        let snippet = match (item.kind, &item.detail) {
+            (Some(CompletionItemKind::FILE), Some(title)) => Some(CompletionItem {
+                label: format!("{}|{title}", item.label),
+                kind: Some(CompletionItemKind::SNIPPET),
+                // Right after the plain completion of the same note.
+                sort_text: item.sort_text.as_ref().map(|sort| format!("{sort}|")),
+                insert_text: Some(format!(
+                    "{}|${{1:{}}}",
+                    escape_snippet(&item.label),
+                    escape_snippet(title)
+                )),
+                insert_text_format: Some(InsertTextFormat::SNIPPET),
+                ..item.clone()
+            }),
+            _ => None,
+        };
+        with_snippets.push(item);
+        with_snippets.extend(snippet);
+    }
+    with_snippets
+}
+
+/// Note completions for a wiki link, replacing the `range` of the target typed so far rather
+/// than adding to it, and closing the link with `]]` unless it's `closed` already, as editors
+/// that pair brackets do. Alias snippets keep their placeholder before the brackets, so the
+/// cursor ends up on the alias and then after the link.
+pub fn wiki_link_completions(
+    items: Vec<CompletionItem>,
+    range: Range,
+    closed: bool,
+) -> Vec<CompletionItem> {
+    let closing = if closed { "" } else { "]]" };
+    items
+        .into_iter()
+        .map(|item| {
+            let text = item.insert_text.as_deref().unwrap_or(&item.label);
+            let new_text = format!("{text}{closing}");
+            CompletionItem {
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, new_text))),
+                insert_text: None,
+                ..item
+            }
+        })
+        .collect()
+}
+
+/// The note a link of `kind` to `target` in the note at `path` points to. An empty target is
+/// the note itself.
+fn linked_note<'a>(
+    index: &'a NoteIndex,
+    path: &Path,
+    target: &str,
+    kind: LinkKind,
+) -> Option<&'a Note> {
+    let link = Link {
+        kind,
+        embed: false,
+        target: target.to_string(),
+        text: None,
+        line: 0,
+        start: 0,
+        end: 0,
+    };
+    let resolved = if link.target_path().is_empty() {
+        Some(path.to_path_buf())
+    } else {
+        index.resolve(path, &link)
+    };
+    index.get(&resolved?)
+}
+
+/// Completions for the alias of a wiki link to `target` in the note at `path`, typed after its
+/// `|`: the title and aliases of the linked note.
+///
+/// `range` covers the alias typed so far, and is replaced by the completion.
+pub fn alias_completions(
+    index: &NoteIndex,
+    path: &Path,
+    target: &str,
+    range: Range,
+) -> Vec<CompletionItem> {
+    let Some(note) = linked_note(index, path, target, LinkKind::Wiki) else {
+        return Vec::new();
+    };
+
+    let mut names = note.title().into_iter().collect::<Vec<_>>();
+    for alias in &note.frontmatter.aliases {
+        if !names.contains(&alias.as_str()) {
+            names.push(alias);
+        }
+    }
+    names
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| CompletionItem {
+            label: name.to_string(),
+            kind: Some(CompletionItemKind::TEXT),
+            sort_text: Some(format!("{i:05}")),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                range,
+                name.to_string(),
+            ))),
+            ..CompletionItem::default()
+        })
+        .collect()
+}
+
+/// Completions for the `#heading` part of a link of `kind` to `target` in the note at `path`:
+/// the headings of the linked note, in document order. An empty target is the note itself.
+///
+/// Markdown links name headings by their anchor, e.g. `#some-heading`, so that's what's inserted
+/// for them. So it is for wiki links to a repeated heading, which its text would name the first
+/// of.
+pub fn heading_completions(
+    index: &NoteIndex,
+    path: &Path,
+    target: &str,
+    kind: LinkKind,
+) -> Vec<CompletionItem> {
+    let Some(note) = linked_note(index, path, target, kind) else {
+        return Vec::new();
+    };
+
+    note.headings
+        .iter()
+        .zip(headings::anchors(&note.headings))
+        .enumerate()
+        .map(|(i, (heading, anchor))| {
+            let named_by_text = note
+                .find_heading(&heading.text)
+                .is_some_and(|found| found.line == heading.line);
+            CompletionItem {
+                label: heading.text.clone(),
+                kind: Some(CompletionItemKind::REFERENCE),
+                detail: Some("#".repeat(heading.level as usize)),
+                sort_text: Some(format!("{i:05}")),
+                insert_text: (kind == LinkKind::Markdown || !named_by_text).then_some(anchor),
+                ..CompletionItem::default()
+            }
+        })
+        .collect()
+}
+
+/// Completions for the block part of a link of `kind` to `target` in the note at `path`, typed
+/// after `#^`, or after a bare `^` in wiki links: the IDs of the blocks of the linked note, in
+/// document order, with the text they mark.
+///
+/// `range` covers the `^` and what's typed after it, and is replaced by `^id`, with the `#` in
+/// front unless `hash_typed`.
+pub fn block_completions(
+    index: &NoteIndex,
+    path: &Path,
+    target: &str,
+    kind: LinkKind,
+    range: Range,
+    hash_typed: bool,
+) -> Vec<CompletionItem> {
+    let Some(note) = linked_note(index, path, target, kind) else {
+        return Vec::new();
+    };
+
+    let hash = if hash_typed { "" } else { "#" };
+    note.blocks
+        .iter()
+        .enumerate()
+        .map(|(i, block)| CompletionItem {
+            label: format!("^{}", block.id),
+            kind: Some(CompletionItemKind::REFERENCE),
+            detail: Some(block.text.clone()),
+            sort_text: Some(format!("{i:05}")),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                range,
+                format!("{hash}^{}", block.id),
+            ))),
+            ..CompletionItem::default()
+        })
+        .collect()
+}
+
+/// Completions for a tag being typed: every tag in the vault.
+///
+/// `range` covers what's been typed so far, including the `#`, and is replaced by the tag.
+pub fn tag_completions(index: &NoteIndex, range: Range) -> Vec<CompletionItem> {
+    index
+        .tags()
+        .into_iter()
+        .map(|(name, count)| CompletionItem {
+            label: name.to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            detail: Some(if count == 1 {
+                "1 note".to_string()
+            } else {
+                format!("{count} notes")
+            }),
+            filter_text: Some(format!("#{name}")),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                range,
+                format!("#{name}"),
+            ))),
+            ..CompletionItem::default()
+        })
+        .collect()
+}
+
+/// Completions for the trigger of `note_type` being typed in the note at `path`: links to every
+/// note of that type, by title.
+///
+/// `range` covers what's been typed so far, including the trigger, and is replaced by the link.
+pub fn typed_note_completions(
+    index: &NoteIndex,
+    path: &Path,
+    note_type: &NoteType,
+    range: Range,
+) -> Vec<CompletionItem> {
+    let Some(note_dir) = path.parent() else {
+        return Vec::new();
+    };
+    let trigger = note_type.trigger.as_deref().unwrap_or_default();
+    let mut items = index
+        .notes()
+        .filter(|(note, parsed)| {
+            *note != path && parsed.frontmatter.note_type.as_deref() == Some(&note_type.name)
+        })
+        .filter_map(|(note, parsed)| {
+            let title = match parsed.title() {
+                Some(title) => title.to_string(),
+                None => note.file_stem()?.to_string_lossy().into_owned(),
+            };
+            let relative = note.strip_prefix(index.root()).unwrap_or(note);
+            Some(CompletionItem {
+                filter_text: Some(format!("{trigger}{title}")),
+                label: title,
+                kind: Some(CompletionItemKind::FILE),
+                detail: Some(links::path_to_target(relative)),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                    range,
+                    format!("[[{}]]", code_actions::wiki_target(note_dir, note)),
+                ))),
+                data: Some(json!({ "path": note })),
+                ..CompletionItem::default()
+            })
+        })
+        .collect::<Vec<_>>();
+    items.sort_by(|a, b| a.label.cmp(&b.label));
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tower_lsp::lsp_types::Position;
+
+    use super::*;
+
+    #[test]
+    fn notes_complete_by_path_and_alias() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("sub")).unwrap();
+        fs::write(root.path().join("a.md"), "").unwrap();
+        fs::write(root.path().join("sub/b.md"), "---\naliases: [Bee]\n---\n").unwrap();
+        let index = NoteIndex::scan(root.path());
+
+        let items = note_completions(&index, &root.path().join("a.md"), LinkStyle::Relative);
+        let labels = items
+            .iter()
+            .map(|item| (item.label.as_str(), item.detail.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec![("sub/b.md", None), ("Bee", Some("sub/b.md"))]);
+
+        let labels = |style| {
+            note_completions(&index, &root.path().join("sub/b.md"), style)
+                .into_iter()
+                .map(|item| item.label)
+                .collect::<Vec<_>>()
+        };
+        assert!(labels(LinkStyle::Relative).is_empty());
+        assert_eq!(labels(LinkStyle::Root), vec!["a.md"]);
+    }
+
+    #[test]
+    fn markdown_links_complete_encoded_paths() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.md"), "").unwrap();
+        fs::write(
+            root.path().join("my note.md"),
+            "---\naliases: [Mine]\n---\n# Title",
+        )
+        .unwrap();
+        let index = NoteIndex::scan(root.path());
+
+        assert_eq!(open_markdown_link("see [x](my%20", 13), Some("my%20"));
+        assert_eq!(open_markdown_link("[x](done) and", 13), None);
+        assert_eq!(open_markdown_link("[[wiki", 6), None);
+
+        let range = Range::new(Position::new(0, 8), Position::new(0, 13));
+        let items = note_completions(&index, &root.path().join("a.md"), LinkStyle::Relative);
+        let items = markdown_link_completions(items, range);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].filter_text.as_deref(), Some("my%20note.md Title"));
+        assert_eq!(
+            items[0].text_edit,
+            Some(CompletionTextEdit::Edit(TextEdit::new(
+                range,
+                "my%20note.md".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn cached_completions_are_refined() {
+        let root = tempfile::tempdir().unwrap();
+        for name in ["a.md", "apple.md", "banana.md", "Grape.md"] {
+            fs::write(root.path().join(name), "").unwrap();
+        }
+        let mut index = NoteIndex::scan(root.path());
+        let path = root.path().join("a.md");
+        let mut cache = CompletionCache::default();
+        let mut labels = |index: &NoteIndex, query| {
+            cache
+                .note_completions(index, &path, LinkStyle::Relative, query)
+                .into_iter()
+                .map(|item| item.label)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            labels(&index, ""),
+            vec!["Grape.md", "apple.md", "banana.md"]
+        );
+        assert_eq!(labels(&index, "ap"), vec!["apple.md", "Grape.md"]);
+        assert_eq!(labels(&index, "apl"), vec!["apple.md"]);
+        assert_eq!(labels(&index, "b"), vec!["banana.md"]);
+
+        // New notes show up right away.
+        index.update(root.path().join("bap.md"), "");
+        assert_eq!(labels(&index, "b"), vec!["banana.md", "bap.md"]);
+    }
+
+    #[test]
+    fn linked_and_recent_notes_come_first() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::create_dir(root.join("sub")).unwrap();
+        for name in ["a.md", "b.md", "c.md", "sub/d.md"] {
+            fs::write(root.join(name), "").unwrap();
+        }
+        fs::write(root.join("e.md"), "[[c]] [[d]] [[d]]").unwrap();
+        let old = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1);
+        fs::File::options()
+            .write(true)
+            .open(root.join("a.md"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        let index = NoteIndex::scan(root);
+
+        let labels = note_completions(&index, &root.join("e.md"), LinkStyle::Relative)
+            .into_iter()
+            .map(|item| item.label)
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["c.md", "b.md", "a.md", "sub/d.md"]);
+    }
+
+    #[test]
+    fn fuzzy_matches_are_ranked() {
+        assert_eq!(fuzzy_score("notes.md", "xyz"), None);
+        assert_eq!(fuzzy_score("notes.md", "ston"), None);
+        let score = |text| fuzzy_score(text, "dn").unwrap();
+        assert!(score("daily/notes.md") > score("diamond.md"));
+        assert!(score("DailyNotes.md") > score("diamond.md"));
+        assert!(score("dn.md") > score("diamond.md"));
+
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.md"), "").unwrap();
+        fs::write(root.path().join("x1.md"), "# Meeting notes").unwrap();
+        fs::write(root.path().join("mango.md"), "").unwrap();
+        let index = NoteIndex::scan(root.path());
+        let items = CompletionCache::default().note_completions(
+            &index,
+            &root.path().join("a.md"),
+            LinkStyle::Relative,
+            "meet",
+        );
+        let labels = items
+            .iter()
+            .map(|item| item.label.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["x1.md"]);
+        assert_eq!(items[0].sort_text.as_deref(), Some("00000"));
+        assert_eq!(items[0].filter_text.as_deref(), Some("x1.md Meeting notes"));
+    }
+
+    #[test]
+    fn note_documentation_summarizes_the_note() {
+        let content =
+            "---\naliases: [Bee]\ntags: [insects]\n---\n# Bees\n\nThey buzz\nand sting.\n\nMore.";
+        let Documentation::MarkupContent(documentation) =
+            note_documentation(Path::new("/v/b.md"), content, false)
+        else {
+            panic!("expected markup");
+        };
+        assert_eq!(
+            documentation.value,
+            "Bees\n\nAliases: Bee\nTags: #insects\n\nThey buzz\nand sting."
+        );
+
+        let long = "x".repeat(SUMMARY_CHARS + 10);
+        assert_eq!(first_paragraph(&long).chars().count(), SUMMARY_CHARS + 1);
+        assert_eq!(first_paragraph("# Only a title"), "");
+    }
+
+    #[test]
+    fn headings_complete_after_a_hash() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.md"), "# Mine").unwrap();
+        fs::write(root.path().join("other.md"), "# Zebra\n## Apple\n## Apple").unwrap();
+        let index = NoteIndex::scan(root.path());
+        let path = root.path().join("a.md");
+
+        assert_eq!(open_wiki_link("see [[other#Ap", 14), Some("other#Ap"));
+        assert_eq!(open_wiki_link("[[done]] and", 12), None);
+
+        let labels = |target| {
+            heading_completions(&index, &path, target, LinkKind::Wiki)
+                .into_iter()
+                .map(|item| item.label)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(labels("other"), vec!["Zebra", "Apple", "Apple"]);
+        assert_eq!(labels(""), vec!["Mine"]);
+        assert!(labels("missing").is_empty());
+
+        let items = heading_completions(&index, &path, "other.md", LinkKind::Markdown);
+        assert_eq!(items[1].label, "Apple");
+        assert_eq!(items[1].insert_text.as_deref(), Some("apple"));
+        assert_eq!(items[2].insert_text.as_deref(), Some("apple-1"));
+
+        // The text of a repeated heading would name the first one.
+        let items = heading_completions(&index, &path, "other", LinkKind::Wiki);
+        assert_eq!(items[1].insert_text, None);
+        assert_eq!(items[2].insert_text.as_deref(), Some("apple-1"));
+    }
+
+    #[test]
+    fn blocks_complete_after_a_caret() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.md"), "").unwrap();
+        fs::write(
+            root.path().join("other.md"),
+            "# Other\nIntro ^intro\n\n- item ^item",
+        )
+        .unwrap();
+        let index = NoteIndex::scan(root.path());
+        let path = root.path().join("a.md");
+        let range = Range::new(Position::new(0, 7), Position::new(0, 8));
+
+        let items = block_completions(&index, &path, "other", LinkKind::Wiki, range, false);
+        let labels = items
+            .iter()
+            .map(|item| item.label.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, ["^intro", "^item"]);
+        assert_eq!(items[0].detail.as_deref(), Some("Intro"));
+        let new_text = |item: &CompletionItem| match &item.text_edit {
+            Some(CompletionTextEdit::Edit(edit)) => edit.new_text.clone(),
+            _ => String::new(),
+        };
+        assert_eq!(new_text(&items[0]), "#^intro");
+
+        let items = block_completions(&index, &path, "other", LinkKind::Wiki, range, true);
+        assert_eq!(new_text(&items[1]), "^item");
+    }
+
+    #[test]
+    fn aliases_complete_after_a_pipe() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.md"), "").unwrap();
+        fs::write(
+            root.path().join("other.md"),
+            "---\naliases: [Zed, Other]\n---\n# Other",
+        )
+        .unwrap();
+        let index = NoteIndex::scan(root.path());
+        let path = root.path().join("a.md");
+
+        let range = Range::new(Position::new(0, 8), Position::new(0, 9));
+        let items = alias_completions(&index, &path, "other#Part", range);
+        let labels = items
+            .iter()
+            .map(|item| item.label.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["Other", "Zed"]);
+        assert_eq!(
+            items[1].text_edit,
+            Some(CompletionTextEdit::Edit(TextEdit::new(
+                range,
+                "Zed".to_string()
+            )))
+        );
+        assert!(alias_completions(&index, &path, "missing", range).is_empty());
+
+        let items = with_alias_snippets(note_completions(&index, &path, LinkStyle::Relative));
+        let snippet = items
+            .iter()
+            .find(|item| item.kind == Some(CompletionItemKind::SNIPPET))
+            .unwrap();
+        assert_eq!(snippet.label, "other.md|Other");
+        assert_eq!(snippet.insert_text.as_deref(), Some("other.md|${1:Other}"));
+        assert_eq!(escape_snippet("a$b}\\"), "a\\$b\\}\\\\");
+
+        let range = Range::new(Position::new(0, 2), Position::new(0, 4));
+        let new_texts = |items: Vec<CompletionItem>| {
+            items
+                .into_iter()
+                .filter_map(|item| match item.text_edit {
+                    Some(CompletionTextEdit::Edit(edit)) if edit.range == range => {
+                        Some(edit.new_text)
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            new_texts(wiki_link_completions(items.clone(), range, false))[..2],
+            ["other.md]]", "other.md|${1:Other}]]"]
+        );
+        assert_eq!(
+            new_texts(wiki_link_completions(items, range, true))[..2],
+            ["other.md", "other.md|${1:Other}"]
+        );
+    }
+
+    #[test]
+    fn tags_complete_with_their_hash() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("a.md"), "#rust #notes").unwrap();
+        fs::write(root.path().join("b.md"), "#rust").unwrap();
+        let index = NoteIndex::scan(root.path());
+
+        let range = Range::new(Position::new(0, 4), Position::new(0, 6));
+        let items = tag_completions(&index, range);
+        let labels = items
+            .iter()
+            .map(|item| (item.label.as_str(), item.detail.as_deref().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec![("notes", "1 note"), ("rust", "2 notes")]);
+        assert_eq!(
+            items[1].text_edit,
+            Some(CompletionTextEdit::Edit(TextEdit::new(
+                range,
+                "#rust".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn typed_notes_complete_after_their_trigger() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("books")).unwrap();
+        fs::write(
+            root.path().join("books/dune.md"),
+            "---\ntype: book\ntitle: Dune\n---\n",
+        )
+        .unwrap();
+        fs::write(root.path().join("books/emma.md"), "---\ntype: book\n---\n").unwrap();
+        fs::write(root.path().join("film.md"), "---\ntype: film\n---\n").unwrap();
+        let index = NoteIndex::scan(root.path());
+        let book = NoteType {
+            name: String::from("book"),
+            trigger: Some(String::from("📖")),
+            ..NoteType::default()
+        };
+
+        let range = Range::new(Position::new(0, 0), Position::new(0, 4));
+        let items = typed_note_completions(&index, &root.path().join("log.md"), &book, range);
+        let labels = items
+            .iter()
+            .map(|item| (item.label.as_str(), item.filter_text.as_deref().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec![("Dune", "📖Dune"), ("emma", "📖emma")]);
+        assert_eq!(
+            items[0].text_edit,
+            Some(CompletionTextEdit::Edit(TextEdit::new(
+                range,
+                "[[books/dune]]".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn code_block_languages_complete_after_a_fence() {
+        let document = "```ru\n```\n\n````\nfn main() {}\n````";
+        let (typed, range) = open_fence(document, Position::new(0, 5)).unwrap();
+        assert_eq!(typed, "ru");
+        assert_eq!(range, Range::new(Position::new(0, 3), Position::new(0, 5)));
+        assert_eq!(open_fence(document, Position::new(1, 3)), None);
+        assert_eq!(open_fence(document, Position::new(3, 4)).unwrap().0, "");
+        assert_eq!(open_fence(document, Position::new(5, 4)), None);
+
+        let items =
+            fence_language_completions(range, &[("sh", "Runs with sh"), ("dot", "Graphviz")]);
+        let detail = |label: &str| {
+            items
+                .iter()
+                .find(|item| item.label == label)
+                .and_then(|item| item.detail.clone())
+                .unwrap()
+        };
+        assert_eq!(detail("rust"), "Rust");
+        assert_eq!(detail("sh"), "Bash, same as `bash`; Runs with sh");
+        assert_eq!(detail("shell"), "Shell session");
+        assert_eq!(detail("dot"), "Graphviz");
+        assert_eq!(detail("mermaid"), "Mermaid diagram");
+    }
+}
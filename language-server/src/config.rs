@@ -0,0 +1,569 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use serde::Deserialize;
+use serde_json::Value;
+use tower_lsp::lsp_types::DiagnosticSeverity;
+
+use crate::{hooks::Hooks, index::Resolution, plugins::PluginConfig};
+
+/// What to do with a note's attachments when the note is moved.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AttachmentsPolicy {
+    /// Leave attachments where they are and rewrite relative links to keep pointing at them.
+    #[default]
+    UpdateLinks,
+    /// Move attachments stored next to the note (in its folder or below) along with it.
+    Move,
+    /// Don't touch attachments or links.
+    Ignore,
+}
+
+/// Severity of a diagnostic, as written in the settings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    Error,
+    #[default]
+    Warning,
+    Information,
+    Hint,
+}
+
+impl From<Severity> for DiagnosticSeverity {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Error => DiagnosticSeverity::ERROR,
+            Severity::Warning => DiagnosticSeverity::WARNING,
+            Severity::Information => DiagnosticSeverity::INFORMATION,
+            Severity::Hint => DiagnosticSeverity::HINT,
+        }
+    }
+}
+
+/// How completed links name the note they point to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LinkStyle {
+    /// Relative to the folder of the linking note. Only notes in that folder or below are offered.
+    #[default]
+    Relative,
+    /// Relative to the vault root.
+    Root,
+}
+
+/// How wiki links find the notes they point to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LinkResolution {
+    /// By their path relative to the folder of the linking note, or else to the vault root.
+    #[default]
+    Relative,
+    /// Also by their file name or title anywhere in the vault, like `[[My Note]]`, as Obsidian
+    /// does. The note with the shortest path wins if several go by the name.
+    Shortest,
+    /// By their path relative to the vault root.
+    Absolute,
+}
+
+impl From<LinkResolution> for Resolution {
+    fn from(resolution: LinkResolution) -> Self {
+        match resolution {
+            LinkResolution::Relative => Resolution::Relative,
+            LinkResolution::Shortest => Resolution::Shortest,
+            LinkResolution::Absolute => Resolution::Absolute,
+        }
+    }
+}
+
+/// Where notes created by the server go: notes created from broken links and notes extracted
+/// from a selection.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum NewNoteLocation {
+    /// Next to the note they're created from.
+    #[default]
+    SameFolder,
+    /// Always in the same folder, relative to the vault root, e.g. an inbox.
+    Folder { folder: PathBuf },
+    /// In a folder relative to the vault root named by a pattern, in which `{year}`, `{month}`
+    /// and `{day}` stand for today's date in UTC and `{tag}` for the first tag of the note
+    /// they're created from, e.g. `journal/{year}/{month}`.
+    Pattern { pattern: String },
+}
+
+/// A kind of note, e.g. `book` or `meeting`, which notes declare with `type` in their
+/// frontmatter.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NoteType {
+    /// The name notes give as their `type`.
+    pub name: String,
+    /// Shown next to the name when picking the type of a new note, e.g. an emoji.
+    pub icon: Option<String>,
+    /// Starting content of new notes of this type, relative to the vault root, used instead of
+    /// `note_template`.
+    pub template: Option<PathBuf>,
+    /// Folder new notes of this type go in, relative to the vault root, instead of the one
+    /// `new_note_location` picks.
+    pub folder: Option<PathBuf>,
+    /// Frontmatter fields notes of this type must give a value for.
+    pub required: Vec<String>,
+    /// Typing this at the start of a word, e.g. `📖`, offers links to the notes of this type.
+    pub trigger: Option<String>,
+}
+
+impl NoteType {
+    /// The name as shown when picking a type.
+    pub fn label(&self) -> String {
+        match &self.icon {
+            Some(icon) => format!("{icon} {}", self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// A program running the fenced code blocks of a language, e.g. `python` with `python3`. The
+/// block's code is given to it on stdin.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CodeRunner {
+    /// The language as written after the opening fence, e.g. `sh`.
+    pub language: String,
+    pub command: String,
+    pub args: Vec<String>,
+}
+
+/// A program drawing the fenced code blocks of a language as SVG in the preview, e.g. `dot` with
+/// `["-Tsvg"]` for `dot` blocks, or `plantuml` with `["-tsvg", "-pipe"]` for `plantuml` ones. It
+/// reads the block on stdin and writes the SVG on stdout.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DiagramTool {
+    /// The language as written after the opening fence.
+    pub language: String,
+    pub command: String,
+    pub args: Vec<String>,
+}
+
+/// How the preview is rendered.
+///
+/// External programs read markdown and write HTML; see `aurelius::render` for the details. Their
+/// arguments may contain `{path}` and `{dir}`, standing for the note and its folder.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum Renderer {
+    /// The built-in CommonMark renderer.
+    #[default]
+    Builtin,
+    /// A program run for every update of the preview, e.g. MD4C's `md2html` for LaTeX math.
+    Command {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    /// A program kept running, exchanging documents over stdin and stdout.
+    Process {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+/// A program printing exported notes to PDF. `{input}` and `{output}` in its arguments stand for
+/// the HTML file to print and the PDF to write.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfCommand {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl Default for PdfCommand {
+    /// Headless Chromium, which runs the page's scripts for code and math before printing.
+    fn default() -> Self {
+        Self {
+            command: String::from("chromium"),
+            args: [
+                "--headless",
+                "--no-pdf-header-footer",
+                "--print-to-pdf={output}",
+                "{input}",
+            ]
+            .map(String::from)
+            .to_vec(),
+        }
+    }
+}
+
+/// Color scheme of the preview.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PreviewTheme {
+    #[default]
+    Light,
+    Dark,
+}
+
+impl From<PreviewTheme> for aurelius::Theme {
+    fn from(theme: PreviewTheme) -> Self {
+        match theme {
+            PreviewTheme::Light => aurelius::Theme::Light,
+            PreviewTheme::Dark => aurelius::Theme::Dark,
+        }
+    }
+}
+
+/// Direction of the text in the preview.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PreviewDirection {
+    /// Left to right, as in English.
+    #[default]
+    Ltr,
+    /// Right to left, as in Arabic and Hebrew.
+    Rtl,
+}
+
+impl From<PreviewDirection> for aurelius::Direction {
+    fn from(direction: PreviewDirection) -> Self {
+        match direction {
+            PreviewDirection::Ltr => aurelius::Direction::Ltr,
+            PreviewDirection::Rtl => aurelius::Direction::Rtl,
+        }
+    }
+}
+
+/// Server settings, sent by the client as `initializationOptions` and updated through
+/// `workspace/didChangeConfiguration`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Config {
+    /// Render a live preview of the notes being edited. It is shown in the browser with the
+    /// `noteLs.preview.*` commands. Turning it off stops the preview server.
+    pub preview: bool,
+    /// Open the preview in the browser when the server starts or the preview is turned on.
+    pub preview_auto_open: bool,
+    /// How long typing must pause before the preview is rendered again, in milliseconds.
+    pub preview_delay_ms: u64,
+    /// Address the preview server listens on. Anything but a loopback address, like `0.0.0.0`,
+    /// lets other machines on the network read the vault.
+    pub preview_host: String,
+    /// Port the preview server listens on, so its URL can be bookmarked or forwarded. `0` picks
+    /// a free one each time it starts.
+    pub preview_port: u16,
+    pub renderer: Renderer,
+    /// Color scheme of the preview. The dark one also picks a dark theme for code blocks.
+    pub preview_theme: PreviewTheme,
+    /// Direction of the text of notes in the preview. The builtin renderer sets the direction of
+    /// paragraphs, headings and other blocks written the other way from their first letter.
+    pub preview_direction: PreviewDirection,
+    /// Stylesheet added to the preview on top of the default one, relative to the vault root.
+    pub preview_stylesheet: Option<PathBuf>,
+    /// Handlebars template of the preview page, relative to the vault root, placing `{{{ css }}}`,
+    /// `{{{ body }}}`, `{{{ scripts }}}` and `{{ title }}` in a page of its own.
+    pub preview_template: Option<PathBuf>,
+    /// Follow the links in the preview with badges counting the backlinks of the notes they point
+    /// to, and the open tasks of project notes.
+    pub preview_badges: bool,
+    /// Show `%% %%` comments in the preview and exports, as text. HTML comments are never shown.
+    pub preview_comments: bool,
+    /// Programs drawing diagrams in the preview, see `diagrams`. Mermaid diagrams are drawn by
+    /// the preview itself.
+    pub diagram_tools: Vec<DiagramTool>,
+    /// Serve the counters `noteLs/status` reports at `/__/metrics` on the preview server, in
+    /// Prometheus' text format, while the preview is on. Nothing is sent anywhere.
+    pub metrics_endpoint: bool,
+    /// Program `noteLs.export.pdf` prints notes with, after exporting them to HTML.
+    pub pdf_command: PdfCommand,
+    pub attachments_policy: AttachmentsPolicy,
+    pub link_style: LinkStyle,
+    pub link_resolution: LinkResolution,
+    /// Publish diagnostics for notes. Turning this off clears the ones already published.
+    pub diagnostics: bool,
+    /// Severity of diagnostics for wiki links to notes that don't exist.
+    pub broken_link_severity: Severity,
+    /// Warn about links that only resolve because the file system ignores case.
+    pub check_link_case: bool,
+    /// Language of the text the server generates, like diagnostic messages, e.g. `de` or
+    /// `fr-CA`. Defaults to the editor's language, and falls back to English.
+    pub locale: Option<String>,
+    /// Flag notes no other note links to, and notes that link to no other note.
+    pub orphan_diagnostics: bool,
+    /// Warn about embedded images without alt text, which screen readers can't describe.
+    pub alt_text_diagnostics: bool,
+    /// Warn about headings that skip a level, like a `###` right after a `#`.
+    pub heading_order_diagnostics: bool,
+    /// Warn about notes that share a title or an alias, which makes links by that name
+    /// ambiguous, and about headings of a note with the same anchor.
+    pub duplicate_diagnostics: bool,
+    /// Hint at misspelled words in the prose of notes. Code, links, tags and frontmatter aren't
+    /// checked.
+    pub spell_check: bool,
+    /// Hunspell dictionary to check spelling with: the name of one installed in the usual
+    /// folders, like `en_US`, or the path of its `.dic` file, with its `.aff` file next to it.
+    pub spell_dictionary: String,
+    /// The vault's own list of words, one per line, relative to the vault root. "Add to
+    /// dictionary" adds words to it.
+    pub custom_dictionary: PathBuf,
+    /// BibTeX files whose entries can be cited with `[@key]` or `@key`, relative to the vault root
+    /// unless absolute. Their keys are completed after `@`, and hovering a citation shows the
+    /// reference.
+    pub bibliography: Vec<PathBuf>,
+    /// Show the number of backlinks above every linked heading, not only above the title.
+    pub heading_lenses: bool,
+    /// Show how many words each section has and how long it takes to read, as inlay hints after
+    /// headings of this level or above, e.g. 1 for `#` headings only. 0 turns them off.
+    pub section_hint_level: u8,
+    /// Words read in a minute, for the reading times of sections.
+    pub reading_speed: usize,
+    /// In vaults kept in git, show the last commit of a section when hovering its heading, as
+    /// `git blame` has it. The last commit of the whole note is shown on its title either way.
+    pub git_section_blame: bool,
+    /// Check external URLs for the link report, and those of a note when it's saved, warning
+    /// about the ones that are broken. Off by default since it hits the network.
+    pub check_external_links: bool,
+    /// Hosts whose URLs are never checked, like ones that turn away bots, e.g. `example.com`,
+    /// which covers its subdomains too.
+    pub external_link_allowlist: Vec<String>,
+    /// Publish diagnostics for up to this many notes once the vault is indexed, rather than only
+    /// for the notes that are opened. 0 turns this off.
+    pub vault_diagnostics_limit: usize,
+    /// Index every note in the vault on startup. Without the index only open notes are known, so
+    /// links aren't checked. Only read on startup.
+    pub index_vault: bool,
+    /// Vaults with more notes than this aren't indexed, as if `index_vault` were off.
+    pub max_indexed_notes: usize,
+    /// Save the index of each vault in its `.note-ls` folder, so only the notes changed since
+    /// are parsed on the next startup.
+    pub index_cache: bool,
+    /// Encrypt the index cache with a key derived from this passphrase.
+    pub index_cache_passphrase: Option<String>,
+    /// A shell command printing the passphrase to encrypt the index cache with, e.g.
+    /// `secret-tool lookup service note-ls` or `security find-generic-password -s note-ls -w` to
+    /// keep it in the OS keyring. Used instead of `index_cache_passphrase` if both are set. If
+    /// the command fails, the cache is neither read nor written.
+    pub index_cache_passphrase_command: Option<String>,
+    /// Extensions of the files that are notes, without the dot, e.g. `["md", "markdown", "txt"]`.
+    /// Wiki links without an extension try them in order. Only read when a vault is indexed.
+    pub note_extensions: Vec<String>,
+    /// Files and folders left out of the index and of completions, on top of the ones in
+    /// `.gitignore` files, written like lines of a `.gitignore` file at the vault root. Only read
+    /// when a vault is indexed.
+    pub ignore_globs: Vec<String>,
+    /// Megabytes of note content kept in memory for notes that aren't open, e.g. for hover
+    /// previews. The least recently used notes are read from disk again when needed.
+    pub content_cache_mb: usize,
+    /// Ask the editor to report notes changed outside it, which keeps the index up to date. Only
+    /// read on startup.
+    pub watch_files: bool,
+    /// How long edits and changes on disk must pause before the changed notes are indexed again,
+    /// in milliseconds. Bursts of changes, like a `git checkout`, are indexed in one pass.
+    pub index_delay_ms: u64,
+    /// How long a request may take before it's answered with an error, in milliseconds, so the
+    /// editor isn't left waiting on a stuck handler. Commands are never given up on. 0 never
+    /// gives up.
+    pub request_timeout_ms: u64,
+    /// Requests that take longer than this, in milliseconds, are logged with where their time
+    /// went. 0 logs none.
+    pub slow_request_ms: u64,
+    /// How long indexing one batch of changes may take before it's considered stuck, in
+    /// milliseconds. Stuck or crashed indexing is restarted, indexing the vaults again. 0 only
+    /// restarts it if it crashed.
+    pub watchdog_timeout_ms: u64,
+    /// Note used as the starting content of notes created from broken links, relative to the
+    /// vault root. `{{title}}` in it is replaced by the new note's name.
+    pub note_template: Option<PathBuf>,
+    /// Folder of templates to create notes from with `noteLs.newNote` or from broken links,
+    /// relative to the vault root. See `templates` for what they can contain. Add it to
+    /// `ignore_globs` to keep the templates themselves out of the index.
+    pub templates_folder: Option<PathBuf>,
+    /// Folder `noteLs.backupVault` writes backups to, relative to the vault root unless
+    /// absolute. Defaults to a folder next to the vault, named after it with `-backups` added.
+    pub backup_folder: Option<PathBuf>,
+    /// How many backups of a vault to keep. Older ones are deleted after a backup is made, and
+    /// 0 keeps them all.
+    pub backup_retention: usize,
+    /// Folder attachments are kept in, relative to the vault root unless absolute.
+    /// `noteLs.findUnusedAttachments` looks for unused ones there.
+    pub attachments_folder: PathBuf,
+    pub new_note_location: NewNoteLocation,
+    /// Where `noteLs.dailyNote.open` puts the note of each day, relative to the vault root. In
+    /// braces, `YYYY`, `MM` and `DD` stand for the year, month and day in UTC.
+    pub daily_note_pattern: String,
+    /// How dates typed in words after `@`, like `@tomorrow` or `@next monday`, are written when
+    /// they're completed. In braces, `YYYY`, `MM` and `DD` stand for the year, month and day, as
+    /// in `daily_note_pattern`.
+    pub date_format: String,
+    /// Starting content of daily notes, relative to the vault root. `{{title}}` in it is replaced
+    /// by the date, as `YYYY-MM-DD`.
+    pub daily_note_template: Option<PathBuf>,
+    /// The kinds of notes in the vault. Notes declaring one of them as their `type` are checked
+    /// for its required fields, and new notes can be created from its template.
+    pub note_types: Vec<NoteType>,
+    /// Programs running fenced code blocks with `noteLs.runCodeBlock`, which puts what they print
+    /// in an `output` block below the code. Blocks of other languages never run, so leaving this
+    /// empty turns running code off.
+    pub code_runners: Vec<CodeRunner>,
+    /// How long a code block may run before it's stopped, in milliseconds. `requestTimeoutMs`
+    /// doesn't apply to commands, so a block that runs too long always gets its note.
+    pub code_run_timeout_ms: u64,
+    /// Shell commands to run when notes are created, saved, renamed or published.
+    pub hooks: Hooks,
+    /// External programs to start alongside the server, see `plugins`. Only read on startup.
+    pub plugins: Vec<PluginConfig>,
+    /// Named sets of settings, e.g. `writing`, `review` or `presentation`, applied on top of the
+    /// others when chosen with `profile` or the `noteLs.setProfile` command. Objects in a profile
+    /// are merged into the settings they override rather than replacing them.
+    pub profiles: BTreeMap<String, Value>,
+    /// The profile in `profiles` to apply. `noteLs.setProfile` switches to another until this
+    /// setting changes.
+    pub profile: Option<String>,
+}
+
+/// Merge `overrides` into `settings`: objects key by key, and anything else by replacing it.
+fn merge(settings: &mut Value, overrides: &Value) {
+    match (settings, overrides) {
+        (Value::Object(settings), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                merge(settings.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+        (settings, overrides) => *settings = overrides.clone(),
+    }
+}
+
+impl Config {
+    /// The settings in `settings` with a profile applied: `profile` if it's given, or else the
+    /// one they choose themselves. Settings that don't parse and profiles that don't exist are
+    /// errors.
+    pub fn from_settings(settings: &Value, profile: Option<&str>) -> Result<Config, String> {
+        let base = serde_json::from_value::<Config>(settings.clone()).map_err(|e| e.to_string())?;
+        let Some(name) = profile.or(base.profile.as_deref()) else {
+            return Ok(base);
+        };
+        let overrides = base
+            .profiles
+            .get(name)
+            .ok_or_else(|| format!("There's no profile named {name}"))?;
+        let mut merged = settings.clone();
+        merge(&mut merged, overrides);
+        let mut config = serde_json::from_value::<Config>(merged)
+            .map_err(|e| format!("Invalid settings in profile {name}: {e}"))?;
+        config.profile = Some(name.to_string());
+        config.profiles = base.profiles;
+        Ok(config)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            preview: true,
+            preview_auto_open: false,
+            preview_delay_ms: 150,
+            preview_host: String::from("localhost"),
+            preview_port: 0,
+            renderer: Renderer::default(),
+            preview_theme: PreviewTheme::default(),
+            preview_direction: PreviewDirection::default(),
+            preview_stylesheet: None,
+            preview_template: None,
+            preview_badges: false,
+            preview_comments: false,
+            diagram_tools: Vec::new(),
+            metrics_endpoint: false,
+            pdf_command: PdfCommand::default(),
+            attachments_policy: AttachmentsPolicy::default(),
+            link_style: LinkStyle::default(),
+            link_resolution: LinkResolution::default(),
+            diagnostics: true,
+            broken_link_severity: Severity::default(),
+            check_link_case: false,
+            locale: None,
+            orphan_diagnostics: false,
+            alt_text_diagnostics: false,
+            heading_order_diagnostics: false,
+            duplicate_diagnostics: false,
+            spell_check: false,
+            spell_dictionary: String::from("en_US"),
+            custom_dictionary: PathBuf::from(".note-ls/dictionary.txt"),
+            bibliography: Vec::new(),
+            heading_lenses: false,
+            section_hint_level: 1,
+            reading_speed: 200,
+            git_section_blame: false,
+            check_external_links: false,
+            external_link_allowlist: Vec::new(),
+            vault_diagnostics_limit: 1000,
+            index_vault: true,
+            index_cache: false,
+            index_cache_passphrase: None,
+            index_cache_passphrase_command: None,
+            max_indexed_notes: 20_000,
+            note_extensions: vec![String::from("md")],
+            ignore_globs: Vec::new(),
+            content_cache_mb: 32,
+            watch_files: true,
+            index_delay_ms: 100,
+            request_timeout_ms: 10_000,
+            slow_request_ms: 1_000,
+            watchdog_timeout_ms: 60_000,
+            note_template: None,
+            templates_folder: None,
+            backup_folder: None,
+            backup_retention: 10,
+            attachments_folder: PathBuf::from("attachments"),
+            new_note_location: NewNoteLocation::default(),
+            daily_note_pattern: String::from("journal/{YYYY}/{MM}/{YYYY-MM-DD}.md"),
+            date_format: String::from("{YYYY-MM-DD}"),
+            daily_note_template: None,
+            note_types: Vec::new(),
+            code_runners: Vec::new(),
+            code_run_timeout_ms: 10_000,
+            hooks: Hooks::default(),
+            plugins: Vec::new(),
+            profiles: BTreeMap::new(),
+            profile: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn profiles_override_settings() {
+        let settings = json!({
+            "diagnostics": true,
+            "previewTheme": "light",
+            "hooks": { "saved": "git add -A", "created": "touch" },
+            "profiles": {
+                "writing": { "diagnostics": false, "hooks": { "saved": null } },
+                "presentation": { "previewTheme": "dark" },
+            },
+        });
+
+        let base = Config::from_settings(&settings, None).unwrap();
+        assert!(base.diagnostics);
+        assert_eq!(base.profile, None);
+
+        let writing = Config::from_settings(&settings, Some("writing")).unwrap();
+        assert!(!writing.diagnostics);
+        assert_eq!(writing.preview_theme, PreviewTheme::Light);
+        assert_eq!(writing.hooks.saved, None);
+        assert_eq!(writing.hooks.created.as_deref(), Some("touch"));
+        assert_eq!(writing.profile.as_deref(), Some("writing"));
+        assert_eq!(writing.profiles.len(), 2);
+
+        let mut chosen = settings.clone();
+        chosen["profile"] = json!("presentation");
+        let presentation = Config::from_settings(&chosen, None).unwrap();
+        assert_eq!(presentation.preview_theme, PreviewTheme::Dark);
+        assert!(Config::from_settings(&settings, Some("party")).is_err());
+    }
+}
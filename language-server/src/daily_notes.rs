@@ -0,0 +1,184 @@
+//! Daily notes, one per day at a path named after its date by `Config::daily_note_pattern`, for
+//! the `noteLs.dailyNote.*` commands.
+//!
+//! In a pattern like `journal/{YYYY}/{MM}/{YYYY-MM-DD}.md`, `YYYY`, `MM` and `DD` between braces
+//! stand for the year, month and day. Notes whose path matches the pattern are daily notes, which
+//! is how the previous and next ones are found.
+
+use std::path::{Path, PathBuf};
+
+use crate::{index::NoteIndex, links, new_notes::Date};
+
+#[derive(Debug, PartialEq, Eq)]
+enum Part<'a> {
+    Text(&'a str),
+    Year,
+    Month,
+    Day,
+}
+
+fn parts(pattern: &str) -> Vec<Part<'_>> {
+    let mut parts = Vec::new();
+    let mut rest = pattern;
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}').map(|close| open + close) else {
+            break;
+        };
+        parts.push(Part::Text(&rest[..open]));
+        let mut group = &rest[open + 1..close];
+        while let Some(c) = group.chars().next() {
+            let (part, len) = if group.starts_with("YYYY") {
+                (Part::Year, 4)
+            } else if group.starts_with("MM") {
+                (Part::Month, 2)
+            } else if group.starts_with("DD") {
+                (Part::Day, 2)
+            } else {
+                (Part::Text(&group[..c.len_utf8()]), c.len_utf8())
+            };
+            parts.push(part);
+            group = &group[len..];
+        }
+        rest = &rest[close + 1..];
+    }
+    parts.push(Part::Text(rest));
+    parts
+}
+
+/// `date` written the way `pattern` says, e.g. `{DD}.{MM}.{YYYY}`.
+pub fn format(pattern: &str, date: Date) -> String {
+    parts(pattern)
+        .into_iter()
+        .map(|part| match part {
+            Part::Text(text) => text.to_string(),
+            Part::Year => format!("{:04}", date.year),
+            Part::Month => format!("{:02}", date.month),
+            Part::Day => format!("{:02}", date.day),
+        })
+        .collect()
+}
+
+/// The path of the daily note of `date`, relative to the vault root.
+pub fn path(pattern: &str, date: Date) -> PathBuf {
+    format(pattern, date)
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .collect()
+}
+
+/// The date of the daily note at `path`, relative to the vault root, or `None` if it isn't one.
+///
+/// Patterns that leave out the year, month or day name no daily notes.
+pub fn date_of(pattern: &str, path: &Path) -> Option<Date> {
+    let path = links::path_to_target(&links::nfc_path(path));
+    let mut rest = path.as_str();
+    let (mut year, mut month, mut day) = (None, None, None);
+    for part in parts(pattern) {
+        let (field, width) = match part {
+            Part::Text(text) => {
+                rest = rest.strip_prefix(text)?;
+                continue;
+            }
+            Part::Year => (&mut year, 4),
+            Part::Month => (&mut month, 2),
+            Part::Day => (&mut day, 2),
+        };
+        let digits = rest
+            .get(..width)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))?;
+        let value = digits.parse::<i64>().ok()?;
+        // A field given twice, like the year in `{YYYY}/{YYYY-MM-DD}`, must agree with itself.
+        if field.is_some_and(|known| known != value) {
+            return None;
+        }
+        *field = Some(value);
+        rest = &rest[width..];
+    }
+    let date = Date {
+        year: year?,
+        month: u32::try_from(month?)
+            .ok()
+            .filter(|m| (1..=12).contains(m))?,
+        day: u32::try_from(day?).ok().filter(|d| (1..=31).contains(d))?,
+    };
+    rest.is_empty().then_some(date)
+}
+
+/// The daily note in `index` closest to `date`, before it or, if `later`, after it.
+pub fn neighbour(pattern: &str, index: &NoteIndex, date: Date, later: bool) -> Option<PathBuf> {
+    let notes = index.notes().filter_map(|(path, _)| {
+        let relative = path.strip_prefix(index.root()).ok()?;
+        Some((date_of(pattern, relative)?, path))
+    });
+    let found = match later {
+        true => notes
+            .filter(|(day, _)| *day > date)
+            .min_by_key(|(day, _)| *day),
+        false => notes
+            .filter(|(day, _)| *day < date)
+            .max_by_key(|(day, _)| *day),
+    };
+    found.map(|(_, path)| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    const PATTERN: &str = "journal/{YYYY}/{MM}/{YYYY-MM-DD}.md";
+
+    #[test]
+    fn daily_notes_are_named_after_their_date() {
+        let date = Date::from_days(20_742);
+        let path = path(PATTERN, date);
+        assert_eq!(path, Path::new("journal/2026/10/2026-10-16.md"));
+        assert_eq!(date_of(PATTERN, &path), Some(date));
+
+        assert_eq!(
+            date_of(PATTERN, Path::new("journal/2025/10/2026-10-16.md")),
+            None
+        );
+        assert_eq!(
+            date_of(PATTERN, Path::new("journal/2026/13/2026-13-16.md")),
+            None
+        );
+        assert_eq!(
+            date_of(PATTERN, Path::new("journal/2026/10/2026-10-16 copy.md")),
+            None
+        );
+        assert_eq!(
+            date_of("log/{YYYY-MM}.md", Path::new("log/2026-10.md")),
+            None
+        );
+    }
+
+    #[test]
+    fn neighbouring_daily_notes_are_found() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        for path in [
+            "journal/2026/09/2026-09-30.md",
+            "journal/2026/10/2026-10-01.md",
+            "journal/2026/10/2026-10-20.md",
+            "journal/2026/10/ideas.md",
+        ] {
+            fs::create_dir_all(root.join(path).parent().unwrap()).unwrap();
+            fs::write(root.join(path), "").unwrap();
+        }
+        let index = NoteIndex::scan(root);
+        let today = Date::from_days(20_742);
+
+        assert_eq!(
+            neighbour(PATTERN, &index, today, false),
+            Some(root.join("journal/2026/10/2026-10-01.md"))
+        );
+        assert_eq!(
+            neighbour(PATTERN, &index, today, true),
+            Some(root.join("journal/2026/10/2026-10-20.md"))
+        );
+        let last = date_of(PATTERN, Path::new("journal/2026/10/2026-10-20.md")).unwrap();
+        assert_eq!(neighbour(PATTERN, &index, last, true), None);
+    }
+}
@@ -0,0 +1,203 @@
+//! Dates typed in words after `@`, like `@tomorrow`, `@next monday` or `@in 3 days`, completed to
+//! the date they name, written the way `Config::date_format` says.
+//!
+//! Days are counted from today in UTC, like daily notes. A weekday on its own is the next one
+//! after today, `next` picks the one in the coming week, from Monday, and `last` the latest one
+//! before today.
+
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, Position, Range, TextEdit,
+};
+
+use crate::{daily_notes, new_notes::Date};
+
+const WEEKDAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+/// `date` moved by `months` months, on the same day or the last day of a shorter month.
+fn add_months(date: Date, months: i64) -> Date {
+    let index = date.year * 12 + i64::from(date.month) - 1 + months;
+    let (year, month) = (index.div_euclid(12), index.rem_euclid(12) as u32 + 1);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let length = match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => 31,
+    };
+    Date {
+        year,
+        month,
+        day: date.day.min(length),
+    }
+}
+
+/// `today` moved by `n` of `unit`, e.g. `weeks`.
+fn shift(today: Date, n: i64, unit: &str) -> Option<Date> {
+    let unit = unit.strip_suffix('s').unwrap_or(unit);
+    Some(match unit {
+        "day" => Date::from_days(today.days() + n),
+        "week" => Date::from_days(today.days() + 7 * n),
+        "month" => add_months(today, n),
+        "year" => add_months(today, 12 * n),
+        _ => return None,
+    })
+}
+
+/// The date `phrase` names, counting from `today`, or `None` if it doesn't name one.
+pub fn resolve(phrase: &str, today: Date) -> Option<Date> {
+    let phrase = phrase.to_lowercase();
+    let words = phrase.split_whitespace().collect::<Vec<_>>();
+    let weekday = |name: &str| WEEKDAYS.iter().position(|day| *day == name);
+    let days = today.days();
+    let this_weekday = i64::from(today.weekday());
+    Some(match words.as_slice() {
+        ["today"] => today,
+        ["tomorrow"] => Date::from_days(days + 1),
+        ["yesterday"] => Date::from_days(days - 1),
+        [name] => {
+            let ahead = (weekday(name)? as i64 - this_weekday - 1).rem_euclid(7) + 1;
+            Date::from_days(days + ahead)
+        }
+        ["next", "week" | "month" | "year"] => shift(today, 1, words[1])?,
+        ["last", "week" | "month" | "year"] => shift(today, -1, words[1])?,
+        ["next", name] => Date::from_days(days - this_weekday + 7 + weekday(name)? as i64),
+        ["last", name] => {
+            let back = (this_weekday - weekday(name)? as i64 - 1).rem_euclid(7) + 1;
+            Date::from_days(days - back)
+        }
+        ["in", n, unit] => shift(today, n.parse().ok()?, unit)?,
+        [n, unit, "ago"] => shift(today, -n.parse::<i64>().ok()?, unit)?,
+        _ => return None,
+    })
+}
+
+/// What's been typed after the `@` before `position` of `document`, and the range from the `@`
+/// to the cursor. The `@` starts the line or follows whitespace or a bracket, and only letters,
+/// digits and spaces follow it.
+fn typed_phrase(document: &str, position: Position) -> Option<(&str, Range)> {
+    let line = document.lines().nth(position.line as usize)?;
+    let before = line.get(..position.character as usize)?;
+    let at = before.rfind('@')?;
+    let typed = &before[at + 1..];
+    let starts = before[..at]
+        .chars()
+        .next_back()
+        .is_none_or(|c| c.is_whitespace() || matches!(c, '(' | '['));
+    let words = typed.chars().all(|c| c.is_alphanumeric() || c == ' ');
+    (starts && words && !typed.trim().is_empty() && !typed.starts_with(' ')).then(|| {
+        let range = Range::new(Position::new(position.line, at as u32), position);
+        (typed, range)
+    })
+}
+
+/// The phrases completed for `typed`: the fixed ones, and counts of days, weeks and months once
+/// a number is typed.
+fn phrases(typed: &str) -> Vec<String> {
+    let mut phrases = ["today", "tomorrow", "yesterday"]
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+    phrases.extend(WEEKDAYS.map(String::from));
+    for direction in ["next", "last"] {
+        phrases.extend(["week", "month", "year"].map(|unit| format!("{direction} {unit}")));
+        phrases.extend(WEEKDAYS.map(|day| format!("{direction} {day}")));
+    }
+    let number = typed
+        .split_whitespace()
+        .find_map(|word| word.parse::<u32>().ok());
+    if let Some(n) = number {
+        for unit in ["days", "weeks", "months"] {
+            phrases.push(format!("in {n} {unit}"));
+            phrases.push(format!("{n} {unit} ago"));
+        }
+    }
+    phrases
+}
+
+/// Completions for a date being typed in words at `position` of `document`: the phrases starting
+/// with what's been typed, replaced by the date they name in `format`.
+pub fn completions(
+    document: &str,
+    position: Position,
+    today: Date,
+    format: &str,
+) -> Vec<CompletionItem> {
+    let Some((typed, range)) = typed_phrase(document, position) else {
+        return Vec::new();
+    };
+    let typed = typed.to_lowercase();
+    phrases(&typed)
+        .into_iter()
+        .filter(|phrase| phrase.starts_with(&typed))
+        .filter_map(|phrase| {
+            let date = daily_notes::format(format, resolve(&phrase, today)?);
+            Some(CompletionItem {
+                label: format!("@{phrase}"),
+                kind: Some(CompletionItemKind::VALUE),
+                detail: Some(date.clone()),
+                filter_text: Some(format!("@{phrase}")),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(range, date))),
+                ..CompletionItem::default()
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phrases_name_dates() {
+        // A Friday.
+        let today = Date::from_days(20_742);
+        let resolved = |phrase| resolve(phrase, today).map(|date| date.to_string());
+        assert_eq!(resolved("today").unwrap(), "2026-10-16");
+        assert_eq!(resolved("Tomorrow").unwrap(), "2026-10-17");
+        assert_eq!(resolved("friday").unwrap(), "2026-10-23");
+        assert_eq!(resolved("monday").unwrap(), "2026-10-19");
+        assert_eq!(resolved("next monday").unwrap(), "2026-10-19");
+        assert_eq!(resolved("next friday").unwrap(), "2026-10-23");
+        assert_eq!(resolved("last friday").unwrap(), "2026-10-09");
+        assert_eq!(resolved("last thursday").unwrap(), "2026-10-15");
+        assert_eq!(resolved("in 3 days").unwrap(), "2026-10-19");
+        assert_eq!(resolved("2 weeks ago").unwrap(), "2026-10-02");
+        assert_eq!(resolved("next year").unwrap(), "2027-10-16");
+        assert_eq!(resolved("someday"), None);
+
+        let january = Date::from_days(Date::from_days(20_742).days() + 107);
+        assert_eq!(january.to_string(), "2027-01-31");
+        assert_eq!(add_months(january, 1).to_string(), "2027-02-28");
+        assert_eq!(add_months(january, -2).to_string(), "2026-11-30");
+    }
+
+    #[test]
+    fn dates_are_completed_after_at() {
+        let today = Date::from_days(20_742);
+        let document = "Due @next mo and mail me@to";
+        let items = completions(document, Position::new(0, 12), today, "{DD}.{MM}.{YYYY}");
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].label, "@next month");
+        assert_eq!(items[0].detail.as_deref(), Some("16.11.2026"));
+        let Some(CompletionTextEdit::Edit(edit)) = &items[1].text_edit else {
+            panic!("expected an edit");
+        };
+        assert_eq!(
+            edit.range,
+            Range::new(Position::new(0, 4), Position::new(0, 12))
+        );
+        assert_eq!(edit.new_text, "19.10.2026");
+
+        assert!(completions(document, Position::new(0, 27), today, "{YYYY-MM-DD}").is_empty());
+        let items = completions("@in 2", Position::new(0, 5), today, "{YYYY-MM-DD}");
+        assert_eq!(items[0].label, "@in 2 days");
+    }
+}
@@ -0,0 +1,658 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    path::Path,
+};
+
+use serde_json::json;
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, DocumentDiagnosticReportKind,
+    FullDocumentDiagnosticReport, Location, Position, Range, UnchangedDocumentDiagnosticReport,
+};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    config::NoteType,
+    frontmatter::Frontmatter,
+    headings::{self, Heading},
+    hover,
+    i18n::Messages,
+    index::{self, Note, NoteIndex},
+    links::{self, Link, LinkKind},
+    spelling::Misspelling,
+    uri,
+};
+
+/// Whether a wiki link points at a note with one of `extensions`, rather than e.g. an embedded
+/// image.
+pub fn links_to_note(link: &Link, extensions: &[String]) -> bool {
+    let target = Path::new(link.target_path());
+    target.extension().is_none() || index::has_extension(target, extensions)
+}
+
+/// The range of the text between a wiki link's brackets.
+fn link_text_range(link: &Link) -> Range {
+    let start = link.start + if link.embed { 3 } else { 2 };
+    Range::new(
+        Position::new(link.line, start as u32),
+        Position::new(link.line, (link.end - 2) as u32),
+    )
+}
+
+/// Flag wiki links in the note at `path` that don't resolve to a note in the vault.
+pub fn broken_links(
+    path: &Path,
+    links: &[Link],
+    index: &NoteIndex,
+    severity: DiagnosticSeverity,
+    messages: &Messages,
+) -> Vec<Diagnostic> {
+    links
+        .iter()
+        .filter(|link| link.kind == LinkKind::Wiki && links_to_note(link, index.extensions()))
+        .filter(|link| !link.target_path().is_empty())
+        .filter(|link| index.resolve(path, link).is_none())
+        .map(|link| Diagnostic {
+            range: link_text_range(link),
+            severity: Some(severity),
+            source: Some("note-ls".to_string()),
+            message: messages.get("broken-link", &[("target", link.target_path().into())]),
+            ..Diagnostic::default()
+        })
+        .collect()
+}
+
+/// Flag the note at `path` if no other note links to it, or if it links to no other note.
+pub fn orphan_problems(
+    path: &Path,
+    links: &[Link],
+    index: &NoteIndex,
+    messages: &Messages,
+) -> Vec<Diagnostic> {
+    let this = links::nfc_path(path);
+    let other = |target: &Path| links::nfc_path(target) != this;
+    let mut problems = Vec::new();
+    if !index.backlinks(path).iter().any(|(from, _)| other(from)) {
+        problems.push("orphan-note");
+    }
+    if !links
+        .iter()
+        .filter_map(|link| index.resolve(path, link))
+        .any(|target| other(&target))
+    {
+        problems.push("dead-end-note");
+    }
+    problems
+        .into_iter()
+        .map(|id| Diagnostic {
+            range: Range::default(),
+            severity: Some(DiagnosticSeverity::INFORMATION),
+            source: Some("note-ls".to_string()),
+            message: messages.get(id, &[]),
+            ..Diagnostic::default()
+        })
+        .collect()
+}
+
+/// The target `link` should have to match the case of the file it resolves to, if it doesn't.
+///
+/// Such links only resolve on case-insensitive file systems (the default on macOS and Windows)
+/// and break once the vault is synced to e.g. Linux.
+pub fn case_correction(path: &Path, link: &Link, index: &NoteIndex) -> Option<String> {
+    let resolved = index.resolve(path, link)?;
+    let target = link.decoded_path().nfc().collect::<String>();
+
+    // Respell the named files and folders from the end of the resolved path, leaving `..` alone.
+    let mut on_disk = resolved.components().rev();
+    let mut parts = target
+        .split('/')
+        .rev()
+        .enumerate()
+        .map(|(i, part)| match part {
+            "" | "." | ".." => Some(part.to_string()),
+            _ => {
+                let name = on_disk.next()?.as_os_str();
+                let name = if i == 0 && Path::new(part).extension().is_none() {
+                    Path::new(name).file_stem()?
+                } else {
+                    name
+                };
+                Some(name.to_string_lossy().nfc().collect())
+            }
+        })
+        .collect::<Option<Vec<String>>>()?;
+    parts.reverse();
+    let corrected = parts.join("/");
+
+    if corrected == target || corrected.to_lowercase() != target.to_lowercase() {
+        return None;
+    }
+    Some(match link.kind {
+        LinkKind::Wiki => corrected,
+        LinkKind::Markdown => links::encode_target(&corrected),
+    })
+}
+
+/// Flag links in the note at `path` whose case doesn't match the file they resolve to.
+pub fn case_mismatches(
+    path: &Path,
+    links: &[Link],
+    index: &NoteIndex,
+    messages: &Messages,
+) -> Vec<Diagnostic> {
+    links
+        .iter()
+        .filter_map(|link| {
+            let corrected = case_correction(path, link, index)?;
+            Some(Diagnostic {
+                range: link.range(),
+                severity: Some(DiagnosticSeverity::WARNING),
+                source: Some("note-ls".to_string()),
+                message: messages.get("link-case", &[("corrected", corrected.into())]),
+                ..Diagnostic::default()
+            })
+        })
+        .collect()
+}
+
+/// The alt text of an embedded image, if it has any. The alias of a wiki embed is its alt text
+/// unless it's a size, like `![[cat.png|300]]` or `![[cat.png|300x200]]`.
+pub fn alt_text(link: &Link) -> Option<&str> {
+    let text = match link.kind {
+        LinkKind::Markdown => link.text.as_deref()?,
+        LinkKind::Wiki => {
+            let (_, alias) = link.target.split_once('|')?;
+            let size = alias
+                .split('x')
+                .all(|part| !part.is_empty() && part.trim().bytes().all(|b| b.is_ascii_digit()));
+            if size {
+                return None;
+            }
+            alias
+        }
+    };
+    Some(text.trim()).filter(|text| !text.is_empty())
+}
+
+/// Whether `link` embeds an image without alt text.
+pub fn lacks_alt_text(link: &Link) -> bool {
+    link.embed && hover::is_image(link.target_path()) && alt_text(link).is_none()
+}
+
+/// Flag embedded images without alt text, which screen readers announce by their file name, if
+/// at all.
+pub fn missing_alt_text(links: &[Link], messages: &Messages) -> Vec<Diagnostic> {
+    links
+        .iter()
+        .filter(|link| lacks_alt_text(link))
+        .map(|link| Diagnostic {
+            range: Range::new(
+                Position::new(link.line, link.start as u32),
+                Position::new(link.line, link.end as u32),
+            ),
+            severity: Some(DiagnosticSeverity::WARNING),
+            source: Some("note-ls".to_string()),
+            message: messages.get("missing-alt-text", &[]),
+            ..Diagnostic::default()
+        })
+        .collect()
+}
+
+/// The headings that skip a level from the heading before them, like a `###` right after a `#`,
+/// with the level they should have.
+pub fn skipped_levels(headings: &[Heading]) -> impl Iterator<Item = (&Heading, u8)> {
+    headings
+        .windows(2)
+        .filter(|pair| pair[1].level > pair[0].level + 1)
+        .map(|pair| (&pair[1], pair[0].level + 1))
+}
+
+/// Flag headings that skip a level, which leaves a gap in the outline screen readers navigate
+/// by.
+pub fn skipped_heading_levels(headings: &[Heading], messages: &Messages) -> Vec<Diagnostic> {
+    skipped_levels(headings)
+        .map(|(heading, expected)| Diagnostic {
+            // The `#` markers, for headings that aren't indented.
+            range: Range::new(
+                Position::new(heading.line, 0),
+                Position::new(heading.line, u32::from(heading.level)),
+            ),
+            severity: Some(DiagnosticSeverity::WARNING),
+            source: Some("note-ls".to_string()),
+            message: messages.get(
+                "skipped-heading-level",
+                &[
+                    ("previous", (expected - 1).into()),
+                    ("level", heading.level.into()),
+                ],
+            ),
+            ..Diagnostic::default()
+        })
+        .collect()
+}
+
+/// Where `note` gets `name`, its title or one of its aliases: the `#` heading with it as its
+/// text, or else the opening `---` of the frontmatter.
+fn name_range(note: &Note, name: &str) -> Range {
+    let heading = note
+        .headings
+        .iter()
+        .find(|heading| heading.level == 1 && heading.text == name)
+        .filter(|_| note.frontmatter.title.is_none());
+    match heading {
+        Some(heading) => Range::new(
+            Position::new(heading.line, 0),
+            Position::new(heading.line, 1),
+        ),
+        None => Range::new(Position::new(0, 0), Position::new(0, 3)),
+    }
+}
+
+/// Warn that `note`, at `path`, has the title or an alias of other notes in `index`, which makes
+/// `[[name]]` links ambiguous. Each warning points at the other note.
+pub fn duplicate_names(
+    path: &Path,
+    note: &Note,
+    index: &NoteIndex,
+    messages: &Messages,
+) -> Vec<Diagnostic> {
+    index
+        .namesakes(path)
+        .into_iter()
+        .map(|(name, other)| {
+            let related = uri::from_path(&other).map(|other_uri| {
+                let range = index
+                    .get(&other)
+                    .map_or_else(Range::default, |other| name_range(other, &name));
+                vec![DiagnosticRelatedInformation {
+                    location: Location::new(other_uri, range),
+                    message: messages.get("duplicate-name-other", &[]),
+                }]
+            });
+            Diagnostic {
+                range: name_range(note, &name),
+                severity: Some(DiagnosticSeverity::WARNING),
+                source: Some("note-ls".to_string()),
+                message: messages.get("duplicate-name", &[("name", name.into())]),
+                related_information: related,
+                ..Diagnostic::default()
+            }
+        })
+        .collect()
+}
+
+/// Warn about headings of the note at `path` with the same anchor as an earlier heading, which
+/// links to the heading can't tell apart. Each warning points at the first of them.
+pub fn duplicate_headings(
+    path: &Path,
+    headings: &[Heading],
+    messages: &Messages,
+) -> Vec<Diagnostic> {
+    let uri = uri::from_path(path);
+    let slugs = headings
+        .iter()
+        .map(|heading| headings::slugify(&heading.text))
+        .collect::<Vec<_>>();
+    let marker = |heading: &Heading| {
+        Range::new(
+            Position::new(heading.line, 0),
+            Position::new(heading.line, u32::from(heading.level)),
+        )
+    };
+    headings
+        .iter()
+        .enumerate()
+        .filter_map(|(i, heading)| {
+            let first = slugs[..i].iter().position(|slug| *slug == slugs[i])?;
+            let related = uri.clone().map(|uri| {
+                vec![DiagnosticRelatedInformation {
+                    location: Location::new(uri, marker(&headings[first])),
+                    message: messages.get("duplicate-heading-first", &[]),
+                }]
+            });
+            Some(Diagnostic {
+                range: marker(heading),
+                severity: Some(DiagnosticSeverity::WARNING),
+                source: Some("note-ls".to_string()),
+                message: messages.get("duplicate-heading", &[("anchor", slugs[i].clone().into())]),
+                related_information: related,
+                ..Diagnostic::default()
+            })
+        })
+        .collect()
+}
+
+/// Warn about external links that were found to be broken, `broken` telling why a URL is.
+pub fn broken_urls(
+    links: &[Link],
+    broken: impl Fn(&str) -> Option<String>,
+    messages: &Messages,
+) -> Vec<Diagnostic> {
+    links
+        .iter()
+        .filter_map(|link| {
+            let problem = broken(&link.target)?;
+            Some(Diagnostic {
+                range: Range::new(
+                    Position::new(link.line, link.start as u32),
+                    Position::new(link.line, link.end as u32),
+                ),
+                severity: Some(DiagnosticSeverity::WARNING),
+                source: Some("note-ls".to_string()),
+                message: messages.get(
+                    "broken-url",
+                    &[
+                        ("url", link.target.as_str().into()),
+                        ("problem", problem.into()),
+                    ],
+                ),
+                ..Diagnostic::default()
+            })
+        })
+        .collect()
+}
+
+/// Hint at words the dictionaries don't know. The word is kept in the diagnostic's data for the
+/// code action adding it to the vault's dictionary.
+pub fn unknown_words(misspellings: &[Misspelling], messages: &Messages) -> Vec<Diagnostic> {
+    misspellings
+        .iter()
+        .map(|misspelling| Diagnostic {
+            range: Range::new(
+                Position::new(misspelling.line, misspelling.start as u32),
+                Position::new(misspelling.line, misspelling.end as u32),
+            ),
+            severity: Some(DiagnosticSeverity::HINT),
+            source: Some("note-ls".to_string()),
+            message: messages.get("unknown-word", &[("word", misspelling.word.clone().into())]),
+            data: Some(json!({ "unknownWord": misspelling.word })),
+            ..Diagnostic::default()
+        })
+        .collect()
+}
+
+/// The word an `unknown_words` diagnostic is about.
+pub fn unknown_word(diagnostic: &Diagnostic) -> Option<&str> {
+    diagnostic.data.as_ref()?.get("unknownWord")?.as_str()
+}
+
+/// Flag a note whose frontmatter declares a `type` that isn't one of `types`, or that lacks
+/// fields its type requires. Notes without a `type` aren't checked.
+pub fn note_type_problems(
+    frontmatter: &Frontmatter,
+    types: &[NoteType],
+    messages: &Messages,
+) -> Vec<Diagnostic> {
+    let Some(name) = &frontmatter.note_type else {
+        return Vec::new();
+    };
+    if types.is_empty() {
+        return Vec::new();
+    }
+    let diagnostic = |message| Diagnostic {
+        // The opening `---` of the frontmatter.
+        range: Range::new(Position::new(0, 0), Position::new(0, 3)),
+        severity: Some(DiagnosticSeverity::WARNING),
+        source: Some("note-ls".to_string()),
+        message,
+        ..Diagnostic::default()
+    };
+
+    let Some(note_type) = types.iter().find(|note_type| note_type.name == *name) else {
+        let known = types
+            .iter()
+            .map(|note_type| note_type.name.as_str())
+            .collect::<Vec<_>>();
+        return vec![diagnostic(messages.get(
+            "unknown-note-type",
+            &[
+                ("name", name.as_str().into()),
+                ("expected", known.join(", ").into()),
+            ],
+        ))];
+    };
+    note_type
+        .required
+        .iter()
+        .filter(|field| !frontmatter.fields.contains(field))
+        .map(|field| {
+            diagnostic(messages.get(
+                "missing-note-field",
+                &[
+                    ("name", name.as_str().into()),
+                    ("field", field.as_str().into()),
+                ],
+            ))
+        })
+        .collect()
+}
+
+/// The result ID of `diagnostics` for pull diagnostics, the same for the same diagnostics.
+pub fn result_id(diagnostics: &[Diagnostic]) -> String {
+    let mut hasher = DefaultHasher::new();
+    serde_json::to_string(diagnostics)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// The pull diagnostics report of `diagnostics`: just their result ID if the client already has
+/// them as `previous_result_id`, so they aren't sent again.
+pub fn report(
+    diagnostics: Vec<Diagnostic>,
+    previous_result_id: Option<&str>,
+) -> DocumentDiagnosticReportKind {
+    let result_id = result_id(&diagnostics);
+    if previous_result_id == Some(result_id.as_str()) {
+        return DocumentDiagnosticReportKind::Unchanged(UnchangedDocumentDiagnosticReport {
+            result_id,
+        });
+    }
+    DocumentDiagnosticReportKind::Full(FullDocumentDiagnosticReport {
+        result_id: Some(result_id),
+        items: diagnostics,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::links;
+
+    #[test]
+    fn only_missing_notes_are_flagged() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::write(root.join("exists.md"), "").unwrap();
+        let index = crate::index::NoteIndex::scan(root);
+
+        let links = links::parse_links(
+            "[[exists]] [[missing]] ![[image.png]] [[#heading]] [[exists|Gone]]",
+        );
+        let diagnostics = broken_links(
+            &root.join("note.md"),
+            &links,
+            &index,
+            DiagnosticSeverity::WARNING,
+            &Messages::default(),
+        );
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "No note named \"missing\"");
+        assert_eq!(
+            diagnostics[0].range,
+            Range::new(Position::new(0, 13), Position::new(0, 20))
+        );
+    }
+
+    #[test]
+    fn case_mismatches_are_corrected() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::create_dir(root.join("Projects")).unwrap();
+        fs::write(root.join("Projects/Big Plan.md"), "").unwrap();
+        let index = crate::index::NoteIndex::scan(root);
+
+        let note = root.join("note.md");
+        let correction = |source: &str| {
+            let link = &links::parse_line(source, 0)[0];
+            case_correction(&note, link, &index)
+        };
+        assert_eq!(
+            correction("[[projects/big plan#Goals]]").as_deref(),
+            Some("Projects/Big Plan")
+        );
+        assert_eq!(
+            correction("[x](./projects/big%20plan.MD)").as_deref(),
+            Some("./Projects/Big%20Plan.md")
+        );
+        assert_eq!(correction("[[Projects/Big Plan]]"), None);
+    }
+
+    #[test]
+    fn notes_cut_off_from_the_vault_are_flagged() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::write(root.join("a.md"), "[[b]] [[a]]").unwrap();
+        fs::write(root.join("b.md"), "[[b]]").unwrap();
+        let index = crate::index::NoteIndex::scan(root);
+        let messages = |name: &str| {
+            let path = root.join(name);
+            let links = &index.get(&path).unwrap().links;
+            orphan_problems(&path, links, &index, &Messages::default())
+                .into_iter()
+                .map(|diagnostic| diagnostic.message)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(messages("a.md"), vec!["No other note links here"]);
+        assert_eq!(messages("b.md"), vec!["This note links to no other note"]);
+    }
+
+    #[test]
+    fn images_need_alt_text() {
+        let links = links::parse_links(
+            "![](cat.png) ![A cat](cat.png) ![[cat.png]] ![[cat.png|A cat]]\n\
+             ![[cat.png|300x200]] ![ ](cat.JPG) ![[notes]] [](cat.png) ![](doc.pdf)",
+        );
+        let diagnostics = missing_alt_text(&links, &Messages::default());
+
+        let ranges = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.range)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranges,
+            vec![
+                Range::new(Position::new(0, 0), Position::new(0, 12)),
+                Range::new(Position::new(0, 31), Position::new(0, 43)),
+                Range::new(Position::new(1, 0), Position::new(1, 20)),
+                Range::new(Position::new(1, 21), Position::new(1, 34)),
+            ]
+        );
+        assert_eq!(diagnostics[0].message, "Image has no alt text");
+    }
+
+    #[test]
+    fn headings_that_skip_a_level_are_flagged() {
+        let headings = crate::headings::parse_headings(
+            "## Start
+#### Deep
+### Back
+# Top
+### Skip",
+        );
+        let diagnostics = skipped_heading_levels(&headings, &Messages::default());
+
+        let found = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.range.start.line, diagnostic.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                (1, "Heading jumps from level 2 to level 4"),
+                (4, "Heading jumps from level 1 to level 3"),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicates_point_at_each_other() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::write(root.join("a.md"), "# Tea\n## Green\n## green!\n## Black").unwrap();
+        fs::write(root.join("b.md"), "---\naliases: [tea]\n---\n").unwrap();
+        let index = crate::index::NoteIndex::scan(root);
+        let note = index.get(&root.join("a.md")).unwrap();
+
+        let names = duplicate_names(&root.join("a.md"), note, &index, &Messages::default());
+        assert_eq!(names.len(), 1);
+        assert_eq!(
+            names[0].message,
+            "Another note is also called \"Tea\", so links by that name are ambiguous"
+        );
+        assert_eq!(names[0].range.start.line, 0);
+        let related = &names[0].related_information.as_ref().unwrap()[0];
+        assert_eq!(
+            related.location.uri,
+            uri::from_path(&root.join("b.md")).unwrap()
+        );
+
+        let headings = duplicate_headings(&root.join("a.md"), &note.headings, &Messages::default());
+        assert_eq!(headings.len(), 1);
+        assert_eq!(headings[0].range.start.line, 2);
+        let related = &headings[0].related_information.as_ref().unwrap()[0];
+        assert_eq!(related.location.range.start.line, 1);
+    }
+
+    #[test]
+    fn unchanged_diagnostics_are_not_sent_again() {
+        let diagnostic = Diagnostic {
+            message: String::from("No note named \"missing\""),
+            ..Diagnostic::default()
+        };
+        let DocumentDiagnosticReportKind::Full(full) = report(vec![diagnostic.clone()], None)
+        else {
+            panic!("the first report should be full");
+        };
+        let result_id = full.result_id.unwrap();
+        assert!(matches!(
+            report(vec![diagnostic.clone()], Some(&result_id)),
+            DocumentDiagnosticReportKind::Unchanged(_)
+        ));
+        assert!(matches!(
+            report(Vec::new(), Some(&result_id)),
+            DocumentDiagnosticReportKind::Full(_)
+        ));
+    }
+
+    #[test]
+    fn notes_are_checked_against_their_type() {
+        let types = [NoteType {
+            name: String::from("book"),
+            required: vec![String::from("author"), String::from("title")],
+            ..NoteType::default()
+        }];
+        let messages = |document: &str| {
+            let frontmatter = crate::frontmatter::parse(document).unwrap_or_default();
+            note_type_problems(&frontmatter, &types, &Messages::default())
+                .into_iter()
+                .map(|diagnostic| diagnostic.message)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            messages("---\ntype: book\ntitle: Dune\nauthor:\n---\n"),
+            vec!["Notes of type \"book\" require the \"author\" field"]
+        );
+        assert!(messages("---\ntype: book\ntitle: Dune\nauthor: Herbert\n---\n").is_empty());
+        assert_eq!(
+            messages("---\ntype: film\n---\n"),
+            vec!["Unknown note type \"film\", expected one of: book"]
+        );
+        assert!(messages("# No type").is_empty());
+    }
+}
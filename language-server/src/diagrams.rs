@@ -0,0 +1,216 @@
+//! Diagrams drawn by external programs for the preview, like Graphviz for ```` ```dot ```` blocks
+//! and PlantUML for ```` ```plantuml ```` blocks.
+//!
+//! The programs `Config::diagram_tools` names read a block's source on stdin and write SVG on
+//! stdout, which replaces the block in the preview. Drawings are kept by a hash of the tool and
+//! the source, so typing elsewhere in a note doesn't draw its diagrams again. Blocks a tool fails
+//! on are shown as code.
+
+use std::{collections::HashMap, io, path::Path, process::Stdio, time::Duration};
+
+use sha2::{Digest, Sha256};
+use tokio::{io::AsyncWriteExt, process::Command, sync::Mutex};
+use tower_lsp::{lsp_types::MessageType, Client};
+
+use crate::{code_blocks, config::DiagramTool, logging};
+
+/// How long a tool may take to draw a diagram.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most drawings kept. The cache is emptied once it's full.
+const MAX_CACHED: usize = 256;
+
+#[derive(Debug, Default)]
+struct State {
+    tools: Vec<DiagramTool>,
+    /// The SVG of each diagram, or `None` if its tool failed, by the hash of its tool and source.
+    drawings: HashMap<[u8; 32], Option<String>>,
+}
+
+/// The diagram tools and the diagrams they drew, shared by everything rendering the preview.
+#[derive(Debug)]
+pub struct Diagrams {
+    client: Client,
+    state: Mutex<State>,
+}
+
+impl Diagrams {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Draw diagrams with `tools` from now on, forgetting the drawings if they changed.
+    pub async fn set_tools(&self, tools: &[DiagramTool]) {
+        let mut state = self.state.lock().await;
+        if state.tools != tools {
+            state.tools = tools.to_vec();
+            state.drawings.clear();
+        }
+    }
+
+    /// `markdown`, read from the folder `dir`, with its diagrams drawn. Tools that fail are
+    /// logged, once for each diagram.
+    pub async fn render(&self, markdown: &str, dir: Option<&Path>) -> String {
+        let mut state = self.state.lock().await;
+        if state.tools.is_empty() {
+            return markdown.to_string();
+        }
+        let mut svgs = HashMap::new();
+        for block in code_blocks::blocks(markdown) {
+            let Some(tool) = state
+                .tools
+                .iter()
+                .find(|tool| tool.language == block.language)
+                .cloned()
+            else {
+                continue;
+            };
+            let hash = hash(&tool, &block.code);
+            let svg = match state.drawings.get(&hash) {
+                Some(svg) => svg.clone(),
+                None => {
+                    let drawn = match draw(&tool, &block.code, dir).await {
+                        Ok(svg) => Some(svg),
+                        Err(e) => {
+                            let message =
+                                format!("Could not draw a {} diagram: {e}", tool.language);
+                            logging::log(&self.client, MessageType::ERROR, message).await;
+                            None
+                        }
+                    };
+                    if state.drawings.len() >= MAX_CACHED {
+                        state.drawings.clear();
+                    }
+                    state.drawings.insert(hash, drawn.clone());
+                    drawn
+                }
+            };
+            if let Some(svg) = svg {
+                svgs.insert(block.start, (block.end, svg));
+            }
+        }
+        replace_blocks(markdown, &svgs)
+    }
+}
+
+fn hash(tool: &DiagramTool, source: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(tool.command.as_bytes());
+    for arg in &tool.args {
+        hasher.update([0]);
+        hasher.update(arg.as_bytes());
+    }
+    hasher.update([0]);
+    hasher.update(source.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Draw the diagram `source` with `tool`, in `dir` if it's given, returning the SVG.
+pub async fn draw(tool: &DiagramTool, source: &str, dir: Option<&Path>) -> io::Result<String> {
+    let mut command = Command::new(&tool.command);
+    command
+        .args(&tool.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    let mut child = command.spawn()?;
+    let mut stdin = child.stdin.take().ok_or(io::ErrorKind::BrokenPipe)?;
+    let source = source.to_string();
+    // Write while the tool writes its output, so neither waits on a full pipe.
+    tokio::spawn(async move {
+        let _ = stdin.write_all(source.as_bytes()).await;
+    });
+    let output = tokio::time::timeout(TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "timed out"))??;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "{} failed: {}",
+            tool.command,
+            error.trim()
+        )));
+    }
+    let svg = String::from_utf8_lossy(&output.stdout);
+    inline_svg(&svg).ok_or_else(|| io::Error::other(format!("{} wrote no SVG", tool.command)))
+}
+
+/// `svg` without the XML declaration and doctype before the `<svg>` element, on one line so it
+/// stays a single HTML block in markdown.
+fn inline_svg(svg: &str) -> Option<String> {
+    let start = svg.find("<svg")?;
+    let end = svg.rfind("</svg>")? + "</svg>".len();
+    let svg = svg.get(start..end)?;
+    Some(svg.lines().map(str::trim).collect::<Vec<_>>().join(" "))
+}
+
+/// `markdown` with the blocks starting on the lines of `svgs` replaced by their drawing and
+/// ending on the line given with it.
+///
+/// Like habit trackers, every line of a block stays on a line of its own, so the lines of the rest
+/// of the note still match the source when the preview scrolls to one.
+fn replace_blocks(markdown: &str, svgs: &HashMap<u32, (u32, String)>) -> String {
+    if svgs.is_empty() {
+        return markdown.to_string();
+    }
+    let mut lines = markdown.lines().map(str::to_string).collect::<Vec<_>>();
+    for (start, (end, svg)) in svgs {
+        for line in &mut lines[*start as usize..=*end as usize] {
+            // Blank lines would end the HTML block.
+            *line = String::from("<!-- -->");
+        }
+        lines[*start as usize] = format!("<div class=\"diagram\">{svg}</div>");
+    }
+    let mut replaced = lines.join("\n");
+    if markdown.ends_with('\n') {
+        replaced.push('\n');
+    }
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagrams_replace_their_blocks() {
+        let svg = "<?xml version=\"1.0\"?>\n<!DOCTYPE svg>\n<svg width=\"10\">\n  <g/>\n</svg>\n";
+        let svg = inline_svg(svg).unwrap();
+        assert_eq!(svg, "<svg width=\"10\"> <g/> </svg>");
+        assert_eq!(inline_svg("Error: syntax"), None);
+
+        let markdown = "# Flow\n```dot\ndigraph { a -> b }\n```\nAfter\n";
+        let svgs = HashMap::from([(1, (3, svg))]);
+        assert_eq!(
+            replace_blocks(markdown, &svgs),
+            "# Flow\n<div class=\"diagram\"><svg width=\"10\"> <g/> </svg></div>\n<!-- -->\n\
+             <!-- -->\nAfter\n"
+        );
+    }
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn tools_read_the_source_and_write_svg() {
+        let tool = |script: &str| DiagramTool {
+            language: String::from("dot"),
+            command: String::from("sh"),
+            args: vec![String::from("-c"), script.to_string()],
+        };
+        let svg = draw(&tool("printf '<svg>'; cat; echo '</svg>'"), "a -> b", None)
+            .await
+            .unwrap();
+        assert_eq!(svg, "<svg>a -> b</svg>");
+
+        let error = draw(&tool("echo 'syntax error' >&2; exit 1"), "a ->", None)
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "sh failed: syntax error");
+    }
+}
@@ -0,0 +1,175 @@
+//! Clickable links for `textDocument/documentLink`.
+//!
+//! External links and bare URLs get their targets right away. Links to notes and attachments are
+//! only resolved against the index when the editor asks, with `documentLink/resolve`.
+
+use std::path::Path;
+
+use serde_json::json;
+use tower_lsp::lsp_types::{DocumentLink, Position, Range, Url};
+
+use crate::{
+    index::NoteIndex,
+    links::{self, Link},
+    uri,
+};
+
+/// Schemes of URLs recognized outside of links.
+const SCHEMES: [&str; 2] = ["https://", "http://"];
+
+/// Byte ranges of the bare URLs on `line`, outside of any of its `links`.
+fn bare_urls(line: &str, links: &[Link]) -> Vec<(usize, usize)> {
+    let mut urls = Vec::new();
+    let mut i = 0;
+    while let Some(found) = SCHEMES
+        .iter()
+        .filter_map(|scheme| line[i..].find(scheme))
+        .min()
+    {
+        let start = i + found;
+        let rest = &line[start..];
+        let mut len = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"' | '`'))
+            .unwrap_or(rest.len());
+        // Punctuation ending a sentence isn't part of the URL, and neither is the `)` closing
+        // parentheses around it.
+        loop {
+            let url = &rest[..len];
+            let unbalanced =
+                url.ends_with(')') && url.matches('(').count() < url.matches(')').count();
+            if url.ends_with(['.', ',', ';', ':', '!', '?', '\'', '*', '_']) || unbalanced {
+                len -= 1;
+            } else {
+                break;
+            }
+        }
+        let end = start + len;
+        let has_host = !rest[..len].ends_with("//");
+        if has_host
+            && !links
+                .iter()
+                .any(|link| link.start <= start && start < link.end)
+        {
+            urls.push((start, end));
+        }
+        i = end.max(start + 1);
+    }
+    urls
+}
+
+/// The links in `document`, the note at `uri`. Links within the vault are left for `resolve`.
+pub fn document_links(uri: &Url, document: &str) -> Vec<DocumentLink> {
+    let mut found = Vec::new();
+    for (n, line) in document.lines().enumerate() {
+        let line_links = links::parse_line(line, n as u32);
+        for link in &line_links {
+            let target = if link.is_external() {
+                match Url::parse(link.target_path()) {
+                    Ok(target) => Some(target),
+                    Err(_) => continue,
+                }
+            } else {
+                None
+            };
+            found.push(DocumentLink {
+                range: link.range(),
+                data: target.is_none().then(|| json!({ "uri": uri })),
+                target,
+                tooltip: None,
+            });
+        }
+        for (start, end) in bare_urls(line, &line_links) {
+            let Ok(target) = Url::parse(&line[start..end]) else {
+                continue;
+            };
+            found.push(DocumentLink {
+                range: Range::new(
+                    Position::new(n as u32, start as u32),
+                    Position::new(n as u32, end as u32),
+                ),
+                target: Some(target),
+                tooltip: None,
+                data: None,
+            });
+        }
+    }
+    found
+}
+
+/// The note a document link was found in, as recorded by `document_links`.
+pub fn source(link: &DocumentLink) -> Option<Url> {
+    let uri = link.data.as_ref()?.get("uri")?.as_str()?;
+    Url::parse(uri).ok()
+}
+
+/// The file `link` in the note at `path` points to, or `None` if nothing in the vault matches.
+///
+/// Links to headings point at the heading's line, with a `#L` fragment.
+pub fn resolve(link: &Link, path: &Path, index: &NoteIndex) -> Option<Url> {
+    // Links like `[[#Heading]]` point into the current note.
+    let target = if link.target_path().is_empty() {
+        Some(path.to_path_buf())
+    } else {
+        index.resolve(path, link)
+    };
+    let Some(target) = target else {
+        // Attachments aren't indexed, but exist on disk.
+        let note_dir = path.parent().unwrap_or(index.root());
+        let file = link
+            .candidates(note_dir, index.root())
+            .into_iter()
+            .find(|candidate| candidate.is_file())?;
+        return uri::from_path(&file);
+    };
+
+    let mut target_uri = uri::from_path(&target)?;
+    if let Some(line) = index.anchor_line(&target, link) {
+        target_uri.set_fragment(Some(&format!("L{}", line + 1)));
+    }
+    Some(target_uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(line: &str) -> Vec<&str> {
+        bare_urls(line, &links::parse_line(line, 0))
+            .into_iter()
+            .map(|(start, end)| &line[start..end])
+            .collect()
+    }
+
+    #[test]
+    fn bare_urls_outside_links() {
+        assert_eq!(
+            urls("See https://example.com/a_(b), or (http://example.org)."),
+            vec!["https://example.com/a_(b)", "http://example.org"]
+        );
+        assert_eq!(
+            urls("[site](https://example.com) <https://x.io>"),
+            vec!["https://x.io"]
+        );
+        assert!(urls("no scheme: example.com, no host: https://").is_empty());
+    }
+
+    #[test]
+    fn internal_links_are_resolved_later() {
+        let uri = Url::parse("file:///vault/note.md").unwrap();
+        let document = "[[other#Heading]] [web](https://example.com)\nhttps://example.org";
+        let found = document_links(&uri, document);
+
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].target, None);
+        assert_eq!(source(&found[0]), Some(uri));
+        assert_eq!(
+            found[1].target.as_ref().unwrap().as_str(),
+            "https://example.com/"
+        );
+        assert_eq!(found[2].range.start, Position::new(1, 0));
+        assert_eq!(
+            found[2].target.as_ref().unwrap().as_str(),
+            "https://example.org/"
+        );
+    }
+}
@@ -0,0 +1,364 @@
+//! Converting positions between the server and the editor.
+//!
+//! The server counts the characters of a position in bytes of UTF-8 everywhere, so it can slice
+//! lines with them. Editors count them in code units of the encoding agreed on when the server
+//! is initialized: UTF-8 if the client offers it, or else UTF-16, the one every client supports.
+//! Positions are converted where they come in from the editor and where they go out to it, so
+//! notes with emoji, CJK or accented characters get the right ranges.
+
+use std::collections::HashMap;
+
+use tower_lsp::lsp_types::{
+    ClientCapabilities, DocumentChangeOperation, DocumentChanges, OneOf, Position,
+    PositionEncodingKind, Range, TextDocumentContentChangeEvent, Url, WorkspaceEdit,
+};
+
+/// How the editor counts the characters of a position.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// In bytes, like the server.
+    Utf8,
+    /// In UTF-16 code units, the default of the protocol.
+    #[default]
+    Utf16,
+}
+
+impl Encoding {
+    /// The encoding to use with a client with `capabilities`: UTF-8 if it offers it, as nothing
+    /// has to be converted then, or else UTF-16.
+    pub fn negotiate(capabilities: &ClientCapabilities) -> Self {
+        let utf8 = capabilities
+            .general
+            .as_ref()
+            .and_then(|general| general.position_encodings.as_ref())
+            .is_some_and(|offered| offered.contains(&PositionEncodingKind::UTF8));
+        match utf8 {
+            true => Self::Utf8,
+            false => Self::Utf16,
+        }
+    }
+
+    /// The encoding as advertised in the server capabilities.
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            Self::Utf8 => PositionEncodingKind::UTF8,
+            Self::Utf16 => PositionEncodingKind::UTF16,
+        }
+    }
+
+    /// The code units `c` takes up.
+    fn len(self, c: char) -> usize {
+        match self {
+            Self::Utf8 => c.len_utf8(),
+            Self::Utf16 => c.len_utf16(),
+        }
+    }
+
+    /// The byte offset in `line` of the editor's `character`. Characters past the end of the
+    /// line are clamped to it, and ones in the middle of a character moved to its start.
+    pub fn to_byte(self, line: &str, character: u32) -> usize {
+        let character = character as usize;
+        let mut units = 0;
+        for (byte, c) in line.char_indices() {
+            units += self.len(c);
+            if units > character {
+                return byte;
+            }
+        }
+        line.len()
+    }
+
+    /// The editor's character for the byte offset `byte` in `line`. Offsets past the end of the
+    /// line are clamped to it, and ones in the middle of a character moved to its start.
+    pub fn from_byte(self, line: &str, byte: usize) -> u32 {
+        if self == Self::Utf8 || line.is_ascii() {
+            return byte.min(line.len()) as u32;
+        }
+        line.char_indices()
+            .take_while(|(start, c)| start + c.len_utf8() <= byte)
+            .map(|(_, c)| self.len(c))
+            .sum::<usize>() as u32
+    }
+
+    /// `position` of `document`, from the editor, counted in bytes.
+    pub fn to_server(self, document: &str, position: Position) -> Position {
+        if self == Self::Utf8 {
+            return position;
+        }
+        let line = line(document, position.line);
+        Position::new(position.line, self.to_byte(line, position.character) as u32)
+    }
+
+    /// `position` of `document`, counted in bytes, for the editor.
+    pub fn to_client(self, document: &str, position: Position) -> Position {
+        if self == Self::Utf8 {
+            return position;
+        }
+        let line = line(document, position.line);
+        Position::new(
+            position.line,
+            self.from_byte(line, position.character as usize),
+        )
+    }
+
+    pub fn range_to_server(self, document: &str, range: Range) -> Range {
+        Range::new(
+            self.to_server(document, range.start),
+            self.to_server(document, range.end),
+        )
+    }
+
+    pub fn range_to_client(self, document: &str, range: Range) -> Range {
+        Range::new(
+            self.to_client(document, range.start),
+            self.to_client(document, range.end),
+        )
+    }
+
+    /// Convert the ranges of the edits `edit` makes to the documents in `documents`, by their
+    /// URI, for the editor. Edits of other documents, like ones the edit creates, are left as
+    /// they are.
+    pub fn edit_to_client(self, documents: &HashMap<Url, String>, edit: &mut WorkspaceEdit) {
+        let convert = |uri: &Url, range: &mut Range| {
+            if let Some(document) = documents.get(uri) {
+                *range = self.range_to_client(document, *range);
+            }
+        };
+        for (uri, edits) in edit.changes.iter_mut().flatten() {
+            for edit in edits {
+                convert(uri, &mut edit.range);
+            }
+        }
+        let document_edits = match &mut edit.document_changes {
+            Some(DocumentChanges::Edits(edits)) => edits.iter_mut().collect(),
+            Some(DocumentChanges::Operations(operations)) => operations
+                .iter_mut()
+                .filter_map(|operation| match operation {
+                    DocumentChangeOperation::Edit(edit) => Some(edit),
+                    DocumentChangeOperation::Op(_) => None,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        for document_edit in document_edits {
+            for edit in &mut document_edit.edits {
+                let range = match edit {
+                    OneOf::Left(edit) => &mut edit.range,
+                    OneOf::Right(edit) => &mut edit.text_edit.range,
+                };
+                convert(&document_edit.text_document.uri, range);
+            }
+        }
+    }
+
+    /// Apply the edits of `changes` to `document` in turn, as the editor sent them. A change
+    /// without a range replaces the whole document.
+    pub fn apply_changes(
+        self,
+        document: &mut String,
+        changes: Vec<TextDocumentContentChangeEvent>,
+    ) {
+        for change in changes {
+            match change.range {
+                Some(range) => {
+                    let start = self.offset(document, range.start);
+                    let end = self.offset(document, range.end).max(start);
+                    document.replace_range(start..end, &change.text);
+                }
+                None => *document = change.text,
+            }
+        }
+    }
+
+    /// The byte offset in `document` of the editor's `position`. Positions past the end of a line
+    /// are clamped to it, and ones past the end of the document to that.
+    pub fn offset(self, document: &str, position: Position) -> usize {
+        let mut offset = 0;
+        for (n, line) in document.split_inclusive('\n').enumerate() {
+            if n == position.line as usize {
+                let text = line.trim_end_matches('\n').trim_end_matches('\r');
+                return offset + self.to_byte(text, position.character);
+            }
+            offset += line.len();
+        }
+        document.len()
+    }
+
+    /// The editor's position of the byte offset `offset` in `document`, the inverse of `offset`.
+    pub fn position(self, document: &str, offset: usize) -> Position {
+        let before = &document[..offset];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        let line = document[line_start..]
+            .split('\n')
+            .next()
+            .unwrap_or_default();
+        Position::new(
+            before.matches('\n').count() as u32,
+            self.from_byte(line, offset - line_start),
+        )
+    }
+}
+
+/// Line `n` of `document`, or an empty line past its end.
+fn line(document: &str, n: u32) -> &str {
+    document.lines().nth(n as usize).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use tower_lsp::lsp_types::{GeneralClientCapabilities, TextEdit};
+
+    use super::*;
+
+    #[test]
+    fn offsets_and_positions_are_inverse() {
+        let document = "ab\nc😀d\n";
+        assert_eq!(Encoding::Utf8.offset("ab\ncd", Position::new(1, 9)), 5);
+        assert_eq!(Encoding::Utf16.offset(document, Position::new(1, 3)), 8);
+        assert_eq!(Encoding::Utf8.position(document, 8), Position::new(1, 5));
+        assert_eq!(Encoding::Utf16.position(document, 8), Position::new(1, 3));
+    }
+
+    #[test]
+    fn encodings_are_negotiated() {
+        let offering = |kinds: Vec<PositionEncodingKind>| ClientCapabilities {
+            general: Some(GeneralClientCapabilities {
+                position_encodings: Some(kinds),
+                ..GeneralClientCapabilities::default()
+            }),
+            ..ClientCapabilities::default()
+        };
+        assert_eq!(
+            Encoding::negotiate(&ClientCapabilities::default()),
+            Encoding::Utf16
+        );
+        let utf8 = offering(vec![
+            PositionEncodingKind::UTF16,
+            PositionEncodingKind::UTF8,
+        ]);
+        assert_eq!(Encoding::negotiate(&utf8), Encoding::Utf8);
+        let utf32 = offering(vec![PositionEncodingKind::UTF32]);
+        assert_eq!(Encoding::negotiate(&utf32), Encoding::Utf16);
+    }
+
+    #[test]
+    fn positions_are_converted() {
+        // "é" takes two bytes and one UTF-16 unit, "😀" four bytes and two UTF-16 units.
+        let document = "# Café 😀 [[link]]\n漢字 #tag";
+        let utf16 = Encoding::Utf16;
+        assert_eq!(
+            utf16.to_server(document, Position::new(0, 10)),
+            Position::new(0, 13)
+        );
+        assert_eq!(
+            utf16.to_client(document, Position::new(0, 13)),
+            Position::new(0, 10)
+        );
+        assert_eq!(
+            utf16.to_server(document, Position::new(1, 3)),
+            Position::new(1, 7)
+        );
+        assert_eq!(
+            Encoding::Utf8.to_server(document, Position::new(0, 13)),
+            Position::new(0, 13)
+        );
+        // Past the end of the line, and in the middle of the emoji.
+        assert_eq!(
+            utf16.to_server(document, Position::new(1, 40)),
+            Position::new(1, 11)
+        );
+        assert_eq!(utf16.to_byte("😀a", 1), 0);
+        assert_eq!(utf16.from_byte("😀a", 2), 0);
+    }
+
+    #[test]
+    fn edits_are_converted() {
+        let note = Url::parse("file:///note.md").unwrap();
+        let created = Url::parse("file:///new.md").unwrap();
+        let documents = HashMap::from([(note.clone(), "# Café [[old]]".to_string())]);
+        let range = |start, end| Range::new(Position::new(0, start), Position::new(0, end));
+        let mut edit = WorkspaceEdit {
+            changes: Some(HashMap::from([
+                (
+                    note.clone(),
+                    vec![TextEdit::new(range(8, 15), "[[new]]".into())],
+                ),
+                (
+                    created.clone(),
+                    vec![TextEdit::new(range(9, 9), "text".into())],
+                ),
+            ])),
+            ..WorkspaceEdit::default()
+        };
+
+        Encoding::Utf16.edit_to_client(&documents, &mut edit);
+        let changes = edit.changes.unwrap();
+        assert_eq!(changes[&note][0].range, range(7, 14));
+        assert_eq!(changes[&created][0].range, range(9, 9));
+    }
+
+    #[test]
+    fn incremental_changes_are_applied() {
+        let mut document = String::from("😀 one\r\ntwo\nthree");
+        let change = |range: Option<Range>, text: &str| TextDocumentContentChangeEvent {
+            range,
+            range_length: None,
+            text: text.to_string(),
+        };
+        let range = |start: (u32, u32), end: (u32, u32)| {
+            Some(Range::new(
+                Position::new(start.0, start.1),
+                Position::new(end.0, end.1),
+            ))
+        };
+        Encoding::Utf16.apply_changes(
+            &mut document,
+            vec![
+                change(range((0, 3), (0, 6)), "1"),
+                change(range((1, 0), (2, 2)), "T"),
+                change(range((1, 9), (1, 9)), "!"),
+                change(range((5, 0), (5, 0)), "\nend"),
+            ],
+        );
+        assert_eq!(document, "😀 1\r\nTree!\nend");
+
+        Encoding::Utf16.apply_changes(&mut document, vec![change(None, "new")]);
+        assert_eq!(document, "new");
+    }
+
+    proptest! {
+        #[test]
+        fn conversions_round_trip(
+            line in "\\PC{0,30}",
+            character in 0u32..40,
+            encoding in prop_oneof![Just(Encoding::Utf8), Just(Encoding::Utf16)],
+        ) {
+            let byte = encoding.to_byte(&line, character);
+            prop_assert!(line.is_char_boundary(byte));
+            let back = encoding.from_byte(&line, byte);
+            prop_assert!(back <= character);
+            prop_assert_eq!(encoding.to_byte(&line, back), byte);
+        }
+
+        #[test]
+        fn changes_never_panic(
+            document in "\\PC{0,20}(\r?\n\\PC{0,20}){0,3}",
+            line in 0u32..6,
+            start in 0u32..30,
+            len in 0u32..30,
+            text in "\\PC{0,5}",
+        ) {
+            let mut document = document;
+            let change = TextDocumentContentChangeEvent {
+                range: Some(Range::new(
+                    Position::new(line, start),
+                    Position::new(line, start + len),
+                )),
+                range_length: None,
+                text,
+            };
+            Encoding::Utf16.apply_changes(&mut document, vec![change]);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Quotes of another note, for the `noteLs.insertExcerpt` command.
+//!
+//! Unlike an embed, an excerpt is plain markdown: a blockquote of the note or one of its sections
+//! followed by a link back to it, which renders the same anywhere the note is exported to.
+
+use std::path::Path;
+
+use tower_lsp::lsp_types::Position;
+
+use crate::{
+    frontmatter,
+    index::Note,
+    links::{self, encode_target},
+};
+
+/// The lines to quote from `content`, the content of `note`: the section under `heading`, or the
+/// body of the note without its frontmatter and title heading.
+fn quoted_lines<'a>(content: &'a str, note: &Note, heading: Option<&str>) -> Option<Vec<&'a str>> {
+    let lines = content.lines().enumerate();
+    let lines = match heading {
+        Some(heading) => {
+            let heading = note.find_heading(heading)?;
+            let end = note.section_end(heading).unwrap_or(u32::MAX) as usize;
+            lines
+                .take(end)
+                .skip(heading.line as usize + 1)
+                .map(|(_, line)| line)
+                .collect::<Vec<_>>()
+        }
+        None => {
+            let title_line = note
+                .headings
+                .iter()
+                .find(|heading| heading.level == 1)
+                .map(|heading| heading.line as usize);
+            lines
+                .skip(frontmatter::body_start(content) as usize)
+                .filter(|(n, _)| Some(*n) != title_line)
+                .map(|(_, line)| line)
+                .collect()
+        }
+    };
+
+    let start = lines.iter().position(|line| !line.trim().is_empty());
+    let end = lines.iter().rposition(|line| !line.trim().is_empty());
+    match (start, end) {
+        (Some(start), Some(end)) => Some(lines[start..=end].to_vec()),
+        _ => Some(Vec::new()),
+    }
+}
+
+/// A blockquote of `heading`'s section of `content`, the note at `target`, or of the whole note
+/// without one, ending with a link to it from a note in `from_dir`.
+///
+/// Returns `None` if the note has no such heading.
+pub fn excerpt(
+    from_dir: &Path,
+    target: &Path,
+    content: &str,
+    note: &Note,
+    heading: Option<&str>,
+) -> Option<String> {
+    let lines = quoted_lines(content, note, heading)?;
+
+    let title = note
+        .title()
+        .map(str::to_string)
+        .or_else(|| Some(target.file_stem()?.to_string_lossy().into_owned()))
+        .unwrap_or_default();
+    let mut link_target = encode_target(&links::path_to_target(&links::relative_path(
+        from_dir, target,
+    )));
+    let mut text = title;
+    if let Some(heading) = heading.and_then(|heading| note.find_heading(heading)) {
+        link_target.push('#');
+        link_target.push_str(&note.heading_anchor(heading));
+        text.push_str(" › ");
+        text.push_str(&heading.text);
+    }
+
+    let mut quote = String::new();
+    for line in lines {
+        if line.trim().is_empty() {
+            quote.push_str(">\n");
+        } else {
+            quote.push_str("> ");
+            quote.push_str(line);
+            quote.push('\n');
+        }
+    }
+    if !quote.is_empty() {
+        quote.push_str(">\n");
+    }
+    quote.push_str(&format!("> — [{text}]({link_target})\n"));
+    Some(quote)
+}
+
+/// The position at the very end of `document`.
+pub fn end_of(document: &str) -> Position {
+    let line_start = document.rfind('\n').map_or(0, |i| i + 1);
+    Position::new(
+        document.matches('\n').count() as u32,
+        (document.len() - line_start) as u32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_are_quoted_with_a_link_back() {
+        let content = "---\ntitle: Other\n---\n# Heading\nintro\n\n## Some Part\n\none\n\ntwo\n\
+                       ### Nested\nthree\n## Next\nfour";
+        let note = Note::parse(content);
+        let target = Path::new("/vault/sub/other note.md");
+
+        assert_eq!(
+            excerpt(
+                Path::new("/vault"),
+                target,
+                content,
+                &note,
+                Some("some part")
+            )
+            .unwrap(),
+            "> one\n>\n> two\n> ### Nested\n> three\n>\n\
+             > — [Other › Some Part](sub/other%20note.md#some-part)\n"
+        );
+        assert_eq!(
+            excerpt(Path::new("/vault/sub"), target, content, &note, None)
+                .unwrap()
+                .lines()
+                .last(),
+            Some("> — [Other](other%20note.md)")
+        );
+        assert_eq!(
+            excerpt(Path::new("/vault"), target, content, &note, Some("missing")),
+            None
+        );
+    }
+
+    #[test]
+    fn end_positions() {
+        assert_eq!(end_of(""), Position::new(0, 0));
+        assert_eq!(end_of("one\ntwo"), Position::new(1, 3));
+        assert_eq!(end_of("one\n"), Position::new(1, 0));
+    }
+}
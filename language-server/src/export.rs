@@ -0,0 +1,235 @@
+//! Exports of a note as a standalone HTML file or a PDF, for `noteLs.export.html` and
+//! `noteLs.export.pdf`, and of every note of a vault for `noteLs.publish`.
+//!
+//! A note is rendered like the preview renders it. Renderers don't know wiki links, so notes the
+//! note embeds are flattened into it first, and the remaining wiki links become markdown links to
+//! the files they point to, relative to the note. Links that don't resolve are left as their text.
+//! Links to headings point at their anchors, which the builtin renderer gives the headings.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use aurelius::render::{ExternalCommand, PulldownCmark, Renderer as _, RendererProcess};
+use tokio::process::Command;
+
+use crate::{
+    config::{Config, PdfCommand, PreviewDirection, PreviewTheme, Renderer},
+    diagnostics, flatten,
+    index::{Note, NoteIndex},
+    links::{self, Link, LinkKind},
+};
+
+/// The file the wiki `link` in the note at `path` points to, if it exists.
+pub fn wiki_target(path: &Path, link: &Link, index: &NoteIndex) -> Option<PathBuf> {
+    if let Some(note) = index.resolve(path, link) {
+        return Some(note);
+    }
+    let note_dir = path.parent()?;
+    link.candidates(note_dir, index.root())
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+}
+
+/// Where links to notes point in exported markdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteLinks {
+    /// To the notes' files, for a note exported on its own.
+    Files,
+    /// To the notes' HTML pages, next to their files, for a published vault. Markdown links to
+    /// notes are changed too.
+    Pages,
+}
+
+/// The anchor of the heading `link` names in `note`, if it names one.
+pub fn heading_anchor(link: &Link, note: Option<&Note>) -> Option<String> {
+    let note = note?;
+    Some(note.heading_anchor(note.find_heading(link.anchor()?)?))
+}
+
+/// The markdown `link` becomes, pointing to `target` at the heading `anchor`, or its text if
+/// there's no target. Links within the note only have an anchor.
+fn markdown_link(
+    link: &Link,
+    note_dir: &Path,
+    target: Option<&Path>,
+    anchor: Option<&str>,
+) -> String {
+    let text = match link.embed {
+        true => diagnostics::alt_text(link).unwrap_or_default(),
+        false => link.alias().unwrap_or_else(|| match link.target_path() {
+            "" => link.anchor().unwrap_or_default(),
+            target => target,
+        }),
+    };
+    let mut destination = match target {
+        Some(target) => links::encode_target(&links::path_to_target(&links::relative_path(
+            note_dir, target,
+        ))),
+        None if link.target_path().is_empty() && anchor.is_some() => String::new(),
+        None => return text.to_string(),
+    };
+    if let Some(anchor) = anchor {
+        destination.push('#');
+        destination.push_str(anchor);
+    }
+    match link.embed {
+        true => format!("![{text}]({destination})"),
+        false => format!("[{text}]({destination})"),
+    }
+}
+
+/// The markdown link `link` in the note at `path` becomes, if it points to a note and
+/// `note_links` sends it to the note's page.
+fn page_link(link: &Link, path: &Path, index: &NoteIndex, note_links: NoteLinks) -> Option<String> {
+    if note_links != NoteLinks::Pages || link.is_external() || link.target_path().is_empty() {
+        return None;
+    }
+    let target = index.resolve(path, link)?;
+    let note_dir = path.parent()?;
+    let page = links::relative_path(note_dir, &target.with_extension("html"));
+    Some(link.with_target(&links::encode_target(&links::path_to_target(&page))))
+}
+
+/// `content`, the content of the note at `path`, as markdown any renderer understands, with
+/// links to notes pointing where `note_links` says. `read` gives the content of an embedded note.
+pub fn markdown(
+    path: &Path,
+    content: &str,
+    index: &NoteIndex,
+    read: &mut dyn FnMut(&Path) -> Option<String>,
+    note_links: NoteLinks,
+) -> String {
+    let flattened = flatten::flatten(path, content, index, read);
+    // Embedded notes add their headings, so anchors within the note are taken from all of them.
+    let own = Note::parse(&flattened);
+    let note_dir = path.parent().unwrap_or(index.root());
+    let mut markdown = String::with_capacity(flattened.len());
+    for (number, line) in flattened.split_inclusive('\n').enumerate() {
+        let mut end = 0;
+        for link in links::parse_line(line, number as u32) {
+            let new = match link.kind {
+                LinkKind::Wiki => {
+                    let (target, anchor) = match link.target_path() {
+                        "" => (None, heading_anchor(&link, Some(&own))),
+                        _ => {
+                            let target = wiki_target(path, &link, index);
+                            let note = target.as_deref().and_then(|target| index.get(target));
+                            let anchor = heading_anchor(&link, note);
+                            let target = match target {
+                                Some(target)
+                                    if note_links == NoteLinks::Pages && index.is_note(&target) =>
+                                {
+                                    Some(target.with_extension("html"))
+                                }
+                                target => target,
+                            };
+                            (target, anchor)
+                        }
+                    };
+                    markdown_link(&link, note_dir, target.as_deref(), anchor.as_deref())
+                }
+                LinkKind::Markdown => match page_link(&link, path, index, note_links) {
+                    Some(new) => new,
+                    None => continue,
+                },
+            };
+            markdown.push_str(&line[end..link.start]);
+            markdown.push_str(&new);
+            end = link.end;
+        }
+        markdown.push_str(&line[end..]);
+    }
+    markdown
+}
+
+/// How exported pages look with the preview settings of `config`, for the vault at `root`.
+pub fn style(config: &Config, root: &Path) -> aurelius::export::Style {
+    aurelius::export::Style {
+        theme: config.preview_theme.into(),
+        direction: config.preview_direction.into(),
+        highlight_theme: String::from(match config.preview_theme {
+            PreviewTheme::Light => "github",
+            PreviewTheme::Dark => "github-dark",
+        }),
+        user_css: config
+            .preview_stylesheet
+            .as_ref()
+            .and_then(|stylesheet| fs::read_to_string(root.join(stylesheet)).ok()),
+    }
+}
+
+/// Render `markdown`, read from the file at `path`, with `renderer`, like the preview does.
+///
+/// This blocks on the renderer, which may be an external program.
+pub fn render(
+    renderer: &Renderer,
+    direction: PreviewDirection,
+    markdown: &str,
+    path: &Path,
+) -> io::Result<String> {
+    match renderer.clone() {
+        Renderer::Builtin => PulldownCmark::default()
+            .with_direction(direction.into())
+            .with_heading_ids()
+            .render(markdown, Some(path)),
+        Renderer::Command { command, args } => {
+            ExternalCommand::new(command, args).render(markdown, Some(path))
+        }
+        Renderer::Process { command, args } => {
+            RendererProcess::new(command, args).render(markdown, Some(path))
+        }
+    }
+}
+
+/// Print the HTML file `input` to the PDF `output` with `command`.
+pub async fn print_pdf(command: &PdfCommand, input: &Path, output: &Path) -> io::Result<()> {
+    let args = command.args.iter().map(|arg| {
+        arg.replace("{input}", &input.to_string_lossy())
+            .replace("{output}", &output.to_string_lossy())
+    });
+    let result = Command::new(&command.command).args(args).output().await?;
+    if !result.status.success() {
+        let error = String::from_utf8_lossy(&result.stderr);
+        return Err(io::Error::other(format!(
+            "{} failed: {}",
+            command.command,
+            error.trim()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn wiki_links_become_markdown_links() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::create_dir_all(root.join("notes/images")).unwrap();
+        fs::write(root.join("notes/images/cat.png"), "").unwrap();
+        fs::write(root.join("other note.md"), "# Usage\n# Usage\n").unwrap();
+        fs::write(root.join("part.md"), "Embedded [[other note#usage-1]]\n").unwrap();
+        let content = "[[other note|Other]] and [[gone]]\n![[images/cat.png|A cat]] ![[part]]\n\
+                       # Setup\n[[#Setup]]\n";
+        fs::write(root.join("notes/note.md"), content).unwrap();
+        let index = NoteIndex::scan(root);
+
+        let mut read = |path: &Path| fs::read_to_string(path).ok();
+        let note = root.join("notes/note.md");
+        let markdown = markdown(&note, content, &index, &mut read, NoteLinks::Files);
+        assert_eq!(
+            markdown,
+            "[Other](../other%20note.md) and gone\n![A cat](images/cat.png)\n\
+             <!-- embedded from part.md -->\n\
+             Embedded [other note](../other%20note.md#usage-1)\n\
+             <!-- end of part.md -->\n\
+             # Setup\n[Setup](#setup)\n"
+        );
+    }
+}
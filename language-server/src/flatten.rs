@@ -0,0 +1,171 @@
+//! Flattening a note for `noteLs.flattenNote`, to share it with people outside the vault.
+//!
+//! Embeds of other notes (`![[note]]` or `![[note#Section]]`) are replaced by what they embed,
+//! recursively, between comments naming where it came from. A note already being embedded is
+//! not embedded again, so notes embedding each other don't loop forever.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    fences::Fences,
+    frontmatter,
+    index::{Note, NoteIndex},
+    links::{self, Link},
+};
+
+/// `content`, the content of the note at `path`, with its embeds of notes in `index` replaced by
+/// their content. `read` gives the content of an embedded note.
+pub fn flatten(
+    path: &Path,
+    content: &str,
+    index: &NoteIndex,
+    read: &mut dyn FnMut(&Path) -> Option<String>,
+) -> String {
+    let mut embedding = vec![links::nfc_path(path)];
+    flatten_into(path, content, index, read, &mut embedding)
+}
+
+/// `flatten` for a note embedded by the notes in `embedding`, the outermost one first.
+fn flatten_into(
+    path: &Path,
+    content: &str,
+    index: &NoteIndex,
+    read: &mut dyn FnMut(&Path) -> Option<String>,
+    embedding: &mut Vec<PathBuf>,
+) -> String {
+    let mut lines = Vec::new();
+    let mut fences = Fences::default();
+    for (n, line) in content.lines().enumerate() {
+        if fences.in_code(line) || !line.contains("![") {
+            lines.push(line.to_string());
+            continue;
+        }
+
+        let mut flattened = String::new();
+        let mut last = 0;
+        for link in links::parse_line(line, n as u32) {
+            if !link.embed {
+                continue;
+            }
+            let Some(target) = index.resolve(path, &link) else {
+                continue;
+            };
+            let Some(embedded) = embed(&link, &target, index, read, embedding) else {
+                continue;
+            };
+            // The embedded lines go on lines of their own.
+            flattened.push_str(line[last..link.start].trim_end());
+            if !flattened.trim().is_empty() && !flattened.ends_with('\n') {
+                flattened.push('\n');
+            }
+            flattened.push_str(&embedded);
+            if !line[link.end..].trim().is_empty() {
+                flattened.push('\n');
+            }
+            last = link.end;
+        }
+        match last {
+            0 => flattened.push_str(line),
+            _ => flattened.push_str(line[last..].trim_start()),
+        }
+        lines.push(flattened);
+    }
+
+    let mut flattened = lines.join("\n");
+    if content.ends_with('\n') {
+        flattened.push('\n');
+    }
+    flattened
+}
+
+/// What `link` embeds from the note at `target`, flattened, between comments naming it.
+///
+/// Returns `None` if the note can't be read or has no heading the link names, and a comment in
+/// place of the note if it's already being embedded.
+fn embed(
+    link: &Link,
+    target: &Path,
+    index: &NoteIndex,
+    read: &mut dyn FnMut(&Path) -> Option<String>,
+    embedding: &mut Vec<PathBuf>,
+) -> Option<String> {
+    let name = links::path_to_target(target.strip_prefix(index.root()).unwrap_or(target));
+    let source = match link.anchor() {
+        Some(anchor) => format!("{name}#{anchor}"),
+        None => name,
+    };
+    if embedding.contains(&links::nfc_path(target)) {
+        return Some(format!(
+            "<!-- {source} is left out, it embeds this note -->"
+        ));
+    }
+
+    let content = read(target)?;
+    let note = Note::parse(&content);
+    let lines = content.lines().enumerate();
+    let lines = match link.anchor() {
+        Some(anchor) => {
+            let heading = note.find_heading(anchor)?;
+            let end = note.section_end(heading).unwrap_or(u32::MAX) as usize;
+            lines
+                .take(end)
+                .skip(heading.line as usize)
+                .map(|(_, line)| line)
+                .collect::<Vec<_>>()
+        }
+        None => lines
+            .skip(frontmatter::body_start(&content) as usize)
+            .map(|(_, line)| line)
+            .collect(),
+    };
+    let start = lines.iter().position(|line| !line.trim().is_empty());
+    let end = lines.iter().rposition(|line| !line.trim().is_empty());
+    let body = match (start, end) {
+        (Some(start), Some(end)) => lines[start..=end].join("\n"),
+        _ => String::new(),
+    };
+
+    embedding.push(links::nfc_path(target));
+    let flattened = flatten_into(target, &body, index, read, embedding);
+    embedding.pop();
+    Some(format!(
+        "<!-- embedded from {source} -->\n{flattened}\n<!-- end of {source} -->"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn embeds_are_flattened_once() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        let write = |name: &str, content: &str| fs::write(root.join(name), content).unwrap();
+        write(
+            "b.md",
+            "# B\nintro\n## Part\n\none ![[c]]\n\n## Next\ntwo\n",
+        );
+        write("c.md", "---\ntitle: C\n---\n\nthree\n![[home]]\n");
+        let home = "# Home\n![[b#Part]]\n![image](pic.png)\n```\n![[c]]\n```\n";
+        write("home.md", home);
+        let index = NoteIndex::scan(root);
+
+        let mut read = |path: &Path| fs::read_to_string(path).ok();
+        assert_eq!(
+            flatten(&root.join("home.md"), home, &index, &mut read),
+            "# Home\n\
+             <!-- embedded from b.md#Part -->\n\
+             ## Part\n\n\
+             one\n\
+             <!-- embedded from c.md -->\n\
+             three\n\
+             <!-- home.md is left out, it embeds this note -->\n\
+             <!-- end of c.md -->\n\
+             <!-- end of b.md#Part -->\n\
+             ![image](pic.png)\n```\n![[c]]\n```\n"
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! The sentence, paragraph and section at the cursor, for the `noteLs/sectionRange` request.
+//!
+//! Clients use it for focus and typewriter modes, which dim everything but what's being written,
+//! and to select a sentence or paragraph at a time, without parsing the markdown themselves.
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Position, Range, TextDocumentIdentifier};
+
+use crate::{code_blocks, frontmatter, headings, lists};
+
+/// Characters that end a sentence when whitespace follows them.
+const TERMINATORS: [char; 4] = ['.', '!', '?', '…'];
+
+/// Characters that may come between the end of a sentence and the whitespace after it.
+const CLOSING: [char; 9] = ['"', '\'', ')', ']', '*', '_', '”', '’', '»'];
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionRangeParams {
+    pub text_document: TextDocumentIdentifier,
+    pub position: Position,
+}
+
+/// The result of `noteLs/sectionRange`.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SectionRange {
+    /// The sentence at the cursor, without the list marker or `>` before it. There's none in
+    /// code blocks and on blank lines.
+    pub sentence: Option<Range>,
+    /// The paragraph, list item, heading or code block at the cursor. There's none on blank
+    /// lines.
+    pub paragraph: Option<Range>,
+    /// From the heading above the cursor to the next one of the same or a higher level, or from
+    /// the start of the body to the first heading. Blank lines at the end are left out.
+    pub section: Range,
+}
+
+/// The end of line `n` of `lines`.
+fn end_of(lines: &[&str], n: usize) -> Position {
+    Position::new(n as u32, lines.get(n).map_or(0, |line| line.len()) as u32)
+}
+
+/// The sentences on lines `start` to `end` of `lines`, each line's text starting at the byte
+/// offset `text_start` gives for it.
+fn sentences(
+    lines: &[&str],
+    start: usize,
+    end: usize,
+    text_start: impl Fn(&str) -> usize,
+) -> Vec<Range> {
+    let mut sentences = Vec::new();
+    let mut current: Option<Range> = None;
+    let mut ended = false;
+    for (n, line) in lines.iter().enumerate().take(end + 1).skip(start) {
+        let from = text_start(line);
+        // The line break after each line is whitespace too.
+        let chars = line[from..]
+            .char_indices()
+            .map(|(i, c)| (from + i, c))
+            .chain([(line.len(), '\n')]);
+        for (i, c) in chars {
+            if c.is_whitespace() {
+                if ended {
+                    sentences.extend(current.take());
+                    ended = false;
+                }
+                continue;
+            }
+            let here = Position::new(n as u32, i as u32);
+            let after = Position::new(n as u32, (i + c.len_utf8()) as u32);
+            match current.as_mut() {
+                Some(sentence) => sentence.end = after,
+                None => current = Some(Range::new(here, after)),
+            }
+            ended = TERMINATORS.contains(&c) || (ended && CLOSING.contains(&c));
+        }
+    }
+    sentences.extend(current);
+    sentences
+}
+
+/// The sentence, paragraph and section of `document` at `position`, or `None` if it's in the
+/// frontmatter or past the end.
+pub fn section_range(document: &str, position: Position) -> Option<SectionRange> {
+    let lines = document.lines().collect::<Vec<_>>();
+    let line = position.line as usize;
+    let body_start = frontmatter::body_start(document);
+    if line > lines.len() || position.line < body_start {
+        return None;
+    }
+    let headings = headings::parse_headings(document);
+    let blocks = code_blocks::blocks(document);
+
+    let heading = headings
+        .iter()
+        .rev()
+        .find(|heading| heading.line <= position.line);
+    let (start, level) = heading.map_or((body_start, 0), |heading| (heading.line, heading.level));
+    let next = headings
+        .iter()
+        .find(|heading| heading.line > start && (level == 0 || heading.level <= level));
+    let mut end = next.map_or(lines.len(), |heading| heading.line as usize);
+    while end > start as usize + 1 && lines[end - 1].trim().is_empty() {
+        end -= 1;
+    }
+    let section = Range::new(
+        Position::new(start, 0),
+        end_of(&lines, end.max(start as usize + 1) - 1),
+    );
+
+    let is_heading = |n: usize| headings.iter().any(|heading| heading.line as usize == n);
+    let block = blocks
+        .iter()
+        .find(|block| block.start <= position.line && position.line <= block.end);
+    let blank = lines.get(line).is_none_or(|text| text.trim().is_empty());
+    let (paragraph, sentence) = match block {
+        Some(block) => {
+            let paragraph = Range::new(
+                Position::new(block.start, 0),
+                end_of(&lines, block.end as usize),
+            );
+            (Some(paragraph), None)
+        }
+        None if blank => (None, None),
+        None => {
+            // Paragraphs end at blank lines, headings, code blocks and the start of list items.
+            let separate = |n: usize| {
+                lines[n].trim().is_empty()
+                    || is_heading(n)
+                    || blocks
+                        .iter()
+                        .any(|block| block.start as usize <= n && n <= block.end as usize)
+            };
+            let (mut first, mut last) = (line, line);
+            if !is_heading(line) {
+                while first > body_start as usize
+                    && !lists::is_item(lines[first])
+                    && !separate(first - 1)
+                {
+                    first -= 1;
+                }
+                while last + 1 < lines.len()
+                    && !separate(last + 1)
+                    && !lists::is_item(lines[last + 1])
+                {
+                    last += 1;
+                }
+            }
+            let text_start = |text: &str| match is_heading(line) {
+                true => text.len() - text.trim_start().trim_start_matches('#').trim_start().len(),
+                false => lists::text_start(text),
+            };
+            let sentences = sentences(&lines, first, last, text_start);
+            let sentence = sentences
+                .iter()
+                .rev()
+                .find(|sentence| sentence.start <= position)
+                .or(sentences.first())
+                .copied();
+            let paragraph = Range::new(Position::new(first as u32, 0), end_of(&lines, last));
+            (Some(paragraph), sentence)
+        }
+    };
+
+    Some(SectionRange {
+        sentence,
+        paragraph,
+        section,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> Range {
+        Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1))
+    }
+
+    #[test]
+    fn sentences_paragraphs_and_sections_are_found() {
+        let document = "---\ntitle: Tea\n---\nIntro.\n# Tea\n\
+                        Green tea costs 4.50 a cup. It's \"good!\" Wrapped\n\
+                        over lines?\n\
+                        - First item. Second\n  sentence\n\
+                        \n> Quoted.\n\
+                        ## Kinds\n```\ncode.\n```\n\n\
+                        # Cake\n";
+
+        let at = |line, character| section_range(document, Position::new(line, character));
+        assert_eq!(at(1, 0), None);
+        assert_eq!(
+            at(3, 2).unwrap(),
+            SectionRange {
+                sentence: Some(range((3, 0), (3, 6))),
+                paragraph: Some(range((3, 0), (3, 6))),
+                section: range((3, 0), (3, 6)),
+            }
+        );
+
+        let tea = at(5, 30).unwrap();
+        assert_eq!(tea.sentence, Some(range((5, 28), (5, 40))));
+        assert_eq!(tea.paragraph, Some(range((5, 0), (6, 11))));
+        assert_eq!(tea.section, range((4, 0), (14, 3)));
+        assert_eq!(at(6, 3).unwrap().sentence, Some(range((5, 41), (6, 11))));
+        assert_eq!(at(5, 5).unwrap().sentence, Some(range((5, 0), (5, 27))));
+
+        let item = at(8, 4).unwrap();
+        assert_eq!(item.sentence, Some(range((7, 14), (8, 10))));
+        assert_eq!(item.paragraph, Some(range((7, 0), (8, 10))));
+        assert_eq!(at(7, 0).unwrap().sentence, Some(range((7, 2), (7, 13))));
+
+        let heading = at(4, 0).unwrap();
+        assert_eq!(heading.sentence, Some(range((4, 2), (4, 5))));
+        assert_eq!(heading.paragraph, Some(range((4, 0), (4, 5))));
+
+        let code = at(13, 1).unwrap();
+        assert_eq!(code.sentence, None);
+        assert_eq!(code.paragraph, Some(range((12, 0), (14, 3))));
+        assert_eq!(code.section, range((11, 0), (14, 3)));
+
+        assert_eq!(at(10, 4).unwrap().sentence, Some(range((10, 2), (10, 9))));
+
+        let blank = at(9, 0).unwrap();
+        assert_eq!((blank.sentence, blank.paragraph), (None, None));
+        assert_eq!(at(16, 0).unwrap().section, range((16, 0), (16, 6)));
+    }
+}
@@ -0,0 +1,92 @@
+//! Folding ranges for `textDocument/foldingRange`: sections under headings, and blocks that span
+//! several lines, like code blocks, lists, quotes, tables and frontmatter.
+
+use tower_lsp::lsp_types::{FoldingRange, FoldingRangeKind};
+
+use crate::syntax::{Kind, Syntax};
+
+fn range(start_line: u32, end_line: u32, kind: Option<FoldingRangeKind>) -> FoldingRange {
+    FoldingRange {
+        start_line,
+        start_character: None,
+        end_line,
+        end_character: None,
+        kind,
+        collapsed_text: None,
+    }
+}
+
+/// The folding ranges of `document`, parsed into `syntax`. A section folds up to the next heading
+/// of the same or a higher level, without the blank lines before it.
+pub fn folding_ranges(document: &str, syntax: &Syntax) -> Vec<FoldingRange> {
+    let lines = document.lines().collect::<Vec<_>>();
+    let last_text_line = |before: u32| {
+        (0..before)
+            .rev()
+            .find(|line| !lines[*line as usize].trim().is_empty())
+            .unwrap_or(0)
+    };
+
+    let headings = syntax.headings().collect::<Vec<_>>();
+    let mut ranges = Vec::new();
+    for (i, (heading, level, _)) in headings.iter().enumerate() {
+        let start = heading.range.start.line;
+        let next = headings[i + 1..]
+            .iter()
+            .find(|(_, next_level, _)| next_level <= level)
+            .map_or(lines.len() as u32, |(next, ..)| next.range.start.line);
+        let end = last_text_line(next);
+        if end > start {
+            ranges.push(range(start, end, None));
+        }
+    }
+
+    for node in syntax.nodes() {
+        let kind = match node.kind {
+            Kind::Frontmatter => Some(FoldingRangeKind::Imports),
+            Kind::CodeBlock(_)
+            | Kind::BlockQuote
+            | Kind::List
+            | Kind::Item
+            | Kind::FootnoteDefinition
+            | Kind::Table
+            | Kind::Html => None,
+            _ => continue,
+        };
+        let (start, end) = (node.range.start.line, node.range.end.line);
+        let folded = ranges
+            .iter()
+            .any(|range| range.start_line == start && range.end_line == end);
+        if end > start && !folded {
+            ranges.push(range(start, end, kind));
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_and_blocks_fold() {
+        let document = "---\ntags: [tea]\n---\n# Tea\n\n## Kinds\n- green\n- black\n\n\
+                        ```sh\nbrew\n```\n\n## Notes\n> Hot\n> water\n";
+        let ranges = folding_ranges(document, &Syntax::parse(document))
+            .into_iter()
+            .map(|range| (range.start_line, range.end_line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranges,
+            [
+                (3, 15),
+                (5, 11),
+                (13, 15),
+                (0, 2),
+                (6, 7),
+                (9, 11),
+                (14, 15)
+            ]
+        );
+    }
+}
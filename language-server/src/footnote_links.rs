@@ -0,0 +1,141 @@
+//! Footnotes in the preview and exports, written out from the footnotes `crate::footnotes`
+//! finds, so they match what hovering them in the editor shows.
+//!
+//! References become superscript numbers, in the order footnotes are first referenced, with the
+//! footnote's text shown when the pointer rests on them. Definitions become list items with
+//! their number and a link back to each reference.
+
+use std::collections::HashMap;
+
+use crate::{footnotes, habits::escape};
+
+/// The `id` of the `n`th reference to footnote `number`, counting from 0.
+fn reference_id(number: usize, n: usize) -> String {
+    match n {
+        0 => format!("fnref-{number}"),
+        n => format!("fnref-{number}-{}", n + 1),
+    }
+}
+
+/// `markdown` with its footnote references and definitions written out as HTML. References to
+/// footnotes that aren't defined are left alone.
+///
+/// Every line stays on a line of its own, so the lines of the note still match the source when
+/// the preview scrolls to one.
+pub fn render(markdown: &str) -> String {
+    let definitions = footnotes::parse_definitions(markdown);
+    if definitions.is_empty() {
+        return markdown.to_string();
+    }
+    let references = footnotes::parse_references(markdown)
+        .into_iter()
+        .filter(|reference| footnotes::definition(&definitions, &reference.label).is_some())
+        .collect::<Vec<_>>();
+
+    // Footnotes are numbered in the order they're first referenced, then in the order of the
+    // definitions of those that never are.
+    let mut numbers = HashMap::new();
+    let labels = references
+        .iter()
+        .map(|reference| &reference.label)
+        .chain(definitions.iter().map(|definition| &definition.label));
+    for label in labels {
+        let next = numbers.len() + 1;
+        numbers.entry(label.to_lowercase()).or_insert(next);
+    }
+
+    let mut lines = markdown.lines().map(str::to_string).collect::<Vec<_>>();
+    let mut counts = HashMap::<usize, usize>::new();
+    let mut replacements = Vec::new();
+    for reference in &references {
+        let number = numbers[&reference.label.to_lowercase()];
+        let count = counts.entry(number).or_default();
+        let text = footnotes::definition(&definitions, &reference.label)
+            .map(|definition| {
+                definition
+                    .text
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .unwrap_or_default();
+        let html = format!(
+            "<sup class=\"footnote-ref\" id=\"{}\"><a href=\"#fn-{number}\" title=\"{}\">{number}</a></sup>",
+            reference_id(number, *count),
+            escape(&text)
+        );
+        *count += 1;
+        replacements.push((reference.line, reference.start..reference.end, html));
+    }
+    // Replace from the end of each line, so the offsets of the references before stay right.
+    for (line, range, html) in replacements.into_iter().rev() {
+        lines[line as usize].replace_range(range, &html);
+    }
+
+    for definition in &definitions {
+        let number = numbers[&definition.label.to_lowercase()];
+        // Only the first definition of a footnote is linked to.
+        if footnotes::definition(&definitions, &definition.label) != Some(definition) {
+            continue;
+        }
+        let backrefs = (0..counts.get(&number).copied().unwrap_or(0))
+            .map(|n| {
+                format!(
+                    " <a class=\"footnote-backref\" href=\"#{}\">↩</a>",
+                    reference_id(number, n)
+                )
+            })
+            .collect::<String>();
+        lines[definition.end_line as usize].push_str(&backrefs);
+        // The references on the definition's first line have been replaced already, but the
+        // label before the text hasn't.
+        let first = &mut lines[definition.line as usize];
+        first.replace_range(
+            ..definition.text_start,
+            &format!("- <span class=\"footnote\" id=\"fn-{number}\">{number}.</span> "),
+        );
+    }
+
+    let mut rendered = lines.join("\n");
+    if markdown.ends_with('\n') {
+        rendered.push('\n');
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn footnotes_link_both_ways() {
+        let markdown = "Tea[^tea] and cake[^c].\nMore tea[^Tea].\n\n\
+                        [^c]: Cake.\n[^tea]: \"Green\" [^c]\n  tea.\n";
+        let rendered = render(markdown);
+        let lines = rendered.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "Tea<sup class=\"footnote-ref\" id=\"fnref-1\"><a href=\"#fn-1\" \
+             title=\"&quot;Green&quot; [^c] tea.\">1</a></sup> and \
+             cake<sup class=\"footnote-ref\" id=\"fnref-2\"><a href=\"#fn-2\" title=\"Cake.\">2</a></sup>."
+        );
+        assert!(lines[1].contains("id=\"fnref-1-2\"><a href=\"#fn-1\""));
+        assert_eq!(
+            lines[3],
+            "- <span class=\"footnote\" id=\"fn-2\">2.</span> Cake. \
+             <a class=\"footnote-backref\" href=\"#fnref-2\">↩</a> \
+             <a class=\"footnote-backref\" href=\"#fnref-2-2\">↩</a>"
+        );
+        assert!(
+            lines[4].starts_with("- <span class=\"footnote\" id=\"fn-1\">1.</span> \"Green\" <sup")
+        );
+        assert_eq!(
+            lines[5],
+            "  tea. <a class=\"footnote-backref\" href=\"#fnref-1\">↩</a> \
+             <a class=\"footnote-backref\" href=\"#fnref-1-2\">↩</a>"
+        );
+
+        let plain = "No notes[^1] here\n";
+        assert_eq!(render(plain), plain);
+    }
+}
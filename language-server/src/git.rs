@@ -0,0 +1,234 @@
+//! What git knows about the notes of a vault kept in a git repository: the last commit of a note
+//! or of one of its sections, shown when hovering headings, and the notes changed lately, for
+//! `noteLs.recentNotes`.
+//!
+//! `git` is run as a program, so none of this is there if it isn't installed or the vault isn't a
+//! repository.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use tokio::process::Command;
+
+use crate::new_notes::Date;
+
+/// How long `git` may take to answer.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The hash blame gives lines that aren't committed yet.
+const UNCOMMITTED: &str = "0000000000000000000000000000000000000000";
+
+/// A commit that changed a note.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Commit {
+    /// The abbreviated hash.
+    pub hash: String,
+    pub author: String,
+    /// The day it was authored, as `YYYY-MM-DD`.
+    pub date: String,
+    /// The first line of its message.
+    pub summary: String,
+}
+
+impl Commit {
+    /// A line about the commit for a hover, e.g. "Last changed on 2024-03-01 by Ada in `1a2b3c4`:
+    /// Add notes".
+    pub fn describe(&self, markdown: bool) -> String {
+        let hash = match markdown {
+            true => format!("`{}`", self.hash),
+            false => self.hash.clone(),
+        };
+        format!(
+            "Last changed on {} by {} in {hash}: {}",
+            self.date, self.author, self.summary
+        )
+    }
+}
+
+/// Run `git` with `args` in `dir`, returning what it prints, or `None` if it fails.
+async fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(TIMEOUT, output).await.ok()?.ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The directory and name of the file at `path`, which git is run in and given.
+fn split(path: &Path) -> Option<(&Path, &str)> {
+    Some((path.parent()?, path.file_name()?.to_str()?))
+}
+
+/// The last commit that changed the file at `path`.
+pub async fn last_commit(path: &Path) -> Option<Commit> {
+    let (dir, name) = split(path)?;
+    let format = "--format=%h%x00%an%x00%as%x00%s";
+    let output = git(dir, &["log", "-1", format, "--", name]).await?;
+    let mut fields = output.trim_end().splitn(4, '\0');
+    Some(Commit {
+        hash: fields.next().filter(|hash| !hash.is_empty())?.to_string(),
+        author: fields.next()?.to_string(),
+        date: fields.next()?.to_string(),
+        summary: fields.next()?.to_string(),
+    })
+}
+
+/// The latest of the commits `git blame --porcelain` names, leaving out uncommitted lines.
+fn latest_blamed(porcelain: &str) -> Option<Commit> {
+    // Each commit's details come once, after the first line it's blamed for.
+    let mut commits = HashMap::<&str, (Commit, i64)>::new();
+    let mut current = None;
+    for line in porcelain.lines() {
+        if line.starts_with('\t') {
+            continue;
+        }
+        let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+        if key.len() == 40 && key.bytes().all(|b| b.is_ascii_hexdigit()) {
+            current = Some(key);
+            commits.entry(key).or_insert_with(|| {
+                let commit = Commit {
+                    hash: key[..7].to_string(),
+                    author: String::new(),
+                    date: String::new(),
+                    summary: String::new(),
+                };
+                (commit, 0)
+            });
+            continue;
+        }
+        let Some((commit, time)) = current.and_then(|hash| commits.get_mut(hash)) else {
+            continue;
+        };
+        match key {
+            "author" => commit.author = value.to_string(),
+            "author-time" => {
+                *time = value.parse().unwrap_or(0);
+                commit.date = Date::from_days(time.div_euclid(86_400)).to_string();
+            }
+            "summary" => commit.summary = value.to_string(),
+            _ => {}
+        }
+    }
+    commits
+        .into_iter()
+        .filter(|(hash, _)| *hash != UNCOMMITTED)
+        .max_by_key(|(_, (_, time))| *time)
+        .map(|(_, (commit, _))| commit)
+}
+
+/// The last commit that changed any of the lines `start` to `end`, counted from zero, of the file
+/// at `path`, as `git blame` has it.
+pub async fn last_commit_of_lines(path: &Path, start: u32, end: u32) -> Option<Commit> {
+    let (dir, name) = split(path)?;
+    let lines = format!("{},{}", start + 1, end.max(start) + 1);
+    let output = git(dir, &["blame", "--porcelain", "-L", &lines, "--", name]).await?;
+    latest_blamed(&output)
+}
+
+/// The files under `dir` changed in the last `days` days, newest first, with the day of their last
+/// change as `YYYY-MM-DD`. Changes that aren't committed yet, and new files, count as today's.
+/// Files that were deleted are left in. `None` if `dir` isn't in a git repository.
+pub async fn recent_changes(dir: &Path, days: u32) -> Option<Vec<(PathBuf, String)>> {
+    let since = format!("--since={days} days ago");
+    let log = git(
+        dir,
+        &[
+            "log",
+            &since,
+            "--relative",
+            "--name-only",
+            "--format=%x00%as",
+        ],
+    )
+    .await?;
+    let changed = git(dir, &["diff", "--name-only", "--relative", "HEAD"])
+        .await
+        .unwrap_or_default();
+    let untracked = git(dir, &["ls-files", "--others", "--exclude-standard"])
+        .await
+        .unwrap_or_default();
+
+    let today = Date::today().to_string();
+    let mut recent = Vec::<(PathBuf, String)>::new();
+    let mut add = |file: &str, date: &str| {
+        let path = dir.join(file);
+        if !recent.iter().any(|(seen, _)| *seen == path) {
+            recent.push((path, date.to_string()));
+        }
+    };
+    for file in changed.lines().chain(untracked.lines()) {
+        add(file, &today);
+    }
+    // The log is newest first, so the first date a file is seen with is its last change.
+    let mut date = "";
+    for line in log.lines() {
+        match line.strip_prefix('\0') {
+            Some(day) => date = day,
+            None if !line.is_empty() => add(line, date),
+            None => {}
+        }
+    }
+    Some(recent)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Commit everything in `dir`, authored at `date`, like "1 hour ago".
+    async fn commit(dir: &Path, message: &str, date: &str) {
+        let identity = ["-c", "user.name=Ada", "-c", "user.email=ada@example.com"];
+        let date = format!("--date={date}");
+        git(dir, &["add", "-A"]).await.unwrap();
+        let args = [&identity[..], &["commit", "-q", "-m", message, &date]].concat();
+        git(dir, &args).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn commits_of_notes_and_sections_are_found() {
+        let vault = tempfile::tempdir().unwrap();
+        let dir = vault.path();
+        let note = dir.join("note.md");
+        if git(dir, &["init", "-q"]).await.is_none() {
+            // Without git there's nothing to test.
+            return;
+        }
+        assert_eq!(last_commit(&note).await, None);
+
+        fs::write(&note, "# Note\nfirst\n## Later\nold\n").unwrap();
+        // An hour apart, so blame can tell which commit is later.
+        commit(dir, "Start", "1 hour ago").await;
+        fs::write(&note, "# Note\nfirst\n## Later\nnew\n").unwrap();
+        commit(dir, "Edit the later section", "now").await;
+        fs::write(dir.join("draft.md"), "").unwrap();
+
+        let last = last_commit(&note).await.unwrap();
+        assert_eq!(last.author, "Ada");
+        assert_eq!(last.summary, "Edit the later section");
+        assert_eq!(last.date, Date::today().to_string());
+        let first = last_commit_of_lines(&note, 0, 1).await.unwrap();
+        assert_eq!(first.summary, "Start");
+        let later = last_commit_of_lines(&note, 2, 3).await.unwrap();
+        assert_eq!(later.hash, last.hash);
+
+        let recent = recent_changes(dir, 7).await.unwrap();
+        let names = recent
+            .iter()
+            .map(|(path, _)| path.strip_prefix(dir).unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["draft.md", "note.md"]);
+        assert!(recent_changes(&dir.join("missing"), 7).await.is_none());
+    }
+}
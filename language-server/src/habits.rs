@@ -0,0 +1,298 @@
+//! Habit trackers: fenced `habits` blocks with a row per day and a column per habit.
+//!
+//! ````markdown
+//! ```habits
+//! day        | read | run
+//! 2026-10-15 | x    |
+//! 2026-10-16 | x    | x
+//! ```
+//! ````
+//!
+//! Any text in a cell marks the habit as done that day. The preview shows the blocks as grids,
+//! and `noteLs.toggleHabit` checks or unchecks a habit for today.
+
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+/// A day of a habit tracker.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Day {
+    pub line: u32,
+    pub date: String,
+    /// Whether each habit was done, in the order of `Block::habits`.
+    pub done: Vec<bool>,
+}
+
+/// A habit tracker block.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Block {
+    /// The line of the opening fence.
+    pub start: u32,
+    /// The line of the closing fence, or of the last line of the document if it isn't closed.
+    pub end: u32,
+    /// The heading of the column of days.
+    pub day_label: String,
+    pub habits: Vec<String>,
+    pub days: Vec<Day>,
+}
+
+impl Block {
+    /// The index of the habit named `name`, ignoring case.
+    pub fn habit(&self, name: &str) -> Option<usize> {
+        self.habits
+            .iter()
+            .position(|habit| habit.to_lowercase() == name.to_lowercase())
+    }
+}
+
+fn cells(line: &str) -> Vec<&str> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    line.split('|').map(str::trim).collect()
+}
+
+/// The fence opening a habit tracker, if `line` is one.
+fn opening_fence(line: &str) -> Option<&str> {
+    let line = line.trim();
+    ["```", "~~~"].into_iter().find(|fence| {
+        line.strip_prefix(fence)
+            .is_some_and(|info| info.trim() == "habits")
+    })
+}
+
+/// The habit trackers in `document`.
+pub fn blocks(document: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut lines = document.lines().zip(0u32..);
+    while let Some((line, n)) = lines.next() {
+        let Some(fence) = opening_fence(line) else {
+            continue;
+        };
+        let mut block = Block {
+            start: n,
+            end: n,
+            day_label: String::new(),
+            habits: Vec::new(),
+            days: Vec::new(),
+        };
+        let mut header = true;
+        for (line, n) in lines.by_ref() {
+            block.end = n;
+            if line.trim() == fence {
+                break;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let mut cells = cells(line).into_iter();
+            let first = cells.next().unwrap_or_default().to_string();
+            if header {
+                block.day_label = first;
+                block.habits = cells.map(str::to_string).collect();
+                header = false;
+            } else {
+                let mut done = cells.map(|cell| !cell.is_empty()).collect::<Vec<_>>();
+                done.resize(block.habits.len(), false);
+                block.days.push(Day {
+                    line: n,
+                    date: first,
+                    done,
+                });
+            }
+        }
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// The index of the habit whose column holds `character` on `line`, a row of a tracker.
+pub fn habit_at(line: &str, character: usize) -> Option<usize> {
+    let before = line.get(..character)?;
+    let leading = usize::from(line.trim_start().starts_with('|'));
+    before.matches('|').count().checked_sub(leading + 1)
+}
+
+/// A row of `block`, with its cells padded to line up with the header.
+fn row(block: &Block, date: &str, done: &[bool]) -> String {
+    let mut row = format!(
+        "{date:<width$}",
+        width = block.day_label.chars().count().max(date.chars().count())
+    );
+    for (habit, done) in block.habits.iter().zip(done) {
+        let cell = if *done { "x" } else { "" };
+        row.push_str(&format!(" | {cell:<width$}", width = habit.chars().count()));
+    }
+    row.trim_end().to_string()
+}
+
+/// An edit to `block` checking `habit` on `date` if it isn't done, or unchecking it if it is,
+/// adding a row for the date if there isn't one.
+pub fn toggle(block: &Block, habit: usize, date: &str) -> TextEdit {
+    match block.days.iter().find(|day| day.date == date) {
+        Some(day) => {
+            let mut done = day.done.clone();
+            if let Some(cell) = done.get_mut(habit) {
+                *cell = !*cell;
+            }
+            let line = day.line;
+            TextEdit::new(
+                Range::new(Position::new(line, 0), Position::new(line + 1, 0)),
+                format!("{}\n", row(block, date, &done)),
+            )
+        }
+        None => {
+            let mut done = vec![false; block.habits.len()];
+            if let Some(cell) = done.get_mut(habit) {
+                *cell = true;
+            }
+            let end = Position::new(block.end, 0);
+            TextEdit::new(
+                Range::new(end, end),
+                format!("{}\n", row(block, date, &done)),
+            )
+        }
+    }
+}
+
+/// `text` escaped for HTML.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// `markdown` with its habit trackers written out as HTML tables for the preview.
+///
+/// Every line of a tracker stays on a line of its own, so the lines of the rest of the note
+/// still match the source when the preview scrolls to one.
+pub fn render(markdown: &str) -> String {
+    let blocks = blocks(markdown);
+    if blocks.is_empty() {
+        return markdown.to_string();
+    }
+
+    let mut lines = markdown.lines().map(str::to_string).collect::<Vec<_>>();
+    for block in &blocks {
+        for line in &mut lines[block.start as usize..=block.end as usize] {
+            // Blank lines would end the HTML block.
+            *line = String::from("<!-- -->");
+        }
+        lines[block.start as usize] = String::from("<table class=\"habits\">");
+        let header = markdown
+            .lines()
+            .enumerate()
+            .take(block.end as usize)
+            .skip(block.start as usize + 1)
+            .find(|(_, line)| !line.trim().is_empty());
+        if let Some((header, _)) = header {
+            let mut html = format!("<tr><th>{}</th>", escape(&block.day_label));
+            for habit in &block.habits {
+                html.push_str(&format!("<th>{}</th>", escape(habit)));
+            }
+            html.push_str("</tr>");
+            lines[header] = html;
+        }
+        for day in &block.days {
+            let mut html = format!("<tr><th>{}</th>", escape(&day.date));
+            for done in &day.done {
+                html.push_str(if *done {
+                    "<td class=\"done\">✓</td>"
+                } else {
+                    "<td></td>"
+                });
+            }
+            html.push_str("</tr>");
+            lines[day.line as usize] = html;
+        }
+        lines[block.end as usize].push_str("</table>");
+    }
+
+    let mut rendered = lines.join("\n");
+    if markdown.ends_with('\n') {
+        rendered.push('\n');
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTE: &str = "# Habits\n\n```habits\nday        | read | run\n\n\
+                        2026-10-15 | x    |\n2026-10-16 |      | x\n```\nAfter\n";
+
+    #[test]
+    fn trackers_are_read_by_row() {
+        let blocks = blocks(NOTE);
+        assert_eq!(
+            blocks,
+            vec![Block {
+                start: 2,
+                end: 7,
+                day_label: String::from("day"),
+                habits: vec![String::from("read"), String::from("run")],
+                days: vec![
+                    Day {
+                        line: 5,
+                        date: String::from("2026-10-15"),
+                        done: vec![true, false]
+                    },
+                    Day {
+                        line: 6,
+                        date: String::from("2026-10-16"),
+                        done: vec![false, true]
+                    },
+                ],
+            }]
+        );
+        assert_eq!(blocks[0].habit("Run"), Some(1));
+        assert_eq!(habit_at("2026-10-15 | x    |", 13), Some(0));
+        assert_eq!(habit_at("| 2026-10-15 | x | |", 19), Some(1));
+        assert_eq!(habit_at("2026-10-15 | x    |", 4), None);
+    }
+
+    #[test]
+    fn toggling_updates_or_adds_todays_row() {
+        let block = &blocks(NOTE)[0];
+        assert_eq!(
+            toggle(block, 0, "2026-10-16"),
+            TextEdit::new(
+                Range::new(Position::new(6, 0), Position::new(7, 0)),
+                String::from("2026-10-16 | x    | x\n")
+            )
+        );
+        assert_eq!(
+            toggle(block, 0, "2026-10-15").new_text,
+            "2026-10-15 |      |\n"
+        );
+        assert_eq!(
+            toggle(block, 1, "2026-10-17"),
+            TextEdit::new(
+                Range::new(Position::new(7, 0), Position::new(7, 0)),
+                String::from("2026-10-17 |      | x\n")
+            )
+        );
+    }
+
+    #[test]
+    fn trackers_render_as_tables_line_for_line() {
+        let rendered = render(NOTE);
+        assert_eq!(rendered.lines().count(), NOTE.lines().count());
+        assert_eq!(
+            rendered.lines().collect::<Vec<_>>(),
+            vec![
+                "# Habits",
+                "",
+                "<table class=\"habits\">",
+                "<tr><th>day</th><th>read</th><th>run</th></tr>",
+                "<!-- -->",
+                "<tr><th>2026-10-15</th><td class=\"done\">✓</td><td></td></tr>",
+                "<tr><th>2026-10-16</th><td></td><td class=\"done\">✓</td></tr>",
+                "<!-- --></table>",
+                "After",
+            ]
+        );
+        assert_eq!(render("no trackers"), "no trackers");
+    }
+}
@@ -0,0 +1,95 @@
+//! Highlighting every occurrence in a note of the link or tag under the cursor, for
+//! `textDocument/documentHighlight`.
+
+use tower_lsp::lsp_types::{DocumentHighlight, DocumentHighlightKind, Position, Range};
+
+use crate::{index::Note, links::Link};
+
+/// Whether a link or tag on `line` from byte `start` to `end` covers `position`.
+fn covers(line: u32, start: usize, end: usize, position: Position) -> bool {
+    let character = position.character as usize;
+    line == position.line && start <= character && character < end
+}
+
+fn highlight(range: Range) -> DocumentHighlight {
+    DocumentHighlight {
+        range,
+        kind: Some(DocumentHighlightKind::TEXT),
+    }
+}
+
+/// The occurrences in `note` of the tag or link at `position`, including itself, or `None` if
+/// there's neither there. Links are occurrences of each other if `same_target` says so.
+pub fn highlights(
+    note: &Note,
+    position: Position,
+    same_target: impl Fn(&Link, &Link) -> bool,
+) -> Option<Vec<DocumentHighlight>> {
+    let tag = note
+        .tags
+        .iter()
+        .find(|tag| covers(tag.line, tag.start, tag.end, position));
+    if let Some(tag) = tag {
+        let occurrences = note
+            .tags
+            .iter()
+            .filter(|other| other.name == tag.name)
+            .map(|other| highlight(other.range()));
+        return Some(occurrences.collect());
+    }
+
+    let link = note
+        .links
+        .iter()
+        .find(|link| covers(link.line, link.start, link.end, position))?;
+    let occurrences = note
+        .links
+        .iter()
+        .filter(|other| same_target(link, other))
+        .map(|other| {
+            highlight(Range::new(
+                Position::new(other.line, other.start as u32),
+                Position::new(other.line, other.end as u32),
+            ))
+        });
+    Some(occurrences.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn occurrences_of_links_and_tags_are_highlighted() {
+        let note = Note::parse(
+            "---\ntags: [tea]\n---\n#tea with [[Cake]] and [cake](cake.md#top)\n\
+             [[Scones]] #tea/green %%[[Cake]]%% [[cake|again]]",
+        );
+        let same_target = |a: &Link, b: &Link| {
+            a.decoded_path().trim_end_matches(".md").to_lowercase()
+                == b.decoded_path().trim_end_matches(".md").to_lowercase()
+        };
+        let ranges = |position| {
+            highlights(&note, position, same_target).map(|highlights| {
+                highlights
+                    .into_iter()
+                    .map(|highlight| {
+                        let range = highlight.range;
+                        (range.start.line, range.start.character, range.end.character)
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(
+            ranges(Position::new(3, 2)),
+            Some(vec![(1, 7, 10), (3, 0, 4)])
+        );
+        assert_eq!(
+            ranges(Position::new(3, 12)),
+            Some(vec![(3, 10, 18), (3, 23, 42), (4, 35, 49)])
+        );
+        assert_eq!(ranges(Position::new(4, 3)), Some(vec![(4, 0, 10)]));
+        assert_eq!(ranges(Position::new(3, 5)), None);
+    }
+}
@@ -0,0 +1,166 @@
+//! Shell commands run when notes are created, saved, renamed or published.
+//!
+//! A hook is run with `sh -c` (`cmd /C` on Windows) in the vault root. It gets the event and the
+//! note's path in `NOTE_LS_EVENT`, `NOTE_LS_PATH` and, for renames, `NOTE_LS_OLD_PATH`, and the
+//! same information plus the note's metadata as a JSON object on stdin.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::{Output, Stdio},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::index::Note;
+
+/// Something that happened to a note.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Event {
+    Created,
+    Saved,
+    Renamed,
+    /// The user published the note's vault with the `noteLs.publish` command, run on the note.
+    Published,
+}
+
+impl Event {
+    fn name(self) -> &'static str {
+        match self {
+            Event::Created => "created",
+            Event::Saved => "saved",
+            Event::Renamed => "renamed",
+            Event::Published => "published",
+        }
+    }
+}
+
+/// The hooks setting: a shell command for each event, if any.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Hooks {
+    pub created: Option<String>,
+    pub saved: Option<String>,
+    pub renamed: Option<String>,
+    pub published: Option<String>,
+}
+
+impl Hooks {
+    pub fn command(&self, event: Event) -> Option<&str> {
+        match event {
+            Event::Created => self.created.as_deref(),
+            Event::Saved => self.saved.as_deref(),
+            Event::Renamed => self.renamed.as_deref(),
+            Event::Published => self.published.as_deref(),
+        }
+        .filter(|command| !command.trim().is_empty())
+    }
+}
+
+/// What a hook is told about the event, on stdin.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+    pub event: Event,
+    pub path: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_path: Option<PathBuf>,
+    pub title: Option<String>,
+    pub aliases: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+impl Payload {
+    /// Describe `event` for the note at `path`, with its metadata if it's indexed.
+    pub fn new(event: Event, path: &Path, note: Option<&Note>) -> Self {
+        Self {
+            event,
+            path: path.to_path_buf(),
+            old_path: None,
+            title: note.and_then(Note::title).map(str::to_string),
+            aliases: note.map_or(Vec::new(), |note| note.frontmatter.aliases.clone()),
+            tags: note.map_or(Vec::new(), |note| {
+                note.tags.iter().map(|tag| tag.name.clone()).collect()
+            }),
+        }
+    }
+}
+
+/// The shell running `command`: `sh -c`, or `cmd /C` on Windows.
+pub fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
+
+/// Run the hook `command` in `root`, waiting for it to finish.
+pub async fn run(command: &str, root: &Path, payload: &Payload) -> io::Result<Output> {
+    let mut shell = shell(command);
+    shell
+        .current_dir(root)
+        .env("NOTE_LS_EVENT", payload.event.name())
+        .env("NOTE_LS_PATH", &payload.path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(old_path) = &payload.old_path {
+        shell.env("NOTE_LS_OLD_PATH", old_path);
+    }
+
+    let mut child = shell.spawn()?;
+    let mut stdin = child.stdin.take().ok_or(io::ErrorKind::BrokenPipe)?;
+    let json = serde_json::to_vec(payload)?;
+    // Hooks that don't read their input close stdin early; that's not an error.
+    let _ = stdin.write_all(&json).await;
+    drop(stdin);
+
+    child.wait_with_output().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn hooks_get_the_event_in_the_environment_and_on_stdin() {
+        let root = tempfile::tempdir().unwrap();
+        let note = Note::parse("---\naliases: [Gear]\n---\n# Kit\n#camping");
+        let mut payload = Payload::new(Event::Renamed, Path::new("/v/new.md"), Some(&note));
+        payload.old_path = Some(PathBuf::from("/v/old.md"));
+
+        let command = r#"echo "$NOTE_LS_EVENT $NOTE_LS_OLD_PATH $NOTE_LS_PATH"; cat"#;
+        let output = run(command, root.path(), &payload).await.unwrap();
+        let output = String::from_utf8(output.stdout).unwrap();
+        let (env, json) = output.split_once('\n').unwrap();
+        assert_eq!(env, "renamed /v/old.md /v/new.md");
+
+        let json = serde_json::from_str::<serde_json::Value>(json).unwrap();
+        assert_eq!(json["title"], "Kit");
+        assert_eq!(json["aliases"][0], "Gear");
+        assert_eq!(json["tags"][0], "camping");
+        assert_eq!(json["oldPath"], "/v/old.md");
+    }
+
+    #[test]
+    fn blank_hooks_are_ignored() {
+        let hooks = Hooks {
+            saved: Some("  ".to_string()),
+            published: Some("rsync -a . host:notes".to_string()),
+            ..Hooks::default()
+        };
+        assert_eq!(hooks.command(Event::Saved), None);
+        assert_eq!(hooks.command(Event::Created), None);
+        assert!(hooks.command(Event::Published).is_some());
+    }
+}
@@ -0,0 +1,277 @@
+use std::{fs, path::Path};
+
+use tower_lsp::lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind, Range};
+
+use crate::{
+    footnotes::{Definition, Reference},
+    frontmatter,
+    git::Commit,
+    index::Note,
+    links::Link,
+    uri,
+};
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "svg", "bmp"];
+
+/// Images up to this size are shown inline as data URLs, which clients display even when they
+/// won't load local files. Larger ones are linked to, to keep the hover small.
+const MAX_INLINE_IMAGE: u64 = 512 << 10;
+
+/// Whether `target` looks like an image file.
+pub fn is_image(target: &str) -> bool {
+    Path::new(target)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Format a byte count for humans, e.g. `12.3 KiB`.
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Build the hover for an image link.
+///
+/// `note_dir` is the directory of the note containing the link; targets are resolved against it
+/// and then against the vault `root`. When `markdown` is false the client can't display images,
+/// so only the metadata is returned.
+pub fn image_hover(link: &Link, note_dir: &Path, root: &Path, markdown: bool) -> Option<Hover> {
+    if !link.embed || !is_image(&link.decoded_path()) {
+        return None;
+    }
+
+    let path = link
+        .candidates(note_dir, root)
+        .into_iter()
+        .find(|candidate| candidate.is_file())?;
+    let metadata = fs::metadata(&path).ok()?;
+    let name = path.file_name()?.to_string_lossy();
+
+    let mut details = format!("{}: ", name);
+    if let Ok(dimensions) = imagesize::size(&path) {
+        details.push_str(&format!(
+            "{} × {} px, ",
+            dimensions.width, dimensions.height
+        ));
+    }
+    details.push_str(&format_size(metadata.len()));
+
+    let contents = if markdown {
+        let url = match metadata.len() <= MAX_INLINE_IMAGE {
+            true => aurelius::export::file_data_url(&path),
+            false => None,
+        };
+        let url = match url {
+            Some(url) => url,
+            None => uri::from_path(&path)?.to_string(),
+        };
+        MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!(
+                "![{}]({})\n\n{}",
+                link.text.as_deref().unwrap_or(""),
+                url,
+                details
+            ),
+        }
+    } else {
+        MarkupContent {
+            kind: MarkupKind::PlainText,
+            value: details,
+        }
+    };
+
+    Some(Hover {
+        contents: HoverContents::Markup(contents),
+        range: Some(link.range()),
+    })
+}
+
+/// How many lines of a note a link preview shows at most.
+const PREVIEW_LINES: usize = 10;
+
+/// The lines of `content` to preview for `link`: the section its `#heading` anchor names, or
+/// the start of the note with its frontmatter and title heading left out.
+fn preview_lines<'a>(link: &Link, content: &'a str, note: &Note) -> Vec<&'a str> {
+    let lines = content.lines();
+
+    if let Some(heading) = link.anchor().and_then(|anchor| note.find_heading(anchor)) {
+        let end = note
+            .section_end(heading)
+            .map(|end| end as usize)
+            .unwrap_or(usize::MAX);
+        return lines
+            .enumerate()
+            .take(end)
+            .skip(heading.line as usize + 1)
+            .map(|(_, line)| line)
+            .take(PREVIEW_LINES)
+            .collect();
+    }
+
+    let title_line = note
+        .headings
+        .iter()
+        .find(|heading| heading.level == 1)
+        .map(|heading| heading.line as usize);
+    lines
+        .enumerate()
+        .skip(frontmatter::body_start(content) as usize)
+        .filter(|(n, _)| Some(*n) != title_line)
+        .map(|(_, line)| line)
+        .take(PREVIEW_LINES)
+        .collect()
+}
+
+/// Build the hover previewing the note at `path`, which `link` points to.
+///
+/// The preview shows the note's title (see `Note::title`, or else its file name) followed by
+/// the first few lines of the note, or of the section named by the link's anchor.
+pub fn note_hover(link: &Link, path: &Path, content: &str, note: &Note, markdown: bool) -> Hover {
+    let title = note
+        .title()
+        .map(str::to_string)
+        .or_else(|| Some(path.file_stem()?.to_string_lossy().into_owned()))
+        .unwrap_or_default();
+    let section = link
+        .anchor()
+        .and_then(|anchor| note.find_heading(anchor))
+        .map(|heading| format!(" › {}", heading.text))
+        .unwrap_or_default();
+    let body = preview_lines(link, content, note).join("\n");
+    let body = body.trim();
+
+    let contents = if markdown {
+        MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("**{title}{section}**\n\n---\n\n{body}"),
+        }
+    } else {
+        MarkupContent {
+            kind: MarkupKind::PlainText,
+            value: format!("{title}{section}\n\n{body}"),
+        }
+    };
+
+    Hover {
+        contents: HoverContents::Markup(contents),
+        range: Some(link.range()),
+    }
+}
+
+/// Build the hover telling what `commit`, the last one to change the note or section whose
+/// heading covers `range`, was.
+pub fn commit_hover(commit: &Commit, range: Range, markdown: bool) -> Hover {
+    let kind = match markdown {
+        true => MarkupKind::Markdown,
+        false => MarkupKind::PlainText,
+    };
+    Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind,
+            value: commit.describe(markdown),
+        }),
+        range: Some(range),
+    }
+}
+
+/// Build the hover showing the text of the footnote `reference` points to, which `definition`
+/// gives.
+pub fn footnote_hover(reference: &Reference, definition: &Definition, markdown: bool) -> Hover {
+    let kind = match markdown {
+        true => MarkupKind::Markdown,
+        false => MarkupKind::PlainText,
+    };
+    Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind,
+            value: definition.text.clone(),
+        }),
+        range: Some(reference.range()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::links;
+
+    #[test]
+    fn format_size_units() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(2048), "2.0 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+    }
+
+    #[test]
+    fn note_previews_show_the_start_or_the_linked_section() {
+        let content = "# Title\nintro\n## First\none\n### Nested\ntwo\n## Second\nthree";
+        let note = Note::parse(content);
+
+        let link = &links::parse_line("[[note]]", 0)[0];
+        assert_eq!(
+            preview_lines(link, content, &note),
+            vec![
+                "intro",
+                "## First",
+                "one",
+                "### Nested",
+                "two",
+                "## Second",
+                "three"
+            ]
+        );
+
+        let link = &links::parse_line("[[note#first]]", 0)[0];
+        assert_eq!(
+            preview_lines(link, content, &note),
+            vec!["one", "### Nested", "two"]
+        );
+
+        let hover = note_hover(link, Path::new("/v/note.md"), content, &note, false);
+        let HoverContents::Markup(contents) = hover.contents else {
+            panic!("expected markup");
+        };
+        assert!(contents.value.starts_with("Title › First\n\none"));
+    }
+
+    #[test]
+    fn images_are_shown_inline() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend([0, 0, 0, 2, 0, 0, 0, 3, 8, 2, 0, 0, 0, 0, 0, 0, 0]);
+        fs::write(dir.path().join("cat.png"), &png).unwrap();
+
+        let link = &links::parse_line("![A cat](cat.png)", 0)[0];
+        let hover = image_hover(link, dir.path(), dir.path(), true).unwrap();
+        let HoverContents::Markup(contents) = hover.contents else {
+            panic!("expected markup");
+        };
+        assert!(contents
+            .value
+            .starts_with("![A cat](data:image/png;base64,iVBORw0KGgo"));
+        assert!(contents.value.ends_with("\n\ncat.png: 2 × 3 px, 33 B"));
+    }
+
+    #[test]
+    fn image_extensions() {
+        assert!(is_image("assets/diagram.PNG"));
+        assert!(is_image("photo.jpeg"));
+        assert!(!is_image("note.md"));
+        assert!(!is_image("no-extension"));
+    }
+}
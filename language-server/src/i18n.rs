@@ -0,0 +1,121 @@
+//! Translations of the text the server generates, like diagnostic messages and backlink counts.
+//!
+//! The translations are Fluent catalogs in `locales/`, embedded in the binary. Messages missing
+//! from a catalog, and locales without one, fall back to English.
+
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource, FluentValue};
+use unic_langid::LanguageIdentifier;
+
+/// The locale messages fall back to.
+pub const FALLBACK: &str = "en";
+
+/// The embedded catalogs, by locale.
+const CATALOGS: &[(&str, &str)] = &[
+    ("de", include_str!("../locales/de.ftl")),
+    ("en", include_str!("../locales/en.ftl")),
+    ("fr", include_str!("../locales/fr.ftl")),
+];
+
+fn bundle(locale: &str, catalog: &str) -> Option<FluentBundle<FluentResource>> {
+    let language = locale.parse::<LanguageIdentifier>().ok()?;
+    let resource = FluentResource::try_new(catalog.to_string()).ok()?;
+    let mut bundle = FluentBundle::new_concurrent(vec![language]);
+    // Messages go in plain text, where Unicode isolation marks show up as stray characters.
+    bundle.set_use_isolating(false);
+    bundle.add_resource(resource).ok()?;
+    Some(bundle)
+}
+
+/// The catalog for `locale`, e.g. `de-AT`, or else for its language.
+fn catalog(locale: &str) -> Option<(&'static str, &'static str)> {
+    let language = locale.parse::<LanguageIdentifier>().ok()?;
+    let full = language.to_string();
+    CATALOGS
+        .iter()
+        .find(|(name, _)| full.eq_ignore_ascii_case(name))
+        .or_else(|| {
+            CATALOGS
+                .iter()
+                .find(|(name, _)| language.language.as_str() == *name)
+        })
+        .copied()
+}
+
+/// The messages of one locale.
+pub struct Messages {
+    /// The bundle of the locale, followed by the English one if that's not it.
+    bundles: Vec<FluentBundle<FluentResource>>,
+}
+
+impl Messages {
+    pub fn new(locale: &str) -> Self {
+        let mut bundles = Vec::new();
+        if let Some((name, catalog)) = catalog(locale).filter(|(name, _)| *name != FALLBACK) {
+            bundles.extend(bundle(name, catalog));
+        }
+        let (name, catalog) = catalog(FALLBACK).expect("the fallback catalog is embedded");
+        bundles.extend(bundle(name, catalog));
+        Self { bundles }
+    }
+
+    /// The message `id` with the variables in `args` filled in, or `id` itself if no catalog has
+    /// it.
+    pub fn get(&self, id: &str, args: &[(&str, FluentValue)]) -> String {
+        let mut fluent_args = FluentArgs::new();
+        for (name, value) in args {
+            fluent_args.set(*name, value.clone());
+        }
+        for bundle in &self.bundles {
+            let Some(pattern) = bundle.get_message(id).and_then(|message| message.value()) else {
+                continue;
+            };
+            let mut errors = Vec::new();
+            return bundle
+                .format_pattern(pattern, Some(&fluent_args), &mut errors)
+                .into_owned();
+        }
+        id.to_string()
+    }
+
+    /// `count` of something, in a message that has a singular and a plural form.
+    pub fn count(&self, id: &str, count: usize) -> String {
+        self.get(id, &[("count", count.into())])
+    }
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Self::new(FALLBACK)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_fall_back_to_english() {
+        let english = Messages::default();
+        assert_eq!(english.count("backlinks", 1), "1 backlink");
+        assert_eq!(english.count("backlinks", 3), "3 backlinks");
+        assert_eq!(
+            english.get("broken-link", &[("target", "soup".into())]),
+            "No note named \"soup\""
+        );
+
+        let german = Messages::new("de-AT");
+        assert_eq!(german.count("backlinks", 2), "2 Rückverweise");
+        assert_eq!(
+            Messages::new("fr").count("open-tasks", 0),
+            "0 tâche ouverte"
+        );
+        assert_eq!(Messages::new("xx").count("backlinks", 2), "2 backlinks");
+        assert_eq!(english.get("no-such-message", &[]), "no-such-message");
+
+        // A catalog that doesn't parse would silently fall back to English.
+        for (name, catalog) in CATALOGS {
+            let resource = FluentResource::try_new(catalog.to_string());
+            assert!(resource.is_ok(), "{name} doesn't parse");
+        }
+    }
+}
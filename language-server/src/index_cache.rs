@@ -0,0 +1,243 @@
+//! The index of a vault saved between sessions, for the `indexCache` setting, so that only the
+//! notes changed since are parsed again when the server starts.
+//!
+//! The cache is a JSON file in the vault's `.note-ls` folder, which is never indexed. A cache
+//! that can't be read, or was written in another format, is ignored and written again.
+//!
+//! Since the cache holds the titles, headings, links and tags of every note, it can be encrypted
+//! with a passphrase, for vaults on shared machines. The key is derived from the passphrase with
+//! PBKDF2 and a salt of its own for each save, and the JSON is sealed with AES-256-GCM, so a
+//! cache written with another passphrase, or that was tampered with, is ignored like any other
+//! that can't be read.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::SystemTime,
+};
+
+use ring::{
+    aead::{self, Aad, LessSafeKey, Nonce, UnboundKey},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    hooks,
+    index::{Note, NoteIndex},
+};
+
+/// Where the cache goes, relative to the vault root.
+pub const CACHE_PATH: &str = ".note-ls/index.json";
+
+/// The format of the cache. Bump it whenever what the index keeps of a note changes.
+const VERSION: u32 = 2;
+
+/// What an encrypted cache starts with, before the salt, the nonce and the sealed JSON.
+const MAGIC: &[u8] = b"note-ls encrypted cache 1\n";
+
+const SALT_LEN: usize = 16;
+
+/// Rounds of PBKDF2 deriving the key from the passphrase.
+const ITERATIONS: NonZeroU32 = match NonZeroU32::new(600_000) {
+    Some(iterations) => iterations,
+    None => unreachable!(),
+};
+
+/// How the cache is kept on disk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Protection {
+    Plain,
+    /// Encrypted with a key derived from the passphrase.
+    Encrypted(String),
+}
+
+#[derive(Serialize)]
+struct Saved<'a> {
+    version: u32,
+    /// Notes by their path relative to the root, with the time their file was modified.
+    notes: Vec<(&'a Path, SystemTime, &'a Note)>,
+}
+
+#[derive(Deserialize)]
+struct Loaded {
+    version: u32,
+    notes: Vec<(PathBuf, SystemTime, Note)>,
+}
+
+/// The key derived from `passphrase` with `salt`.
+fn key(passphrase: &str, salt: &[u8]) -> LessSafeKey {
+    let mut key = [0; 32];
+    let algorithm = pbkdf2::PBKDF2_HMAC_SHA256;
+    pbkdf2::derive(algorithm, ITERATIONS, salt, passphrase.as_bytes(), &mut key);
+    let key = UnboundKey::new(&aead::AES_256_GCM, &key).expect("AES-256 keys are 32 bytes");
+    LessSafeKey::new(key)
+}
+
+/// `plain` encrypted with `passphrase`.
+fn encrypt(mut plain: Vec<u8>, passphrase: &str) -> io::Result<Vec<u8>> {
+    let random = SystemRandom::new();
+    let mut salt = [0; SALT_LEN];
+    let mut nonce = [0; aead::NONCE_LEN];
+    random
+        .fill(&mut salt)
+        .and_then(|()| random.fill(&mut nonce))
+        .map_err(|_| io::Error::other("no random numbers for the salt"))?;
+    key(passphrase, &salt)
+        .seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(MAGIC),
+            &mut plain,
+        )
+        .map_err(|_| io::Error::other("couldn't encrypt the cache"))?;
+    Ok([MAGIC, &salt, &nonce, &plain].concat())
+}
+
+/// The contents of the cache `content` encrypted with `passphrase`, if it was.
+fn decrypt(content: &[u8], passphrase: &str) -> Option<Vec<u8>> {
+    let rest = content.strip_prefix(MAGIC)?;
+    let (salt, rest) = rest.split_at_checked(SALT_LEN)?;
+    let (nonce, sealed) = rest.split_at_checked(aead::NONCE_LEN)?;
+    let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+    let mut sealed = sealed.to_vec();
+    let plain = key(passphrase, salt)
+        .open_in_place(nonce, Aad::from(MAGIC), &mut sealed)
+        .ok()?;
+    Some(plain.to_vec())
+}
+
+/// The passphrase `command` prints, e.g. `secret-tool lookup service note-ls` to read it from
+/// the keyring. The line break at the end of its output is left out.
+pub async fn passphrase_from(command: &str) -> io::Result<String> {
+    let output = hooks::shell(command)
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "`{command}` failed ({}): {}",
+            output.status,
+            stderr.trim()
+        )));
+    }
+    let passphrase = String::from_utf8(output.stdout).map_err(io::Error::other)?;
+    let passphrase = passphrase.trim_end_matches(['\r', '\n']);
+    match passphrase.is_empty() {
+        true => Err(io::Error::other(format!(
+            "`{command}` printed no passphrase"
+        ))),
+        false => Ok(passphrase.to_string()),
+    }
+}
+
+/// The notes cached for the vault at `root`, by path with the time their file was modified, or
+/// none if there's no usable cache. A cache that isn't kept as `protection` says isn't usable.
+pub fn load(root: &Path, protection: &Protection) -> HashMap<PathBuf, (SystemTime, Note)> {
+    let Ok(content) = fs::read(root.join(CACHE_PATH)) else {
+        return HashMap::new();
+    };
+    let content = match protection {
+        Protection::Plain => content,
+        Protection::Encrypted(passphrase) => match decrypt(&content, passphrase) {
+            Some(content) => content,
+            None => return HashMap::new(),
+        },
+    };
+    match serde_json::from_slice::<Loaded>(&content) {
+        Ok(loaded) if loaded.version == VERSION => loaded
+            .notes
+            .into_iter()
+            .map(|(path, modified, note)| (root.join(path), (modified, note)))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+/// Save the notes of `index` that were parsed from disk to the cache of its vault, kept as
+/// `protection` says.
+pub fn save(index: &NoteIndex, protection: &Protection) -> io::Result<()> {
+    let root = index.root();
+    let notes = index
+        .disk_notes()
+        .filter_map(|(path, modified, note)| Some((path.strip_prefix(root).ok()?, modified, note)))
+        .collect();
+    let json = serde_json::to_vec(&Saved {
+        version: VERSION,
+        notes,
+    })?;
+    let content = match protection {
+        Protection::Plain => json,
+        Protection::Encrypted(passphrase) => encrypt(json, passphrase)?,
+    };
+
+    let path = root.join(CACHE_PATH);
+    if let Some(folder) = path.parent() {
+        fs::create_dir_all(folder)?;
+    }
+    // Replace the old cache in one go, so a crash can't leave half of it behind.
+    let partial = path.with_extension("json.partial");
+    fs::write(&partial, content)?;
+    fs::rename(partial, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_notes_are_loaded() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::write(root.join("a.md"), "# A\n[[b#Part]] #tag").unwrap();
+        fs::write(root.join("b.md"), "---\ntitle: B\n---\n## Part").unwrap();
+        let index = NoteIndex::scan(root);
+
+        assert!(load(root, &Protection::Plain).is_empty());
+        save(&index, &Protection::Plain).unwrap();
+        let loaded = load(root, &Protection::Plain);
+
+        assert_eq!(loaded.len(), 2);
+        let (modified, note) = &loaded[&root.join("a.md")];
+        assert_eq!(
+            *modified,
+            fs::metadata(root.join("a.md")).unwrap().modified().unwrap()
+        );
+        assert_eq!(note.links, index.get(&root.join("a.md")).unwrap().links);
+        assert_eq!(note.tags[0].name, "tag");
+        assert_eq!(loaded[&root.join("b.md")].1.title(), Some("B"));
+
+        fs::write(root.join(CACHE_PATH), r#"{"version":0,"notes":[]}"#).unwrap();
+        assert!(load(root, &Protection::Plain).is_empty());
+    }
+
+    #[test]
+    fn encrypted_caches_need_the_passphrase() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::write(root.join("a.md"), "# Secret plans\n#private").unwrap();
+        let index = NoteIndex::scan(root);
+        let encrypted = Protection::Encrypted(String::from("correct horse"));
+
+        save(&index, &encrypted).unwrap();
+        let content = fs::read(root.join(CACHE_PATH)).unwrap();
+        assert!(content.starts_with(MAGIC));
+        let text = String::from_utf8_lossy(&content);
+        assert!(!text.contains("Secret") && !text.contains("private"));
+
+        let loaded = load(root, &encrypted);
+        assert_eq!(loaded[&root.join("a.md")].1.tags[0].name, "private");
+        let wrong = Protection::Encrypted(String::from("wrong horse"));
+        assert!(load(root, &wrong).is_empty());
+        assert!(load(root, &Protection::Plain).is_empty());
+
+        // A plain cache isn't trusted once the cache is to be encrypted.
+        save(&index, &Protection::Plain).unwrap();
+        assert!(load(root, &encrypted).is_empty());
+    }
+}
@@ -0,0 +1,117 @@
+//! The `noteLs/indexChanged` notification, which tells clients how the index changed so they can
+//! keep file trees, tag panels and graph views in sync without asking again.
+
+use std::{collections::BTreeSet, path::Path};
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{notification::Notification, Url};
+
+use crate::{
+    index::{Note, NoteIndex, Renames},
+    uri,
+};
+
+pub enum IndexChanged {}
+
+impl Notification for IndexChanged {
+    type Params = IndexChangedParams;
+    const METHOD: &'static str = "noteLs/indexChanged";
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteRenamed {
+    pub old_uri: Url,
+    pub new_uri: Url,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexChangedParams {
+    pub added: Vec<Url>,
+    pub removed: Vec<Url>,
+    pub renamed: Vec<NoteRenamed>,
+    /// Notes whose tags changed, without being added or removed.
+    pub tags_changed: Vec<Url>,
+    /// Whether the whole vault was indexed again, so anything could have changed.
+    pub reindexed: bool,
+}
+
+fn tag_names(note: &Note) -> BTreeSet<&str> {
+    note.tags.iter().map(|tag| tag.name.as_str()).collect()
+}
+
+impl IndexChangedParams {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Record the note at `path` being replaced by `note`, or removed if there's none, before the
+    /// change is made to `index`.
+    pub fn note_updated(&mut self, index: &NoteIndex, path: &Path, note: Option<&Note>) {
+        let Some(uri) = uri::from_path(path) else {
+            return;
+        };
+        match (index.get(path), note) {
+            (None, Some(_)) => self.added.push(uri),
+            (Some(_), None) => self.removed.push(uri),
+            (Some(old), Some(new)) if tag_names(old) != tag_names(new) => {
+                self.tags_changed.push(uri)
+            }
+            _ => (),
+        }
+    }
+
+    /// Record the notes `renames` moves, before they're applied to `index`.
+    pub fn notes_renamed(&mut self, index: &NoteIndex, renames: &Renames) {
+        let mut renamed = index
+            .notes()
+            .filter_map(|(path, _)| {
+                Some(NoteRenamed {
+                    old_uri: uri::from_path(path)?,
+                    new_uri: uri::from_path(&renames.map(path)?)?,
+                })
+            })
+            .collect::<Vec<_>>();
+        renamed.sort_by(|a, b| a.old_uri.cmp(&b.old_uri));
+        self.renamed.extend(renamed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn changes_are_recorded_before_they_are_made() {
+        let mut index = NoteIndex::from_paths(Path::new("/vault"), Vec::new());
+        index.update("/vault/a.md".into(), "#one");
+        index.update("/vault/b.md".into(), "#one");
+        let uri = |path: &str| Url::from_file_path(path).unwrap();
+
+        let mut changes = IndexChangedParams::default();
+        changes.note_updated(&index, Path::new("/vault/a.md"), Some(&Note::parse("#two")));
+        changes.note_updated(
+            &index,
+            Path::new("/vault/b.md"),
+            Some(&Note::parse("#one text")),
+        );
+        changes.note_updated(&index, Path::new("/vault/c.md"), Some(&Note::parse("")));
+        changes.note_updated(&index, Path::new("/vault/d.md"), None);
+        let renames = Renames::new(vec![(PathBuf::from("/vault"), PathBuf::from("/notes"))]);
+        changes.notes_renamed(&index, &renames);
+
+        assert_eq!(changes.added, vec![uri("/vault/c.md")]);
+        assert!(changes.removed.is_empty());
+        assert_eq!(changes.tags_changed, vec![uri("/vault/a.md")]);
+        assert_eq!(
+            changes.renamed[1],
+            NoteRenamed {
+                old_uri: uri("/vault/b.md"),
+                new_uri: uri("/notes/b.md"),
+            }
+        );
+    }
+}
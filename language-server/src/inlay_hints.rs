@@ -0,0 +1,103 @@
+//! Inlay hints after headings with the length of their section, for `textDocument/inlayHint`:
+//! about how many words it has, and how many minutes they take to read.
+//!
+//! A section runs to the next heading of the same or a higher level, so it includes its
+//! subsections. Comments aren't counted, like in the note's word count.
+
+use tower_lsp::lsp_types::{InlayHint, InlayHintLabel, Position, Range};
+
+use crate::{comments, headings, i18n::Messages, note_info};
+
+/// The line of each heading of `document` of `max_level` or above, with the number of words in
+/// its section.
+pub fn section_lengths(document: &str, max_level: u8) -> Vec<(u32, usize)> {
+    let blanked = comments::blank(document);
+    let lines = blanked.lines().collect::<Vec<_>>();
+    let headings = headings::parse_headings(document);
+    headings
+        .iter()
+        .enumerate()
+        .filter(|(_, heading)| heading.level <= max_level)
+        .map(|(i, heading)| {
+            let end = headings[i + 1..]
+                .iter()
+                .find(|next| next.level <= heading.level)
+                .map_or(lines.len(), |next| next.line as usize);
+            let start = heading.line as usize + 1;
+            let words = note_info::count_words(lines[start.min(end)..end].iter().copied());
+            (heading.line, words)
+        })
+        .collect()
+}
+
+/// The hints after the headings of `document` on the lines of `range`, for headings of
+/// `max_level` or above, reading `words_per_minute`. Empty sections get none.
+pub fn section_hints(
+    document: &str,
+    range: Range,
+    max_level: u8,
+    words_per_minute: usize,
+    messages: &Messages,
+) -> Vec<InlayHint> {
+    let lines = document.lines().collect::<Vec<_>>();
+    section_lengths(document, max_level)
+        .into_iter()
+        .filter(|(line, words)| *words > 0 && range.start.line <= *line && *line <= range.end.line)
+        .map(|(line, words)| {
+            let minutes = words.div_ceil(words_per_minute.max(1));
+            // Counts are rounded past a hundred, as they're only meant to give an idea.
+            let words = match words {
+                0..=99 => words,
+                _ => (words + 5) / 10 * 10,
+            };
+            let args = [("words", words.into()), ("minutes", minutes.into())];
+            let end = lines.get(line as usize).map_or(0, |text| text.len());
+            InlayHint {
+                position: Position::new(line, end as u32),
+                label: InlayHintLabel::String(messages.get("section-length", &args)),
+                kind: None,
+                text_edits: None,
+                tooltip: None,
+                padding_left: Some(true),
+                padding_right: None,
+                data: None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_are_counted_with_their_subsections() {
+        let body = "word ".repeat(339);
+        let document = format!(
+            "---\ntitle: Not counted\n---\n# One\n{body}\n## Two\nthree more words\n\
+             %% not counted %%\n# Empty\n\n# Last\none"
+        );
+        assert_eq!(
+            section_lengths(&document, 2),
+            [(3, 343), (5, 3), (8, 0), (10, 1)]
+        );
+        assert_eq!(section_lengths(&document, 1), [(3, 343), (8, 0), (10, 1)]);
+
+        let everything = Range::new(Position::new(0, 0), Position::new(11, 0));
+        let hints = section_hints(&document, everything, 1, 200, &Messages::default());
+        let labels = hints
+            .iter()
+            .map(|hint| match &hint.label {
+                InlayHintLabel::String(label) => (hint.position, label.as_str()),
+                InlayHintLabel::LabelParts(_) => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels,
+            [
+                (Position::new(3, 5), "≈ 340 words · 2 min"),
+                (Position::new(10, 6), "≈ 1 word · 1 min"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! A language server for markdown notes.
+//!
+//! `server` implements the LSP on top of `note_ls_core`, which parses notes and indexes the vault
+//! independently of the protocol. Its modules are re-exported here so the rest of the server can
+//! use them as `crate::links`, `crate::index` and so on.
+
+pub use note_ls_core::{
+    blocks, comments, compare, contents, fences, footnotes, frontmatter, graph, headings, ignore,
+    index, links, report, search, tags, uri,
+};
+
+pub mod attachments;
+pub mod backup;
+pub mod badges;
+pub mod books;
+pub mod bundle;
+pub mod call_hierarchy;
+pub mod charts;
+pub mod citations;
+pub mod code_actions;
+pub mod code_blocks;
+pub mod code_lens;
+pub mod commands;
+pub mod completion;
+pub mod config;
+pub mod daily_notes;
+pub mod dates;
+pub mod diagnostics;
+pub mod diagrams;
+pub mod document_links;
+pub mod encoding;
+pub mod excerpt;
+pub mod export;
+pub mod flatten;
+pub mod focus;
+pub mod folding;
+pub mod footnote_links;
+pub mod git;
+pub mod habits;
+pub mod highlights;
+pub mod hooks;
+pub mod hover;
+pub mod i18n;
+pub mod index_cache;
+pub mod index_changes;
+pub mod inlay_hints;
+pub mod link_checker;
+pub mod link_style;
+pub mod lists;
+pub mod logging;
+pub mod metrics;
+pub mod new_notes;
+pub mod note_info;
+pub mod plugins;
+pub mod preview;
+pub mod progress;
+pub mod publish;
+pub mod reindex;
+pub mod rename;
+pub mod semantic_tokens;
+pub mod server;
+pub mod spelling;
+pub mod stats;
+pub mod symbols;
+pub mod syntax;
+pub mod tables;
+pub mod tasks;
+pub mod templates;
+pub mod timeout;
+pub mod toc;
+pub mod vault_setup;
+pub mod vaults;
+pub mod watchdog;
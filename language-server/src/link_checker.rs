@@ -0,0 +1,243 @@
+//! Checking the external links of notes, for the `checkExternalLinks` setting and the
+//! `noteLs.links.check` command, and for the dead URLs of the link report.
+//!
+//! URLs are checked in the background, waiting between requests to the same host, and what's
+//! found is kept for a while, so saving a note again doesn't send its hosts the same requests.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tower_lsp::lsp_types::Url;
+
+use crate::{index::NoteIndex, links::Link};
+
+/// How long a request may take before the URL is taken to be broken.
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// How long what's found of a URL is kept.
+const CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+/// How long to wait between requests to the same host.
+const HOST_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct LinkChecker {
+    agent: ureq::Agent,
+    /// When each URL was checked, and why it's broken, if it is.
+    results: Mutex<HashMap<String, (Instant, Option<String>)>>,
+    /// When each host was last sent a request, or will be by a check waiting its turn.
+    hosts: Mutex<HashMap<String, Instant>>,
+}
+
+impl Default for LinkChecker {
+    fn default() -> Self {
+        Self::new(TIMEOUT)
+    }
+}
+
+impl LinkChecker {
+    /// A checker taking URLs that don't answer within `timeout` to be broken.
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+            results: Mutex::new(HashMap::new()),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Why `url` is broken, like "404 Not Found", if it was found to be lately.
+    pub fn broken(&self, url: &str) -> Option<String> {
+        let results = self.results.lock().unwrap();
+        let (checked, problem) = results.get(url)?;
+        (checked.elapsed() < CACHE_TTL)
+            .then(|| problem.clone())
+            .flatten()
+    }
+
+    fn checked_lately(&self, url: &str) -> bool {
+        let results = self.results.lock().unwrap();
+        results
+            .get(url)
+            .is_some_and(|(checked, _)| checked.elapsed() < CACHE_TTL)
+    }
+
+    /// Check the `urls` that weren't checked lately, or all of them if `recheck` is set, and
+    /// return whether any of them broke or got fixed.
+    pub async fn check(&self, urls: Vec<String>, recheck: bool) -> bool {
+        let mut changed = false;
+        for url in urls {
+            if !recheck && self.checked_lately(&url) {
+                continue;
+            }
+            let Some(host) = Url::parse(&url)
+                .ok()
+                .and_then(|parsed| parsed.host_str().map(str::to_string))
+            else {
+                continue;
+            };
+            self.wait_for(&host).await;
+            let agent = self.agent.clone();
+            let request_url = url.clone();
+            let problem = tokio::task::spawn_blocking(move || problem(&agent, &request_url))
+                .await
+                .unwrap_or(None);
+            let old = self
+                .results
+                .lock()
+                .unwrap()
+                .insert(url, (Instant::now(), problem.clone()));
+            changed |= old.map(|(_, old)| old) != Some(problem);
+        }
+        changed
+    }
+
+    /// The `urls` found broken, after checking the ones that weren't checked lately.
+    pub async fn broken_among(&self, urls: Vec<String>) -> HashSet<String> {
+        self.check(urls.clone(), false).await;
+        urls.into_iter()
+            .filter(|url| self.broken(url).is_some())
+            .collect()
+    }
+
+    /// Wait until `host` may be sent another request. Each check takes the next turn before
+    /// waiting for it, so however many notes are saved at once, a host gets one request at a
+    /// time, while checks of other hosts go ahead.
+    async fn wait_for(&self, host: &str) {
+        let turn = {
+            let mut hosts = self.hosts.lock().unwrap();
+            let now = Instant::now();
+            let turn = hosts
+                .get(host)
+                .map_or(now, |last| (*last + HOST_INTERVAL).max(now));
+            hosts.insert(host.to_string(), turn);
+            turn
+        };
+        tokio::time::sleep_until(turn.into()).await;
+    }
+}
+
+/// What's wrong with `url`, like "404 Not Found" or "timed out reading response", or `None` if
+/// it loads. Pages that turn the checker away, like ones behind a login, aren't broken.
+fn problem(agent: &ureq::Agent, url: &str) -> Option<String> {
+    let response = match agent.head(url).call() {
+        // Some servers don't support HEAD, so retry with GET before giving up.
+        Err(ureq::Error::Status(405, _)) => agent.get(url).call(),
+        response => response,
+    };
+    match response {
+        Ok(_) | Err(ureq::Error::Status(401 | 403 | 429, _)) => None,
+        Err(ureq::Error::Status(code, response)) => {
+            Some(format!("{code} {}", response.status_text()))
+        }
+        Err(ureq::Error::Transport(transport)) => Some(
+            transport
+                .message()
+                .map_or_else(|| transport.kind().to_string(), str::to_string),
+        ),
+    }
+}
+
+/// Whether `url` is on one of the `hosts` never checked, or a subdomain of one.
+pub fn is_allowed(url: &str, hosts: &[String]) -> bool {
+    let Some(host) = Url::parse(url)
+        .ok()
+        .and_then(|parsed| parsed.host_str().map(str::to_lowercase))
+    else {
+        return false;
+    };
+    hosts.iter().any(|allowed| {
+        let allowed = allowed.trim().to_lowercase();
+        host == allowed || host.ends_with(&format!(".{allowed}"))
+    })
+}
+
+/// The `http` and `https` URLs `links` point at that should be checked: all but the ones on
+/// the `allowed` hosts.
+pub fn urls_to_check(links: &[Link], allowed: &[String]) -> Vec<String> {
+    let mut urls = links
+        .iter()
+        .filter(|link| link.target.starts_with("http://") || link.target.starts_with("https://"))
+        .map(|link| link.target.clone())
+        .filter(|url| !is_allowed(url, allowed))
+        .collect::<Vec<_>>();
+    urls.sort();
+    urls.dedup();
+    urls
+}
+
+/// The URLs to check of every note in `index`, as with [`urls_to_check`].
+pub fn vault_urls_to_check(index: &NoteIndex, allowed: &[String]) -> Vec<String> {
+    let mut urls = index
+        .notes()
+        .flat_map(|(_, note)| urls_to_check(&note.links, allowed))
+        .collect::<Vec<_>>();
+    urls.sort();
+    urls.dedup();
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use super::*;
+
+    /// Serve 404 for `/gone` and 200 for everything else, counting requests.
+    fn serve() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(&stream);
+                reader.read_line(&mut request_line).unwrap();
+                while reader.read_line(&mut String::new()).unwrap() > 2 {}
+                counted.fetch_add(1, Ordering::SeqCst);
+                let status = match request_line.contains("/gone") {
+                    true => "404 Not Found",
+                    false => "200 OK",
+                };
+                let response = format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\n\r\n");
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (base, requests)
+    }
+
+    #[tokio::test]
+    async fn broken_links_are_found_and_remembered() {
+        let (base, requests) = serve();
+        let checker = LinkChecker::default();
+        let urls = vec![format!("{base}/gone"), format!("{base}/here")];
+
+        assert!(checker.check(urls.clone(), false).await);
+        assert_eq!(checker.broken(&urls[0]).as_deref(), Some("404 Not Found"));
+        assert_eq!(checker.broken(&urls[1]), None);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        // Checked lately, so the host isn't asked again unless told to.
+        assert!(!checker.check(urls.clone(), false).await);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert!(!checker.check(urls[..1].to_vec(), true).await);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn allowed_hosts_cover_subdomains() {
+        let hosts = vec!["example.com".to_string()];
+        assert!(is_allowed("https://example.com/a", &hosts));
+        assert!(is_allowed("https://docs.Example.com/a", &hosts));
+        assert!(!is_allowed("https://notexample.com/a", &hosts));
+    }
+}
@@ -0,0 +1,241 @@
+//! Converting links between wiki links, `[[note|alias]]`, and markdown links, `[alias](note.md)`,
+//! so vaults can move from one style to the other a link or a note at a time.
+//!
+//! Converted links point to the same file: markdown links by its path relative to the note,
+//! percent-encoded and with its extension, and wiki links by the name the vault's link resolution
+//! finds it by, without the extension of a note. Markdown links name headings by their anchor and
+//! wiki links by their text. Links that don't resolve keep their target as written.
+
+use std::path::Path;
+
+use tower_lsp::lsp_types::TextEdit;
+
+use crate::{
+    code_actions, code_blocks, comments, diagnostics, export, headings,
+    index::{Note, NoteIndex, Resolution},
+    links::{self, Link, LinkKind},
+};
+
+/// The markdown link the wiki `link` in `note`, the note at `path`, becomes.
+pub fn to_markdown(link: &Link, path: &Path, note: &Note, index: &NoteIndex) -> Option<String> {
+    if link.kind != LinkKind::Wiki {
+        return None;
+    }
+    let text = match link.embed {
+        true => diagnostics::alt_text(link).unwrap_or_default(),
+        false => link.alias().unwrap_or_else(|| match link.target_path() {
+            "" => link.anchor().unwrap_or_default(),
+            target => target,
+        }),
+    };
+
+    let (mut destination, anchor) = match link.target_path() {
+        "" => (String::new(), export::heading_anchor(link, Some(note))),
+        target_path => match export::wiki_target(path, link, index) {
+            Some(target) => {
+                let note_dir = path.parent().unwrap_or(index.root());
+                let relative = links::relative_path(note_dir, &target);
+                let anchor = export::heading_anchor(link, index.get(&target));
+                (
+                    links::encode_target(&links::path_to_target(&relative)),
+                    anchor,
+                )
+            }
+            None => {
+                let mut target = target_path.to_string();
+                if Path::new(target_path).extension().is_none() {
+                    if let Some(extension) = index.extensions().first() {
+                        target = format!("{target}.{extension}");
+                    }
+                }
+                (links::encode_target(&target), None)
+            }
+        },
+    };
+    // Anchors of headings that aren't found are made the way renderers make them, except for
+    // references to blocks, which have no anchor of their own.
+    let anchor = anchor.or_else(|| {
+        link.anchor().map(|anchor| match anchor.starts_with('^') {
+            true => anchor.to_string(),
+            false => headings::slugify(anchor),
+        })
+    });
+    if let Some(anchor) = anchor {
+        destination.push('#');
+        destination.push_str(&anchor);
+    }
+
+    let embed = if link.embed { "!" } else { "" };
+    Some(format!("{embed}[{text}]({destination})"))
+}
+
+/// The shortest target a wiki link from the note at `path` finds `resolved` by: its path
+/// relative to the note or to the vault root, or its name alone if the vault resolves names.
+fn wiki_target(path: &Path, resolved: &Path, index: &NoteIndex) -> String {
+    let note_dir = path.parent().unwrap_or(index.root());
+    let target = |base: &Path| match index.is_note(resolved) {
+        true => code_actions::wiki_target(base, resolved),
+        false => links::path_to_target(&links::relative_path(base, resolved)),
+    };
+    let mut targets = vec![target(index.root())];
+    if index.resolution() != Resolution::Absolute {
+        targets.push(target(note_dir));
+    }
+    if index.resolution() == Resolution::Shortest {
+        targets.extend(target(note_dir).rsplit('/').next().map(str::to_string));
+    }
+    let finds = |target: &String| {
+        links::parse_line(&format!("[[{target}]]"), 0)
+            .first()
+            .and_then(|link| index.resolve(path, link))
+            .is_some_and(|found| found == resolved)
+    };
+    let fallback = targets[0].clone();
+    targets
+        .into_iter()
+        .filter(finds)
+        .min_by_key(String::len)
+        .unwrap_or(fallback)
+}
+
+/// Whether `text` can go in a wiki link without ending it or starting its alias.
+fn fits_wiki_link(text: &str) -> bool {
+    !text.contains(['|', '[', ']'])
+}
+
+/// The wiki link the markdown `link` in `note`, the note at `path`, becomes, unless it's external
+/// or has text or a target that can't be written in a wiki link.
+pub fn to_wiki(link: &Link, path: &Path, note: &Note, index: &NoteIndex) -> Option<String> {
+    if link.kind != LinkKind::Markdown || link.is_external() {
+        return None;
+    }
+    let (target, linked) = match link.target_path() {
+        "" => (String::new(), Some(note)),
+        _ => match index.resolve(path, link) {
+            Some(resolved) => (wiki_target(path, &resolved, index), index.get(&resolved)),
+            None => {
+                let decoded = link.decoded_path();
+                let target = match index.is_note(Path::new(decoded.as_ref())) {
+                    true => links::path_to_target(&Path::new(decoded.as_ref()).with_extension("")),
+                    false => decoded.into_owned(),
+                };
+                (target, None)
+            }
+        },
+    };
+    let anchor = link.anchor().map(|anchor| {
+        let anchor = links::decode_target(anchor);
+        match linked.and_then(|note| note.find_heading(&anchor)) {
+            Some(heading) => heading.text.clone(),
+            None => anchor.into_owned(),
+        }
+    });
+    if target.is_empty() && anchor.is_none() {
+        return None;
+    }
+
+    // Text that only repeats what the wiki link shows anyway isn't kept as an alias.
+    let text = link.text.as_deref().unwrap_or_default().trim();
+    let shown = match &anchor {
+        Some(anchor) if target.is_empty() => anchor.as_str(),
+        _ => target.as_str(),
+    };
+    let alias = (!text.is_empty() && text != shown).then_some(text);
+
+    let mut wiki = target;
+    if let Some(anchor) = &anchor {
+        wiki.push('#');
+        wiki.push_str(anchor);
+    }
+    if !fits_wiki_link(&wiki) || !alias.is_none_or(fits_wiki_link) {
+        return None;
+    }
+    if let Some(alias) = alias {
+        wiki.push('|');
+        wiki.push_str(alias);
+    }
+    let embed = if link.embed { "!" } else { "" };
+    Some(format!("{embed}[[{wiki}]]"))
+}
+
+/// The edits converting every link in `document`, the note at `path`, to the `style` it doesn't
+/// have yet. Links in comments and code blocks are left alone.
+pub fn convert_all(
+    document: &str,
+    path: &Path,
+    index: &NoteIndex,
+    style: LinkKind,
+) -> Vec<TextEdit> {
+    let note = Note::parse(document);
+    let blocks = code_blocks::blocks(document);
+    links::parse_links(&comments::blank(document))
+        .iter()
+        .filter(|link| {
+            !blocks
+                .iter()
+                .any(|block| block.start <= link.line && link.line <= block.end)
+        })
+        .filter_map(|link| {
+            let new = match style {
+                LinkKind::Markdown => to_markdown(link, path, &note, index),
+                LinkKind::Wiki => to_wiki(link, path, &note, index),
+            }?;
+            Some(TextEdit::new(link.range(), new))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn links_are_converted_both_ways() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::create_dir_all(root.join("notes/images")).unwrap();
+        fs::write(root.join("notes/images/cat.png"), "").unwrap();
+        fs::write(root.join("other note.md"), "# Usage\n# Usage\n").unwrap();
+        let content = "# Setup\n[[other note#Usage|Other]] and [[gone#Some Part]]\n\
+                       ![[images/cat.png|A cat]] [[#Setup]]\n\
+                       ```\n[[code]]\n```\n";
+        fs::write(root.join("notes/note.md"), content).unwrap();
+        let index = NoteIndex::scan(root);
+        let path = root.join("notes/note.md");
+
+        let edits = convert_all(content, &path, &index, LinkKind::Markdown);
+        let markdown = edits
+            .iter()
+            .map(|edit| edit.new_text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            markdown,
+            [
+                "[Other](../other%20note.md#usage)",
+                "[gone](gone.md#some-part)",
+                "![A cat](images/cat.png)",
+                "[Setup](#setup)",
+            ]
+        );
+
+        let converted =
+            "# Setup\n[Other](../other%20note.md#usage) and [gone](gone.md#some-part)\n\
+                         ![A cat](images/cat.png) [Setup](#setup) [site](https://example.com)\n";
+        let edits = convert_all(converted, &path, &index, LinkKind::Wiki);
+        let wiki = edits
+            .iter()
+            .map(|edit| edit.new_text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            wiki,
+            [
+                "[[other note#Usage|Other]]",
+                "[[gone#some-part]]",
+                "![[images/cat.png|A cat]]",
+                "[[#Setup]]",
+            ]
+        );
+    }
+}
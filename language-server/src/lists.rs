@@ -0,0 +1,250 @@
+//! Continuing lists and blockquotes when Enter is pressed, for `textDocument/onTypeFormatting`.
+//!
+//! The new line gets the prefix of the one before: its `>` markers, indentation and list marker,
+//! numbered one more for ordered lists, with an empty checkbox for tasks. Pressing Enter on an
+//! item with nothing in it ends the list instead, removing the item.
+
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+use crate::{code_blocks, frontmatter};
+
+/// A list marker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Marker {
+    Bullet(char),
+    Ordered { number: u64, delimiter: char },
+}
+
+/// What a line starts with, before its text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Prefix<'a> {
+    /// The `>` markers of blockquotes, with the spaces after them.
+    quote: &'a str,
+    indent: &'a str,
+    marker: Option<Marker>,
+    /// The byte offset of the marker's number, or of the bullet.
+    marker_start: usize,
+    task: bool,
+    /// The text after the prefix.
+    text: &'a str,
+}
+
+impl Prefix<'_> {
+    /// The prefix of the line after this one.
+    fn next(&self) -> String {
+        let marker = match self.marker {
+            Some(Marker::Bullet(bullet)) => format!("{bullet} "),
+            Some(Marker::Ordered { number, delimiter }) => format!("{}{delimiter} ", number + 1),
+            None => String::new(),
+        };
+        let task = if self.task { "[ ] " } else { "" };
+        format!("{}{}{marker}{task}", self.quote, self.indent)
+    }
+}
+
+/// The list marker `text` starts with, and the text after it.
+fn marker(text: &str) -> Option<(Marker, &str)> {
+    let digits = text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let (marker, rest) = match digits {
+        0 => {
+            let bullet = text
+                .chars()
+                .next()
+                .filter(|c| matches!(c, '-' | '*' | '+'))?;
+            (Marker::Bullet(bullet), &text[1..])
+        }
+        1..=9 => {
+            let delimiter = text[digits..]
+                .chars()
+                .next()
+                .filter(|c| matches!(c, '.' | ')'))?;
+            let number = text[..digits].parse().ok()?;
+            (Marker::Ordered { number, delimiter }, &text[digits + 1..])
+        }
+        _ => return None,
+    };
+    // The marker has to be followed by a space, unless the item is empty.
+    match rest.strip_prefix([' ', '\t']) {
+        Some(rest) => Some((marker, rest.trim_start_matches([' ', '\t']))),
+        None => rest.is_empty().then_some((marker, rest)),
+    }
+}
+
+/// The prefix of `line`.
+fn parse(line: &str) -> Prefix<'_> {
+    let mut rest = line;
+    loop {
+        let trimmed = rest.trim_start_matches(' ');
+        match trimmed.strip_prefix('>') {
+            Some(after) if rest.len() - trimmed.len() <= 3 => {
+                rest = after.strip_prefix(' ').unwrap_or(after);
+            }
+            _ => break,
+        }
+    }
+    let quote = &line[..line.len() - rest.len()];
+    let text = rest.trim_start_matches([' ', '\t']);
+    let indent = &rest[..rest.len() - text.len()];
+    let marker_start = line.len() - text.len();
+    let Some((marker, text)) = marker(text) else {
+        return Prefix {
+            quote,
+            indent,
+            marker: None,
+            marker_start,
+            task: false,
+            text,
+        };
+    };
+    let (task, text) = match text.get(..3) {
+        Some("[ ]" | "[x]" | "[X]") if text[3..].is_empty() || text[3..].starts_with(' ') => {
+            (true, text[3..].trim_start())
+        }
+        _ => (false, text),
+    };
+    Prefix {
+        quote,
+        indent,
+        marker: Some(marker),
+        marker_start,
+        task,
+        text,
+    }
+}
+
+/// Whether `line` starts a list item.
+pub fn is_item(line: &str) -> bool {
+    parse(line).marker.is_some()
+}
+
+/// The byte offset where the text of `line` starts, after its `>` markers, indentation, list
+/// marker and checkbox.
+pub fn text_start(line: &str) -> usize {
+    line.len() - parse(line).text.len()
+}
+
+/// The edits numbering the items after `line` of the ordered list it starts with `prefix`,
+/// following on from `number`.
+fn renumber(lines: &[&str], line: usize, prefix: &Prefix, mut number: u64) -> Vec<TextEdit> {
+    let Some(Marker::Ordered { delimiter, .. }) = prefix.marker else {
+        return Vec::new();
+    };
+    let mut edits = Vec::new();
+    for (n, text) in lines.iter().enumerate().skip(line + 1) {
+        if text.trim().is_empty() {
+            continue;
+        }
+        let item = parse(text);
+        if item.quote != prefix.quote || item.indent.len() < prefix.indent.len() {
+            break;
+        }
+        // Lines indented further are nested in an item.
+        if item.indent.len() > prefix.indent.len() {
+            continue;
+        }
+        let Some(Marker::Ordered {
+            number: old,
+            delimiter: other,
+        }) = item.marker
+        else {
+            break;
+        };
+        if other != delimiter {
+            break;
+        }
+        number += 1;
+        if old != number {
+            let start = item.marker_start as u32;
+            let end = start + old.to_string().len() as u32;
+            edits.push(TextEdit::new(
+                Range::new(Position::new(n as u32, start), Position::new(n as u32, end)),
+                number.to_string(),
+            ));
+        }
+    }
+    edits
+}
+
+/// The edits after Enter was pressed in `document`, with the cursor now at `position`: the
+/// prefix of the line before, or the removal of that line if it's an empty item.
+pub fn on_enter(document: &str, position: Position) -> Vec<TextEdit> {
+    let lines = document.lines().collect::<Vec<_>>();
+    let line = position.line as usize;
+    let Some(previous) = line.checked_sub(1).and_then(|n| lines.get(n)) else {
+        return Vec::new();
+    };
+    let in_code = code_blocks::blocks(document)
+        .iter()
+        .any(|block| block.start < position.line && position.line <= block.end);
+    if position.line <= frontmatter::body_start(document) || in_code {
+        return Vec::new();
+    }
+
+    let prefix = parse(previous);
+    if prefix.marker.is_none() && prefix.quote.is_empty() {
+        return Vec::new();
+    }
+    let current = lines.get(line).copied().unwrap_or("");
+    let cursor = (position.character as usize).min(current.len());
+    let Some(typed) = current.get(..cursor) else {
+        return Vec::new();
+    };
+    // Something other than indentation before the cursor isn't a new line.
+    if !typed.trim().is_empty() {
+        return Vec::new();
+    }
+    let before_cursor = Range::new(Position::new(position.line, 0), position);
+
+    if prefix.text.trim().is_empty() && current[cursor..].trim().is_empty() {
+        let item = Position::new(position.line - 1, 0);
+        return vec![TextEdit::new(Range::new(item, position), String::new())];
+    }
+    let mut edits = vec![TextEdit::new(before_cursor, prefix.next())];
+    if let Some(Marker::Ordered { number, .. }) = prefix.marker {
+        edits.extend(renumber(&lines, line, &prefix, number + 1));
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The edits of pressing Enter at the end of `line` of `document`, as the lines they start
+    /// and end on and their text.
+    fn enter(document: &str, line: u32) -> Vec<(u32, u32, String)> {
+        let mut lines = document.lines().map(str::to_string).collect::<Vec<_>>();
+        lines.insert(line as usize + 1, String::new());
+        on_enter(&lines.join("\n"), Position::new(line + 1, 0))
+            .into_iter()
+            .map(|edit| (edit.range.start.line, edit.range.end.line, edit.new_text))
+            .collect()
+    }
+
+    #[test]
+    fn items_and_quotes_are_continued() {
+        let edit = |line: u32, text: &str| vec![(line, line, String::from(text))];
+        assert_eq!(enter("- milk", 0), edit(1, "- "));
+        assert_eq!(enter("  * [x] eggs", 0), edit(1, "  * [ ] "));
+        assert_eq!(enter("> quoted\n> > nested", 1), edit(2, "> > "));
+        assert_eq!(enter("> 1) one", 0), edit(1, "> 2) "));
+        assert_eq!(enter("Text", 0), []);
+        assert_eq!(enter("---\n- not: a list\n---\n", 1), []);
+        assert_eq!(enter("```\n- code\n```", 1), []);
+
+        // Ordered lists are numbered again after the new item.
+        assert_eq!(
+            enter("1. one\n2. two\n   nested\n2. three\n\n3. four\n- other", 0),
+            [
+                (1, 1, String::from("2. ")),
+                (2, 2, String::from("3")),
+                (4, 4, String::from("4")),
+                (6, 6, String::from("5")),
+            ]
+        );
+
+        // Enter on an empty item removes it.
+        assert_eq!(enter("- milk\n- [ ] ", 1), [(1, 2, String::new())]);
+        assert_eq!(enter("> quoted\n>", 1), [(1, 2, String::new())]);
+    }
+}
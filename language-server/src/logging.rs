@@ -0,0 +1,33 @@
+//! Logging, for `--log-file` and `--log-level`.
+//!
+//! What the server does is traced to the log file, each request in a span of its own, so a trace
+//! shows what went on while completion or the preview misbehaved. Messages meant for the user
+//! are shown in the editor too.
+
+use std::{fs::OpenOptions, io, path::Path, sync::Mutex};
+
+use tower_lsp::{lsp_types::MessageType, Client};
+use tracing::level_filters::LevelFilter;
+
+/// Trace events of `level` and up to the file at `path`, appending to what's there.
+pub fn init(path: &Path, level: LevelFilter) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    tracing_subscriber::fmt()
+        .with_writer(Mutex::new(file))
+        .with_max_level(level)
+        .with_ansi(false)
+        .init();
+    Ok(())
+}
+
+/// Log `message` of `kind` to the log file, and show it in the editor of `client`.
+pub async fn log(client: &Client, kind: MessageType, message: impl Into<String>) {
+    let message = message.into();
+    match kind {
+        MessageType::ERROR => tracing::error!("{message}"),
+        MessageType::WARNING => tracing::warn!("{message}"),
+        MessageType::INFO => tracing::info!("{message}"),
+        _ => tracing::debug!("{message}"),
+    }
+    client.log_message(kind, message).await;
+}
@@ -1,313 +1,158 @@
-use std::{collections::HashMap, ffi::OsStr, path::PathBuf, process::Command};
+use std::{collections::HashSet, io, path::PathBuf};
 
-use tokio::sync::Mutex;
-use tower_lsp::{
-    jsonrpc::{Error, ErrorCode, Result},
-    lsp_types::{
-        CompletionItem, CompletionItemKind, CompletionList, CompletionOptions, CompletionParams,
-        CompletionResponse, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
-        DidOpenTextDocumentParams, GotoDefinitionParams, GotoDefinitionResponse, InitializeParams,
-        InitializeResult, InitializedParams, MessageType, Position, ServerCapabilities,
-        TextDocumentContentChangeEvent, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
-        WorkDoneProgressOptions,
-    },
-    Client, LanguageServer, LspService, Server,
+use clap::{Parser, Subcommand};
+use language_server::{
+    link_checker::{self, LinkChecker},
+    logging,
+    server::{MarkdownLanguageServer, Shared},
 };
-use walkdir::WalkDir;
-
-/// Get the word in `document` at position `cursor_pos`. Cut off word at cursor
-/// position.
-///
-/// ## Example:
-/// ```
-/// let doc = "this is a sentence";
-/// let position = Position { line: 0, character: 1 };
-/// let curr_word = get_current_word(doc, position).unwrap();
-/// assert_eq!(curr_word, "t");
-/// ```
-fn get_current_word(document: &str, cursor_pos: Position) -> Option<&str> {
-    let line = document.lines().nth(cursor_pos.line as usize)?;
-    let character = cursor_pos.character as usize;
-
-    // Go to position at cursor_position.character
-    // Go backwards until end of iterator or whitespace, save index
-    let backwards_chars = line[..character]
-        .chars()
-        .rev()
-        .take_while(|c| !c.is_whitespace())
-        .count();
-
-    let start_word = character - backwards_chars;
-
-    Some(&line[start_word..character])
-}
-
-struct Files {
-    files: HashMap<Url, File>,
+use note_ls_core::{index::NoteIndex, report};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+};
+use tower_lsp::Server;
+use tracing::level_filters::LevelFilter;
+
+/// Markdown language server for note-taking. Talks LSP over stdin and stdout unless told to
+/// listen for editors.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Serve every editor that connects over TCP to ADDRESS, e.g. 127.0.0.1:9257, sharing one
+    /// index and preview between them.
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "pipe")]
+    listen: Option<String>,
+    /// Serve every editor that connects to the Unix socket or Windows named pipe at PATH.
+    #[arg(long, value_name = "PATH")]
+    pipe: Option<String>,
+    /// Write a trace of what the server does to FILE.
+    #[arg(long, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+    /// The least important events traced: off, error, warn, info, debug or trace.
+    #[arg(long, value_name = "LEVEL", default_value = "info")]
+    log_level: LevelFilter,
 }
 
-impl Files {
-    /// Add new file
-    pub fn add_file(&mut self, uri: Url, content: File) {
-        self.files.insert(uri, content);
-    }
-
-    /// Find file with matching uri.
-    pub fn get_file_mut(&mut self, uri: &Url) -> Option<&mut File> {
-        self.files.get_mut(uri)
-    }
-
-    pub fn get_file(&self, uri: &Url) -> Option<&File> {
-        self.files.get(uri)
-    }
-
-    pub fn remove_file(&mut self, uri: &Url) {
-        self.files.remove(uri);
-    }
+#[derive(Subcommand)]
+enum Command {
+    /// Print the report of broken links in the vault at DIR and exit.
+    LinkReport {
+        #[arg(value_name = "DIR", default_value = ".")]
+        root: PathBuf,
+        /// Check external links too.
+        #[arg(long)]
+        external: bool,
+    },
 }
 
-#[derive(Clone)]
-struct File {
-    content: String,
+/// Serve one editor connected over `stream`, sharing `shared` with the others.
+fn serve<S>(stream: S, shared: &Shared)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (read, write) = tokio::io::split(stream);
+    let (service, socket) = MarkdownLanguageServer::service_sharing(shared.clone());
+    let server = service.inner().inner().clone();
+    tokio::spawn(async move {
+        Server::new(read, write, socket).serve(service).await;
+        server.exit().await;
+    });
 }
 
-impl File {
-    pub fn new(content: String) -> Self {
-        Self { content }
-    }
-
-    /// Overwrite current content with `new_content`.
-    pub fn overwrite(&mut self, new_content: String) {
-        self.content = new_content;
-    }
-
-    pub fn update(&mut self, changes: Vec<TextDocumentContentChangeEvent>) {
-        todo!("implement incremental document synchronization")
+/// Serve every editor that connects to `address`, e.g. `127.0.0.1:9257`.
+async fn listen(address: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(address).await?;
+    eprintln!("Listening on {}", listener.local_addr()?);
+    let shared = Shared::default();
+    loop {
+        let (stream, _) = listener.accept().await?;
+        serve(stream, &shared);
     }
 }
 
-// TODO: Implement incremental document synchronization instead of full.
-struct MarkdownLanguageServer {
-    client: Client,
-    files: Mutex<Files>,
-    current_file: Mutex<Option<Url>>,
-    preview_server: Mutex<aurelius::Server>,
-}
-
-impl MarkdownLanguageServer {
-    pub fn new(client: Client) -> Self {
-        let mut preview_server =
-            aurelius::Server::bind("localhost:0").expect("Couldn't start preview server");
-
-        // Use MD4C as rendered
-        let mut md2html = Command::new("/home/callum/Projects/note-ls/md4c/md2html/md2html");
-        md2html.arg("--flatex-math");
-        preview_server.set_external_renderer(md2html);
-
-        Self {
-            client,
-            files: Mutex::new(Files {
-                files: HashMap::new(),
-            }),
-            current_file: Mutex::new(None),
-            preview_server: Mutex::new(preview_server),
-        }
-    }
-
-    pub async fn get_current_file_contents(&self) -> Option<File> {
-        let current_file = self.current_file.lock().await;
-        let c2 = current_file.clone()?;
-        let lock = self.files.lock().await;
-        let c = lock.get_file(&c2)?;
-        let thing = c.clone();
-        Some(thing)
+/// Serve every editor that connects to the Unix socket at `path`.
+#[cfg(unix)]
+async fn listen_on_pipe(path: &str) -> io::Result<()> {
+    // A socket left behind by a server that didn't shut down cleanly.
+    if std::fs::metadata(path).is_ok_and(|metadata| {
+        use std::os::unix::fs::FileTypeExt;
+        metadata.file_type().is_socket()
+    }) {
+        std::fs::remove_file(path)?;
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    eprintln!("Listening on {path}");
+    let shared = Shared::default();
+    loop {
+        let (stream, _) = listener.accept().await?;
+        serve(stream, &shared);
     }
 }
 
-#[tower_lsp::async_trait]
-impl LanguageServer for MarkdownLanguageServer {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
-        // TODO: Client must support goto definition link
-
-        // Open preview in browser
-        let mut preview_server = self.preview_server.lock().await;
-        preview_server.set_highlight_theme("github".to_string());
-        preview_server
-            .open_browser()
-            .map_err(|_| Error::new(ErrorCode::InternalError))?;
-
-        Ok(InitializeResult {
-            capabilities: ServerCapabilities {
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
-                )),
-                completion_provider: Some(CompletionOptions {
-                    trigger_characters: Some(vec!["[[".to_string()]),
-                    resolve_provider: None,
-                    work_done_progress_options: WorkDoneProgressOptions::default(),
-                    all_commit_characters: None,
-                }),
-                ..ServerCapabilities::default()
-            },
-            ..InitializeResult::default()
-        })
-    }
-
-    async fn initialized(&self, _: InitializedParams) {
-        self.client
-            .log_message(MessageType::INFO, "mdls language server initialized")
-            .await;
-    }
-
-    async fn shutdown(&self) -> Result<()> {
-        // TODO: Is there any shutdown logic required?
-        Ok(())
+/// Serve every editor that connects to the named pipe at `path`, e.g. `\\.\pipe\note-ls`.
+#[cfg(windows)]
+async fn listen_on_pipe(path: &str) -> io::Result<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let mut pipe = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(path)?;
+    eprintln!("Listening on {path}");
+    let shared = Shared::default();
+    loop {
+        pipe.connect().await?;
+        // The next editor connects to a new instance of the pipe.
+        let connected = std::mem::replace(&mut pipe, ServerOptions::new().create(path)?);
+        serve(connected, &shared);
     }
+}
 
-    async fn did_open(&self, request: DidOpenTextDocumentParams) {
-        let mut state = self.files.lock().await;
-        state.add_file(
-            request.text_document.uri.clone(),
-            File::new(request.text_document.text.clone()),
-        );
-
-        let mut current_file = self.current_file.lock().await;
-        *current_file = Some(request.text_document.uri);
-
-        // TODO: Open preview in browser
-        self.preview_server
-            .lock()
-            .await
-            .send(request.text_document.text)
-            .expect("Couldn't send preview to server");
-    }
-
-    async fn did_change(&self, mut request: DidChangeTextDocumentParams) {
-        debug_assert!(request.content_changes.len() > 0);
-
-        let mut state = self.files.lock().await;
-        let Some(file) = state.get_file_mut(&request.text_document.uri) else { return; };
-        let last_index = request.content_changes.len() - 1;
-        let new_content = request.content_changes.swap_remove(last_index).text;
-        file.overwrite(new_content.clone());
-
-        let mut current_file = self.current_file.lock().await;
-        *current_file = Some(request.text_document.uri);
-
-        // Update preview in browser
-        self.preview_server
-            .lock()
-            .await
-            .send(new_content)
-            .expect("Couldn't send preview to server");
-    }
-
-    async fn did_close(&self, request: DidCloseTextDocumentParams) {
-        let mut state = self.files.lock().await;
-        state.remove_file(&request.text_document.uri);
-
-        // TODO: Close preview in browser
-        // Maybe switch to current document instead?
-    }
-
-    // TODO: Filter files as user types more characters.
-    async fn completion(&self, request: CompletionParams) -> Result<Option<CompletionResponse>> {
-        // Get current location in file
-        let state = self.files.lock().await;
-        let file = state
-            .get_file(&request.text_document_position.text_document.uri)
-            .ok_or(Error::new(ErrorCode::InvalidParams))?;
-        let pos = request.text_document_position.position;
-
-        let current_word =
-            get_current_word(&file.content, pos).ok_or(Error::new(ErrorCode::InvalidParams))?;
-
-        self.client
-            .log_message(MessageType::INFO, format!("Current word: {}", current_word))
-            .await;
-
-        if current_word.starts_with("[[") && !current_word.ends_with(']') {
-            // Get all files in currrent dir or nested dirs that end with .md other than self.
-            let current_path = self
-                .current_file
-                .lock()
-                .await
-                .clone()
-                .ok_or(Error::new(ErrorCode::InternalError))?;
-            let path = PathBuf::from(current_path.path());
-            let path_parent = path.parent().ok_or(Error::new(ErrorCode::InternalError))?;
-
-            let files = WalkDir::new(path_parent)
-                .sort_by(|a, b| a.depth().cmp(&b.depth())) // Not working
-                .into_iter()
-                .filter_map(|e| e.ok())
-                .filter(|e| e.path().extension() == Some(OsStr::new("md")))
-                .map(|e| CompletionItem {
-                    label: e
-                        .path()
-                        .strip_prefix(path_parent)
-                        .unwrap()
-                        .to_string_lossy()
-                        .into(),
-                    kind: Some(CompletionItemKind::FILE),
-                    ..CompletionItem::default()
-                })
-                .collect::<Vec<CompletionItem>>();
-
-            Ok(Some(CompletionResponse::List(CompletionList {
-                is_incomplete: false,
-                items: files,
-            })))
-        } else {
-            Ok(None)
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    if let Some(Command::LinkReport { root, external }) = cli.command {
+        let index = NoteIndex::scan(&root);
+        let dead = match external {
+            true => {
+                let urls = link_checker::vault_urls_to_check(&index, &[]);
+                LinkChecker::default().broken_among(urls).await
+            }
+            false => HashSet::new(),
+        };
+        let report = report::link_report(&index, &dead);
+        println!("{}", report);
+        return;
+    }
+    if let Some(path) = &cli.log_file {
+        if let Err(e) = logging::init(path, cli.log_level) {
+            eprintln!("note-ls: could not open {}: {e}", path.display());
+            std::process::exit(1);
         }
     }
 
-    async fn goto_definition(
-        &self,
-        params: GotoDefinitionParams,
-    ) -> Result<Option<GotoDefinitionResponse>> {
-        todo!()
+    let listened = match (&cli.listen, &cli.pipe) {
+        (Some(address), _) => Some(listen(address).await),
+        (None, Some(path)) => Some(listen_on_pipe(path).await),
+        (None, None) => None,
+    };
+    match listened {
+        Some(Ok(())) => return,
+        Some(Err(e)) => {
+            eprintln!("note-ls: {e}");
+            std::process::exit(1);
+        }
+        None => {}
     }
-}
 
-#[tokio::main]
-async fn main() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let (service, socket) = LspService::new(|client| MarkdownLanguageServer::new(client));
+    let (service, socket) = MarkdownLanguageServer::service();
+    let server = service.inner().inner().clone();
     Server::new(stdin, stdout, socket).serve(service).await;
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn get_current_word_works() {
-        let doc = "this is a sentence\nThis is another line. Here is a word.";
-        let position = Position {
-            line: 0,
-            character: 1,
-        };
-        let curr_word =
-            get_current_word(doc, position).expect("Getting current word returned None");
-        assert_eq!(curr_word, "t");
-
-        let position = Position {
-            line: 1,
-            character: 8,
-        };
-        let curr_word =
-            get_current_word(doc, position).expect("Getting current word returned None");
-        assert_eq!(curr_word, "");
-
-        let position = Position {
-            line: 0,
-            character: 0,
-        };
-        let curr_word = get_current_word(doc, position).unwrap();
-        assert_eq!(curr_word, "");
-    }
+    // Clean up after clients that exit without shutting the server down first.
+    server.exit().await;
 }
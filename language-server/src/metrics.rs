@@ -0,0 +1,267 @@
+//! Counters of what the server does, for `noteLs/status` and the preview server's
+//! `/__/metrics`, so users can keep an eye on the server. They never leave the machine.
+//!
+//! Durations are kept in microseconds, and reported in milliseconds by `noteLs/status` and in
+//! seconds in Prometheus' text format, as it expects.
+
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tower_lsp::lsp_types::Url;
+
+use crate::contents::CacheStats;
+
+/// How many times something happened and how long it took.
+#[derive(Debug, Default)]
+pub struct Timing {
+    count: AtomicU64,
+    total_us: AtomicU64,
+    last_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+impl Timing {
+    pub fn record(&self, elapsed: Duration) {
+        let us = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_us.fetch_add(us, Ordering::Relaxed);
+        self.last_us.store(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    fn status(&self) -> TimingStatus {
+        let ms = |us: &AtomicU64| us.load(Ordering::Relaxed) as f64 / 1000.0;
+        TimingStatus {
+            count: self.count.load(Ordering::Relaxed),
+            total_ms: ms(&self.total_us),
+            last_ms: ms(&self.last_us),
+            max_ms: ms(&self.max_us),
+        }
+    }
+}
+
+/// The counters, shared by the server, the service timing requests and the preview's renders.
+#[derive(Debug)]
+pub struct Metrics {
+    started: Instant,
+    /// Requests answered, or timed out.
+    pub requests: Timing,
+    pub timed_out: AtomicU64,
+    /// Indexing whole vaults, when they're opened or indexed again.
+    pub indexing: Timing,
+    /// Indexing the notes changed since the last pass.
+    pub reindexing: Timing,
+    /// Rendering notes for the preview.
+    pub renders: Timing,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            requests: Timing::default(),
+            timed_out: AtomicU64::default(),
+            indexing: Timing::default(),
+            reindexing: Timing::default(),
+            renders: Timing::default(),
+        }
+    }
+}
+
+impl Metrics {
+    /// The counters along with the hit rates of the caches, which keep their own counts.
+    pub fn status(
+        &self,
+        contents: CacheStats,
+        completions: CacheStats,
+        metrics_url: Option<Url>,
+    ) -> Status {
+        Status {
+            uptime_secs: self.started.elapsed().as_secs(),
+            requests: self.requests.status(),
+            timed_out: self.timed_out.load(Ordering::Relaxed),
+            indexing: self.indexing.status(),
+            reindexing: self.reindexing.status(),
+            renders: self.renders.status(),
+            content_cache: contents.into(),
+            completion_cache: completions.into(),
+            metrics_url,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimingStatus {
+    pub count: u64,
+    pub total_ms: f64,
+    pub last_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStatus {
+    pub hits: u64,
+    pub misses: u64,
+    /// The share of lookups that were hits, once there were any.
+    pub hit_rate: Option<f64>,
+}
+
+impl From<CacheStats> for CacheStatus {
+    fn from(stats: CacheStats) -> Self {
+        let lookups = stats.hits + stats.misses;
+        Self {
+            hits: stats.hits,
+            misses: stats.misses,
+            hit_rate: (lookups > 0).then(|| stats.hits as f64 / lookups as f64),
+        }
+    }
+}
+
+/// The result of `noteLs/status`: what the server did since it started.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    pub uptime_secs: u64,
+    pub requests: TimingStatus,
+    pub timed_out: u64,
+    pub indexing: TimingStatus,
+    pub reindexing: TimingStatus,
+    pub renders: TimingStatus,
+    pub content_cache: CacheStatus,
+    pub completion_cache: CacheStatus,
+    /// Where the preview server serves the counters, if `metricsEndpoint` is on and it's running.
+    pub metrics_url: Option<Url>,
+}
+
+/// Add a metric to `text` in Prometheus' text format, with one sample per label.
+fn metric(text: &mut String, name: &str, kind: &str, help: &str, samples: &[(&str, f64)]) {
+    let _ = writeln!(text, "# HELP {name} {help}");
+    let _ = writeln!(text, "# TYPE {name} {kind}");
+    for (labels, value) in samples {
+        let _ = writeln!(text, "{name}{labels} {value}");
+    }
+}
+
+/// `status` in Prometheus' text format.
+pub fn prometheus(status: &Status) -> String {
+    let seconds = |timing: &TimingStatus| timing.total_ms / 1000.0;
+    let count = |timing: &TimingStatus| timing.count as f64;
+    let mut text = String::new();
+    metric(
+        &mut text,
+        "note_ls_uptime_seconds",
+        "gauge",
+        "Time since the server started.",
+        &[("", status.uptime_secs as f64)],
+    );
+    metric(
+        &mut text,
+        "note_ls_requests_total",
+        "counter",
+        "Requests answered, including those that timed out.",
+        &[("", count(&status.requests))],
+    );
+    metric(
+        &mut text,
+        "note_ls_requests_timed_out_total",
+        "counter",
+        "Requests that timed out.",
+        &[("", status.timed_out as f64)],
+    );
+    metric(
+        &mut text,
+        "note_ls_request_seconds_total",
+        "counter",
+        "Time spent answering requests.",
+        &[("", seconds(&status.requests))],
+    );
+    let passes = [
+        (r#"{kind="vault"}"#, &status.indexing),
+        (r#"{kind="changes"}"#, &status.reindexing),
+    ];
+    metric(
+        &mut text,
+        "note_ls_index_passes_total",
+        "counter",
+        "Times notes were indexed, whole vaults or the notes that changed.",
+        &passes.map(|(kind, timing)| (kind, count(timing))),
+    );
+    metric(
+        &mut text,
+        "note_ls_index_seconds_total",
+        "counter",
+        "Time spent indexing notes.",
+        &passes.map(|(kind, timing)| (kind, seconds(timing))),
+    );
+    metric(
+        &mut text,
+        "note_ls_renders_total",
+        "counter",
+        "Notes rendered for the preview.",
+        &[("", count(&status.renders))],
+    );
+    metric(
+        &mut text,
+        "note_ls_render_seconds_total",
+        "counter",
+        "Time spent rendering notes for the preview.",
+        &[("", seconds(&status.renders))],
+    );
+    let caches = [
+        (r#"{cache="contents"}"#, &status.content_cache),
+        (r#"{cache="completions"}"#, &status.completion_cache),
+    ];
+    metric(
+        &mut text,
+        "note_ls_cache_hits_total",
+        "counter",
+        "Lookups a cache could answer.",
+        &caches.map(|(cache, stats)| (cache, stats.hits as f64)),
+    );
+    metric(
+        &mut text,
+        "note_ls_cache_misses_total",
+        "counter",
+        "Lookups a cache couldn't answer.",
+        &caches.map(|(cache, stats)| (cache, stats.misses as f64)),
+    );
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_are_reported() {
+        let metrics = Metrics::default();
+        metrics.requests.record(Duration::from_millis(30));
+        metrics.requests.record(Duration::from_millis(10));
+        metrics.timed_out.fetch_add(1, Ordering::Relaxed);
+        let contents = CacheStats { hits: 3, misses: 1 };
+        let status = metrics.status(contents, CacheStats::default(), None);
+        assert_eq!(
+            status.requests,
+            TimingStatus {
+                count: 2,
+                total_ms: 40.0,
+                last_ms: 10.0,
+                max_ms: 30.0,
+            }
+        );
+        assert_eq!(status.content_cache.hit_rate, Some(0.75));
+        assert_eq!(status.completion_cache.hit_rate, None);
+
+        let text = prometheus(&status);
+        assert!(text.contains("# TYPE note_ls_requests_total counter\nnote_ls_requests_total 2\n"));
+        assert!(text.contains("note_ls_request_seconds_total 0.04\n"));
+        assert!(text.contains("note_ls_cache_hits_total{cache=\"contents\"} 3\n"));
+        assert!(text.contains("note_ls_index_passes_total{kind=\"changes\"} 0\n"));
+    }
+}
@@ -0,0 +1,237 @@
+//! Where notes created by the server go, following `Config::new_note_location`.
+//!
+//! Notes created from broken links and notes extracted from a selection all go through `folder`,
+//! so they end up in the same place. Notes created with `noteLs.newNote` may instead go where
+//! their `NoteType` says.
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    config::{NewNoteLocation, NoteType},
+    frontmatter,
+    index::Note,
+    links, templates,
+};
+
+/// A day of the proleptic Gregorian calendar, in UTC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    /// The date `days` days after 1970-01-01.
+    pub fn from_days(days: i64) -> Self {
+        // Howard Hinnant's `civil_from_days`, with eras of 400 years starting on March 1st.
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let day_of_era = z.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_index = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+        let month = if month_index < 10 {
+            month_index + 3
+        } else {
+            month_index - 9
+        } as u32;
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        Self { year, month, day }
+    }
+
+    /// The number of days from 1970-01-01 to the date, the inverse of `from_days`.
+    pub fn days(&self) -> i64 {
+        let year = self.year - i64::from(self.month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let month = i64::from(self.month);
+        let month_index = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * month_index + 2) / 5 + i64::from(self.day) - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    /// The day of the week, from 0 for Monday to 6 for Sunday.
+    pub fn weekday(&self) -> u32 {
+        // 1970-01-01 was a Thursday.
+        (self.days() + 3).rem_euclid(7) as u32
+    }
+
+    pub fn today() -> Self {
+        let seconds = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Self::from_days((seconds / 86_400) as i64)
+    }
+}
+
+impl fmt::Display for Date {
+    /// The date as `YYYY-MM-DD`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// The folder `pattern` names, relative to the vault root, for a note created from `from`.
+///
+/// `{year}`, `{month}` and `{day}` stand for `date`, and `{tag}` for the first tag of `from`.
+/// Components left empty, e.g. `{tag}` when there are no tags, are dropped.
+fn expand(pattern: &str, from: Option<&Note>, date: Date) -> PathBuf {
+    let tag = from
+        .and_then(|note| note.tags.first())
+        .map_or("", |tag| tag.name.as_str());
+    let expanded = pattern
+        .replace("{year}", &format!("{:04}", date.year))
+        .replace("{month}", &format!("{:02}", date.month))
+        .replace("{day}", &format!("{:02}", date.day))
+        .replace("{tag}", tag);
+    expanded
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .collect()
+}
+
+/// The folder to create a new note in, for a note created from the note at `from`, or from
+/// nowhere in particular without one.
+///
+/// `note` is the parsed content of `from`, if it's known, for `{tag}` in patterns.
+pub fn folder(
+    location: &NewNoteLocation,
+    root: &Path,
+    from: Option<&Path>,
+    note: Option<&Note>,
+    date: Date,
+) -> PathBuf {
+    match location {
+        NewNoteLocation::SameFolder => from.and_then(Path::parent).unwrap_or(root).to_path_buf(),
+        NewNoteLocation::Folder { folder } => links::normalize(&root.join(folder)),
+        NewNoteLocation::Pattern { pattern } => {
+            links::normalize(&root.join(expand(pattern, note, date)))
+        }
+    }
+}
+
+/// A path in `folder` for a new note named `title` that no file has yet, numbering the name if
+/// needed, e.g. `Untitled 2.md`.
+pub fn unused_path(folder: &Path, title: &str) -> PathBuf {
+    let mut path = folder.join(format!("{title}.md"));
+    let mut n = 2;
+    while path.exists() {
+        path = folder.join(format!("{title} {n}.md"));
+        n += 1;
+    }
+    path
+}
+
+/// The starting content of a new note named `title`, created on `date`: `template`, filled in by
+/// `templates::fill`, declaring `note_type` in its frontmatter if it has one.
+///
+/// Templates that don't declare the type get it added to their frontmatter, or get frontmatter
+/// with the type and its required fields left empty if they have none.
+pub fn content(
+    note_type: Option<&NoteType>,
+    template: Option<&str>,
+    title: &str,
+    date: Date,
+) -> String {
+    let content = templates::fill(template.unwrap_or_default(), title, date);
+    let Some(note_type) = note_type else {
+        return content;
+    };
+    if frontmatter::parse(&content).is_some_and(|frontmatter| frontmatter.note_type.is_some()) {
+        return content;
+    }
+
+    let declaration = format!("type: {}\n", note_type.name);
+    if frontmatter::split(&content).is_some() {
+        let start = content.find('\n').map_or(content.len(), |i| i + 1);
+        return format!("{}{declaration}{}", &content[..start], &content[start..]);
+    }
+    let mut fields = declaration;
+    for field in &note_type.required {
+        fields.push_str(&format!("{field}:\n"));
+    }
+    format!("---\n{fields}---\n{content}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_from_days() {
+        let date = |year, month, day| Date { year, month, day };
+        assert_eq!(Date::from_days(0), date(1970, 1, 1));
+        assert_eq!(Date::from_days(-1), date(1969, 12, 31));
+        assert_eq!(Date::from_days(11_016), date(2000, 2, 29));
+        assert_eq!(Date::from_days(20_742), date(2026, 10, 16));
+        assert_eq!(Date::from_days(20_742).to_string(), "2026-10-16");
+        for days in [-800_000, -1, 0, 59, 11_016, 20_742, 3_000_000] {
+            assert_eq!(Date::from_days(days).days(), days);
+        }
+        assert_eq!(Date::from_days(20_742).weekday(), 4);
+    }
+
+    #[test]
+    fn folders_for_each_location() {
+        let root = Path::new("/vault");
+        let from = Path::new("/vault/projects/note.md");
+        let note = Note::parse("#work/client text");
+        let date = Date::from_days(20_742);
+        let folder = |location| folder(&location, root, Some(from), Some(&note), date);
+
+        assert_eq!(
+            folder(NewNoteLocation::SameFolder),
+            Path::new("/vault/projects")
+        );
+        assert_eq!(
+            folder(NewNoteLocation::Folder {
+                folder: PathBuf::from("inbox/../Inbox")
+            }),
+            Path::new("/vault/Inbox")
+        );
+        assert_eq!(
+            folder(NewNoteLocation::Pattern {
+                pattern: String::from("{tag}/{year}/{month}-{day}")
+            }),
+            Path::new("/vault/work/client/2026/10-16")
+        );
+        assert_eq!(expand("{tag}/{year}", None, date), PathBuf::from("2026"));
+    }
+
+    #[test]
+    fn typed_notes_declare_their_type() {
+        let book = NoteType {
+            name: String::from("book"),
+            required: vec![String::from("author")],
+            ..NoteType::default()
+        };
+        let date = Date::from_days(20_742);
+        assert_eq!(
+            content(Some(&book), Some("# {{title}}\n"), "Dune", date),
+            "---\ntype: book\nauthor:\n---\n# Dune\n"
+        );
+        assert_eq!(
+            content(Some(&book), Some("---\nauthor: ?\n---\n"), "Dune", date),
+            "---\ntype: book\nauthor: ?\n---\n"
+        );
+        assert_eq!(
+            content(Some(&book), Some("---\ntype: novel\n---\n"), "Dune", date),
+            "---\ntype: novel\n---\n"
+        );
+        assert_eq!(content(None, None, "Dune", date), "");
+
+        let folder = tempfile::tempdir().unwrap();
+        let folder = folder.path();
+        assert_eq!(unused_path(folder, "Dune"), folder.join("Dune.md"));
+        std::fs::write(folder.join("Dune.md"), "").unwrap();
+        assert_eq!(unused_path(folder, "Dune"), folder.join("Dune 2.md"));
+    }
+}
@@ -0,0 +1,205 @@
+//! Everything known about a single note, for the `noteLs/noteInfo` request.
+//!
+//! Clients use it for "note info" popups, which would otherwise take a request per link plus
+//! references and symbols.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Location, Range, Url};
+
+use crate::{
+    comments, diagnostics, document_links, frontmatter,
+    index::{Note, NoteIndex},
+    links::{Link, LinkKind},
+    uri,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteInfoParams {
+    pub uri: Url,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrontmatterInfo {
+    pub title: Option<String>,
+    pub aliases: Vec<String>,
+    pub tags: Vec<String>,
+    pub date: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboundLink {
+    pub range: Range,
+    /// The target as written, including any `#heading`.
+    pub target: String,
+    pub wiki: bool,
+    pub embed: bool,
+    pub external: bool,
+    /// The file the link points to, for links within the vault that resolve.
+    pub resolved: Option<Url>,
+    /// Whether the link points into the vault but nothing there matches. Only known when the
+    /// whole vault is indexed.
+    pub broken: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteInfo {
+    pub uri: Url,
+    pub title: Option<String>,
+    pub frontmatter: FrontmatterInfo,
+    pub links: Vec<OutboundLink>,
+    pub backlinks: Vec<Location>,
+    /// Tags in the frontmatter and the body, without duplicates, in the order they first appear.
+    pub tags: Vec<String>,
+    pub word_count: usize,
+}
+
+/// Number of words in the body of `document`, after its frontmatter, leaving out comments.
+pub fn word_count(document: &str) -> usize {
+    let blanked = comments::blank(document);
+    count_words(
+        blanked
+            .lines()
+            .skip(frontmatter::body_start(&blanked) as usize),
+    )
+}
+
+/// The number of words on `lines`. Only words with a letter or digit in them count.
+pub fn count_words<'a>(lines: impl IntoIterator<Item = &'a str>) -> usize {
+    lines
+        .into_iter()
+        .flat_map(str::split_whitespace)
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .count()
+}
+
+fn outbound(link: &Link, path: &Path, index: &NoteIndex, vault_indexed: bool) -> OutboundLink {
+    let external = link.is_external();
+    let resolved = if external {
+        None
+    } else {
+        document_links::resolve(link, path, index)
+    };
+    let checked = match link.kind {
+        LinkKind::Wiki => diagnostics::links_to_note(link, index.extensions()),
+        LinkKind::Markdown => true,
+    };
+    OutboundLink {
+        range: link.range(),
+        target: link.target.clone(),
+        wiki: link.kind == LinkKind::Wiki,
+        embed: link.embed,
+        external,
+        broken: vault_indexed && checked && !external && resolved.is_none(),
+        resolved,
+    }
+}
+
+/// What's known about `document`, the content of the note at `path`.
+///
+/// Broken links are only flagged if `vault_indexed`, since otherwise most notes they could point
+/// to are missing from the index.
+pub fn note_info(
+    path: &Path,
+    document: &str,
+    index: &NoteIndex,
+    vault_indexed: bool,
+) -> Option<NoteInfo> {
+    let uri = uri::from_path(path)?;
+    let note = Note::parse(document);
+
+    let mut tags = Vec::<String>::new();
+    for tag in &note.tags {
+        if !tags.contains(&tag.name) {
+            tags.push(tag.name.clone());
+        }
+    }
+    let backlinks = index
+        .backlinks(path)
+        .into_iter()
+        .filter_map(|(source, link)| Some(Location::new(uri::from_path(source)?, link.range())))
+        .collect();
+
+    Some(NoteInfo {
+        uri,
+        title: note.title().map(str::to_string),
+        links: note
+            .links
+            .iter()
+            .map(|link| outbound(link, path, index, vault_indexed))
+            .collect(),
+        backlinks,
+        tags,
+        word_count: word_count(document),
+        frontmatter: FrontmatterInfo {
+            title: note.frontmatter.title.clone(),
+            aliases: note.frontmatter.aliases.clone(),
+            tags: note
+                .frontmatter
+                .tags
+                .iter()
+                .map(|tag| tag.name.clone())
+                .collect(),
+            date: note.frontmatter.date.clone(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn words_are_counted_after_the_frontmatter() {
+        assert_eq!(
+            word_count("---\ntitle: Not counted\n---\n# Two words\n\n- and - more"),
+            4
+        );
+        assert_eq!(word_count(""), 0);
+    }
+
+    #[test]
+    fn links_are_resolved_or_broken() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        let content = "---\ntags: [a]\n---\n[[other#Two]] [[missing]] ![[image.png]] \
+                       [web](https://example.com) #b #a";
+        fs::write(root.join("note.md"), content).unwrap();
+        fs::write(root.join("other.md"), "# One\n## Two\n[[note]]").unwrap();
+        let index = NoteIndex::scan(root);
+        let path = root.join("note.md");
+
+        let info = note_info(&path, content, &index, true).unwrap();
+        let links = info
+            .links
+            .iter()
+            .map(|link| (link.resolved.is_some(), link.broken, link.external))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            links,
+            vec![
+                (true, false, false),
+                (false, true, false),
+                (false, false, false),
+                (false, false, true)
+            ]
+        );
+        assert_eq!(
+            info.links[0].resolved.as_ref().unwrap().fragment(),
+            Some("L2")
+        );
+        assert_eq!(info.backlinks.len(), 1);
+        assert_eq!(info.tags, vec!["a", "b"]);
+        assert_eq!(info.frontmatter.tags, vec!["a"]);
+
+        let info = note_info(&path, content, &index, false).unwrap();
+        assert!(!info.links[1].broken);
+    }
+}
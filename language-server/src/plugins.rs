@@ -0,0 +1,318 @@
+//! Plugins: external programs that add completions, diagnostics and commands to the server.
+//!
+//! A plugin is started once per session and talks to the server over its stdin and stdout, one
+//! JSON message per line. The server sends `{"id": 1, "method": "...", "params": ...}` and waits
+//! for `{"id": 1, "result": ...}` or `{"id": 1, "error": "message"}`. The methods are:
+//!
+//! - `initialize` with `{"root": "/path/to/vault"}`, answered with the plugin's capabilities,
+//!   e.g. `{"completion": true, "diagnostics": false, "commands": ["myPlugin.doThing"]}`.
+//! - `completion` with `{"uri", "position", "text"}`, answered with LSP `CompletionItem`s.
+//! - `diagnostics` with `{"uri", "text"}`, answered with LSP `Diagnostic`s.
+//! - `executeCommand` with `{"command", "arguments"}`, answered with any JSON value.
+//! - `shutdown`, sent without waiting for an answer before the plugin is killed.
+//!
+//! Positions exchanged with plugins count the characters of a line in bytes of UTF-8, whatever
+//! the editor counts them in; the server converts them on the way.
+//!
+//! Plugins run in the vault root with an empty environment apart from `PATH` and the variables
+//! they are configured with. A plugin that fails to answer within its timeout, or answers with
+//! something that isn't valid, is stopped for the rest of the session.
+
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind},
+    path::Path,
+    process::Stdio,
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{Child, ChildStdin, ChildStdout, Command},
+    time,
+};
+
+/// How a plugin is started, from the `plugins` setting.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginConfig {
+    /// Name used in log messages and as the source of the plugin's diagnostics.
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment variables set for the plugin, on top of `PATH`.
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    /// How long to wait for an answer before giving up on the plugin, in milliseconds.
+    #[serde(default = "default_timeout")]
+    pub timeout_ms: u64,
+}
+
+fn default_timeout() -> u64 {
+    2000
+}
+
+/// What a plugin contributes, as it answered `initialize`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Capabilities {
+    pub completion: bool,
+    pub diagnostics: bool,
+    pub commands: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    id: u64,
+    #[serde(default)]
+    result: Value,
+    error: Option<String>,
+}
+
+/// A running plugin.
+pub struct Plugin {
+    pub name: String,
+    pub capabilities: Capabilities,
+    timeout: Duration,
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Plugin {
+    /// Start the plugin described by `config` and ask for its capabilities.
+    pub async fn start(config: &PluginConfig, root: &Path) -> io::Result<Self> {
+        let mut command = Command::new(&config.command);
+        command
+            .args(&config.args)
+            .current_dir(root)
+            .env_clear()
+            .envs(std::env::var_os("PATH").map(|path| ("PATH", path)))
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true);
+
+        let mut child = command.spawn()?;
+        let stdin = child.stdin.take().ok_or(ErrorKind::BrokenPipe)?;
+        let stdout = child.stdout.take().ok_or(ErrorKind::BrokenPipe)?;
+
+        let mut plugin = Self {
+            name: config.name.clone(),
+            capabilities: Capabilities::default(),
+            timeout: Duration::from_millis(config.timeout_ms),
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+            next_id: 1,
+        };
+        plugin.capabilities = plugin
+            .request("initialize", json!({ "root": root }))
+            .await?;
+        Ok(plugin)
+    }
+
+    /// Send a request and wait for the answer.
+    pub async fn request<T: DeserializeOwned>(
+        &mut self,
+        method: &str,
+        params: Value,
+    ) -> io::Result<T> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let mut message = json!({ "id": id, "method": method, "params": params }).to_string();
+        message.push('\n');
+
+        let exchange = async {
+            self.stdin.write_all(message.as_bytes()).await?;
+            self.stdin.flush().await?;
+
+            let mut line = String::new();
+            if self.stdout.read_line(&mut line).await? == 0 {
+                return Err(io::Error::new(ErrorKind::UnexpectedEof, "plugin exited"));
+            }
+            Ok(line)
+        };
+        let line = time::timeout(self.timeout, exchange)
+            .await
+            .map_err(|_| io::Error::new(ErrorKind::TimedOut, "plugin didn't answer in time"))??;
+
+        let response = serde_json::from_str::<Response>(&line)?;
+        if response.id != id {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("expected an answer to request {id}, got {}", response.id),
+            ));
+        }
+        if let Some(error) = response.error {
+            return Err(io::Error::other(error));
+        }
+        Ok(serde_json::from_value(response.result)?)
+    }
+
+    /// Tell the plugin to exit, and make sure it does.
+    pub async fn shutdown(mut self) {
+        let message = json!({ "id": self.next_id, "method": "shutdown" }).to_string() + "\n";
+        let _ = self.stdin.write_all(message.as_bytes()).await;
+        let _ = self.child.kill().await;
+    }
+}
+
+/// A feature plugins can contribute to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feature {
+    Completion,
+    Diagnostics,
+}
+
+/// The plugins running in this session.
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+}
+
+impl Plugins {
+    /// Start every configured plugin, returning the ones that came up and why the others didn't.
+    pub async fn start(configs: &[PluginConfig], root: &Path) -> (Self, Vec<String>) {
+        let mut plugins = Vec::new();
+        let mut errors = Vec::new();
+        for config in configs {
+            match Plugin::start(config, root).await {
+                Ok(plugin) => plugins.push(plugin),
+                Err(e) => errors.push(format!("Plugin {} failed to start: {e}", config.name)),
+            }
+        }
+        (Self { plugins }, errors)
+    }
+
+    /// Commands contributed by plugins.
+    pub fn commands(&self) -> Vec<String> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| plugin.capabilities.commands.iter().cloned())
+            .collect()
+    }
+
+    /// Ask every plugin contributing `feature` and collect their answers.
+    ///
+    /// Plugins that fail are stopped; the second value describes what went wrong with them.
+    pub async fn request_all<T: DeserializeOwned>(
+        &mut self,
+        feature: Feature,
+        params: Value,
+    ) -> (Vec<(String, T)>, Vec<String>) {
+        let method = match feature {
+            Feature::Completion => "completion",
+            Feature::Diagnostics => "diagnostics",
+        };
+
+        let mut results = Vec::new();
+        let mut errors = Vec::new();
+        let mut failed = Vec::new();
+        for (i, plugin) in self.plugins.iter_mut().enumerate() {
+            let wanted = match feature {
+                Feature::Completion => plugin.capabilities.completion,
+                Feature::Diagnostics => plugin.capabilities.diagnostics,
+            };
+            if !wanted {
+                continue;
+            }
+            match plugin.request(method, params.clone()).await {
+                Ok(result) => results.push((plugin.name.clone(), result)),
+                Err(e) => {
+                    errors.push(format!("Plugin {} stopped after failing: {e}", plugin.name));
+                    failed.push(i);
+                }
+            }
+        }
+        for i in failed.into_iter().rev() {
+            self.plugins.remove(i).shutdown().await;
+        }
+
+        (results, errors)
+    }
+
+    /// Run a command contributed by a plugin, or return `None` if no plugin has it.
+    pub async fn execute(
+        &mut self,
+        command: &str,
+        arguments: Vec<Value>,
+    ) -> Option<io::Result<Value>> {
+        let plugin = self
+            .plugins
+            .iter_mut()
+            .find(|plugin| plugin.capabilities.commands.iter().any(|c| c == command))?;
+        let params = json!({ "command": command, "arguments": arguments });
+        Some(plugin.request("executeCommand", params).await)
+    }
+
+    /// Stop every plugin.
+    pub async fn shutdown(&mut self) {
+        for plugin in self.plugins.drain(..) {
+            plugin.shutdown().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A plugin answering every request with `result`, echoing the request id.
+    fn echo_plugin(result: &str) -> PluginConfig {
+        let script = format!(
+            r#"while read -r line; do
+                 id=$(echo "$line" | sed 's/.*"id":\([0-9]*\).*/\1/')
+                 echo "{{\"id\":$id,\"result\":{result}}}"
+               done"#
+        );
+        PluginConfig {
+            name: "echo".to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script],
+            env: HashMap::new(),
+            timeout_ms: 2000,
+        }
+    }
+
+    #[tokio::test]
+    async fn plugins_answer_requests_and_are_stopped_when_they_fail() {
+        let root = tempfile::tempdir().unwrap();
+        let config = echo_plugin(r#"{\"completion\":true,\"commands\":[\"echo.run\"]}"#);
+        let (mut plugins, errors) = Plugins::start(&[config], root.path()).await;
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(plugins.commands(), vec!["echo.run"]);
+
+        let result = plugins
+            .execute("echo.run", Vec::new())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(result["commands"][0], "echo.run");
+        assert!(plugins.execute("other", Vec::new()).await.is_none());
+
+        // The answer isn't a list of completion items, so the plugin is stopped.
+        let (items, errors) = plugins
+            .request_all::<Vec<Value>>(Feature::Completion, json!({}))
+            .await;
+        assert!(items.is_empty());
+        assert_eq!(errors.len(), 1);
+        assert!(plugins.commands().is_empty());
+    }
+
+    #[tokio::test]
+    async fn plugins_that_cannot_start_are_reported() {
+        let root = tempfile::tempdir().unwrap();
+        let mut config = echo_plugin("{}");
+        config.command = "note-ls-no-such-plugin".to_string();
+        let (plugins, errors) = Plugins::start(&[config], root.path()).await;
+        assert!(plugins.commands().is_empty());
+        assert!(errors[0].starts_with("Plugin echo failed to start"));
+    }
+}
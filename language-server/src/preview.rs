@@ -0,0 +1,216 @@
+//! Debouncing of preview updates, and the `noteLs/previewStarted` notification.
+//!
+//! Rendering a large note on every keystroke makes typing stutter, so edits are queued and a
+//! background task renders the latest content of each edited note once the edits pause. Updates
+//! are prepared concurrently and may be queued out of order, so each carries a version, and one
+//! older than what's queued or shown already for its note is dropped.
+
+use std::{
+    collections::HashMap,
+    io,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{mpsc, Mutex},
+    time,
+};
+use tower_lsp::{
+    lsp_types::{notification::Notification, MessageType, Url},
+    Client,
+};
+
+use crate::{charts, diagrams::Diagrams, footnote_links, habits, logging, metrics::Metrics, uri};
+
+/// The preview server, shared with the task rendering updates. `None` until it's started and
+/// after it's shut down.
+pub type PreviewServer = Arc<Mutex<Option<aurelius::Server>>>;
+
+/// Tells the client where the preview server is listening, whenever it starts, so the preview
+/// can be opened or forwarded from elsewhere, e.g. over SSH.
+pub enum PreviewStarted {}
+
+impl Notification for PreviewStarted {
+    type Params = PreviewStartedParams;
+    const METHOD: &'static str = "noteLs/previewStarted";
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewStartedParams {
+    /// URL of the preview following the note being edited.
+    pub url: Url,
+}
+
+/// New content of a note to show in the preview.
+#[derive(Debug)]
+pub struct Update {
+    pub uri: Url,
+    pub markdown: String,
+    /// Counts up with the requests changing notes, so a later version of the note wins.
+    pub version: u64,
+    /// Line to scroll the preview to once it's rendered, usually the one edited.
+    pub line: Option<u32>,
+    /// How long to wait for further edits before rendering.
+    pub delay: Duration,
+}
+
+/// Show `markdown`, the content of the note at `uri`, in the preview.
+///
+/// Every note has a preview channel of its own, named by its URI, and the default channel
+/// follows the note being edited. Habit trackers are shown as tables, charts are drawn and
+/// footnotes linked, and `diagrams` draws the diagrams. How long rendering takes is recorded in `metrics`.
+pub async fn show(
+    server: &mut aurelius::Server,
+    uri: &Url,
+    markdown: &str,
+    metrics: &Metrics,
+    diagrams: &Diagrams,
+) -> io::Result<()> {
+    let start = Instant::now();
+    let path = uri::to_path(uri);
+    let dir = path.as_deref().and_then(Path::parent);
+    let markdown = diagrams.render(markdown, dir).await;
+    let markdown = &footnote_links::render(&habits::render(&charts::render(&markdown)));
+    let sent = match path {
+        Some(path) => {
+            server.send_file(markdown, &path).await?;
+            server.send_file_to(uri.as_str(), markdown, &path).await
+        }
+        None => {
+            server.send(markdown).await?;
+            server.send_to(uri.as_str(), markdown).await
+        }
+    };
+    metrics.renders.record(start.elapsed());
+    sent
+}
+
+/// Queues preview updates for a background task, which renders them once edits pause.
+pub struct Debouncer {
+    updates: mpsc::UnboundedSender<Update>,
+}
+
+impl Debouncer {
+    /// Start rendering updates on `server`, logging failures to `client`.
+    ///
+    /// The task stops when the debouncer is dropped, after rendering what's still queued.
+    pub fn spawn(
+        server: PreviewServer,
+        client: Client,
+        metrics: Arc<Metrics>,
+        diagrams: Arc<Diagrams>,
+    ) -> Self {
+        let (updates, queue) = mpsc::unbounded_channel();
+        tokio::spawn(run(queue, server, client, metrics, diagrams));
+        Self { updates }
+    }
+
+    pub fn push(&self, update: Update) {
+        // The task only stops once we're dropped.
+        let _ = self.updates.send(update);
+    }
+}
+
+/// Add `update` to the `pending` ones, replacing any earlier version of the same note, or drop
+/// it if a later version is pending already.
+///
+/// Pending updates are kept in the order their notes were last edited, so the last one is
+/// rendered last and ends up on the default channel.
+fn coalesce(pending: &mut Vec<Update>, mut update: Update) {
+    if let Some(i) = pending.iter().position(|pending| pending.uri == update.uri) {
+        if pending[i].version > update.version {
+            return;
+        }
+        let earlier = pending.remove(i);
+        update.line = update.line.or(earlier.line);
+    }
+    pending.push(update);
+}
+
+async fn run(
+    mut queue: mpsc::UnboundedReceiver<Update>,
+    server: PreviewServer,
+    client: Client,
+    metrics: Arc<Metrics>,
+    diagrams: Arc<Diagrams>,
+) {
+    // The version of each note shown last.
+    let mut shown = HashMap::<Url, u64>::new();
+    while let Some(update) = queue.recv().await {
+        let mut pending = vec![update];
+        let mut open = true;
+        while open {
+            let delay = pending.last().map_or(Duration::ZERO, |update| update.delay);
+            match time::timeout(delay, queue.recv()).await {
+                Ok(Some(update)) => coalesce(&mut pending, update),
+                Ok(None) => open = false,
+                Err(_) => break,
+            }
+        }
+
+        for update in pending {
+            if shown.get(&update.uri) > Some(&update.version) {
+                continue;
+            }
+            shown.insert(update.uri.clone(), update.version);
+            let mut server = server.lock().await;
+            let Some(server) = server.as_mut() else {
+                return;
+            };
+            // An external renderer can fail, e.g. if it isn't installed.
+            if let Err(e) = show(server, &update.uri, &update.markdown, &metrics, &diagrams).await {
+                let message = format!("Could not render preview: {e}");
+                logging::log(&client, MessageType::ERROR, message).await;
+                continue;
+            }
+            if let Some(line) = update.line {
+                server.scroll_to_line(line);
+                server.scroll_to_line_in(update.uri.as_str(), line);
+            }
+        }
+
+        if !open {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(uri: &str, markdown: &str, version: u64, line: Option<u32>) -> Update {
+        Update {
+            uri: Url::parse(uri).unwrap(),
+            markdown: markdown.to_string(),
+            version,
+            line,
+            delay: Duration::from_millis(100),
+        }
+    }
+
+    #[test]
+    fn only_the_latest_content_of_each_note_is_kept() {
+        let mut pending = Vec::new();
+        coalesce(&mut pending, update("file:///a.md", "a", 0, Some(0)));
+        coalesce(&mut pending, update("file:///b.md", "b", 1, Some(4)));
+        coalesce(&mut pending, update("file:///a.md", "ab", 2, None));
+        coalesce(&mut pending, update("file:///a.md", "abc", 4, Some(2)));
+        // Prepared more slowly than the later edit, which it mustn't overwrite.
+        coalesce(&mut pending, update("file:///a.md", "ab-", 3, None));
+        coalesce(&mut pending, update("file:///b.md", "bc", 5, None));
+
+        let pending = pending
+            .iter()
+            .map(|update| (update.uri.path(), update.markdown.as_str(), update.line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pending,
+            vec![("/a.md", "abc", Some(2)), ("/b.md", "bc", Some(4))]
+        );
+    }
+}
@@ -0,0 +1,87 @@
+//! Progress of long-running work, like indexing a vault, reported to the client with
+//! `$/progress` so the editor can show what the server is busy with.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use tower_lsp::{
+    lsp_types::{
+        notification, request::WorkDoneProgressCreate, NumberOrString, ProgressParams,
+        ProgressParamsValue, WorkDoneProgress, WorkDoneProgressBegin, WorkDoneProgressCreateParams,
+        WorkDoneProgressEnd, WorkDoneProgressReport,
+    },
+    Client,
+};
+
+static NEXT_TOKEN: AtomicU32 = AtomicU32::new(0);
+
+/// Progress shown by the client. If it doesn't support progress, reports go nowhere.
+pub struct Progress {
+    client: Client,
+    token: Option<NumberOrString>,
+}
+
+impl Progress {
+    /// Start showing the progress of the work called `title`, if the client `supports` it.
+    pub async fn begin(client: &Client, supports: bool, title: &str) -> Self {
+        let mut progress = Self {
+            client: client.clone(),
+            token: None,
+        };
+        if !supports {
+            return progress;
+        }
+        let token = NumberOrString::String(format!(
+            "note-ls/{}",
+            NEXT_TOKEN.fetch_add(1, Ordering::Relaxed)
+        ));
+        let params = WorkDoneProgressCreateParams {
+            token: token.clone(),
+        };
+        if client
+            .send_request::<WorkDoneProgressCreate>(params)
+            .await
+            .is_err()
+        {
+            return progress;
+        }
+        progress.token = Some(token);
+        progress
+            .send(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: title.to_string(),
+                percentage: Some(0),
+                ..WorkDoneProgressBegin::default()
+            }))
+            .await;
+        progress
+    }
+
+    async fn send(&self, value: WorkDoneProgress) {
+        let Some(token) = &self.token else {
+            return;
+        };
+        let params = ProgressParams {
+            token: token.clone(),
+            value: ProgressParamsValue::WorkDone(value),
+        };
+        self.client
+            .send_notification::<notification::Progress>(params)
+            .await;
+    }
+
+    /// Report that `done` of `total` steps are done, which the client shows after the title,
+    /// e.g. "Indexing notes 120/3500".
+    pub async fn report(&self, done: usize, total: usize) {
+        self.send(WorkDoneProgress::Report(WorkDoneProgressReport {
+            message: Some(format!("{done}/{total}")),
+            percentage: Some((done * 100 / total.max(1)) as u32),
+            ..WorkDoneProgressReport::default()
+        }))
+        .await;
+    }
+
+    /// Stop showing the progress.
+    pub async fn end(self) {
+        self.send(WorkDoneProgress::End(WorkDoneProgressEnd::default()))
+            .await;
+    }
+}
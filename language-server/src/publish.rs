@@ -0,0 +1,195 @@
+//! Static sites of a vault, for `noteLs.publish`.
+//!
+//! Every note becomes an HTML page at the same place in the output folder as it has in the vault,
+//! rendered like `noteLs.export.html` renders it, but with links to notes pointing at their pages.
+//! The attachments notes link to are copied along, and a page listing the notes is written as
+//! `index.html`, unless the vault has an `index` note of its own.
+
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::{PreviewDirection, Renderer},
+    export, links,
+};
+
+/// The name of the page listing the notes.
+pub const INDEX_PAGE: &str = "index.html";
+
+/// A note to publish.
+#[derive(Debug)]
+pub struct Page {
+    pub path: PathBuf,
+    pub title: String,
+    /// The note's content, as export::markdown gives it.
+    pub markdown: String,
+}
+
+/// The site of the vault at `root`, written to `output`.
+#[derive(Debug)]
+pub struct Site {
+    pub root: PathBuf,
+    pub output: PathBuf,
+    pub renderer: Renderer,
+    pub direction: PreviewDirection,
+    pub style: aurelius::export::Style,
+}
+
+impl Site {
+    /// Write the `pages` and copy the `attachments`, then write the index page.
+    ///
+    /// This blocks on the renderer, which may be an external program, and on the disk.
+    pub fn write(&self, pages: Vec<Page>, attachments: BTreeSet<PathBuf>) -> io::Result<()> {
+        let mut listed = Vec::new();
+        for page in pages {
+            let site_path = site_path(&self.root, &page.path, true);
+            let html = self.render(&page.markdown, &page.path, &page.title)?;
+            self.write_file(&site_path, html.as_bytes())?;
+            listed.push((page.title, site_path));
+        }
+        for attachment in attachments {
+            let site_path = site_path(&self.root, &attachment, false);
+            self.write_file(&site_path, &fs::read(&attachment)?)?;
+        }
+
+        if listed.iter().any(|(_, page)| page == Path::new(INDEX_PAGE)) {
+            return Ok(());
+        }
+        let name = self
+            .root
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        let markdown = index_markdown(&name, &listed);
+        let html = self.render(&markdown, &self.root.join(INDEX_PAGE), &name)?;
+        self.write_file(Path::new(INDEX_PAGE), html.as_bytes())
+    }
+
+    /// A page titled `title` showing `markdown`, read from the file at `path`.
+    fn render(&self, markdown: &str, path: &Path, title: &str) -> io::Result<String> {
+        let html = export::render(&self.renderer, self.direction, markdown, path)?;
+        // Images are copied along, so they're linked rather than inlined.
+        Ok(aurelius::export::standalone_page(
+            &html,
+            title,
+            None,
+            &self.style,
+        ))
+    }
+
+    /// Write `contents` to the file at `site_path` in the site, creating its folder.
+    fn write_file(&self, site_path: &Path, contents: &[u8]) -> io::Result<()> {
+        let path = self.output.join(site_path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)
+    }
+}
+
+/// Where a vault at `root` is published unless the command says otherwise: a folder named after
+/// it, next to it.
+pub fn default_output(root: &Path) -> PathBuf {
+    let name = root.file_name().map_or_else(
+        || String::from("vault"),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    root.with_file_name(format!("{name}-site"))
+}
+
+/// Where the file at `path`, in the vault at `root`, goes in the site: the same place, with an
+/// `.html` extension if it's a note.
+pub fn site_path(root: &Path, path: &Path, note: bool) -> PathBuf {
+    let relative = path.strip_prefix(root).map_or_else(
+        |_| PathBuf::from(path.file_name().unwrap_or_default()),
+        Path::to_path_buf,
+    );
+    match note {
+        true => relative.with_extension("html"),
+        false => relative,
+    }
+}
+
+/// The markdown of the page listing `pages`, pairs of a title and the page's path in the site,
+/// sorted by path.
+pub fn index_markdown(title: &str, pages: &[(String, PathBuf)]) -> String {
+    let mut pages = pages.iter().collect::<Vec<_>>();
+    pages.sort_by(|(_, a), (_, b)| a.cmp(b));
+    let mut markdown = format!("# {title}\n\n");
+    for (title, page) in pages {
+        let title = title.replace('[', "\\[").replace(']', "\\]");
+        let target = links::encode_target(&links::path_to_target(page));
+        markdown.push_str(&format!("- [{title}]({target})\n"));
+    }
+    markdown
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        export::{self, NoteLinks},
+        index::NoteIndex,
+    };
+
+    #[test]
+    fn notes_link_to_their_pages() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::create_dir(root.join("projects")).unwrap();
+        fs::write(root.join("projects/garden plan.md"), "# Plan\n## Beds\n").unwrap();
+        fs::write(root.join("photo.png"), "").unwrap();
+        let content =
+            "[[projects/garden plan#Beds|Beds]] [plan](projects/garden%20plan.md) ![[photo.png]]\n";
+        fs::write(root.join("home.md"), content).unwrap();
+        let index = NoteIndex::scan(root);
+
+        let mut read = |path: &Path| fs::read_to_string(path).ok();
+        let home = root.join("home.md");
+        assert_eq!(
+            export::markdown(&home, content, &index, &mut read, NoteLinks::Pages),
+            "[Beds](projects/garden%20plan.html#beds) \
+             [plan](projects/garden%20plan.html) ![](photo.png)\n"
+        );
+
+        let plan = root.join("projects/garden plan.md");
+        let page = site_path(root, &plan, true);
+        assert_eq!(page, Path::new("projects/garden plan.html"));
+        assert_eq!(
+            site_path(root, &root.join("photo.png"), false),
+            Path::new("photo.png")
+        );
+        assert_eq!(
+            index_markdown("Garden", &[(String::from("Plan [v2]"), page)]),
+            "# Garden\n\n- [Plan \\[v2\\]](projects/garden%20plan.html)\n"
+        );
+        assert_eq!(
+            default_output(Path::new("/notes/garden")),
+            Path::new("/notes/garden-site")
+        );
+
+        let output = tempfile::tempdir().unwrap();
+        let site = Site {
+            root: root.to_path_buf(),
+            output: output.path().to_path_buf(),
+            renderer: Renderer::Builtin,
+            direction: PreviewDirection::default(),
+            style: aurelius::export::Style::default(),
+        };
+        let pages = vec![Page {
+            path: plan.clone(),
+            title: String::from("Plan"),
+            markdown: fs::read_to_string(&plan).unwrap(),
+        }];
+        site.write(pages, BTreeSet::from([root.join("photo.png")]))
+            .unwrap();
+        let read = |path: &str| fs::read_to_string(output.path().join(path)).unwrap();
+        assert!(read("projects/garden plan.html").contains("<h2 id=\"beds\">Beds</h2>"));
+        assert!(read("index.html").contains("href=\"projects/garden%20plan.html\""));
+        assert_eq!(read("photo.png"), "");
+    }
+}
@@ -0,0 +1,172 @@
+//! The queue of notes waiting to be re-indexed.
+//!
+//! Edits, saves and file watcher events all queue the notes they touch. The server takes them off
+//! the queue in passes: a pass starts with the first change and collects more until none arrive
+//! for a while, so a burst like a `git checkout` touching a thousand notes is indexed in one go.
+//!
+//! The queue is bounded, but adding to it never waits. Changes that don't fit are dropped and the
+//! next pass rescans the whole vault instead.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::{sync::mpsc, time};
+
+use crate::index::Note;
+
+/// Number of changes the queue holds before it overflows.
+const CAPACITY: usize = 4096;
+
+/// What happened to a note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// The note was edited in the editor, which sent its new content.
+    Edited(String),
+    /// The note changed on disk, or was created.
+    Changed,
+    Deleted,
+}
+
+/// The sending end of the queue.
+#[derive(Debug)]
+pub struct Queue {
+    changes: mpsc::Sender<(PathBuf, Change)>,
+    overflowed: Arc<AtomicBool>,
+}
+
+/// The receiving end of the queue.
+#[derive(Debug)]
+pub struct Receiver {
+    changes: mpsc::Receiver<(PathBuf, Change)>,
+    overflowed: Arc<AtomicBool>,
+}
+
+/// Changes to index in one go.
+#[derive(Debug, Default)]
+pub struct Pass {
+    /// The last change of every note.
+    pub changes: HashMap<PathBuf, Change>,
+    /// Whether changes were dropped, so the whole vault must be scanned again.
+    pub rescan: bool,
+}
+
+/// A new, empty queue.
+pub fn queue() -> (Queue, Receiver) {
+    let (sender, receiver) = mpsc::channel(CAPACITY);
+    let overflowed = Arc::new(AtomicBool::new(false));
+    let queue = Queue {
+        changes: sender,
+        overflowed: Arc::clone(&overflowed),
+    };
+    let receiver = Receiver {
+        changes: receiver,
+        overflowed,
+    };
+    (queue, receiver)
+}
+
+impl Queue {
+    pub fn push(&self, path: PathBuf, change: Change) {
+        if self.changes.try_send((path, change)).is_err() {
+            self.overflowed.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Receiver {
+    /// Wait for the next pass: the changes queued until none arrive for `quiet`.
+    ///
+    /// Returns `None` once the queue is dropped and empty.
+    pub async fn next_pass(&mut self, quiet: Duration) -> Option<Pass> {
+        let mut pass = Pass::default();
+        let (path, change) = self.changes.recv().await?;
+        pass.changes.insert(path, change);
+        while let Ok(Some((path, change))) = time::timeout(quiet, self.changes.recv()).await {
+            pass.changes.insert(path, change);
+        }
+        pass.rescan = self.overflowed.swap(false, Ordering::Relaxed);
+        Some(pass)
+    }
+}
+
+impl Pass {
+    /// Parse the changed notes, reading the ones that changed on disk. `None` means the note is
+    /// gone from the index.
+    ///
+    /// This blocks on the file system, and is meant to run before taking the index lock.
+    pub fn parse(self) -> Vec<(PathBuf, Option<Note>)> {
+        self.changes
+            .into_iter()
+            .map(|(path, change)| {
+                let note = match change {
+                    Change::Edited(content) => Some(Note::parse(&content)),
+                    Change::Changed => fs::read_to_string(&path)
+                        .ok()
+                        .map(|content| Note::parse(&content)),
+                    Change::Deleted => None,
+                };
+                (path, note)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn bursts_are_coalesced_into_one_pass() {
+        let (queue, mut receiver) = queue();
+        let quiet = Duration::from_millis(50);
+        for i in 0..100 {
+            queue.push(PathBuf::from(format!("/v/{}.md", i % 10)), Change::Changed);
+        }
+        queue.push(PathBuf::from("/v/0.md"), Change::Deleted);
+
+        let pass = receiver.next_pass(quiet).await.unwrap();
+        assert_eq!(pass.changes.len(), 10);
+        assert_eq!(pass.changes[&PathBuf::from("/v/0.md")], Change::Deleted);
+        assert!(!pass.rescan);
+
+        drop(queue);
+        assert!(receiver.next_pass(quiet).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn overflowing_asks_for_a_rescan() {
+        let (queue, mut receiver) = queue();
+        for i in 0..CAPACITY + 1 {
+            queue.push(PathBuf::from(format!("/v/{i}.md")), Change::Changed);
+        }
+
+        let pass = receiver.next_pass(Duration::ZERO).await.unwrap();
+        assert!(pass.rescan);
+    }
+
+    #[test]
+    fn edits_win_over_the_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let saved = dir.path().join("saved.md");
+        fs::write(&saved, "[[on disk]]").unwrap();
+
+        let mut pass = Pass::default();
+        pass.changes
+            .insert(saved.clone(), Change::Edited("[[edited]]".to_string()));
+        pass.changes
+            .insert(dir.path().join("gone.md"), Change::Changed);
+        let mut parsed = pass.parse();
+        parsed.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert!(parsed[0].1.is_none());
+        assert_eq!(parsed[1].1.as_ref().unwrap().links[0].target, "edited");
+    }
+}
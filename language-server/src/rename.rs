@@ -0,0 +1,402 @@
+//! Keeping links intact when files are renamed or moved, and when headings are renamed.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+use crate::{
+    config::AttachmentsPolicy,
+    headings::{self, Heading},
+    index::{self, Match, Note, NoteIndex, Renames},
+    links::{self, Link, LinkKind},
+};
+
+/// Link edits (and extra attachment moves) needed to keep a moved note's attachment links working.
+pub struct AttachmentMove {
+    pub edits: Vec<TextEdit>,
+    /// Attachments that should be moved along with the note.
+    pub moves: Vec<(PathBuf, PathBuf)>,
+}
+
+/// Attachments linked from `links` in the note at `old`, resolved to existing files.
+fn attachment_links<'a, S: AsRef<str>>(
+    old: &Path,
+    links: &'a [Link],
+    extensions: &[S],
+) -> Vec<(&'a Link, PathBuf)> {
+    let old_dir = old.parent().unwrap_or(old);
+
+    links
+        .iter()
+        .filter(|link| link.kind == LinkKind::Markdown && !link.is_external())
+        .filter(|link| !link.target_path().is_empty())
+        .filter_map(|link| {
+            let path = links::normalize(&old_dir.join(link.decoded_path().as_ref()));
+            let is_attachment = path.is_file() && !index::has_extension(&path, extensions);
+            is_attachment.then_some((link, path))
+        })
+        .collect()
+}
+
+/// Work out what has to change when the note at `old` is moved to `new`.
+///
+/// `renames` holds every rename in the current operation, so attachments that are already being
+/// moved (e.g. because their whole folder is) are not moved a second time.
+pub fn move_attachments<S: AsRef<str>>(
+    old: &Path,
+    new: &Path,
+    links: &[Link],
+    renames: &Renames,
+    policy: AttachmentsPolicy,
+    extensions: &[S],
+) -> AttachmentMove {
+    let mut result = AttachmentMove {
+        edits: Vec::new(),
+        moves: Vec::new(),
+    };
+    if policy == AttachmentsPolicy::Ignore {
+        return result;
+    }
+
+    let old_dir = old.parent().unwrap_or(old);
+    let new_dir = new.parent().unwrap_or(new);
+    let mut moved = HashMap::new();
+
+    for (link, path) in attachment_links(old, links, extensions) {
+        let destination = match renames.map(&path) {
+            Some(destination) => destination,
+            None if policy == AttachmentsPolicy::Move && path.starts_with(old_dir) => {
+                let destination = new_dir.join(path.strip_prefix(old_dir).unwrap());
+                moved.insert(path.clone(), destination.clone());
+                destination
+            }
+            None => path,
+        };
+
+        let target = links::encode_target(&links::path_to_target(&links::relative_path(
+            new_dir,
+            &destination,
+        )));
+        if target == link.target_path() {
+            continue;
+        }
+
+        result
+            .edits
+            .push(TextEdit::new(link.range(), link.with_target(&target)));
+    }
+
+    result.moves = moved.into_iter().collect();
+    result.moves.sort();
+    result
+}
+
+/// Rewrite `link`, found in the note at `from` and pointing at the note `old_target`, so that it
+/// points at `new_target` from the note's new location `new_from`.
+///
+/// Links keep their style: links written relative to the vault root stay root-relative, and wiki
+/// links without an extension stay without one.
+fn retarget(
+    link: &Link,
+    (from, new_from): (&Path, &Path),
+    (old_target, new_target): (&Path, &Path),
+    root: &Path,
+) -> Option<TextEdit> {
+    let relative_to_note = link
+        .candidates(from.parent()?, root)
+        .first()
+        .map(|candidate| links::nfc_path(candidate))
+        == Some(links::nfc_path(old_target));
+    let base = if relative_to_note {
+        new_from.parent()?
+    } else {
+        root
+    };
+
+    let mut target = links::relative_path(base, new_target);
+    if link.kind == LinkKind::Wiki && Path::new(link.target_path()).extension().is_none() {
+        target.set_extension("");
+    }
+    let target = match link.kind {
+        LinkKind::Wiki => links::path_to_target(&target),
+        LinkKind::Markdown => links::encode_target(&links::path_to_target(&target)),
+    };
+
+    (target != link.target_path()).then(|| TextEdit::new(link.range(), link.with_target(&target)))
+}
+
+/// Edits to every link between notes that `renames` would break, keyed by the current path of
+/// the note containing the link.
+///
+/// This covers links to renamed notes as well as relative links from renamed notes to ones that
+/// stay put.
+pub fn relink_notes(index: &NoteIndex, renames: &Renames) -> HashMap<PathBuf, Vec<TextEdit>> {
+    let mut edits = HashMap::<PathBuf, Vec<TextEdit>>::new();
+
+    for (from, note) in index.notes() {
+        let new_from = renames.map(from);
+        for link in &note.links {
+            let Some((target, found)) = index.resolve_match(from, link) else {
+                continue;
+            };
+            let new_target = renames.map(&target);
+            if new_from.is_none() && new_target.is_none() {
+                continue;
+            }
+            // Links by title or alias keep working wherever the note goes, and so do links by
+            // file name as long as it stays.
+            let same_name = match &new_target {
+                Some(new_target) => new_target.file_stem() == target.file_stem(),
+                None => true,
+            };
+            if found == Match::Title || found == Match::Alias || found == Match::Name && same_name {
+                continue;
+            }
+
+            let edit = retarget(
+                link,
+                (from, new_from.as_deref().unwrap_or(from)),
+                (&target, new_target.as_deref().unwrap_or(&target)),
+                index.root(),
+            );
+            if let Some(edit) = edit {
+                edits.entry(from.to_path_buf()).or_default().push(edit);
+            }
+        }
+    }
+
+    edits
+}
+
+/// The heading on the line of `position` in `content`, along with the range of its text.
+pub fn heading_at(content: &str, position: Position) -> Option<(Heading, Range)> {
+    let heading = headings::parse_headings(content)
+        .into_iter()
+        .find(|heading| heading.line == position.line)?;
+    let line = content.lines().nth(position.line as usize)?;
+    let hashes = line.len() - line.trim_start().len() + heading.level as usize;
+    let start = hashes + (line[hashes..].len() - line[hashes..].trim_start().len());
+    let end = start + heading.text.len();
+    let range = Range::new(
+        Position::new(heading.line, start as u32),
+        Position::new(heading.line, end as u32),
+    );
+    Some((heading, range))
+}
+
+/// The anchor replacing `anchor`, which names `old` in `note`, once the heading's text is `new`.
+/// Anchors written as slugs stay slugs, numbered if another heading has the same one.
+fn new_anchor(anchor: &str, note: &Note, old: &Heading, new: &str) -> String {
+    if anchor == old.text || anchor != note.heading_anchor(old) {
+        return new.to_string();
+    }
+    let renamed = Note {
+        headings: note
+            .headings
+            .iter()
+            .map(|heading| match heading.line == old.line {
+                true => Heading {
+                    text: new.to_string(),
+                    ..heading.clone()
+                },
+                false => heading.clone(),
+            })
+            .collect(),
+        ..Note::default()
+    };
+    renamed.heading_anchor(old)
+}
+
+/// Edits to every link in the vault pointing at `heading` in the note at `target`, whose content
+/// is `content`, so they keep pointing at it once its text is `new`. They're keyed by the path of
+/// the linking note.
+pub fn relink_heading(
+    index: &NoteIndex,
+    target: &Path,
+    content: &str,
+    heading: &Heading,
+    new: &str,
+) -> HashMap<PathBuf, Vec<TextEdit>> {
+    let note = Note::parse(content);
+    let names_heading = |link: &Link| {
+        link.anchor()
+            .and_then(|anchor| note.find_heading(anchor))
+            .is_some_and(|linked| linked.line == heading.line)
+    };
+
+    // Links like `[[#Heading]]` within the note, and links to it from the rest of the vault.
+    let own = note
+        .links
+        .iter()
+        .filter(|link| link.target_path().is_empty())
+        .map(|link| (target, link));
+    let mut edits = HashMap::<PathBuf, Vec<TextEdit>>::new();
+    for (from, link) in own.chain(index.backlinks(target)) {
+        if !names_heading(link) {
+            continue;
+        }
+        let anchor = new_anchor(link.anchor().unwrap_or_default(), &note, heading, new);
+        edits
+            .entry(from.to_path_buf())
+            .or_default()
+            .push(TextEdit::new(link.range(), link.with_anchor(&anchor)));
+    }
+    edits
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn moving_a_note_updates_or_moves_attachments() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::create_dir_all(root.join("notes/img")).unwrap();
+        fs::create_dir_all(root.join("shared")).unwrap();
+        fs::create_dir_all(root.join("archive")).unwrap();
+        fs::write(root.join("notes/img/local.png"), "").unwrap();
+        fs::write(root.join("shared/logo.png"), "").unwrap();
+
+        let old = root.join("notes/note.md");
+        let new = root.join("archive/note.md");
+        let links = links::parse_links("![](img/local.png)\n![logo](../shared/logo.png#x)");
+        let renames = Renames::new(vec![(old.clone(), new.clone())]);
+
+        let update = move_attachments(
+            &old,
+            &new,
+            &links,
+            &renames,
+            AttachmentsPolicy::UpdateLinks,
+            &["md"],
+        );
+        assert!(update.moves.is_empty());
+        assert_eq!(update.edits.len(), 1);
+        assert_eq!(update.edits[0].new_text, "![](../notes/img/local.png)");
+
+        let moved = move_attachments(
+            &old,
+            &new,
+            &links,
+            &renames,
+            AttachmentsPolicy::Move,
+            &["md"],
+        );
+        assert_eq!(
+            moved.moves,
+            vec![(
+                root.join("notes/img/local.png"),
+                root.join("archive/img/local.png")
+            )]
+        );
+        assert!(moved.edits.is_empty());
+
+        let ignored = move_attachments(
+            &old,
+            &new,
+            &links,
+            &renames,
+            AttachmentsPolicy::Ignore,
+            &["md"],
+        );
+        assert!(ignored.edits.is_empty() && ignored.moves.is_empty());
+    }
+
+    #[test]
+    fn renaming_a_note_relinks_the_vault() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("foo.md"), "").unwrap();
+        fs::write(root.join("a.md"), "[[foo]] and [x](foo.md#h)").unwrap();
+        fs::write(root.join("sub/b.md"), "[[foo|F]] and ![y](../foo.md)").unwrap();
+
+        let index = NoteIndex::scan(root);
+        let renames = Renames::new(vec![(root.join("foo.md"), root.join("notes/bar.md"))]);
+        let edits = relink_notes(&index, &renames);
+
+        let new_text = |path: &str| {
+            edits[&root.join(path)]
+                .iter()
+                .map(|edit| edit.new_text.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            new_text("a.md"),
+            vec!["[[notes/bar]]", "[x](notes/bar.md#h)"]
+        );
+        assert_eq!(
+            new_text("sub/b.md"),
+            vec!["[[notes/bar|F]]", "![y](../notes/bar.md)"]
+        );
+    }
+
+    #[test]
+    fn renaming_a_heading_relinks_its_anchors() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        let content = "# Note\n\n##  Old Title ##\nSee [[#Old Title]] and [[#Note]].\n";
+        fs::write(root.join("note.md"), content).unwrap();
+        fs::write(
+            root.join("a.md"),
+            "[[note#old title|here]] [x](note.md#old-title) [[note]] [[note#Note]]",
+        )
+        .unwrap();
+        let index = NoteIndex::scan(root);
+
+        let (heading, range) = heading_at(content, Position::new(2, 5)).unwrap();
+        assert_eq!(heading.text, "Old Title");
+        assert_eq!(range, Range::new(Position::new(2, 4), Position::new(2, 13)));
+        assert_eq!(heading_at(content, Position::new(1, 0)), None);
+
+        let edits = relink_heading(&index, &root.join("note.md"), content, &heading, "New One");
+        let new_text = |path: &str| {
+            edits[&root.join(path)]
+                .iter()
+                .map(|edit| edit.new_text.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(new_text("note.md"), vec!["[[#New One]]"]);
+        assert_eq!(
+            new_text("a.md"),
+            vec!["[[note#New One|here]]", "[x](note.md#new-one)"]
+        );
+        assert_eq!(
+            edits[&root.join("note.md")][0].range,
+            Range::new(Position::new(3, 4), Position::new(3, 18))
+        );
+    }
+
+    #[test]
+    fn links_by_name_survive_moves() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("sub/foo.md"), "---\naliases: [Eff]\n---\n").unwrap();
+        fs::write(root.join("a.md"), "[[foo]] [[Eff]] [[sub/foo]]").unwrap();
+
+        let mut index = NoteIndex::scan(root);
+        index.set_resolution(index::Resolution::Shortest);
+        let renames = Renames::new(vec![(root.join("sub/foo.md"), root.join("other/foo.md"))]);
+        let edits = relink_notes(&index, &renames);
+        let new_text = edits[&root.join("a.md")]
+            .iter()
+            .map(|edit| edit.new_text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(new_text, vec!["[[other/foo]]"]);
+
+        let renames = Renames::new(vec![(root.join("sub/foo.md"), root.join("sub/bar.md"))]);
+        let edits = relink_notes(&index, &renames);
+        let new_text = edits[&root.join("a.md")]
+            .iter()
+            .map(|edit| edit.new_text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(new_text, vec!["[[sub/bar]]", "[[sub/bar]]"]);
+    }
+}
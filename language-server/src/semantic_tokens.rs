@@ -0,0 +1,283 @@
+//! Semantic tokens, for `textDocument/semanticTokens`, so editors without a good Markdown grammar
+//! can still highlight what the server knows about notes.
+//!
+//! The token types are standard ones, which themes color without any setup: wiki links are
+//! `class`es, tags `decorator`s, frontmatter keys `property`s, headings `namespace`s and the
+//! language of a code block a `type`. Headings have a `level1` to `level6` modifier, and embeds
+//! an `embed` one. Tokens never overlap: a heading's is split around the links and tags in it.
+
+use tower_lsp::lsp_types::{
+    Range, SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensLegend,
+};
+
+use crate::{
+    code_blocks, comments,
+    encoding::Encoding,
+    frontmatter, headings,
+    links::{self, LinkKind},
+    tags,
+};
+
+/// The token types, in the order of the legend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    WikiLink,
+    Tag,
+    FrontmatterKey,
+    Heading,
+    Language,
+}
+
+const TYPES: [SemanticTokenType; 5] = [
+    SemanticTokenType::CLASS,
+    SemanticTokenType::new("decorator"),
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::NAMESPACE,
+    SemanticTokenType::TYPE,
+];
+
+/// The token modifiers, in the order of their bits: the heading levels, then `embed`.
+const MODIFIERS: [&str; 7] = [
+    "level1", "level2", "level3", "level4", "level5", "level6", "embed",
+];
+
+const EMBED: u32 = 1 << 6;
+
+/// A token, with byte offsets into its line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Token {
+    line: u32,
+    start: usize,
+    end: usize,
+    kind: Kind,
+    modifiers: u32,
+}
+
+/// The legend of the tokens, advertised in the server capabilities.
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TYPES.to_vec(),
+        token_modifiers: MODIFIERS
+            .into_iter()
+            .map(SemanticTokenModifier::new)
+            .collect(),
+    }
+}
+
+/// The key of the frontmatter field `line` starts, as its start and end.
+fn frontmatter_key(line: &str) -> Option<(usize, usize)> {
+    let trimmed = line.trim_start();
+    let start = line.len() - trimmed.len();
+    let len = trimmed
+        .find(": ")
+        .or_else(|| trimmed.trim_end().strip_suffix(':').map(str::len))?;
+    let key = &trimmed[..len];
+    let valid = !key.is_empty() && !key.starts_with(['-', '#', '"', '\'']);
+    valid.then_some((start, start + len))
+}
+
+/// The tokens of `document`, in order.
+fn tokens(document: &str) -> Vec<Token> {
+    let lines = document.lines().collect::<Vec<_>>();
+    let body_start = frontmatter::body_start(document);
+    let blocks = code_blocks::blocks(document);
+    let in_code = |line: u32| {
+        blocks
+            .iter()
+            .any(|block| block.start <= line && line <= block.end)
+    };
+    let mut tokens = Vec::new();
+
+    // The frontmatter is between the `---` on the first line and the one before the body.
+    let frontmatter = lines
+        .iter()
+        .zip(0u32..)
+        .take(body_start.saturating_sub(1) as usize)
+        .skip(1);
+    for (line, n) in frontmatter {
+        if let Some((start, end)) = frontmatter_key(line) {
+            tokens.push(Token {
+                line: n,
+                start,
+                end,
+                kind: Kind::FrontmatterKey,
+                modifiers: 0,
+            });
+        }
+    }
+
+    for block in blocks.iter().filter(|block| !block.language.is_empty()) {
+        let line = lines[block.start as usize];
+        let fence = line.trim_start().trim_start_matches(['`', '~']);
+        let Some(start) = fence.find(&block.language) else {
+            continue;
+        };
+        let start = line.len() - fence.len() + start;
+        tokens.push(Token {
+            line: block.start,
+            start,
+            end: start + block.language.len(),
+            kind: Kind::Language,
+            modifiers: 0,
+        });
+    }
+
+    // Links and tags in comments aren't highlighted, as they aren't indexed either.
+    let blanked = comments::blank(document);
+    let links = links::parse_links(&blanked)
+        .into_iter()
+        .filter(|link| {
+            link.kind == LinkKind::Wiki && link.line >= body_start && !in_code(link.line)
+        })
+        .map(|link| Token {
+            line: link.line,
+            start: link.start,
+            end: link.end,
+            kind: Kind::WikiLink,
+            modifiers: if link.embed { EMBED } else { 0 },
+        });
+    let tags = tags::parse_tags(&blanked).into_iter().map(|tag| Token {
+        line: tag.line,
+        start: tag.start,
+        end: tag.end,
+        kind: Kind::Tag,
+        modifiers: 0,
+    });
+    let mut inline = links.chain(tags).collect::<Vec<_>>();
+    inline.sort_by_key(|token| (token.line, token.start));
+
+    for heading in headings::parse_headings(document) {
+        let line = lines[heading.line as usize];
+        let mut start = line.len() - line.trim_start().len();
+        let end = line.trim_end().len();
+        let mut segment = |start: usize, end: usize| {
+            if start < end {
+                tokens.push(Token {
+                    line: heading.line,
+                    start,
+                    end,
+                    kind: Kind::Heading,
+                    modifiers: 1 << (heading.level - 1),
+                });
+            }
+        };
+        for token in inline.iter().filter(|token| token.line == heading.line) {
+            segment(start, token.start);
+            start = start.max(token.end);
+        }
+        segment(start, end);
+    }
+
+    tokens.extend(inline);
+    tokens.sort_by_key(|token| (token.line, token.start));
+    tokens
+}
+
+/// The semantic tokens of `document`, only those on the lines of `range` if it's given, with
+/// characters counted in `encoding`.
+pub fn semantic_tokens(
+    document: &str,
+    range: Option<Range>,
+    encoding: Encoding,
+) -> Vec<SemanticToken> {
+    let in_range = |token: &Token| {
+        range.is_none_or(|range| range.start.line <= token.line && token.line <= range.end.line)
+    };
+    let lines = document.lines().collect::<Vec<_>>();
+    let (mut line, mut start) = (0, 0);
+    tokens(document)
+        .into_iter()
+        .filter(in_range)
+        .map(|token| {
+            let text = lines.get(token.line as usize).copied().unwrap_or_default();
+            let (token_start, token_end) = (
+                encoding.from_byte(text, token.start),
+                encoding.from_byte(text, token.end),
+            );
+            // Each token is given relative to the one before it.
+            let delta_line = token.line - line;
+            let delta_start = match delta_line {
+                0 => token_start - start,
+                _ => token_start,
+            };
+            (line, start) = (token.line, token_start);
+            SemanticToken {
+                delta_line,
+                delta_start,
+                length: token_end - token_start,
+                token_type: token.kind as u32,
+                token_modifiers_bitset: token.modifiers,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::Position;
+
+    use super::*;
+
+    /// The tokens of `document` as their text, type and modifiers, decoded the way clients do.
+    fn decode(document: &str, range: Option<Range>) -> Vec<(String, String, u32)> {
+        let lines = document.lines().collect::<Vec<_>>();
+        let (mut line, mut start) = (0, 0);
+        semantic_tokens(document, range, Encoding::Utf8)
+            .into_iter()
+            .map(|token| {
+                line += token.delta_line;
+                start = match token.delta_line {
+                    0 => start + token.delta_start,
+                    _ => token.delta_start,
+                };
+                let end = start + token.length;
+                let text = &lines[line as usize][start as usize..end as usize];
+                let kind = TYPES[token.token_type as usize].as_str().to_string();
+                (text.to_string(), kind, token.token_modifiers_bitset)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn note_constructs_are_classified() {
+        let document = "---\ntitle: Tea\ntags:\n  - drinks\n---\n\
+                        ## About [[Tea]] #drinks\n\
+                        See ![[teapot.png]] and [md](b.md) %%[[hidden]]%%\n\
+                        ```rust\n[[code]] #code\n```\n";
+        let token =
+            |text: &str, kind: &str, modifiers| (text.to_string(), kind.to_string(), modifiers);
+        assert_eq!(
+            decode(document, None),
+            [
+                token("title", "property", 0),
+                token("tags", "property", 0),
+                token("## About ", "namespace", 2),
+                token("[[Tea]]", "class", 0),
+                token(" ", "namespace", 2),
+                token("#drinks", "decorator", 0),
+                token("![[teapot.png]]", "class", EMBED),
+                token("rust", "type", 0),
+            ]
+        );
+
+        let range = Range::new(Position::new(6, 0), Position::new(7, 0));
+        assert_eq!(
+            decode(document, Some(range)),
+            [
+                token("![[teapot.png]]", "class", EMBED),
+                token("rust", "type", 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokens_are_counted_in_the_encoding() {
+        // "é" takes two bytes and one UTF-16 unit.
+        let tokens = semantic_tokens("## Café [[Tea]]", None, Encoding::Utf16);
+        let spans = tokens
+            .iter()
+            .map(|token| (token.delta_start, token.length))
+            .collect::<Vec<_>>();
+        assert_eq!(spans, [(0, 8), (8, 7)]);
+    }
+}
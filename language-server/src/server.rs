@@ -0,0 +1,4798 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    attachments, backup, badges,
+    books::{self, Books, BooksParams},
+    bundle, call_hierarchy, charts, citations, code_actions, code_blocks, code_lens, commands,
+    comments, compare,
+    completion::{self, CompletionCache},
+    config::{Config, PreviewDirection, PreviewTheme, Renderer},
+    contents::ContentCache,
+    daily_notes, dates, diagnostics,
+    diagrams::Diagrams,
+    document_links,
+    encoding::Encoding,
+    excerpt, export, flatten,
+    focus::{self, SectionRange, SectionRangeParams},
+    folding, footnote_links, footnotes, frontmatter, git, graph, habits, highlights,
+    hooks::{self, Event},
+    hover,
+    i18n::{self, Messages},
+    ignore::Ignore,
+    index::{Note, NoteIndex, Renames},
+    index_cache,
+    index_changes::{IndexChanged, IndexChangedParams},
+    inlay_hints,
+    link_checker::{self, LinkChecker},
+    link_style,
+    links::{self, Link, LinkKind},
+    lists, logging,
+    metrics::{self, Metrics},
+    new_notes::{self, Date},
+    note_info::{self, NoteInfo, NoteInfoParams},
+    plugins::{Feature, Plugins},
+    preview::{self, Debouncer, PreviewServer, PreviewStarted, PreviewStartedParams},
+    progress::Progress,
+    publish,
+    reindex::{self, Change},
+    rename, report, search, semantic_tokens,
+    spelling::{self, Dictionary},
+    stats::{self, Stats},
+    symbols,
+    syntax::Syntax,
+    tables, tags, tasks, templates,
+    timeout::{self, Limits, Timeout},
+    toc, uri, vault_setup,
+    vaults::{Vault, Vaults},
+    watchdog,
+};
+use aurelius::render::{ExternalCommand, PulldownCmark, RendererProcess};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use tokio::sync::{Mutex, RwLock, RwLockReadGuard};
+use tower_lsp::{
+    jsonrpc::{Error, ErrorCode, Result},
+    lsp_types::{
+        request::ShowDocument, ApplyWorkspaceEditResponse, CallHierarchyIncomingCall,
+        CallHierarchyIncomingCallsParams, CallHierarchyItem, CallHierarchyOutgoingCall,
+        CallHierarchyOutgoingCallsParams, CallHierarchyPrepareParams,
+        CallHierarchyServerCapability, ClientCapabilities, CodeActionOrCommand, CodeActionParams,
+        CodeActionProviderCapability, CodeActionResponse, CodeLens, CodeLensOptions,
+        CodeLensParams, CompletionItem, CompletionList, CompletionOptions, CompletionParams,
+        CompletionResponse, CompletionTextEdit, CreateFile, CreateFilesParams, Diagnostic,
+        DiagnosticOptions, DiagnosticServerCapabilities, DidChangeConfigurationParams,
+        DidChangeTextDocumentParams, DidChangeWatchedFilesParams,
+        DidChangeWatchedFilesRegistrationOptions, DidChangeWorkspaceFoldersParams,
+        DidCloseTextDocumentParams, DidOpenTextDocumentParams, DidSaveTextDocumentParams,
+        DocumentChangeOperation, DocumentChanges, DocumentDiagnosticParams,
+        DocumentDiagnosticReport, DocumentDiagnosticReportKind, DocumentDiagnosticReportResult,
+        DocumentFormattingParams, DocumentHighlight, DocumentHighlightParams, DocumentLink,
+        DocumentLinkOptions, DocumentLinkParams, DocumentOnTypeFormattingOptions,
+        DocumentOnTypeFormattingParams, DocumentRangeFormattingParams, DocumentSymbol,
+        DocumentSymbolParams, DocumentSymbolResponse, ExecuteCommandOptions, ExecuteCommandParams,
+        FileChangeType, FileOperationFilter, FileOperationPattern,
+        FileOperationRegistrationOptions, FileRename, FileSystemWatcher, FoldingRange,
+        FoldingRangeParams, FoldingRangeProviderCapability, GotoDefinitionParams,
+        GotoDefinitionResponse, Hover, HoverParams, HoverProviderCapability, InitializeParams,
+        InitializeResult, InitializedParams, InlayHint, InlayHintParams, Location, MarkupKind,
+        MessageActionItem, MessageType, OneOf, OptionalVersionedTextDocumentIdentifier, Position,
+        PrepareRenameResponse, Range, ReferenceParams, Registration,
+        RelatedFullDocumentDiagnosticReport, RelatedUnchangedDocumentDiagnosticReport, RenameFile,
+        RenameFilesParams, RenameOptions, RenameParams, ResourceOp, SemanticTokens,
+        SemanticTokensFullOptions, SemanticTokensOptions, SemanticTokensParams,
+        SemanticTokensRangeParams, SemanticTokensRangeResult, SemanticTokensResult,
+        SemanticTokensServerCapabilities, ServerCapabilities, ShowDocumentParams,
+        TextDocumentContentChangeEvent, TextDocumentEdit, TextDocumentIdentifier,
+        TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind,
+        TextDocumentSyncOptions, TextDocumentSyncSaveOptions, TextEdit, Url,
+        WorkDoneProgressOptions, WorkspaceDiagnosticParams, WorkspaceDiagnosticReport,
+        WorkspaceDiagnosticReportResult, WorkspaceDocumentDiagnosticReport, WorkspaceEdit,
+        WorkspaceFileOperationsServerCapabilities, WorkspaceFoldersServerCapabilities,
+        WorkspaceFullDocumentDiagnosticReport, WorkspaceServerCapabilities,
+        WorkspaceUnchangedDocumentDiagnosticReport,
+    },
+    Client, ClientSocket, LanguageServer, LspService,
+};
+
+/// Get the word in `document` at position `cursor_pos`. Cut off word at cursor
+/// position.
+///
+/// ## Example:
+/// ```ignore
+/// let doc = "this is a sentence";
+/// let position = Position { line: 0, character: 1 };
+/// let curr_word = get_current_word(doc, position).unwrap();
+/// assert_eq!(curr_word, "t");
+/// ```
+fn get_current_word(document: &str, cursor_pos: Position) -> Option<&str> {
+    let line = document.lines().nth(cursor_pos.line as usize)?;
+    let character = cursor_pos.character as usize;
+
+    // Go to position at cursor_position.character, giving up if it's past the end of the line
+    // or in the middle of a character.
+    // Go backwards until end of iterator or whitespace, save index
+    let backwards_bytes = line
+        .get(..character)?
+        .chars()
+        .rev()
+        .take_while(|c| !c.is_whitespace())
+        .map(char::len_utf8)
+        .sum::<usize>();
+
+    let start_word = character - backwards_bytes;
+
+    Some(&line[start_word..character])
+}
+
+/// `markdown` without its `%% %%` comments, unless `config` shows them in the preview.
+fn hide_comments(markdown: String, config: &Config) -> String {
+    match config.preview_comments {
+        true => markdown,
+        false => comments::strip(&markdown, comments::Kind::Percent).into_owned(),
+    }
+}
+
+/// Wrap an unexpected failure in a JSON-RPC internal error, keeping its message.
+fn internal_error(e: impl ToString) -> Error {
+    Error {
+        code: ErrorCode::InternalError,
+        message: e.to_string().into(),
+        data: None,
+    }
+}
+
+/// Register for file operations on every file and folder on disk.
+fn file_operation_options() -> FileOperationRegistrationOptions {
+    FileOperationRegistrationOptions {
+        filters: vec![FileOperationFilter {
+            scheme: Some("file".to_string()),
+            pattern: FileOperationPattern {
+                glob: "**".to_string(),
+                matches: None,
+                options: None,
+            },
+        }],
+    }
+}
+
+/// The vaults the client opened: its workspace folders, or else its root URI.
+fn workspace_roots(params: &InitializeParams) -> Vec<PathBuf> {
+    match &params.workspace_folders {
+        Some(folders) if !folders.is_empty() => folders
+            .iter()
+            .filter_map(|folder| uri::to_path(&folder.uri))
+            .collect(),
+        _ => params.root_uri.iter().filter_map(uri::to_path).collect(),
+    }
+}
+
+/// The URIs of the notes in `index`.
+fn note_uris(index: &NoteIndex) -> Vec<Url> {
+    index
+        .notes()
+        .filter_map(|(path, _)| uri::from_path(path))
+        .collect()
+}
+
+/// A glob pattern matching every note with one of `extensions`, for file watchers.
+fn note_glob(extensions: &[String]) -> String {
+    match extensions {
+        [extension] => format!("**/*.{extension}"),
+        _ => format!("**/*.{{{}}}", extensions.join(",")),
+    }
+}
+
+/// Convert the renames of a file operation request into filesystem paths.
+fn file_renames(files: &[FileRename]) -> Renames {
+    Renames::new(
+        files
+            .iter()
+            .filter_map(|file| {
+                let old = uri::to_path(&Url::parse(&file.old_uri).ok()?)?;
+                let new = uri::to_path(&Url::parse(&file.new_uri).ok()?)?;
+                Some((old, new))
+            })
+            .collect(),
+    )
+}
+
+/// Number of notes read at a time while indexing a vault, between which requests are answered
+/// from the notes indexed so far.
+const INDEX_BATCH: usize = 200;
+
+/// Number of notes checked at a time by the diagnostics pass over the vault.
+const VAULT_DIAGNOSTICS_BATCH: usize = 50;
+
+/// Pause between batches of the diagnostics pass, to leave the server free to answer the editor.
+const VAULT_DIAGNOSTICS_PAUSE: Duration = Duration::from_millis(20);
+
+/// Parameters of the `noteLs/cursorMoved` notification.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CursorMovedParams {
+    pub text_document: TextDocumentIdentifier,
+    pub position: Position,
+}
+
+/// `edits` of `document`, with ranges counted in bytes, as `encoding` counts them.
+fn edits_to_client(encoding: Encoding, document: &str, mut edits: Vec<TextEdit>) -> Vec<TextEdit> {
+    for edit in &mut edits {
+        edit.range = encoding.range_to_client(document, edit.range);
+    }
+    edits
+}
+
+/// Convert the ranges of `symbols` of `document`, and of their children, to how `encoding`
+/// counts them.
+fn symbols_to_client(encoding: Encoding, document: &str, symbols: &mut [DocumentSymbol]) {
+    for symbol in symbols {
+        symbol.range = encoding.range_to_client(document, symbol.range);
+        symbol.selection_range = encoding.range_to_client(document, symbol.selection_range);
+        if let Some(children) = &mut symbol.children {
+            symbols_to_client(encoding, document, children);
+        }
+    }
+}
+
+/// The first line that differs between `old` and `new`, if they differ at all.
+fn first_changed_line(old: &str, new: &str) -> Option<u32> {
+    let mut old_lines = old.lines();
+    let mut new_lines = new.lines();
+    let mut line: u32 = 0;
+    loop {
+        match (old_lines.next(), new_lines.next()) {
+            (None, None) => return (old != new).then_some(line.saturating_sub(1)),
+            (a, b) if a == b => line += 1,
+            _ => return Some(line),
+        }
+    }
+}
+
+struct Files {
+    files: HashMap<Url, File>,
+}
+
+impl Files {
+    /// Add new file
+    pub fn add_file(&mut self, uri: Url, content: File) {
+        self.files.insert(uri, content);
+    }
+
+    /// Find file with matching uri.
+    pub fn get_file_mut(&mut self, uri: &Url) -> Option<&mut File> {
+        self.files.get_mut(uri)
+    }
+
+    pub fn get_file(&self, uri: &Url) -> Option<&File> {
+        self.files.get(uri)
+    }
+
+    pub fn remove_file(&mut self, uri: &Url) {
+        self.files.remove(uri);
+    }
+
+    /// The URIs and contents of the open files.
+    pub fn open_contents(&self) -> Vec<(Url, String)> {
+        self.files
+            .iter()
+            .map(|(uri, file)| (uri.clone(), file.content.clone()))
+            .collect()
+    }
+
+    /// URIs of every open file.
+    pub fn uris(&self) -> Vec<Url> {
+        self.files.keys().cloned().collect()
+    }
+}
+
+#[derive(Clone)]
+struct File {
+    content: String,
+    /// The markdown structure of the content, kept up to date as it's edited.
+    syntax: Syntax,
+}
+
+impl File {
+    pub fn new(content: String) -> Self {
+        let syntax = Syntax::parse(&content);
+        Self { content, syntax }
+    }
+
+    /// Apply the edits the editor made, counted in `encoding`, returning the first line they
+    /// changed.
+    pub fn update(
+        &mut self,
+        changes: Vec<TextDocumentContentChangeEvent>,
+        encoding: Encoding,
+    ) -> Option<u32> {
+        let old_content = self.content.clone();
+        encoding.apply_changes(&mut self.content, changes);
+        let changed = first_changed_line(&old_content, &self.content);
+        if let Some(line) = changed {
+            self.syntax.update(&self.content, line);
+        }
+        changed
+    }
+}
+
+/// The server. Clones share its state, so background tasks can hold one.
+#[derive(Clone)]
+pub struct MarkdownLanguageServer {
+    state: Arc<ServerState>,
+}
+
+/// What the server keeps between requests.
+pub struct ServerState {
+    client: Client,
+    /// The open notes. Requests only read them, so they don't wait on each other, only on the
+    /// editor opening, changing or closing one.
+    files: RwLock<Files>,
+    current_file: RwLock<Option<Url>>,
+    /// The preview server, started when the client initializes the server.
+    preview_server: PreviewServer,
+    /// Renders edits to the preview once typing pauses. Started along with the preview server.
+    preview_updates: Mutex<Option<Debouncer>>,
+    /// The version of the next content sent to the preview, counting up in the order the
+    /// requests changing notes came in.
+    preview_version: AtomicU64,
+    client_capabilities: Mutex<ClientCapabilities>,
+    /// How the client counts the characters of positions, agreed on when it initializes.
+    encoding: Mutex<Encoding>,
+    /// The vaults of the workspace folders the client opened, and their indexes.
+    vaults: Arc<RwLock<Vaults>>,
+    /// How many sessions share the vaults and the preview server, this one included.
+    sessions: Arc<AtomicUsize>,
+    /// Whether a refresh of pulled diagnostics is about to be requested.
+    refreshing_diagnostics: AtomicBool,
+    /// Whether the session has been cleaned up, on `shutdown` or once the client exited.
+    exited: AtomicBool,
+    /// Roots of the vaults added on `initialize`, indexed in the background once the client is
+    /// initialized.
+    pending_vaults: Mutex<Option<Vec<PathBuf>>>,
+    /// Content of notes that aren't open, read from disk when needed.
+    contents: Mutex<ContentCache>,
+    /// Note completions of the link being typed.
+    completions: Mutex<CompletionCache>,
+    /// Notes waiting to be indexed again. Started when the client initializes the server.
+    reindex: Mutex<Option<reindex::Queue>>,
+    /// The task indexing them, restarted by the watchdog when it's wedged.
+    reindexer: Mutex<Option<watchdog::Task>>,
+    /// The time limits on requests, from the settings.
+    request_limits: Arc<Limits>,
+    /// Counters of what the server did, for `noteLs/status`.
+    metrics: Arc<Metrics>,
+    /// Diagrams drawn for the preview.
+    diagrams: Arc<Diagrams>,
+    /// What's known of the external links of notes.
+    link_checker: Arc<LinkChecker>,
+    config: Mutex<Config>,
+    /// The settings as the client last sent them, before any profile is applied.
+    settings: Mutex<Value>,
+    /// The profile chosen with `noteLs.setProfile`, which wins over the `profile` setting.
+    profile: Mutex<Option<String>>,
+    /// The locale the client runs in, which the `locale` setting overrides.
+    client_locale: Mutex<Option<String>>,
+    /// Text the server generates, in the language of the locale.
+    messages: Mutex<Arc<Messages>>,
+    plugins: Mutex<Plugins>,
+    /// The dictionary `spellDictionary` names, loaded the first time it's needed, or `None` if it
+    /// couldn't be.
+    dictionary: Mutex<Option<(String, Option<Arc<Dictionary>>)>>,
+}
+
+impl Deref for MarkdownLanguageServer {
+    type Target = ServerState;
+
+    fn deref(&self) -> &ServerState {
+        &self.state
+    }
+}
+
+/// What the sessions of a server that editors connect to over a socket or pipe share: the vaults
+/// with their indexes, and the preview server. The rest, like open notes and settings, belongs
+/// to each editor.
+#[derive(Clone, Default)]
+pub struct Shared {
+    vaults: Arc<RwLock<Vaults>>,
+    preview_server: PreviewServer,
+    sessions: Arc<AtomicUsize>,
+}
+
+impl MarkdownLanguageServer {
+    /// The server as a service, with the custom methods it supports on top of the LSP.
+    ///
+    /// Requests are answered with an error once they take longer than `requestTimeoutMs`.
+    pub fn service() -> (Timeout<LspService<Self>>, ClientSocket) {
+        Self::service_sharing(Shared::default())
+    }
+
+    /// The server as a service for one of the editors connected to it, sharing `shared` with
+    /// the others.
+    pub fn service_sharing(shared: Shared) -> (Timeout<LspService<Self>>, ClientSocket) {
+        let mut timeout = None;
+        let (service, socket) = LspService::build(|client| {
+            let server = Self::sharing(client.clone(), shared);
+            timeout = Some((
+                client,
+                Arc::clone(&server.request_limits),
+                Arc::clone(&server.metrics),
+            ));
+            server
+        })
+        .custom_method("noteLs/cursorMoved", Self::cursor_moved)
+        .custom_method("noteLs/noteInfo", Self::note_info)
+        .custom_method("noteLs/books", Self::books)
+        .custom_method("noteLs/status", Self::status)
+        .custom_method("noteLs/sectionRange", Self::section_range)
+        .finish();
+        let (client, limits, metrics) =
+            timeout.expect("the server is built along with the service");
+        (Timeout::new(service, client, limits, metrics), socket)
+    }
+
+    pub fn new(client: Client) -> Self {
+        Self::sharing(client, Shared::default())
+    }
+
+    fn sharing(client: Client, shared: Shared) -> Self {
+        shared.sessions.fetch_add(1, Ordering::SeqCst);
+        let state = ServerState {
+            diagrams: Arc::new(Diagrams::new(client.clone())),
+            link_checker: Arc::new(LinkChecker::default()),
+            client,
+            files: RwLock::new(Files {
+                files: HashMap::new(),
+            }),
+            current_file: RwLock::new(None),
+            preview_server: shared.preview_server,
+            preview_updates: Mutex::new(None),
+            preview_version: AtomicU64::new(0),
+            client_capabilities: Mutex::new(ClientCapabilities::default()),
+            encoding: Mutex::new(Encoding::default()),
+            vaults: shared.vaults,
+            sessions: shared.sessions,
+            refreshing_diagnostics: AtomicBool::new(false),
+            exited: AtomicBool::new(false),
+            pending_vaults: Mutex::new(None),
+            contents: Mutex::new(ContentCache::new(Config::default().content_cache_mb << 20)),
+            completions: Mutex::new(CompletionCache::default()),
+            reindex: Mutex::new(None),
+            reindexer: Mutex::new(None),
+            request_limits: Arc::new(Limits::default()),
+            metrics: Arc::new(Metrics::default()),
+            config: Mutex::new(Config::default()),
+            settings: Mutex::new(Value::Object(Default::default())),
+            profile: Mutex::new(None),
+            client_locale: Mutex::new(None),
+            messages: Mutex::new(Arc::new(Messages::default())),
+            plugins: Mutex::new(Plugins::default()),
+            dictionary: Mutex::new(None),
+        };
+        Self {
+            state: Arc::new(state),
+        }
+    }
+
+    async fn get_current_file_contents(&self) -> Option<File> {
+        let current_file = self.current_file.read().await;
+        let c2 = current_file.clone()?;
+        let lock = self.files.read().await;
+        let c = lock.get_file(&c2)?;
+        let thing = c.clone();
+        Some(thing)
+    }
+
+    /// The content of the note at `path` on disk, from the cache if it hasn't changed.
+    async fn read_note(&self, path: &Path) -> std::io::Result<String> {
+        let mut contents = timeout::timed("waiting for note contents", self.contents.lock()).await;
+        timeout::timed("reading notes", async {
+            Ok(contents.get(path)?.to_string())
+        })
+        .await
+    }
+
+    /// The content of the note `uri`, at `path`, as it's open in the editor or else on disk.
+    async fn note_content(&self, uri: &Url, path: &Path) -> Result<String> {
+        let open = self
+            .files
+            .read()
+            .await
+            .get_file(uri)
+            .map(|file| file.content.clone());
+        match open {
+            Some(content) => Ok(content),
+            None => self.read_note(path).await.map_err(internal_error),
+        }
+    }
+
+    /// The content of the notes at `uris`, to convert ranges in them for the editor with, from
+    /// the editor if they're open. Notes that can't be read are left out, and all of them if the
+    /// editor counts characters in bytes too.
+    async fn client_documents(&self, uris: Vec<Url>) -> HashMap<Url, String> {
+        let mut documents = HashMap::new();
+        if *self.encoding.lock().await == Encoding::Utf8 {
+            return documents;
+        }
+        for uri in uris {
+            if documents.contains_key(&uri) {
+                continue;
+            }
+            let Some(path) = uri::to_path(&uri) else {
+                continue;
+            };
+            if let Ok(content) = self.note_content(&uri, &path).await {
+                documents.insert(uri, content);
+            }
+        }
+        documents
+    }
+
+    /// `locations`, with ranges counted in bytes, as the editor counts them.
+    async fn locations_to_client(&self, mut locations: Vec<Location>) -> Vec<Location> {
+        let encoding = *self.encoding.lock().await;
+        let documents = self
+            .client_documents(
+                locations
+                    .iter()
+                    .map(|location| location.uri.clone())
+                    .collect(),
+            )
+            .await;
+        for location in &mut locations {
+            if let Some(document) = documents.get(&location.uri) {
+                location.range = encoding.range_to_client(document, location.range);
+            }
+        }
+        locations
+    }
+
+    /// Convert the ranges of `edit`, counted in bytes, to how the editor counts them.
+    async fn edit_to_client(&self, edit: &mut WorkspaceEdit) {
+        let mut uris = edit
+            .changes
+            .iter()
+            .flat_map(|changes| changes.keys().cloned())
+            .collect::<Vec<_>>();
+        match &edit.document_changes {
+            Some(DocumentChanges::Edits(edits)) => {
+                uris.extend(edits.iter().map(|edit| edit.text_document.uri.clone()));
+            }
+            Some(DocumentChanges::Operations(operations)) => {
+                uris.extend(operations.iter().filter_map(|operation| match operation {
+                    DocumentChangeOperation::Edit(edit) => Some(edit.text_document.uri.clone()),
+                    DocumentChangeOperation::Op(_) => None,
+                }));
+            }
+            None => {}
+        }
+        let documents = self.client_documents(uris).await;
+        self.encoding.lock().await.edit_to_client(&documents, edit);
+    }
+
+    /// Ask the editor to apply `edit`, with ranges counted in bytes.
+    async fn apply_edit(&self, mut edit: WorkspaceEdit) -> Result<ApplyWorkspaceEditResponse> {
+        self.edit_to_client(&mut edit).await;
+        self.client.apply_edit(edit).await
+    }
+
+    /// The dictionary `name`, as `spellDictionary` gives it, loaded if it wasn't yet. Dictionaries
+    /// that can't be loaded are logged once and give `None`.
+    async fn dictionary(&self, name: &str) -> Option<Arc<Dictionary>> {
+        let mut dictionary = self.dictionary.lock().await;
+        if let Some((loaded, dictionary)) = &*dictionary {
+            if loaded == name {
+                return dictionary.clone();
+            }
+        }
+        let loaded = match spelling::find(name) {
+            Some(path) => tokio::task::spawn_blocking(move || Dictionary::load(&path))
+                .await
+                .unwrap_or_else(|e| Err(std::io::Error::other(e))),
+            None => Err(std::io::ErrorKind::NotFound.into()),
+        };
+        let loaded = match loaded {
+            Ok(loaded) => Some(Arc::new(loaded)),
+            Err(e) => {
+                let message = format!("Could not load the dictionary {name}: {e}");
+                logging::log(&self.client, MessageType::ERROR, message).await;
+                None
+            }
+        };
+        *dictionary = Some((name.to_string(), loaded.clone()));
+        loaded
+    }
+
+    /// Load the messages of the configured locale, or else of the client's.
+    async fn set_locale(&self) {
+        let locale = match self.config.lock().await.locale.clone() {
+            Some(locale) => Some(locale),
+            None => self.client_locale.lock().await.clone(),
+        };
+        let messages = Messages::new(locale.as_deref().unwrap_or(i18n::FALLBACK));
+        *self.messages.lock().await = Arc::new(messages);
+    }
+
+    /// Whether the client pulls diagnostics, rather than having them published.
+    async fn pulls_diagnostics(&self) -> bool {
+        let capabilities = self.client_capabilities.lock().await;
+        let text_document = capabilities.text_document.as_ref();
+        text_document.is_some_and(|text_document| text_document.diagnostic.is_some())
+    }
+
+    /// Ask a client that pulls diagnostics to pull them again, as they changed. Requests made
+    /// before the client is asked are answered by the one refresh.
+    fn refresh_diagnostics(&self) {
+        if self.refreshing_diagnostics.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let server = self.clone();
+        tokio::spawn(async move {
+            let supported = server
+                .client_capabilities
+                .lock()
+                .await
+                .workspace
+                .as_ref()
+                .and_then(|workspace| workspace.diagnostic.as_ref()?.refresh_support)
+                .unwrap_or(false);
+            server.refreshing_diagnostics.store(false, Ordering::SeqCst);
+            if supported {
+                let _ = server.client.workspace_diagnostic_refresh().await;
+            }
+        });
+    }
+
+    /// Publish diagnostics for the note at `uri` from its indexed content, or have them pulled
+    /// again by a client that pulls them.
+    async fn publish_diagnostics(&self, uri: Url, version: Option<i32>) {
+        if self.pulls_diagnostics().await {
+            self.refresh_diagnostics();
+            return;
+        }
+        if let Some(diagnostics) = self.note_diagnostics(&uri).await {
+            self.client
+                .publish_diagnostics(uri, diagnostics, version)
+                .await;
+        }
+    }
+
+    /// The diagnostics of the note at `uri` from its indexed content, with their ranges in the
+    /// client's encoding, or `None` if it isn't indexed.
+    async fn note_diagnostics(&self, uri: &Url) -> Option<Vec<Diagnostic>> {
+        let path = uri::to_path(uri)?;
+        let config = self.config.lock().await.clone();
+        if !config.diagnostics {
+            // Clear what was reported before they were turned off.
+            return Some(Vec::new());
+        }
+        let messages = self.messages.lock().await.clone();
+        let mut diagnostics = {
+            let vaults = self.vaults.read().await;
+            let vault_indexed = vaults.indexed(&path);
+            let index = vaults.index_for(&path);
+            let note = index.get(&path)?;
+            let mut diagnostics = Vec::new();
+            if vault_indexed {
+                diagnostics = diagnostics::broken_links(
+                    &path,
+                    &note.links,
+                    index,
+                    config.broken_link_severity.into(),
+                    &messages,
+                );
+                if config.check_link_case {
+                    diagnostics.extend(diagnostics::case_mismatches(
+                        &path,
+                        &note.links,
+                        index,
+                        &messages,
+                    ));
+                }
+                if config.orphan_diagnostics {
+                    diagnostics.extend(diagnostics::orphan_problems(
+                        &path,
+                        &note.links,
+                        index,
+                        &messages,
+                    ));
+                }
+                if config.duplicate_diagnostics {
+                    diagnostics.extend(diagnostics::duplicate_names(&path, note, index, &messages));
+                }
+            }
+            if config.alt_text_diagnostics {
+                diagnostics.extend(diagnostics::missing_alt_text(&note.links, &messages));
+            }
+            // Only links that were checked, on save or with `noteLs.links.check`, are known to
+            // be broken.
+            let allowed = &config.external_link_allowlist;
+            diagnostics.extend(diagnostics::broken_urls(
+                &note.links,
+                |url| {
+                    self.link_checker
+                        .broken(url)
+                        .filter(|_| !link_checker::is_allowed(url, allowed))
+                },
+                &messages,
+            ));
+            if config.heading_order_diagnostics {
+                diagnostics.extend(diagnostics::skipped_heading_levels(
+                    &note.headings,
+                    &messages,
+                ));
+            }
+            if config.duplicate_diagnostics {
+                diagnostics.extend(diagnostics::duplicate_headings(
+                    &path,
+                    &note.headings,
+                    &messages,
+                ));
+            }
+            diagnostics.extend(diagnostics::note_type_problems(
+                &note.frontmatter,
+                &config.note_types,
+                &messages,
+            ));
+            diagnostics
+        };
+
+        let open = self
+            .files
+            .read()
+            .await
+            .get_file(uri)
+            .map(|file| (file.content.clone(), file.syntax.clone()));
+        let text = match open {
+            Some(open) => Some(open),
+            None => self
+                .read_note(&path)
+                .await
+                .ok()
+                .map(|content| (content.clone(), Syntax::parse(&content))),
+        };
+        let text = text.map(|(text, syntax)| {
+            // Links and headings in code are only examples.
+            diagnostics.retain(|diagnostic| !syntax.in_code(diagnostic.range.start));
+            text
+        });
+        if let Some(text) = &text {
+            if config.spell_check {
+                if let Some(dictionary) = self.dictionary(&config.spell_dictionary).await {
+                    let custom = match self.get_root(&path).await {
+                        Ok(root) => Dictionary::read_list(&root.join(&config.custom_dictionary))
+                            .unwrap_or_default(),
+                        Err(_) => Dictionary::default(),
+                    };
+                    let misspellings = spelling::misspellings(text, &[&dictionary, &custom]);
+                    diagnostics.extend(diagnostics::unknown_words(&misspellings, &messages));
+                }
+            }
+            let params = json!({ "uri": uri, "text": text });
+            let found = self
+                .plugin_results::<Vec<Diagnostic>>(Feature::Diagnostics, params)
+                .await;
+            for (name, found) in found {
+                diagnostics.extend(found.into_iter().map(|diagnostic| Diagnostic {
+                    source: diagnostic.source.or_else(|| Some(name.clone())),
+                    ..diagnostic
+                }));
+            }
+        }
+
+        if let Some(text) = &text {
+            let encoding = *self.encoding.lock().await;
+            for diagnostic in &mut diagnostics {
+                diagnostic.range = encoding.range_to_client(text, diagnostic.range);
+            }
+        }
+        Some(diagnostics)
+    }
+
+    /// Check the external links of the note at `uri` in the background, all of them again if
+    /// `recheck` is set, and publish its diagnostics again if any broke or got fixed.
+    fn check_links_in_background(&self, uri: Url, recheck: bool) {
+        let server = self.clone();
+        tokio::spawn(async move {
+            let Some(path) = uri::to_path(&uri) else {
+                return;
+            };
+            let allowed = server.config.lock().await.external_link_allowlist.clone();
+            let urls = match server.index_for(&path).await.get(&path) {
+                Some(note) => link_checker::urls_to_check(&note.links, &allowed),
+                None => return,
+            };
+            if server.link_checker.check(urls, recheck).await {
+                server.publish_diagnostics(uri, None).await;
+            }
+        });
+    }
+
+    /// Publish diagnostics for the notes in the vault at `root`, up to the configured limit, so
+    /// problems show up in the editor before the notes are opened.
+    async fn publish_vault_diagnostics(&self, root: &Path) {
+        let config = self.config.lock().await.clone();
+        let limit = config.vault_diagnostics_limit;
+        if limit == 0 || !config.diagnostics {
+            return;
+        }
+        // The client pulls the diagnostics of the workspace itself.
+        if self.pulls_diagnostics().await {
+            self.refresh_diagnostics();
+            return;
+        }
+        let mut paths = match self.vaults.read().await.vault(root) {
+            Some(vault) if vault.indexed && vault.root == root => vault
+                .index
+                .notes()
+                .map(|(path, _)| path.to_path_buf())
+                .collect::<Vec<_>>(),
+            _ => return,
+        };
+        paths.sort();
+        if paths.len() > limit {
+            let message = format!(
+                "Only checking {limit} of {} notes; raise vaultDiagnosticsLimit to check them all",
+                paths.len()
+            );
+            logging::log(&self.client, MessageType::INFO, message).await;
+        }
+
+        for (i, path) in paths.iter().take(limit).enumerate() {
+            if i > 0 && i % VAULT_DIAGNOSTICS_BATCH == 0 {
+                tokio::time::sleep(VAULT_DIAGNOSTICS_PAUSE).await;
+            }
+            let Some(uri) = uri::from_path(path) else {
+                continue;
+            };
+            // Open notes already have diagnostics for what's in the editor.
+            if self.files.read().await.get_file(&uri).is_some() {
+                continue;
+            }
+            self.publish_diagnostics(uri, None).await;
+        }
+    }
+
+    /// Clear the diagnostics of the notes at `uris`.
+    async fn clear_diagnostics(&self, uris: Vec<Url>) {
+        if self.pulls_diagnostics().await {
+            self.refresh_diagnostics();
+            return;
+        }
+        for uri in uris {
+            self.client.publish_diagnostics(uri, Vec::new(), None).await;
+        }
+    }
+
+    /// Tell the user that the server turned something off by itself.
+    async fn degraded(&self, message: String) {
+        logging::log(&self.client, MessageType::WARNING, message.clone()).await;
+        self.client
+            .show_message(MessageType::WARNING, message)
+            .await;
+    }
+
+    /// Add the vault at `root` to the vaults, with none of its notes indexed yet.
+    async fn add_vault(&self, root: &Path) {
+        let config = self.config.lock().await.clone();
+        let mut index = NoteIndex::from_paths(root, Vec::new());
+        index.set_ignore(Ignore::new(root, &config.ignore_globs));
+        index.set_extensions(config.note_extensions.clone());
+        index.set_resolution(config.link_resolution.into());
+        self.vaults.write().await.insert(Vault {
+            root: root.to_path_buf(),
+            indexed: false,
+            index,
+        });
+    }
+
+    /// Index the notes of the vaults at `roots`, which were added already, unless indexing is
+    /// turned off or a vault is too large.
+    ///
+    /// Notes are read a batch at a time, so requests in the meantime are answered from the notes
+    /// indexed so far, and the client is shown how far along indexing is. Links are only checked
+    /// once the whole vault is indexed.
+    async fn index_vaults(&self, roots: &[PathBuf], progress: &Progress) {
+        let config = self.config.lock().await.clone();
+
+        let mut vaults = Vec::new();
+        for root in roots.iter().filter(|_| config.index_vault) {
+            let mut ignore = Ignore::new(root, &config.ignore_globs);
+            let paths = NoteIndex::note_paths(&mut ignore, &config.note_extensions);
+            if paths.len() > config.max_indexed_notes {
+                let message = format!(
+                    "The vault has {} notes, more than maxIndexedNotes ({}), so only open notes \
+                     are indexed and links aren't checked",
+                    paths.len(),
+                    config.max_indexed_notes
+                );
+                self.degraded(message).await;
+                continue;
+            }
+            vaults.push((root, paths));
+        }
+
+        let total = vaults.iter().map(|(_, paths)| paths.len()).sum();
+        let mut done = 0;
+        'vaults: for (root, paths) in vaults {
+            let start = Instant::now();
+            let protection = match config.index_cache {
+                true => self.index_cache_protection(&config).await,
+                false => None,
+            };
+            let mut cached = match &protection {
+                Some(protection) => index_cache::load(root, protection),
+                None => HashMap::new(),
+            };
+            for batch in paths.chunks(INDEX_BATCH) {
+                let notes = NoteIndex::read_notes(batch.to_vec(), &mut cached);
+                match self.vaults.write().await.get_mut(root) {
+                    Some(vault) => vault.index.add_disk_notes(notes),
+                    // The workspace folder was closed meanwhile.
+                    None => continue 'vaults,
+                }
+                done += batch.len();
+                progress.report(done, total).await;
+                tokio::task::yield_now().await;
+            }
+
+            let mut vaults = self.vaults.write().await;
+            let Some(vault) = vaults.get_mut(root) else {
+                continue;
+            };
+            vault.indexed = true;
+            self.metrics.indexing.record(start.elapsed());
+            if let Some(protection) = &protection {
+                self.save_index_cache(&vault.index, protection).await;
+            }
+        }
+    }
+
+    /// Start showing the progress of indexing, if the client can.
+    async fn indexing_progress(&self) -> Progress {
+        let supported = self
+            .client_capabilities
+            .lock()
+            .await
+            .window
+            .as_ref()
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false);
+        Progress::begin(&self.client, supported, "Indexing notes").await
+    }
+
+    /// Ask the client to tell us about notes created, changed or deleted outside the editor.
+    async fn watch_files(&self) {
+        let can_watch = self
+            .client_capabilities
+            .lock()
+            .await
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+            .and_then(|watched| watched.dynamic_registration)
+            .unwrap_or(false);
+        // Without the index there's nothing to keep up to date.
+        let indexed = self.vaults.read().await.iter().any(|vault| vault.indexed);
+        let config = self.config.lock().await.clone();
+        let watch = config.watch_files && indexed;
+        if can_watch && watch {
+            let options = DidChangeWatchedFilesRegistrationOptions {
+                watchers: vec![FileSystemWatcher {
+                    glob_pattern: note_glob(&config.note_extensions).into(),
+                    kind: None,
+                }],
+            };
+            let registration = Registration {
+                id: "note-ls-watched-files".to_string(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+                register_options: serde_json::to_value(options).ok(),
+            };
+            if let Err(e) = self.client.register_capability(vec![registration]).await {
+                logging::log(
+                    &self.client,
+                    MessageType::WARNING,
+                    format!("Could not watch files, the index may go stale: {e}"),
+                )
+                .await;
+            }
+        }
+    }
+
+    /// How the index cache is to be kept, or `None` if its passphrase couldn't be had, in which
+    /// case it's neither read nor written.
+    async fn index_cache_protection(&self, config: &Config) -> Option<index_cache::Protection> {
+        let passphrase = match &config.index_cache_passphrase_command {
+            Some(command) => match index_cache::passphrase_from(command).await {
+                Ok(passphrase) => Some(passphrase),
+                Err(e) => {
+                    let message = format!("Couldn't get the passphrase of the index cache: {e}");
+                    logging::log(&self.client, MessageType::WARNING, message).await;
+                    return None;
+                }
+            },
+            None => config.index_cache_passphrase.clone(),
+        };
+        Some(match passphrase {
+            Some(passphrase) => index_cache::Protection::Encrypted(passphrase),
+            None => index_cache::Protection::Plain,
+        })
+    }
+
+    /// Save `index` for the next session, logging why it couldn't be saved.
+    async fn save_index_cache(&self, index: &NoteIndex, protection: &index_cache::Protection) {
+        if let Err(e) = index_cache::save(index, protection) {
+            let message = format!("Couldn't save the index of {}: {e}", index.root().display());
+            logging::log(&self.client, MessageType::WARNING, message).await;
+        }
+    }
+
+    /// Queue the note at `path` to be indexed again.
+    async fn reindex(&self, path: PathBuf, change: Change) {
+        if let Some(queue) = self.reindex.lock().await.as_ref() {
+            queue.push(path, change);
+        }
+    }
+
+    /// Start indexing queued changes in the background, beginning with the `first` pass if
+    /// there is one.
+    ///
+    /// The task stops when the queue is dropped.
+    async fn start_reindexing(&self, first: Option<reindex::Pass>) {
+        let (queue, mut receiver) = reindex::queue();
+        *self.reindex.lock().await = Some(queue);
+        let server = self.clone();
+        let task = watchdog::Task::spawn(|heartbeat| async move {
+            let mut next = first;
+            loop {
+                let pass = match next.take() {
+                    Some(pass) => pass,
+                    None => {
+                        let delay =
+                            Duration::from_millis(server.config.lock().await.index_delay_ms);
+                        match receiver.next_pass(delay).await {
+                            Some(pass) => pass,
+                            None => return,
+                        }
+                    }
+                };
+                heartbeat.busy();
+                let start = Instant::now();
+                let timing = match pass.rescan {
+                    true => &server.metrics.indexing,
+                    false => &server.metrics.reindexing,
+                };
+                server.apply_pass(pass).await;
+                timing.record(start.elapsed());
+                heartbeat.idle();
+            }
+        });
+        *self.reindexer.lock().await = Some(task);
+    }
+
+    /// Check on indexing changes every so often, restarting it if it's wedged. Changes queued
+    /// meanwhile may be lost, so the restarted task indexes the vaults again first.
+    ///
+    /// The watchdog stops once the server shuts down.
+    async fn start_watchdog(&self) {
+        let server = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(watchdog::INTERVAL).await;
+                if server.reindex.lock().await.is_none() {
+                    return;
+                }
+                let limit = match server.config.lock().await.watchdog_timeout_ms {
+                    0 => Duration::MAX,
+                    ms => Duration::from_millis(ms),
+                };
+                let wedged = server
+                    .reindexer
+                    .lock()
+                    .await
+                    .as_ref()
+                    .is_some_and(|task| task.is_wedged(limit));
+                if !wedged {
+                    continue;
+                }
+                logging::log(
+                    &server.client,
+                    MessageType::WARNING,
+                    "Indexing changes stopped or got stuck, restarting it",
+                )
+                .await;
+                let rescan = reindex::Pass {
+                    rescan: true,
+                    ..reindex::Pass::default()
+                };
+                server.start_reindexing(Some(rescan)).await;
+            }
+        });
+    }
+
+    /// Index the notes changed in `pass`, then refresh the diagnostics of open notes, whose links
+    /// may have started or stopped resolving.
+    async fn apply_pass(&self, pass: reindex::Pass) {
+        let rescan = pass.rescan;
+        // Changes were dropped, so only a full scan can tell what changed.
+        let scan_roots = match rescan {
+            true => self
+                .vaults
+                .read()
+                .await
+                .iter()
+                .filter(|vault| vault.indexed)
+                .map(|vault| vault.root.clone())
+                .collect(),
+            false => Vec::new(),
+        };
+        let (globs, extensions, resolution) = {
+            let config = self.config.lock().await;
+            (
+                config.ignore_globs.clone(),
+                config.note_extensions.clone(),
+                config.link_resolution,
+            )
+        };
+        let parsed = tokio::task::spawn_blocking(move || {
+            let scanned = scan_roots
+                .iter()
+                .map(|root| {
+                    let mut index =
+                        NoteIndex::scan_with(Ignore::new(root, &globs), extensions.clone());
+                    index.set_resolution(resolution.into());
+                    index
+                })
+                .collect::<Vec<_>>();
+            (scanned, pass.parse())
+        })
+        .await;
+        let Ok((scanned, notes)) = parsed else {
+            return;
+        };
+        if !scanned.is_empty() {
+            logging::log(
+                &self.client,
+                MessageType::INFO,
+                "Too many changes at once, indexed the vault again",
+            )
+            .await;
+        }
+
+        let open = self.files.read().await.open_contents();
+        let mut changes = IndexChangedParams {
+            reindexed: !scanned.is_empty(),
+            ..IndexChangedParams::default()
+        };
+        {
+            let mut vaults = self.vaults.write().await;
+            for scanned in scanned {
+                let root = scanned.root().to_path_buf();
+                *vaults.index_for_mut(&root) = scanned;
+            }
+            for (path, note) in notes {
+                let index = vaults.index_for_mut(&path);
+                // Ignored notes are only indexed while they're open.
+                if note.is_some() && index.is_ignored(&path) && index.get(&path).is_none() {
+                    continue;
+                }
+                changes.note_updated(index, &path, note.as_ref());
+                match note {
+                    Some(note) => index.update_note(path, note),
+                    None => index.remove(&path),
+                }
+            }
+            if rescan {
+                // The editor knows better than the disk.
+                for (uri, content) in &open {
+                    if let Some(path) = uri::to_path(uri) {
+                        vaults.index_for_mut(&path).update(path, content);
+                    }
+                }
+            }
+        }
+
+        self.index_changed(changes).await;
+
+        for (uri, _) in open {
+            self.publish_diagnostics(uri, None).await;
+        }
+    }
+
+    /// Tell the client how long `content`, the note at `uri`, is now.
+    async fn send_stats(&self, uri: Url, content: &str) {
+        let reading_speed = self.config.lock().await.reading_speed;
+        let stats = stats::stats(uri, content, reading_speed);
+        self.client.send_notification::<Stats>(stats).await;
+    }
+
+    /// Tell the client about `changes` to the index, if there are any.
+    async fn index_changed(&self, changes: IndexChangedParams) {
+        if !changes.is_empty() {
+            self.client.send_notification::<IndexChanged>(changes).await;
+        }
+    }
+
+    /// Start the preview server, unless it's running already.
+    async fn start_preview(&self) {
+        if self.preview_server.lock().await.is_some() {
+            return;
+        }
+        let (host, port) = {
+            let config = self.config.lock().await;
+            (config.preview_host.clone(), config.preview_port)
+        };
+        match aurelius::Server::bind((host.as_str(), port)).await {
+            Ok(mut preview_server) => {
+                logging::log(
+                    &self.client,
+                    MessageType::INFO,
+                    format!("Preview available at {}", preview_server.url()),
+                )
+                .await;
+                // Serve the vaults, so images and links relative to notes work in the preview.
+                for (prefix, root) in self.vaults.read().await.preview_roots() {
+                    preview_server.add_static_root(&prefix, root);
+                }
+                *self.preview_server.lock().await = Some(preview_server);
+                *self.preview_updates.lock().await = Some(Debouncer::spawn(
+                    Arc::clone(&self.preview_server),
+                    self.client.clone(),
+                    Arc::clone(&self.metrics),
+                    Arc::clone(&self.diagrams),
+                ));
+            }
+            Err(e) => {
+                self.degraded(format!(
+                    "Could not start the preview server on {host}:{port}: {e}"
+                ))
+                .await;
+                return;
+            }
+        }
+        let (renderer, direction) = {
+            let config = self.config.lock().await;
+            (config.renderer.clone(), config.preview_direction)
+        };
+        self.set_renderer(&renderer, direction).await;
+        self.style_preview().await;
+        self.serve_metrics().await;
+        self.announce_preview().await;
+    }
+
+    /// Send `noteLs/previewStarted` with the URL of the preview server, if it's running.
+    async fn announce_preview(&self) {
+        let url = match self.preview_server.lock().await.as_ref() {
+            Some(preview_server) => preview_server.url(),
+            None => return,
+        };
+        self.client
+            .send_notification::<PreviewStarted>(PreviewStartedParams { url })
+            .await;
+    }
+
+    /// Clean up the session: stop its plugins and indexing, stop the preview unless another
+    /// editor still shares it, and save the index cache. Done on `shutdown`, or once the client
+    /// exits if it didn't ask to shut down first.
+    pub async fn exit(&self) {
+        if self.exited.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        self.plugins.lock().await.shutdown().await;
+        // The preview stays up for the other editors connected to the server.
+        let sessions = self
+            .sessions
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+        if sessions == Ok(1) {
+            self.stop_preview().await;
+        }
+        self.reindex.lock().await.take();
+        self.reindexer.lock().await.take();
+        let config = self.config.lock().await.clone();
+        let protection = match config.index_cache {
+            true => self.index_cache_protection(&config).await,
+            false => None,
+        };
+        if let Some(protection) = protection {
+            let vaults = self.vaults.read().await;
+            for vault in vaults.iter().filter(|vault| vault.indexed) {
+                self.save_index_cache(&vault.index, &protection).await;
+            }
+        }
+    }
+
+    /// Start the preview server again if it stopped accepting connections, so a broken preview
+    /// doesn't stay broken until the editor restarts.
+    async fn revive_preview(&self) {
+        let stopped = match self.preview_server.lock().await.as_ref() {
+            Some(preview_server) => !preview_server.is_running(),
+            None => return,
+        };
+        if stopped {
+            logging::log(
+                &self.client,
+                MessageType::WARNING,
+                "The preview server stopped, restarting it",
+            )
+            .await;
+            self.stop_preview().await;
+            self.start_preview().await;
+        }
+    }
+
+    /// Stop the preview server, closing the previews open in the browser.
+    async fn stop_preview(&self) {
+        self.preview_updates.lock().await.take();
+        if let Some(preview_server) = self.preview_server.lock().await.take() {
+            preview_server.shutdown().await;
+        }
+    }
+
+    /// Render the preview with `renderer` from now on, for text running in `direction`.
+    async fn set_renderer(&self, renderer: &Renderer, direction: PreviewDirection) {
+        let mut preview_server = self.preview_server.lock().await;
+        let Some(preview_server) = preview_server.as_mut() else {
+            return;
+        };
+        match renderer.clone() {
+            Renderer::Builtin => preview_server.set_renderer(
+                PulldownCmark::with_source_lines()
+                    .with_direction(direction.into())
+                    .with_heading_ids(),
+            ),
+            Renderer::Command { command, args } => {
+                preview_server.set_renderer(ExternalCommand::new(command, args))
+            }
+            Renderer::Process { command, args } => {
+                preview_server.set_renderer(RendererProcess::new(command, args))
+            }
+        }
+    }
+
+    /// Apply the preview theme, direction and stylesheet settings. Open previews pick them up when reloaded.
+    async fn style_preview(&self) {
+        let config = self.config.lock().await;
+        let root = self.first_root().await;
+        let mut preview_server = self.preview_server.lock().await;
+        let Some(preview_server) = preview_server.as_mut() else {
+            return;
+        };
+
+        let highlight_theme = match config.preview_theme {
+            PreviewTheme::Light => "github",
+            PreviewTheme::Dark => "github-dark",
+        };
+        preview_server.set_theme(config.preview_theme.into());
+        preview_server.set_direction(config.preview_direction.into());
+        preview_server.set_highlight_theme(highlight_theme.to_string());
+        // Relative paths are taken from the vault root; absolute ones replace it when joined.
+        let in_vault = |path: &PathBuf| {
+            root.as_ref()
+                .map_or_else(|| path.clone(), |root| root.join(path))
+        };
+        preview_server.set_user_stylesheet(config.preview_stylesheet.as_ref().map(in_vault));
+        preview_server.set_template(config.preview_template.as_ref().map(in_vault));
+    }
+
+    /// Serve the counters of `noteLs/status` on the preview server if `metricsEndpoint` is on,
+    /// or stop serving them.
+    async fn serve_metrics(&self) {
+        let enabled = self.config.lock().await.metrics_endpoint;
+        let mut preview_server = self.preview_server.lock().await;
+        let Some(preview_server) = preview_server.as_mut() else {
+            return;
+        };
+        if !enabled {
+            preview_server.set_metrics(None);
+            return;
+        }
+        // The state owns the preview server, which mustn't keep it alive.
+        let state = Arc::downgrade(&self.state);
+        let url = preview_server.metrics_url();
+        preview_server.set_metrics(Some(Box::new(move || {
+            let Some(state) = state.upgrade() else {
+                return String::new();
+            };
+            // Requests are answered on the preview server's threads, outside the runtime, so
+            // they can wait for the caches.
+            let status = state.metrics.status(
+                state.contents.blocking_lock().stats(),
+                state.completions.blocking_lock().stats(),
+                Some(url.clone()),
+            );
+            metrics::prometheus(&status)
+        })));
+    }
+
+    /// Open the preview of `channel` in the user's browser, telling them where to find it if
+    /// that fails. Returns its URL, or `None` if the preview server isn't running.
+    async fn open_preview(&self, channel: &str) -> Option<Url> {
+        let preview_server = self.preview_server.lock().await;
+        let preview_server = preview_server.as_ref()?;
+        let url = preview_server.channel_url(channel);
+        if let Err(e) = preview_server.open_browser_to(channel).await {
+            let message = format!("Could not open the preview in a browser ({e}); it is at {url}");
+            self.client
+                .show_message(MessageType::WARNING, message)
+                .await;
+        }
+        Some(url)
+    }
+
+    /// Run one of the `noteLs.preview.*` commands on the preview of the note at `uri`, or
+    /// without one, on the preview following the note being edited.
+    async fn preview_command(&self, command: &str, uri: Option<Url>) -> Result<Option<Value>> {
+        if !self.config.lock().await.preview {
+            return Err(Error {
+                code: ErrorCode::InvalidRequest,
+                message: "The preview is turned off".into(),
+                data: None,
+            });
+        }
+
+        let channel = uri
+            .as_ref()
+            .map_or(aurelius::DEFAULT_CHANNEL, Url::as_str)
+            .to_string();
+        let viewed = self
+            .preview_server
+            .lock()
+            .await
+            .as_ref()
+            .is_some_and(|preview_server| preview_server.viewers_in(&channel) > 0);
+        if command == commands::PREVIEW_CLOSE || (command == commands::PREVIEW_TOGGLE && viewed) {
+            if let Some(preview_server) = self.preview_server.lock().await.as_mut() {
+                preview_server.close_channel(&channel);
+            }
+            return Ok(None);
+        }
+
+        // Show the note first so the page doesn't open empty.
+        let note = match uri {
+            Some(uri) => Some(uri),
+            None => self.current_file.read().await.clone(),
+        };
+        if let Some(note) = note {
+            let path = uri::to_path(&note);
+            let open = self
+                .files
+                .read()
+                .await
+                .get_file(&note)
+                .map(|file| file.content.clone());
+            // Notes that aren't open are previewed as saved.
+            let content = match (open, &path) {
+                (Some(content), _) => content,
+                (None, Some(path)) => self.read_note(path).await.map_err(internal_error)?,
+                (None, None) => return Err(Error::new(ErrorCode::InvalidParams)),
+            };
+
+            if let Some(preview_server) = self.preview_server.lock().await.as_mut() {
+                let sent = match &path {
+                    Some(path) => preview_server.send_file_to(&channel, &content, path).await,
+                    None => preview_server.send_to(&channel, &content).await,
+                };
+                sent.map_err(internal_error)?;
+            }
+        }
+
+        match self.open_preview(&channel).await {
+            Some(url) => Ok(Some(json!(url))),
+            None => Err(internal_error("the preview server isn't running")),
+        }
+    }
+
+    /// Show `markdown`, the content of the note at `uri`, in the preview, if it's enabled, once
+    /// there have been no further edits for `delay`, scrolled to `line`. It's prepared without
+    /// holding up the request that changed it, as badges need the index, but gets its version
+    /// first, so it can't overwrite the content of a later request that's prepared sooner.
+    fn preview_in_background(
+        &self,
+        uri: Url,
+        markdown: String,
+        line: Option<u32>,
+        delay: Duration,
+    ) {
+        let version = self.preview_version.fetch_add(1, Ordering::SeqCst);
+        let server = self.clone();
+        tokio::spawn(async move {
+            if !server.config.lock().await.preview {
+                return;
+            }
+            server.revive_preview().await;
+            let markdown = server.for_preview(&uri, markdown).await;
+            tracing::debug!(%uri, bytes = markdown.len(), "rendering preview");
+            if let Some(preview_updates) = server.preview_updates.lock().await.as_ref() {
+                preview_updates.push(preview::Update {
+                    uri,
+                    markdown,
+                    version,
+                    line,
+                    delay,
+                });
+            }
+        });
+    }
+
+    /// `markdown`, the content of the note at `uri`, ready for the preview: without its `%% %%`
+    /// comments unless they're shown, and with badges after its links if they're turned on.
+    async fn for_preview(&self, uri: &Url, markdown: String) -> String {
+        let config = self.config.lock().await.clone();
+        let markdown = hide_comments(markdown, &config);
+        if !config.preview_badges {
+            return markdown;
+        }
+        let messages = self.messages.lock().await.clone();
+        match uri::to_path(uri) {
+            Some(path) => {
+                let index = self.index_for(&path).await;
+                badges::decorate(&markdown, &path, &index, &messages)
+            }
+            None => markdown,
+        }
+    }
+
+    /// The hover at `pos`, counted in bytes, of the note at `uri`: the footnote, citation or link
+    /// there, or else the last commit of the heading there.
+    async fn hover_at(&self, uri: Url, pos: Position) -> Result<Option<Hover>> {
+        let state = self.files.read().await;
+        let file = state
+            .get_file(&uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?;
+
+        // Only use markdown (and embed images) if the client can render it in hovers.
+        let markdown = self
+            .client_capabilities
+            .lock()
+            .await
+            .text_document
+            .as_ref()
+            .and_then(|td| td.hover.as_ref())
+            .and_then(|hover| hover.content_format.as_ref())
+            .map(|formats| formats.contains(&MarkupKind::Markdown))
+            .unwrap_or(false);
+
+        if let Some(reference) = footnotes::reference_at(&file.content, pos) {
+            let definitions = footnotes::parse_definitions(&file.content);
+            return Ok(footnotes::definition(&definitions, &reference.label)
+                .map(|definition| hover::footnote_hover(&reference, definition, markdown)));
+        }
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let bibliography = self.config.lock().await.bibliography.clone();
+        if let Some((key, range)) = citations::key_at(&file.content, pos) {
+            if !bibliography.is_empty() {
+                let root = self.get_root(&path).await?;
+                let entries = citations::load(&root, &bibliography).await;
+                if let Some(entry) = entries.iter().find(|entry| entry.key == key) {
+                    return Ok(Some(citations::hover(entry, range, markdown)));
+                }
+            }
+        }
+        let Some(link) = links::link_at(&file.content, pos) else {
+            let note = Note::parse(&file.content);
+            let line_count = file.content.lines().count() as u32;
+            drop(state);
+            return Ok(self
+                .git_hover(&path, &note, pos.line, line_count, markdown)
+                .await);
+        };
+
+        let note_dir = path.parent().ok_or(Error::new(ErrorCode::InternalError))?;
+
+        let root = self.get_root(&path).await.ok();
+        let root = root.as_deref().unwrap_or(note_dir);
+        if let Some(hover) = hover::image_hover(&link, note_dir, root, markdown) {
+            return Ok(Some(hover));
+        }
+
+        // Preview the linked note, preferring unsaved content if it's open.
+        let index = self.index_for(&path).await;
+        let Some(target) = index.resolve(&path, &link) else {
+            return Ok(None);
+        };
+        let Some(note) = index.get(&target) else {
+            return Ok(None);
+        };
+        let content = match uri::from_path(&target).and_then(|uri| state.get_file(&uri)) {
+            Some(file) => file.content.clone(),
+            None => self.read_note(&target).await.map_err(internal_error)?,
+        };
+
+        Ok(Some(hover::note_hover(
+            &link, &target, &content, note, markdown,
+        )))
+    }
+
+    /// The hover on the heading at `line` of `note`, the note at `path` with `line_count` lines,
+    /// when its vault is a git repository: the last commit of the note on its first `#` heading,
+    /// and with `gitSectionBlame`, the last commit of the section under any other heading.
+    async fn git_hover(
+        &self,
+        path: &Path,
+        note: &Note,
+        line: u32,
+        line_count: u32,
+        markdown: bool,
+    ) -> Option<Hover> {
+        let heading = note.headings.iter().find(|heading| heading.line == line)?;
+        let first = note.headings.iter().find(|heading| heading.level == 1);
+        let commit = if first.is_some_and(|first| first.line == line) {
+            git::last_commit(path).await?
+        } else if self.config.lock().await.git_section_blame {
+            let end = note.section_end(heading).unwrap_or(line_count);
+            git::last_commit_of_lines(path, line, end.saturating_sub(1)).await?
+        } else {
+            return None;
+        };
+        let range = Range::new(Position::new(line, 0), Position::new(line + 1, 0));
+        Some(hover::commit_hover(&commit, range, markdown))
+    }
+
+    /// Scroll the previews of the note at `uri` to `line`.
+    async fn scroll_preview(&self, uri: &Url, line: u32) {
+        if self.config.lock().await.preview {
+            if let Some(preview_server) = self.preview_server.lock().await.as_ref() {
+                preview_server.scroll_to_line(line);
+                preview_server.scroll_to_line_in(uri.as_str(), line);
+            }
+        }
+    }
+
+    /// Handle `noteLs/noteInfo`: the links, backlinks, tags and frontmatter of a note, from its
+    /// content in the editor if it's open.
+    pub async fn note_info(&self, params: NoteInfoParams) -> Result<Option<NoteInfo>> {
+        let path = uri::to_path(&params.uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let open = self
+            .files
+            .read()
+            .await
+            .get_file(&params.uri)
+            .map(|file| file.content.clone());
+        let document = match open {
+            Some(content) => content,
+            None => match self.read_note(&path).await {
+                Ok(content) => content,
+                Err(_) => return Ok(None),
+            },
+        };
+        let vaults = self.vaults.read().await;
+        Ok(note_info::note_info(
+            &path,
+            &document,
+            vaults.index_for(&path),
+            vaults.indexed(&path),
+        ))
+    }
+
+    /// Handle `noteLs/books`: the books in a vault being read and the ones finished.
+    pub async fn books(&self, params: BooksParams) -> Result<Books> {
+        let root = self.command_root(params.uri).await?;
+        Ok(books::books(&*self.index_for(&root).await))
+    }
+
+    /// Handle `noteLs/status`: counters of what the server did since it started.
+    pub async fn status(&self) -> Result<metrics::Status> {
+        let contents = self.contents.lock().await.stats();
+        let completions = self.completions.lock().await.stats();
+        let metrics_url = match self.config.lock().await.metrics_endpoint {
+            true => self
+                .preview_server
+                .lock()
+                .await
+                .as_ref()
+                .map(|preview_server| preview_server.metrics_url()),
+            false => None,
+        };
+        Ok(self.metrics.status(contents, completions, metrics_url))
+    }
+
+    /// Handle `noteLs/sectionRange`: the sentence, paragraph and section at the cursor, for focus
+    /// modes.
+    pub async fn section_range(&self, params: SectionRangeParams) -> Result<Option<SectionRange>> {
+        let state = self.files.read().await;
+        let file = state
+            .get_file(&params.text_document.uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let encoding = *self.encoding.lock().await;
+        let position = encoding.to_server(&file.content, params.position);
+        let to_client = |range| encoding.range_to_client(&file.content, range);
+        Ok(
+            focus::section_range(&file.content, position).map(|range| SectionRange {
+                sentence: range.sentence.map(to_client),
+                paragraph: range.paragraph.map(to_client),
+                section: to_client(range.section),
+            }),
+        )
+    }
+
+    /// Handle `noteLs/cursorMoved`, sent by clients that want the preview to follow the cursor.
+    pub async fn cursor_moved(&self, params: CursorMovedParams) {
+        let uri = params.text_document.uri;
+        let previewed = self.current_file.read().await.clone();
+        if previewed.as_ref() != Some(&uri) {
+            let Some(content) = self
+                .files
+                .read()
+                .await
+                .get_file(&uri)
+                .map(|file| file.content.clone())
+            else {
+                return;
+            };
+            *self.current_file.write().await = Some(uri.clone());
+            self.preview_in_background(uri.clone(), content, None, Duration::ZERO);
+        }
+        self.scroll_preview(&uri, params.position.line).await;
+    }
+
+    /// Write a zip of the note `args` names, the notes it links to and their attachments, and
+    /// return its URI.
+    async fn bundle_note(&self, args: commands::BundleNoteArgs) -> Result<Option<Value>> {
+        let uri = match args.uri {
+            Some(uri) => uri,
+            None => self
+                .current_file
+                .read()
+                .await
+                .clone()
+                .ok_or_else(|| Error::invalid_params("no note to bundle"))?,
+        };
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+
+        let state = self.files.read().await;
+        let index = self.index_for(&path).await;
+        let output = match args.output {
+            Some(output) => index.root().join(output),
+            None => {
+                let name = path
+                    .file_stem()
+                    .ok_or(Error::new(ErrorCode::InvalidParams))?;
+                std::env::temp_dir().join(name).with_extension("zip")
+            }
+        };
+        let bundled = bundle::contents(&index, &path, args.depth.unwrap_or(1));
+
+        let mut files = Vec::new();
+        let mut contents = self.contents.lock().await;
+        for note in &bundled.notes {
+            // Prefer unsaved content if the note is open.
+            let content = match uri::from_path(note).and_then(|uri| state.get_file(&uri)) {
+                Some(file) => file.content.clone(),
+                None => contents.get(note).map_err(internal_error)?.to_string(),
+            };
+            let content = bundle::rewrite_links(&content, note, &index, &bundled);
+            files.push((bundle::entry_name(index.root(), note), content.into_bytes()));
+        }
+        for attachment in &bundled.attachments {
+            let bytes = std::fs::read(attachment).map_err(internal_error)?;
+            files.push((bundle::entry_name(index.root(), attachment), bytes));
+        }
+        let zip = std::fs::File::create(&output).map_err(internal_error)?;
+        bundle::write_zip(zip, &files).map_err(internal_error)?;
+        Ok(Some(json!(uri::from_path(&output))))
+    }
+
+    /// The note `args` names as a single markdown document, with the notes it embeds written
+    /// out in it.
+    async fn flatten_note(&self, args: commands::FlattenNoteArgs) -> Result<Option<Value>> {
+        let uri = match args.uri {
+            Some(uri) => uri,
+            None => self
+                .current_file
+                .read()
+                .await
+                .clone()
+                .ok_or_else(|| Error::invalid_params("no note to flatten"))?,
+        };
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+
+        let state = self.files.read().await;
+        let content = match state.get_file(&uri) {
+            Some(file) => file.content.clone(),
+            None => self.read_note(&path).await.map_err(internal_error)?,
+        };
+        let index = self.index_for(&path).await;
+        let mut contents = self.contents.lock().await;
+        // Prefer unsaved content of the embedded notes that are open.
+        let mut read = |path: &Path| match uri::from_path(path).and_then(|uri| state.get_file(&uri))
+        {
+            Some(file) => Some(file.content.clone()),
+            None => contents.get(path).ok().map(|content| content.to_string()),
+        };
+        let flattened = flatten::flatten(&path, &content, &index, &mut read);
+        Ok(Some(json!(flattened)))
+    }
+
+    /// Export the note at `args.uri` to a standalone HTML file, or a PDF if `pdf`, returning the
+    /// URI of the file written.
+    async fn export_note(&self, args: commands::ExportArgs, pdf: bool) -> Result<Option<Value>> {
+        let uri = match args.uri {
+            Some(uri) => uri,
+            None => self
+                .current_file
+                .read()
+                .await
+                .clone()
+                .ok_or_else(|| Error::invalid_params("no note to export"))?,
+        };
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let root = self.get_root(&path).await?;
+        let config = self.config.lock().await.clone();
+
+        let (markdown, title) = {
+            let state = self.files.read().await;
+            let content = match state.get_file(&uri) {
+                Some(file) => file.content.clone(),
+                None => self.read_note(&path).await.map_err(internal_error)?,
+            };
+            let index = self.index_for(&path).await;
+            let mut contents = self.contents.lock().await;
+            let mut read =
+                |path: &Path| match uri::from_path(path).and_then(|uri| state.get_file(&uri)) {
+                    Some(file) => Some(file.content.clone()),
+                    None => contents.get(path).ok().map(|content| content.to_string()),
+                };
+            let title = Note::parse(&content).title().map(str::to_string);
+            let markdown =
+                export::markdown(&path, &content, &index, &mut read, export::NoteLinks::Files);
+            let markdown = hide_comments(markdown, &config);
+            (
+                footnote_links::render(&habits::render(&charts::render(&markdown))),
+                title,
+            )
+        };
+        let title = title.unwrap_or_else(|| {
+            path.file_stem()
+                .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned())
+        });
+
+        let (renderer, direction, note) = (
+            config.renderer.clone(),
+            config.preview_direction,
+            path.clone(),
+        );
+        let html = tokio::task::spawn_blocking(move || {
+            export::render(&renderer, direction, &markdown, &note)
+        })
+        .await
+        .map_err(internal_error)?
+        .map_err(|e| internal_error(format!("Could not render {}: {e}", path.display())))?;
+        let style = export::style(&config, &root);
+        let page = aurelius::export::standalone_page(&html, &title, path.parent(), &style);
+
+        let output = match args.output {
+            Some(output) => root.join(output),
+            None => path.with_extension(if pdf { "pdf" } else { "html" }),
+        };
+        let written = match pdf {
+            false => tokio::fs::write(&output, page).await,
+            true => {
+                let input = std::env::temp_dir()
+                    .join(format!("note-ls-export-{}.html", std::process::id()));
+                let written = match tokio::fs::write(&input, page).await {
+                    Ok(()) => export::print_pdf(&config.pdf_command, &input, &output).await,
+                    Err(e) => Err(e),
+                };
+                let _ = tokio::fs::remove_file(&input).await;
+                written
+            }
+        };
+        written
+            .map_err(|e| internal_error(format!("Could not export {}: {e}", output.display())))?;
+        Ok(Some(json!(uri::from_path(&output))))
+    }
+
+    /// Back up the vault `args` names, prune the backups beyond the configured number, and return
+    /// the URI of the backup.
+    async fn backup_vault(&self, args: commands::BackupVaultArgs) -> Result<Option<Value>> {
+        let root = self.command_root(args.uri).await?;
+        let config = self.config.lock().await.clone();
+        let folder = match config.backup_folder {
+            Some(folder) => root.join(folder),
+            None => {
+                let name = root
+                    .file_name()
+                    .ok_or(Error::new(ErrorCode::InvalidParams))?;
+                let mut name = name.to_os_string();
+                name.push("-backups");
+                root.with_file_name(name)
+            }
+        };
+        let seconds = std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let output = folder.join(backup::archive_name(&root, seconds));
+
+        let files = Ignore::new(&root, &config.ignore_globs)
+            .walk()
+            .into_iter()
+            // Earlier backups, if they're kept in the vault.
+            .filter(|path| !path.starts_with(&folder))
+            .collect::<Vec<_>>();
+        let backup = backup::Backup::new(&*self.index_for(&root).await, files);
+        // Reading the whole vault blocks for a while.
+        let zip = output.clone();
+        let written = tokio::task::spawn_blocking(move || {
+            std::fs::create_dir_all(&folder)?;
+            backup.write(std::fs::File::create(&zip)?, seconds)?;
+            if config.backup_retention > 0 {
+                backup::prune(&folder, &root, config.backup_retention)?;
+            }
+            std::io::Result::Ok(())
+        })
+        .await
+        .map_err(internal_error)?;
+        written.map_err(internal_error)?;
+        Ok(Some(json!(uri::from_path(&output))))
+    }
+
+    /// Set up the vault `args` names with folders, templates and a home note, leaving alone what's
+    /// there already, and open the home note. Returns what was created and the settings that use
+    /// it, for the client to save.
+    async fn init_vault(&self, args: commands::InitVaultArgs) -> Result<Option<Value>> {
+        let root = self.command_root(args.uri).await?;
+        let created = vault_setup::scaffold(&root).map_err(internal_error)?;
+        for path in created.iter().filter(|path| path.is_file()) {
+            self.reindex(path.clone(), Change::Changed).await;
+        }
+        if let Some(home) = uri::from_path(&root.join(vault_setup::HOME)) {
+            self.show_note(&home, None).await;
+        }
+        let created = created
+            .iter()
+            .filter_map(|path| uri::from_path(path))
+            .collect::<Vec<_>>();
+        Ok(Some(json!({
+            "created": created,
+            "settings": vault_setup::settings(),
+        })))
+    }
+
+    /// Publish the vault `args` names: render every note to a page in the output folder, copy
+    /// the attachments they link to and list them on an index page, whose URI is returned. The
+    /// `published` hook runs once it's done.
+    async fn publish(&self, args: commands::PublishArgs) -> Result<Option<Value>> {
+        let note = match &args.uri {
+            Some(uri) => Some(uri.clone()),
+            None => self.current_file.read().await.clone(),
+        }
+        .and_then(|uri| uri::to_path(&uri));
+        let root = self.command_root(args.uri).await?;
+        let output = match args.output {
+            Some(output) => root.join(output),
+            None => publish::default_output(&root),
+        };
+        if output == root {
+            return Err(Error::invalid_params("can't publish a vault into itself"));
+        }
+        let config = self.config.lock().await.clone();
+
+        let mut pages = Vec::new();
+        let mut attachments = BTreeSet::new();
+        {
+            let state = self.files.read().await;
+            let index = self.index_for(&root).await;
+            let mut contents = self.contents.lock().await;
+            let mut read =
+                |path: &Path| match uri::from_path(path).and_then(|uri| state.get_file(&uri)) {
+                    Some(file) => Some(file.content.clone()),
+                    None => contents.get(path).ok().map(|content| content.to_string()),
+                };
+            let notes = index
+                .notes()
+                .filter(|(path, _)| !index.is_ignored(path))
+                .map(|(path, note)| (path.to_path_buf(), note.title().map(str::to_string)))
+                .collect::<Vec<_>>();
+            for (path, title) in notes {
+                let Some(content) = read(&path) else {
+                    continue;
+                };
+                let markdown =
+                    export::markdown(&path, &content, &index, &mut read, export::NoteLinks::Pages);
+                let markdown = hide_comments(markdown, &config);
+                let title = title.unwrap_or_else(|| {
+                    path.file_stem()
+                        .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned())
+                });
+                attachments.extend(
+                    bundle::contents(&index, &path, 0)
+                        .attachments
+                        .into_iter()
+                        .filter(|attachment| attachment.starts_with(&root)),
+                );
+                pages.push(publish::Page {
+                    path,
+                    title,
+                    markdown: footnote_links::render(&habits::render(&charts::render(&markdown))),
+                });
+            }
+        }
+
+        let site = publish::Site {
+            root: root.clone(),
+            output: output.clone(),
+            renderer: config.renderer.clone(),
+            direction: config.preview_direction,
+            style: export::style(&config, &root),
+        };
+        let published = tokio::task::spawn_blocking(move || site.write(pages, attachments))
+            .await
+            .map_err(internal_error)?;
+        published.map_err(|e| {
+            internal_error(format!("Could not publish to {}: {e}", output.display()))
+        })?;
+
+        self.run_hook(Event::Published, note.as_deref().unwrap_or(&root), None)
+            .await;
+        Ok(Some(json!(uri::from_path(
+            &output.join(publish::INDEX_PAGE)
+        ))))
+    }
+
+    /// Compare the two copies of a vault `args` names.
+    async fn compare_vaults(&self, args: commands::CompareVaultsArgs) -> Result<Option<Value>> {
+        let (Some(path_a), Some(path_b)) = (args.path_a, args.path_b) else {
+            return Err(Error::invalid_params("pathA and pathB are required"));
+        };
+        for path in [&path_a, &path_b] {
+            if !path.is_dir() {
+                let message = format!("{} is not a directory", path.display());
+                return Err(Error::invalid_params(message));
+            }
+        }
+        let config = self.config.lock().await.clone();
+        let scan = move |root: &Path| {
+            let ignore = Ignore::new(root, &config.ignore_globs);
+            let mut index = NoteIndex::scan_with(ignore, config.note_extensions.clone());
+            index.set_resolution(config.link_resolution.into());
+            index
+        };
+        // Scanning and hashing both copies reads every note.
+        let diff =
+            tokio::task::spawn_blocking(move || compare::compare(&scan(&path_a), &scan(&path_b)))
+                .await
+                .map_err(internal_error)?;
+        Ok(Some(json!(diff)))
+    }
+
+    /// Where the words `args` asks for occur in the notes of the vault, in the best matching
+    /// notes first.
+    async fn search(&self, args: commands::SearchArgs) -> Result<Option<Value>> {
+        let root = self.command_root(args.uri).await?;
+        let limit = args.limit.unwrap_or(100);
+        let terms = search::query_terms(&args.query);
+
+        let encoding = *self.encoding.lock().await;
+        let state = self.files.read().await;
+        let index = self.index_for(&root).await;
+        let mut contents = self.contents.lock().await;
+        let mut found = Vec::new();
+        for (path, score) in index.search(&args.query) {
+            let Some(uri) = uri::from_path(path) else {
+                continue;
+            };
+            // Prefer unsaved content if the note is open.
+            let content = match state.get_file(&uri) {
+                Some(file) => file.content.clone(),
+                None => match contents.get(path) {
+                    Ok(content) => content.to_string(),
+                    Err(_) => continue,
+                },
+            };
+            let lines = content.lines().collect::<Vec<_>>();
+            for range in search::find_terms(&content, &terms) {
+                found.push(json!({
+                    "uri": uri,
+                    "range": encoding.range_to_client(&content, range),
+                    "score": score,
+                    "text": lines[range.start.line as usize].trim(),
+                }));
+            }
+            if found.len() >= limit {
+                break;
+            }
+        }
+        found.truncate(limit);
+        Ok(Some(json!(found)))
+    }
+
+    /// Write the link graph of the vault `args` names to a file, and return its URI.
+    async fn export_graph(&self, args: commands::GraphExportArgs) -> Result<Option<Value>> {
+        let root = self.command_root(args.uri).await?;
+        let output = match args.output {
+            Some(output) => root.join(output),
+            None => {
+                let name = root
+                    .file_name()
+                    .ok_or(Error::new(ErrorCode::InvalidParams))?;
+                std::env::temp_dir()
+                    .join(name)
+                    .with_extension(args.format.extension())
+            }
+        };
+        let exported = graph::export(&graph::graph(&*self.index_for(&root).await), args.format);
+        std::fs::write(&output, exported).map_err(internal_error)?;
+        Ok(Some(json!(uri::from_path(&output))))
+    }
+
+    /// Quote the note or section `args` names into another note, with a link back to it.
+    async fn insert_excerpt(&self, args: commands::InsertExcerptArgs) -> Result<Option<Value>> {
+        let target_uri = args
+            .target_note
+            .ok_or_else(|| Error::invalid_params("no note to quote"))?;
+        let target = uri::to_path(&target_uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let uri = match args.uri {
+            Some(uri) => uri,
+            None => self
+                .current_file
+                .read()
+                .await
+                .clone()
+                .ok_or_else(|| Error::invalid_params("no note to insert the excerpt into"))?,
+        };
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let dir = path.parent().ok_or(Error::new(ErrorCode::InvalidParams))?;
+
+        let target_content = self.note_content(&target_uri, &target).await?;
+        let content = self.note_content(&uri, &path).await?;
+        let encoding = *self.encoding.lock().await;
+        let position = match args.position {
+            Some(position) => encoding.to_server(&content, position),
+            None => excerpt::end_of(&content),
+        };
+        let note = Note::parse(&target_content);
+        let mut quote = excerpt::excerpt(
+            dir,
+            &target,
+            &target_content,
+            &note,
+            args.heading.as_deref(),
+        )
+        .ok_or_else(|| Error::invalid_params("no such heading in the note"))?;
+        // The quote has to start a line of its own.
+        if position.character > 0 {
+            quote.insert(0, '\n');
+        }
+
+        let edit = WorkspaceEdit {
+            changes: Some(HashMap::from([(
+                uri,
+                vec![TextEdit::new(Range::new(position, position), quote)],
+            )])),
+            ..WorkspaceEdit::default()
+        };
+        let response = self.apply_edit(edit).await?;
+        Ok(Some(json!(response.applied)))
+    }
+
+    async fn new_note(&self, args: commands::NewNoteArgs) -> Result<Option<Value>> {
+        let from = match args.uri {
+            Some(uri) => Some(uri),
+            None => self.current_file.read().await.clone(),
+        };
+        let root = self.command_root(from.clone()).await?;
+        let config = self.config.lock().await.clone();
+        let types = &config.note_types;
+
+        let note_type = match args.note_type {
+            Some(name) => Some(
+                types
+                    .iter()
+                    .find(|note_type| note_type.name == name)
+                    .ok_or_else(|| Error::invalid_params(format!("no note type named {name}")))?,
+            ),
+            None if types.is_empty() => None,
+            None => {
+                let actions = types
+                    .iter()
+                    .map(|note_type| MessageActionItem {
+                        title: note_type.label(),
+                        properties: HashMap::new(),
+                    })
+                    .collect();
+                let picked = self
+                    .client
+                    .show_message_request(MessageType::INFO, "Type of the new note", Some(actions))
+                    .await?;
+                // Dismissing the picker cancels the command.
+                let Some(picked) = picked else {
+                    return Ok(None);
+                };
+                types
+                    .iter()
+                    .find(|note_type| note_type.label() == picked.title)
+            }
+        };
+
+        let from = from.as_ref().and_then(uri::to_path);
+        let folder = match note_type.and_then(|note_type| note_type.folder.as_ref()) {
+            Some(folder) => links::normalize(&root.join(folder)),
+            None => {
+                let index = match &from {
+                    Some(from) => Some(self.index_for(from).await),
+                    None => None,
+                };
+                let note = from
+                    .as_ref()
+                    .zip(index.as_ref())
+                    .and_then(|(from, index)| index.get(from));
+                new_notes::folder(
+                    &config.new_note_location,
+                    &root,
+                    from.as_deref(),
+                    note,
+                    Date::today(),
+                )
+            }
+        };
+        let templates = match &config.templates_folder {
+            Some(folder) => templates::load(&root.join(folder), &config.note_extensions),
+            None => Vec::new(),
+        };
+        let type_template = note_type.and_then(|note_type| note_type.template.as_ref());
+        let template = match args.template {
+            Some(name) => Some(
+                templates
+                    .into_iter()
+                    .find(|template| template.name == name)
+                    .ok_or_else(|| Error::invalid_params(format!("no template named {name}")))?
+                    .content,
+            ),
+            None if type_template.is_some() || templates.is_empty() => type_template
+                .or(config.note_template.as_ref())
+                .and_then(|template| std::fs::read_to_string(root.join(template)).ok()),
+            None => {
+                let actions = templates
+                    .iter()
+                    .map(|template| MessageActionItem {
+                        title: template.name.clone(),
+                        properties: HashMap::new(),
+                    })
+                    .collect();
+                let picked = self
+                    .client
+                    .show_message_request(
+                        MessageType::INFO,
+                        "Template of the new note",
+                        Some(actions),
+                    )
+                    .await?;
+                let Some(picked) = picked else {
+                    return Ok(None);
+                };
+                templates
+                    .into_iter()
+                    .find(|template| template.name == picked.title)
+                    .map(|template| template.content)
+            }
+        };
+
+        let title = args.title.unwrap_or_else(|| String::from("Untitled"));
+        let path = new_notes::unused_path(&folder, &title);
+        let uri = uri::from_path(&path).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let mut content = new_notes::content(note_type, template.as_deref(), &title, Date::today());
+        let cursor = templates::take_cursor(&mut content);
+        if !self.create_note(&uri, content).await? {
+            return Ok(None);
+        }
+        self.show_note(&uri, cursor).await;
+        Ok(Some(json!(uri)))
+    }
+
+    /// Ask the client to create the note at `uri` with `content`, returning whether it did.
+    async fn create_note(&self, uri: &Url, content: String) -> Result<bool> {
+        let mut operations = vec![DocumentChangeOperation::Op(ResourceOp::Create(
+            CreateFile {
+                uri: uri.clone(),
+                options: None,
+                annotation_id: None,
+            },
+        ))];
+        if !content.is_empty() {
+            operations.push(DocumentChangeOperation::Edit(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier {
+                    uri: uri.clone(),
+                    version: None,
+                },
+                edits: vec![OneOf::Left(TextEdit::new(
+                    Range::new(Position::new(0, 0), Position::new(0, 0)),
+                    content,
+                ))],
+            }));
+        }
+        let edit = WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Operations(operations)),
+            ..WorkspaceEdit::default()
+        };
+        Ok(self.apply_edit(edit).await?.applied)
+    }
+
+    /// Ask the client to open the note at `uri`, with the cursor at `cursor` if given.
+    async fn show_note(&self, uri: &Url, cursor: Option<Position>) {
+        let documents = match cursor {
+            Some(_) => self.client_documents(vec![uri.clone()]).await,
+            None => HashMap::new(),
+        };
+        let encoding = *self.encoding.lock().await;
+        let cursor = cursor.map(|cursor| match documents.get(uri) {
+            Some(document) => encoding.to_client(document, cursor),
+            None => cursor,
+        });
+        // Not every client can open documents for the server, and the note exists either way.
+        let _ = self
+            .client
+            .send_request::<ShowDocument>(ShowDocumentParams {
+                uri: uri.clone(),
+                external: None,
+                take_focus: Some(true),
+                selection: cursor.map(|cursor| Range::new(cursor, cursor)),
+            })
+            .await;
+    }
+
+    /// Give the image embedded at `args.range` alt text.
+    async fn set_alt_text(&self, args: commands::SetAltTextArgs) -> Result<Option<Value>> {
+        let (Some(uri), Some(range)) = (args.uri, args.range) else {
+            return Err(Error::invalid_params(
+                "the note and range of an image are required",
+            ));
+        };
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let open = self
+            .files
+            .read()
+            .await
+            .get_file(&uri)
+            .map(|file| file.content.clone());
+        let content = match open {
+            Some(content) => content,
+            None => self.read_note(&path).await.map_err(internal_error)?,
+        };
+        let range = self.encoding.lock().await.range_to_server(&content, range);
+        let line = content.lines().nth(range.start.line as usize).unwrap_or("");
+        let link = links::parse_line(line, range.start.line)
+            .into_iter()
+            .find(|link| link.embed && link.start as u32 == range.start.character)
+            .ok_or_else(|| Error::invalid_params("no image at the range"))?;
+
+        let alt = args
+            .alt
+            .filter(|alt| !alt.trim().is_empty())
+            .unwrap_or_else(|| code_actions::default_alt_text(&link));
+        let edit = TextEdit::new(link.range(), code_actions::with_alt_text(&link, &alt));
+        let edit = WorkspaceEdit {
+            changes: Some(HashMap::from([(uri, vec![edit])])),
+            ..WorkspaceEdit::default()
+        };
+        self.apply_edit(edit).await?;
+        Ok(None)
+    }
+
+    /// Bring the table of contents of the note `args.uri` up to date, or insert one.
+    async fn update_toc(&self, args: commands::TocUpdateArgs) -> Result<Option<Value>> {
+        let uri = args
+            .uri
+            .ok_or_else(|| Error::invalid_params("a note is required"))?;
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let open = self
+            .files
+            .read()
+            .await
+            .get_file(&uri)
+            .map(|file| file.content.clone());
+        let content = match open {
+            Some(content) => content,
+            None => self.read_note(&path).await.map_err(internal_error)?,
+        };
+        if let Some(edit) = toc::update(&content, args.line) {
+            let edit = WorkspaceEdit {
+                changes: Some(HashMap::from([(uri, vec![edit])])),
+                ..WorkspaceEdit::default()
+            };
+            self.apply_edit(edit).await?;
+        }
+        Ok(None)
+    }
+
+    /// Move the selection `args.range` of the note `args.uri` into a new note named `args.title`,
+    /// and link to it in its place.
+    async fn extract_to_note(&self, args: commands::ExtractToNoteArgs) -> Result<Option<Value>> {
+        let (Some(uri), Some(range)) = (args.uri, args.range) else {
+            return Err(Error::invalid_params(
+                "the note and a selection are required",
+            ));
+        };
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let open = self
+            .files
+            .read()
+            .await
+            .get_file(&uri)
+            .map(|file| file.content.clone());
+        let content = match open {
+            Some(content) => content,
+            None => self.read_note(&path).await.map_err(internal_error)?,
+        };
+        let range = self.encoding.lock().await.range_to_server(&content, range);
+        let root = self.get_root(&path).await?;
+        let config = self.config.lock().await.clone();
+        let folder = new_notes::folder(
+            &config.new_note_location,
+            &root,
+            Some(&path),
+            Some(&Note::parse(&content)),
+            Date::today(),
+        );
+
+        let title = args.title.filter(|title| !title.trim().is_empty());
+        let (_, edit) = code_actions::extract_edit(
+            &uri,
+            &content,
+            range,
+            &folder,
+            &config.note_extensions,
+            title.as_deref(),
+        )
+        .ok_or_else(|| Error::invalid_params("nothing is selected"))?;
+        self.apply_edit(edit).await?;
+        Ok(None)
+    }
+
+    /// Add `args.word` to the dictionary of the vault of `args.uri`, and check the open notes
+    /// again.
+    async fn add_to_dictionary(
+        &self,
+        args: commands::AddToDictionaryArgs,
+    ) -> Result<Option<Value>> {
+        let word = args
+            .word
+            .map(|word| word.trim().to_string())
+            .filter(|word| !word.is_empty())
+            .ok_or_else(|| Error::invalid_params("a word is required"))?;
+        let root = self.command_root(args.uri).await?;
+        let path = root.join(&self.config.lock().await.custom_dictionary);
+        spelling::add_to_list(&path, &word)
+            .map_err(|e| internal_error(format!("Could not add to {}: {e}", path.display())))?;
+
+        let open = self.files.read().await.uris();
+        for uri in open {
+            self.publish_diagnostics(uri, None).await;
+        }
+        Ok(None)
+    }
+
+    /// Check the task at `args.position` in the note `args.uri`, or uncheck it if it's done, along
+    /// with the tasks nested under it.
+    async fn toggle_task(&self, args: commands::ToggleTaskArgs) -> Result<Option<Value>> {
+        let uri = match args.uri {
+            Some(uri) => uri,
+            None => self
+                .current_file
+                .read()
+                .await
+                .clone()
+                .ok_or_else(|| Error::invalid_params("no note with a task"))?,
+        };
+        let position = args
+            .position
+            .ok_or_else(|| Error::invalid_params("the position of a task is required"))?;
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let content = self.note_content(&uri, &path).await?;
+        let task = tasks::task_at(&content, position.line)
+            .ok_or_else(|| Error::invalid_params("no task at the position"))?;
+
+        let edit = WorkspaceEdit {
+            changes: Some(HashMap::from([(uri, tasks::toggle(&content, &task))])),
+            ..WorkspaceEdit::default()
+        };
+        let response = self.apply_edit(edit).await?;
+        Ok(Some(json!(response.applied)))
+    }
+
+    /// Switch to the profile `args` names, or back to the one the settings choose, and return
+    /// the profile in use with the names of all of them.
+    async fn set_profile(&self, args: commands::SetProfileArgs) -> Result<Option<Value>> {
+        let settings = self.settings.lock().await.clone();
+        let config = Config::from_settings(&settings, args.profile.as_deref())
+            .map_err(Error::invalid_params)?;
+        *self.profile.lock().await = args.profile;
+        let result = json!({
+            "profile": config.profile,
+            "profiles": config.profiles.keys().collect::<Vec<_>>(),
+        });
+        self.set_config(config).await;
+        Ok(Some(result))
+    }
+
+    /// Apply new settings, starting or stopping whatever depends on the ones that changed.
+    async fn set_config(&self, config: Config) {
+        let old = std::mem::replace(&mut *self.config.lock().await, config.clone());
+        if config.preview && !old.preview {
+            self.start_preview().await;
+        } else if !config.preview && old.preview {
+            self.stop_preview().await;
+        } else if config.preview
+            && (config.preview_host != old.preview_host || config.preview_port != old.preview_port)
+        {
+            // Open previews close with the old server; they can be opened again at the new URL.
+            self.stop_preview().await;
+            self.start_preview().await;
+        }
+        self.request_limits
+            .set(config.request_timeout_ms, config.slow_request_ms);
+        if config.content_cache_mb != old.content_cache_mb {
+            let budget = config.content_cache_mb << 20;
+            self.contents.lock().await.set_budget(budget);
+        }
+        if config.renderer != old.renderer || config.preview_direction != old.preview_direction {
+            self.set_renderer(&config.renderer, config.preview_direction)
+                .await;
+        }
+        if config.preview_theme != old.preview_theme
+            || config.preview_direction != old.preview_direction
+            || config.preview_stylesheet != old.preview_stylesheet
+            || config.preview_template != old.preview_template
+        {
+            self.style_preview().await;
+        }
+        if config.diagram_tools != old.diagram_tools {
+            self.diagrams.set_tools(&config.diagram_tools).await;
+        }
+        if config.metrics_endpoint != old.metrics_endpoint {
+            self.serve_metrics().await;
+        }
+        let auto_open = |config: &Config| config.preview && config.preview_auto_open;
+        if auto_open(&config) && !auto_open(&old) {
+            self.open_preview(aurelius::DEFAULT_CHANNEL).await;
+        }
+
+        if config.link_resolution != old.link_resolution {
+            for index in self.vaults.write().await.indexes_mut() {
+                index.set_resolution(config.link_resolution.into());
+            }
+        }
+        if config.locale != old.locale {
+            self.set_locale().await;
+        }
+        if old.diagnostics && !config.diagnostics {
+            let uris = self
+                .vaults
+                .read()
+                .await
+                .iter()
+                .flat_map(|vault| note_uris(&vault.index))
+                .collect();
+            self.clear_diagnostics(uris).await;
+        }
+        // Diagnostics depend on the settings, so refresh them for every open note.
+        let open = self.files.read().await.uris();
+        for uri in open {
+            self.publish_diagnostics(uri, None).await;
+        }
+    }
+
+    /// The notes of the vault `args` names changed in its last days, newest first, with their title
+    /// and the day of their last change. Git's history is used when the vault is a repository, and
+    /// the files' modification times otherwise.
+    async fn recent_notes(&self, args: commands::RecentNotesArgs) -> Result<Option<Value>> {
+        let root = self.command_root(args.uri).await?;
+        let days = args.days.unwrap_or(7);
+        let changes = git::recent_changes(&root, days).await;
+
+        let index = self.index_for(&root).await;
+        let recent = match changes {
+            Some(changes) => changes,
+            None => {
+                let since = Date::today().days() - i64::from(days);
+                let mut recent = index
+                    .disk_notes()
+                    .filter_map(|(path, modified, _)| {
+                        let seconds = modified
+                            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+                            .ok()?
+                            .as_secs();
+                        let day = Date::from_days((seconds / 86_400) as i64);
+                        (day.days() >= since).then(|| (path.to_path_buf(), day.to_string()))
+                    })
+                    .collect::<Vec<_>>();
+                recent.sort_by(|(a, a_day), (b, b_day)| b_day.cmp(a_day).then(a.cmp(b)));
+                recent
+            }
+        };
+        let notes = recent
+            .into_iter()
+            .filter_map(|(path, date)| {
+                let note = index.get(&path)?;
+                Some(json!({
+                    "uri": uri::from_path(&path)?,
+                    "title": note.title(),
+                    "date": date,
+                }))
+            })
+            .collect::<Vec<_>>();
+        Ok(Some(json!(notes)))
+    }
+
+    /// The open tasks of the vault `args` names that match its filters, as locations with their
+    /// text, due date and tags. Tasks that are due come first, soonest first.
+    async fn list_tasks(&self, args: commands::TasksListArgs) -> Result<Option<Value>> {
+        let root = self.command_root(args.uri).await?;
+        let limit = args.limit.unwrap_or(500);
+        let day = |day: Option<String>| match day.as_deref() {
+            Some("today") => Some(Date::today().to_string()),
+            _ => day,
+        };
+        let (due_before, due_after) = (day(args.due_before), day(args.due_after));
+        let tag = args
+            .tag
+            .map(|tag| tag.trim_start_matches('#').to_lowercase());
+        let tagged = |name: &str, tag: &str| {
+            let name = name.to_lowercase();
+            name == tag || name.starts_with(&format!("{tag}/"))
+        };
+
+        let state = self.files.read().await;
+        let index = self.index_for(&root).await;
+        let mut contents = self.contents.lock().await;
+        let mut notes = index
+            .notes()
+            .filter(|(_, note)| note.open_tasks > 0)
+            .collect::<Vec<_>>();
+        notes.sort_by_key(|(path, _)| *path);
+        let mut found = Vec::new();
+        for (path, note) in notes {
+            let Some(uri) = uri::from_path(path) else {
+                continue;
+            };
+            // Prefer unsaved content if the note is open.
+            let content = match state.get_file(&uri) {
+                Some(file) => file.content.clone(),
+                None => match contents.get(path) {
+                    Ok(content) => content.to_string(),
+                    Err(_) => continue,
+                },
+            };
+            for task in tasks::open_tasks(&content) {
+                if let Some(tag) = &tag {
+                    let note_tagged = note.tags.iter().any(|name| tagged(&name.name, tag));
+                    if !note_tagged && !task.tags.iter().any(|name| tagged(name, tag)) {
+                        continue;
+                    }
+                }
+                // Dates in the same format sort the same as text.
+                let due = task.due.as_deref();
+                if due_before
+                    .as_deref()
+                    .is_some_and(|day| due.is_none_or(|due| due > day))
+                    || due_after
+                        .as_deref()
+                        .is_some_and(|day| due.is_none_or(|due| due < day))
+                {
+                    continue;
+                }
+                found.push((uri.clone(), task));
+            }
+        }
+        // Sorting is stable, so tasks due the same day stay in the order of their notes.
+        found.sort_by(|(_, a), (_, b)| match (&a.due, &b.due) {
+            (Some(a), Some(b)) => a.cmp(b),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        });
+        found.truncate(limit);
+        let found = found
+            .into_iter()
+            .map(|(uri, task)| {
+                json!({
+                    "uri": uri,
+                    "range": task.range(),
+                    "text": task.text,
+                    "due": task.due,
+                    "tags": task.tags,
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(Some(json!(found)))
+    }
+
+    /// Run the code block at `args.line` of the note `args.uri` with the runner configured for its
+    /// language, and put what it printed below it.
+    async fn run_code_block(&self, args: commands::RunCodeBlockArgs) -> Result<Option<Value>> {
+        let uri = match args.uri {
+            Some(uri) => uri,
+            None => self
+                .current_file
+                .read()
+                .await
+                .clone()
+                .ok_or_else(|| Error::invalid_params("no note with code to run"))?,
+        };
+        let line = args
+            .line
+            .ok_or_else(|| Error::invalid_params("the line of a code block is required"))?;
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let config = self.config.lock().await.clone();
+        let content = self.note_content(&uri, &path).await?;
+        let block = code_blocks::block_at(&content, line)
+            .ok_or_else(|| Error::invalid_params("no code block at the line"))?;
+        let runner = config
+            .code_runners
+            .iter()
+            .find(|runner| runner.language == block.language)
+            .ok_or_else(|| {
+                Error::invalid_params(format!("no runner for {} code", block.language))
+            })?;
+
+        let dir = path.parent().unwrap_or(&path);
+        let timeout = Duration::from_millis(config.code_run_timeout_ms);
+        let output = code_blocks::run(runner, &block.code, dir, timeout)
+            .await
+            .map_err(|e| internal_error(format!("Could not run {}: {e}", runner.command)))?;
+
+        // The note may have been edited while the code ran.
+        let content = self.note_content(&uri, &path).await?;
+        if code_blocks::block_at(&content, line).as_ref() != Some(&block) {
+            return Err(Error::invalid_params("the code block changed while it ran"));
+        }
+        let edit = WorkspaceEdit {
+            changes: Some(HashMap::from([(
+                uri,
+                vec![code_blocks::output_edit(&content, &block, &output)],
+            )])),
+            ..WorkspaceEdit::default()
+        };
+        let response = self.apply_edit(edit).await?;
+        Ok(Some(json!(response.applied)))
+    }
+
+    /// Open today's daily note in the vault of `args.uri`, creating it from the daily note
+    /// template if it doesn't exist yet.
+    async fn open_daily_note(&self, args: commands::DailyNoteArgs) -> Result<Option<Value>> {
+        let root = self.command_root(args.uri).await?;
+        let config = self.config.lock().await.clone();
+        let date = Date::today();
+        let path = root.join(daily_notes::path(&config.daily_note_pattern, date));
+        let uri = uri::from_path(&path).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let mut cursor = None;
+        if !path.exists() {
+            let template = config
+                .daily_note_template
+                .as_ref()
+                .and_then(|template| std::fs::read_to_string(root.join(template)).ok());
+            let mut content =
+                new_notes::content(None, template.as_deref(), &date.to_string(), date);
+            cursor = templates::take_cursor(&mut content);
+            if !self.create_note(&uri, content).await? {
+                return Ok(None);
+            }
+        }
+        self.show_note(&uri, cursor).await;
+        Ok(Some(json!(uri)))
+    }
+
+    /// Open the daily note before the one at `args.uri`, or after it if `later`. Returns `null`
+    /// if there is none.
+    async fn adjacent_daily_note(
+        &self,
+        args: commands::DailyNoteArgs,
+        later: bool,
+    ) -> Result<Option<Value>> {
+        let from = match args.uri {
+            Some(uri) => Some(uri),
+            None => self.current_file.read().await.clone(),
+        };
+        let root = self.command_root(from.clone()).await?;
+        let pattern = self.config.lock().await.daily_note_pattern.clone();
+        let date = from
+            .as_ref()
+            .and_then(uri::to_path)
+            .and_then(|path| daily_notes::date_of(&pattern, path.strip_prefix(&root).ok()?))
+            .unwrap_or_else(Date::today);
+        let found = daily_notes::neighbour(&pattern, &*self.index_for(&root).await, date, later);
+        let Some(uri) = found.as_deref().and_then(uri::from_path) else {
+            return Ok(None);
+        };
+        self.show_note(&uri, None).await;
+        Ok(Some(json!(uri)))
+    }
+
+    async fn toggle_habit(&self, args: commands::ToggleHabitArgs) -> Result<Option<Value>> {
+        let uri = match args.uri {
+            Some(uri) => uri,
+            None => self
+                .current_file
+                .read()
+                .await
+                .clone()
+                .ok_or_else(|| Error::invalid_params("no note with a habit tracker"))?,
+        };
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let open = self
+            .files
+            .read()
+            .await
+            .get_file(&uri)
+            .map(|file| file.content.clone());
+        let content = match open {
+            Some(content) => content,
+            None => self.read_note(&path).await.map_err(internal_error)?,
+        };
+
+        let blocks = habits::blocks(&content);
+        let block = args
+            .position
+            .and_then(|position| {
+                blocks
+                    .iter()
+                    .find(|block| (block.start..=block.end).contains(&position.line))
+            })
+            .or(blocks.first())
+            .ok_or_else(|| Error::invalid_params("no habit tracker in the note"))?;
+        let habit = match (&args.habit, args.position) {
+            (Some(name), _) => block.habit(name),
+            (None, Some(position)) => content
+                .lines()
+                .nth(position.line as usize)
+                .and_then(|line| habits::habit_at(line, position.character as usize))
+                .filter(|habit| *habit < block.habits.len()),
+            (None, None) => None,
+        }
+        .ok_or_else(|| Error::invalid_params("no such habit in the tracker"))?;
+
+        let edit = WorkspaceEdit {
+            changes: Some(HashMap::from([(
+                uri,
+                vec![habits::toggle(block, habit, &Date::today().to_string())],
+            )])),
+            ..WorkspaceEdit::default()
+        };
+        let response = self.apply_edit(edit).await?;
+        Ok(Some(json!(response.applied)))
+    }
+
+    async fn update_reading_progress(
+        &self,
+        args: commands::UpdateReadingProgressArgs,
+    ) -> Result<Option<Value>> {
+        let uri = match args.uri {
+            Some(uri) => uri,
+            None => self
+                .current_file
+                .read()
+                .await
+                .clone()
+                .ok_or_else(|| Error::invalid_params("no book to update"))?,
+        };
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let open = self
+            .files
+            .read()
+            .await
+            .get_file(&uri)
+            .map(|file| file.content.clone());
+        let content = match open {
+            Some(content) => content,
+            None => self.read_note(&path).await.map_err(internal_error)?,
+        };
+
+        let frontmatter = frontmatter::parse(&content).unwrap_or_default();
+        if frontmatter.note_type.as_deref() != Some(books::BOOK_TYPE) {
+            return Err(Error::invalid_params("the note isn't a book"));
+        }
+        let fields = books::progress_fields(&frontmatter, args.page, args.percent, args.finished);
+        let edit = WorkspaceEdit {
+            changes: Some(HashMap::from([(uri, books::set_fields(&content, &fields))])),
+            ..WorkspaceEdit::default()
+        };
+        let response = self.apply_edit(edit).await?;
+        Ok(Some(json!(response.applied)))
+    }
+
+    /// Run the hook configured for `event` on the note at `path`, if there is one, in the
+    /// background.
+    async fn run_hook(&self, event: Event, path: &Path, old_path: Option<&Path>) {
+        let Some(command) = self
+            .config
+            .lock()
+            .await
+            .hooks
+            .command(event)
+            .map(str::to_string)
+        else {
+            return;
+        };
+        let Ok(root) = self.get_root(path).await else {
+            return;
+        };
+
+        let mut payload = hooks::Payload::new(event, path, self.index_for(path).await.get(path));
+        payload.old_path = old_path.map(Path::to_path_buf);
+
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            let message = match hooks::run(&command, &root, &payload).await {
+                Ok(output) if output.status.success() => return,
+                Ok(output) => format!(
+                    "Hook `{command}` failed ({}): {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+                Err(e) => format!("Hook `{command}` failed to start: {e}"),
+            };
+            logging::log(&client, MessageType::WARNING, message).await;
+        });
+    }
+
+    /// Ask the plugins contributing `feature`, logging the ones that fail.
+    async fn plugin_results<T: DeserializeOwned>(
+        &self,
+        feature: Feature,
+        params: Value,
+    ) -> Vec<(String, T)> {
+        let (results, errors) = timeout::timed("waiting for plugins", async {
+            self.plugins.lock().await.request_all(feature, params).await
+        })
+        .await;
+        for error in errors {
+            logging::log(&self.client, MessageType::WARNING, error).await;
+        }
+        results
+    }
+
+    /// Get the root of the vault containing `path`, or an error if it isn't in any of the
+    /// workspace folders.
+    pub async fn get_root(&self, path: &Path) -> Result<PathBuf> {
+        self.vaults
+            .read()
+            .await
+            .root(path)
+            .map(Path::to_path_buf)
+            .ok_or_else(|| Error::invalid_params("no workspace root"))
+    }
+
+    /// The root of the vault a command acts on: the one containing the note at `uri`, or else the
+    /// note last edited, or else the vault opened first.
+    async fn command_root(&self, uri: Option<Url>) -> Result<PathBuf> {
+        let uri = match uri {
+            Some(uri) => Some(uri),
+            None => self.current_file.read().await.clone(),
+        };
+        let vaults = self.vaults.read().await;
+        let vault = match uri.as_ref().and_then(uri::to_path) {
+            Some(path) => vaults.vault(&path),
+            None => vaults.first(),
+        };
+        vault
+            .map(|vault| vault.root.clone())
+            .ok_or_else(|| Error::invalid_params("no workspace root"))
+    }
+
+    /// The root of the vault opened first, which paths in the settings are relative to.
+    async fn first_root(&self) -> Option<PathBuf> {
+        Some(self.vaults.read().await.first()?.root.clone())
+    }
+
+    /// The index of the vault containing `path`. Requests only read it, so they don't wait on
+    /// each other, only on the index changing.
+    async fn index_for(&self, path: &Path) -> RwLockReadGuard<'_, NoteIndex> {
+        let vaults = timeout::timed("waiting for the index", self.vaults.read()).await;
+        RwLockReadGuard::map(vaults, |vaults| vaults.index_for(path))
+    }
+}
+
+#[tower_lsp::async_trait]
+impl LanguageServer for MarkdownLanguageServer {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        // TODO: Client must support goto definition link
+        let roots = workspace_roots(&params);
+        let encoding = Encoding::negotiate(&params.capabilities);
+        *self.encoding.lock().await = encoding;
+        *self.client_capabilities.lock().await = params.capabilities;
+        if let Some(options) = params.initialization_options {
+            *self.config.lock().await =
+                Config::from_settings(&options, None).map_err(Error::invalid_params)?;
+            *self.settings.lock().await = options;
+        }
+        *self.client_locale.lock().await = params.locale;
+        self.set_locale().await;
+        let config = self.config.lock().await.clone();
+        self.contents
+            .lock()
+            .await
+            .set_budget(config.content_cache_mb << 20);
+        self.request_limits
+            .set(config.request_timeout_ms, config.slow_request_ms);
+        self.diagrams.set_tools(&config.diagram_tools).await;
+        self.start_reindexing(None).await;
+        self.start_watchdog().await;
+
+        // The vaults are indexed once the client is initialized, and can report the progress.
+        // Ones another editor connected to the server opened are indexed already.
+        let mut new_roots = Vec::new();
+        for root in &roots {
+            if self.vaults.write().await.get_mut(root).is_none() {
+                self.add_vault(root).await;
+                new_roots.push(root.clone());
+            }
+        }
+        *self.pending_vaults.lock().await = Some(new_roots);
+        // Notes outside the vaults too.
+        let resolution = self.config.lock().await.link_resolution;
+        for index in self.vaults.write().await.indexes_mut() {
+            index.set_resolution(resolution.into());
+        }
+        if let Some(root) = roots.first() {
+            let configs = self.config.lock().await.plugins.clone();
+            let (plugins, errors) = Plugins::start(&configs, root).await;
+            for error in errors {
+                logging::log(&self.client, MessageType::ERROR, error).await;
+            }
+            *self.plugins.lock().await = plugins;
+        }
+
+        // Start the preview server, and open the preview in the browser
+        let config = self.config.lock().await.clone();
+        if config.preview {
+            self.start_preview().await;
+        }
+        if config.preview && config.preview_auto_open {
+            self.open_preview(aurelius::DEFAULT_CHANNEL).await;
+        }
+
+        let mut trigger_characters = vec![
+            "[[".to_string(),
+            "(".to_string(),
+            "#".to_string(),
+            "^".to_string(),
+            "@".to_string(),
+            "`".to_string(),
+        ];
+        trigger_characters.extend(
+            config
+                .note_types
+                .iter()
+                .filter_map(|note_type| note_type.trigger.as_deref()?.chars().next())
+                .map(String::from),
+        );
+
+        Ok(InitializeResult {
+            capabilities: ServerCapabilities {
+                position_encoding: Some(encoding.kind()),
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                        ..TextDocumentSyncOptions::default()
+                    },
+                )),
+                completion_provider: Some(CompletionOptions {
+                    trigger_characters: Some(trigger_characters),
+                    resolve_provider: Some(true),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                    all_commit_characters: None,
+                    completion_item: None,
+                }),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                definition_provider: Some(OneOf::Left(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
+                    DiagnosticOptions {
+                        identifier: Some("note-ls".to_string()),
+                        // Broken links depend on the other notes.
+                        inter_file_dependencies: true,
+                        workspace_diagnostics: true,
+                        work_done_progress_options: WorkDoneProgressOptions::default(),
+                    },
+                )),
+                references_provider: Some(OneOf::Left(true)),
+                call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                })),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: None,
+                }),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(true),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
+                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                semantic_tokens_provider: Some(
+                    SemanticTokensServerCapabilities::SemanticTokensOptions(
+                        SemanticTokensOptions {
+                            legend: semantic_tokens::legend(),
+                            range: Some(true),
+                            full: Some(SemanticTokensFullOptions::Bool(true)),
+                            ..Default::default()
+                        },
+                    ),
+                ),
+                document_highlight_provider: Some(OneOf::Left(true)),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                document_range_formatting_provider: Some(OneOf::Left(true)),
+                document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+                    first_trigger_character: String::from("\n"),
+                    more_trigger_character: None,
+                }),
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                        supported: Some(true),
+                        change_notifications: Some(OneOf::Left(true)),
+                    }),
+                    file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                        will_rename: Some(file_operation_options()),
+                        did_rename: Some(file_operation_options()),
+                        did_create: Some(file_operation_options()),
+                        ..WorkspaceFileOperationsServerCapabilities::default()
+                    }),
+                }),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: commands::all()
+                        .into_iter()
+                        .chain(self.plugins.lock().await.commands())
+                        .collect(),
+                    work_done_progress_options: WorkDoneProgressOptions::default(),
+                }),
+                ..ServerCapabilities::default()
+            },
+            ..InitializeResult::default()
+        })
+    }
+
+    async fn initialized(&self, _: InitializedParams) {
+        logging::log(
+            &self.client,
+            MessageType::INFO,
+            "mdls language server initialized",
+        )
+        .await;
+        // The preview started while initializing, before the client could be told about it.
+        self.announce_preview().await;
+
+        // Only index once, however many times the client says it's initialized.
+        let Some(roots) = self.pending_vaults.lock().await.take() else {
+            return;
+        };
+        let server = self.clone();
+        tokio::spawn(async move {
+            let progress = server.indexing_progress().await;
+            server.index_vaults(&roots, &progress).await;
+            // Notes opened while indexing had their links left unchecked.
+            let open = server.files.read().await.uris();
+            for uri in open {
+                server.publish_diagnostics(uri, None).await;
+            }
+            progress.end().await;
+            server.watch_files().await;
+            for root in roots {
+                server.publish_vault_diagnostics(&root).await;
+            }
+        });
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        // Clients usually send every setting, often under the server's section.
+        let settings = match params.settings {
+            Value::Object(mut settings) if settings.contains_key("noteLs") => {
+                settings.remove("noteLs").unwrap_or_default()
+            }
+            settings => settings,
+        };
+        // A profile chosen with `noteLs.setProfile` lasts until the `profile` setting changes, or
+        // the profile is gone.
+        let mut chosen = self.profile.lock().await.clone();
+        let profiles = settings.get("profiles");
+        let gone = |name: &String| profiles.and_then(|profiles| profiles.get(name)).is_none();
+        if self.settings.lock().await.get("profile") != settings.get("profile")
+            || chosen.as_ref().is_some_and(gone)
+        {
+            chosen = None;
+        }
+        let config = match Config::from_settings(&settings, chosen.as_deref()) {
+            Ok(config) => config,
+            Err(e) => {
+                logging::log(
+                    &self.client,
+                    MessageType::ERROR,
+                    format!("Invalid settings: {e}"),
+                )
+                .await;
+                return;
+            }
+        };
+
+        *self.profile.lock().await = chosen;
+        *self.settings.lock().await = settings;
+        self.set_config(config).await;
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        self.exit().await;
+        Ok(())
+    }
+
+    async fn did_open(&self, request: DidOpenTextDocumentParams) {
+        let mut state = self.files.write().await;
+        state.add_file(
+            request.text_document.uri.clone(),
+            File::new(request.text_document.text.clone()),
+        );
+        drop(state);
+
+        if let Some(path) = uri::to_path(&request.text_document.uri) {
+            let note = Note::parse(&request.text_document.text);
+            let mut changes = IndexChangedParams::default();
+            {
+                let mut vaults = self.vaults.write().await;
+                let index = vaults.index_for_mut(&path);
+                changes.note_updated(index, &path, Some(&note));
+                index.update_note(path, note);
+            }
+            self.index_changed(changes).await;
+        }
+        self.publish_diagnostics(
+            request.text_document.uri.clone(),
+            Some(request.text_document.version),
+        )
+        .await;
+        self.send_stats(
+            request.text_document.uri.clone(),
+            &request.text_document.text,
+        )
+        .await;
+
+        *self.current_file.write().await = Some(request.text_document.uri.clone());
+
+        self.preview_in_background(
+            request.text_document.uri,
+            request.text_document.text,
+            None,
+            Duration::ZERO,
+        );
+    }
+
+    async fn did_change(&self, request: DidChangeTextDocumentParams) {
+        let encoding = *self.encoding.lock().await;
+        let delay = Duration::from_millis(self.config.lock().await.preview_delay_ms);
+        let mut state = self.files.write().await;
+        let Some(file) = state.get_file_mut(&request.text_document.uri) else {
+            return;
+        };
+        let edited_line = file.update(request.content_changes, encoding);
+        let new_content = file.content.clone();
+        // Update preview in browser once typing pauses, following the edit. It's queued while
+        // the note is locked, so edits reach the preview in the order they're made.
+        self.preview_in_background(
+            request.text_document.uri.clone(),
+            new_content.clone(),
+            edited_line,
+            delay,
+        );
+        drop(state);
+
+        // Diagnostics are published once the note is indexed again.
+        if let Some(path) = uri::to_path(&request.text_document.uri) {
+            self.reindex(path, Change::Edited(new_content.clone()))
+                .await;
+        }
+        self.send_stats(request.text_document.uri.clone(), &new_content)
+            .await;
+
+        *self.current_file.write().await = Some(request.text_document.uri);
+    }
+
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        if let Some(path) = uri::to_path(&params.text_document.uri) {
+            self.reindex(path.clone(), Change::Changed).await;
+            self.run_hook(Event::Saved, &path, None).await;
+        }
+        if self.config.lock().await.check_external_links {
+            self.check_links_in_background(params.text_document.uri, false);
+        }
+    }
+
+    async fn did_close(&self, request: DidCloseTextDocumentParams) {
+        let mut state = self.files.write().await;
+        state.remove_file(&request.text_document.uri);
+        drop(state);
+
+        // Forget unsaved edits.
+        if let Some(path) = uri::to_path(&request.text_document.uri) {
+            self.reindex(path, Change::Changed).await;
+        }
+
+        // Close the note's own previews; the default one keeps showing it until another note is
+        // edited.
+        if let Some(preview_server) = self.preview_server.lock().await.as_mut() {
+            preview_server.close_channel(request.text_document.uri.as_str());
+        }
+    }
+
+    async fn completion(&self, request: CompletionParams) -> Result<Option<CompletionResponse>> {
+        // Get current location in file
+        let uri = &request.text_document_position.text_document.uri;
+        let encoding = *self.encoding.lock().await;
+        let (content, pos, in_code) = {
+            let files = self.files.read().await;
+            let file = files
+                .get_file(uri)
+                .ok_or(Error::new(ErrorCode::InvalidParams))?;
+            let pos = encoding.to_server(&file.content, request.text_document_position.position);
+            (file.content.clone(), pos, file.syntax.in_code(pos))
+        };
+
+        let current_word =
+            get_current_word(&content, pos).ok_or(Error::new(ErrorCode::InvalidParams))?;
+
+        tracing::debug!(current_word, ?pos, "completing");
+
+        let (note_types, bibliography, date_format) = {
+            let config = self.config.lock().await;
+            (
+                config.note_types.clone(),
+                config.bibliography.clone(),
+                config.date_format.clone(),
+            )
+        };
+
+        // Headings of the linked note after `[[note#` or `](note.md#`.
+        let line = content.lines().nth(pos.line as usize).unwrap_or("");
+        let open_link = completion::open_wiki_link(line, pos.character as usize);
+        let open_markdown_link = completion::open_markdown_link(line, pos.character as usize);
+        let anchor = match (open_link, open_markdown_link) {
+            (Some(inner), _) => inner
+                .split_once('#')
+                .filter(|(_, anchor)| !anchor.contains('|'))
+                .map(|(target, anchor)| (target, anchor, LinkKind::Wiki)),
+            (None, Some(inner)) => inner
+                .split_once('#')
+                .map(|(target, anchor)| (target, anchor, LinkKind::Markdown)),
+            (None, None) => None,
+        };
+        // Blocks of the linked note after `[[note#^`, `](note.md#^`, or just `[[note^`.
+        let block = match anchor {
+            Some((target, anchor, kind)) => anchor
+                .strip_prefix('^')
+                .map(|typed| (target, typed, kind, true)),
+            None => open_link
+                .filter(|inner| !inner.contains('|'))
+                .and_then(|inner| inner.split_once('^'))
+                .map(|(target, typed)| (target, typed, LinkKind::Wiki, false)),
+        };
+        // Note completions are filtered by what's typed, so the editor must ask again as more is.
+        let mut is_incomplete = false;
+        let items = if let Some((_, range)) = completion::open_fence(&content, pos) {
+            let config = self.config.lock().await;
+            let runners = config
+                .code_runners
+                .iter()
+                .map(|runner| (runner.language.as_str(), "Runs with noteLs.runCodeBlock"));
+            let tools = config
+                .diagram_tools
+                .iter()
+                .map(|tool| (tool.language.as_str(), "Diagram drawn in the preview"));
+            let others = runners.chain(tools).collect::<Vec<_>>();
+            Some(completion::fence_language_completions(range, &others))
+        } else if in_code {
+            // Nothing in code is a link, tag or citation.
+            None
+        } else if let Some((target, typed, kind, hash_typed)) = block {
+            let path = uri::to_path(uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+            let range = Range::new(
+                Position::new(pos.line, pos.character - typed.len() as u32 - 1),
+                pos,
+            );
+            Some(completion::block_completions(
+                &*self.index_for(&path).await,
+                &path,
+                target.trim(),
+                kind,
+                range,
+                hash_typed,
+            ))
+        } else if let Some((target, _, kind)) = anchor {
+            let path = uri::to_path(uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+            Some(completion::heading_completions(
+                &*self.index_for(&path).await,
+                &path,
+                target.trim(),
+                kind,
+            ))
+        } else if let Some((target, alias)) = open_link.and_then(|inner| inner.split_once('|')) {
+            // The alias after `[[note|`.
+            let path = uri::to_path(uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+            let range = Range::new(
+                Position::new(pos.line, pos.character - alias.len() as u32),
+                pos,
+            );
+            Some(completion::alias_completions(
+                &*self.index_for(&path).await,
+                &path,
+                target.trim(),
+                range,
+            ))
+        } else if let Some(typed) = open_markdown_link {
+            let path = uri::to_path(uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+            let style = self.config.lock().await.link_style;
+            let index = self.index_for(&path).await;
+            let items = self.completions.lock().await.note_completions(
+                &index,
+                &path,
+                style,
+                &links::decode_target(typed),
+            );
+            let range = Range::new(
+                Position::new(pos.line, pos.character - typed.len() as u32),
+                pos,
+            );
+            is_incomplete = true;
+            Some(completion::markdown_link_completions(items, range))
+        } else if current_word.starts_with("[[") && !current_word.ends_with(']') {
+            let current_path = self
+                .current_file
+                .read()
+                .await
+                .clone()
+                .ok_or(Error::new(ErrorCode::InternalError))?;
+            let path = uri::to_path(&current_path).ok_or(Error::new(ErrorCode::InternalError))?;
+            let style = self.config.lock().await.link_style;
+            let snippets = self
+                .client_capabilities
+                .lock()
+                .await
+                .text_document
+                .as_ref()
+                .and_then(|td| td.completion.as_ref())
+                .and_then(|completion| completion.completion_item.as_ref())
+                .and_then(|item| item.snippet_support)
+                .unwrap_or(false);
+            let index = self.index_for(&path).await;
+            is_incomplete = true;
+            let typed = open_link.unwrap_or("");
+            let items = self
+                .completions
+                .lock()
+                .await
+                .note_completions(&index, &path, style, typed);
+            let items = if snippets {
+                completion::with_alias_snippets(items)
+            } else {
+                items
+            };
+            let range = Range::new(
+                Position::new(pos.line, pos.character - typed.len() as u32),
+                pos,
+            );
+            let closed = line
+                .get(pos.character as usize..)
+                .is_some_and(|after| after.starts_with("]]"));
+            Some(completion::wiki_link_completions(items, range, closed))
+        } else if let Some(note_type) = note_types.iter().find(|note_type| {
+            note_type
+                .trigger
+                .as_deref()
+                .is_some_and(|trigger| !trigger.is_empty() && current_word.starts_with(trigger))
+        }) {
+            let range = Range::new(
+                Position::new(pos.line, pos.character - current_word.len() as u32),
+                pos,
+            );
+            let path = uri::to_path(uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+            Some(completion::typed_note_completions(
+                &*self.index_for(&path).await,
+                &path,
+                note_type,
+                range,
+            ))
+        } else if let Some((_, range)) =
+            citations::typed_key(&content, pos).filter(|_| !bibliography.is_empty())
+        {
+            let path = uri::to_path(uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+            let root = self.get_root(&path).await?;
+            let entries = citations::load(&root, &bibliography).await;
+            Some(citations::completions(&entries, range))
+        } else if current_word.starts_with('#') && !current_word.starts_with("##") {
+            let range = Range::new(
+                Position::new(pos.line, pos.character - current_word.len() as u32),
+                pos,
+            );
+            let path = uri::to_path(uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+            Some(completion::tag_completions(
+                &*self.index_for(&path).await,
+                range,
+            ))
+        } else {
+            None
+        };
+
+        // Dates typed in words may look like citation keys, so they're offered alongside them.
+        let dates = match in_code {
+            true => Vec::new(),
+            false => dates::completions(&content, pos, Date::today(), &date_format),
+        };
+        let items = match items {
+            Some(mut items) => {
+                items.extend(dates);
+                Some(items)
+            }
+            None if !dates.is_empty() => {
+                is_incomplete = true;
+                Some(dates)
+            }
+            None => None,
+        };
+
+        let params = json!({ "uri": uri, "position": pos, "text": content });
+        let contributed = self
+            .plugin_results::<Vec<CompletionItem>>(Feature::Completion, params)
+            .await
+            .into_iter()
+            .flat_map(|(_, items)| items)
+            .collect::<Vec<_>>();
+        let mut items = match items {
+            Some(mut items) => {
+                items.extend(contributed);
+                items
+            }
+            None if !contributed.is_empty() => contributed,
+            None => return Ok(None),
+        };
+        for item in &mut items {
+            match &mut item.text_edit {
+                Some(CompletionTextEdit::Edit(edit)) => {
+                    edit.range = encoding.range_to_client(&content, edit.range);
+                }
+                Some(CompletionTextEdit::InsertAndReplace(edit)) => {
+                    edit.insert = encoding.range_to_client(&content, edit.insert);
+                    edit.replace = encoding.range_to_client(&content, edit.replace);
+                }
+                None => {}
+            }
+            for edit in item.additional_text_edits.iter_mut().flatten() {
+                edit.range = encoding.range_to_client(&content, edit.range);
+            }
+        }
+
+        tracing::debug!(items = items.len(), is_incomplete, "completed");
+        Ok(Some(CompletionResponse::List(CompletionList {
+            is_incomplete,
+            items,
+        })))
+    }
+
+    async fn completion_resolve(&self, mut item: CompletionItem) -> Result<CompletionItem> {
+        let Some(path) = completion::completed_note(&item) else {
+            return Ok(item);
+        };
+        // Prefer unsaved content if the note is open.
+        let open = match uri::from_path(&path) {
+            Some(uri) => self
+                .files
+                .read()
+                .await
+                .get_file(&uri)
+                .map(|file| file.content.clone()),
+            None => None,
+        };
+        let content = match open {
+            Some(content) => content,
+            None => match self.read_note(&path).await {
+                Ok(content) => content,
+                Err(_) => return Ok(item),
+            },
+        };
+
+        let markdown = self
+            .client_capabilities
+            .lock()
+            .await
+            .text_document
+            .as_ref()
+            .and_then(|td| td.completion.as_ref())
+            .and_then(|completion| completion.completion_item.as_ref())
+            .and_then(|item| item.documentation_format.as_ref())
+            .is_some_and(|formats| formats.contains(&MarkupKind::Markdown));
+        item.documentation = Some(completion::note_documentation(&path, &content, markdown));
+        Ok(item)
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let content = self
+            .files
+            .read()
+            .await
+            .get_file(&uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?
+            .content
+            .clone();
+        let encoding = *self.encoding.lock().await;
+        let pos = encoding.to_server(&content, params.text_document_position_params.position);
+
+        let hover = self.hover_at(uri, pos).await?;
+        Ok(hover.map(|mut hover| {
+            hover.range = hover
+                .range
+                .map(|range| encoding.range_to_client(&content, range));
+            hover
+        }))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = params.text_document.uri;
+
+        let state = self.files.read().await;
+        let file = state
+            .get_file(&uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let encoding = *self.encoding.lock().await;
+        let range = encoding.range_to_server(&file.content, params.range);
+
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let root = self.get_root(&path).await?;
+        let config = self.config.lock().await.clone();
+        let index = self.index_for(&path).await;
+        let template = config
+            .note_template
+            .and_then(|template| std::fs::read_to_string(root.join(template)).ok());
+
+        let note = Note::parse(&file.content);
+        let folder = new_notes::folder(
+            &config.new_note_location,
+            &root,
+            Some(&path),
+            Some(&note),
+            Date::today(),
+        );
+
+        let mut actions = Vec::new();
+        for link in links::parse_links(&comments::blank(&file.content))
+            .iter()
+            .filter(|link| range.start.line <= link.line && link.line <= range.end.line)
+        {
+            actions.extend(code_actions::encode_spaces(&uri, link));
+            if config.alt_text_diagnostics && diagnostics::lacks_alt_text(link) {
+                let range = encoding.range_to_client(&file.content, link.range());
+                actions.push(code_actions::add_alt_text(&uri, range));
+            }
+            if config.check_link_case {
+                if let Some(target) = diagnostics::case_correction(&path, link, &index) {
+                    actions.push(code_actions::fix_case(&uri, link, &target));
+                }
+            }
+
+            let on_link = range.start.line == link.line
+                && link.start as u32 <= range.start.character
+                && range.start.character <= link.end as u32;
+            if on_link {
+                let new = match link.kind {
+                    LinkKind::Wiki => link_style::to_markdown(link, &path, &note, &index),
+                    LinkKind::Markdown => link_style::to_wiki(link, &path, &note, &index),
+                };
+                if let Some(new) = new {
+                    actions.push(code_actions::convert_link(&uri, link, new));
+                    let style = match link.kind {
+                        LinkKind::Wiki => LinkKind::Markdown,
+                        LinkKind::Markdown => LinkKind::Wiki,
+                    };
+                    let edits = link_style::convert_all(&file.content, &path, &index, style);
+                    actions.extend(code_actions::convert_all_links(&uri, style, edits));
+                }
+            }
+            // Offer to create the note a broken link points to when the cursor is on it.
+            if on_link
+                && diagnostics::links_to_note(link, index.extensions())
+                && index.resolve(&path, link).is_none()
+            {
+                if let Some(new) = link.candidates(&folder, &root).first() {
+                    let date = Date::today();
+                    actions.extend(code_actions::create_note(
+                        &uri,
+                        link,
+                        new,
+                        &root,
+                        template.as_deref(),
+                        date,
+                    ));
+                    let templates = match &config.templates_folder {
+                        Some(templates) => {
+                            templates::load(&root.join(templates), &config.note_extensions)
+                        }
+                        None => Vec::new(),
+                    };
+                    for template in &templates {
+                        actions.extend(code_actions::create_note_from(
+                            &uri, link, new, &root, template, date,
+                        ));
+                    }
+                }
+            }
+        }
+        if config.heading_order_diagnostics {
+            for (heading, level) in diagnostics::skipped_levels(&note.headings)
+                .filter(|(heading, _)| (range.start.line..=range.end.line).contains(&heading.line))
+            {
+                actions.extend(code_actions::fix_heading_level(
+                    &uri,
+                    &file.content,
+                    heading.line,
+                    level,
+                ));
+            }
+        }
+        for diagnostic in &params.context.diagnostics {
+            if let Some(word) = diagnostics::unknown_word(diagnostic) {
+                actions.push(code_actions::add_to_dictionary(&uri, word, diagnostic));
+            }
+        }
+        // A table of contents is offered on blank lines, and kept up to date from anywhere.
+        let blank = file
+            .content
+            .lines()
+            .nth(range.start.line as usize)
+            .is_none_or(|line| line.trim().is_empty());
+        if blank || toc::block(&file.content).is_some() {
+            actions.extend(code_actions::update_toc(
+                &uri,
+                &file.content,
+                range.start.line,
+            ));
+        }
+        if let Some(task) = tasks::task_at(&file.content, range.start.line) {
+            actions.push(code_actions::toggle_task(&uri, &file.content, &task));
+        }
+        actions.extend(code_actions::add_block_id(
+            &uri,
+            &file.content,
+            range.start.line,
+        ));
+        if let Some(block) = code_blocks::block_at(&file.content, range.start.line) {
+            if config
+                .code_runners
+                .iter()
+                .any(|runner| runner.language == block.language)
+            {
+                actions.push(code_actions::run_code_block(&uri, &block));
+            }
+        }
+        if range.start != range.end {
+            actions.extend(code_actions::toggle_comment(&uri, &file.content, range));
+            actions.extend(code_actions::extract_selection(
+                &uri,
+                &file.content,
+                range,
+                &folder,
+                index.extensions(),
+            ));
+            // Commands are given ranges as the editor counts them.
+            actions.push(code_actions::extract_to_note(&uri, params.range));
+        }
+        drop(index);
+        drop(state);
+        for action in &mut actions {
+            if let Some(edit) = &mut action.edit {
+                self.edit_to_client(edit).await;
+            }
+        }
+        let actions = actions
+            .into_iter()
+            .map(CodeActionOrCommand::CodeAction)
+            .collect::<Vec<_>>();
+
+        Ok((!actions.is_empty()).then_some(actions))
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        let state = self.files.read().await;
+        let file = state
+            .get_file(&params.text_document.uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?;
+
+        let encoding = *self.encoding.lock().await;
+        let mut outline = symbols::outline(&file.content, &file.syntax);
+        symbols_to_client(encoding, &file.content, &mut outline);
+        Ok(Some(DocumentSymbolResponse::Nested(outline)))
+    }
+
+    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+        let state = self.files.read().await;
+        let file = state
+            .get_file(&params.text_document.uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?;
+
+        Ok(Some(folding::folding_ranges(&file.content, &file.syntax)))
+    }
+
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult> {
+        let diagnostics = self
+            .note_diagnostics(&params.text_document.uri)
+            .await
+            .unwrap_or_default();
+        let report = match diagnostics::report(diagnostics, params.previous_result_id.as_deref()) {
+            DocumentDiagnosticReportKind::Full(report) => {
+                DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                    related_documents: None,
+                    full_document_diagnostic_report: report,
+                })
+            }
+            DocumentDiagnosticReportKind::Unchanged(report) => {
+                DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                    related_documents: None,
+                    unchanged_document_diagnostic_report: report,
+                })
+            }
+        };
+        Ok(report.into())
+    }
+
+    /// The diagnostics of the notes that aren't open, up to `vaultDiagnosticsLimit` of them. Open
+    /// notes have theirs pulled one by one.
+    async fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> Result<WorkspaceDiagnosticReportResult> {
+        let config = self.config.lock().await.clone();
+        let mut paths = match config.diagnostics {
+            true => self
+                .vaults
+                .read()
+                .await
+                .iter()
+                .filter(|vault| vault.indexed)
+                .flat_map(|vault| vault.index.notes().map(|(path, _)| path.to_path_buf()))
+                .collect::<Vec<_>>(),
+            false => Vec::new(),
+        };
+        paths.sort();
+        let previous = params
+            .previous_result_ids
+            .into_iter()
+            .map(|previous| (previous.uri, previous.value))
+            .collect::<HashMap<_, _>>();
+
+        let mut items = Vec::new();
+        for path in paths.iter().take(config.vault_diagnostics_limit) {
+            let Some(uri) = uri::from_path(path) else {
+                continue;
+            };
+            if self.files.read().await.get_file(&uri).is_some() {
+                continue;
+            }
+            let Some(diagnostics) = self.note_diagnostics(&uri).await else {
+                continue;
+            };
+            let previous = previous.get(&uri).map(String::as_str);
+            items.push(match diagnostics::report(diagnostics, previous) {
+                DocumentDiagnosticReportKind::Full(report) => {
+                    WorkspaceDocumentDiagnosticReport::Full(WorkspaceFullDocumentDiagnosticReport {
+                        uri,
+                        version: None,
+                        full_document_diagnostic_report: report,
+                    })
+                }
+                DocumentDiagnosticReportKind::Unchanged(report) => {
+                    WorkspaceDocumentDiagnosticReport::Unchanged(
+                        WorkspaceUnchangedDocumentDiagnosticReport {
+                            uri,
+                            version: None,
+                            unchanged_document_diagnostic_report: report,
+                        },
+                    )
+                }
+            });
+        }
+        Ok(WorkspaceDiagnosticReport { items }.into())
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>> {
+        let state = self.files.read().await;
+        let file = state
+            .get_file(&params.text_document.uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let encoding = *self.encoding.lock().await;
+        let data = semantic_tokens::semantic_tokens(&file.content, None, encoding);
+        Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: None,
+            data,
+        })))
+    }
+
+    async fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> Result<Option<SemanticTokensRangeResult>> {
+        let state = self.files.read().await;
+        let file = state
+            .get_file(&params.text_document.uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let encoding = *self.encoding.lock().await;
+        let data = semantic_tokens::semantic_tokens(&file.content, Some(params.range), encoding);
+        Ok(Some(SemanticTokensRangeResult::Tokens(SemanticTokens {
+            result_id: None,
+            data,
+        })))
+    }
+
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let state = self.files.read().await;
+        let file = state
+            .get_file(&params.text_document.uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let encoding = *self.encoding.lock().await;
+        Ok(Some(edits_to_client(
+            encoding,
+            &file.content,
+            tables::format(&file.content, None),
+        )))
+    }
+
+    async fn range_formatting(
+        &self,
+        params: DocumentRangeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let state = self.files.read().await;
+        let file = state
+            .get_file(&params.text_document.uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let encoding = *self.encoding.lock().await;
+        let range = encoding.range_to_server(&file.content, params.range);
+        Ok(Some(edits_to_client(
+            encoding,
+            &file.content,
+            tables::format(&file.content, Some(range)),
+        )))
+    }
+
+    async fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        if params.ch != "\n" {
+            return Ok(None);
+        }
+        let uri = params.text_document_position.text_document.uri;
+        let state = self.files.read().await;
+        let file = state
+            .get_file(&uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let encoding = *self.encoding.lock().await;
+        let position = encoding.to_server(&file.content, params.text_document_position.position);
+        let edits = lists::on_enter(&file.content, position);
+        Ok((!edits.is_empty()).then(|| edits_to_client(encoding, &file.content, edits)))
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        let uri = params.text_document.uri;
+        let Some(path) = uri::to_path(&uri) else {
+            return Ok(None);
+        };
+        // Headings come from what's in the editor, which the index may not have caught up with.
+        let note = match self.files.read().await.get_file(&uri) {
+            Some(file) => Note::parse(&file.content),
+            None => return Ok(None),
+        };
+        let headings = self.config.lock().await.heading_lenses;
+        let messages = self.messages.lock().await.clone();
+        let index = self.index_for(&path).await;
+        let encoding = *self.encoding.lock().await;
+        let sources = index
+            .backlinks(&path)
+            .into_iter()
+            .filter_map(|(source, _)| uri::from_path(source))
+            .collect::<Vec<_>>();
+        let documents = self.client_documents(sources).await;
+        let to_client = |mut location: Location| {
+            if let Some(document) = documents.get(&location.uri) {
+                location.range = encoding.range_to_client(document, location.range);
+            }
+            location
+        };
+        Ok(Some(code_lens::backlink_lenses(
+            &path, &note, &index, headings, &messages, to_client,
+        )))
+    }
+
+    async fn document_link(&self, params: DocumentLinkParams) -> Result<Option<Vec<DocumentLink>>> {
+        let uri = params.text_document.uri;
+        let state = self.files.read().await;
+        let Some(file) = state.get_file(&uri) else {
+            return Ok(None);
+        };
+        let encoding = *self.encoding.lock().await;
+        let mut links = document_links::document_links(&uri, &file.content);
+        for link in &mut links {
+            link.range = encoding.range_to_client(&file.content, link.range);
+        }
+        Ok(Some(links))
+    }
+
+    async fn document_link_resolve(&self, mut link: DocumentLink) -> Result<DocumentLink> {
+        let Some(source) = document_links::source(&link) else {
+            return Ok(link);
+        };
+        let Some(path) = uri::to_path(&source) else {
+            return Ok(link);
+        };
+        let open = self
+            .files
+            .read()
+            .await
+            .get_file(&source)
+            .map(|file| file.content.clone());
+        let document = match open {
+            Some(content) => content,
+            None => self.read_note(&path).await.map_err(internal_error)?,
+        };
+
+        let start = self
+            .encoding
+            .lock()
+            .await
+            .to_server(&document, link.range.start);
+        if let Some(found) = links::link_at(&document, start) {
+            let index = self.index_for(&path).await;
+            link.target = document_links::resolve(&found, &path, &index);
+        }
+        Ok(link)
+    }
+
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let uri = params.text_document_position.text_document.uri;
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+
+        let state = self.files.read().await;
+        let file = state
+            .get_file(&uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let pos = self
+            .encoding
+            .lock()
+            .await
+            .to_server(&file.content, params.text_document_position.position);
+        let index = self.index_for(&path).await;
+
+        let locations = match tags::tag_at(&file.content, pos) {
+            // Every use of the tag under the cursor.
+            Some(tag) => index
+                .tag_occurrences(&tag.name)
+                .into_iter()
+                .filter_map(|(source, tag)| {
+                    Some(Location::new(uri::from_path(source)?, tag.range()))
+                })
+                .collect::<Vec<_>>(),
+            None => {
+                // Backlinks of the note under the cursor, or of the current note if there isn't
+                // one.
+                let target = links::link_at(&file.content, pos)
+                    .and_then(|link| index.resolve(&path, &link))
+                    .unwrap_or(path);
+                index
+                    .backlinks(&target)
+                    .into_iter()
+                    .filter_map(|(source, link)| {
+                        Some(Location::new(uri::from_path(source)?, link.range()))
+                    })
+                    .collect()
+            }
+        };
+        drop(index);
+        drop(state);
+
+        Ok(Some(self.locations_to_client(locations).await))
+    }
+
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> Result<Option<Vec<CallHierarchyItem>>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+
+        let state = self.files.read().await;
+        let file = state
+            .get_file(&uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let pos = self
+            .encoding
+            .lock()
+            .await
+            .to_server(&file.content, params.text_document_position_params.position);
+        let index = self.index_for(&path).await;
+
+        // The note under the cursor, or the current note if there isn't one.
+        let target = links::link_at(&file.content, pos)
+            .and_then(|link| index.resolve(&path, &link))
+            .unwrap_or(path);
+        Ok(call_hierarchy::item(&index, &target).map(|item| vec![item]))
+    }
+
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        let path = uri::to_path(&params.item.uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let mut calls = call_hierarchy::incoming_calls(&*self.index_for(&path).await, &path);
+        // The ranges are those of the links in the notes the calls come from.
+        let encoding = *self.encoding.lock().await;
+        let documents = self
+            .client_documents(calls.iter().map(|call| call.from.uri.clone()).collect())
+            .await;
+        for call in &mut calls {
+            if let Some(document) = documents.get(&call.from.uri) {
+                for range in &mut call.from_ranges {
+                    *range = encoding.range_to_client(document, *range);
+                }
+            }
+        }
+        Ok(Some(calls))
+    }
+
+    async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        let path = uri::to_path(&params.item.uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let mut calls = call_hierarchy::outgoing_calls(&*self.index_for(&path).await, &path);
+        // The ranges are those of the links in the note the calls go out from.
+        let encoding = *self.encoding.lock().await;
+        let documents = self.client_documents(vec![params.item.uri.clone()]).await;
+        if let Some(document) = documents.get(&params.item.uri) {
+            for range in calls.iter_mut().flat_map(|call| &mut call.from_ranges) {
+                *range = encoding.range_to_client(document, *range);
+            }
+        }
+        Ok(Some(calls))
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let config = self.config.lock().await.clone();
+        if config.section_hint_level == 0 {
+            return Ok(None);
+        }
+        let messages = self.messages.lock().await.clone();
+        let state = self.files.read().await;
+        let file = state
+            .get_file(&params.text_document.uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let encoding = *self.encoding.lock().await;
+        let mut hints = inlay_hints::section_hints(
+            &file.content,
+            params.range,
+            config.section_hint_level,
+            config.reading_speed,
+            &messages,
+        );
+        for hint in &mut hints {
+            hint.position = encoding.to_client(&file.content, hint.position);
+        }
+        Ok(Some(hints))
+    }
+
+    async fn document_highlight(
+        &self,
+        params: DocumentHighlightParams,
+    ) -> Result<Option<Vec<DocumentHighlight>>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+
+        let state = self.files.read().await;
+        let file = state
+            .get_file(&uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let encoding = *self.encoding.lock().await;
+        let position =
+            encoding.to_server(&file.content, params.text_document_position_params.position);
+        let index = self.index_for(&path).await;
+        let note = Note::parse(&file.content);
+
+        // Links are the same if they find the same note, or name the same one if neither does.
+        let resolve = |link: &Link| {
+            index
+                .resolve(&path, link)
+                .map(|path| links::nfc_path(&path))
+        };
+        let same_target = |link: &Link, other: &Link| match (resolve(link), resolve(other)) {
+            (Some(target), Some(other_target)) => target == other_target,
+            (None, None) => link.decoded_path() == other.decoded_path(),
+            _ => false,
+        };
+        let highlights = highlights::highlights(&note, position, same_target);
+        Ok(highlights.map(|highlights| {
+            highlights
+                .into_iter()
+                .map(|highlight| DocumentHighlight {
+                    range: encoding.range_to_client(&file.content, highlight.range),
+                    ..highlight
+                })
+                .collect()
+        }))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let state = self.files.read().await;
+        let file = state
+            .get_file(&params.text_document.uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?;
+
+        // Only headings can be renamed.
+        let encoding = *self.encoding.lock().await;
+        let position = encoding.to_server(&file.content, params.position);
+        Ok(
+            rename::heading_at(&file.content, position).map(|(heading, range)| {
+                PrepareRenameResponse::RangeWithPlaceholder {
+                    range: encoding.range_to_client(&file.content, range),
+                    placeholder: heading.text,
+                }
+            }),
+        )
+    }
+
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let uri = params.text_document_position.text_document.uri;
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let new_name = params.new_name.trim();
+        if new_name.is_empty() {
+            return Err(Error::invalid_params("headings can't be empty"));
+        }
+
+        let state = self.files.read().await;
+        let file = state
+            .get_file(&uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?;
+        let pos = self
+            .encoding
+            .lock()
+            .await
+            .to_server(&file.content, params.text_document_position.position);
+        let Some((heading, range)) = rename::heading_at(&file.content, pos) else {
+            return Ok(None);
+        };
+
+        // Rewrite the anchors of links to the heading along with the heading itself.
+        let index = self.index_for(&path).await;
+        let mut changes = HashMap::new();
+        for (from, edits) in
+            rename::relink_heading(&index, &path, &file.content, &heading, new_name)
+        {
+            if let Some(from) = uri::from_path(&from) {
+                changes.insert(from, edits);
+            }
+        }
+        changes
+            .entry(uri)
+            .or_insert_with(Vec::new)
+            .push(TextEdit::new(range, new_name.to_string()));
+        drop(index);
+        drop(state);
+        let mut edit = WorkspaceEdit {
+            changes: Some(changes),
+            ..WorkspaceEdit::default()
+        };
+        self.edit_to_client(&mut edit).await;
+        Ok(Some(edit))
+    }
+
+    async fn will_rename_files(&self, params: RenameFilesParams) -> Result<Option<WorkspaceEdit>> {
+        let policy = self.config.lock().await.attachments_policy;
+        let renames = file_renames(&params.files);
+        let vaults = self.vaults.read().await;
+
+        let mut edits = HashMap::new();
+        for index in vaults.indexes() {
+            edits.extend(rename::relink_notes(index, &renames));
+        }
+        let mut moves = Vec::new();
+        for index in vaults.indexes() {
+            for (old, note) in index.notes() {
+                let Some(new) = renames.map(old) else {
+                    continue;
+                };
+                let change = rename::move_attachments(
+                    old,
+                    &new,
+                    &note.links,
+                    &renames,
+                    policy,
+                    index.extensions(),
+                );
+                if !change.edits.is_empty() {
+                    edits
+                        .entry(old.to_path_buf())
+                        .or_default()
+                        .extend(change.edits);
+                }
+                moves.extend(change.moves);
+            }
+        }
+
+        // Edits are applied before the renames, so they target the old locations.
+        let mut edits = edits.into_iter().collect::<Vec<_>>();
+        edits.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut operations = Vec::new();
+        for (path, edits) in edits {
+            operations.push(DocumentChangeOperation::Edit(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier {
+                    uri: uri::from_path(&path).ok_or_else(Error::internal_error)?,
+                    version: None,
+                },
+                edits: edits.into_iter().map(OneOf::Left).collect(),
+            }));
+        }
+        for (from, to) in moves {
+            let (Some(old_uri), Some(new_uri)) = (uri::from_path(&from), uri::from_path(&to))
+            else {
+                continue;
+            };
+            operations.push(DocumentChangeOperation::Op(ResourceOp::Rename(
+                RenameFile {
+                    old_uri,
+                    new_uri,
+                    options: None,
+                    annotation_id: None,
+                },
+            )));
+        }
+
+        if operations.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Operations(operations)),
+            ..WorkspaceEdit::default()
+        }))
+    }
+
+    async fn did_rename_files(&self, params: RenameFilesParams) {
+        let renames = file_renames(&params.files);
+        let mut changes = IndexChangedParams::default();
+        {
+            let mut vaults = self.vaults.write().await;
+            for index in vaults.indexes_mut() {
+                changes.notes_renamed(index, &renames);
+                index.rename(&renames);
+            }
+        }
+        self.index_changed(changes).await;
+
+        let renamed = {
+            let vaults = self.vaults.read().await;
+            renames
+                .iter()
+                .filter(|(_, new)| vaults.index_for(new).is_note(new))
+                .map(|(old, new)| (old.to_path_buf(), new.to_path_buf()))
+                .collect::<Vec<_>>()
+        };
+        for (old, new) in renamed {
+            self.run_hook(Event::Renamed, &new, Some(&old)).await;
+        }
+    }
+
+    async fn did_create_files(&self, params: CreateFilesParams) {
+        let created = {
+            let vaults = self.vaults.read().await;
+            params
+                .files
+                .iter()
+                .filter_map(|file| uri::to_path(&Url::parse(&file.uri).ok()?))
+                .filter(|path| vaults.index_for(path).is_note(path))
+                .collect::<Vec<_>>()
+        };
+        for path in created {
+            self.run_hook(Event::Created, &path, None).await;
+        }
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        let old_preview_roots = self.vaults.read().await.preview_roots();
+        for folder in params.event.removed {
+            let Some(root) = uri::to_path(&folder.uri) else {
+                continue;
+            };
+            let removed = self.vaults.write().await.remove(&root);
+            if let Some(vault) = removed {
+                self.clear_diagnostics(note_uris(&vault.index)).await;
+            }
+        }
+        let mut added = Vec::new();
+        for folder in params.event.added {
+            if let Some(root) = uri::to_path(&folder.uri) {
+                self.add_vault(&root).await;
+                added.push(root);
+            }
+        }
+        let progress = self.indexing_progress().await;
+        self.index_vaults(&added, &progress).await;
+        progress.end().await;
+
+        // Open notes may have moved to another vault.
+        let open = self.files.read().await.open_contents();
+        {
+            let mut vaults = self.vaults.write().await;
+            for (uri, content) in &open {
+                let Some(path) = uri::to_path(uri) else {
+                    continue;
+                };
+                for index in vaults.indexes_mut() {
+                    index.remove(&path);
+                }
+                vaults.index_for_mut(&path).update(path, content);
+            }
+        }
+
+        if let Some(preview_server) = self.preview_server.lock().await.as_mut() {
+            for (prefix, _) in old_preview_roots {
+                preview_server.remove_static_root(&prefix);
+            }
+            for (prefix, root) in self.vaults.read().await.preview_roots() {
+                preview_server.add_static_root(&prefix, root);
+            }
+        }
+        self.index_changed(IndexChangedParams {
+            reindexed: true,
+            ..IndexChangedParams::default()
+        })
+        .await;
+        for (uri, _) in open {
+            self.publish_diagnostics(uri, None).await;
+        }
+        for root in added {
+            self.publish_vault_diagnostics(&root).await;
+        }
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let open = self.files.read().await.uris();
+        // Queueing never waits, so holding the lock is fine.
+        let queue = self.reindex.lock().await;
+        let Some(queue) = queue.as_ref() else {
+            return;
+        };
+        for change in params.changes {
+            let Some(path) = uri::to_path(&change.uri) else {
+                continue;
+            };
+            if change.typ == FileChangeType::DELETED {
+                queue.push(path, Change::Deleted);
+            } else if !open.contains(&change.uri) {
+                // Open documents are kept in sync by the editor, which knows better than the disk.
+                queue.push(path, Change::Changed);
+            }
+        }
+    }
+
+    async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
+        match params.command.as_str() {
+            commands::FIND_UNUSED_ATTACHMENTS => {
+                let args: commands::FindUnusedAttachmentsArgs =
+                    commands::parse_args(params.arguments)?;
+                let root = self.command_root(args.uri).await?;
+
+                // Unsaved changes to open notes count too.
+                let open = self
+                    .files
+                    .read()
+                    .await
+                    .open_contents()
+                    .into_iter()
+                    .filter_map(|(uri, content)| Some((uri::to_path(&uri)?, content)))
+                    .collect::<HashMap<_, _>>();
+                let folder = self.config.lock().await.attachments_folder.clone();
+                let unused =
+                    attachments::find_unused(&*self.index_for(&root).await, &open, &folder);
+                if args.move_to_trash {
+                    attachments::move_to_trash(&root, &unused).map_err(internal_error)?;
+                }
+
+                let uris = unused
+                    .iter()
+                    .filter_map(|path| uri::from_path(path))
+                    .collect::<Vec<_>>();
+                Ok(Some(json!(uris)))
+            }
+            commands::BUNDLE_NOTE => {
+                let args: commands::BundleNoteArgs = commands::parse_args(params.arguments)?;
+                self.bundle_note(args).await
+            }
+            commands::EXPORT_HTML | commands::EXPORT_PDF => {
+                let args: commands::ExportArgs = commands::parse_args(params.arguments)?;
+                self.export_note(args, params.command == commands::EXPORT_PDF)
+                    .await
+            }
+            commands::FLATTEN_NOTE => {
+                let args: commands::FlattenNoteArgs = commands::parse_args(params.arguments)?;
+                self.flatten_note(args).await
+            }
+            commands::TOGGLE_HABIT => {
+                let args: commands::ToggleHabitArgs = commands::parse_args(params.arguments)?;
+                self.toggle_habit(args).await
+            }
+            commands::INIT_VAULT => {
+                let args: commands::InitVaultArgs = commands::parse_args(params.arguments)?;
+                self.init_vault(args).await
+            }
+            commands::INSERT_EXCERPT => {
+                let args: commands::InsertExcerptArgs = commands::parse_args(params.arguments)?;
+                self.insert_excerpt(args).await
+            }
+            commands::NEW_NOTE => {
+                let args: commands::NewNoteArgs = commands::parse_args(params.arguments)?;
+                self.new_note(args).await
+            }
+            commands::TOC_UPDATE => {
+                let args: commands::TocUpdateArgs = commands::parse_args(params.arguments)?;
+                self.update_toc(args).await
+            }
+            commands::EXTRACT_TO_NOTE => {
+                let args: commands::ExtractToNoteArgs = commands::parse_args(params.arguments)?;
+                self.extract_to_note(args).await
+            }
+            commands::SET_ALT_TEXT => {
+                let args: commands::SetAltTextArgs = commands::parse_args(params.arguments)?;
+                self.set_alt_text(args).await
+            }
+            commands::DAILY_NOTE_OPEN => {
+                let args: commands::DailyNoteArgs = commands::parse_args(params.arguments)?;
+                self.open_daily_note(args).await
+            }
+            commands::DAILY_NOTE_PREVIOUS => {
+                let args: commands::DailyNoteArgs = commands::parse_args(params.arguments)?;
+                self.adjacent_daily_note(args, false).await
+            }
+            commands::DAILY_NOTE_NEXT => {
+                let args: commands::DailyNoteArgs = commands::parse_args(params.arguments)?;
+                self.adjacent_daily_note(args, true).await
+            }
+            commands::UPDATE_READING_PROGRESS => {
+                let args: commands::UpdateReadingProgressArgs =
+                    commands::parse_args(params.arguments)?;
+                self.update_reading_progress(args).await
+            }
+            commands::LINK_REPORT => {
+                let args: commands::LinkReportArgs = commands::parse_args(params.arguments)?;
+                let root = self.command_root(args.uri).await?;
+                let (check_external, allowed) = {
+                    let config = self.config.lock().await;
+                    (
+                        config.check_external_links,
+                        config.external_link_allowlist.clone(),
+                    )
+                };
+                let dead = match check_external {
+                    true => {
+                        let urls = link_checker::vault_urls_to_check(
+                            &*self.index_for(&root).await,
+                            &allowed,
+                        );
+                        self.link_checker.broken_among(urls).await
+                    }
+                    false => HashSet::new(),
+                };
+                let report = report::link_report(&*self.index_for(&root).await, &dead);
+                Ok(Some(json!(report)))
+            }
+            commands::LINKS_CHECK => {
+                let args: commands::LinksCheckArgs = commands::parse_args(params.arguments)?;
+                let uri = match args.uri {
+                    Some(uri) => uri,
+                    None => self
+                        .current_file
+                        .read()
+                        .await
+                        .clone()
+                        .ok_or_else(|| Error::invalid_params("no note to check"))?,
+                };
+                self.check_links_in_background(uri, true);
+                Ok(None)
+            }
+            commands::ADD_TO_DICTIONARY => {
+                let args: commands::AddToDictionaryArgs = commands::parse_args(params.arguments)?;
+                self.add_to_dictionary(args).await
+            }
+            commands::BACKUP_VAULT => {
+                let args: commands::BackupVaultArgs = commands::parse_args(params.arguments)?;
+                self.backup_vault(args).await
+            }
+            commands::COMPARE_VAULTS => {
+                let args: commands::CompareVaultsArgs = commands::parse_args(params.arguments)?;
+                self.compare_vaults(args).await
+            }
+            commands::GRAPH_EXPORT => {
+                let args: commands::GraphExportArgs = commands::parse_args(params.arguments)?;
+                self.export_graph(args).await
+            }
+            commands::SEARCH => {
+                let args: commands::SearchArgs = commands::parse_args(params.arguments)?;
+                self.search(args).await
+            }
+            commands::REPORT_ORPHANS => {
+                let args: commands::ReportOrphansArgs = commands::parse_args(params.arguments)?;
+                let root = self.command_root(args.uri).await?;
+                let index = self.index_for(&root).await;
+                Ok(Some(json!(report::orphan_report(&index))))
+            }
+            commands::TOGGLE_TASK => {
+                let args: commands::ToggleTaskArgs = commands::parse_args(params.arguments)?;
+                self.toggle_task(args).await
+            }
+            commands::SET_PROFILE => {
+                let args: commands::SetProfileArgs = commands::parse_args(params.arguments)?;
+                self.set_profile(args).await
+            }
+            commands::TASKS_LIST => {
+                let args: commands::TasksListArgs = commands::parse_args(params.arguments)?;
+                self.list_tasks(args).await
+            }
+            commands::RECENT_NOTES => {
+                let args: commands::RecentNotesArgs = commands::parse_args(params.arguments)?;
+                self.recent_notes(args).await
+            }
+            commands::RUN_CODE_BLOCK => {
+                let args: commands::RunCodeBlockArgs = commands::parse_args(params.arguments)?;
+                self.run_code_block(args).await
+            }
+            commands::PUBLISH => self.publish(commands::parse_args(params.arguments)?).await,
+            commands::PREVIEW_OPEN | commands::PREVIEW_CLOSE | commands::PREVIEW_TOGGLE => {
+                let args: commands::PreviewArgs = commands::parse_args(params.arguments)?;
+                self.preview_command(&params.command, args.uri).await
+            }
+            command => match self
+                .plugins
+                .lock()
+                .await
+                .execute(command, params.arguments)
+                .await
+            {
+                Some(result) => result.map(Some).map_err(internal_error),
+                None => Err(Error::method_not_found()),
+            },
+        }
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let path = uri::to_path(&uri).ok_or(Error::new(ErrorCode::InvalidParams))?;
+
+        let content = self
+            .files
+            .read()
+            .await
+            .get_file(&uri)
+            .ok_or(Error::new(ErrorCode::InvalidParams))?
+            .content
+            .clone();
+        let encoding = *self.encoding.lock().await;
+        let pos = encoding.to_server(&content, params.text_document_position_params.position);
+
+        // A tag is "defined" by every note carrying it.
+        if let Some(tag) = tags::tag_at(&content, pos) {
+            let mut occurrences = self
+                .index_for(&path)
+                .await
+                .tag_occurrences(&tag.name)
+                .into_iter()
+                .map(|(source, tag)| (source.to_path_buf(), tag.range()))
+                .collect::<Vec<_>>();
+            occurrences.dedup_by(|a, b| a.0 == b.0);
+            let locations = occurrences
+                .into_iter()
+                .filter_map(|(source, range)| Some(Location::new(uri::from_path(&source)?, range)))
+                .collect();
+            return Ok(Some(GotoDefinitionResponse::Array(
+                self.locations_to_client(locations).await,
+            )));
+        }
+
+        let index = self.index_for(&path).await;
+        let Some(link) = links::link_at(&content, pos) else {
+            return Ok(None);
+        };
+
+        // Links like `[[#Heading]]` point into the current note.
+        let target = if link.target_path().is_empty() {
+            Some(path)
+        } else {
+            index.resolve(&path, &link)
+        };
+        let Some(target) = target else {
+            return Ok(None);
+        };
+
+        let line = index.anchor_line(&target, &link).unwrap_or(0);
+        let uri = uri::from_path(&target).ok_or(Error::new(ErrorCode::InternalError))?;
+        Ok(Some(GotoDefinitionResponse::Scalar(Location::new(
+            uri,
+            Range::new(Position::new(line, 0), Position::new(line, 0)),
+        ))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn changed_lines() {
+        assert_eq!(first_changed_line("a\nb\nc", "a\nB\nc"), Some(1));
+        assert_eq!(first_changed_line("a\nb", "a\nb\nc"), Some(2));
+        assert_eq!(first_changed_line("a\nb", "a\nb\n"), Some(1));
+        assert_eq!(first_changed_line("a\nb", "a\nb"), None);
+    }
+
+    #[test]
+    fn note_globs_cover_every_extension() {
+        assert_eq!(note_glob(&[String::from("md")]), "**/*.md");
+        assert_eq!(
+            note_glob(&[String::from("md"), String::from("txt")]),
+            "**/*.{md,txt}"
+        );
+    }
+
+    #[test]
+    fn get_current_word_works() {
+        let doc = "this is a sentence\nThis is another line. Here is a word.";
+        let position = Position {
+            line: 0,
+            character: 1,
+        };
+        let curr_word =
+            get_current_word(doc, position).expect("Getting current word returned None");
+        assert_eq!(curr_word, "t");
+
+        let position = Position {
+            line: 1,
+            character: 8,
+        };
+        let curr_word =
+            get_current_word(doc, position).expect("Getting current word returned None");
+        assert_eq!(curr_word, "");
+
+        let position = Position {
+            line: 0,
+            character: 0,
+        };
+        let curr_word = get_current_word(doc, position).unwrap();
+        assert_eq!(curr_word, "");
+    }
+
+    proptest! {
+        #[test]
+        fn get_current_word_never_panics(
+            doc in "\\PC{0,40}(\n\\PC{0,40}){0,3}",
+            line in 0u32..5,
+            character in 0u32..50,
+        ) {
+            if let Some(word) = get_current_word(&doc, Position { line, character }) {
+                prop_assert!(!word.contains(char::is_whitespace));
+            }
+        }
+    }
+}
@@ -0,0 +1,516 @@
+//! Spell checking the prose of notes, for hints at words the dictionaries don't know.
+//!
+//! Dictionaries are Hunspell's: a `.dic` file of words with the flags of the affixes they take,
+//! and the `.aff` file next to it defining them. Words are expanded with their prefixes and
+//! suffixes when the dictionary is loaded; compounding and the rarer affix options aren't
+//! supported, so some valid words may be flagged. A `.dic` file without an `.aff` file, like a
+//! plain list of words, is read as is.
+//!
+//! Each vault can also have its own dictionary, a list of words one per line, which the "add to
+//! dictionary" code action adds to.
+
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use crate::{comments, fences, links, tags};
+
+/// Folders Hunspell dictionaries are usually installed in.
+const DICTIONARY_FOLDERS: [&str; 5] = [
+    "/usr/share/hunspell",
+    "/usr/share/myspell",
+    "/usr/share/myspell/dicts",
+    "/usr/local/share/hunspell",
+    "/Library/Spelling",
+];
+
+/// A set of known words.
+#[derive(Debug, Default)]
+pub struct Dictionary {
+    words: HashSet<String>,
+}
+
+/// How an affix file writes flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FlagKind {
+    /// One character each, the default.
+    Char,
+    /// Two characters each.
+    Long,
+    /// Numbers separated by commas.
+    Num,
+}
+
+fn parse_flags(flags: &str, kind: FlagKind) -> Vec<String> {
+    match kind {
+        FlagKind::Char => flags.chars().map(String::from).collect(),
+        FlagKind::Long => {
+            let chars = flags.chars().collect::<Vec<_>>();
+            chars.chunks(2).map(|pair| pair.iter().collect()).collect()
+        }
+        FlagKind::Num => flags
+            .split(',')
+            .map(|flag| flag.trim().to_string())
+            .collect(),
+    }
+}
+
+/// One character of an affix's condition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Condition {
+    Any,
+    OneOf(Vec<char>),
+    NoneOf(Vec<char>),
+}
+
+impl Condition {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Condition::Any => true,
+            Condition::OneOf(chars) => chars.contains(&c),
+            Condition::NoneOf(chars) => !chars.contains(&c),
+        }
+    }
+}
+
+fn parse_condition(condition: &str) -> Vec<Condition> {
+    let mut parsed = Vec::new();
+    let mut chars = condition.chars();
+    while let Some(c) = chars.next() {
+        parsed.push(match c {
+            '.' => Condition::Any,
+            '[' => {
+                let class = chars.by_ref().take_while(|c| *c != ']').collect::<String>();
+                match class.strip_prefix('^') {
+                    Some(class) => Condition::NoneOf(class.chars().collect()),
+                    None => Condition::OneOf(class.chars().collect()),
+                }
+            }
+            c => Condition::OneOf(vec![c]),
+        });
+    }
+    parsed
+}
+
+/// A prefix or suffix rule: `strip` is taken off the word and `add` put in its place, if the
+/// word starts or ends with what `condition` matches.
+#[derive(Debug, Clone)]
+struct Affix {
+    strip: String,
+    add: String,
+    condition: Vec<Condition>,
+    /// Whether the affix combines with affixes on the other side of the word.
+    cross: bool,
+}
+
+impl Affix {
+    fn apply_prefix(&self, word: &str) -> Option<String> {
+        let mut chars = word.chars();
+        let matches = self
+            .condition
+            .iter()
+            .all(|condition| chars.next().is_some_and(|c| condition.matches(c)));
+        let rest = word.strip_prefix(self.strip.as_str())?;
+        (matches && !rest.is_empty()).then(|| format!("{}{rest}", self.add))
+    }
+
+    fn apply_suffix(&self, word: &str) -> Option<String> {
+        let mut chars = word.chars().rev();
+        let matches = self
+            .condition
+            .iter()
+            .rev()
+            .all(|condition| chars.next().is_some_and(|c| condition.matches(c)));
+        let rest = word.strip_suffix(self.strip.as_str())?;
+        (matches && !rest.is_empty()).then(|| format!("{rest}{}", self.add))
+    }
+}
+
+/// The affixes an `.aff` file defines, by flag.
+#[derive(Debug)]
+struct Affixes {
+    flags: FlagKind,
+    prefixes: HashMap<String, Vec<Affix>>,
+    suffixes: HashMap<String, Vec<Affix>>,
+}
+
+impl Affixes {
+    fn parse(aff: &str) -> Self {
+        let mut affixes = Affixes {
+            flags: FlagKind::Char,
+            prefixes: HashMap::new(),
+            suffixes: HashMap::new(),
+        };
+        let mut cross = HashMap::new();
+        for line in aff.lines() {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            match fields[..] {
+                ["FLAG", "long"] => affixes.flags = FlagKind::Long,
+                ["FLAG", "num"] => affixes.flags = FlagKind::Num,
+                // The header of a group of rules, e.g. `SFX D Y 4`.
+                [kind @ ("PFX" | "SFX"), flag, combines, count]
+                    if count.parse::<usize>().is_ok() && matches!(combines, "Y" | "N") =>
+                {
+                    cross.insert((kind, flag), combines == "Y");
+                }
+                [kind @ ("PFX" | "SFX"), flag, strip, add, condition, ..] => {
+                    let add = add.split('/').next().unwrap_or("");
+                    let affix = Affix {
+                        strip: String::from(if strip == "0" { "" } else { strip }),
+                        add: String::from(if add == "0" { "" } else { add }),
+                        condition: parse_condition(condition),
+                        cross: cross.get(&(kind, flag)).copied().unwrap_or(false),
+                    };
+                    let rules = match kind {
+                        "PFX" => &mut affixes.prefixes,
+                        _ => &mut affixes.suffixes,
+                    };
+                    rules.entry(flag.to_string()).or_default().push(affix);
+                }
+                _ => {}
+            }
+        }
+        affixes
+    }
+
+    /// `word` and the words its `flags` make of it.
+    fn expand(&self, word: &str, flags: &[String]) -> Vec<String> {
+        let mut words = vec![word.to_string()];
+        let rules = |affixes: &HashMap<String, Vec<Affix>>| {
+            flags
+                .iter()
+                .filter_map(|flag| affixes.get(flag))
+                .flatten()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let prefixes = rules(&self.prefixes);
+        let suffixes = rules(&self.suffixes);
+
+        let mut crossing = Vec::new();
+        for suffix in &suffixes {
+            if let Some(suffixed) = suffix.apply_suffix(word) {
+                if suffix.cross {
+                    crossing.push(suffixed.clone());
+                }
+                words.push(suffixed);
+            }
+        }
+        for prefix in &prefixes {
+            words.extend(prefix.apply_prefix(word));
+            if prefix.cross {
+                words.extend(
+                    crossing
+                        .iter()
+                        .filter_map(|suffixed| prefix.apply_prefix(suffixed)),
+                );
+            }
+        }
+        words
+    }
+}
+
+/// The text of a dictionary file in `encoding`, as `SET` in an `.aff` file names it. Encodings
+/// other than UTF-8 are read as Latin-1, which most of the others extend.
+fn decode(bytes: &[u8], encoding: Option<&str>) -> String {
+    match encoding {
+        Some(encoding) if !encoding.eq_ignore_ascii_case("UTF-8") => {
+            bytes.iter().map(|byte| char::from(*byte)).collect()
+        }
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+impl Dictionary {
+    /// The dictionary of the `.dic` file at `path`, expanded with the affixes of the `.aff` file
+    /// next to it, if there is one.
+    ///
+    /// This reads and expands every word, which takes a moment for large dictionaries.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let aff = match fs::read(path.with_extension("aff")) {
+            Ok(aff) => Some(aff),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let encoding = aff.as_ref().and_then(|aff| {
+            let aff = String::from_utf8_lossy(aff);
+            aff.lines()
+                .find_map(|line| line.strip_prefix("SET "))
+                .map(|encoding| encoding.trim().to_string())
+        });
+        let affixes = aff.map(|aff| Affixes::parse(&decode(&aff, encoding.as_deref())));
+        let dic = decode(&fs::read(path)?, encoding.as_deref());
+
+        let mut words = HashSet::new();
+        for (n, line) in dic.lines().enumerate() {
+            // The first line of a `.dic` file is the number of words in it.
+            let Some(entry) = line.split_whitespace().next() else {
+                continue;
+            };
+            if n == 0 && entry.parse::<usize>().is_ok() {
+                continue;
+            }
+            let (word, flags) = entry.split_once('/').unwrap_or((entry, ""));
+            match &affixes {
+                Some(affixes) => {
+                    words.extend(affixes.expand(word, &parse_flags(flags, affixes.flags)))
+                }
+                None => {
+                    words.insert(word.to_string());
+                }
+            }
+        }
+        Ok(Self { words })
+    }
+
+    /// The words of the list at `path`, one per line. A list that doesn't exist is empty.
+    pub fn read_list(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(list) => Ok(Self {
+                words: list
+                    .lines()
+                    .map(str::trim)
+                    .filter(|word| !word.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether `word` is in the dictionary. Like in Hunspell, words in lowercase may also be
+    /// capitalized, as at the start of a sentence, or in capitals.
+    pub fn contains(&self, word: &str) -> bool {
+        if self.words.contains(word) {
+            return true;
+        }
+        let mut chars = word.chars();
+        let capitalized = chars.next().is_some_and(char::is_uppercase);
+        capitalized && self.words.contains(&word.to_lowercase())
+    }
+}
+
+/// The `.dic` file of the dictionary `name`: a path, or the name of one in the usual folders,
+/// like `en_US`.
+pub fn find(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+    if path.extension().is_some() || path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    let home = env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Spelling"));
+    DICTIONARY_FOLDERS
+        .iter()
+        .map(PathBuf::from)
+        .chain(home)
+        .map(|folder| folder.join(format!("{name}.dic")))
+        .find(|path| path.is_file())
+}
+
+/// Add `word` to the list at `path`, creating it if needed.
+pub fn add_to_list(path: &Path, word: &str) -> io::Result<()> {
+    let mut list = match fs::read_to_string(path) {
+        Ok(list) => list,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    if list.lines().any(|known| known.trim() == word) {
+        return Ok(());
+    }
+    if !list.is_empty() && !list.ends_with('\n') {
+        list.push('\n');
+    }
+    list.push_str(word);
+    list.push('\n');
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, list)
+}
+
+/// A word the dictionaries don't know.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Misspelling {
+    pub word: String,
+    pub line: u32,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Whether the whitespace-separated `token` is a URL, an email address or a path rather than
+/// words.
+fn is_address(token: &str) -> bool {
+    let dotted = token
+        .trim_end_matches(|c: char| !c.is_alphanumeric())
+        .split('.')
+        .skip(1)
+        .any(|part| part.starts_with(char::is_alphanumeric));
+    token.contains("://") || token.contains(['@', '/', '\\']) || token.starts_with("www.") || dotted
+}
+
+/// The byte ranges of `line` whose words aren't prose: code spans, links, tags and addresses.
+fn skipped(line: &str, number: u32) -> Vec<Range<usize>> {
+    let mut skipped = links::parse_line(line, number)
+        .into_iter()
+        .map(|link| link.start..link.end)
+        .chain(
+            tags::parse_line(line, number)
+                .into_iter()
+                .map(|tag| tag.start..tag.end),
+        )
+        .collect::<Vec<_>>();
+    let mut code = None;
+    for (i, c) in line.char_indices() {
+        if c == '`' {
+            match code.take() {
+                Some(start) => skipped.push(start..i + 1),
+                None => code = Some(i),
+            }
+        }
+    }
+    if let Some(start) = code {
+        skipped.push(start..line.len());
+    }
+    let mut offset = 0;
+    for token in line.split_inclusive(char::is_whitespace) {
+        if is_address(token.trim_end()) {
+            skipped.push(offset..offset + token.len());
+        }
+        offset += token.len();
+    }
+    skipped
+}
+
+/// The words of `line` with their byte ranges: runs of letters, with apostrophes inside them.
+/// Runs with digits or underscores aren't words.
+fn words(line: &str) -> Vec<(usize, &str)> {
+    let is_part = |c: char| c.is_alphanumeric() || matches!(c, '\'' | '’' | '_');
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in line.char_indices().chain([(line.len(), ' ')]) {
+        match (start, is_part(c)) {
+            (None, true) => start = Some(i),
+            (Some(from), false) => {
+                start = None;
+                let run = &line[from..i];
+                let word = run.trim_matches(['\'', '’']);
+                if word.is_empty() || word.contains(|c: char| c.is_numeric() || c == '_') {
+                    continue;
+                }
+                words.push((from + run.find(word).unwrap_or(0), word));
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+/// Whether `word` should be checked: it's longer than a letter, and not an acronym or in camel
+/// case, which are usually names.
+fn is_checked(word: &str) -> bool {
+    let mut chars = word.chars();
+    chars.next().is_some() && chars.clone().next().is_some() && !chars.any(char::is_uppercase)
+}
+
+/// The words of the prose of `document` none of `dictionaries` know. Frontmatter, comments,
+/// code, links, tags and addresses aren't checked.
+pub fn misspellings(document: &str, dictionaries: &[&Dictionary]) -> Vec<Misspelling> {
+    let blanked = comments::blank(document);
+    let document = blanked.as_ref();
+    let mut misspellings = Vec::new();
+    for (n, line) in fences::body_lines(document) {
+        let Some(line) = line else {
+            continue;
+        };
+        let skipped = skipped(line, n);
+        for (start, word) in words(line) {
+            let end = start + word.len();
+            let normalized = word.replace('’', "'");
+            if !is_checked(word)
+                || skipped
+                    .iter()
+                    .any(|range| range.start < end && start < range.end)
+                || dictionaries
+                    .iter()
+                    .any(|dictionary| dictionary.contains(&normalized))
+            {
+                continue;
+            }
+            misspellings.push(Misspelling {
+                word: word.to_string(),
+                line: n,
+                start,
+                end,
+            });
+        }
+    }
+    misspellings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hunspell_dictionaries_are_expanded() {
+        let dir = tempfile::tempdir().unwrap();
+        let aff = "SET UTF-8\n\
+                   PFX U Y 1\n\
+                   PFX U 0 un .\n\
+                   SFX D Y 2\n\
+                   SFX D y ied [^aeiou]y\n\
+                   SFX D 0 ed [^y]\n";
+        fs::write(dir.path().join("en.aff"), aff).unwrap();
+        fs::write(dir.path().join("en.dic"), "3\ntidy/UD\nlock/UD\ncafé\n").unwrap();
+        let dictionary = Dictionary::load(&dir.path().join("en.dic")).unwrap();
+        for word in [
+            "tidy", "tidied", "untidied", "unlocked", "Locked", "LOCK", "café",
+        ] {
+            assert!(dictionary.contains(word), "{word}");
+        }
+        for word in ["tidyed", "lockied", "Cafe"] {
+            assert!(!dictionary.contains(word), "{word}");
+        }
+
+        assert_eq!(
+            find(&dir.path().join("en.dic").to_string_lossy()),
+            Some(dir.path().join("en.dic"))
+        );
+        let list = dir.path().join(".note-ls/dictionary.txt");
+        add_to_list(&list, "Zettelkasten").unwrap();
+        add_to_list(&list, "Zettelkasten").unwrap();
+        assert_eq!(fs::read_to_string(&list).unwrap(), "Zettelkasten\n");
+        assert!(Dictionary::read_list(&list)
+            .unwrap()
+            .contains("Zettelkasten"));
+    }
+
+    #[test]
+    fn only_prose_is_checked() {
+        let dictionary = Dictionary {
+            words: ["the", "is", "in", "a", "don't", "see", "and", "or"]
+                .map(String::from)
+                .into(),
+        };
+        let document = "---\ntitle: Teh\n---\n\
+                        # Teh plan\n\
+                        The `teh` is in [[Teh note]] and [teh](teh.md), see https://teh.io #teh\n\
+                        ```\nteh\n```\n\
+                        Don’t see NASA, iPhone, a2b, notes.md or snake_case, 'teh'\n";
+        let found = misspellings(document, &[&dictionary])
+            .into_iter()
+            .map(|misspelling| (misspelling.line, misspelling.start, misspelling.word))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                (3, 2, String::from("Teh")),
+                (3, 6, String::from("plan")),
+                (8, 56, String::from("teh")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,69 @@
+//! The `noteLs/stats` notification, which tells clients how long the note being edited is
+//! whenever it changes, so statuslines can show writing progress without asking.
+//!
+//! Frontmatter and comments aren't counted, like in the note's word count.
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{notification::Notification, Url};
+
+use crate::{comments, frontmatter, links, note_info};
+
+pub enum Stats {}
+
+impl Notification for Stats {
+    type Params = StatsParams;
+    const METHOD: &'static str = "noteLs/stats";
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsParams {
+    pub uri: Url,
+    pub words: usize,
+    /// Characters other than whitespace.
+    pub characters: usize,
+    /// Minutes the words take to read, rounded up.
+    pub reading_minutes: usize,
+    /// Links and embeds in the body, to notes, attachments and the web alike.
+    pub links: usize,
+}
+
+/// How long `document`, the content of the note at `uri`, is, reading `words_per_minute`.
+pub fn stats(uri: Url, document: &str, words_per_minute: usize) -> StatsParams {
+    let blanked = comments::blank(document);
+    let body_start = frontmatter::body_start(&blanked) as usize;
+    let body = blanked.lines().skip(body_start).collect::<Vec<_>>();
+    let words = note_info::count_words(body.iter().copied());
+    let links = links::parse_links(&blanked)
+        .iter()
+        .filter(|link| link.line as usize >= body_start)
+        .count();
+    StatsParams {
+        uri,
+        words,
+        characters: body
+            .iter()
+            .flat_map(|line| line.chars())
+            .filter(|c| !c.is_whitespace())
+            .count(),
+        reading_minutes: words.div_ceil(words_per_minute.max(1)),
+        links,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_body_is_counted() {
+        let uri = Url::parse("file:///vault/note.md").unwrap();
+        let document = "---\ntitle: Not counted\n---\n# Title\nSee [[other]] and \
+                        [site](https://example.com).\n%% not [[counted]] %%\n";
+        let stats = stats(uri, document, 2);
+        assert_eq!(stats.words, 5);
+        assert_eq!(stats.characters, 49);
+        assert_eq!(stats.reading_minutes, 3);
+        assert_eq!(stats.links, 2);
+    }
+}
@@ -0,0 +1,94 @@
+//! Outline of a note for `textDocument/documentSymbol`.
+
+use tower_lsp::lsp_types::{DocumentSymbol, Position, Range, SymbolKind};
+
+use crate::syntax::{Node, Syntax};
+
+#[allow(deprecated)]
+fn symbol(heading: &Node, text: &str, end_line: u32) -> DocumentSymbol {
+    let line = heading.range.start.line;
+    DocumentSymbol {
+        name: text.to_string(),
+        detail: None,
+        kind: SymbolKind::STRING,
+        tags: None,
+        deprecated: None,
+        range: Range::new(Position::new(line, 0), Position::new(end_line, 0)),
+        selection_range: Range::new(Position::new(line, 0), heading.range.end),
+        children: None,
+    }
+}
+
+/// Nest `symbol` under the innermost open section, or at the top level.
+fn attach(
+    stack: &mut [(u8, DocumentSymbol)],
+    roots: &mut Vec<DocumentSymbol>,
+    symbol: DocumentSymbol,
+) {
+    match stack.last_mut() {
+        Some((_, parent)) => parent.children.get_or_insert_with(Vec::new).push(symbol),
+        None => roots.push(symbol),
+    }
+}
+
+/// The headings of `document`, parsed into `syntax`, as a tree, with each heading's range
+/// covering its whole section.
+pub fn outline(document: &str, syntax: &Syntax) -> Vec<DocumentSymbol> {
+    let lines = document.lines().count() as u32;
+    let headings = syntax.headings().collect::<Vec<_>>();
+
+    let mut roots = Vec::new();
+    let mut stack: Vec<(u8, DocumentSymbol)> = Vec::new();
+
+    for (i, &(heading, level, text)) in headings.iter().enumerate() {
+        // A section ends where the next heading of the same or a higher level starts.
+        let end_line = headings[i + 1..]
+            .iter()
+            .find(|(_, next_level, _)| *next_level <= level)
+            .map(|(next, ..)| next.range.start.line)
+            .unwrap_or(lines);
+
+        while stack.last().is_some_and(|(open, _)| *open >= level) {
+            let (_, done) = stack.pop().unwrap();
+            attach(&mut stack, &mut roots, done);
+        }
+        stack.push((level, symbol(heading, text, end_line)));
+    }
+
+    while let Some((_, done)) = stack.pop() {
+        attach(&mut stack, &mut roots, done);
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headings_nest_by_level() {
+        let doc = "# Title\n## One\n### One.a\n## Two\ntext\n# Appendix";
+        let outline = outline(doc, &Syntax::parse(doc));
+
+        let names = |symbols: &[DocumentSymbol]| {
+            symbols
+                .iter()
+                .map(|symbol| symbol.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&outline), vec!["Title", "Appendix"]);
+
+        let title = &outline[0];
+        assert_eq!(title.range.end, Position::new(5, 0));
+        let sections = title.children.as_deref().unwrap();
+        assert_eq!(names(sections), vec!["One", "Two"]);
+        assert_eq!(
+            names(sections[0].children.as_deref().unwrap()),
+            vec!["One.a"]
+        );
+        assert_eq!(
+            sections[1].range,
+            Range::new(Position::new(3, 0), Position::new(5, 0))
+        );
+    }
+}
@@ -0,0 +1,377 @@
+//! The markdown structure of a note, parsed once per edit and shared by the features that need
+//! more than its lines: symbols, folding, and telling code from prose for completion and
+//! diagnostics.
+//!
+//! Open notes keep theirs along with their content. After an edit, the blocks before it are kept
+//! and only the rest of the note is parsed again, from the start of the block the edit is in or
+//! follows. A whole note is parsed again when the edit touches its frontmatter, or when it has
+//! link reference definitions, which can change links anywhere in it.
+
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
+use tower_lsp::lsp_types::{Position, Range};
+
+use crate::frontmatter;
+
+/// What a node is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Frontmatter,
+    Heading {
+        level: u8,
+        text: String,
+    },
+    Paragraph,
+    BlockQuote,
+    /// A fenced or indented code block, with the first word after its fence, if any.
+    CodeBlock(String),
+    List,
+    Item,
+    FootnoteDefinition,
+    Table,
+    Html,
+    Rule,
+    /// Code in backticks inside a block.
+    Code,
+}
+
+/// A markdown element of a note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub kind: Kind,
+    /// From the element's start to the end of its last line, without the line break.
+    pub range: Range,
+    /// How many elements it's nested in, 0 for the blocks of the note itself.
+    pub depth: u32,
+}
+
+/// The elements of a note, in the order they start in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Syntax {
+    nodes: Vec<Node>,
+    /// The first line after the frontmatter.
+    body_start: u32,
+    /// Whether the note defines link references.
+    references: bool,
+}
+
+impl Syntax {
+    pub fn parse(document: &str) -> Self {
+        let body_start = frontmatter::body_start(document);
+        let mut syntax = Self {
+            nodes: Vec::new(),
+            body_start,
+            references: false,
+        };
+        if body_start > 0 {
+            let end = Position::new(body_start - 1, line_len(document, body_start - 1));
+            syntax.nodes.push(Node {
+                kind: Kind::Frontmatter,
+                range: Range::new(Position::new(0, 0), end),
+                depth: 0,
+            });
+        }
+        let start = line_offset(document, body_start);
+        syntax.references = parse_into(&mut syntax.nodes, &document[start..], body_start);
+        syntax
+    }
+
+    /// Bring the syntax up to date with `document`, which was edited from line `changed` on.
+    pub fn update(&mut self, document: &str, changed: u32) {
+        if self.references
+            || changed < self.body_start
+            || frontmatter::body_start(document) != self.body_start
+        {
+            *self = Self::parse(document);
+            return;
+        }
+
+        // An edit can join the block it follows, or turn it into another one, like a paragraph
+        // into a setext heading. Lists and quotes can go on past blank lines, and the last block
+        // may be a fence that's never closed, running on into what's added after it.
+        let top = self
+            .nodes
+            .iter()
+            .filter(|node| node.depth == 0 && node.kind != Kind::Frontmatter)
+            .collect::<Vec<_>>();
+        let mut kept = top
+            .iter()
+            .take_while(|node| node.range.end.line + 1 < changed)
+            .count()
+            .min(top.len().saturating_sub(1));
+        while kept > 0 {
+            let last = top[kept - 1];
+            let next = top.get(kept).map_or(changed, |node| node.range.start.line);
+            let continues = matches!(
+                last.kind,
+                Kind::List | Kind::BlockQuote | Kind::FootnoteDefinition
+            );
+            if !continues && last.range.end.line + 1 < next.min(changed) {
+                break;
+            }
+            kept -= 1;
+        }
+        if kept == 0 {
+            *self = Self::parse(document);
+            return;
+        }
+
+        let restart = top[kept - 1].range.end.line + 1;
+        let first_reparsed = self
+            .nodes
+            .iter()
+            .position(|node| node.range.start.line >= restart)
+            .unwrap_or(self.nodes.len());
+        self.nodes.truncate(first_reparsed);
+        let start = line_offset(document, restart);
+        if parse_into(&mut self.nodes, &document[start..], restart) {
+            *self = Self::parse(document);
+        }
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// The headings, with their level and text without markup.
+    pub fn headings(&self) -> impl Iterator<Item = (&Node, u8, &str)> {
+        self.nodes.iter().filter_map(|node| match &node.kind {
+            Kind::Heading { level, text } => Some((node, *level, text.as_str())),
+            _ => None,
+        })
+    }
+
+    /// Whether `position` is in a code block, fences included, or in code in backticks.
+    pub fn in_code(&self, position: Position) -> bool {
+        self.nodes.iter().any(|node| match node.kind {
+            Kind::CodeBlock(_) => {
+                node.range.start.line <= position.line && position.line <= node.range.end.line
+            }
+            Kind::Code => node.range.start <= position && position < node.range.end,
+            _ => false,
+        })
+    }
+}
+
+/// Parse `text`, which starts at line `first_line` of its note, adding its elements to `nodes`.
+/// Returns whether it defines link references.
+fn parse_into(nodes: &mut Vec<Node>, text: &str, first_line: u32) -> bool {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS;
+    let parser = Parser::new_ext(text, options);
+    let references = parser.reference_definitions().iter().next().is_some();
+
+    let line_starts = std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+        .collect::<Vec<_>>();
+    let position = |offset: usize| {
+        let line = line_starts.partition_point(|start| *start <= offset) - 1;
+        Position::new(
+            first_line + line as u32,
+            (offset - line_starts[line]) as u32,
+        )
+    };
+    let range = |span: std::ops::Range<usize>| {
+        let end = span.start + text[span.clone()].trim_end_matches(['\n', '\r']).len();
+        Range::new(position(span.start), position(end.max(span.start)))
+    };
+
+    // The open elements, and which of them are nodes.
+    let mut open: Vec<Option<usize>> = Vec::new();
+    let mut heading = None;
+    for (event, span) in parser.into_offset_iter() {
+        let depth = open.iter().flatten().count() as u32;
+        let kind = match event {
+            Event::Start(tag) => {
+                let kind = match tag {
+                    Tag::Paragraph => Some(Kind::Paragraph),
+                    Tag::Heading(level, ..) => Some(Kind::Heading {
+                        level: heading_level(level),
+                        text: String::new(),
+                    }),
+                    Tag::BlockQuote => Some(Kind::BlockQuote),
+                    Tag::CodeBlock(CodeBlockKind::Fenced(info)) => Some(Kind::CodeBlock(
+                        info.split_whitespace()
+                            .next()
+                            .unwrap_or_default()
+                            .to_string(),
+                    )),
+                    Tag::CodeBlock(CodeBlockKind::Indented) => Some(Kind::CodeBlock(String::new())),
+                    Tag::List(_) => Some(Kind::List),
+                    Tag::Item => Some(Kind::Item),
+                    Tag::FootnoteDefinition(_) => Some(Kind::FootnoteDefinition),
+                    Tag::Table(_) => Some(Kind::Table),
+                    _ => None,
+                };
+                let index = kind.map(|kind| {
+                    if matches!(kind, Kind::Heading { .. }) {
+                        heading = Some(nodes.len());
+                    }
+                    nodes.push(Node {
+                        kind,
+                        range: range(span),
+                        depth,
+                    });
+                    nodes.len() - 1
+                });
+                open.push(index);
+                continue;
+            }
+            Event::End(Tag::Heading(..)) => {
+                heading = None;
+                open.pop();
+                continue;
+            }
+            Event::End(_) => {
+                open.pop();
+                continue;
+            }
+            Event::Text(text) => {
+                if let Some(Kind::Heading { text: heading, .. }) =
+                    heading.map(|i| &mut nodes[i].kind)
+                {
+                    heading.push_str(&text);
+                }
+                continue;
+            }
+            Event::Code(code) => {
+                if let Some(Kind::Heading { text: heading, .. }) =
+                    heading.map(|i| &mut nodes[i].kind)
+                {
+                    heading.push_str(&code);
+                }
+                Kind::Code
+            }
+            // Blocks of HTML come a line at a time.
+            Event::Html(_) if depth == 0 => {
+                let range = range(span);
+                match nodes.last_mut() {
+                    Some(last)
+                        if last.kind == Kind::Html
+                            && last.depth == 0
+                            && last.range.end.line + 1 == range.start.line =>
+                    {
+                        last.range.end = range.end;
+                    }
+                    _ => nodes.push(Node {
+                        kind: Kind::Html,
+                        range,
+                        depth,
+                    }),
+                }
+                continue;
+            }
+            Event::Rule => Kind::Rule,
+            _ => continue,
+        };
+        nodes.push(Node {
+            kind,
+            range: range(span),
+            depth,
+        });
+    }
+    references
+}
+
+fn heading_level(level: HeadingLevel) -> u8 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+/// The byte offset of the start of line `line` of `document`, or its end if it has fewer lines.
+fn line_offset(document: &str, line: u32) -> usize {
+    match line {
+        0 => 0,
+        _ => document
+            .match_indices('\n')
+            .nth(line as usize - 1)
+            .map_or(document.len(), |(i, _)| i + 1),
+    }
+}
+
+fn line_len(document: &str, line: u32) -> u32 {
+    document.lines().nth(line as usize).map_or(0, str::len) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn notes_are_parsed() {
+        let document = "---\ntitle: Tea\n---\n# Tea `time`\n\nSome [tea](tea.md) and `code`.\n\n\
+                        ```rust\n# not a heading\n```\n\n- one\n- two\n\nText\n---\n";
+        let syntax = Syntax::parse(document);
+        let headings = syntax
+            .headings()
+            .map(|(node, level, text)| (node.range.start.line, level, text))
+            .collect::<Vec<_>>();
+        assert_eq!(headings, [(3, 1, "Tea time"), (14, 2, "Text")]);
+        let top = syntax
+            .nodes()
+            .iter()
+            .filter(|node| node.depth == 0)
+            .map(|node| (node.range.start.line, node.range.end.line))
+            .collect::<Vec<_>>();
+        assert_eq!(top, [(0, 2), (3, 3), (5, 5), (7, 9), (11, 12), (14, 15)]);
+        assert!(syntax.nodes().contains(&Node {
+            kind: Kind::Code,
+            range: Range::new(Position::new(5, 23), Position::new(5, 29)),
+            depth: 1,
+        }));
+        assert!(syntax.in_code(Position::new(8, 3)));
+        assert!(syntax.in_code(Position::new(5, 25)));
+        assert!(!syntax.in_code(Position::new(5, 8)));
+    }
+
+    #[test]
+    fn edits_reparse_from_the_edited_block() {
+        let before = "# One\n\nText\n\n- a\n\n- b\n\nMore\n";
+        let mut syntax = Syntax::parse(before);
+        let after = "# One\n\nText\n\n- a\n\n- b\n\nMore\n===\n";
+        syntax.update(after, 9);
+        assert_eq!(syntax, Syntax::parse(after));
+
+        let after = "# One\n\nText\n\n```\n- a\n\n- b\n\nMore\n";
+        syntax.update(after, 4);
+        assert_eq!(syntax, Syntax::parse(after));
+        assert!(syntax.in_code(Position::new(8, 0)));
+    }
+
+    proptest! {
+        #[test]
+        fn updates_match_parsing_again(
+            lines in prop::collection::vec(
+                prop_oneof![
+                    Just(""), Just("# Title"), Just("text"), Just("- item"), Just("  more"),
+                    Just("> quote"), Just("```"), Just("===") , Just("| a |"), Just("|---|"),
+                    Just("<div>"), Just("    code"), Just("[^1]: note"),
+                ],
+                0..12,
+            ),
+            changed in 0usize..12,
+            replacement in prop_oneof![Just(""), Just("```"), Just("- item"), Just("text")],
+        ) {
+            let before = lines.join("\n");
+            let mut edited = lines.clone();
+            if changed < edited.len() {
+                edited[changed] = replacement;
+            } else {
+                edited.push(replacement);
+            }
+            let after = edited.join("\n");
+            let mut syntax = Syntax::parse(&before);
+            syntax.update(&after, changed.min(lines.len()) as u32);
+            prop_assert_eq!(syntax, Syntax::parse(&after));
+        }
+    }
+}
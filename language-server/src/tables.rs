@@ -0,0 +1,251 @@
+//! Formatting pipe tables, for `textDocument/formatting` and `textDocument/rangeFormatting`.
+//!
+//! Columns are padded to the width of their widest cell, aligned the way the delimiter row says,
+//! and every row gets the same number of cells with a pipe on both sides. The delimiter row is
+//! written as dashes filling the column, with colons only where they set the alignment.
+
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+use crate::{fences::Fences, frontmatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Alignment {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+/// A table in a note.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Table {
+    /// The lines of the header row and the last row.
+    start: u32,
+    end: u32,
+    /// The indentation of the header row, kept for every row.
+    indent: String,
+    rows: Vec<Vec<String>>,
+    alignments: Vec<Alignment>,
+}
+
+/// The cells of a table row, trimmed. Escaped pipes don't separate cells.
+fn cells(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = match line.strip_suffix('|') {
+        Some(rest) if !rest.ends_with('\\') => rest,
+        _ => line,
+    };
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut escaped = false;
+    for c in line.chars() {
+        match c {
+            '|' if !escaped => cells.push(std::mem::take(&mut cell)),
+            c => cell.push(c),
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    cells.push(cell);
+    cells
+        .into_iter()
+        .map(|cell| cell.trim().to_string())
+        .collect()
+}
+
+/// The alignments of the columns, if `line` is a delimiter row.
+fn delimiter_row(line: &str) -> Option<Vec<Alignment>> {
+    let trimmed = line.trim_start();
+    if !line.contains('-') || !line.contains('|') || !trimmed.starts_with(['|', ':', '-']) {
+        return None;
+    }
+    cells(line)
+        .iter()
+        .map(|cell| {
+            let left = cell.starts_with(':');
+            let right = cell.ends_with(':') && cell.len() > 1;
+            let dashes = cell.trim_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (left, right) {
+                (true, true) => Alignment::Center,
+                (true, false) => Alignment::Left,
+                (false, true) => Alignment::Right,
+                (false, false) => Alignment::None,
+            })
+        })
+        .collect()
+}
+
+/// The tables in `document`, outside frontmatter and fenced code blocks.
+fn tables(document: &str) -> Vec<Table> {
+    let lines = document.lines().collect::<Vec<_>>();
+    let body_start = frontmatter::body_start(document) as usize;
+    let mut tables = Vec::new();
+    let mut fences = Fences::default();
+    let mut n = body_start;
+    while n < lines.len() {
+        let line = lines[n];
+        let header = (!fences.in_code(line) && line.contains('|'))
+            .then(|| Some((cells(line), delimiter_row(lines.get(n + 1)?)?)))
+            .flatten();
+        let Some((header, alignments)) =
+            header.filter(|(header, alignments)| header.len() == alignments.len())
+        else {
+            n += 1;
+            continue;
+        };
+
+        let mut rows = vec![header];
+        let mut end = n + 1;
+        while let Some(line) = lines.get(end + 1) {
+            if line.trim().is_empty() || !line.contains('|') {
+                break;
+            }
+            rows.push(cells(line));
+            end += 1;
+        }
+        tables.push(Table {
+            start: n as u32,
+            end: end as u32,
+            indent: line[..line.len() - line.trim_start().len()].to_string(),
+            rows,
+            alignments,
+        });
+        n = end + 1;
+    }
+    tables
+}
+
+/// `text` padded with spaces to `width` characters, aligned as `alignment` says.
+fn pad(text: &str, width: usize, alignment: Alignment) -> String {
+    let space = width.saturating_sub(text.chars().count());
+    let (before, after) = match alignment {
+        Alignment::Right => (space, 0),
+        Alignment::Center => (space / 2, space - space / 2),
+        Alignment::None | Alignment::Left => (0, space),
+    };
+    format!("{}{text}{}", " ".repeat(before), " ".repeat(after))
+}
+
+impl Table {
+    /// The lines of the table, formatted.
+    fn format(&self) -> Vec<String> {
+        let columns = self
+            .rows
+            .iter()
+            .map(Vec::len)
+            .chain([self.alignments.len()])
+            .max()
+            .unwrap_or(0);
+        let alignment = |column: usize| {
+            self.alignments
+                .get(column)
+                .copied()
+                .unwrap_or(Alignment::None)
+        };
+        let widths = (0..columns)
+            .map(|column| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.get(column))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or(0)
+                    .max(3)
+            })
+            .collect::<Vec<_>>();
+
+        let line = |cells: Vec<String>| format!("{}| {} |", self.indent, cells.join(" | "));
+        let row = |row: &Vec<String>| {
+            line(
+                (0..columns)
+                    .map(|column| {
+                        let cell = row.get(column).map_or("", String::as_str);
+                        pad(cell, widths[column], alignment(column))
+                    })
+                    .collect(),
+            )
+        };
+        let delimiter = line(
+            (0..columns)
+                .map(|column| {
+                    let width = widths[column];
+                    match alignment(column) {
+                        Alignment::None => "-".repeat(width),
+                        Alignment::Left => format!(":{}", "-".repeat(width - 1)),
+                        Alignment::Right => format!("{}:", "-".repeat(width - 1)),
+                        Alignment::Center => format!(":{}:", "-".repeat(width - 2)),
+                    }
+                })
+                .collect(),
+        );
+
+        let mut lines = vec![row(&self.rows[0]), delimiter];
+        lines.extend(self.rows[1..].iter().map(row));
+        lines
+    }
+}
+
+/// The edits formatting the tables of `document` with a line in `range`, or all of them without
+/// one.
+pub fn format(document: &str, range: Option<Range>) -> Vec<TextEdit> {
+    let lines = document.lines().collect::<Vec<_>>();
+    tables(document)
+        .into_iter()
+        .filter(|table| {
+            range.is_none_or(|range| table.start <= range.end.line && range.start.line <= table.end)
+        })
+        .filter_map(|table| {
+            let formatted = table.format();
+            let current = &lines[table.start as usize..=table.end as usize];
+            if formatted.iter().eq(current.iter()) {
+                return None;
+            }
+            let last = lines[table.end as usize];
+            Some(TextEdit::new(
+                Range::new(
+                    Position::new(table.start, 0),
+                    Position::new(table.end, last.len() as u32),
+                ),
+                formatted.join("\n"),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_are_aligned() {
+        let document = "# Prices\n\
+                        Item|Price|Note\n\
+                        :-|--:|:-:\n\
+                        Tea | 3 | hot\n\
+                        | Café au lait | 4.50 | a \\| b |\n\
+                        \n\
+                        ```\n| a | b |\n|---|---|\n```\n\
+                        | not | a table |\n";
+        let edits = format(document, None);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(1, 0), Position::new(4, 33))
+        );
+        assert_eq!(
+            edits[0].new_text,
+            "| Item         | Price |  Note  |\n\
+             | :----------- | ----: | :----: |\n\
+             | Tea          |     3 |  hot   |\n\
+             | Café au lait |  4.50 | a \\| b |"
+        );
+
+        let formatted = edits[0].new_text.as_str();
+        assert!(format(formatted, None).is_empty());
+        let elsewhere = Range::new(Position::new(0, 0), Position::new(0, 3));
+        assert!(format(document, Some(elsewhere)).is_empty());
+    }
+}
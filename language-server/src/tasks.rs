@@ -0,0 +1,205 @@
+//! Tasks, list items starting with a checkbox like `- [ ]` or `1. [x]`: checking and unchecking
+//! them for `noteLs.task.toggle` and its code action, and listing the open ones for
+//! `noteLs.tasks.list`.
+
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+use crate::{comments, fences::body_lines, tags};
+
+/// A task on a line of a note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Task {
+    pub line: u32,
+    /// The width of the indentation before the list marker.
+    pub indent: usize,
+    /// The byte offset of the character between the checkbox's brackets.
+    pub state: usize,
+    pub done: bool,
+}
+
+/// The task on `line`, numbered `number`, if it's one.
+fn parse(line: &str, number: u32) -> Option<Task> {
+    let trimmed = line.trim_start();
+    let indent = line.len() - trimmed.len();
+    let item = trimmed.strip_prefix(['-', '*', '+']).or_else(|| {
+        let marker = trimmed.trim_start_matches(|c: char| c.is_ascii_digit());
+        (marker.len() < trimmed.len())
+            .then(|| marker.strip_prefix(['.', ')']))
+            .flatten()
+    })?;
+    let done = match item.get(..4)? {
+        " [ ]" => false,
+        " [x]" | " [X]" => true,
+        _ => return None,
+    };
+    Some(Task {
+        line: number,
+        indent,
+        state: line.len() - item.len() + 2,
+        done,
+    })
+}
+
+/// A task that isn't done, with what it says.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenTask {
+    pub line: u32,
+    /// The byte range of the text after the checkbox.
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    /// The day the task is due, as `YYYY-MM-DD`, if a `📅` gives one.
+    pub due: Option<String>,
+    /// The tags in the task's text, without their `#`.
+    pub tags: Vec<String>,
+}
+
+impl OpenTask {
+    pub fn range(&self) -> Range {
+        Range::new(
+            Position::new(self.line, self.start as u32),
+            Position::new(self.line, self.end as u32),
+        )
+    }
+}
+
+/// The date after the first `📅` in `text`, like `📅 2024-05-01`, if it's one.
+fn due_date(text: &str) -> Option<String> {
+    let (_, after) = text.split_once('📅')?;
+    let date = after.trim_start().get(..10)?;
+    let is_date = date.char_indices().all(|(i, c)| match i {
+        4 | 7 => c == '-',
+        _ => c.is_ascii_digit(),
+    });
+    is_date.then(|| date.to_string())
+}
+
+/// The tasks of `document` that aren't done, in order, leaving out those in comments.
+pub fn open_tasks(document: &str) -> Vec<OpenTask> {
+    let document = comments::blank(document);
+    body_lines(&document)
+        .filter_map(|(n, line)| {
+            let line = line?;
+            let task = parse(line, n).filter(|task| !task.done)?;
+            // The text starts after the closing bracket.
+            let rest = &line[task.state + 2..];
+            let text = rest.trim();
+            let start = line.len() - rest.trim_start().len();
+            Some(OpenTask {
+                line: n,
+                start,
+                end: start + text.len(),
+                text: text.to_string(),
+                due: due_date(text),
+                tags: tags::parse_line(line, n)
+                    .into_iter()
+                    .map(|tag| tag.name)
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// The task on `line` of `document`, if there's one.
+pub fn task_at(document: &str, line: u32) -> Option<Task> {
+    let (_, text) = body_lines(document).find(|(n, _)| *n == line)?;
+    parse(text?, line)
+}
+
+/// The edits checking `task` in `document` if it isn't done, or unchecking it if it is, along
+/// with the tasks nested under it.
+pub fn toggle(document: &str, task: &Task) -> Vec<TextEdit> {
+    let done = !task.done;
+    let mut tasks = vec![*task];
+    for (n, line) in body_lines(document).skip(task.line as usize + 1) {
+        let Some(line) = line else {
+            continue;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        // Nested items, and their continuation lines, are indented further than the task.
+        if line.len() - line.trim_start().len() <= task.indent {
+            break;
+        }
+        tasks.extend(parse(line, n));
+    }
+    tasks
+        .into_iter()
+        .filter(|task| task.done != done)
+        .map(|task| {
+            TextEdit::new(
+                Range::new(
+                    Position::new(task.line, task.state as u32),
+                    Position::new(task.line, task.state as u32 + 1),
+                ),
+                String::from(if done { "x" } else { " " }),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_tasks_are_toggled_along() {
+        let document = "- [ ] Trip\n  - [x] Tickets\n    1. [ ] Seats\n    notes\n  * [X] Bags\n\
+                        - [ ] Other\n```\n- [ ] code\n```\n";
+        let trip = task_at(document, 0).unwrap();
+        assert_eq!(
+            trip,
+            Task {
+                line: 0,
+                indent: 0,
+                state: 3,
+                done: false,
+            }
+        );
+        let edits = toggle(document, &trip)
+            .into_iter()
+            .map(|edit| {
+                (
+                    edit.range.start.line,
+                    edit.range.start.character,
+                    edit.new_text,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            edits,
+            [(0, 3, String::from("x")), (2, 8, String::from("x"))]
+        );
+
+        let tickets = task_at(document, 1).unwrap();
+        let edits = toggle(document, &tickets);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].new_text, " ");
+
+        assert_eq!(task_at(document, 3), None);
+        assert_eq!(task_at(document, 7), None);
+    }
+
+    #[test]
+    fn open_tasks_are_listed_with_due_dates_and_tags() {
+        let document = "---\ntags: [work]\n---\n- [ ] Call Sam #phone 📅 2024-05-01\n\
+                        - [x] Done 📅 2024-04-01\n  1. [ ]   Draft #work/report\n\
+                        - [ ] Later 📅 soon\n```\n- [ ] code\n```\n";
+        let tasks = open_tasks(document);
+        assert_eq!(
+            tasks
+                .iter()
+                .map(|task| (task.line, task.text.as_str(), task.due.as_deref()))
+                .collect::<Vec<_>>(),
+            [
+                (3, "Call Sam #phone 📅 2024-05-01", Some("2024-05-01")),
+                (5, "Draft #work/report", None),
+                (6, "Later 📅 soon", None),
+            ]
+        );
+        assert_eq!(tasks[0].tags, ["phone"]);
+        assert_eq!(tasks[1].tags, ["work/report"]);
+        assert_eq!((tasks[1].start, tasks[1].end), (11, 29));
+    }
+}
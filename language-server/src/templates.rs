@@ -0,0 +1,95 @@
+//! Templates of new notes, kept in the folder `Config::templates_folder` names.
+//!
+//! In a template, `{{title}}` stands for the name of the new note and `{{date}}` for the day it's
+//! created, as `YYYY-MM-DD`. Once the note is open, the editor's cursor goes where `{{cursor}}`
+//! was.
+
+use std::{fs, path::Path};
+
+use tower_lsp::lsp_types::Position;
+
+use crate::new_notes::Date;
+
+const CURSOR: &str = "{{cursor}}";
+
+/// A template from the templates folder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    /// The file name of the template, without its extension.
+    pub name: String,
+    pub content: String,
+}
+
+/// The templates in `folder`, its notes with one of `extensions`, sorted by name. Subfolders
+/// aren't searched.
+pub fn load(folder: &Path, extensions: &[String]) -> Vec<Template> {
+    let Ok(entries) = fs::read_dir(folder) else {
+        return Vec::new();
+    };
+    let mut templates = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let extension = path.extension()?.to_str()?;
+            if !extensions.iter().any(|allowed| allowed == extension) {
+                return None;
+            }
+            Some(Template {
+                name: path.file_stem()?.to_string_lossy().into_owned(),
+                content: fs::read_to_string(&path).ok()?,
+            })
+        })
+        .collect::<Vec<_>>();
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    templates
+}
+
+/// `template` with `{{title}}` and `{{date}}` replaced. `{{cursor}}` is left for `take_cursor`,
+/// as more may be added to the note before it's created.
+pub fn fill(template: &str, title: &str, date: Date) -> String {
+    template
+        .replace("{{title}}", title)
+        .replace("{{date}}", &date.to_string())
+}
+
+/// Remove `{{cursor}}` from `content`, returning where it was. Only the first one counts, and
+/// any others are removed too.
+pub fn take_cursor(content: &mut String) -> Option<Position> {
+    let offset = content.find(CURSOR)?;
+    *content = content.replace(CURSOR, "");
+    let before = &content[..offset];
+    let line = before.matches('\n').count();
+    let character = offset - before.rfind('\n').map_or(0, |newline| newline + 1);
+    Some(Position::new(line as u32, character as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_are_filled_in() {
+        let folder = tempfile::tempdir().unwrap();
+        fs::write(folder.path().join("meeting.md"), "# {{title}}\n").unwrap();
+        fs::write(folder.path().join("book.md"), "").unwrap();
+        fs::write(folder.path().join("cover.png"), "").unwrap();
+        let templates = load(folder.path(), &[String::from("md")]);
+        let names = templates
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["book", "meeting"]);
+
+        let date = Date::from_days(20_742);
+        let mut content = fill(
+            "# {{title}}\nOn {{date}}: {{cursor}}\n{{cursor}}",
+            "Standup",
+            date,
+        );
+        assert_eq!(
+            take_cursor(&mut content),
+            Some(Position::new(1, "On 2026-10-16: ".len() as u32))
+        );
+        assert_eq!(content, "# Standup\nOn 2026-10-16: \n");
+        assert_eq!(take_cursor(&mut content), None);
+    }
+}
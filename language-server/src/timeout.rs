@@ -0,0 +1,204 @@
+//! A time limit on requests, for the `requestTimeoutMs` setting, so that a handler stuck on a
+//! lock or a slow disk answers with an error instead of leaving the editor waiting.
+//!
+//! Requests slower than `slowRequestMs` are logged with where their time went. Handlers say what
+//! they spend time on with [`timed`], e.g. waiting for the index or reading notes.
+
+use std::{
+    cell::RefCell,
+    fmt::Write,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use tower::Service;
+use tower_lsp::{
+    jsonrpc::{Error, ErrorCode, Request, Response},
+    lsp_types::MessageType,
+    Client, ExitedError,
+};
+use tracing::Instrument;
+
+use crate::{logging, metrics::Metrics};
+
+/// The LSP's `RequestFailed` error code.
+const REQUEST_FAILED: i64 = -32803;
+
+/// Requests never timed out: starting up, shutting down, which saves the index cache, and
+/// commands, which write files and run programs that dropping them would stop halfway.
+const UNLIMITED: [&str; 3] = ["initialize", "shutdown", "workspace/executeCommand"];
+
+tokio::task_local! {
+    static TIMINGS: RefCell<Vec<(&'static str, Duration)>>;
+}
+
+/// The limits of the settings, shared with the server, which changes them along with the
+/// settings. Zero turns a limit off.
+#[derive(Debug, Default)]
+pub struct Limits {
+    timeout_ms: AtomicU64,
+    slow_ms: AtomicU64,
+}
+
+impl Limits {
+    pub fn set(&self, timeout_ms: u64, slow_ms: u64) {
+        self.timeout_ms.store(timeout_ms, Ordering::Relaxed);
+        self.slow_ms.store(slow_ms, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> (Option<Duration>, Option<Duration>) {
+        let limit = |ms: u64| (ms > 0).then(|| Duration::from_millis(ms));
+        (
+            limit(self.timeout_ms.load(Ordering::Relaxed)),
+            limit(self.slow_ms.load(Ordering::Relaxed)),
+        )
+    }
+}
+
+/// Run `future`, adding the time it takes to what the request being handled spent on `what`.
+pub async fn timed<T>(what: &'static str, future: impl Future<Output = T>) -> T {
+    let start = Instant::now();
+    let output = future.await;
+    let elapsed = start.elapsed();
+    // Background tasks aren't timed.
+    let _ = TIMINGS.try_with(|timings| {
+        let mut timings = timings.borrow_mut();
+        match timings.iter_mut().find(|(spent_on, _)| *spent_on == what) {
+            Some((_, spent)) => *spent += elapsed,
+            None => timings.push((what, elapsed)),
+        }
+    });
+    output
+}
+
+/// Where a request that took `elapsed` spent its time, e.g. "1.2s (900ms waiting for the index,
+/// 20ms reading notes)".
+fn breakdown(elapsed: Duration, timings: &[(&'static str, Duration)]) -> String {
+    let mut message = format!("{elapsed:.1?}");
+    for (i, (what, spent)) in timings.iter().enumerate() {
+        let separator = if i == 0 { " (" } else { ", " };
+        let _ = write!(message, "{separator}{spent:.1?} {what}");
+    }
+    if !timings.is_empty() {
+        message.push(')');
+    }
+    message
+}
+
+/// A service answering requests that take longer than the limit with an error, counting the
+/// requests in `metrics`.
+pub struct Timeout<S> {
+    inner: S,
+    client: Client,
+    limits: Arc<Limits>,
+    metrics: Arc<Metrics>,
+}
+
+impl<S> Timeout<S> {
+    pub fn new(inner: S, client: Client, limits: Arc<Limits>, metrics: Arc<Metrics>) -> Self {
+        Self {
+            inner,
+            client,
+            limits,
+            metrics,
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S> Service<Request> for Timeout<S>
+where
+    S: Service<Request, Response = Option<Response>, Error = ExitedError>,
+    S::Future: Send + 'static,
+{
+    type Response = Option<Response>;
+    type Error = ExitedError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let method = request.method().to_string();
+        let id = request.id().cloned();
+        let (timeout, slow) = match id {
+            // Notifications aren't waited for.
+            Some(_) if !UNLIMITED.contains(&method.as_str()) => self.limits.get(),
+            _ => (None, None),
+        };
+        let client = self.client.clone();
+        let metrics = Arc::clone(&self.metrics);
+        let span = tracing::info_span!("request", %method, id = ?id);
+        let future = self.inner.call(request).instrument(span.clone());
+
+        Box::pin(TIMINGS.scope(RefCell::default(), async move {
+            let start = Instant::now();
+            let result = match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, future).await.ok(),
+                None => Some(future.await),
+            };
+            let elapsed = start.elapsed();
+            let timings = TIMINGS.with(|timings| timings.take());
+            tracing::debug!(parent: &span, "handled in {}", breakdown(elapsed, &timings));
+            if id.is_some() {
+                metrics.requests.record(elapsed);
+            }
+
+            let Some(result) = result else {
+                metrics.timed_out.fetch_add(1, Ordering::Relaxed);
+                let message = format!("{method} timed out after {}", breakdown(elapsed, &timings));
+                logging::log(&client, MessageType::WARNING, message.clone()).await;
+                let error = Error {
+                    code: ErrorCode::ServerError(REQUEST_FAILED),
+                    message: message.into(),
+                    data: None,
+                };
+                return Ok(id.map(|id| Response::from_error(id, error)));
+            };
+            if slow.is_some_and(|slow| elapsed >= slow) {
+                let message = format!("{method} took {}", breakdown(elapsed, &timings));
+                logging::log(&client, MessageType::INFO, message).await;
+            }
+            result
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn time_is_broken_down() {
+        let timings = TIMINGS
+            .scope(RefCell::default(), async {
+                timed("reading notes", time::sleep(Duration::from_millis(10))).await;
+                timed("waiting for the index", async {}).await;
+                timed("reading notes", time::sleep(Duration::from_millis(10))).await;
+                TIMINGS.with(|timings| timings.take())
+            })
+            .await;
+        assert_eq!(timings.len(), 2);
+        assert_eq!(timings[0].0, "reading notes");
+        assert!(timings[0].1 >= Duration::from_millis(20));
+
+        let timings = [("reading notes", Duration::from_millis(20))];
+        assert_eq!(
+            breakdown(Duration::from_millis(1500), &timings),
+            "1.5s (20.0ms reading notes)"
+        );
+        assert_eq!(breakdown(Duration::from_millis(1500), &[]), "1.5s");
+    }
+}
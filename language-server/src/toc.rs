@@ -0,0 +1,143 @@
+//! Tables of contents kept in notes, for `noteLs.toc.update` and its code action.
+//!
+//! A table of contents is a list of links to the note's headings between `<!-- toc -->` and
+//! `<!-- tocstop -->`, the markers other markdown tools use too, so updating it only touches
+//! what's between them. Links point at the anchors the builtin renderer gives the headings. A
+//! note's title, its only level 1 heading, isn't listed.
+
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+
+use crate::{code_blocks, frontmatter, headings};
+
+pub const START: &str = "<!-- toc -->";
+
+pub const END: &str = "<!-- tocstop -->";
+
+/// The lines of the start and end markers of the table of contents in `document`, if it has one.
+pub fn block(document: &str) -> Option<(u32, u32)> {
+    let blocks = code_blocks::blocks(document);
+    let in_code = |line: u32| {
+        blocks
+            .iter()
+            .any(|block| block.start <= line && line <= block.end)
+    };
+    let mut markers = document
+        .lines()
+        .zip(0..)
+        .filter(|(_, n)| !in_code(*n))
+        .map(|(line, n)| (line.trim(), n));
+    let (_, start) = markers.find(|(line, _)| *line == START)?;
+    let (_, end) = markers.find(|(line, _)| *line == END)?;
+    Some((start, end))
+}
+
+/// The list of links to the headings of `document`, each line ending in a line break. Nested
+/// headings are indented under the ones they belong to.
+pub fn contents(document: &str) -> String {
+    let headings = headings::parse_headings(document);
+    let anchors = headings::anchors(&headings);
+    let titled = headings.iter().filter(|heading| heading.level == 1).count() == 1
+        && headings.first().is_some_and(|heading| heading.level == 1);
+    let listed = headings
+        .iter()
+        .zip(&anchors)
+        .skip(usize::from(titled))
+        .collect::<Vec<_>>();
+    let top = listed
+        .iter()
+        .map(|(heading, _)| heading.level)
+        .min()
+        .unwrap_or(1);
+    listed
+        .into_iter()
+        .map(|(heading, anchor)| {
+            let indent = "  ".repeat((heading.level - top) as usize);
+            format!("{indent}- [{}](#{anchor})\n", heading.text)
+        })
+        .collect()
+}
+
+/// Where a new table of contents goes in `document` when no line is given: after the title if
+/// the note starts with one, or else at the start of the body.
+fn default_line(document: &str) -> u32 {
+    let body_start = frontmatter::body_start(document);
+    let title = headings::parse_headings(document)
+        .into_iter()
+        .next()
+        .filter(|heading| heading.level == 1 && heading.line == body_start);
+    title.map_or(body_start, |title| title.line + 1)
+}
+
+/// The edit bringing the table of contents of `document` up to date, or inserting one before
+/// `line` if there's none yet. There's nothing to do if it's up to date or there are no headings.
+pub fn update(document: &str, line: Option<u32>) -> Option<TextEdit> {
+    let contents = contents(document);
+    if contents.is_empty() {
+        return None;
+    }
+
+    if let Some((start, end)) = block(document) {
+        let current = document
+            .lines()
+            .skip(start as usize + 1)
+            .take((end - start - 1) as usize)
+            .map(|line| format!("{line}\n"))
+            .collect::<String>();
+        let range = Range::new(Position::new(start + 1, 0), Position::new(end, 0));
+        return (current != contents).then(|| TextEdit::new(range, contents));
+    }
+
+    let lines = document.lines().count() as u32;
+    let line = line.unwrap_or_else(|| default_line(document));
+    let block = format!("{START}\n{contents}{END}\n");
+    if line < lines {
+        let start = Position::new(line, 0);
+        return Some(TextEdit::new(Range::new(start, start), block));
+    }
+    // At the end of the document, which may not end in a line break.
+    let last = document.lines().last().unwrap_or_default();
+    let (end, block) = match document.ends_with('\n') || document.is_empty() {
+        true => (Position::new(lines, 0), block),
+        false => (
+            Position::new(lines - 1, last.len() as u32),
+            format!("\n{block}"),
+        ),
+    };
+    Some(TextEdit::new(Range::new(end, end), block))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_of_contents_are_inserted_and_updated() {
+        let document = "---\ntitle: Tea\n---\n# Tea\nIntro\n## Kinds\n### Green\n## Kinds\n\
+                        ```\n## Not a heading\n```\n";
+        let edit = update(document, None).unwrap();
+        assert_eq!(edit.range.start, Position::new(4, 0));
+        assert_eq!(
+            edit.new_text,
+            "<!-- toc -->\n- [Kinds](#kinds)\n  - [Green](#green)\n- [Kinds](#kinds-1)\n\
+             <!-- tocstop -->\n"
+        );
+
+        let document = "# One\n<!-- toc -->\n- [Old](#old)\n<!-- tocstop -->\n# Two";
+        let edit = update(document, Some(0)).unwrap();
+        assert_eq!(
+            edit.range,
+            Range::new(Position::new(2, 0), Position::new(3, 0))
+        );
+        assert_eq!(edit.new_text, "- [One](#one)\n- [Two](#two)\n");
+        let updated = "# One\n<!-- toc -->\n- [One](#one)\n- [Two](#two)\n<!-- tocstop -->\n# Two";
+        assert_eq!(update(updated, None), None);
+
+        let edit = update("Text\n## Only", Some(5)).unwrap();
+        assert_eq!(edit.range.start, Position::new(1, 7));
+        assert_eq!(
+            edit.new_text,
+            "\n<!-- toc -->\n- [Only](#only)\n<!-- tocstop -->\n"
+        );
+        assert_eq!(update("No headings", None), None);
+    }
+}
@@ -0,0 +1,111 @@
+//! Setting up a new vault, for `noteLs.initVault`: the folders notes, daily notes and attachments
+//! go in, templates for new notes and daily notes, and a home note to start from.
+//!
+//! Nothing that's already in the vault is overwritten, so running it again only adds what's
+//! missing. Settings come from the editor rather than from a file in the vault, so the ones
+//! that use these folders are returned for the client to save, and listed in the home note.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+
+/// The home note, relative to the vault root.
+pub const HOME: &str = "home.md";
+
+/// The folders of a new vault, relative to its root.
+const FOLDERS: [&str; 3] = ["templates", "journal", "attachments"];
+
+const NOTE_TEMPLATE: &str = "templates/note.md";
+
+const DAILY_TEMPLATE: &str = "templates/daily.md";
+
+/// The settings that use the folders and templates of a new vault.
+pub fn settings() -> Value {
+    json!({
+        "templatesFolder": "templates",
+        "noteTemplate": NOTE_TEMPLATE,
+        "dailyNoteTemplate": DAILY_TEMPLATE,
+        "dailyNotePattern": "journal/{YYYY}/{MM}/{YYYY-MM-DD}.md",
+    })
+}
+
+/// The files of a new vault, relative to its root, with their content.
+fn files() -> Vec<(&'static str, String)> {
+    let settings = serde_json::to_string_pretty(&settings()).unwrap_or_default();
+    let home = format!(
+        "# Home\n\n\
+         Welcome to your vault. Link notes with `[[note name]]` and tag them with `#tags`.\n\n\
+         - `templates/` holds templates for new notes, used by `noteLs.newNote`.\n\
+         - `journal/` holds daily notes, opened with `noteLs.dailyNote.open`.\n\
+         - `attachments/` is for images and other files notes link to.\n\n\
+         These settings make use of them:\n\n\
+         ```json\n{settings}\n```\n"
+    );
+    vec![
+        (
+            NOTE_TEMPLATE,
+            String::from("---\ntitle: {{title}}\ndate: {{date}}\ntags: []\n---\n# {{title}}\n\n{{cursor}}\n"),
+        ),
+        (
+            DAILY_TEMPLATE,
+            String::from("---\ndate: {{date}}\ntags: [journal]\n---\n# {{date}}\n\n## Tasks\n\n- [ ] {{cursor}}\n\n## Notes\n"),
+        ),
+        (HOME, home),
+    ]
+}
+
+/// Create the folders and files of a new vault in `root` that aren't there yet, returning the
+/// paths of those created.
+pub fn scaffold(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut created = Vec::new();
+    for folder in FOLDERS {
+        let path = root.join(folder);
+        if !path.exists() {
+            fs::create_dir_all(&path)?;
+            created.push(path);
+        }
+    }
+    for (file, content) in files() {
+        let path = root.join(file);
+        if !path.exists() {
+            fs::write(&path, content)?;
+            created.push(path);
+        }
+    }
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vaults_are_scaffolded_without_overwriting() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::write(root.join(HOME), "# My home").unwrap();
+
+        let created = scaffold(root).unwrap();
+        assert_eq!(
+            created,
+            [
+                root.join("templates"),
+                root.join("journal"),
+                root.join("attachments"),
+                root.join(NOTE_TEMPLATE),
+                root.join(DAILY_TEMPLATE),
+            ]
+        );
+        assert_eq!(fs::read_to_string(root.join(HOME)).unwrap(), "# My home");
+        let template = fs::read_to_string(root.join(NOTE_TEMPLATE)).unwrap();
+        assert!(template.contains("# {{title}}"));
+
+        fs::remove_file(root.join(HOME)).unwrap();
+        assert_eq!(scaffold(root).unwrap(), [root.join(HOME)]);
+        let home = fs::read_to_string(root.join(HOME)).unwrap();
+        assert!(home.contains("\"dailyNoteTemplate\": \"templates/daily.md\""));
+    }
+}
@@ -0,0 +1,183 @@
+//! The vaults open in the editor, one per workspace folder, each with an index of its own.
+//!
+//! Requests about a note are answered from the vault containing it, so completions, links and
+//! backlinks never cross from one vault into another. Notes opened from outside every vault are
+//! kept in an index of their own.
+
+use std::path::{Path, PathBuf};
+
+use crate::{index::NoteIndex, links};
+
+#[derive(Debug)]
+pub struct Vault {
+    pub root: PathBuf,
+    pub index: NoteIndex,
+    /// Whether every note in the vault was indexed, rather than only the open ones. Links can only
+    /// be checked if it was.
+    pub indexed: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Vaults {
+    vaults: Vec<Vault>,
+    /// Notes opened from outside every vault.
+    loose: NoteIndex,
+}
+
+impl Vaults {
+    /// Add `vault`, replacing any vault with the same root.
+    pub fn insert(&mut self, vault: Vault) {
+        match self.vaults.iter_mut().find(|v| v.root == vault.root) {
+            Some(existing) => *existing = vault,
+            None => self.vaults.push(vault),
+        }
+    }
+
+    /// Remove the vault at `root`, if there is one.
+    pub fn remove(&mut self, root: &Path) -> Option<Vault> {
+        let i = self.vaults.iter().position(|vault| vault.root == root)?;
+        Some(self.vaults.remove(i))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Vault> {
+        self.vaults.iter()
+    }
+
+    /// The vault at `root`.
+    pub fn get_mut(&mut self, root: &Path) -> Option<&mut Vault> {
+        self.vaults.iter_mut().find(|vault| vault.root == root)
+    }
+
+    /// The vault opened first, which settings and plugins are taken from.
+    pub fn first(&self) -> Option<&Vault> {
+        self.vaults.first()
+    }
+
+    fn position(&self, path: &Path) -> Option<usize> {
+        let path = links::nfc_path(path);
+        // Nested vaults win over the ones containing them.
+        self.vaults
+            .iter()
+            .enumerate()
+            .filter(|(_, vault)| path.starts_with(links::nfc_path(&vault.root)))
+            .max_by_key(|(_, vault)| vault.root.components().count())
+            .map(|(i, _)| i)
+    }
+
+    /// The vault containing the file at `path`.
+    pub fn vault(&self, path: &Path) -> Option<&Vault> {
+        Some(&self.vaults[self.position(path)?])
+    }
+
+    /// The root of the vault containing the file at `path`.
+    pub fn root(&self, path: &Path) -> Option<&Path> {
+        self.vault(path).map(|vault| vault.root.as_path())
+    }
+
+    /// Whether every note of the vault containing `path` was indexed.
+    pub fn indexed(&self, path: &Path) -> bool {
+        self.vault(path).is_some_and(|vault| vault.indexed)
+    }
+
+    /// The index of the vault containing the file at `path`, or of the notes outside every vault.
+    pub fn index_for(&self, path: &Path) -> &NoteIndex {
+        match self.position(path) {
+            Some(i) => &self.vaults[i].index,
+            None => &self.loose,
+        }
+    }
+
+    pub fn index_for_mut(&mut self, path: &Path) -> &mut NoteIndex {
+        match self.position(path) {
+            Some(i) => &mut self.vaults[i].index,
+            None => &mut self.loose,
+        }
+    }
+
+    /// Every index, including the one of notes outside every vault.
+    pub fn indexes(&self) -> impl Iterator<Item = &NoteIndex> {
+        self.vaults
+            .iter()
+            .map(|vault| &vault.index)
+            .chain([&self.loose])
+    }
+
+    pub fn indexes_mut(&mut self) -> impl Iterator<Item = &mut NoteIndex> {
+        self.vaults
+            .iter_mut()
+            .map(|vault| &mut vault.index)
+            .chain([&mut self.loose])
+    }
+
+    /// The URL prefixes the preview serves each vault under: the first one at `/`, the others
+    /// under their folder names.
+    pub fn preview_roots(&self) -> Vec<(String, PathBuf)> {
+        self.vaults
+            .iter()
+            .enumerate()
+            .map(|(i, vault)| {
+                let prefix = match (i, vault.root.file_name()) {
+                    (0, _) | (_, None) => String::from("/"),
+                    (_, Some(name)) => format!("/{}/", name.to_string_lossy()),
+                };
+                (prefix, vault.root.clone())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vault(root: &str) -> Vault {
+        Vault {
+            root: PathBuf::from(root),
+            index: NoteIndex::from_paths(Path::new(root), Vec::new()),
+            indexed: true,
+        }
+    }
+
+    #[test]
+    fn notes_belong_to_the_innermost_vault() {
+        let mut vaults = Vaults::default();
+        vaults.insert(vault("/work"));
+        vaults.insert(vault("/home"));
+        vaults.insert(vault("/work/team"));
+
+        assert_eq!(
+            vaults.root(Path::new("/work/team/note.md")),
+            Some(Path::new("/work/team"))
+        );
+        assert_eq!(
+            vaults.root(Path::new("/work/note.md")),
+            Some(Path::new("/work"))
+        );
+        assert_eq!(vaults.root(Path::new("/workshop/note.md")), None);
+
+        vaults
+            .index_for_mut(Path::new("/elsewhere/note.md"))
+            .update("/elsewhere/note.md".into(), "");
+        assert!(vaults
+            .index_for(Path::new("/home/x.md"))
+            .get(Path::new("/elsewhere/note.md"))
+            .is_none());
+        assert!(vaults
+            .index_for(Path::new("/elsewhere/other.md"))
+            .get(Path::new("/elsewhere/note.md"))
+            .is_some());
+
+        vaults.remove(Path::new("/work/team"));
+        assert_eq!(
+            vaults.root(Path::new("/work/team/note.md")),
+            Some(Path::new("/work"))
+        );
+        assert_eq!(
+            vaults.preview_roots(),
+            vec![
+                (String::from("/"), PathBuf::from("/work")),
+                (String::from("/home/"), PathBuf::from("/home"))
+            ]
+        );
+    }
+}
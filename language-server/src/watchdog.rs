@@ -0,0 +1,93 @@
+//! Background tasks the server restarts when they're wedged, for the `watchdogTimeoutMs` setting.
+//!
+//! A task beats its [`Heartbeat`] around every piece of work it does. It's wedged once it stops,
+//! e.g. because it panicked, or once it has been busy with one piece of work for too long.
+
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::task::JoinHandle;
+
+/// How often the server checks on its background tasks.
+pub const INTERVAL: Duration = Duration::from_secs(5);
+
+/// When the task started the work it's busy with, if it's busy.
+#[derive(Debug, Clone, Default)]
+pub struct Heartbeat {
+    busy_since: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Heartbeat {
+    /// Start a piece of work.
+    pub fn busy(&self) {
+        *self.busy_since.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Finish the piece of work.
+    pub fn idle(&self) {
+        *self.busy_since.lock().unwrap() = None;
+    }
+
+    fn busy_for(&self) -> Option<Duration> {
+        self.busy_since.lock().unwrap().map(|since| since.elapsed())
+    }
+}
+
+/// A background task. Dropping it stops the task.
+#[derive(Debug)]
+pub struct Task {
+    handle: JoinHandle<()>,
+    heartbeat: Heartbeat,
+}
+
+impl Task {
+    /// Spawn the task that `run` makes, giving it the heartbeat to beat.
+    pub fn spawn<F>(run: impl FnOnce(Heartbeat) -> F) -> Self
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let heartbeat = Heartbeat::default();
+        Self {
+            handle: tokio::spawn(run(heartbeat.clone())),
+            heartbeat,
+        }
+    }
+
+    /// Whether the task stopped, or has been busy with one piece of work for longer than `limit`.
+    pub fn is_wedged(&self, limit: Duration) -> bool {
+        self.handle.is_finished() || self.heartbeat.busy_for().is_some_and(|busy| busy > limit)
+    }
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn stuck_and_stopped_tasks_are_wedged() {
+        let limit = Duration::from_millis(20);
+        let idle = Task::spawn(|_| std::future::pending());
+        let stuck = Task::spawn(|heartbeat| async move {
+            heartbeat.busy();
+            std::future::pending::<()>().await;
+        });
+        let panicked = Task::spawn(|_| async { panic!("the task broke") });
+
+        time::sleep(limit * 3).await;
+        assert!(!idle.is_wedged(limit));
+        assert!(stuck.is_wedged(limit));
+        assert!(!stuck.is_wedged(limit * 100));
+        assert!(panicked.is_wedged(limit));
+    }
+}
@@ -0,0 +1,43 @@
+//! Generous upper bounds on core operations, so that accidentally quadratic code fails the test
+//! suite instead of only showing up in benchmark runs (`cargo bench`).
+
+use std::time::{Duration, Instant};
+
+use language_server::{completion, config::LinkStyle, index::NoteIndex};
+
+#[path = "../benches/fixtures/mod.rs"]
+mod fixtures;
+
+const VAULT_SIZE: usize = 2_000;
+
+/// Run `f`, failing if it takes longer than `budget`.
+fn within<T>(what: &str, budget: Duration, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    assert!(
+        elapsed <= budget,
+        "{what} took {elapsed:?}, over its budget of {budget:?}"
+    );
+    result
+}
+
+#[test]
+fn core_operations_stay_within_budget() {
+    let vault = fixtures::vault(VAULT_SIZE);
+
+    let index = within("indexing", Duration::from_secs(10), || {
+        NoteIndex::scan(vault.path())
+    });
+
+    let completions = within("completion", Duration::from_secs(1), || {
+        completion::note_completions(&index, &vault.path().join("index.md"), LinkStyle::Relative)
+    });
+    assert_eq!(completions.len(), VAULT_SIZE);
+
+    let target = vault.path().join(format!("{}.md", fixtures::note_name(42)));
+    let backlinks = within("references", Duration::from_secs(2), || {
+        index.backlinks(&target)
+    });
+    assert!(!backlinks.is_empty());
+}
@@ -0,0 +1,208 @@
+//! Drives the language server end-to-end over an in-memory transport.
+
+use std::{fs, path::Path};
+
+use language_server::server::MarkdownLanguageServer;
+use serde_json::{json, Value};
+use tempfile::TempDir;
+use tokio::io::{
+    self, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, DuplexStream, ReadHalf,
+    WriteHalf,
+};
+use tower_lsp::{lsp_types::Url, Server};
+
+/// Create a vault in a temporary directory from `(path, content)` pairs.
+pub fn vault(files: &[(&str, &str)]) -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for (path, content) in files {
+        let path = dir.path().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    dir
+}
+
+/// The URI of the file at `path` in `vault`.
+pub fn uri(vault: &Path, path: &str) -> Url {
+    Url::from_file_path(vault.join(path)).unwrap()
+}
+
+/// A fake editor talking to a server running on the same runtime.
+pub struct TestClient {
+    reader: BufReader<ReadHalf<DuplexStream>>,
+    writer: WriteHalf<DuplexStream>,
+    next_id: i64,
+    /// Notifications received while waiting for something else.
+    pub notifications: Vec<Value>,
+    /// The workspace edits the server asked to apply, which are always applied.
+    pub edits: Vec<Value>,
+}
+
+impl TestClient {
+    /// Start a server and initialize it with the vault at `root` as its workspace.
+    ///
+    /// The diagnostics pass over the vault is turned off, so tests only see the diagnostics of
+    /// the notes they open.
+    pub async fn start(root: &Path) -> Self {
+        Self::start_with(
+            root,
+            json!({ "preview": false, "vaultDiagnosticsLimit": 0 }),
+        )
+        .await
+    }
+
+    /// Start a server like `start`, initialized with the settings `options`.
+    pub async fn start_with(root: &Path, options: Value) -> Self {
+        Self::initialize(json!({
+            "capabilities": {},
+            "rootUri": Url::from_directory_path(root).unwrap(),
+            "initializationOptions": options,
+        }))
+        .await
+    }
+
+    /// Start a server and initialize it with `params`, waiting until it has indexed the vault.
+    ///
+    /// The client claims to show progress, which tells it when indexing is done.
+    pub async fn initialize(mut params: Value) -> Self {
+        params["capabilities"]["window"]["workDoneProgress"] = json!(true);
+        let (client_stream, server_stream) = io::duplex(1 << 16);
+        let (server_read, server_write) = io::split(server_stream);
+        let (service, socket) = MarkdownLanguageServer::service();
+        tokio::spawn(Server::new(server_read, server_write, socket).serve(service));
+
+        let (reader, writer) = io::split(client_stream);
+        let mut client = Self {
+            reader: BufReader::new(reader),
+            writer,
+            next_id: 0,
+            notifications: Vec::new(),
+            edits: Vec::new(),
+        };
+
+        client.request("initialize", params).await;
+        client.notify("initialized", json!({})).await;
+        client
+            .receive_until(|message| {
+                message["method"] == "$/progress" && message["params"]["value"]["kind"] == "end"
+            })
+            .await;
+        client
+    }
+
+    async fn send(&mut self, message: Value) {
+        let body = message.to_string();
+        let frame = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        self.writer.write_all(frame.as_bytes()).await.unwrap();
+    }
+
+    async fn receive(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            self.reader.read_line(&mut header).await.unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length: ") {
+                length = value.parse().unwrap();
+            }
+        }
+
+        let mut body = vec![0; length];
+        self.reader.read_exact(&mut body).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// Read messages until `matches` accepts one, answering requests from the server and
+    /// stashing notifications along the way.
+    async fn receive_until(&mut self, matches: impl Fn(&Value) -> bool) -> Value {
+        loop {
+            let message = self.receive().await;
+            if matches(&message) {
+                return message;
+            }
+            if message["method"] == "workspace/applyEdit" {
+                self.edits.push(message["params"]["edit"].clone());
+                let result = json!({ "applied": true });
+                self.send(json!({ "jsonrpc": "2.0", "id": message["id"], "result": result }))
+                    .await;
+            } else if message.get("id").is_some() && message.get("method").is_some() {
+                let id = message["id"].clone();
+                self.send(json!({ "jsonrpc": "2.0", "id": id, "result": null }))
+                    .await;
+            } else if message.get("method").is_some() {
+                self.notifications.push(message);
+            }
+        }
+    }
+
+    /// Send a request and wait for its result, panicking if the server returns an error.
+    ///
+    /// `null` params are left out, for requests that take none.
+    pub async fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = self.next_id;
+        let mut request = json!({ "jsonrpc": "2.0", "id": id, "method": method });
+        if !params.is_null() {
+            request["params"] = params;
+        }
+        self.send(request).await;
+
+        let response = self
+            .receive_until(|message| message["id"] == json!(id) && message.get("method").is_none())
+            .await;
+        assert!(
+            response.get("error").is_none(),
+            "{method} failed: {response}"
+        );
+        response["result"].clone()
+    }
+
+    pub async fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+            .await;
+    }
+
+    /// Take the notification `method` if it came already, or wait for it.
+    pub async fn notification(&mut self, method: &str) -> Value {
+        if let Some(i) = self
+            .notifications
+            .iter()
+            .position(|n| n["method"] == method)
+        {
+            return self.notifications.remove(i)["params"].clone();
+        }
+        let notification = self
+            .receive_until(|message| message["method"] == method)
+            .await;
+        notification["params"].clone()
+    }
+
+    /// Open a document and wait for the diagnostics the server publishes for it.
+    ///
+    /// Waiting also makes sure the server has seen the document before any further requests.
+    pub async fn open(&mut self, uri: &Url, text: &str) -> Value {
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": uri, "languageId": "markdown", "version": 1, "text": text },
+            }),
+        )
+        .await;
+        self.diagnostics(uri).await
+    }
+
+    /// Wait for the next diagnostics the server publishes for `uri`.
+    pub async fn diagnostics(&mut self, uri: &Url) -> Value {
+        let uri = json!(uri);
+        let diagnostics = self
+            .receive_until(|message| {
+                message["method"] == "textDocument/publishDiagnostics"
+                    && message["params"]["uri"] == uri
+            })
+            .await;
+        diagnostics["params"]["diagnostics"].clone()
+    }
+}
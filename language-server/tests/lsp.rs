@@ -0,0 +1,773 @@
+//! End-to-end tests of the language server against small fixture vaults.
+
+mod common;
+
+use common::{uri, vault, TestClient};
+use serde_json::json;
+use tower_lsp::lsp_types::Url;
+
+const NOTE: &str = "# Note\nSee [[other#Second]] and [[missing]].\n#project\n[[";
+
+#[tokio::test]
+async fn broken_links_are_reported_on_open() {
+    let vault = vault(&[("note.md", NOTE), ("other.md", "# Other\n## Second")]);
+    let mut client = TestClient::start(vault.path()).await;
+
+    let diagnostics = client.open(&uri(vault.path(), "note.md"), NOTE).await;
+    let diagnostics = diagnostics.as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["message"], "No note named \"missing\"");
+}
+
+#[tokio::test]
+async fn vault_is_checked_after_indexing() {
+    let vault = vault(&[
+        ("a.md", "[[missing]]"),
+        ("b.md", "[[a]]"),
+        ("c.md", "[[gone]]"),
+    ]);
+    let options = json!({ "preview": false, "vaultDiagnosticsLimit": 2 });
+    let mut client = TestClient::start_with(vault.path(), options).await;
+
+    let diagnostics = client.diagnostics(&uri(vault.path(), "a.md")).await;
+    assert_eq!(diagnostics[0]["message"], "No note named \"missing\"");
+    let diagnostics = client.diagnostics(&uri(vault.path(), "b.md")).await;
+    assert_eq!(diagnostics, json!([]));
+
+    // Notes past the limit are only checked once opened, so the first diagnostics of c.md are
+    // for what's in the editor rather than on disk.
+    let diagnostics = client
+        .open(&uri(vault.path(), "c.md"), "[[elsewhere]]")
+        .await;
+    assert_eq!(diagnostics[0]["message"], "No note named \"elsewhere\"");
+}
+
+#[tokio::test]
+async fn changed_settings_refresh_diagnostics() {
+    let vault = vault(&[("note.md", NOTE), ("other.md", "# Other\n## Second")]);
+    let note = uri(vault.path(), "note.md");
+    let mut client = TestClient::start(vault.path()).await;
+    let diagnostics = client.open(&note, NOTE).await;
+    assert_eq!(diagnostics[0]["severity"], 2);
+
+    client
+        .notify(
+            "workspace/didChangeConfiguration",
+            json!({ "settings": { "noteLs": { "preview": false, "brokenLinkSeverity": "error" } } }),
+        )
+        .await;
+    let diagnostics = client.diagnostics(&note).await;
+    assert_eq!(diagnostics[0]["severity"], 1);
+}
+
+#[tokio::test]
+async fn diagnostics_can_be_turned_off() {
+    let vault = vault(&[("note.md", NOTE), ("other.md", "# Other\n## Second")]);
+    let note = uri(vault.path(), "note.md");
+    let mut client = TestClient::start(vault.path()).await;
+    let diagnostics = client.open(&note, NOTE).await;
+    assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+
+    client
+        .notify(
+            "workspace/didChangeConfiguration",
+            json!({ "settings": { "preview": false, "diagnostics": false } }),
+        )
+        .await;
+    assert_eq!(client.diagnostics(&note).await, json!([]));
+}
+
+#[tokio::test]
+async fn notes_changed_on_disk_are_indexed_again() {
+    let vault = vault(&[("note.md", NOTE), ("other.md", "# Other\n## Second")]);
+    let note = uri(vault.path(), "note.md");
+    let mut client = TestClient::start(vault.path()).await;
+    let diagnostics = client.open(&note, NOTE).await;
+    assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+
+    // Like a checkout, creating the missing note among many other changes.
+    let mut changes = Vec::new();
+    for i in 0..200 {
+        let name = format!("new {i}.md");
+        std::fs::write(vault.path().join(&name), "").unwrap();
+        changes.push(json!({ "uri": uri(vault.path(), &name), "type": 1 }));
+    }
+    std::fs::write(vault.path().join("missing.md"), "").unwrap();
+    changes.push(json!({ "uri": uri(vault.path(), "missing.md"), "type": 1 }));
+    client
+        .notify(
+            "workspace/didChangeWatchedFiles",
+            json!({ "changes": changes }),
+        )
+        .await;
+
+    assert_eq!(client.diagnostics(&note).await, json!([]));
+
+    let changed = client
+        .notifications
+        .iter()
+        .find(|message| message["method"] == "noteLs/indexChanged")
+        .expect("the client should be told about the new notes");
+    assert_eq!(changed["params"]["added"].as_array().unwrap().len(), 201);
+    assert_eq!(changed["params"]["reindexed"], false);
+}
+
+#[tokio::test]
+async fn large_vaults_are_not_indexed() {
+    let vault = vault(&[("note.md", NOTE), ("other.md", "# Other\n## Second")]);
+    let options = json!({ "preview": false, "maxIndexedNotes": 1 });
+    let mut client = TestClient::start_with(vault.path(), options).await;
+
+    let warning = client
+        .notifications
+        .iter()
+        .find(|message| message["method"] == "window/showMessage")
+        .expect("the user should be told the vault isn't indexed");
+    assert!(warning["params"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("maxIndexedNotes"));
+
+    // Without the index, links to notes that aren't open can't be checked.
+    let diagnostics = client.open(&uri(vault.path(), "note.md"), NOTE).await;
+    assert_eq!(diagnostics, json!([]));
+}
+
+#[tokio::test]
+async fn indexing_progress_is_reported() {
+    let vault = vault(&[("note.md", NOTE), ("other.md", "# Other\n## Second")]);
+    let client = TestClient::start(vault.path()).await;
+
+    let progress = client
+        .notifications
+        .iter()
+        .filter(|message| message["method"] == "$/progress")
+        .map(|message| &message["params"]["value"])
+        .collect::<Vec<_>>();
+    assert_eq!(progress[0]["kind"], "begin");
+    assert_eq!(progress[0]["title"], "Indexing notes");
+    assert_eq!(progress[1]["message"], "2/2");
+    assert_eq!(progress[1]["percentage"], 100);
+}
+
+#[tokio::test]
+async fn completion_offers_notes_tags_and_headings() {
+    let vault = vault(&[
+        ("note.md", NOTE),
+        ("other.md", "# Other\n#project #reading"),
+        ("sub/nested.md", ""),
+    ]);
+    let note = uri(vault.path(), "note.md");
+    let mut client = TestClient::start(vault.path()).await;
+    client.open(&note, NOTE).await;
+
+    let labels = |result: serde_json::Value| {
+        result["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let notes = client
+        .request(
+            "textDocument/completion",
+            json!({ "textDocument": { "uri": note }, "position": { "line": 3, "character": 2 } }),
+        )
+        .await;
+    assert_eq!(labels(notes), vec!["other.md", "sub/nested.md"]);
+
+    let tags = client
+        .request(
+            "textDocument/completion",
+            json!({ "textDocument": { "uri": note }, "position": { "line": 2, "character": 3 } }),
+        )
+        .await;
+    assert_eq!(labels(tags), vec!["project", "reading"]);
+
+    let headings = client
+        .request(
+            "textDocument/completion",
+            json!({ "textDocument": { "uri": note }, "position": { "line": 1, "character": 12 } }),
+        )
+        .await;
+    assert_eq!(labels(headings), vec!["Other"]);
+}
+
+#[tokio::test]
+async fn goto_definition_jumps_to_the_linked_heading() {
+    let vault = vault(&[("note.md", NOTE), ("other.md", "# Other\ntext\n## Second")]);
+    let note = uri(vault.path(), "note.md");
+    let mut client = TestClient::start(vault.path()).await;
+    client.open(&note, NOTE).await;
+
+    let location = client
+        .request(
+            "textDocument/definition",
+            json!({ "textDocument": { "uri": note }, "position": { "line": 1, "character": 8 } }),
+        )
+        .await;
+    assert_eq!(location["uri"], json!(uri(vault.path(), "other.md")));
+    assert_eq!(location["range"]["start"]["line"], 2);
+
+    let nothing = client
+        .request(
+            "textDocument/definition",
+            json!({ "textDocument": { "uri": note }, "position": { "line": 0, "character": 2 } }),
+        )
+        .await;
+    assert!(nothing.is_null());
+}
+
+#[tokio::test]
+async fn markdown_links_complete_and_navigate() {
+    const LINKS: &str = "See [other](sub/other%20note.md#second) and [x](sub/";
+    let vault = vault(&[
+        ("note.md", LINKS),
+        ("sub/other note.md", "# Other\ntext\n## Second"),
+    ]);
+    let note = uri(vault.path(), "note.md");
+    let other = uri(vault.path(), "sub/other note.md");
+    let mut client = TestClient::start(vault.path()).await;
+    client.open(&note, LINKS).await;
+
+    let notes = client
+        .request(
+            "textDocument/completion",
+            json!({ "textDocument": { "uri": note }, "position": { "line": 0, "character": 52 } }),
+        )
+        .await;
+    let item = &notes["items"][0];
+    assert_eq!(item["textEdit"]["newText"], "sub/other%20note.md");
+    assert_eq!(item["textEdit"]["range"]["start"]["character"], 48);
+
+    let headings = client
+        .request(
+            "textDocument/completion",
+            json!({ "textDocument": { "uri": note }, "position": { "line": 0, "character": 32 } }),
+        )
+        .await;
+    assert_eq!(headings["items"][1]["insertText"], "second");
+
+    let location = client
+        .request(
+            "textDocument/definition",
+            json!({ "textDocument": { "uri": note }, "position": { "line": 0, "character": 6 } }),
+        )
+        .await;
+    assert_eq!(location["uri"], json!(other));
+    assert_eq!(location["range"]["start"]["line"], 2);
+
+    let references = client
+        .request(
+            "textDocument/references",
+            json!({
+                "textDocument": { "uri": note },
+                "position": { "line": 0, "character": 6 },
+                "context": { "includeDeclaration": false }
+            }),
+        )
+        .await;
+    assert_eq!(references[0]["uri"], json!(note));
+}
+
+#[tokio::test]
+async fn renaming_a_heading_updates_its_links() {
+    const NOTE: &str = "# Note\n## Old\n";
+    let vault = vault(&[("note.md", NOTE), ("a.md", "[[note#Old]] [x](note.md#old)")]);
+    let note = uri(vault.path(), "note.md");
+    let a = uri(vault.path(), "a.md");
+    let mut client = TestClient::start(vault.path()).await;
+    client.open(&note, NOTE).await;
+
+    let prepared = client
+        .request(
+            "textDocument/prepareRename",
+            json!({ "textDocument": { "uri": note }, "position": { "line": 1, "character": 4 } }),
+        )
+        .await;
+    assert_eq!(prepared["placeholder"], "Old");
+    assert_eq!(prepared["range"]["start"]["character"], 3);
+
+    let edit = client
+        .request(
+            "textDocument/rename",
+            json!({
+                "textDocument": { "uri": note },
+                "position": { "line": 1, "character": 4 },
+                "newName": "New Part"
+            }),
+        )
+        .await;
+    let changes = &edit["changes"];
+    assert_eq!(changes[note.as_str()][0]["newText"], "New Part");
+    assert_eq!(changes[a.as_str()][0]["newText"], "[[note#New Part]]");
+    assert_eq!(changes[a.as_str()][1]["newText"], "[x](note.md#new-part)");
+}
+
+#[tokio::test]
+async fn renames_count_positions_in_utf16() {
+    const NOTE: &str = "# Note\n## Café 😀\n";
+    let vault = vault(&[("note.md", NOTE), ("a.md", "[[note#Café 😀]]")]);
+    let note = uri(vault.path(), "note.md");
+    let a = uri(vault.path(), "a.md");
+    let mut client = TestClient::start(vault.path()).await;
+    client.open(&note, NOTE).await;
+
+    let prepared = client
+        .request(
+            "textDocument/prepareRename",
+            json!({ "textDocument": { "uri": note }, "position": { "line": 1, "character": 9 } }),
+        )
+        .await;
+    assert_eq!(prepared["placeholder"], "Café 😀");
+    assert_eq!(prepared["range"]["end"]["character"], 10);
+
+    let edit = client
+        .request(
+            "textDocument/rename",
+            json!({
+                "textDocument": { "uri": note },
+                "position": { "line": 1, "character": 9 },
+                "newName": "Tea"
+            }),
+        )
+        .await;
+    let changes = &edit["changes"];
+    assert_eq!(changes[note.as_str()][0]["range"]["end"]["character"], 10);
+    assert_eq!(changes[a.as_str()][0]["newText"], "[[note#Tea]]");
+    assert_eq!(changes[a.as_str()][0]["range"]["end"]["character"], 16);
+}
+
+#[tokio::test]
+async fn hovers_count_positions_in_utf16() {
+    const NOTE: &str = "é 😀 [[other]]";
+    let vault = vault(&[("note.md", NOTE), ("other.md", "# Other\nText")]);
+    let note = uri(vault.path(), "note.md");
+    let mut client = TestClient::start(vault.path()).await;
+    client.open(&note, NOTE).await;
+
+    let hover = client
+        .request(
+            "textDocument/hover",
+            json!({ "textDocument": { "uri": note }, "position": { "line": 0, "character": 13 } }),
+        )
+        .await;
+    assert_eq!(hover["range"]["start"]["character"], 5);
+    assert_eq!(hover["range"]["end"]["character"], 14);
+}
+
+#[tokio::test]
+async fn links_resolve_from_the_workspace_folder() {
+    let note_content = "[[top]] ![[assets/image.png]]";
+    let vault = vault(&[
+        ("top.md", "# Top"),
+        ("assets/image.png", ""),
+        ("sub/deep/note.md", note_content),
+    ]);
+    let note = uri(vault.path(), "sub/deep/note.md");
+    let mut client = TestClient::initialize(json!({
+        "capabilities": {},
+        "workspaceFolders": [{ "uri": uri(vault.path(), ""), "name": "vault" }],
+        "initializationOptions": { "preview": false, "vaultDiagnosticsLimit": 0 },
+    }))
+    .await;
+    client.notify("initialized", json!({})).await;
+
+    let diagnostics = client.open(&note, note_content).await;
+    assert_eq!(diagnostics, json!([]));
+    let location = client
+        .request(
+            "textDocument/definition",
+            json!({ "textDocument": { "uri": note }, "position": { "line": 0, "character": 3 } }),
+        )
+        .await;
+    assert_eq!(location["uri"], json!(uri(vault.path(), "top.md")));
+}
+
+#[tokio::test]
+async fn workspace_folders_are_separate_vaults() {
+    let vault = vault(&[
+        ("work/note.md", "[[home]]"),
+        ("work/other.md", ""),
+        ("home/home.md", ""),
+    ]);
+    let folder = |name: &str| json!({ "uri": uri(vault.path(), name), "name": name });
+    let mut client = TestClient::initialize(json!({
+        "capabilities": {},
+        "workspaceFolders": [folder("work"), folder("home")],
+        "initializationOptions": { "preview": false, "vaultDiagnosticsLimit": 0 },
+    }))
+    .await;
+    client.notify("initialized", json!({})).await;
+
+    let note = uri(vault.path(), "work/note.md");
+    let diagnostics = client.open(&note, "[[home]]\n[[").await;
+    assert_eq!(diagnostics[0]["message"], "No note named \"home\"");
+    let completions = client
+        .request(
+            "textDocument/completion",
+            json!({ "textDocument": { "uri": note }, "position": { "line": 1, "character": 2 } }),
+        )
+        .await;
+    assert_eq!(completions["items"][0]["label"], "other.md");
+    assert_eq!(completions["items"].as_array().unwrap().len(), 1);
+
+    // Outside every vault, links can't be checked.
+    client
+        .notify(
+            "workspace/didChangeWorkspaceFolders",
+            json!({ "event": { "added": [], "removed": [folder("work")] } }),
+        )
+        .await;
+    assert_eq!(client.diagnostics(&note).await, json!([]));
+}
+
+#[tokio::test]
+async fn diagnostics_can_be_pulled() {
+    let vault = vault(&[("note.md", NOTE), ("other.md", "# Other\n## Second")]);
+    let note = uri(vault.path(), "note.md");
+    let mut client = TestClient::initialize(json!({
+        "capabilities": { "textDocument": { "diagnostic": {} } },
+        "rootUri": uri(vault.path(), ""),
+        "initializationOptions": { "preview": false },
+    }))
+    .await;
+
+    let report = client
+        .request(
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": note } }),
+        )
+        .await;
+    assert_eq!(report["kind"], "full");
+    assert_eq!(report["items"][0]["message"], "No note named \"missing\"");
+    let report = client
+        .request(
+            "textDocument/diagnostic",
+            json!({ "textDocument": { "uri": note }, "previousResultId": report["resultId"] }),
+        )
+        .await;
+    assert_eq!(report["kind"], "unchanged");
+
+    let report = client
+        .request("workspace/diagnostic", json!({ "previousResultIds": [] }))
+        .await;
+    let items = report["items"].as_array().unwrap();
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[0]["uri"], json!(note));
+    assert_eq!(items[0]["items"].as_array().unwrap().len(), 1);
+    assert_eq!(items[1]["items"], json!([]));
+
+    // The client pulls them, so none are published.
+    assert!(client
+        .notifications
+        .iter()
+        .all(|message| message["method"] != "textDocument/publishDiagnostics"));
+}
+
+#[tokio::test]
+async fn document_links_resolve_to_notes() {
+    let vault = vault(&[("note.md", NOTE), ("other.md", "# Other\ntext\n## Second")]);
+    let note = uri(vault.path(), "note.md");
+    let mut client = TestClient::start(vault.path()).await;
+    client.open(&note, NOTE).await;
+
+    let links = client
+        .request(
+            "textDocument/documentLink",
+            json!({ "textDocument": { "uri": note } }),
+        )
+        .await;
+    let links = links.as_array().unwrap();
+    assert_eq!(links.len(), 2);
+    assert!(links[0]["target"].is_null());
+
+    let resolved = client
+        .request("documentLink/resolve", links[0].clone())
+        .await;
+    let mut other = uri(vault.path(), "other.md");
+    other.set_fragment(Some("L3"));
+    assert_eq!(resolved["target"], json!(other));
+
+    // Broken links stay without a target.
+    let resolved = client
+        .request("documentLink/resolve", links[1].clone())
+        .await;
+    assert!(resolved["target"].is_null());
+}
+
+#[tokio::test]
+async fn status_counts_requests() {
+    let vault = vault(&[("note.md", NOTE), ("other.md", "# Other\n")]);
+    let note = uri(vault.path(), "note.md");
+    let mut client = TestClient::start(vault.path()).await;
+    client.open(&note, NOTE).await;
+    client
+        .request(
+            "textDocument/documentSymbol",
+            json!({ "textDocument": { "uri": note } }),
+        )
+        .await;
+
+    let status = client.request("noteLs/status", json!(null)).await;
+    // At least initialize and the symbols, but not the status itself.
+    assert!(status["requests"]["count"].as_u64().unwrap() >= 2);
+    assert_eq!(status["timedOut"], json!(0));
+    assert!(status["contentCache"]["hits"].is_u64());
+    assert!(status["metricsUrl"].is_null());
+}
+
+#[tokio::test]
+async fn unknown_words_can_be_added_to_the_dictionary() {
+    let text = "Spelling is hard, `sowhat`\n";
+    let vault = vault(&[("note.md", text), ("words.dic", "is\nhard\n")]);
+    let note = uri(vault.path(), "note.md");
+    let dictionary = vault.path().join("words.dic");
+    let options = json!({ "preview": false, "spellCheck": true, "spellDictionary": dictionary });
+    let mut client = TestClient::start_with(vault.path(), options).await;
+
+    let diagnostics = client.open(&note, text).await;
+    assert_eq!(diagnostics[0]["message"], "Unknown word \"Spelling\"");
+    assert_eq!(diagnostics[0]["severity"], 4);
+    assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+
+    let actions = client
+        .request(
+            "textDocument/codeAction",
+            json!({
+                "textDocument": { "uri": note },
+                "range": diagnostics[0]["range"],
+                "context": { "diagnostics": diagnostics },
+            }),
+        )
+        .await;
+    let command = &actions[0]["command"];
+    assert_eq!(command["title"], "Add \"Spelling\" to dictionary");
+    client
+        .request(
+            "workspace/executeCommand",
+            json!({ "command": command["command"], "arguments": command["arguments"] }),
+        )
+        .await;
+    // The note is checked again before the command returns.
+    let published = client
+        .notifications
+        .iter()
+        .rfind(|message| message["params"]["uri"] == json!(note))
+        .unwrap();
+    assert_eq!(published["params"]["diagnostics"], json!([]));
+    let words = std::fs::read_to_string(vault.path().join(".note-ls/dictionary.txt")).unwrap();
+    assert_eq!(words, "Spelling\n");
+}
+
+#[tokio::test]
+async fn preview_listens_where_configured() {
+    let vault = vault(&[("note.md", NOTE)]);
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let options = json!({
+        "vaultDiagnosticsLimit": 0,
+        "previewHost": "127.0.0.1",
+        "previewPort": port,
+    });
+    let mut client = TestClient::start_with(vault.path(), options).await;
+
+    let started = client.notification("noteLs/previewStarted").await;
+    assert_eq!(started["url"], format!("http://127.0.0.1:{port}/"));
+}
+
+#[tokio::test]
+async fn stats_follow_changes() {
+    let vault = vault(&[("note.md", NOTE), ("other.md", "# Other\n## Second")]);
+    let note = uri(vault.path(), "note.md");
+    let mut client = TestClient::start(vault.path()).await;
+    client.open(&note, NOTE).await;
+    let stats = client.notification("noteLs/stats").await;
+    assert_eq!(stats["uri"], json!(note));
+    assert_eq!(stats["links"], 2);
+
+    client
+        .notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": note, "version": 2 },
+                "contentChanges": [{ "text": "three more words" }],
+            }),
+        )
+        .await;
+    let stats = client.notification("noteLs/stats").await;
+    assert_eq!(stats["words"], 3);
+    assert_eq!(stats["readingMinutes"], 1);
+}
+
+#[tokio::test]
+async fn recent_notes_are_listed() {
+    let vault = vault(&[("note.md", NOTE), ("other.md", "# Other\n## Second")]);
+    let mut client = TestClient::start(vault.path()).await;
+
+    let recent = client
+        .request(
+            "workspace/executeCommand",
+            json!({
+                "command": "noteLs.recentNotes",
+                "arguments": [{ "uri": uri(vault.path(), "note.md"), "days": 1 }],
+            }),
+        )
+        .await;
+    let titles = recent
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|note| note["title"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(titles, [json!("Note"), json!("Other")]);
+    assert_eq!(recent[1]["uri"], json!(uri(vault.path(), "other.md")));
+}
+
+#[tokio::test]
+async fn vaults_are_backed_up() {
+    let vault = vault(&[("note.md", NOTE), ("other.md", "# Other\n## Second")]);
+    let options =
+        json!({ "preview": false, "vaultDiagnosticsLimit": 0, "backupFolder": "backups" });
+    let mut client = TestClient::start_with(vault.path(), options).await;
+
+    let backup = client
+        .request(
+            "workspace/executeCommand",
+            json!({
+                "command": "noteLs.backupVault",
+                "arguments": [{ "uri": uri(vault.path(), "note.md") }],
+            }),
+        )
+        .await;
+    let path = Url::parse(backup.as_str().unwrap())
+        .unwrap()
+        .to_file_path()
+        .unwrap();
+    assert!(path.starts_with(vault.path().join("backups")));
+    let archive = zip::ZipArchive::new(std::fs::File::open(path).unwrap()).unwrap();
+    let mut names = archive.file_names().collect::<Vec<_>>();
+    names.sort_unstable();
+    assert_eq!(names, ["manifest.json", "note.md", "other.md"]);
+}
+
+#[tokio::test]
+async fn copies_of_a_vault_are_compared() {
+    let a = vault(&[("note.md", NOTE), ("other.md", "# Other")]);
+    let b = vault(&[("note.md", "# Note\nEdited"), ("new.md", "# New")]);
+    let mut client = TestClient::start(a.path()).await;
+
+    let diff = client
+        .request(
+            "workspace/executeCommand",
+            json!({
+                "command": "noteLs.compareVaults",
+                "arguments": [{ "pathA": a.path(), "pathB": b.path() }],
+            }),
+        )
+        .await;
+    assert_eq!(diff["added"], json!(["new.md"]));
+    assert_eq!(diff["removed"], json!(["other.md"]));
+    assert_eq!(diff["changed"], json!(["note.md"]));
+}
+
+#[tokio::test]
+async fn links_form_a_call_hierarchy() {
+    let vault = vault(&[("note.md", NOTE), ("other.md", "# Other\n## Second")]);
+    let note = uri(vault.path(), "note.md");
+    let mut client = TestClient::start(vault.path()).await;
+    client.open(&note, NOTE).await;
+
+    let items = client
+        .request(
+            "textDocument/prepareCallHierarchy",
+            json!({
+                "textDocument": { "uri": note },
+                "position": { "line": 0, "character": 0 },
+            }),
+        )
+        .await;
+    assert_eq!(items[0]["name"], "Note");
+
+    let outgoing = client
+        .request("callHierarchy/outgoingCalls", json!({ "item": items[0] }))
+        .await;
+    assert_eq!(outgoing[0]["to"]["name"], "Other");
+    assert_eq!(outgoing[0]["fromRanges"][0]["start"]["character"], 4);
+    let incoming = client
+        .request(
+            "callHierarchy/incomingCalls",
+            json!({ "item": outgoing[0]["to"] }),
+        )
+        .await;
+    assert_eq!(incoming[0]["from"]["uri"], json!(note));
+}
+
+#[tokio::test]
+async fn wiki_link_completions_replace_the_typed_path() {
+    let vault = vault(&[("note.md", "[[sub/nes"), ("sub/nested.md", "")]);
+    let note = uri(vault.path(), "note.md");
+    let mut client = TestClient::start(vault.path()).await;
+    client.open(&note, "[[sub/nes").await;
+
+    let completion = json!({
+        "textDocument": { "uri": note },
+        "position": { "line": 0, "character": 9 },
+    });
+    let notes = client
+        .request("textDocument/completion", completion.clone())
+        .await;
+    let edit = &notes["items"][0]["textEdit"];
+    assert_eq!(edit["newText"], "sub/nested.md]]");
+    assert_eq!(edit["range"]["start"]["character"], 2);
+    assert_eq!(edit["range"]["end"]["character"], 9);
+
+    // Brackets the editor closed already aren't added again.
+    client
+        .notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": note, "version": 2 },
+                "contentChanges": [{ "text": "[[sub/nes]]" }],
+            }),
+        )
+        .await;
+    let notes = client.request("textDocument/completion", completion).await;
+    assert_eq!(notes["items"][0]["textEdit"]["newText"], "sub/nested.md");
+}
+
+#[tokio::test]
+#[cfg(unix)]
+async fn code_blocks_outliving_their_run_timeout_are_stopped() {
+    let text = "```sh\nsleep 5\n```\n";
+    let vault = vault(&[("note.md", text)]);
+    let note = uri(vault.path(), "note.md");
+    let options = json!({
+        "preview": false,
+        "vaultDiagnosticsLimit": 0,
+        "codeRunners": [{ "language": "sh", "command": "sh" }],
+        "codeRunTimeoutMs": 300,
+        "requestTimeoutMs": 100,
+    });
+    let mut client = TestClient::start_with(vault.path(), options).await;
+    client.open(&note, text).await;
+
+    // The command outlives the request timeout too, and still writes the output.
+    let applied = client
+        .request(
+            "workspace/executeCommand",
+            json!({
+                "command": "noteLs.runCodeBlock",
+                "arguments": [{ "uri": note, "line": 1 }],
+            }),
+        )
+        .await;
+    assert_eq!(applied, true);
+    let edit = &client.edits[0]["changes"][note.as_str()][0];
+    assert_eq!(edit["newText"], "```output\n[stopped after 300 ms]\n```\n");
+}
@@ -0,0 +1,45 @@
+//! Snapshots of the preview's rendering of the fixtures aurelius' tests share, so changes to the
+//! renderer show up as changes to these. Review them with `cargo insta review`.
+
+use aurelius::{
+    render::{PulldownCmark, Renderer},
+    sanitize::sanitize,
+};
+
+fn render(fixture: &str) -> String {
+    let path = format!(
+        "{}/../aurelius/tests/fixtures/{fixture}.md",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let markdown = std::fs::read_to_string(path).unwrap();
+    let html = PulldownCmark::with_source_lines()
+        .render(&markdown, None)
+        .unwrap();
+    // The preview shows the rendering sanitized.
+    sanitize(&html)
+}
+
+#[test]
+fn wiki_links() {
+    insta::assert_snapshot!(render("wiki_links"));
+}
+
+#[test]
+fn math() {
+    insta::assert_snapshot!(render("math"));
+}
+
+#[test]
+fn callouts() {
+    insta::assert_snapshot!(render("callouts"));
+}
+
+#[test]
+fn embeds() {
+    insta::assert_snapshot!(render("embeds"));
+}
+
+#[test]
+fn html() {
+    insta::assert_snapshot!(render("html"));
+}
@@ -0,0 +1,96 @@
+//! Property tests checking that the parsers can't be made to panic or return nonsense by
+//! malformed notes.
+
+use std::path::PathBuf;
+
+use language_server::{folding, frontmatter, headings, links, symbols, syntax::Syntax, tags, uri};
+use proptest::prelude::*;
+use tower_lsp::lsp_types::Position;
+
+/// Lines heavy in the characters the parsers care about, mixed with multi-byte text.
+fn markdown_line() -> impl Strategy<Value = String> {
+    "([\\[\\]()!#|`<> a-z]|é|日本|🦀){0,40}"
+}
+
+fn markdown_document() -> impl Strategy<Value = String> {
+    prop::collection::vec(prop_oneof![markdown_line(), "```", "#{1,7} .{0,10}"], 0..10)
+        .prop_map(|lines| lines.join("\n"))
+}
+
+proptest! {
+    #[test]
+    fn links_cover_valid_slices(line in markdown_line()) {
+        for link in links::parse_line(&line, 0) {
+            prop_assert!(link.start < link.end && link.end <= line.len());
+            let source = line.get(link.start..link.end);
+            prop_assert!(source.is_some(), "offsets not on char boundaries");
+            let source = source.unwrap();
+            prop_assert!(source.starts_with('[') || source.starts_with("!["));
+            prop_assert!(source.ends_with(']') || source.ends_with(')'));
+
+            // The accessors slice the target too.
+            let _ = (link.target_path(), link.target_suffix(), link.anchor());
+            let _ = link.decoded_path();
+        }
+    }
+
+    #[test]
+    fn tags_cover_valid_slices(line in markdown_line()) {
+        for tag in tags::parse_line(&line, 0) {
+            let source = format!("#{}", tag.name);
+            prop_assert_eq!(line.get(tag.start..tag.end), Some(source.as_str()));
+        }
+    }
+
+    #[test]
+    fn headings_and_outline_never_panic(document in markdown_document()) {
+        let headings = headings::parse_headings(&document);
+        let line_count = document.lines().count() as u32;
+        prop_assert!(headings.iter().all(|heading| heading.line < line_count));
+        prop_assert!(headings.iter().all(|heading| (1..=6).contains(&heading.level)));
+
+        let syntax = Syntax::parse(&document);
+        let _ = symbols::outline(&document, &syntax);
+        let _ = folding::folding_ranges(&document, &syntax);
+        for heading in &headings {
+            let slug = headings::slugify(&heading.text);
+            prop_assert!(slug.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_'));
+        }
+    }
+
+    #[test]
+    fn frontmatter_never_panics(
+        yaml in prop::collection::vec("(title|aliases|tags|date|- |  )?[:\\[\\], #a-zé\"']{0,20}", 0..6),
+        closed in any::<bool>(),
+    ) {
+        let document = format!(
+            "---\n{}\n{}# Body",
+            yaml.join("\n"),
+            if closed { "---\n" } else { "" }
+        );
+        if let Some(parsed) = frontmatter::parse(&document) {
+            for tag in parsed.tags {
+                let line = document.lines().nth(tag.line as usize).unwrap_or("");
+                prop_assert!(line.get(tag.start..tag.end).is_some());
+            }
+        }
+        prop_assert!(frontmatter::body_start(&document) as usize <= document.lines().count());
+    }
+
+    #[test]
+    fn link_at_any_position_never_panics(
+        document in markdown_document(),
+        line in 0u32..12,
+        character in 0u32..200,
+    ) {
+        let _ = links::link_at(&document, Position::new(line, character));
+    }
+
+    #[test]
+    fn file_uris_round_trip(segments in prop::collection::vec("[^/\\\\\0.][^/\\\\\0]{0,10}", 1..5)) {
+        let path = PathBuf::from(format!("/{}", segments.join("/")));
+        let uri = uri::from_path(&path);
+        prop_assert!(uri.is_some());
+        prop_assert_eq!(uri::to_path(&uri.unwrap()), Some(path));
+    }
+}
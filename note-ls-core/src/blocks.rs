@@ -0,0 +1,165 @@
+use crate::fences;
+
+/// A block of a note marked with an ID, like the paragraph ending in `^intro`, which links point
+/// at as `[[note#^intro]]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Block {
+    /// The ID, without its `^`.
+    pub id: String,
+    /// The first line of the block.
+    pub start: u32,
+    /// The line with the ID: the last one of the block, or one of its own after it, as lists and
+    /// tables are marked.
+    pub line: u32,
+    /// The text of the first line of the block, without the ID.
+    pub text: String,
+}
+
+/// The block ID `line` ends with, without its `^`. IDs are letters, digits and dashes, and are
+/// the whole line or come after a space.
+pub fn id_of(line: &str) -> Option<&str> {
+    let line = line.trim_end();
+    let caret = line.rfind('^')?;
+    let id = &line[caret + 1..];
+    let before = &line[..caret];
+    let valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    (valid && (before.trim().is_empty() || before.ends_with(char::is_whitespace))).then_some(id)
+}
+
+fn is_heading(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    let level = trimmed.chars().take_while(|&c| c == '#').count();
+    (1..=6).contains(&level)
+        && trimmed[level..]
+            .chars()
+            .next()
+            .is_none_or(char::is_whitespace)
+}
+
+fn is_list_item(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    let digits = trimmed.chars().take_while(char::is_ascii_digit).count();
+    let marker = match digits {
+        0 => trimmed.strip_prefix(['-', '*', '+']),
+        _ => trimmed[digits..].strip_prefix(['.', ')']),
+    };
+    marker.is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+/// Headings and list items are blocks of their own, however they're surrounded.
+fn starts_block(line: &str) -> bool {
+    is_heading(line) || is_list_item(line)
+}
+
+/// The lines of `document`, with the frontmatter and fenced code blocks blanked, since nothing
+/// in them is a block.
+fn prose_lines(document: &str) -> Vec<&str> {
+    fences::body_lines(document)
+        .map(|(_, line)| line.unwrap_or(""))
+        .collect()
+}
+
+/// The first and last lines of the block `line` is in.
+fn extent(lines: &[&str], line: usize) -> (usize, usize) {
+    let mut start = line;
+    while start > 0
+        && !starts_block(lines[start])
+        && !lines[start - 1].trim().is_empty()
+        && !is_heading(lines[start - 1])
+    {
+        start -= 1;
+    }
+    let mut end = line;
+    while !is_heading(lines[end])
+        && end + 1 < lines.len()
+        && !lines[end + 1].trim().is_empty()
+        && !starts_block(lines[end + 1])
+    {
+        end += 1;
+    }
+    (start, end)
+}
+
+/// The first and last lines of the paragraph, heading or list item at `line` of `document`, or
+/// `None` if the line is blank or in frontmatter or code.
+pub fn block_at(document: &str, line: u32) -> Option<(u32, u32)> {
+    let lines = prose_lines(document);
+    let line = line as usize;
+    if lines.get(line)?.trim().is_empty() {
+        return None;
+    }
+    let (start, end) = extent(&lines, line);
+    Some((start as u32, end as u32))
+}
+
+/// Find every block of `document` marked with an ID, skipping frontmatter and fenced code blocks.
+pub fn parse_blocks(document: &str) -> Vec<Block> {
+    let lines = prose_lines(document);
+    let mut blocks = Vec::new();
+    for (n, line) in lines.iter().enumerate() {
+        let Some(id) = id_of(line) else {
+            continue;
+        };
+        let own_line = line.trim() == format!("^{id}");
+        // An ID on a line of its own marks the block before it.
+        let marked = match own_line {
+            true => (0..n).rev().find(|&i| !lines[i].trim().is_empty()),
+            false => Some(n),
+        };
+        let start = marked.map_or(n, |marked| extent(&lines, marked).0);
+        let first = match start == n {
+            true => &line[..line.trim_end().len() - id.len() - 1],
+            false => lines[start],
+        };
+        blocks.push(Block {
+            id: id.to_string(),
+            start: start as u32,
+            line: n as u32,
+            text: first.trim().to_string(),
+        });
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_mark_the_block_they_end() {
+        let doc = "---\nid: ^meta\n---\nFirst line\nsecond line ^para\n\n- one\n- two ^item\n\n\
+                   | a |\n| - |\n\n^table\n```\ncode ^not\n```\n# Heading ^head\nx^nospace";
+        let blocks = parse_blocks(doc);
+        let found = blocks
+            .iter()
+            .map(|block| {
+                (
+                    block.id.as_str(),
+                    block.start,
+                    block.line,
+                    block.text.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                ("para", 3, 4, "First line"),
+                ("item", 7, 7, "- two"),
+                ("table", 9, 12, "| a |"),
+                ("head", 16, 16, "# Heading"),
+            ]
+        );
+    }
+
+    #[test]
+    fn blocks_are_found_around_a_line() {
+        let doc = "# Title\nOne\ntwo\n\n- item\n  more\n```\ncode\n```";
+        assert_eq!(block_at(doc, 0), Some((0, 0)));
+        assert_eq!(block_at(doc, 2), Some((1, 2)));
+        assert_eq!(block_at(doc, 3), None);
+        assert_eq!(block_at(doc, 5), Some((4, 5)));
+        assert_eq!(block_at(doc, 7), None);
+    }
+}
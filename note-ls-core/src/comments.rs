@@ -0,0 +1,199 @@
+//! Comments, which aren't part of what a note says: `%% ... %%` as in Obsidian, and HTML's
+//! `<!-- ... -->`.
+//!
+//! Both may span lines, and a comment that's never closed goes on to the end of the note.
+//! Markers in code spans, fenced code blocks and frontmatter don't start comments. Links and
+//! tags in comments aren't indexed, and their words aren't counted or spell checked.
+
+use std::borrow::Cow;
+
+use crate::{fences::Fences, frontmatter};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// `%% ... %%`.
+    Percent,
+    /// `<!-- ... -->`.
+    Html,
+}
+
+impl Kind {
+    pub fn open(self) -> &'static str {
+        match self {
+            Kind::Percent => "%%",
+            Kind::Html => "<!--",
+        }
+    }
+
+    pub fn close(self) -> &'static str {
+        match self {
+            Kind::Percent => "%%",
+            Kind::Html => "-->",
+        }
+    }
+}
+
+/// A comment in a note.
+///
+/// `start` and `end` are byte offsets into the note and include the markers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Comment {
+    pub kind: Kind,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Find every comment in `document`.
+pub fn parse_comments(document: &str) -> Vec<Comment> {
+    let body_start = frontmatter::body_start(document) as usize;
+    let mut comments = Vec::new();
+    let mut open: Option<(Kind, usize)> = None;
+    let mut fences = Fences::default();
+    let mut line_start = 0;
+
+    for (n, line) in document.split_inclusive('\n').enumerate() {
+        let offset = line_start;
+        line_start += line.len();
+        if n < body_start {
+            continue;
+        }
+        if open.is_none() && fences.in_code(line) {
+            continue;
+        }
+
+        let mut in_code = false;
+        let mut i = 0;
+        while let Some(rest) = line.get(i..).filter(|rest| !rest.is_empty()) {
+            match open {
+                Some((kind, start)) if rest.starts_with(kind.close()) => {
+                    i += kind.close().len();
+                    comments.push(Comment {
+                        kind,
+                        start,
+                        end: offset + i,
+                    });
+                    open = None;
+                    continue;
+                }
+                Some(_) => {}
+                None if rest.starts_with('`') => in_code = !in_code,
+                None if !in_code => {
+                    let kind = [Kind::Percent, Kind::Html]
+                        .into_iter()
+                        .find(|kind| rest.starts_with(kind.open()));
+                    if let Some(kind) = kind {
+                        open = Some((kind, offset + i));
+                        i += kind.open().len();
+                        continue;
+                    }
+                }
+                None => {}
+            }
+            i += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+
+    if let Some((kind, start)) = open {
+        comments.push(Comment {
+            kind,
+            start,
+            end: document.len(),
+        });
+    }
+    comments
+}
+
+/// `document` with the text of its comments replaced by spaces. Line breaks are kept, and every
+/// character is replaced by as many spaces as it takes bytes, so positions in the result are the
+/// same as in `document`.
+pub fn blank(document: &str) -> Cow<'_, str> {
+    let comments = parse_comments(document);
+    if comments.is_empty() {
+        return Cow::Borrowed(document);
+    }
+    let mut blanked = String::with_capacity(document.len());
+    let mut last = 0;
+    for comment in comments {
+        blanked.push_str(&document[last..comment.start]);
+        for c in document[comment.start..comment.end].chars() {
+            match c {
+                '\n' | '\r' => blanked.push(c),
+                c => blanked.push_str(&" ".repeat(c.len_utf8())),
+            }
+        }
+        last = comment.end;
+    }
+    blanked.push_str(&document[last..]);
+    Cow::Owned(blanked)
+}
+
+/// `document` without its comments of `kind`, keeping the line breaks in them so the lines after
+/// stay where they were.
+pub fn strip(document: &str, kind: Kind) -> Cow<'_, str> {
+    let comments = parse_comments(document)
+        .into_iter()
+        .filter(|comment| comment.kind == kind)
+        .collect::<Vec<_>>();
+    if comments.is_empty() {
+        return Cow::Borrowed(document);
+    }
+    let mut stripped = String::with_capacity(document.len());
+    let mut last = 0;
+    for comment in comments {
+        stripped.push_str(&document[last..comment.start]);
+        let text = &document[comment.start..comment.end];
+        stripped.push_str(&"\n".repeat(text.matches('\n').count()));
+        last = comment.end;
+    }
+    stripped.push_str(&document[last..]);
+    Cow::Owned(stripped)
+}
+
+/// The comment the byte offset `offset` of `document` is in, if any.
+pub fn comment_at(document: &str, offset: usize) -> Option<Comment> {
+    parse_comments(document)
+        .into_iter()
+        .find(|comment| comment.start <= offset && offset < comment.end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_are_found_outside_code() {
+        let doc = "---\ntitle: 100%%\n---\nSee %%[[hidden]]%% and <!-- #draft\n\
+                   still -->. `%%code%%`\n```\n%% fenced\n```\n%% open to the end ✓";
+        let comments = parse_comments(doc)
+            .into_iter()
+            .map(|comment| (comment.kind, &doc[comment.start..comment.end]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            comments,
+            [
+                (Kind::Percent, "%%[[hidden]]%%"),
+                (Kind::Html, "<!-- #draft\nstill -->"),
+                (Kind::Percent, "%% open to the end ✓"),
+            ]
+        );
+
+        let blanked = blank(doc);
+        assert_eq!(blanked.len(), doc.len());
+        assert_eq!(
+            blanked.lines().collect::<Vec<_>>()[3..5],
+            [
+                format!("See{}and{}", " ".repeat(16), " ".repeat(12)),
+                format!("{}. `%%code%%`", " ".repeat(9)),
+            ]
+        );
+        assert_eq!(
+            strip("a %%b\nc%% d\n<!-- e -->", Kind::Percent),
+            "a \n d\n<!-- e -->"
+        );
+        assert_eq!(
+            comment_at(doc, 25).map(|comment| comment.kind),
+            Some(Kind::Percent)
+        );
+        assert_eq!(comment_at(doc, 20), None);
+    }
+}
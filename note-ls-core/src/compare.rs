@@ -0,0 +1,151 @@
+//! Comparing two copies of a vault, for `noteLs.compareVaults`, to reconcile backups or the
+//! copies on different devices.
+//!
+//! Notes are matched by their path in the vault and compared by a hash of their content. Links
+//! are compared as the notes they resolve to, so rewording a link doesn't count, but a link that
+//! resolves in one copy and not the other does.
+
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet},
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use crate::{index::NoteIndex, links};
+
+/// The links of a note that differ between the two copies, as the paths of the notes they
+/// point to.
+#[derive(Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct LinkDivergence {
+    pub note: PathBuf,
+    pub only_in_a: Vec<String>,
+    pub only_in_b: Vec<String>,
+}
+
+/// How copy `b` of a vault differs from copy `a`. Paths are relative to the vault's root and
+/// sorted.
+#[derive(Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct VaultDiff {
+    /// Notes only in `b`.
+    pub added: Vec<PathBuf>,
+    /// Notes only in `a`.
+    pub removed: Vec<PathBuf>,
+    /// Notes in both whose content differs.
+    pub changed: Vec<PathBuf>,
+    /// Notes in both that link to different notes.
+    pub links: Vec<LinkDivergence>,
+}
+
+/// A hash of the content of the file at `path`, or `None` if it can't be read.
+fn content_hash(path: &Path) -> Option<u64> {
+    let bytes = fs::read(path).ok()?;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// The notes of `index` by their path relative to the root, with the notes they link to.
+fn link_graph(index: &NoteIndex) -> BTreeMap<PathBuf, (PathBuf, BTreeSet<String>)> {
+    let relative = |path: &Path| links::nfc_path(path.strip_prefix(index.root()).unwrap_or(path));
+    index
+        .link_graph()
+        .into_iter()
+        .map(|(path, targets)| {
+            let targets = targets
+                .iter()
+                .map(|target| links::path_to_target(&relative(target)))
+                .collect();
+            (relative(&path), (path, targets))
+        })
+        .collect()
+}
+
+/// Compare the vault indexed by `a` with its copy indexed by `b`.
+pub fn compare(a: &NoteIndex, b: &NoteIndex) -> VaultDiff {
+    let a = link_graph(a);
+    let b = link_graph(b);
+
+    let mut diff = VaultDiff {
+        added: b
+            .keys()
+            .filter(|note| !a.contains_key(*note))
+            .cloned()
+            .collect(),
+        removed: a
+            .keys()
+            .filter(|note| !b.contains_key(*note))
+            .cloned()
+            .collect(),
+        ..VaultDiff::default()
+    };
+    for (note, (path_a, links_a)) in &a {
+        let Some((path_b, links_b)) = b.get(note) else {
+            continue;
+        };
+        if content_hash(path_a) != content_hash(path_b) {
+            diff.changed.push(note.clone());
+        }
+        if links_a != links_b {
+            diff.links.push(LinkDivergence {
+                note: note.clone(),
+                only_in_a: links_a.difference(links_b).cloned().collect(),
+                only_in_b: links_b.difference(links_a).cloned().collect(),
+            });
+        }
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn copies_are_compared_by_content_and_links() {
+        let a = tempfile::tempdir().unwrap();
+        let b = tempfile::tempdir().unwrap();
+        let write =
+            |root: &Path, name: &str, content: &str| fs::write(root.join(name), content).unwrap();
+        for root in [a.path(), b.path()] {
+            write(root, "same.md", "[[linked]]");
+            write(root, "linked.md", "");
+        }
+        write(a.path(), "old.md", "");
+        write(a.path(), "edited.md", "[[same]] [[old]]");
+        write(b.path(), "new.md", "");
+        write(b.path(), "edited.md", "[[same|Same]] [[new]]");
+        // Reworded, but still linking to the same note.
+        write(a.path(), "reworded.md", "[[same]]");
+        write(b.path(), "reworded.md", "[same](same.md)");
+
+        let diff = compare(&NoteIndex::scan(a.path()), &NoteIndex::scan(b.path()));
+
+        assert_eq!(diff.added, vec![PathBuf::from("new.md")]);
+        assert_eq!(diff.removed, vec![PathBuf::from("old.md")]);
+        assert_eq!(
+            diff.changed,
+            vec![PathBuf::from("edited.md"), PathBuf::from("reworded.md")]
+        );
+        assert_eq!(
+            diff.links,
+            vec![LinkDivergence {
+                note: PathBuf::from("edited.md"),
+                only_in_a: vec![String::from("old.md")],
+                only_in_b: vec![String::from("new.md")],
+            }]
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! Note content read from disk on demand.
+//!
+//! The index only keeps what it parsed out of each note, so features that need a note's text,
+//! like hover previews, read it from disk. Recently read notes are cached, and the least recently
+//! used ones are dropped once the cache grows past its budget.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+#[derive(Debug)]
+struct Cached {
+    content: Arc<str>,
+    /// When the file was modified and its length when it was read, to notice it changing.
+    modified: SystemTime,
+    len: u64,
+    /// When the content was last used, as a tick of the cache's clock.
+    used: u64,
+}
+
+/// How often a cache had what it was asked for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// A cache of note contents, holding at most a budget of bytes.
+#[derive(Debug)]
+pub struct ContentCache {
+    budget: usize,
+    size: usize,
+    entries: HashMap<PathBuf, Cached>,
+    /// Cached paths by when they were last used, least recently first.
+    recency: BTreeMap<u64, PathBuf>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+impl ContentCache {
+    /// An empty cache that holds up to `budget` bytes of content.
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            size: 0,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// The content of the file at `path`, read again if it changed since it was cached.
+    ///
+    /// Files larger than the whole budget are read but not cached.
+    pub fn get(&mut self, path: &Path) -> io::Result<Arc<str>> {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                self.remove(path);
+                return Err(e);
+            }
+        };
+        let modified = metadata.modified()?;
+        self.clock += 1;
+
+        if let Some(cached) = self.entries.get_mut(path) {
+            if cached.modified == modified && cached.len == metadata.len() {
+                self.recency.remove(&cached.used);
+                cached.used = self.clock;
+                self.recency.insert(self.clock, path.to_path_buf());
+                self.stats.hits += 1;
+                return Ok(Arc::clone(&cached.content));
+            }
+        }
+
+        self.stats.misses += 1;
+        self.remove(path);
+        let content: Arc<str> = fs::read_to_string(path)?.into();
+        if content.len() <= self.budget {
+            self.size += content.len();
+            self.recency.insert(self.clock, path.to_path_buf());
+            self.entries.insert(
+                path.to_path_buf(),
+                Cached {
+                    content: Arc::clone(&content),
+                    modified,
+                    len: metadata.len(),
+                    used: self.clock,
+                },
+            );
+            self.evict();
+        }
+        Ok(content)
+    }
+
+    /// Forget the content of the file at `path`.
+    pub fn remove(&mut self, path: &Path) {
+        if let Some(cached) = self.entries.remove(path) {
+            self.size -= cached.content.len();
+            self.recency.remove(&cached.used);
+        }
+    }
+
+    /// Hold up to `budget` bytes from now on, dropping content to fit.
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict();
+    }
+
+    /// Number of bytes of content held.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// How often content was found in the cache, rather than read from disk.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.budget {
+            let Some((_, path)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some(cached) = self.entries.remove(&path) {
+                self.size -= cached.content.len();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_notes_are_evicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        for name in ["a.md", "b.md", "c.md"] {
+            fs::write(path(name), "1234").unwrap();
+        }
+        fs::write(path("big.md"), "123456789").unwrap();
+
+        let mut cache = ContentCache::new(8);
+        cache.get(&path("a.md")).unwrap();
+        cache.get(&path("b.md")).unwrap();
+        cache.get(&path("a.md")).unwrap();
+        cache.get(&path("c.md")).unwrap();
+        assert_eq!(cache.size(), 8);
+        assert_eq!(cache.stats(), CacheStats { hits: 1, misses: 3 });
+        assert!(cache.entries.contains_key(&path("a.md")));
+        assert!(!cache.entries.contains_key(&path("b.md")));
+
+        // Too big to keep, but still read.
+        assert_eq!(&*cache.get(&path("big.md")).unwrap(), "123456789");
+        assert_eq!(cache.size(), 8);
+
+        cache.set_budget(4);
+        assert_eq!(cache.size(), 4);
+        assert!(cache.entries.contains_key(&path("c.md")));
+    }
+
+    #[test]
+    fn changed_and_deleted_notes_are_read_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("note.md");
+        fs::write(&path, "old").unwrap();
+
+        let mut cache = ContentCache::new(1024);
+        assert_eq!(&*cache.get(&path).unwrap(), "old");
+        fs::write(&path, "newer").unwrap();
+        assert_eq!(&*cache.get(&path).unwrap(), "newer");
+        assert_eq!(cache.size(), 5);
+
+        fs::remove_file(&path).unwrap();
+        assert!(cache.get(&path).is_err());
+        assert_eq!(cache.size(), 0);
+    }
+}
@@ -0,0 +1,74 @@
+//! Fenced code blocks, for the parsers that go through a note line by line and skip code.
+//!
+//! A block opens with three or more backticks or tildes and is closed by a line of at least as
+//! many of the same character and nothing else, so a `~~~~` block can hold ```` ``` ```` lines.
+//! A block that's never closed runs to the end of the note.
+
+use crate::frontmatter;
+
+/// The fence `line` starts with and the text after it, if it's a fence.
+pub fn fence(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_start();
+    let char = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.len() - line.trim_start_matches(char).len();
+    (len >= 3).then(|| line.split_at(len))
+}
+
+/// Whether `line` closes a block opened with `opening`.
+pub fn closes(line: &str, opening: &str) -> bool {
+    fence(line).is_some_and(|(fence, info)| fence.starts_with(opening) && info.trim().is_empty())
+}
+
+/// Follows the fenced code blocks of a note as its lines are given to it in order.
+#[derive(Debug, Default)]
+pub struct Fences<'a> {
+    /// The fence of the block the last line was in, if it didn't close it.
+    open: Option<&'a str>,
+}
+
+impl<'a> Fences<'a> {
+    /// Whether `line`, the next line of the note, is part of a fenced code block, fences
+    /// included.
+    pub fn in_code(&mut self, line: &'a str) -> bool {
+        match self.open {
+            Some(opening) => {
+                if closes(line, opening) {
+                    self.open = None;
+                }
+                true
+            }
+            None => {
+                self.open = fence(line).map(|(fence, _)| fence);
+                self.open.is_some()
+            }
+        }
+    }
+}
+
+/// The lines of `document` with their numbers, `None` for those in frontmatter or fenced code
+/// blocks.
+pub fn body_lines(document: &str) -> impl Iterator<Item = (u32, Option<&str>)> {
+    let body_start = frontmatter::body_start(document);
+    let mut fences = Fences::default();
+    document.lines().zip(0u32..).map(move |(line, n)| {
+        let prose = n >= body_start && !fences.in_code(line);
+        (n, prose.then_some(line))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fences_close_on_the_same_kind() {
+        let document = "---\n```\n---\n~~~~ md\n```\nnot prose\n~~~~\nprose\n```";
+        let prose = body_lines(document)
+            .filter_map(|(n, line)| line.map(|_| n))
+            .collect::<Vec<_>>();
+        assert_eq!(prose, [7]);
+        assert_eq!(fence("  ```rust"), Some(("```", "rust")));
+        assert!(!closes("```", "````"));
+        assert!(closes("`````", "````"));
+    }
+}
@@ -0,0 +1,217 @@
+//! Footnotes: references like `[^1]` in the text, and the definitions they point to, like
+//! `[^1]: Where this comes from.` at the start of a line.
+//!
+//! A definition goes on over the lines right after it, and over the paragraphs after those that
+//! are indented by four spaces. Labels match without regard to case, like in CommonMark.
+
+use lsp_types::{Position, Range};
+
+use crate::fences::body_lines;
+
+/// A reference to a footnote.
+///
+/// `start` and `end` are byte offsets into the line and include the brackets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Reference {
+    /// The label without the `^`.
+    pub label: String,
+    pub line: u32,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Reference {
+    /// The range the reference covers, including the brackets.
+    pub fn range(&self) -> Range {
+        Range::new(
+            Position::new(self.line, self.start as u32),
+            Position::new(self.line, self.end as u32),
+        )
+    }
+}
+
+/// The definition of a footnote.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Definition {
+    pub label: String,
+    /// The first and last lines of the definition.
+    pub line: u32,
+    pub end_line: u32,
+    /// The byte offset on the first line where the text starts, after the `[^label]:`.
+    pub text_start: usize,
+    /// The text of the footnote, with the indentation of its lines taken off. Paragraphs are
+    /// separated by a blank line.
+    pub text: String,
+}
+
+impl Definition {
+    /// Whether this defines the footnote `label`.
+    pub fn defines(&self, label: &str) -> bool {
+        self.label.to_lowercase() == label.to_lowercase()
+    }
+}
+
+/// The label of the `[^label]` `text` starts with, and the length of the brackets around it.
+fn label(text: &str) -> Option<(&str, usize)> {
+    let rest = text.strip_prefix("[^")?;
+    let label = &rest[..rest.find(']')?];
+    let valid = !label.is_empty() && !label.contains(char::is_whitespace);
+    valid.then_some((label, label.len() + 3))
+}
+
+/// The label of the definition `line` starts, and where its text starts.
+fn definition_start(line: &str) -> Option<(&str, usize)> {
+    let trimmed = line.trim_start();
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let (label, len) = label(trimmed)?;
+    let text = trimmed[len..].strip_prefix(':')?;
+    Some((label, line.len() - text.trim_start().len()))
+}
+
+/// Find every footnote reference on a single line, outside code spans. The label starting a
+/// definition isn't a reference.
+pub fn parse_line(line: &str, line_number: u32) -> Vec<Reference> {
+    let mut references = Vec::new();
+    let skip = definition_start(line).map_or(0, |(_, start)| start);
+    let mut in_code = false;
+    let mut previous = None;
+
+    for (i, c) in line.char_indices() {
+        if c == '`' {
+            in_code = !in_code;
+        }
+        let escaped = previous == Some('\\');
+        previous = Some(c);
+        if i < skip || in_code || escaped || c != '[' {
+            continue;
+        }
+        if let Some((label, len)) = label(&line[i..]) {
+            references.push(Reference {
+                label: label.to_string(),
+                line: line_number,
+                start: i,
+                end: i + len,
+            });
+        }
+    }
+
+    references
+}
+
+/// Find every footnote reference in `document`, skipping frontmatter and fenced code blocks.
+pub fn parse_references(document: &str) -> Vec<Reference> {
+    body_lines(document)
+        .filter_map(|(n, line)| Some(parse_line(line?, n)))
+        .flatten()
+        .collect()
+}
+
+/// Find every footnote definition in `document`, skipping frontmatter and fenced code blocks.
+pub fn parse_definitions(document: &str) -> Vec<Definition> {
+    let lines = body_lines(document).collect::<Vec<_>>();
+    let mut definitions = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let (n, line) = lines[i];
+        i += 1;
+        let Some((label, text_start)) = line.and_then(definition_start) else {
+            continue;
+        };
+        let line = line.unwrap_or_default();
+        let mut definition = Definition {
+            label: label.to_string(),
+            line: n,
+            end_line: n,
+            text_start,
+            text: line[text_start..].trim_end().to_string(),
+        };
+
+        let mut blank = false;
+        while let Some((n, Some(next))) = lines.get(i).copied() {
+            if next.trim().is_empty() {
+                blank = true;
+                i += 1;
+                continue;
+            }
+            let indented = next.starts_with("    ") || next.starts_with('\t');
+            if definition_start(next).is_some() || (blank && !indented) {
+                break;
+            }
+            definition.text.push_str(if blank { "\n\n" } else { "\n" });
+            definition.text.push_str(next.trim());
+            definition.end_line = n;
+            blank = false;
+            i += 1;
+        }
+        definitions.push(definition);
+    }
+    definitions
+}
+
+/// Find the footnote reference under the cursor, if any.
+pub fn reference_at(document: &str, position: Position) -> Option<Reference> {
+    let line = document.lines().nth(position.line as usize)?;
+    let character = position.character as usize;
+
+    parse_line(line, position.line)
+        .into_iter()
+        .find(|reference| reference.start <= character && character < reference.end)
+}
+
+/// The definition of the footnote `label` among `definitions`. The first one wins if there are
+/// several.
+pub fn definition<'a>(definitions: &'a [Definition], label: &str) -> Option<&'a Definition> {
+    definitions
+        .iter()
+        .find(|definition| definition.defines(label))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn references_and_definitions_are_found() {
+        let doc = "Claim[^1] and `[^code]`, \\[^escaped] or [^ not]\n\
+                   More[^Note].\n\
+                   ```\n[^1]: fenced\n```\n\
+                   [^1]: First line\n  lazy line [^note]\n\n    Second paragraph.\n\
+                   [^note]: Other\n\nAfter";
+        let references = parse_references(doc)
+            .into_iter()
+            .map(|reference| (reference.label, reference.line, reference.start))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            references,
+            [
+                (String::from("1"), 0, 5),
+                (String::from("Note"), 1, 4),
+                (String::from("note"), 6, 12),
+            ]
+        );
+
+        let definitions = parse_definitions(doc);
+        assert_eq!(definitions.len(), 2);
+        assert_eq!(
+            definitions[0],
+            Definition {
+                label: String::from("1"),
+                line: 5,
+                end_line: 8,
+                text_start: 6,
+                text: String::from("First line\nlazy line [^note]\n\nSecond paragraph."),
+            }
+        );
+        assert_eq!((definitions[1].line, definitions[1].end_line), (9, 9));
+        assert_eq!(definition(&definitions, "NOTE").unwrap().text, "Other");
+
+        let reference = reference_at(doc, Position::new(1, 6)).unwrap();
+        assert_eq!(
+            reference.range(),
+            Range::new(Position::new(1, 4), Position::new(1, 11))
+        );
+        assert_eq!(reference_at(doc, Position::new(5, 2)), None);
+    }
+}
@@ -0,0 +1,172 @@
+//! YAML frontmatter at the top of a note.
+
+use std::collections::BTreeMap;
+
+use serde_yaml::Value;
+
+use crate::tags::Tag;
+
+/// The frontmatter fields the server understands. Other fields are ignored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Frontmatter {
+    pub title: Option<String>,
+    /// Other names the note can be linked by, e.g. `[[Alias]]`.
+    pub aliases: Vec<String>,
+    /// Tags listed under `tags`, located in the frontmatter. Their ranges cover only the name,
+    /// since there's no `#`.
+    pub tags: Vec<Tag>,
+    pub date: Option<String>,
+    /// The `type` of note, e.g. `book`, which the settings may say more about.
+    pub note_type: Option<String>,
+    /// Names of the fields that have a value, including the ones above.
+    pub fields: Vec<String>,
+    /// Values of the fields written as a single string, number or boolean, by name.
+    pub values: BTreeMap<String, String>,
+}
+
+impl Frontmatter {
+    /// The value of the field `name`, if it's a single string, number or boolean.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+}
+
+/// The YAML between the `---` fences at the very start of `document`, and the line the note's
+/// body starts on.
+pub fn split(document: &str) -> Option<(&str, u32)> {
+    let mut lines = document.split_inclusive('\n');
+    if lines.next()?.trim_end() != "---" {
+        return None;
+    }
+
+    let start = document.find('\n')? + 1;
+    let mut offset = start;
+    for (n, line) in lines.enumerate() {
+        if matches!(line.trim_end(), "---" | "...") {
+            return Some((&document[start..offset], n as u32 + 2));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// The line a note's body starts on, after any frontmatter.
+pub fn body_start(document: &str) -> u32 {
+    split(document).map(|(_, line)| line).unwrap_or(0)
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// A field that may be written as either a single value or a list.
+fn one_or_many(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::Sequence(values)) => values.iter().filter_map(scalar).collect(),
+        Some(value) => scalar(value).into_iter().collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Find `name` in the frontmatter, to give a frontmatter tag a location.
+fn locate_tag(yaml: &str, name: String) -> Tag {
+    // The YAML starts on the second line of the document.
+    for (n, line) in yaml.lines().enumerate() {
+        if let Some(start) = line.find(name.as_str()) {
+            return Tag {
+                end: start + name.len(),
+                name,
+                line: n as u32 + 1,
+                start,
+            };
+        }
+    }
+    Tag {
+        name,
+        line: 0,
+        start: 0,
+        end: 0,
+    }
+}
+
+/// Parse the frontmatter of `document`, if it has any valid frontmatter.
+pub fn parse(document: &str) -> Option<Frontmatter> {
+    let (yaml, _) = split(document)?;
+    let value = serde_yaml::from_str::<Value>(yaml).ok()?;
+    let fields = value.as_mapping()?;
+
+    let mut aliases = one_or_many(fields.get("aliases"));
+    aliases.extend(one_or_many(fields.get("alias")));
+
+    // Tags may also be written as a single comma or space separated string.
+    let tags = one_or_many(fields.get("tags"))
+        .iter()
+        .flat_map(|tags| tags.split([',', ' ']))
+        .map(|tag| tag.trim().trim_start_matches('#'))
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| locate_tag(yaml, tag.to_string()))
+        .collect();
+
+    Some(Frontmatter {
+        title: fields.get("title").and_then(scalar),
+        aliases,
+        tags,
+        date: fields.get("date").and_then(scalar),
+        note_type: fields.get("type").and_then(scalar),
+        fields: fields
+            .iter()
+            .filter(|(_, value)| !value.is_null())
+            .filter_map(|(key, _)| scalar(key))
+            .collect(),
+        values: fields
+            .iter()
+            .filter_map(|(key, value)| Some((scalar(key)?, scalar(value)?)))
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_are_read_in_either_form() {
+        let doc = "---\ntitle: Café\naliases: [One, \"Two\"]\ntags: \"rust, #notes\"\ndate: 2023-01-02\n---\n# Heading";
+        let frontmatter = parse(doc).unwrap();
+        assert_eq!(frontmatter.title.as_deref(), Some("Café"));
+        assert_eq!(frontmatter.aliases, vec!["One", "Two"]);
+        assert_eq!(frontmatter.date.as_deref(), Some("2023-01-02"));
+        assert_eq!(frontmatter.fields, vec!["title", "aliases", "tags", "date"]);
+        assert_eq!(frontmatter.value("date"), Some("2023-01-02"));
+        assert_eq!(frontmatter.value("aliases"), None);
+
+        let tags = frontmatter
+            .tags
+            .iter()
+            .map(|tag| (tag.name.as_str(), tag.line, tag.start))
+            .collect::<Vec<_>>();
+        assert_eq!(tags, vec![("rust", 3, 7), ("notes", 3, 14)]);
+        assert_eq!(body_start(doc), 6);
+
+        let doc = "---\naliases: Single\ntags:\n  - a\ntype: book\nauthor:\n...\n";
+        let frontmatter = parse(doc).unwrap();
+        assert_eq!(frontmatter.aliases, vec!["Single"]);
+        assert_eq!(frontmatter.note_type.as_deref(), Some("book"));
+        assert_eq!(frontmatter.fields, vec!["aliases", "tags", "type"]);
+        assert_eq!(frontmatter.tags[0].name, "a");
+    }
+
+    #[test]
+    fn frontmatter_must_be_closed_and_at_the_start() {
+        assert_eq!(parse("---\ntitle: x\n"), None);
+        assert_eq!(parse("\n---\ntitle: x\n---\n"), None);
+        assert_eq!(parse("---\n: [unbalanced\n---\n"), None);
+        assert_eq!(body_start("# No frontmatter"), 0);
+    }
+}
@@ -0,0 +1,168 @@
+//! The vault's link graph, for `noteLs.graph.export`, in a form other tools can draw: DOT for
+//! Graphviz, or JSON for e.g. D3.
+
+use std::{collections::BTreeSet, path::Path};
+
+use crate::{index::NoteIndex, links};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Format {
+    Dot,
+    #[default]
+    Json,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Dot => "dot",
+            Format::Json => "json",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct Node {
+    /// The note's path in the vault.
+    pub id: String,
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+}
+
+/// A link from the note `source` to the note `target`, by their ids.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct Edge {
+    pub source: String,
+    pub target: String,
+}
+
+#[derive(Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct Graph {
+    /// Every note, sorted by id.
+    pub nodes: Vec<Node>,
+    /// One edge for each pair of linked notes, however many links there are between them.
+    pub edges: Vec<Edge>,
+}
+
+/// The graph of the notes in `index` and the links between them.
+pub fn graph(index: &NoteIndex) -> Graph {
+    let id = |path: &Path| {
+        let relative = path.strip_prefix(index.root()).unwrap_or(path);
+        links::path_to_target(&links::nfc_path(relative))
+    };
+
+    let mut graph = Graph::default();
+    let mut edges = BTreeSet::new();
+    for (path, targets) in index.link_graph() {
+        let source = id(&path);
+        edges.extend(targets.iter().map(|target| Edge {
+            source: source.clone(),
+            target: id(target),
+        }));
+        let note = index.get(&path);
+        graph.nodes.push(Node {
+            id: source,
+            title: note.and_then(|note| note.title()).map(str::to_string),
+            tags: note
+                .map(|note| note.tags.iter().map(|tag| tag.name.clone()).collect())
+                .unwrap_or_default(),
+        });
+    }
+    graph.nodes.sort_by(|a, b| a.id.cmp(&b.id));
+    graph.edges = edges.into_iter().collect();
+    graph
+}
+
+/// `text` as a quoted DOT ID.
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// `graph` in Graphviz's DOT language. Nodes are labelled with the note's title, and carry its
+/// tags, separated by commas, in a `tags` attribute.
+pub fn to_dot(graph: &Graph) -> String {
+    let mut dot = String::from("digraph vault {\n");
+    for node in &graph.nodes {
+        let label = node.title.as_deref().unwrap_or(&node.id);
+        dot.push_str(&format!("  {} [label={}", quote(&node.id), quote(label)));
+        if !node.tags.is_empty() {
+            dot.push_str(&format!(", tags={}", quote(&node.tags.join(","))));
+        }
+        dot.push_str("];\n");
+    }
+    for edge in &graph.edges {
+        dot.push_str(&format!(
+            "  {} -> {};\n",
+            quote(&edge.source),
+            quote(&edge.target)
+        ));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// `graph` written out in `format`. JSON needs the `serde` feature.
+#[cfg(feature = "serde")]
+pub fn export(graph: &Graph, format: Format) -> String {
+    match format {
+        Format::Dot => to_dot(graph),
+        Format::Json => serde_json::to_string_pretty(graph).unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn the_graph_is_exported_as_dot() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(
+            root.join("a.md"),
+            "# Say \"hi\"\n#idea #draft\n[[b]] [[sub/c]] [[b]]",
+        )
+        .unwrap();
+        fs::write(root.join("b.md"), "[[a]] [[missing]]").unwrap();
+        fs::write(root.join("sub/c.md"), "").unwrap();
+
+        let graph = graph(&NoteIndex::scan(root));
+
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(graph.nodes[2].id, "sub/c.md");
+        assert_eq!(
+            to_dot(&graph),
+            "digraph vault {\n  \
+               \"a.md\" [label=\"Say \\\"hi\\\"\", tags=\"idea,draft\"];\n  \
+               \"b.md\" [label=\"b.md\"];\n  \
+               \"sub/c.md\" [label=\"sub/c.md\"];\n  \
+               \"a.md\" -> \"b.md\";\n  \
+               \"a.md\" -> \"sub/c.md\";\n  \
+               \"b.md\" -> \"a.md\";\n\
+             }\n"
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashSet;
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::fences;
+
+/// An ATX heading (`# Title`) in a note.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Heading {
+    pub level: u8,
+    pub text: String,
+    pub line: u32,
+}
+
+/// Find every ATX heading in `document`, skipping frontmatter and fenced code blocks.
+pub fn parse_headings(document: &str) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    for (n, line) in fences::body_lines(document) {
+        let Some(line) = line else {
+            continue;
+        };
+        let trimmed = line.trim_start();
+        let level = trimmed.chars().take_while(|&c| c == '#').count();
+        if level == 0 || level > 6 {
+            continue;
+        }
+        let rest = &trimmed[level..];
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            continue;
+        }
+
+        headings.push(Heading {
+            level: level as u8,
+            text: rest.trim().trim_end_matches('#').trim_end().to_string(),
+            line: n,
+        });
+    }
+
+    headings
+}
+
+/// Convert heading text into the anchor used for it in rendered HTML, GitHub style.
+///
+/// Letters are lowercased, spaces become `-`, and punctuation other than `-` and `_` is dropped.
+/// Text is normalized to NFC first so that composed and decomposed accents give the same slug.
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .nfc()
+        .filter_map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                Some(c.to_lowercase().next().unwrap_or(c))
+            } else if c.is_whitespace() {
+                Some('-')
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// The anchors of `headings` in rendered HTML: their slugs, with `-1`, `-2` and so on added to
+/// the ones an earlier heading already has, GitHub style, so that every heading has its own.
+pub fn anchors(headings: &[Heading]) -> Vec<String> {
+    let mut used = HashSet::new();
+    headings
+        .iter()
+        .map(|heading| {
+            let slug = slugify(&heading.text);
+            let mut anchor = slug.clone();
+            let mut n = 0;
+            while !used.insert(anchor.clone()) {
+                n += 1;
+                anchor = format!("{slug}-{n}");
+            }
+            anchor
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headings_outside_code_blocks() {
+        let doc = "---\n# comment\n---\n# Title\ntext\n```\n# not a heading\n```\n## Sub section ##\n#hashtag";
+        let headings = parse_headings(doc);
+        assert_eq!(
+            headings,
+            vec![
+                Heading {
+                    level: 1,
+                    text: "Title".to_string(),
+                    line: 3
+                },
+                Heading {
+                    level: 2,
+                    text: "Sub section".to_string(),
+                    line: 8
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn slugs() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("snake_case and-dashes"), "snake_case-and-dashes");
+        assert_eq!(slugify("Cafe\u{301}"), slugify("Caf\u{e9}"));
+    }
+
+    #[test]
+    fn repeated_slugs_are_numbered() {
+        let headings = parse_headings("# Notes\n## Notes\n## Notes-1\n# notes!\n");
+        assert_eq!(
+            anchors(&headings),
+            ["notes", "notes-1", "notes-1-1", "notes-2"]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+//! Files left out of a vault: those matched by its `.gitignore` files and by extra patterns.
+//!
+//! Patterns use the `.gitignore` syntax: ones containing a `/` are relative to the folder they're
+//! given for, others match a file or folder name anywhere below it, a trailing `/` only matches
+//! folders and a leading `!` includes again what an earlier pattern left out. Nothing in a folder
+//! that is left out can be included again.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use glob::{MatchOptions, Pattern};
+
+/// Folders never worth looking into, whatever the patterns say: git's, and the server's own in
+/// `.note-ls`.
+const ALWAYS_IGNORED: [&str; 2] = [".git", ".note-ls"];
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Debug)]
+struct Rule {
+    /// The folder the pattern was given for.
+    base: PathBuf,
+    pattern: Pattern,
+    /// Whether the pattern is matched against the path relative to `base`, rather than against
+    /// file names.
+    anchored: bool,
+    dir_only: bool,
+    negated: bool,
+}
+
+impl Rule {
+    fn parse(base: &Path, line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.trim_start_matches('/');
+        Some(Self {
+            base: base.to_path_buf(),
+            pattern: Pattern::new(line).ok()?,
+            anchored,
+            dir_only,
+            negated,
+        })
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let Ok(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        if self.anchored {
+            self.pattern.matches_path_with(relative, MATCH_OPTIONS)
+        } else {
+            relative.file_name().is_some_and(|name| {
+                self.pattern
+                    .matches_with(&name.to_string_lossy(), MATCH_OPTIONS)
+            })
+        }
+    }
+}
+
+/// The patterns of files left out of the vault at a root.
+#[derive(Debug, Default)]
+pub struct Ignore {
+    root: PathBuf,
+    rules: Vec<Rule>,
+}
+
+impl Ignore {
+    /// The files left out of the vault at `root` by its top-level `.gitignore` and by `patterns`,
+    /// which are relative to `root`. `.gitignore` files in folders below are added by `walk`.
+    pub fn new(root: &Path, patterns: &[String]) -> Self {
+        let mut ignore = Self {
+            root: root.to_path_buf(),
+            rules: Vec::new(),
+        };
+        ignore.add_gitignore(root);
+        ignore.rules.extend(
+            patterns
+                .iter()
+                .filter_map(|pattern| Rule::parse(root, pattern)),
+        );
+        ignore
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn add_gitignore(&mut self, dir: &Path) {
+        let Ok(content) = fs::read_to_string(dir.join(".gitignore")) else {
+            return;
+        };
+        self.rules
+            .extend(content.lines().filter_map(|line| Rule::parse(dir, line)));
+    }
+
+    /// Whether the patterns leave out the file or folder at `path`, without looking at the
+    /// folders containing it.
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if is_dir
+            && path
+                .file_name()
+                .is_some_and(|name| ALWAYS_IGNORED.iter().any(|ignored| name == *ignored))
+        {
+            return true;
+        }
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(path, is_dir))
+            .is_some_and(|rule| !rule.negated)
+    }
+
+    /// Whether the file at `path` is left out of the vault, by itself or by a folder containing
+    /// it. Files outside the vault aren't.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        let mut dir = self.root.clone();
+        let mut components = relative.components().peekable();
+        while let Some(component) = components.next() {
+            dir.push(component);
+            let is_dir = components.peek().is_some();
+            if self.matches(&dir, is_dir) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Every file in the vault that isn't left out, reading the `.gitignore` files of the folders
+    /// on the way.
+    pub fn walk(&mut self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        let mut dirs = vec![self.root.clone()];
+        while let Some(dir) = dirs.pop() {
+            if dir != self.root {
+                self.add_gitignore(&dir);
+            }
+            let Ok(entries) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let Ok(file_type) = entry.file_type() else {
+                    continue;
+                };
+                let path = entry.path();
+                if self.matches(&path, file_type.is_dir()) {
+                    continue;
+                }
+                if file_type.is_dir() {
+                    dirs.push(path);
+                } else if file_type.is_file() {
+                    files.push(path);
+                }
+            }
+        }
+        files
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gitignore_files_and_patterns_leave_files_out() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        for dir in [".git", "node_modules/pkg", "archive/old", "notes/drafts"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            ".git/HEAD.md",
+            "node_modules/pkg/README.md",
+            "archive/old/a.md",
+            "archive/keep.md",
+            "notes/a.md",
+            "notes/drafts/b.md",
+            "notes/drafts/c.md",
+            "notes/scratch.tmp",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        fs::write(root.join(".gitignore"), "node_modules/\n*.tmp\n").unwrap();
+        fs::write(root.join("notes/drafts/.gitignore"), "*.md\n!c.md\n").unwrap();
+
+        let mut ignore = Ignore::new(root, &[String::from("/archive/old")]);
+        let mut files = ignore
+            .walk()
+            .into_iter()
+            .map(|path| path.strip_prefix(root).unwrap().to_path_buf())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                PathBuf::from(".gitignore"),
+                PathBuf::from("archive/keep.md"),
+                PathBuf::from("notes/a.md"),
+                PathBuf::from("notes/drafts/.gitignore"),
+                PathBuf::from("notes/drafts/c.md"),
+            ]
+        );
+
+        assert!(ignore.is_ignored(&root.join("node_modules/pkg/new.md")));
+        assert!(ignore.is_ignored(&root.join("notes/drafts/d.md")));
+        assert!(!ignore.is_ignored(&root.join("notes/new.md")));
+        assert!(!ignore.is_ignored(Path::new("/elsewhere/node_modules/a.md")));
+    }
+}
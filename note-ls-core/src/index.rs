@@ -0,0 +1,1243 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::SystemTime,
+};
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    blocks::{self, Block},
+    comments, fences,
+    frontmatter::{self, Frontmatter},
+    headings::{self, Heading},
+    ignore::Ignore,
+    links::{self, Link, LinkKind},
+    search,
+    tags::{self, Tag},
+};
+
+/// The extension of notes, unless the settings give others.
+pub const NOTE_EXTENSION: &str = "md";
+
+/// What the index knows about a single note.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Note {
+    pub frontmatter: Frontmatter,
+    pub links: Vec<Link>,
+    pub headings: Vec<Heading>,
+    /// Blocks marked with an ID, which links can point at.
+    pub blocks: Vec<Block>,
+    /// Tags in the frontmatter followed by the ones in the body.
+    pub tags: Vec<Tag>,
+    /// The number of unchecked tasks (`- [ ]`) in the body.
+    pub open_tasks: usize,
+    /// Every term in the note, sorted, with the number of times it occurs.
+    pub terms: Vec<(String, u32)>,
+}
+
+impl Note {
+    pub fn parse(content: &str) -> Self {
+        let frontmatter = frontmatter::parse(content).unwrap_or_default();
+        // What's in comments isn't part of the note, but positions still match the content.
+        let body = comments::blank(content);
+        let mut tags = frontmatter.tags.clone();
+        tags.extend(tags::parse_tags(&body));
+
+        let mut note = Self {
+            frontmatter,
+            links: links::parse_links(&body),
+            headings: headings::parse_headings(&body),
+            blocks: blocks::parse_blocks(&body),
+            tags,
+            open_tasks: count_open_tasks(&body),
+            terms: search::count_terms(&body),
+        };
+        // Notes live in the index for the whole session, so don't keep spare capacity around.
+        note.links.shrink_to_fit();
+        note.headings.shrink_to_fit();
+        note.blocks.shrink_to_fit();
+        note.tags.shrink_to_fit();
+        note.terms.shrink_to_fit();
+        note
+    }
+
+    /// The number of times `term` occurs in the note.
+    pub fn term_count(&self, term: &str) -> u32 {
+        self.terms
+            .binary_search_by(|(other, _)| other.as_str().cmp(term))
+            .map_or(0, |i| self.terms[i].1)
+    }
+
+    /// The note's title: its frontmatter `title`, or else its first `#` heading.
+    pub fn title(&self) -> Option<&str> {
+        self.frontmatter.title.as_deref().or_else(|| {
+            self.headings
+                .iter()
+                .find(|heading| heading.level == 1)
+                .map(|heading| heading.text.as_str())
+        })
+    }
+
+    /// Find the heading `anchor` names, either by its anchor in rendered HTML or by its text.
+    /// Anchors tell repeated headings apart, while text names the first heading with it.
+    pub fn find_heading(&self, anchor: &str) -> Option<&Heading> {
+        // `^id` names a block, not a heading.
+        if anchor.starts_with('^') {
+            return None;
+        }
+        let slug = headings::slugify(anchor);
+        let anchors = headings::anchors(&self.headings);
+        self.headings
+            .iter()
+            .zip(&anchors)
+            .find_map(|(heading, anchor_of)| (*anchor_of == slug).then_some(heading))
+            .or_else(|| {
+                self.headings
+                    .iter()
+                    .find(|heading| heading.text.eq_ignore_ascii_case(anchor))
+            })
+    }
+
+    /// Find the block marked with `id`, given without its `^`.
+    pub fn find_block(&self, id: &str) -> Option<&Block> {
+        self.blocks.iter().find(|block| block.id == id)
+    }
+
+    /// The anchor of `heading`, one of the note's headings, in rendered HTML.
+    pub fn heading_anchor(&self, heading: &Heading) -> String {
+        let anchors = headings::anchors(&self.headings);
+        self.headings
+            .iter()
+            .position(|other| other.line == heading.line)
+            .map_or_else(|| headings::slugify(&heading.text), |i| anchors[i].clone())
+    }
+
+    /// The line where the section under `heading` ends: the next heading of the same or a higher
+    /// level, if there is one.
+    pub fn section_end(&self, heading: &Heading) -> Option<u32> {
+        self.headings
+            .iter()
+            .find(|next| next.line > heading.line && next.level <= heading.level)
+            .map(|next| next.line)
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// The path as it is spelled on disk, which may not be NFC.
+    path: PathBuf,
+    note: Note,
+    /// The number `postings` know the note by, given when it's inserted.
+    id: u32,
+    /// When the file the note was parsed from was modified, or `None` if it was parsed from
+    /// content that may not be on disk, like the editor's.
+    modified: Option<SystemTime>,
+}
+
+/// Source of `NoteIndex::names_generation`, shared by every index so that a new index never
+/// reuses the generation of the one it replaces.
+static NAMES_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+/// The key an indexed path is looked up by when case is ignored.
+fn fold_case(path: &Path) -> PathBuf {
+    PathBuf::from(links::nfc_path(path).to_string_lossy().to_lowercase())
+}
+
+/// The key an alias is looked up by. Like file names, aliases ignore case.
+fn fold_alias(alias: &str) -> String {
+    alias.trim().nfc().collect::<String>().to_lowercase()
+}
+
+/// How wiki links name the notes they point to. Markdown links always name paths, relative to
+/// the linking note or else to the vault root.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Resolution {
+    /// By their path relative to the folder of the linking note, or else to the vault root.
+    #[default]
+    Relative,
+    /// By their path like `Relative`, or else by their file name or title anywhere in the vault,
+    /// as in `[[My Note]]`. When several notes go by the name, the one with the shortest path
+    /// wins.
+    Shortest,
+    /// By their path relative to the vault root.
+    Absolute,
+}
+
+/// How a link found the note it points to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Match {
+    Path,
+    /// By the note's file name, with `Resolution::Shortest`.
+    Name,
+    /// By the note's title, with `Resolution::Shortest`.
+    Title,
+    Alias,
+}
+
+/// In-memory index of every note in the vault, keyed by absolute path.
+///
+/// The index is built once when the server is initialized and kept up to date as documents
+/// change. Unlike `Files`, which only tracks documents the client has opened, it covers notes
+/// that have never been opened, so features can query the whole vault without touching the disk.
+///
+/// Paths are looked up in Unicode NFC form, so `[[Café]]` finds `Café.md` even when the
+/// filesystem (e.g. on macOS) stores the name decomposed. Paths handed out by the index are
+/// always spelled the way they are on disk.
+#[derive(Debug)]
+pub struct NoteIndex {
+    root: PathBuf,
+    notes: HashMap<PathBuf, Entry>,
+    /// Keys of `notes` by their case-folded form, so links resolve on case-insensitive file
+    /// systems the way they do in the editor.
+    folded: HashMap<PathBuf, PathBuf>,
+    /// Keys of `notes` by the case-folded aliases in their frontmatter.
+    aliases: HashMap<String, Vec<PathBuf>>,
+    /// Keys of `notes` by their case-folded file names without the extension, and by their
+    /// case-folded titles.
+    names: HashMap<String, Vec<PathBuf>>,
+    titles: HashMap<String, Vec<PathBuf>>,
+    /// The ids of the notes each term occurs in, and the keys of `notes` by id.
+    postings: HashMap<String, HashSet<u32>>,
+    ids: HashMap<u32, PathBuf>,
+    next_id: u32,
+    resolution: Resolution,
+    names_generation: u64,
+    /// Files left out of the vault, which aren't indexed unless they're opened.
+    ignore: Ignore,
+    /// Extensions of the files that are notes, without the dot.
+    extensions: Vec<String>,
+}
+
+impl Default for NoteIndex {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::new(),
+            notes: HashMap::new(),
+            folded: HashMap::new(),
+            aliases: HashMap::new(),
+            names: HashMap::new(),
+            titles: HashMap::new(),
+            postings: HashMap::new(),
+            ids: HashMap::new(),
+            next_id: 0,
+            resolution: Resolution::default(),
+            names_generation: 0,
+            ignore: Ignore::default(),
+            extensions: vec![NOTE_EXTENSION.to_string()],
+        }
+    }
+}
+
+/// Whether `path` has one of `extensions`, ignoring ASCII case.
+pub fn has_extension<S: AsRef<str>>(path: &Path, extensions: &[S]) -> bool {
+    path.extension().is_some_and(|extension| {
+        extensions
+            .iter()
+            .any(|e| extension.eq_ignore_ascii_case(OsStr::new(e.as_ref())))
+    })
+}
+
+impl NoteIndex {
+    /// Walk `root` and parse every markdown file in it, leaving out the ones its `.gitignore`
+    /// files match.
+    pub fn scan(root: &Path) -> Self {
+        Self::scan_with(Ignore::new(root, &[]), vec![NOTE_EXTENSION.to_string()])
+    }
+
+    /// Walk the root of `ignore` and parse every file with one of `extensions` in it that
+    /// `ignore` doesn't leave out.
+    pub fn scan_with(mut ignore: Ignore, extensions: Vec<String>) -> Self {
+        let paths = Self::note_paths(&mut ignore, &extensions);
+        let mut index = Self::from_paths(ignore.root(), paths);
+        index.set_ignore(ignore);
+        index.set_extensions(extensions);
+        index
+    }
+
+    /// Walk the root of `ignore` for the files with one of `extensions` in it that `ignore`
+    /// doesn't leave out, without reading them.
+    pub fn note_paths<S: AsRef<str>>(ignore: &mut Ignore, extensions: &[S]) -> Vec<PathBuf> {
+        ignore
+            .walk()
+            .into_iter()
+            .filter(|path| has_extension(path, extensions))
+            .collect()
+    }
+
+    /// Take files with one of `extensions` to be notes from now on, e.g. `["md", "txt"]`. Wiki
+    /// links without an extension are resolved by trying each of them in order.
+    pub fn set_extensions(&mut self, extensions: Vec<String>) {
+        self.extensions = extensions;
+    }
+
+    pub fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    /// Resolve wiki links the way `resolution` says from now on.
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution;
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    /// Whether the file at `path` is a note, judging by its extension.
+    pub fn is_note(&self, path: &Path) -> bool {
+        has_extension(path, &self.extensions)
+    }
+
+    /// Leave the files `ignore` matches out of the index from now on. Notes indexed already are
+    /// kept.
+    pub fn set_ignore(&mut self, ignore: Ignore) {
+        self.ignore = ignore;
+    }
+
+    /// Whether the file at `path` is left out of the vault, e.g. by a `.gitignore` file.
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        self.ignore.is_ignored(path)
+    }
+
+    /// Parse the notes at `paths`, in the vault at `root`.
+    pub fn from_paths(root: &Path, paths: Vec<PathBuf>) -> Self {
+        Self::from_paths_cached(root, paths, HashMap::new())
+    }
+
+    /// Like `from_paths`, but take the notes in `cached`, by path with the modification time of
+    /// the file they were parsed from, instead of parsing the ones whose file hasn't changed
+    /// since.
+    pub fn from_paths_cached(
+        root: &Path,
+        paths: Vec<PathBuf>,
+        mut cached: HashMap<PathBuf, (SystemTime, Note)>,
+    ) -> Self {
+        let mut index = Self {
+            root: root.to_path_buf(),
+            ..Self::default()
+        };
+        index.add_disk_notes(Self::read_notes(paths, &mut cached));
+        index
+    }
+
+    /// Parse the notes at `paths` with the modification time of their file, taking the ones in
+    /// `cached` whose file hasn't changed since instead. Files that can't be read are left out.
+    pub fn read_notes(
+        paths: Vec<PathBuf>,
+        cached: &mut HashMap<PathBuf, (SystemTime, Note)>,
+    ) -> Vec<(PathBuf, Option<SystemTime>, Note)> {
+        let mut notes = Vec::with_capacity(paths.len());
+        for path in paths {
+            let modified = fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            let note = match cached.remove(&path) {
+                Some((cached_modified, note)) if modified == Some(cached_modified) => note,
+                _ => match fs::read_to_string(&path) {
+                    Ok(content) => Note::parse(&content),
+                    Err(_) => continue,
+                },
+            };
+            notes.push((path, modified, note));
+        }
+        notes
+    }
+
+    /// Index notes read from disk by `read_notes`, e.g. a batch at a time while the vault is
+    /// being indexed. Notes indexed already, like the ones opened in the editor meanwhile, are
+    /// kept.
+    pub fn add_disk_notes(&mut self, notes: Vec<(PathBuf, Option<SystemTime>, Note)>) {
+        for (path, modified, note) in notes {
+            if self.notes.contains_key(&links::nfc_path(&path)) {
+                continue;
+            }
+            self.insert(Entry {
+                note,
+                path,
+                id: 0,
+                modified,
+            });
+        }
+        self.touch_names();
+    }
+
+    fn touch_names(&mut self) {
+        self.names_generation = NAMES_GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A number that changes whenever notes are added, removed or renamed, or their titles or
+    /// aliases change, for caching what only depends on the names of notes.
+    pub fn names_generation(&self) -> u64 {
+        self.names_generation
+    }
+
+    fn insert(&mut self, mut entry: Entry) {
+        let key = links::nfc_path(&entry.path);
+        entry.id = self.next_id;
+        self.next_id += 1;
+        self.ids.insert(entry.id, key.clone());
+        for (term, _) in &entry.note.terms {
+            self.postings
+                .entry(term.clone())
+                .or_default()
+                .insert(entry.id);
+        }
+        self.folded.insert(fold_case(&entry.path), key.clone());
+        for alias in &entry.note.frontmatter.aliases {
+            let keys = self.aliases.entry(fold_alias(alias)).or_default();
+            if !keys.contains(&key) {
+                keys.push(key.clone());
+            }
+        }
+        if let Some(name) = entry.path.file_stem() {
+            let name = fold_alias(&name.to_string_lossy());
+            self.names.entry(name).or_default().push(key.clone());
+        }
+        if let Some(title) = entry.note.title() {
+            let title = fold_alias(title);
+            self.titles.entry(title).or_default().push(key.clone());
+        }
+        self.notes.insert(key, entry);
+    }
+
+    fn remove_key(&mut self, key: &Path) -> Option<Entry> {
+        let entry = self.notes.remove(key)?;
+        self.ids.remove(&entry.id);
+        for (term, _) in &entry.note.terms {
+            if let Some(ids) = self.postings.get_mut(term) {
+                ids.remove(&entry.id);
+                if ids.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+        let folded = fold_case(key);
+        if self.folded.get(&folded).map(PathBuf::as_path) == Some(key) {
+            self.folded.remove(&folded);
+        }
+        for alias in &entry.note.frontmatter.aliases {
+            let alias = fold_alias(alias);
+            if let Some(keys) = self.aliases.get_mut(&alias) {
+                keys.retain(|other| other != key);
+                if keys.is_empty() {
+                    self.aliases.remove(&alias);
+                }
+            }
+        }
+        let name = entry
+            .path
+            .file_stem()
+            .map(|name| fold_alias(&name.to_string_lossy()));
+        let title = entry.note.title().map(fold_alias);
+        for (map, name) in [(&mut self.names, name), (&mut self.titles, title)] {
+            let Some(name) = name else {
+                continue;
+            };
+            if let Some(keys) = map.get_mut(&name) {
+                keys.retain(|other| other != key);
+                if keys.is_empty() {
+                    map.remove(&name);
+                }
+            }
+        }
+        Some(entry)
+    }
+
+    /// Look up a note by path, falling back to ignoring case.
+    fn lookup(&self, path: &Path) -> Option<&Entry> {
+        self.notes.get(&links::nfc_path(path)).or_else(|| {
+            let key = self.folded.get(&fold_case(path))?;
+            self.notes.get(key)
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Re-parse a note from its current content, adding it if it wasn't indexed yet.
+    pub fn update(&mut self, path: PathBuf, content: &str) {
+        self.update_note(path, Note::parse(content));
+    }
+
+    /// Index a note parsed already, e.g. outside a lock on the index.
+    pub fn update_note(&mut self, path: PathBuf, note: Note) {
+        let old = self.remove_key(&links::nfc_path(&path));
+        let same_names = old.as_ref().is_some_and(|old| {
+            old.note.title() == note.title()
+                && old.note.frontmatter.aliases == note.frontmatter.aliases
+        });
+        if !same_names {
+            self.touch_names();
+        }
+        // Keep the spelling on disk of notes that are already indexed.
+        let path = match old {
+            Some(entry) => entry.path,
+            None => path,
+        };
+        self.insert(Entry {
+            path,
+            note,
+            id: 0,
+            modified: None,
+        });
+    }
+
+    /// Forget a note that no longer exists.
+    pub fn remove(&mut self, path: &Path) {
+        if self.remove_key(&links::nfc_path(path)).is_some() {
+            self.touch_names();
+        }
+    }
+
+    /// Move the entries of renamed notes (or notes in renamed folders) to their new paths.
+    pub fn rename(&mut self, renames: &Renames) {
+        let moved = self
+            .notes
+            .iter()
+            .filter_map(|(key, entry)| Some((key.clone(), renames.map(&entry.path)?)))
+            .collect::<Vec<_>>();
+        if !moved.is_empty() {
+            self.touch_names();
+        }
+
+        for (old, new) in moved {
+            if let Some(entry) = self.remove_key(&old) {
+                self.insert(Entry {
+                    path: new,
+                    note: entry.note,
+                    id: 0,
+                    modified: entry.modified,
+                });
+            }
+        }
+    }
+
+    pub fn notes(&self) -> impl Iterator<Item = (&Path, &Note)> {
+        self.notes
+            .values()
+            .map(|entry| (entry.path.as_path(), &entry.note))
+    }
+
+    /// The notes parsed from their file on disk, with the time the file was modified, e.g. to
+    /// cache them for `from_paths_cached`. Notes parsed from the editor's content are left out.
+    pub fn disk_notes(&self) -> impl Iterator<Item = (&Path, SystemTime, &Note)> {
+        self.notes
+            .values()
+            .filter_map(|entry| Some((entry.path.as_path(), entry.modified?, &entry.note)))
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&Note> {
+        self.lookup(path).map(|entry| &entry.note)
+    }
+
+    /// Find the line a link's `#heading` or `#^block` anchor points at in the note at `target`.
+    pub fn anchor_line(&self, target: &Path, link: &Link) -> Option<u32> {
+        let note = self.get(target)?;
+        match link.block_id() {
+            Some(id) => Some(note.find_block(id)?.start),
+            None => Some(note.find_heading(link.anchor()?)?.line),
+        }
+    }
+
+    /// Find the note that `link` in the note at `from` points to.
+    ///
+    /// Candidates that match exactly win over ones that only match when ignoring case, which win
+    /// over notes with a matching file name or title (with `Resolution::Shortest`), which win
+    /// over notes with a matching alias.
+    pub fn resolve(&self, from: &Path, link: &Link) -> Option<PathBuf> {
+        self.resolve_match(from, link).map(|(path, _)| path)
+    }
+
+    /// Like `resolve`, also telling how the link found the note.
+    pub fn resolve_match(&self, from: &Path, link: &Link) -> Option<(PathBuf, Match)> {
+        let note_dir = match (self.resolution, link.kind) {
+            (Resolution::Absolute, LinkKind::Wiki) => &self.root,
+            _ => from.parent().unwrap_or(&self.root),
+        };
+        let candidates = link.candidates_with(note_dir, &self.root, &self.extensions);
+        let by_path = candidates
+            .iter()
+            .find_map(|candidate| self.notes.get(&links::nfc_path(candidate)))
+            .or_else(|| {
+                candidates
+                    .iter()
+                    .find_map(|candidate| self.lookup(candidate))
+            });
+        let shortest = self.resolution == Resolution::Shortest && link.kind == LinkKind::Wiki;
+
+        let (entry, found) = if let Some(entry) = by_path {
+            (entry, Match::Path)
+        } else if let Some(entry) = self.by_name(link).filter(|_| shortest) {
+            (entry, Match::Name)
+        } else if let Some(entry) = self.by_title(link).filter(|_| shortest) {
+            (entry, Match::Title)
+        } else {
+            (self.by_alias(link)?, Match::Alias)
+        };
+        Some((entry.path.clone(), found))
+    }
+
+    /// The note with the shortest path among `keys`.
+    fn shortest<'a>(&self, keys: impl Iterator<Item = &'a PathBuf>) -> Option<&Entry> {
+        let key = keys.min_by_key(|key| (key.components().count(), *key))?;
+        self.notes.get(key)
+    }
+
+    /// The note a wiki link names by its file name anywhere in the vault, along with any folders
+    /// above it, as in `[[projects/plan]]` for `work/projects/plan.md`.
+    fn by_name(&self, link: &Link) -> Option<&Entry> {
+        if link.kind != LinkKind::Wiki {
+            return None;
+        }
+        let target = Path::new(link.target_path());
+        let explicit = has_extension(target, &self.extensions);
+        let name = match explicit {
+            true => target.file_stem()?,
+            false => target.file_name()?,
+        };
+        let keys = self.names.get(&fold_alias(&name.to_string_lossy()))?;
+        let target = fold_case(target);
+        self.shortest(keys.iter().filter(|key| {
+            let key = fold_case(key);
+            match explicit {
+                true => key.ends_with(&target),
+                false => key.with_extension("").ends_with(&target),
+            }
+        }))
+    }
+
+    /// The note a wiki link names by its title.
+    fn by_title(&self, link: &Link) -> Option<&Entry> {
+        if link.kind != LinkKind::Wiki {
+            return None;
+        }
+        self.shortest(self.titles.get(&fold_alias(link.target_path()))?.iter())
+    }
+
+    /// The note a wiki link names by one of its aliases.
+    fn by_alias(&self, link: &Link) -> Option<&Entry> {
+        if link.kind != LinkKind::Wiki {
+            return None;
+        }
+        self.shortest(self.aliases.get(&fold_alias(link.target_path()))?.iter())
+    }
+
+    /// The other notes with the title or one of the aliases of the note at `path`, by path, with
+    /// the name they share. `[[name]]` links can't tell them apart.
+    pub fn namesakes(&self, path: &Path) -> Vec<(String, PathBuf)> {
+        let Some(entry) = self.lookup(path) else {
+            return Vec::new();
+        };
+        let key = links::nfc_path(&entry.path);
+        let names = entry
+            .note
+            .title()
+            .into_iter()
+            .chain(entry.note.frontmatter.aliases.iter().map(String::as_str));
+        let mut namesakes = Vec::<(String, PathBuf)>::new();
+        for name in names.filter(|name| !name.trim().is_empty()) {
+            let folded = fold_alias(name);
+            let keys = [self.titles.get(&folded), self.aliases.get(&folded)];
+            for other in keys.into_iter().flatten().flatten() {
+                let Some(other) = self.notes.get(other).filter(|_| *other != key) else {
+                    continue;
+                };
+                if !namesakes.iter().any(|(_, path)| *path == other.path) {
+                    namesakes.push((name.to_string(), other.path.clone()));
+                }
+            }
+        }
+        namesakes.sort_by(|a, b| a.1.cmp(&b.1));
+        namesakes
+    }
+
+    /// The number of links pointing at each note that is linked to, by the note's path.
+    pub fn link_counts(&self) -> HashMap<PathBuf, usize> {
+        let mut counts = HashMap::new();
+        for (path, note) in self.notes() {
+            for link in &note.links {
+                if let Some(target) = self.resolve(path, link) {
+                    *counts.entry(target).or_insert(0) += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    /// The notes with every term of `query` in them, best match first, with their scores.
+    ///
+    /// Notes score higher the more often the terms occur in them, and the rarer the terms are in
+    /// the vault.
+    pub fn search(&self, query: &str) -> Vec<(&Path, f64)> {
+        let terms = search::query_terms(query);
+        let Some(mut postings) = terms
+            .iter()
+            .map(|term| self.postings.get(term))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Vec::new();
+        };
+        postings.sort_by_key(|ids| ids.len());
+        let Some((rarest, others)) = postings.split_first() else {
+            return Vec::new();
+        };
+
+        let total = self.notes.len() as f64;
+        let mut found = rarest
+            .iter()
+            .filter(|id| others.iter().all(|ids| ids.contains(id)))
+            .filter_map(|id| self.notes.get(self.ids.get(id)?))
+            .map(|entry| {
+                let score = terms
+                    .iter()
+                    .map(|term| {
+                        let count = f64::from(entry.note.term_count(term));
+                        let notes = self.postings[term].len() as f64;
+                        (1.0 + count.ln()) * (1.0 + total / notes).ln()
+                    })
+                    .sum::<f64>();
+                (entry.path.as_path(), score)
+            })
+            .collect::<Vec<_>>();
+        found.sort_by(|(a, a_score), (b, b_score)| {
+            b_score.total_cmp(a_score).then_with(|| a.cmp(b))
+        });
+        found
+    }
+
+    /// The vault's link graph: every note, by its path, with the other notes it links to.
+    pub fn link_graph(&self) -> HashMap<PathBuf, HashSet<PathBuf>> {
+        self.notes()
+            .map(|(path, note)| {
+                let targets = note
+                    .links
+                    .iter()
+                    .filter_map(|link| self.resolve(path, link))
+                    .filter(|target| links::nfc_path(target) != links::nfc_path(path))
+                    .collect();
+                (path.to_path_buf(), targets)
+            })
+            .collect()
+    }
+
+    /// Every tag used in the vault, with the number of notes carrying it.
+    pub fn tags(&self) -> BTreeMap<&str, usize> {
+        let mut tags = BTreeMap::new();
+        for (_, note) in self.notes() {
+            let mut names = note
+                .tags
+                .iter()
+                .map(|tag| tag.name.as_str())
+                .collect::<Vec<_>>();
+            names.sort_unstable();
+            names.dedup();
+            for name in names {
+                *tags.entry(name).or_default() += 1;
+            }
+        }
+        tags
+    }
+
+    /// Every occurrence of the tag `name` in the vault, along with the note carrying it.
+    pub fn tag_occurrences(&self, name: &str) -> Vec<(&Path, &Tag)> {
+        let mut occurrences = self
+            .notes()
+            .flat_map(|(path, note)| note.tags.iter().map(move |tag| (path, tag)))
+            .filter(|(_, tag)| tag.name == name)
+            .collect::<Vec<_>>();
+        occurrences.sort_by_key(|(path, tag)| (*path, tag.line, tag.start));
+        occurrences
+    }
+
+    /// Every link in the vault pointing at the note at `target`, along with the linking note.
+    pub fn backlinks(&self, target: &Path) -> Vec<(&Path, &Link)> {
+        let target = links::nfc_path(target);
+        let mut backlinks = self
+            .notes()
+            .flat_map(|(path, note)| note.links.iter().map(move |link| (path, link)))
+            .filter(|(path, link)| {
+                self.resolve(path, link)
+                    .map(|resolved| links::nfc_path(&resolved))
+                    == Some(target.clone())
+            })
+            .collect::<Vec<_>>();
+        backlinks.sort_by_key(|(path, link)| (*path, link.line, link.start));
+        backlinks
+    }
+}
+
+/// Count the list items in `document` that are unchecked tasks, skipping frontmatter and fenced
+/// code blocks.
+fn count_open_tasks(document: &str) -> usize {
+    let mut count = 0;
+    for line in fences::body_lines(document).filter_map(|(_, line)| line) {
+        let trimmed = line.trim_start();
+        let item = trimmed.strip_prefix(['-', '*', '+']).or_else(|| {
+            let marker = trimmed.trim_start_matches(|c: char| c.is_ascii_digit());
+            (marker.len() < trimmed.len())
+                .then(|| marker.strip_prefix(['.', ')']))
+                .flatten()
+        });
+        if item.is_some_and(|item| item.starts_with(" [ ]")) {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// A set of file and folder renames, applied as a whole.
+#[derive(Debug, Default)]
+pub struct Renames {
+    renames: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Renames {
+    pub fn new(renames: Vec<(PathBuf, PathBuf)>) -> Self {
+        Self { renames }
+    }
+
+    /// The renamed files and folders, as `(old, new)` pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &Path)> {
+        self.renames
+            .iter()
+            .map(|(old, new)| (old.as_path(), new.as_path()))
+    }
+
+    /// Where `path` ends up after the renames, if it is moved at all.
+    ///
+    /// A rename of a folder moves everything inside it.
+    pub fn map(&self, path: &Path) -> Option<PathBuf> {
+        self.renames.iter().find_map(|(old, new)| {
+            let rest = path.strip_prefix(old).ok()?;
+            Some(if rest.as_os_str().is_empty() {
+                new.clone()
+            } else {
+                new.join(rest)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_headings_are_found_by_anchor() {
+        let note = Note::parse("# Setup\n## Linux\n# Usage\n## Linux\n");
+        let line = |anchor| note.find_heading(anchor).map(|heading| heading.line);
+        assert_eq!(line("Linux"), Some(1));
+        assert_eq!(line("linux"), Some(1));
+        assert_eq!(line("linux-1"), Some(3));
+        assert_eq!(line("linux-2"), None);
+        assert_eq!(note.heading_anchor(&note.headings[3]), "linux-1");
+    }
+
+    #[test]
+    fn block_references_point_at_the_block() {
+        let root = Path::new("/vault");
+        let mut index = NoteIndex::from_paths(root, Vec::new());
+        index.update(root.join("a.md"), "[[b#^intro]] [[b#^gone]] [[b#intro]]");
+        index.update(root.join("b.md"), "# Intro\n\nFirst\nsecond ^intro\n");
+
+        let links = &index.get(&root.join("a.md")).unwrap().links;
+        assert_eq!(links[0].block_id(), Some("intro"));
+        let line = |link| index.anchor_line(&root.join("b.md"), link);
+        assert_eq!(line(&links[0]), Some(2));
+        assert_eq!(line(&links[1]), None);
+        assert_eq!(line(&links[2]), Some(0));
+    }
+
+    #[test]
+    fn renames_map_folders() {
+        let renames = Renames::new(vec![(PathBuf::from("/v/old"), PathBuf::from("/v/new"))]);
+        assert_eq!(
+            renames.map(Path::new("/v/old/a/b.md")),
+            Some(PathBuf::from("/v/new/a/b.md"))
+        );
+        assert_eq!(renames.map(Path::new("/v/older.md")), None);
+    }
+
+    #[test]
+    fn open_tasks_are_counted() {
+        let note = Note::parse(
+            "---\ntitle: Tasks\n---\n- [ ] a\n  * [ ] b\n- [x] done\n\
+             1. [ ] c\n-[ ] d\n```\n- [ ] code\n```\n",
+        );
+        assert_eq!(note.open_tasks, 3);
+    }
+
+    #[test]
+    fn comments_are_left_out() {
+        let note =
+            Note::parse("[[kept]] %%[[hidden]] #draft\n- [ ] later%% #kept\n<!-- [[old]] -->");
+        let targets = note
+            .links
+            .iter()
+            .map(|link| link.target.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(targets, ["kept"]);
+        assert_eq!(note.tags.len(), 1);
+        assert_eq!((note.tags[0].line, note.tags[0].start), (1, 14));
+        assert_eq!((note.open_tasks, note.term_count("later")), (0, 0));
+    }
+
+    #[test]
+    fn resolution_ignores_unicode_normalization() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        // "Café" spelled with a combining accent, the way macOS stores file names.
+        let decomposed = root.join("Cafe\u{301}.md");
+        fs::write(&decomposed, "").unwrap();
+        fs::write(root.join("note.md"), "[[Caf\u{e9}]]").unwrap();
+
+        let index = NoteIndex::scan(root);
+        let link = &index.get(&root.join("note.md")).unwrap().links[0];
+        assert_eq!(index.resolve(&root.join("note.md"), link), Some(decomposed));
+    }
+
+    #[test]
+    fn resolution_strategies() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::create_dir_all(root.join("work/projects")).unwrap();
+        fs::create_dir(root.join("deep")).unwrap();
+        fs::write(root.join("work/projects/Plan.md"), "# Grand Plan").unwrap();
+        fs::write(root.join("work/a.md"), "").unwrap();
+        fs::write(root.join("work/Plan.md"), "").unwrap();
+        fs::write(root.join("deep/plan.md"), "").unwrap();
+        fs::write(root.join("a.md"), "").unwrap();
+
+        let mut index = NoteIndex::scan(root);
+        let from = root.join("work/a.md");
+        let resolve = |index: &NoteIndex, target: &str| {
+            let link = &links::parse_line(target, 0)[0];
+            index.resolve_match(&from, link).map(|(path, found)| {
+                let path = path.strip_prefix(root).unwrap().to_path_buf();
+                (links::path_to_target(&path), found)
+            })
+        };
+
+        assert_eq!(
+            resolve(&index, "[[plan]]"),
+            Some((String::from("work/Plan.md"), Match::Path))
+        );
+        assert_eq!(resolve(&index, "[[grand plan]]"), None);
+        assert_eq!(
+            resolve(&index, "[[projects/plan.md]]"),
+            Some((String::from("work/projects/Plan.md"), Match::Path))
+        );
+
+        index.set_resolution(Resolution::Shortest);
+        assert_eq!(
+            resolve(&index, "[[Grand Plan|the plan]]"),
+            Some((String::from("work/projects/Plan.md"), Match::Title))
+        );
+        assert_eq!(
+            resolve(&index, "[[deep/plan]]"),
+            Some((String::from("deep/plan.md"), Match::Path))
+        );
+        assert_eq!(
+            resolve(&index, "[[work/projects/PLAN.md]]"),
+            Some((String::from("work/projects/Plan.md"), Match::Path))
+        );
+
+        let from = root.join("a.md");
+        let link = &links::parse_line("[[x/projects/plan]]", 0)[0];
+        assert_eq!(index.resolve_match(&from, link), None);
+        let link = &links::parse_line("[[projects/plan]]", 0)[0];
+        assert_eq!(
+            index.resolve_match(&from, link),
+            Some((root.join("work/projects/Plan.md"), Match::Name))
+        );
+        // The shortest path wins.
+        let link = &links::parse_line("[[plan]]", 0)[0];
+        assert_eq!(
+            index.resolve_match(&from, link),
+            Some((root.join("deep/plan.md"), Match::Name))
+        );
+        let link = &links::parse_line("[text](plan.md)", 0)[0];
+        assert_eq!(index.resolve_match(&from, link), None);
+
+        index.remove(&root.join("deep/plan.md"));
+        let link = &links::parse_line("[[plan]]", 0)[0];
+        assert_eq!(
+            index.resolve_match(&from, link),
+            Some((root.join("work/Plan.md"), Match::Name))
+        );
+
+        index.set_resolution(Resolution::Absolute);
+        assert_eq!(resolve(&index, "[[plan]]"), None);
+        assert_eq!(
+            resolve(&index, "[[work/plan]]"),
+            Some((String::from("work/Plan.md"), Match::Path))
+        );
+        assert_eq!(
+            resolve(&index, "[x](plan.md)"),
+            Some((String::from("work/Plan.md"), Match::Path))
+        );
+    }
+
+    #[test]
+    fn resolution_falls_back_to_ignoring_case() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::write(root.join("Note.md"), "").unwrap();
+        fs::write(root.join("a.md"), "[[note]]").unwrap();
+
+        let mut index = NoteIndex::scan(root);
+        let link = &index.get(&root.join("a.md")).unwrap().links[0];
+        assert_eq!(
+            index.resolve(&root.join("a.md"), link),
+            Some(root.join("Note.md"))
+        );
+
+        let link = link.clone();
+        index.remove(&root.join("Note.md"));
+        assert_eq!(index.resolve(&root.join("a.md"), &link), None);
+    }
+
+    #[test]
+    fn notes_may_have_other_extensions() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::write(root.join("a.md"), "[[b]] [[c]]").unwrap();
+        fs::write(root.join("b.txt"), "").unwrap();
+        fs::write(root.join("c.markdown"), "").unwrap();
+        fs::write(root.join("image.png"), "").unwrap();
+
+        let extensions = ["md", "markdown", "txt"].map(String::from).to_vec();
+        let index = NoteIndex::scan_with(Ignore::new(root, &[]), extensions);
+        let mut paths = index
+            .notes()
+            .map(|(path, _)| path.strip_prefix(root).unwrap().to_path_buf())
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(paths, ["a.md", "b.txt", "c.markdown"].map(PathBuf::from));
+
+        let links = &index.get(&root.join("a.md")).unwrap().links;
+        let resolved = links
+            .iter()
+            .map(|link| index.resolve(&root.join("a.md"), link))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            resolved,
+            vec![Some(root.join("b.txt")), Some(root.join("c.markdown"))]
+        );
+        assert!(index.is_note(Path::new("/elsewhere/D.TXT")));
+        assert!(!NoteIndex::scan(root).is_note(&root.join("b.txt")));
+    }
+
+    #[test]
+    fn links_resolve_through_aliases() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::write(root.join("long name.md"), "---\naliases: [Short]\n---\n").unwrap();
+        fs::write(root.join("a.md"), "[[short#Heading]] [x](Short)").unwrap();
+
+        let mut index = NoteIndex::scan(root);
+        let links = index.get(&root.join("a.md")).unwrap().links.clone();
+        assert_eq!(
+            index.resolve(&root.join("a.md"), &links[0]),
+            Some(root.join("long name.md"))
+        );
+        assert_eq!(index.resolve(&root.join("a.md"), &links[1]), None);
+
+        index.update(root.join("long name.md"), "no aliases any more");
+        assert_eq!(index.resolve(&root.join("a.md"), &links[0]), None);
+    }
+
+    #[test]
+    fn notes_sharing_a_name_are_namesakes() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::write(root.join("a.md"), "# Tea\n").unwrap();
+        fs::write(root.join("b.md"), "---\ntitle: tea\n---\n").unwrap();
+        fs::write(root.join("c.md"), "---\naliases: [Tea, Brew]\n---\n").unwrap();
+        fs::write(root.join("d.md"), "# Coffee\n").unwrap();
+
+        let index = NoteIndex::scan(root);
+        assert_eq!(
+            index.namesakes(&root.join("a.md")),
+            [
+                ("Tea".to_string(), root.join("b.md")),
+                ("Tea".to_string(), root.join("c.md"))
+            ]
+        );
+        assert_eq!(
+            index.namesakes(&root.join("c.md"))[0],
+            ("Tea".to_string(), root.join("a.md"))
+        );
+        assert!(index.namesakes(&root.join("d.md")).is_empty());
+    }
+
+    #[test]
+    fn names_generation_follows_names_only() {
+        let root = Path::new("/vault");
+        let mut index = NoteIndex::from_paths(root, Vec::new());
+        let mut generation = index.names_generation();
+        let mut changed = |index: &NoteIndex| {
+            let old = std::mem::replace(&mut generation, index.names_generation());
+            old != generation
+        };
+
+        index.update(root.join("a.md"), "text");
+        assert!(changed(&index));
+        index.update(root.join("a.md"), "[[b]] #tag");
+        assert!(!changed(&index));
+        index.update(root.join("a.md"), "---\naliases: [A]\n---\n");
+        assert!(changed(&index));
+        index.update(root.join("a.md"), "---\naliases: [A]\n---\n# Title");
+        assert!(changed(&index));
+        index.remove(&root.join("missing.md"));
+        assert!(!changed(&index));
+        index.remove(&root.join("a.md"));
+        assert!(changed(&index));
+        assert_ne!(
+            NoteIndex::from_paths(root, Vec::new()).names_generation(),
+            index.names_generation()
+        );
+    }
+
+    #[test]
+    fn tags_across_the_vault() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::write(root.join("a.md"), "#project #todo\n#todo again").unwrap();
+        fs::write(root.join("b.md"), "```\n#fenced\n```\n#todo").unwrap();
+
+        let index = NoteIndex::scan(root);
+        assert_eq!(
+            index.tags().into_iter().collect::<Vec<_>>(),
+            vec![("project", 1), ("todo", 2)]
+        );
+
+        let occurrences = index
+            .tag_occurrences("todo")
+            .into_iter()
+            .map(|(path, tag)| (path.strip_prefix(root).unwrap(), tag.line))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            occurrences,
+            vec![
+                (Path::new("a.md"), 0),
+                (Path::new("a.md"), 1),
+                (Path::new("b.md"), 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn notes_are_searched_by_their_words() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::write(root.join("soup.md"), "# Soup\nTomato soup, then more soup.").unwrap();
+        fs::write(root.join("bread.md"), "Bread goes with soup.").unwrap();
+        fs::write(root.join("tomato.md"), "Tomato salad").unwrap();
+
+        let mut index = NoteIndex::scan(root);
+        let found = |index: &NoteIndex, query: &str| {
+            index
+                .search(query)
+                .into_iter()
+                .map(|(path, _)| path.strip_prefix(root).unwrap().to_path_buf())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            found(&index, "SOUP"),
+            vec![PathBuf::from("soup.md"), PathBuf::from("bread.md")]
+        );
+        assert_eq!(found(&index, "tomato soup"), vec![PathBuf::from("soup.md")]);
+        assert!(found(&index, "soup cake").is_empty());
+        assert!(found(&index, "").is_empty());
+
+        index.update(root.join("bread.md"), "Bread and butter");
+        index.remove(&root.join("soup.md"));
+        assert!(found(&index, "soup").is_empty());
+        assert_eq!(found(&index, "butter"), vec![PathBuf::from("bread.md")]);
+    }
+
+    #[test]
+    fn cached_notes_are_kept_until_their_file_changes() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        let (a, b) = (root.join("a.md"), root.join("b.md"));
+        fs::write(&a, "# A on disk").unwrap();
+        fs::write(&b, "# B on disk").unwrap();
+        let modified = |path: &Path| fs::metadata(path).unwrap().modified().unwrap();
+
+        let cached = HashMap::from([
+            (a.clone(), (modified(&a), Note::parse("# A cached"))),
+            (
+                b.clone(),
+                (SystemTime::UNIX_EPOCH, Note::parse("# B cached")),
+            ),
+        ]);
+        let mut index = NoteIndex::from_paths_cached(root, vec![a.clone(), b.clone()], cached);
+        assert_eq!(index.get(&a).unwrap().title(), Some("A cached"));
+        assert_eq!(index.get(&b).unwrap().title(), Some("B on disk"));
+        assert_eq!(index.disk_notes().count(), 2);
+
+        // The editor's content may never make it to disk.
+        index.update(a.clone(), "# A edited");
+        let on_disk = index
+            .disk_notes()
+            .map(|(path, time, _)| (path.to_path_buf(), time))
+            .collect::<Vec<_>>();
+        assert_eq!(on_disk, vec![(b.clone(), modified(&b))]);
+
+        // Reading the vault in batches doesn't replace what the editor has.
+        let notes = NoteIndex::read_notes(vec![a.clone()], &mut HashMap::new());
+        assert_eq!(notes[0].2.title(), Some("A on disk"));
+        index.add_disk_notes(notes);
+        assert_eq!(index.get(&a).unwrap().title(), Some("A edited"));
+    }
+
+    #[test]
+    fn backlinks_across_the_vault() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::create_dir(root.join("sub")).unwrap();
+        fs::write(root.join("target.md"), "# Target").unwrap();
+        fs::write(root.join("a.md"), "[[target]] and [again](target.md)").unwrap();
+        fs::write(root.join("sub/b.md"), "[[../target#Target]]\n[[a]]").unwrap();
+
+        let index = NoteIndex::scan(root);
+        let backlinks = index.backlinks(&root.join("target.md"));
+        let sources = backlinks
+            .iter()
+            .map(|(path, link)| (path.strip_prefix(root).unwrap(), link.line, link.start))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            sources,
+            vec![
+                (Path::new("a.md"), 0, 0),
+                (Path::new("a.md"), 0, 15),
+                (Path::new("sub/b.md"), 0, 0),
+            ]
+        );
+        assert_eq!(
+            index.link_counts(),
+            HashMap::from([(root.join("target.md"), 3), (root.join("a.md"), 1)])
+        );
+        assert_eq!(
+            index.link_graph(),
+            HashMap::from([
+                (root.join("target.md"), HashSet::new()),
+                (root.join("a.md"), HashSet::from([root.join("target.md")])),
+                (
+                    root.join("sub/b.md"),
+                    HashSet::from([root.join("target.md"), root.join("a.md")])
+                ),
+            ])
+        );
+    }
+}
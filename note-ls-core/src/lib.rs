@@ -0,0 +1,45 @@
+//! The note-taking engine behind note-ls, usable without the language server.
+//!
+//! It parses markdown notes (links, headings, block IDs, tags and YAML frontmatter) and keeps an index of a
+//! vault that resolves links between notes and answers backlink and tag queries. Positions and
+//! URIs use the `lsp-types` types, so results map directly onto editor locations.
+//!
+//! ```
+//! use note_ls_core::index::Note;
+//!
+//! let note = Note::parse("# Groceries\nSee [[recipes#Soup]] for #cooking ideas.");
+//! assert_eq!(note.title(), Some("Groceries"));
+//! assert_eq!(note.links[0].target_path(), "recipes");
+//! assert_eq!(note.links[0].anchor(), Some("Soup"));
+//! assert_eq!(note.tags[0].name, "cooking");
+//! ```
+//!
+//! Use [`index::NoteIndex::scan`] to index a whole vault, and [`index::NoteIndex::resolve`] to
+//! find the note a link points to. The index only keeps what it parsed out of each note; read
+//! their text through a [`contents::ContentCache`].
+//!
+//! Vault-wide views of the index are built from it too: [`report`] finds links that lead
+//! nowhere and notes cut off from the rest, [`graph`] exports the link graph, and [`compare`]
+//! tells two copies of a vault apart.
+//!
+//! With the `serde` feature, parsed notes can be serialized, e.g. to cache an index between
+//! sessions with [`index::NoteIndex::from_paths_cached`], and so can the reports.
+
+pub use lsp_types;
+
+pub mod blocks;
+pub mod comments;
+pub mod compare;
+pub mod contents;
+pub mod fences;
+pub mod footnotes;
+pub mod frontmatter;
+pub mod graph;
+pub mod headings;
+pub mod ignore;
+pub mod index;
+pub mod links;
+pub mod report;
+pub mod search;
+pub mod tags;
+pub mod uri;
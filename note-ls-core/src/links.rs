@@ -0,0 +1,427 @@
+use std::{
+    borrow::Cow,
+    path::{Component, Path, PathBuf},
+};
+
+use lsp_types::{Position, Range};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use unicode_normalization::UnicodeNormalization;
+
+/// The syntax a link was written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LinkKind {
+    /// `[[target]]`
+    Wiki,
+    /// `[text](target)`
+    Markdown,
+}
+
+/// A link found in a document.
+///
+/// `start` and `end` are byte offsets into `line` and cover the whole link, including brackets
+/// and the leading `!` of embeds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Link {
+    pub kind: LinkKind,
+    /// Whether the link is an embed (`![[...]]` or `![...](...)`).
+    pub embed: bool,
+    pub target: String,
+    pub text: Option<String>,
+    pub line: u32,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Link {
+    /// The part of the target naming a file, without any `#heading` or `|alias` suffix.
+    pub fn target_path(&self) -> &str {
+        let end = self.target.find(['#', '|']).unwrap_or(self.target.len());
+        self.target[..end].trim()
+    }
+
+    /// The file the target names, as it's spelled on disk.
+    ///
+    /// Markdown targets are percent-decoded and may be wrapped in `<...>`; wiki link targets are
+    /// taken literally.
+    pub fn decoded_path(&self) -> Cow<'_, str> {
+        let target = self.target_path();
+        match self.kind {
+            LinkKind::Wiki => Cow::Borrowed(target),
+            LinkKind::Markdown => decode_target(target),
+        }
+    }
+
+    /// Whether the link points outside the vault, e.g. `https://...` or `mailto:...`.
+    pub fn is_external(&self) -> bool {
+        self.kind == LinkKind::Markdown
+            && (self.target.contains("://") || self.target.starts_with("mailto:"))
+    }
+
+    /// The `#heading` or `|alias` suffix of the target, including the separator.
+    pub fn target_suffix(&self) -> &str {
+        let start = self.target.find(['#', '|']).unwrap_or(self.target.len());
+        &self.target[start..]
+    }
+
+    /// The `#heading` part of the target, without the `#`.
+    pub fn anchor(&self) -> Option<&str> {
+        // A `#` in the alias isn't an anchor, as in `[[csharp|C# notes]]`.
+        let end = self.target.find('|').unwrap_or(self.target.len());
+        let target = &self.target[..end];
+        let anchor = target[target.find('#')? + 1..].trim();
+        (!anchor.is_empty()).then_some(anchor)
+    }
+
+    /// The ID of the block the target points at, as in `[[note#^intro]]`, without the `^`.
+    pub fn block_id(&self) -> Option<&str> {
+        self.anchor()?.strip_prefix('^')
+    }
+
+    /// The text a wiki link shows instead of its target, as in `[[target|alias]]`.
+    pub fn alias(&self) -> Option<&str> {
+        if self.kind != LinkKind::Wiki {
+            return None;
+        }
+        let (_, alias) = self.target.split_once('|')?;
+        let alias = alias.trim();
+        (!alias.is_empty()).then_some(alias)
+    }
+
+    /// The range the whole link covers.
+    pub fn range(&self) -> Range {
+        Range::new(
+            Position::new(self.line, self.start as u32),
+            Position::new(self.line, self.end as u32),
+        )
+    }
+
+    /// The source of this link with its target path replaced, keeping any text and suffix.
+    pub fn with_target(&self, target: &str) -> String {
+        let embed = if self.embed { "!" } else { "" };
+        let suffix = self.target_suffix();
+        match self.kind {
+            LinkKind::Wiki => format!("{embed}[[{target}{suffix}]]"),
+            LinkKind::Markdown => format!(
+                "{embed}[{}]({target}{suffix})",
+                self.text.as_deref().unwrap_or("")
+            ),
+        }
+    }
+
+    /// The source of this link with its `#heading` anchor replaced, keeping its target path,
+    /// text and alias.
+    pub fn with_anchor(&self, anchor: &str) -> String {
+        let alias = self.target.find('|').map_or("", |i| &self.target[i..]);
+        let target = format!("{}#{anchor}{alias}", self.target_path());
+        let embed = if self.embed { "!" } else { "" };
+        match self.kind {
+            LinkKind::Wiki => format!("{embed}[[{target}]]"),
+            LinkKind::Markdown => {
+                format!("{embed}[{}]({target})", self.text.as_deref().unwrap_or(""))
+            }
+        }
+    }
+
+    /// Paths this link could refer to, in order of preference.
+    ///
+    /// Targets are tried relative to the directory of the linking note first and then relative
+    /// to the vault root. Wiki links may omit the `.md` extension.
+    pub fn candidates(&self, note_dir: &Path, root: &Path) -> Vec<PathBuf> {
+        self.candidates_with(note_dir, root, &["md"])
+    }
+
+    /// Like `candidates`, for vaults whose notes have one of `extensions`, which wiki links may
+    /// omit. They're tried in order.
+    pub fn candidates_with<S: AsRef<str>>(
+        &self,
+        note_dir: &Path,
+        root: &Path,
+        extensions: &[S],
+    ) -> Vec<PathBuf> {
+        let target = self.decoded_path();
+        if target.is_empty() || self.is_external() {
+            return Vec::new();
+        }
+
+        let target = PathBuf::from(target.as_ref());
+        let relatives = if self.kind == LinkKind::Wiki && target.extension().is_none() {
+            extensions
+                .iter()
+                .map(|extension| target.with_extension(extension.as_ref()))
+                .collect()
+        } else {
+            vec![target]
+        };
+
+        [note_dir, root]
+            .iter()
+            .flat_map(|base| {
+                relatives
+                    .iter()
+                    .map(|relative| normalize(&base.join(relative)))
+            })
+            .collect()
+    }
+}
+
+/// Lexically resolve `.` and `..` components without touching the filesystem.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Convert a path to Unicode NFC form, so names typed in links compare equal to names on disk.
+pub fn nfc_path(path: &Path) -> PathBuf {
+    PathBuf::from(path.to_string_lossy().nfc().collect::<String>())
+}
+
+/// The path of `to` relative to the directory `from_dir`, e.g. `../assets/image.png`.
+///
+/// Both paths must be absolute and normalized.
+pub fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from = from_dir.components().collect::<Vec<_>>();
+    let to = to.components().collect::<Vec<_>>();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    relative.extend(&to[common..]);
+    relative
+}
+
+/// Format a relative path the way it's written in a link, always using `/` as the separator.
+pub fn path_to_target(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Characters that have to be percent-encoded in a markdown link target.
+const TARGET_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'(')
+    .add(b')')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?');
+
+/// The path a markdown link `target` names: percent-decoded, without any `<...>` around it.
+pub fn decode_target(target: &str) -> Cow<'_, str> {
+    let target = target.trim_start_matches('<').trim_end_matches('>');
+    percent_decode_str(target).decode_utf8_lossy()
+}
+
+/// Percent-encode a path for use as a markdown link target, e.g. `my%20note.md`.
+pub fn encode_target(path: &str) -> String {
+    utf8_percent_encode(path, TARGET_ENCODE_SET).to_string()
+}
+
+/// Find every link on a single line.
+pub fn parse_line(line: &str, line_number: u32) -> Vec<Link> {
+    let bytes = line.as_bytes();
+    let mut links = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] != b'[' {
+            i += 1;
+            continue;
+        }
+
+        let embed = i > 0 && bytes[i - 1] == b'!';
+        let start = if embed { i - 1 } else { i };
+
+        if line[i..].starts_with("[[") {
+            if let Some(len) = line[i + 2..].find("]]") {
+                let inner = &line[i + 2..i + 2 + len];
+                let end = i + 2 + len + 2;
+                links.push(Link {
+                    kind: LinkKind::Wiki,
+                    embed,
+                    target: inner.to_string(),
+                    text: None,
+                    line: line_number,
+                    start,
+                    end,
+                });
+                i = end;
+                continue;
+            }
+        } else if let Some(text_len) = line[i + 1..].find(']') {
+            let after_text = i + 1 + text_len + 1;
+            if line[after_text..].starts_with('(') {
+                if let Some(target_len) = line[after_text + 1..].find(')') {
+                    let text = &line[i + 1..i + 1 + text_len];
+                    let target = &line[after_text + 1..after_text + 1 + target_len];
+                    let end = after_text + 1 + target_len + 1;
+                    links.push(Link {
+                        kind: LinkKind::Markdown,
+                        embed,
+                        target: target.trim().to_string(),
+                        text: Some(text.to_string()),
+                        line: line_number,
+                        start,
+                        end,
+                    });
+                    i = end;
+                    continue;
+                }
+            }
+        }
+
+        i += 1;
+    }
+
+    links
+}
+
+/// Find every link in `document`.
+pub fn parse_links(document: &str) -> Vec<Link> {
+    document
+        .lines()
+        .enumerate()
+        .flat_map(|(n, line)| parse_line(line, n as u32))
+        .collect()
+}
+
+/// Find the link under the cursor, if any.
+pub fn link_at(document: &str, position: Position) -> Option<Link> {
+    let line = document.lines().nth(position.line as usize)?;
+    let character = position.character as usize;
+
+    parse_line(line, position.line)
+        .into_iter()
+        .find(|link| link.start <= character && character < link.end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_line_finds_all_link_kinds() {
+        let links = parse_line("see [[note]] and ![alt](img.png) or ![[embed.png]]", 0);
+        assert_eq!(links.len(), 3);
+
+        assert_eq!(links[0].kind, LinkKind::Wiki);
+        assert_eq!(links[0].target, "note");
+        assert!(!links[0].embed);
+        assert_eq!((links[0].start, links[0].end), (4, 12));
+
+        assert_eq!(links[1].kind, LinkKind::Markdown);
+        assert_eq!(links[1].target, "img.png");
+        assert_eq!(links[1].text.as_deref(), Some("alt"));
+        assert!(links[1].embed);
+
+        assert_eq!(links[2].kind, LinkKind::Wiki);
+        assert_eq!(links[2].target, "embed.png");
+        assert!(links[2].embed);
+    }
+
+    #[test]
+    fn unterminated_links_are_ignored() {
+        assert!(parse_line("[[unterminated", 0).is_empty());
+        assert!(parse_line("[text](unterminated", 0).is_empty());
+        assert!(parse_line("[just brackets]", 0).is_empty());
+    }
+
+    #[test]
+    fn candidates_resolve_relative_to_note_then_root() {
+        let link = &parse_line("[[../other#Heading]]", 0)[0];
+        assert_eq!(link.target_path(), "../other");
+        assert_eq!(
+            link.candidates(Path::new("/vault/dir"), Path::new("/vault")),
+            vec![PathBuf::from("/vault/other.md"), PathBuf::from("/other.md")]
+        );
+        assert_eq!(
+            link.candidates_with(Path::new("/vault/dir"), Path::new("/vault"), &["md", "txt"]),
+            vec![
+                PathBuf::from("/vault/other.md"),
+                PathBuf::from("/vault/other.txt"),
+                PathBuf::from("/other.md"),
+                PathBuf::from("/other.txt")
+            ]
+        );
+
+        assert_eq!(link.anchor(), Some("Heading"));
+
+        let link = &parse_line("[site](https://example.com)", 0)[0];
+        assert!(link
+            .candidates(Path::new("/vault"), Path::new("/vault"))
+            .is_empty());
+    }
+
+    #[test]
+    fn aliases_are_split_from_the_target() {
+        let link = &parse_line("[[dir/note#Part|the part]]", 0)[0];
+        assert_eq!(link.target_path(), "dir/note");
+        assert_eq!(link.anchor(), Some("Part"));
+        assert_eq!(link.alias(), Some("the part"));
+        assert_eq!(link.with_target("other"), "[[other#Part|the part]]");
+        assert_eq!(link.with_anchor("Whole"), "[[dir/note#Whole|the part]]");
+        let link = &parse_line("![x](note.md#part)", 0)[0];
+        assert_eq!(link.with_anchor("whole"), "![x](note.md#whole)");
+
+        let link = &parse_line("[[csharp|C# notes]]", 0)[0];
+        assert_eq!(link.target_path(), "csharp");
+        assert_eq!(link.anchor(), None);
+        assert_eq!(link.alias(), Some("C# notes"));
+
+        assert_eq!(parse_line("[[note|]]", 0)[0].alias(), None);
+        assert_eq!(parse_line("[a|b](note.md)", 0)[0].alias(), None);
+    }
+
+    #[test]
+    fn markdown_targets_are_percent_encoded() {
+        let link = &parse_line("[x](my%20notes/caf%C3%A9.md#Top)", 0)[0];
+        assert_eq!(link.decoded_path(), "my notes/café.md");
+        assert_eq!(
+            link.candidates(Path::new("/vault"), Path::new("/vault"))[0],
+            PathBuf::from("/vault/my notes/café.md")
+        );
+        assert_eq!(encode_target("my notes/100%.md"), "my%20notes/100%25.md");
+
+        let link = &parse_line("[[my%20note]]", 0)[0];
+        assert_eq!(link.decoded_path(), "my%20note");
+    }
+
+    #[test]
+    fn relative_paths() {
+        assert_eq!(
+            relative_path(Path::new("/vault/a/b"), Path::new("/vault/c/img.png")),
+            PathBuf::from("../../c/img.png")
+        );
+        assert_eq!(
+            path_to_target(&relative_path(
+                Path::new("/vault"),
+                Path::new("/vault/assets/img.png")
+            )),
+            "assets/img.png"
+        );
+    }
+
+    #[test]
+    fn link_at_cursor() {
+        let doc = "first line\nsome [[link]] here";
+        let link = link_at(doc, Position::new(1, 7)).unwrap();
+        assert_eq!(link.target, "link");
+        assert!(link_at(doc, Position::new(1, 2)).is_none());
+    }
+}
@@ -0,0 +1,336 @@
+//! Vault-wide reports of links that lead nowhere, and of notes cut off from the rest of the
+//! vault.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use unicode_normalization::UnicodeNormalization;
+use walkdir::WalkDir;
+
+use crate::{
+    index::{self, NoteIndex},
+    links::{self, Link, LinkKind},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub enum ProblemKind {
+    BrokenLink,
+    MissingAnchor,
+    UnresolvedEmbed,
+    DeadUrl,
+}
+
+impl fmt::Display for ProblemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProblemKind::BrokenLink => "broken link",
+            ProblemKind::MissingAnchor => "missing anchor",
+            ProblemKind::UnresolvedEmbed => "unresolved embed",
+            ProblemKind::DeadUrl => "dead URL",
+        })
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    pub target: String,
+    pub line: u32,
+}
+
+#[derive(Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct Counts {
+    pub broken_links: usize,
+    pub missing_anchors: usize,
+    pub unresolved_embeds: usize,
+    pub dead_urls: usize,
+}
+
+impl Counts {
+    fn add(&mut self, kind: ProblemKind) {
+        match kind {
+            ProblemKind::BrokenLink => self.broken_links += 1,
+            ProblemKind::MissingAnchor => self.missing_anchors += 1,
+            ProblemKind::UnresolvedEmbed => self.unresolved_embeds += 1,
+            ProblemKind::DeadUrl => self.dead_urls += 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct NoteReport {
+    pub path: PathBuf,
+    pub counts: Counts,
+    pub problems: Vec<Problem>,
+}
+
+#[derive(Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct LinkReport {
+    pub totals: Counts,
+    /// Notes with at least one problem, sorted by path.
+    pub notes: Vec<NoteReport>,
+}
+
+impl fmt::Display for LinkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for note in &self.notes {
+            writeln!(
+                f,
+                "{} ({} problems)",
+                note.path.display(),
+                note.problems.len()
+            )?;
+            for problem in &note.problems {
+                writeln!(
+                    f,
+                    "  line {}: {} {}",
+                    problem.line + 1,
+                    problem.kind,
+                    problem.target
+                )?;
+            }
+        }
+        write!(
+            f,
+            "{} broken links, {} missing anchors, {} unresolved embeds, {} dead URLs",
+            self.totals.broken_links,
+            self.totals.missing_anchors,
+            self.totals.unresolved_embeds,
+            self.totals.dead_urls
+        )
+    }
+}
+
+/// Whether a link target names a note with one of `extensions`, rather than an attachment.
+fn is_note_target(link: &Link, extensions: &[String]) -> bool {
+    let target = Path::new(link.target_path());
+    index::has_extension(target, extensions)
+        || (link.kind == LinkKind::Wiki && target.extension().is_none())
+}
+
+/// Whether a non-note link target exists, relative to the note, the root, or (for wiki embeds)
+/// anywhere in the vault by file name.
+fn attachment_exists(link: &Link, note_dir: &Path, root: &Path, names: &HashSet<String>) -> bool {
+    let target = link.decoded_path();
+    if link.kind == LinkKind::Wiki && names.contains(&target.nfc().collect::<String>()) {
+        return true;
+    }
+    [note_dir, root]
+        .iter()
+        .any(|base| links::normalize(&base.join(target.as_ref())).exists())
+}
+
+/// Check a single note's links against the index.
+fn check_note(
+    path: &Path,
+    links: &[Link],
+    index: &NoteIndex,
+    names: &HashSet<String>,
+) -> Vec<Problem> {
+    let root = index.root();
+    let note_dir = path.parent().unwrap_or(root);
+    let mut problems = Vec::new();
+
+    for link in links.iter().filter(|link| !link.is_external()) {
+        let problem = |kind| Problem {
+            kind,
+            target: link.target.clone(),
+            line: link.line,
+        };
+
+        // Links like `[[#Heading]]` point into the same note.
+        let target = if link.target_path().is_empty() {
+            Some(path.to_path_buf())
+        } else if is_note_target(link, index.extensions()) {
+            index.resolve(path, link)
+        } else {
+            if !attachment_exists(link, note_dir, root, names) {
+                problems.push(problem(if link.embed {
+                    ProblemKind::UnresolvedEmbed
+                } else {
+                    ProblemKind::BrokenLink
+                }));
+            }
+            continue;
+        };
+
+        match target {
+            None if link.embed => problems.push(problem(ProblemKind::UnresolvedEmbed)),
+            None => problems.push(problem(ProblemKind::BrokenLink)),
+            Some(target) => {
+                let note = index.get(&target);
+                if let (Some(anchor), Some(note)) = (link.anchor(), note) {
+                    let found = match link.block_id() {
+                        Some(id) => note.find_block(id).is_some(),
+                        None => note.find_heading(anchor).is_some(),
+                    };
+                    if !found {
+                        problems.push(problem(ProblemKind::MissingAnchor));
+                    }
+                }
+            }
+        }
+    }
+
+    problems
+}
+
+/// Build a report of every broken link, missing anchor and unresolved embed in the vault, and of
+/// links to the `dead` external URLs, as found by the link checker.
+pub fn link_report(index: &NoteIndex, dead: &HashSet<String>) -> LinkReport {
+    let names = WalkDir::new(index.root())
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.file_name().to_string_lossy().nfc().collect::<String>())
+        .collect::<HashSet<_>>();
+    let mut notes = BTreeMap::new();
+    for (path, note) in index.notes() {
+        let mut problems = check_note(path, &note.links, index, &names);
+        problems.extend(
+            note.links
+                .iter()
+                .filter(|link| dead.contains(&link.target))
+                .map(|link| Problem {
+                    kind: ProblemKind::DeadUrl,
+                    target: link.target.clone(),
+                    line: link.line,
+                }),
+        );
+
+        if !problems.is_empty() {
+            problems.sort_by_key(|problem| problem.line);
+            notes.insert(path.to_path_buf(), problems);
+        }
+    }
+
+    let mut report = LinkReport::default();
+    for (path, problems) in notes {
+        let mut counts = Counts::default();
+        for problem in &problems {
+            counts.add(problem.kind);
+            report.totals.add(problem.kind);
+        }
+        report.notes.push(NoteReport {
+            path,
+            counts,
+            problems,
+        });
+    }
+
+    report
+}
+
+#[derive(Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct OrphanReport {
+    /// Notes no other note links to, sorted by path.
+    pub orphans: Vec<PathBuf>,
+    /// Notes that link to no other note, sorted by path.
+    pub dead_ends: Vec<PathBuf>,
+}
+
+/// Find the notes in the vault that no other note links to, and those that link nowhere.
+pub fn orphan_report(index: &NoteIndex) -> OrphanReport {
+    let graph = index.link_graph();
+    let linked = graph
+        .values()
+        .flatten()
+        .map(|target| links::nfc_path(target))
+        .collect::<HashSet<_>>();
+
+    let mut report = OrphanReport::default();
+    for (path, targets) in graph {
+        if targets.is_empty() {
+            report.dead_ends.push(path.clone());
+        }
+        if !linked.contains(&links::nfc_path(&path)) {
+            report.orphans.push(path);
+        }
+    }
+    report.orphans.sort();
+    report.dead_ends.sort();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn report_groups_problems_by_note() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::write(root.join("image.png"), "").unwrap();
+        fs::write(root.join("target.md"), "# Real heading\nText ^real").unwrap();
+        fs::write(
+            root.join("note.md"),
+            "[[target#Real heading]] [[target#Fake]] [[missing]]\n![[image.png]] ![[gone.png]]\n\
+             [[target#^real]] [[target#^fake]]\n[gone](https://gone.example) [fine](https://fine.example)",
+        )
+        .unwrap();
+
+        let dead = HashSet::from(["https://gone.example".to_string()]);
+        let report = link_report(&NoteIndex::scan(root), &dead);
+
+        assert_eq!(report.notes.len(), 1);
+        assert_eq!(report.notes[0].path, root.join("note.md"));
+        assert_eq!(report.totals.missing_anchors, 2);
+        assert_eq!(report.totals.broken_links, 1);
+        assert_eq!(report.totals.unresolved_embeds, 1);
+        assert_eq!(report.totals.dead_urls, 1);
+    }
+
+    #[test]
+    fn orphans_and_dead_ends_are_reported() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        let write = |name: &str, content: &str| fs::write(root.join(name), content).unwrap();
+        write("home.md", "[[a]] [[home]]");
+        write("a.md", "[[b]] [[missing]]");
+        write("b.md", "[[a]]");
+        write("alone.md", "[[alone]] [site](https://example.com)");
+
+        let report = orphan_report(&NoteIndex::scan(root));
+
+        assert_eq!(
+            report.orphans,
+            vec![root.join("alone.md"), root.join("home.md")]
+        );
+        assert_eq!(report.dead_ends, vec![root.join("alone.md")]);
+    }
+}
@@ -0,0 +1,108 @@
+//! Words for full-text search of notes.
+//!
+//! Text is split into words at anything that isn't a letter or a digit, and words are compared
+//! case-insensitively, so `Soup,` and `soup` are the same term.
+
+use lsp_types::{Position, Range};
+use unicode_normalization::UnicodeNormalization;
+
+/// The words of `line` with their byte offsets.
+pub fn words(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut rest = line.char_indices().peekable();
+    std::iter::from_fn(move || {
+        let (start, _) = rest.find(|(_, c)| c.is_alphanumeric())?;
+        let mut end = line.len();
+        while let Some(&(i, c)) = rest.peek() {
+            if !c.is_alphanumeric() {
+                end = i;
+                break;
+            }
+            rest.next();
+        }
+        Some((start, &line[start..end]))
+    })
+}
+
+/// The term `word` is indexed and searched by.
+pub fn term(word: &str) -> String {
+    word.nfc().collect::<String>().to_lowercase()
+}
+
+/// The terms of a search query, without repeats.
+pub fn query_terms(query: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    for (_, word) in words(query) {
+        let term = term(word);
+        if !terms.contains(&term) {
+            terms.push(term);
+        }
+    }
+    terms
+}
+
+/// Every term in `content`, sorted, with the number of times it occurs.
+pub fn count_terms(content: &str) -> Vec<(String, u32)> {
+    let mut terms = words(content)
+        .map(|(_, word)| term(word))
+        .collect::<Vec<_>>();
+    terms.sort_unstable();
+    let mut counts = Vec::<(String, u32)>::new();
+    for term in terms {
+        match counts.last_mut() {
+            Some((last, count)) if *last == term => *count += 1,
+            _ => counts.push((term, 1)),
+        }
+    }
+    counts
+}
+
+/// The ranges of the words in `content` that are one of `terms`.
+pub fn find_terms(content: &str, terms: &[String]) -> Vec<Range> {
+    let mut found = Vec::new();
+    for (n, line) in content.lines().enumerate() {
+        for (start, word) in words(line) {
+            if terms.contains(&term(word)) {
+                found.push(Range::new(
+                    Position::new(n as u32, start as u32),
+                    Position::new(n as u32, (start + word.len()) as u32),
+                ));
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_is_split_into_terms() {
+        assert_eq!(
+            words("Soup, [[recipes#Soup]] 2x-café").collect::<Vec<_>>(),
+            vec![
+                (0, "Soup"),
+                (8, "recipes"),
+                (16, "Soup"),
+                (23, "2x"),
+                (26, "café")
+            ]
+        );
+        assert_eq!(query_terms("soup SOUP café"), vec!["soup", "café"]);
+        assert_eq!(
+            count_terms("Soup and soup\nand bread"),
+            vec![
+                (String::from("and"), 2),
+                (String::from("bread"), 1),
+                (String::from("soup"), 2)
+            ]
+        );
+        assert_eq!(
+            find_terms("# Soup\nA soupy soup.", &query_terms("soup")),
+            vec![
+                Range::new(Position::new(0, 2), Position::new(0, 6)),
+                Range::new(Position::new(1, 8), Position::new(1, 12)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use lsp_types::{Position, Range};
+
+use crate::fences;
+
+/// A `#tag` in a note.
+///
+/// `start` and `end` are byte offsets into the line and include the `#`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tag {
+    /// The tag without its leading `#`.
+    pub name: String,
+    pub line: u32,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Tag {
+    /// The range the tag covers, including the `#`.
+    pub fn range(&self) -> Range {
+        Range::new(
+            Position::new(self.line, self.start as u32),
+            Position::new(self.line, self.end as u32),
+        )
+    }
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '/'
+}
+
+/// Find every tag on a single line.
+///
+/// A tag is a `#` at the start of a word followed by letters, digits, `_`, `-` or `/`, with at
+/// least one character that isn't a digit so that e.g. issue numbers like `#123` aren't tags.
+pub fn parse_line(line: &str, line_number: u32) -> Vec<Tag> {
+    let mut tags = Vec::new();
+    let mut in_code = false;
+    let mut previous = None;
+
+    for (i, c) in line.char_indices() {
+        if c == '`' {
+            in_code = !in_code;
+        }
+        let at_word_start = previous.map(char::is_whitespace).unwrap_or(true);
+        previous = Some(c);
+
+        if in_code || c != '#' || !at_word_start {
+            continue;
+        }
+
+        let name_len = line[i + 1..]
+            .chars()
+            .take_while(|&c| is_tag_char(c))
+            .map(char::len_utf8)
+            .sum::<usize>();
+        let name = &line[i + 1..i + 1 + name_len];
+        if name.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+
+        tags.push(Tag {
+            name: name.to_string(),
+            line: line_number,
+            start: i,
+            end: i + 1 + name_len,
+        });
+    }
+
+    tags
+}
+
+/// Find the tag under the cursor, if any.
+pub fn tag_at(document: &str, position: Position) -> Option<Tag> {
+    let line = document.lines().nth(position.line as usize)?;
+    let character = position.character as usize;
+
+    parse_line(line, position.line)
+        .into_iter()
+        .find(|tag| tag.start <= character && character < tag.end)
+}
+
+/// Find every tag in `document`, skipping frontmatter and fenced code blocks.
+///
+/// Tags listed in the frontmatter are found by `frontmatter::parse` instead.
+pub fn parse_tags(document: &str) -> Vec<Tag> {
+    let mut tags = Vec::new();
+    for (n, line) in fences::body_lines(document) {
+        if let Some(line) = line {
+            tags.extend(parse_line(line, n));
+        }
+    }
+
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_are_found_outside_code() {
+        let doc =
+            "# Heading\n#project/alpha and #todo, not#this or #42\n`#code`\n```\n#fenced\n```";
+        let names = parse_tags(doc)
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["project/alpha", "todo"]);
+
+        let tag = tag_at(doc, Position::new(1, 20)).unwrap();
+        assert_eq!(tag.name, "todo");
+        assert_eq!(tag_at(doc, Position::new(1, 24)), None);
+    }
+}
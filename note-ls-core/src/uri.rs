@@ -0,0 +1,159 @@
+//! Conversion between `file:` URIs and paths.
+//!
+//! `Url::to_file_path` only understands paths of the platform the server was built for, and is
+//! easily confused by what editors send in practice, like VS Code's `file:///c%3A/...`. The
+//! conversion is done by hand here so that the same rules apply everywhere and can be tested on
+//! any platform.
+
+use std::path::{Path, PathBuf};
+
+use lsp_types::Url;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+
+/// The path a `file:` URI points to.
+pub fn to_path(uri: &Url) -> Option<PathBuf> {
+    uri_to_path(uri, cfg!(windows)).map(PathBuf::from)
+}
+
+/// The `file:` URI of an absolute path.
+pub fn from_path(path: &Path) -> Option<Url> {
+    path_to_uri(&path.to_string_lossy(), cfg!(windows))
+}
+
+/// Split a Windows path into its drive (`C:`) and the rest, with the drive letter uppercased.
+fn split_drive(path: &str) -> Option<(String, &str)> {
+    let mut chars = path.chars();
+    let letter = chars.next().filter(char::is_ascii_alphabetic)?;
+    if chars.next() != Some(':') {
+        return None;
+    }
+    Some((format!("{}:", letter.to_ascii_uppercase()), &path[2..]))
+}
+
+fn uri_to_path(uri: &Url, windows: bool) -> Option<String> {
+    if uri.scheme() != "file" {
+        return None;
+    }
+    let path = percent_decode_str(uri.path()).decode_utf8().ok()?;
+    let host = uri
+        .host_str()
+        .filter(|host| !host.is_empty() && *host != "localhost");
+
+    if !windows {
+        // There's no way to name a file on another host on Unix.
+        return host.is_none().then(|| path.into_owned());
+    }
+
+    let path = path.replace('/', "\\");
+    match host {
+        Some(host) => Some(format!("\\\\{host}{path}")),
+        None => {
+            let (drive, rest) = split_drive(path.strip_prefix('\\')?)?;
+            Some(if rest.is_empty() {
+                format!("{drive}\\")
+            } else {
+                format!("{drive}{rest}")
+            })
+        }
+    }
+}
+
+/// Characters escaped in path segments. `Url` silently drops tabs and newlines in paths it's
+/// given rather than escaping them, so everything unusual is escaped up front.
+const SEGMENT_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b'\\')
+    .add(b']')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+/// Join path segments into the percent-encoded path of a URI.
+fn encode_segments<'a>(segments: impl Iterator<Item = &'a str>) -> String {
+    segments
+        .map(|segment| format!("/{}", utf8_percent_encode(segment, SEGMENT_ENCODE_SET)))
+        .collect()
+}
+
+fn path_to_uri(path: &str, windows: bool) -> Option<Url> {
+    let mut uri = Url::parse("file:///").ok()?;
+
+    if !windows {
+        let rest = path.strip_prefix('/')?;
+        uri.set_path(&encode_segments(rest.split('/')));
+        return Some(uri);
+    }
+
+    let path = path.replace('/', "\\");
+    if let Some(unc) = path.strip_prefix("\\\\") {
+        let (host, rest) = unc.split_once('\\')?;
+        uri.set_host(Some(host)).ok()?;
+        uri.set_path(&encode_segments(rest.split('\\')));
+    } else {
+        let (drive, rest) = split_drive(&path)?;
+        let rest = rest.strip_prefix('\\').unwrap_or(rest);
+        let segments = rest.split('\\').filter(|segment| !segment.is_empty());
+        uri.set_path(&encode_segments(
+            std::iter::once(drive.as_str()).chain(segments),
+        ));
+    }
+    Some(uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(uri: &str, path: &str, windows: bool) {
+        let uri = Url::parse(uri).unwrap();
+        assert_eq!(uri_to_path(&uri, windows).as_deref(), Some(path));
+        assert_eq!(path_to_uri(path, windows), Some(uri));
+    }
+
+    #[test]
+    fn unix_paths() {
+        round_trip(
+            "file:///home/me/my%20notes/caf%C3%A9.md",
+            "/home/me/my notes/café.md",
+            false,
+        );
+        let remote = Url::parse("file://server/share/note.md").unwrap();
+        assert_eq!(uri_to_path(&remote, false), None);
+        assert_eq!(path_to_uri("relative/note.md", false), None);
+    }
+
+    #[test]
+    fn windows_paths() {
+        round_trip(
+            "file:///C:/Users/Me/My%20Notes/a.md",
+            "C:\\Users\\Me\\My Notes\\a.md",
+            true,
+        );
+        round_trip(
+            "file://server/share/notes/a.md",
+            "\\\\server\\share\\notes\\a.md",
+            true,
+        );
+
+        // VS Code sends lowercase drive letters with the colon escaped.
+        let uri = Url::parse("file:///c%3A/notes/a.md").unwrap();
+        assert_eq!(uri_to_path(&uri, true).as_deref(), Some("C:\\notes\\a.md"));
+        assert_eq!(path_to_uri("C:", true).unwrap().as_str(), "file:///C:");
+    }
+
+    #[test]
+    fn other_schemes_are_not_paths() {
+        let uri = Url::parse("untitled:Untitled-1").unwrap();
+        assert_eq!(to_path(&uri), None);
+    }
+}